@@ -0,0 +1,610 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// ECC starts on the smallest halving of the images whose shorter side is at least this.
+const MIN_LEVEL_SIZE: usize = 32;
+
+/// ECC stops refining a level once an update moves the image's corners by less than this many
+/// pixels.
+const CONVERGED: f64 = 1e-3;
+
+/// How the transform is estimated.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// Phase correlation: the peak of the normalized cross-power spectrum. Fast and robust to
+    /// changes in brightness, but finds translation only.
+    Phase,
+    /// Enhanced correlation coefficient maximization (Evangelidis and Psarakis), started from
+    /// phase correlation and refined from coarse to fine; finds the `motion` model.
+    #[default]
+    Ecc,
+}
+
+/// Which transforms `ecc` looks for.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Motion {
+    /// Shift only.
+    Translation,
+    /// Shift and rotation.
+    Euclidean,
+    /// Shift, rotation and uniform scale.
+    #[default]
+    Similarity,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    method: Method,
+    #[serde(default)]
+    motion: Motion,
+    /// Most ECC updates per pyramid level.
+    #[serde(default = "default_iterations")]
+    iterations: u32,
+}
+
+fn default_iterations() -> u32 {
+    50
+}
+
+const MANIFEST: &CStr = cr#"name = "align_plugin"
+version = "0.1.0"
+description = "Replaces the image by the secondary input (--input2) warped into register with it"
+
+[defaults]
+method = "ecc"
+motion = "similarity"
+iterations = 50
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Aligns the secondary input onto the image, in both formats. It is not local: the transform is
+/// estimated from the whole images.
+///
+/// Fails if the host passed no secondary input.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    // SAFETY: the host keeps the secondary input valid for the duration of the call.
+    let Some(moving) = (unsafe { ctx.input2_image() }) else {
+        return Err(PluginError::Invalid("no secondary input to align"));
+    };
+    let premultiplied = ctx.premultiplied();
+
+    let size = (image.width as usize, image.height as usize);
+    let moving_size = (moving.width as usize, moving.height as usize);
+    match (image.pixels, moving.pixels) {
+        (Pixels::Rgba8(buf), PixelsRef::Rgba8(moving)) => align(size, buf, moving_size, moving, params, premultiplied),
+        (Pixels::Rgba32F(buf), PixelsRef::Rgba32F(moving)) => {
+            align(size, buf, moving_size, moving, params, premultiplied)
+        }
+        _ => return Err(PluginError::Format),
+    }
+    Ok(())
+}
+
+/// Estimates where each pixel of `buf` lies in `moving` and replaces it by the bilinearly
+/// interpolated `moving` pixel there; pixels that map outside `moving` become transparent.
+///
+/// The transform is estimated on the sRGB-encoded luma; interpolation is done on premultiplied
+/// values so transparent pixels do not bleed their color.
+fn align<T: Sample>(
+    (width, height): (usize, usize),
+    buf: &mut [T],
+    (moving_width, moving_height): (usize, usize),
+    moving: &[T],
+    params: &Params,
+    premultiplied: bool,
+) {
+    if width == 0 || height == 0 || moving_width == 0 || moving_height == 0 {
+        return;
+    }
+    let reference = Gray::of(width, height, buf, premultiplied);
+    let target = Gray::of(moving_width, moving_height, moving, premultiplied);
+    let warp = match params.method {
+        Method::Phase => {
+            let (dx, dy) = phase_correlate(&reference, &target);
+            Warp::translation(&reference, dx, dy)
+        }
+        Method::Ecc => ecc(reference, target, params),
+    };
+
+    let pixel = |x: i64, y: i64| -> [f32; 4] {
+        if x < 0 || y < 0 || x >= moving_width as i64 || y >= moving_height as i64 {
+            return [0.0; 4];
+        }
+        let px = &moving[(y as usize * moving_width + x as usize) * 4..][..4];
+        let alpha = if premultiplied { 1.0 } else { px[3].to_f32() / T::MAX };
+        [px[0].to_f32() * alpha, px[1].to_f32() * alpha, px[2].to_f32() * alpha, px[3].to_f32()]
+    };
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let (sx, sy) = warp.map((i % width) as f64, (i / width) as f64);
+        let (x0, y0) = (sx.floor(), sy.floor());
+        let (fx, fy) = ((sx - x0) as f32, (sy - y0) as f32);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let taps = [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)];
+        let mut value = [0.0; 4];
+        for (ox, oy, weight) in taps {
+            for (v, p) in value.iter_mut().zip(pixel(x0 + ox, y0 + oy)) {
+                *v += p * weight;
+            }
+        }
+        let alpha = value[3] / T::MAX;
+        for c in 0..3 {
+            let v = if premultiplied {
+                value[c]
+            } else if alpha > 0.0 {
+                value[c] / alpha
+            } else {
+                0.0
+            };
+            px[c] = T::from_f32(v);
+        }
+        px[3] = T::from_f32(value[3]);
+    }
+}
+
+/// Luma of an image, sRGB-encoded and straight.
+#[derive(Debug, Clone)]
+struct Gray {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Gray {
+    fn of<T: Sample>(width: usize, height: usize, data: &[T], premultiplied: bool) -> Self {
+        let data = data
+            .chunks_exact(4)
+            .map(|px| {
+                let alpha = px[3].to_f32() / T::MAX;
+                let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+                (0..3).map(|c| LUMA[c] * plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)).sum()
+            })
+            .collect();
+        Self { width, height, data }
+    }
+
+    /// The center, around which rotation and scale act.
+    fn center(&self) -> (f64, f64) {
+        ((self.width as f64 - 1.0) / 2.0, (self.height as f64 - 1.0) / 2.0)
+    }
+
+    /// Averages 2x2 blocks, dropping an odd last row or column.
+    fn half(&self) -> Self {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let data = (0..width * height)
+            .map(|i| {
+                let (x, y) = (2 * (i % width), 2 * (i / width));
+                let at = |x: usize, y: usize| self.data[y * self.width + x];
+                (at(x, y) + at(x + 1, y) + at(x, y + 1) + at(x + 1, y + 1)) / 4.0
+            })
+            .collect();
+        Self { width, height, data }
+    }
+
+    /// Returns the value at `(x, y)` by bilinear interpolation, or `None` outside the image.
+    fn sample(&self, x: f64, y: f64) -> Option<f32> {
+        if x < 0.0 || y < 0.0 || x > (self.width - 1) as f64 || y > (self.height - 1) as f64 {
+            return None;
+        }
+        let x0 = (x as usize).min(self.width.saturating_sub(2));
+        let y0 = (y as usize).min(self.height.saturating_sub(2));
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = ((x - x0 as f64) as f32, (y - y0 as f64) as f32);
+        let at = |x: usize, y: usize| self.data[y * self.width + x];
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        Some(top * (1.0 - fy) + bottom * fy)
+    }
+
+    /// Central-difference derivatives along x and y, one-sided at the edges.
+    fn gradients(&self) -> (Gray, Gray) {
+        let (w, h) = (self.width, self.height);
+        let at = |x: usize, y: usize| self.data[y * w + x];
+        let derivative = |lo: usize, hi: usize, a: f32, b: f32| if hi > lo { (b - a) / (hi - lo) as f32 } else { 0.0 };
+        let gx = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let (lo, hi) = (x.saturating_sub(1), (x + 1).min(w - 1));
+                derivative(lo, hi, at(lo, y), at(hi, y))
+            })
+            .collect();
+        let gy = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let (lo, hi) = (y.saturating_sub(1), (y + 1).min(h - 1));
+                derivative(lo, hi, at(x, lo), at(x, hi))
+            })
+            .collect();
+        (Gray { data: gx, ..*self }, Gray { data: gy, ..*self })
+    }
+}
+
+/// Maps a point of the reference to the moving image: rotated and scaled by `(a, -b; b, a)`
+/// around the reference's center, which lands on `(tx, ty)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Warp {
+    center: (f64, f64),
+    a: f64,
+    b: f64,
+    tx: f64,
+    ty: f64,
+}
+
+impl Warp {
+    /// A pure shift: the reference's pixel `(x, y)` lies at `(x + dx, y + dy)` in the moving image.
+    fn translation(reference: &Gray, dx: f64, dy: f64) -> Self {
+        let center = reference.center();
+        Self { center, a: 1.0, b: 0.0, tx: center.0 + dx, ty: center.1 + dy }
+    }
+
+    fn map(&self, x: f64, y: f64) -> (f64, f64) {
+        let (u, v) = (x - self.center.0, y - self.center.1);
+        (self.a * u - self.b * v + self.tx, self.b * u + self.a * v + self.ty)
+    }
+
+    /// The same transform on the level [`Gray::half`] was taken from, whose reference has its
+    /// center at `center`.
+    fn doubled(&self, center: (f64, f64)) -> Self {
+        // A pixel `x` of the halved image covers `2x` and `2x + 1` of the full one.
+        let (ox, oy) = (center.0 - 2.0 * self.center.0 - 0.5, center.1 - 2.0 * self.center.1 - 0.5);
+        let tx = 2.0 * self.tx + 0.5 + self.a * ox - self.b * oy;
+        let ty = 2.0 * self.ty + 0.5 + self.b * ox + self.a * oy;
+        Self { center, tx, ty, ..*self }
+    }
+
+    /// Number of parameters `motion` varies.
+    fn parameters(motion: Motion) -> usize {
+        match motion {
+            Motion::Translation => 2,
+            Motion::Euclidean => 3,
+            Motion::Similarity => 4,
+        }
+    }
+
+    /// Derivatives of the mapped point of `(u, v)`, relative to the center, by each parameter.
+    fn jacobian(&self, motion: Motion, u: f64, v: f64) -> [(f64, f64); 4] {
+        let shift = [(1.0, 0.0), (0.0, 1.0)];
+        match motion {
+            Motion::Translation => [shift[0], shift[1], (0.0, 0.0), (0.0, 0.0)],
+            // The angle, with the scale held at the current one.
+            Motion::Euclidean => [shift[0], shift[1], (-self.b * u - self.a * v, self.a * u - self.b * v), (0.0, 0.0)],
+            Motion::Similarity => [shift[0], shift[1], (u, v), (-v, u)],
+        }
+    }
+
+    fn update(&mut self, motion: Motion, delta: &[f64]) {
+        self.tx += delta[0];
+        self.ty += delta[1];
+        match motion {
+            Motion::Translation => {}
+            Motion::Euclidean => {
+                let (scale, angle) = (self.a.hypot(self.b), self.b.atan2(self.a) + delta[2]);
+                (self.a, self.b) = (scale * angle.cos(), scale * angle.sin());
+            }
+            Motion::Similarity => {
+                self.a += delta[2];
+                self.b += delta[3];
+            }
+        }
+    }
+}
+
+/// Estimates the transform by ECC over a pyramid of both images, starting on the coarsest level
+/// from the phase-correlation shift.
+///
+/// A level stops early when an update barely moves the image, or when the correlation can no
+/// longer be improved, keeping the last estimate.
+fn ecc(reference: Gray, target: Gray, params: &Params) -> Warp {
+    let mut levels = vec![(reference, target)];
+    while let Some((r, t)) = levels.last()
+        && r.width.min(r.height).min(t.width).min(t.height) >= 2 * MIN_LEVEL_SIZE
+    {
+        let next = (r.half(), t.half());
+        levels.push(next);
+    }
+
+    let coarsest = &levels[levels.len() - 1];
+    let (dx, dy) = phase_correlate(&coarsest.0, &coarsest.1);
+    let mut warp = Warp::translation(&coarsest.0, dx, dy);
+    let n = Warp::parameters(params.motion);
+    for (level, (reference, target)) in levels.iter().enumerate().rev() {
+        if level + 1 < levels.len() {
+            warp = warp.doubled(reference.center());
+        }
+        let (gx, gy) = target.gradients();
+        let extent = reference.width.max(reference.height) as f64;
+        for _ in 0..params.iterations {
+            let mut samples = Vec::new();
+            for (i, &t) in reference.data.iter().enumerate() {
+                let (x, y) = ((i % reference.width) as f64, (i / reference.width) as f64);
+                let (sx, sy) = warp.map(x, y);
+                let sampled = (target.sample(sx, sy), gx.sample(sx, sy), gy.sample(sx, sy));
+                if let (Some(value), Some(dx), Some(dy)) = sampled {
+                    let (u, v) = (x - warp.center.0, y - warp.center.1);
+                    let mut g = [0.0; 4];
+                    for (g, (jx, jy)) in g.iter_mut().zip(warp.jacobian(params.motion, u, v)) {
+                        *g = dx as f64 * jx + dy as f64 * jy;
+                    }
+                    samples.push((t as f64, value as f64, g));
+                }
+            }
+            if samples.len() < 16 {
+                break;
+            }
+            let count = samples.len() as f64;
+            let t_mean = samples.iter().map(|s| s.0).sum::<f64>() / count;
+            let i_mean = samples.iter().map(|s| s.1).sum::<f64>() / count;
+
+            let mut hessian = [[0.0; 4]; 4];
+            let (mut image_projection, mut template_projection) = ([0.0; 4], [0.0; 4]);
+            let (mut correlation, mut image_norm) = (0.0, 0.0);
+            for (t, v, g) in &samples {
+                let (t, v) = (t - t_mean, v - i_mean);
+                correlation += t * v;
+                image_norm += v * v;
+                for r in 0..n {
+                    image_projection[r] += g[r] * v;
+                    template_projection[r] += g[r] * t;
+                    for c in 0..n {
+                        hessian[r][c] += g[r] * g[c];
+                    }
+                }
+            }
+            let Some(image_hessian) = solve(&hessian, &image_projection, n) else {
+                break;
+            };
+            let dot = |a: &[f64; 4], b: &[f64; 4]| (0..n).map(|k| a[k] * b[k]).sum::<f64>();
+            let lambda_n = image_norm - dot(&image_projection, &image_hessian);
+            let lambda_d = correlation - dot(&template_projection, &image_hessian);
+            if lambda_d <= 0.0 {
+                break;
+            }
+            let lambda = lambda_n / lambda_d;
+            let error: [f64; 4] = std::array::from_fn(|k| lambda * template_projection[k] - image_projection[k]);
+            let Some(delta) = solve(&hessian, &error, n) else {
+                break;
+            };
+            let before = warp;
+            warp.update(params.motion, &delta[..n]);
+            let half = extent / 2.0;
+            let moved = [(-half, -half), (half, half)].iter().fold(0.0f64, |m, &(u, v)| {
+                let (x, y) = (warp.center.0 + u, warp.center.1 + v);
+                let (p, q) = (warp.map(x, y), before.map(x, y));
+                m.max((p.0 - q.0).hypot(p.1 - q.1))
+            });
+            if moved < CONVERGED {
+                break;
+            }
+        }
+    }
+    warp
+}
+
+/// Solves the leading `n` x `n` system `m x = rhs` by Gaussian elimination with partial pivoting;
+/// `None` if it is singular.
+fn solve(m: &[[f64; 4]; 4], rhs: &[f64; 4], n: usize) -> Option<[f64; 4]> {
+    let (mut m, mut rhs) = (*m, *rhs);
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        rhs.swap(col, pivot);
+        let pivot_row = m[col];
+        for row in col + 1..n {
+            let factor = m[row][col] / pivot_row[col];
+            for (v, p) in m[row][col..n].iter_mut().zip(&pivot_row[col..n]) {
+                *v -= factor * p;
+            }
+            rhs[row] -= factor * rhs[col];
+        }
+    }
+    let mut x = [0.0; 4];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (rhs[row] - sum) / m[row][row];
+    }
+    Some(x)
+}
+
+/// Returns the shift `(dx, dy)` for which `reference` at `(x, y)` best matches `target` at
+/// `(x + dx, y + dy)`, to a fraction of a pixel.
+///
+/// Both images are windowed to hide their edges and zero-padded to a common power-of-two size.
+fn phase_correlate(reference: &Gray, target: &Gray) -> (f64, f64) {
+    let w = reference.width.max(target.width).next_power_of_two();
+    let h = reference.height.max(target.height).next_power_of_two();
+    let spectrum = |image: &Gray| {
+        let mean = image.data.iter().map(|&v| v as f64).sum::<f64>() / image.data.len() as f64;
+        let (mut re, mut im) = (vec![0.0; w * h], vec![0.0; w * h]);
+        let hann = |i: usize, n: usize| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * i as f64 / n as f64).cos();
+        for (i, &v) in image.data.iter().enumerate() {
+            let (x, y) = (i % image.width, i / image.width);
+            re[y * w + x] = (v as f64 - mean) * hann(x, image.width) * hann(y, image.height);
+        }
+        fft_2d(&mut re, &mut im, w, h, false);
+        (re, im)
+    };
+    let (ar, ai) = spectrum(reference);
+    let (br, bi) = spectrum(target);
+    let (mut re, mut im) = (vec![0.0; w * h], vec![0.0; w * h]);
+    for i in 0..w * h {
+        // The reference's spectrum times the target's conjugate, keeping only the phase.
+        let (r, m) = (ar[i] * br[i] + ai[i] * bi[i], ai[i] * br[i] - ar[i] * bi[i]);
+        let norm = r.hypot(m);
+        if norm > 1e-12 {
+            (re[i], im[i]) = (r / norm, m / norm);
+        }
+    }
+    fft_2d(&mut re, &mut im, w, h, true);
+
+    let peak = (0..w * h).max_by(|&a, &b| re[a].total_cmp(&re[b])).unwrap_or(0);
+    let (px, py) = (peak % w, peak / w);
+    let at = |x: usize, y: usize| re[(y % h) * w + x % w];
+    // A parabola through the peak and its neighbours locates it between pixels.
+    let offset = |before: f64, peak: f64, after: f64| {
+        let curvature = before - 2.0 * peak + after;
+        if curvature.abs() > 1e-12 { 0.5 * (before - after) / curvature } else { 0.0 }
+    };
+    let fx = px as f64 + offset(at(px + w - 1, py), at(px, py), at(px + 1, py));
+    let fy = py as f64 + offset(at(px, py + h - 1), at(px, py), at(px, py + 1));
+    // The correlation peaks at minus the shift, modulo the padded size.
+    let unwrap = |v: f64, n: usize| if v > n as f64 / 2.0 { v - n as f64 } else { v };
+    (-unwrap(fx, w), -unwrap(fy, h))
+}
+
+/// Transforms rows and then columns in place.
+fn fft_2d(re: &mut [f64], im: &mut [f64], w: usize, h: usize, inverse: bool) {
+    for y in 0..h {
+        fft(&mut re[y * w..][..w], &mut im[y * w..][..w], inverse);
+    }
+    let (mut col_re, mut col_im) = (vec![0.0; h], vec![0.0; h]);
+    for x in 0..w {
+        for y in 0..h {
+            (col_re[y], col_im[y]) = (re[y * w + x], im[y * w + x]);
+        }
+        fft(&mut col_re, &mut col_im, inverse);
+        for y in 0..h {
+            (re[y * w + x], im[y * w + x]) = (col_re[y], col_im[y]);
+        }
+    }
+}
+
+/// Iterative radix-2 Cooley-Tukey transform of a power-of-two length; the inverse is scaled by
+/// `1 / n`.
+fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (wr, wi) = ((angle * k as f64).cos(), (angle * k as f64).sin());
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * wr - im[b] * wi, re[b] * wi + im[b] * wr);
+                (re[b], im[b]) = (re[a] - tr, im[a] - ti);
+                (re[a], im[a]) = (re[a] + tr, im[a] + ti);
+            }
+        }
+        len <<= 1;
+    }
+    if inverse {
+        for v in re.iter_mut().chain(im.iter_mut()) {
+            *v /= n as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    const SIZE: usize = 64;
+
+    fn run(buf: &mut [u8], moving: &[u8], moving_size: (u32, u32), params: &str) -> Result<(), PluginError> {
+        let ctx = CallContext {
+            input2_data: moving.as_ptr(),
+            input2_width: moving_size.0,
+            input2_height: moving_size.1,
+            ..CallContext::new(0)
+        };
+        testing::run_with(process, &ctx, SIZE as u32, SIZE as u32, buf, params)
+    }
+
+    /// Smooth value noise over 8-pixel cells, defined everywhere so it can be warped exactly.
+    fn scene(x: f64, y: f64) -> f64 {
+        let hash = |x: i64, y: i64| (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).rem_euclid(200) as f64;
+        let (cx, cy) = ((x / 8.0).floor(), (y / 8.0).floor());
+        let (tx, ty) = (x / 8.0 - cx, y / 8.0 - cy);
+        let (cx, cy) = (cx as i64, cy as i64);
+        let top = hash(cx, cy) * (1.0 - tx) + hash(cx + 1, cy) * tx;
+        let bottom = hash(cx, cy + 1) * (1.0 - tx) + hash(cx + 1, cy + 1) * tx;
+        top * (1.0 - ty) + bottom * ty + 20.0
+    }
+
+    /// Renders `width` x `height` pixels whose `(x, y)` shows the scene at `map(x, y)`.
+    fn render(width: usize, height: usize, map: impl Fn(f64, f64) -> (f64, f64)) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = map((i % width) as f64, (i / width) as f64);
+                let v = scene(x, y).round() as u8;
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    /// Mean absolute difference over the central half of the image.
+    fn error(a: &[u8], b: &[u8]) -> f64 {
+        let range = SIZE / 4..SIZE * 3 / 4;
+        let diffs: Vec<u8> = range
+            .clone()
+            .flat_map(|y| range.clone().map(move |x| (y * SIZE + x) * 4))
+            .map(|i| a[i].abs_diff(b[i]))
+            .collect();
+        diffs.iter().map(|&d| d as f64).sum::<f64>() / diffs.len() as f64
+    }
+
+    #[test]
+    fn test_recovers_shift_of_larger_image() {
+        let reference = render(SIZE, SIZE, |x, y| (x + 100.0, y + 100.0));
+        // The moving image shows the same scene 7 pixels further right and 4 up, with more around it.
+        let moving = render(80, 72, |x, y| (x + 100.0 - 7.0 - 5.0, y + 100.0 + 4.0 - 3.0));
+        for method in ["phase", "ecc"] {
+            let mut img = reference.clone();
+            run(&mut img, &moving, (80, 72), &format!(r#"{{"method": "{method}"}}"#)).unwrap();
+            assert!(error(&img, &reference) < 1.5, "{method}: {}", error(&img, &reference));
+        }
+    }
+
+    #[test]
+    fn test_ecc_recovers_rotation_and_scale() {
+        let reference = render(SIZE, SIZE, |x, y| (x + 100.0, y + 100.0));
+        let c = (SIZE as f64 - 1.0) / 2.0;
+        let (angle, scale) = (5f64.to_radians(), 1.06);
+        // The moving image is the reference turned and magnified about its center, and shifted.
+        let moving = render(SIZE, SIZE, |x, y| {
+            let (u, v) = ((x - c - 2.0) / scale, (y - c + 1.0) / scale);
+            let (ru, rv) = (angle.cos() * u + angle.sin() * v, -angle.sin() * u + angle.cos() * v);
+            (ru + c + 100.0, rv + c + 100.0)
+        });
+        let mut phase = reference.clone();
+        run(&mut phase, &moving, (SIZE as u32, SIZE as u32), r#"{"method": "phase"}"#).unwrap();
+        let mut euclidean = reference.clone();
+        run(&mut euclidean, &moving, (SIZE as u32, SIZE as u32), r#"{"motion": "euclidean"}"#).unwrap();
+        let mut similarity = reference.clone();
+        run(&mut similarity, &moving, (SIZE as u32, SIZE as u32), "{}").unwrap();
+        let errors = [error(&phase, &reference), error(&euclidean, &reference), error(&similarity, &reference)];
+        assert!(errors[2] < 1.5 && errors[2] < errors[1] && errors[1] < errors[0], "{errors:?}");
+
+        // Without a secondary input there is nothing to align.
+        let mut img = reference.clone();
+        assert!(testing::run(process, SIZE as u32, SIZE as u32, &mut img, "{}").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Which values the black and white points are measured on.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// The luma; all channels get the same stretch, so the color balance is kept.
+    #[default]
+    Luminance,
+    /// Each channel on its own, which also removes color casts.
+    Channels,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// Percentage of visible pixels allowed to clip at each end; a little ignores stray
+    /// specks of black and white.
+    #[serde(default = "default_clip")]
+    clip: f32,
+}
+
+fn default_clip() -> f32 {
+    0.5
+}
+
+/// Smallest measured range that is still stretched; flatter images are left alone.
+const MIN_RANGE: f32 = 1.0 / 255.0;
+
+const MANIFEST: &CStr = cr#"name = "auto_levels_plugin"
+version = "0.1.0"
+description = "Stretches the histogram between percentiles of the luma or of each channel"
+
+[defaults]
+mode = "luminance"
+clip = 0.5
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Stretches the levels of both formats.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    if params.clip.is_nan() || !(0.0..50.0).contains(&params.clip) {
+        return Err(PluginError::Invalid("clip must be in 0..50"));
+    }
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => auto_levels(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => auto_levels(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Measures the black and white points as the `clip` and `100 - clip` percentiles of the
+/// sRGB-encoded values of visible pixels and stretches them linearly to 0 and 1.
+fn auto_levels<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let fraction = params.clip / 100.0;
+    let stretch = match params.mode {
+        Mode::Luminance => {
+            let range = percentiles(luma_values(buf, premultiplied), fraction);
+            [range; 3]
+        }
+        Mode::Channels => std::array::from_fn(|c| percentiles(channel_values(buf, c, premultiplied), fraction)),
+    };
+    if stretch.iter().all(Option::is_none) {
+        return;
+    }
+
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        std::array::from_fn(|c| match stretch[c] {
+            Some((black, white)) => {
+                let v = (plugin_sdk::to_encoded::<T>(rgb[c]) - black) / (white - black);
+                plugin_sdk::from_encoded::<T>(v.clamp(0.0, 1.0))
+            }
+            None => rgb[c],
+        })
+    });
+}
+
+/// Straight, sRGB-encoded values of the visible pixels, normalized to `[0, 1]`.
+fn visible<T: Sample>(buf: &[T], premultiplied: bool) -> impl Iterator<Item = [f32; 3]> + '_ {
+    buf.chunks_exact(4).filter_map(move |px| {
+        let alpha = px[3].to_f32() / T::MAX;
+        let scale = if premultiplied { T::MAX * alpha } else { T::MAX };
+        (alpha > 0.0).then(|| std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)))
+    })
+}
+
+fn luma_values<T: Sample>(buf: &[T], premultiplied: bool) -> Vec<f32> {
+    visible(buf, premultiplied).map(|rgb| (0..3).map(|c| LUMA[c] * rgb[c]).sum()).collect()
+}
+
+fn channel_values<T: Sample>(buf: &[T], channel: usize, premultiplied: bool) -> Vec<f32> {
+    visible(buf, premultiplied).map(|rgb| rgb[channel]).collect()
+}
+
+/// Returns the values with `fraction` of `values` below and above them, or `None` if there are
+/// no values or they span less than [`MIN_RANGE`].
+fn percentiles(mut values: Vec<f32>, fraction: f32) -> Option<(f32, f32)> {
+    if values.is_empty() {
+        return None;
+    }
+    let last = values.len() - 1;
+    let skip = ((values.len() as f32 * fraction) as usize).min(last / 2);
+    let black = *values.select_nth_unstable_by(skip, f32::total_cmp).1;
+    let white = *values.select_nth_unstable_by(last - skip, f32::total_cmp).1;
+    (white - black >= MIN_RANGE).then_some((black, white))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 100-pixel gray ramp from 50 to 149 with a tint added to red.
+    fn ramp(tint: u8) -> Vec<u8> {
+        (50..150u8).flat_map(|v| [v + tint, v, v, 255]).collect()
+    }
+
+    #[test]
+    fn test_luminance_stretch_keeps_balance() {
+        let mut img = ramp(0);
+        run(&mut img, 100, 1, r#"{"clip": 0.0}"#).unwrap();
+        assert_eq!(&img[..4], &[0, 0, 0, 255]);
+        assert_eq!(&img[99 * 4..], &[255, 255, 255, 255]);
+
+        // With clipping, the darkest and brightest pixels saturate.
+        let mut img = ramp(0);
+        run(&mut img, 100, 1, r#"{"clip": 5.0}"#).unwrap();
+        assert_eq!((img[4 * 4], img[5 * 4]), (0, 0));
+        assert!(img[6 * 4] > 0);
+        assert_eq!(img[94 * 4], 255);
+
+        // One stretch for all channels: the red tint grows with the contrast but stays.
+        let mut img = ramp(20);
+        run(&mut img, 100, 1, r#"{"clip": 0.0}"#).unwrap();
+        let middle = &img[50 * 4..51 * 4];
+        assert!(middle[0] > middle[1] + 20 && middle[1] == middle[2], "{middle:?}");
+    }
+
+    #[test]
+    fn test_channels_remove_cast() {
+        let mut img = ramp(20);
+        run(&mut img, 100, 1, r#"{"mode": "channels", "clip": 0.0}"#).unwrap();
+        assert!(img.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2]), "{:?}", &img[..40]);
+
+        // A flat image and a transparent one are left alone.
+        let flat = [120u8, 60, 30, 255].repeat(10);
+        let mut img = flat.clone();
+        run(&mut img, 10, 1, r#"{"mode": "channels"}"#).unwrap();
+        assert_eq!(img, flat);
+        let mut img = [0u8; 8];
+        run(&mut img, 2, 1, "{}").unwrap();
+        assert_eq!(img, [0; 8]);
+
+        assert!(run(&mut img, 2, 1, r#"{"clip": 50.0}"#).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Standard deviation of the spatial Gaussian in pixels.
+    #[serde(default = "default_sigma_spatial")]
+    sigma_spatial: f32,
+    /// Standard deviation of the range Gaussian, as a distance between sRGB-encoded colors in
+    /// units of full scale; edges with a larger step than this are kept.
+    #[serde(default = "default_sigma_range")]
+    sigma_range: f32,
+    /// Half-width of the window; 0 uses twice `sigma_spatial`.
+    #[serde(default)]
+    radius: u32,
+    /// Number of times the filter is applied; repeated passes flatten areas further.
+    #[serde(default = "default_iterations")]
+    iterations: u32,
+}
+
+fn default_sigma_spatial() -> f32 {
+    3.0
+}
+
+fn default_sigma_range() -> f32 {
+    0.1
+}
+
+fn default_iterations() -> u32 {
+    1
+}
+
+impl Params {
+    fn radius(&self) -> u32 {
+        match self.radius {
+            0 => (2.0 * self.sigma_spatial).ceil().max(0.0) as u32,
+            r => r,
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "bilateral_plugin"
+version = "0.1.0"
+description = "Edge-preserving bilateral smoothing"
+
+[defaults]
+sigma_spatial = 3.0
+sigma_range = 0.1
+radius = 0
+iterations = 1
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Smooths both formats on the host's thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    let run = move || match pixels {
+        Pixels::Rgba8(buf) => smooth(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => smooth(w, h, buf, params, premultiplied),
+    };
+
+    plugin_sdk::install(ctx, run)
+}
+
+/// Each iteration reaches `radius` pixels further.
+fn halo(params: &Params) -> Option<u32> {
+    Some(params.radius().saturating_mul(params.iterations))
+}
+
+/// Replaces every pixel with an average of its window, weighted by a Gaussian of the distance
+/// to the pixel and a Gaussian of the color difference, so neighbors across an edge barely count.
+///
+/// Color differences are measured between straight sRGB-encoded colors, alpha included, so a
+/// setting behaves the same in either working space. The stored values are averaged as they are,
+/// which keeps premultiplied data premultiplied.
+fn smooth<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    let r = params.radius() as i64;
+    if width == 0 || height == 0 || r == 0 || params.sigma_spatial <= 0.0 || params.sigma_range <= 0.0 {
+        return;
+    }
+    let row_len = width * 4;
+    let spatial: Vec<f32> = (-r..=r)
+        .flat_map(|dy| (-r..=r).map(move |dx| (dx * dx + dy * dy) as f32))
+        .map(|d2| (-d2 / (2.0 * params.sigma_spatial * params.sigma_spatial)).exp())
+        .collect();
+    let range_scale = -1.0 / (2.0 * params.sigma_range * params.sigma_range);
+
+    for _ in 0..params.iterations {
+        let src: Vec<f32> = buf.par_iter().map(|v| v.to_f32()).collect();
+        let guide: Vec<[f32; 4]> = src.par_chunks_exact(4).map(|px| encoded::<T>(px, premultiplied)).collect();
+
+        buf.par_chunks_exact_mut(row_len).enumerate().for_each(|(y, row)| {
+            for (x, out) in row.chunks_exact_mut(4).enumerate() {
+                let center = guide[y * width + x];
+                let mut acc = [0.0f32; 4];
+                let mut total = 0.0;
+                for dy in -r..=r {
+                    let sy = y as i64 + dy;
+                    if sy < 0 || sy >= height as i64 {
+                        continue;
+                    }
+                    for dx in -r..=r {
+                        let sx = x as i64 + dx;
+                        if sx < 0 || sx >= width as i64 {
+                            continue;
+                        }
+                        let i = sy as usize * width + sx as usize;
+                        let d2: f32 = guide[i].iter().zip(&center).map(|(a, b)| (a - b) * (a - b)).sum();
+                        let weight = spatial[((dy + r) * (2 * r + 1) + dx + r) as usize] * (d2 * range_scale).exp();
+                        for (a, v) in acc.iter_mut().zip(&src[i * 4..i * 4 + 4]) {
+                            *a += v * weight;
+                        }
+                        total += weight;
+                    }
+                }
+                // The center pixel always contributes with weight 1, so `total` is positive.
+                for (o, a) in out.iter_mut().zip(acc) {
+                    *o = T::from_f32(a / total);
+                }
+            }
+        });
+    }
+}
+
+/// A pixel's straight-alpha color, sRGB-encoded and normalized to `[0, 1]`, followed by its alpha.
+fn encoded<T: Sample>(px: &[f32], premultiplied: bool) -> [f32; 4] {
+    let alpha = px[3] / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    let color = |v: f32| plugin_sdk::to_encoded::<T>(v / scale);
+    [color(px[0]), color(px[1]), color(px[2]), alpha]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// An 8x8 image, dark on the left half and bright on the right, with a little noise.
+    fn noisy_edge() -> Vec<u8> {
+        (0..64)
+            .flat_map(|i| {
+                let base = if i % 8 < 4 { 60 } else { 200 };
+                let v = base + [0, 6, 3, 9][i % 4] - 4;
+                [v as u8, v as u8, v as u8, 255]
+            })
+            .collect()
+    }
+
+    fn spread(img: &[u8], columns: std::ops::Range<usize>) -> u8 {
+        let values: Vec<u8> =
+            img.chunks(4).enumerate().filter(|(i, _)| columns.contains(&(i % 8))).map(|(_, p)| p[0]).collect();
+        values.iter().max().unwrap() - values.iter().min().unwrap()
+    }
+
+    #[test]
+    fn test_smooths_noise_and_keeps_edge() {
+        let mut img = noisy_edge();
+        run(&mut img, 8, 8, r#"{"sigma_spatial": 2, "sigma_range": 0.1}"#).unwrap();
+        assert!(spread(&img, 0..4) < spread(&noisy_edge(), 0..4));
+        // The pixels next to the edge stay on their side of it.
+        assert!(img[3 * 4] < 80 && img[4 * 4] > 180, "{:?}", &img[..32]);
+
+        // A huge range sigma turns it into a plain Gaussian blur, which softens the edge.
+        let mut blurred = noisy_edge();
+        run(&mut blurred, 8, 8, r#"{"sigma_spatial": 2, "sigma_range": 100}"#).unwrap();
+        assert!(blurred[3 * 4] > 90);
+    }
+
+    #[test]
+    fn test_flat_image_unchanged() {
+        let src = [90u8, 60, 30, 200].repeat(25);
+        let mut img = src.clone();
+        run(&mut img, 5, 5, "{}").unwrap();
+        assert_eq!(img, src);
+
+        let params = plugin_sdk::params_from_str(r#"{"sigma_spatial": 1.5, "iterations": 2}"#).unwrap();
+        assert_eq!(halo(&params), Some(6));
+    }
+}
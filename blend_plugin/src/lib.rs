@@ -0,0 +1,272 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+/// How the secondary input (the layer) is combined with the image below it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// The layer is drawn over the image.
+    #[default]
+    Normal,
+    /// Darkens: the product of both colors.
+    Multiply,
+    /// Lightens: the inverse of the product of the inverted colors.
+    Screen,
+    /// Multiplies dark and screens light parts of the image, raising its contrast by the layer.
+    Overlay,
+    /// The darker of both colors, per channel.
+    Darken,
+    /// The lighter of both colors, per channel.
+    Lighten,
+    /// The absolute difference of both colors; black where the images agree.
+    Difference,
+    /// Keeps the image where the layer is white and makes it transparent where it is black.
+    Mask,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// How much of the blended result replaces the image; 0 leaves it unchanged.
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+}
+
+fn default_opacity() -> f32 {
+    1.0
+}
+
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+const MANIFEST: &CStr = cr#"name = "blend_plugin"
+version = "0.1.0"
+description = "Blends the secondary input (--input2) over the image, or applies it as a mask"
+
+[defaults]
+mode = "normal"
+opacity = 1.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Blends the secondary input into the image, in both formats. It is not local: the layer is
+/// stretched over the whole image, so a pixel's partner depends on its position in it.
+///
+/// Fails if the host passed no secondary input.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    // SAFETY: the host keeps the secondary input valid for the duration of the call.
+    let Some(layer) = (unsafe { ctx.input2_image() }) else {
+        return Err(PluginError::Invalid("no secondary input to blend"));
+    };
+    if params.opacity.is_nan() {
+        return Err(PluginError::Invalid("opacity must be a number"));
+    }
+    let premultiplied = ctx.premultiplied();
+
+    let size = (image.width as usize, image.height as usize);
+    let layer_size = (layer.width as usize, layer.height as usize);
+    match (image.pixels, layer.pixels) {
+        (Pixels::Rgba8(buf), PixelsRef::Rgba8(layer)) => blend(size, buf, layer_size, layer, params, premultiplied),
+        (Pixels::Rgba32F(buf), PixelsRef::Rgba32F(layer)) => blend(size, buf, layer_size, layer, params, premultiplied),
+        _ => return Err(PluginError::Format),
+    }
+    Ok(())
+}
+
+/// Blends `layer`, stretched to the image size, into `buf`.
+///
+/// The blend modes work on sRGB-encoded straight colors, like the layer modes of image editors,
+/// and composite the result over the image with the layer's alpha times `opacity`.
+fn blend<T: Sample>(
+    (width, height): (usize, usize),
+    buf: &mut [T],
+    layer_size: (usize, usize),
+    layer: &[T],
+    params: &Params,
+    premultiplied: bool,
+) {
+    if width == 0 || height == 0 || layer_size.0 == 0 || layer_size.1 == 0 {
+        return;
+    }
+    let opacity = params.opacity.clamp(0.0, 1.0);
+    let layer = Layer::new(layer_size, layer, premultiplied);
+
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let sampled = layer.sample((i % width, i / width), (width, height));
+        let top: [f32; 4] =
+            std::array::from_fn(|c| if c < 3 { plugin_sdk::to_encoded::<T>(sampled[c]) } else { sampled[3] });
+        let alpha = px[3].to_f32() / T::MAX;
+
+        if params.mode == Mode::Mask {
+            let luma: f32 = (0..3).map(|c| LUMA[c] * top[c]).sum();
+            let keep = 1.0 - opacity * (1.0 - luma * top[3]);
+            let channels = if premultiplied { 0..4 } else { 3..4 };
+            for c in channels {
+                px[c] = T::from_f32(px[c].to_f32() * keep);
+            }
+            continue;
+        }
+
+        let below: [f32; 3] = std::array::from_fn(|c| {
+            let v = px[c].to_f32() / T::MAX;
+            let v = if premultiplied && alpha > 0.0 { v / alpha } else { v };
+            plugin_sdk::to_encoded::<T>(v)
+        });
+        // Where the image is transparent the layer shows unblended (W3C compositing).
+        let source: [f32; 3] =
+            std::array::from_fn(|c| (1.0 - alpha) * top[c] + alpha * mix(params.mode, below[c], top[c]));
+        let top_alpha = top[3] * opacity;
+        let out_alpha = top_alpha + alpha * (1.0 - top_alpha);
+        for c in 0..3 {
+            let color = if out_alpha > 0.0 {
+                (top_alpha * source[c] + (1.0 - top_alpha) * alpha * below[c]) / out_alpha
+            } else {
+                0.0
+            };
+            let color = plugin_sdk::from_encoded::<T>(color);
+            let color = if premultiplied { color * out_alpha } else { color };
+            px[c] = T::from_f32(color * T::MAX);
+        }
+        px[3] = T::from_f32(out_alpha * T::MAX);
+    }
+}
+
+/// Blends the encoded channel values `below` (the image) and `top` (the layer).
+fn mix(mode: Mode, below: f32, top: f32) -> f32 {
+    match mode {
+        Mode::Normal | Mode::Mask => top,
+        Mode::Multiply => below * top,
+        Mode::Screen => below + top - below * top,
+        Mode::Overlay if below <= 0.5 => 2.0 * below * top,
+        Mode::Overlay => 1.0 - 2.0 * (1.0 - below) * (1.0 - top),
+        Mode::Darken => below.min(top),
+        Mode::Lighten => below.max(top),
+        Mode::Difference => (below - top).abs(),
+    }
+}
+
+/// The secondary input as normalized premultiplied values, for bilinear sampling.
+struct Layer {
+    width: usize,
+    height: usize,
+    data: Vec<[f32; 4]>,
+}
+
+impl Layer {
+    fn new<T: Sample>((width, height): (usize, usize), src: &[T], premultiplied: bool) -> Self {
+        let data = src
+            .chunks_exact(4)
+            .map(|px| {
+                let a = px[3].to_f32() / T::MAX;
+                let scale = if premultiplied { 1.0 } else { a };
+                let v = |c: usize| px[c].to_f32() / T::MAX * scale;
+                [v(0), v(1), v(2), a]
+            })
+            .collect();
+        Self { width, height, data }
+    }
+
+    /// Returns the normalized straight color of the layer stretched over a `size` image at
+    /// pixel `(x, y)`.
+    fn sample(&self, (x, y): (usize, usize), size: (usize, usize)) -> [f32; 4] {
+        let position = |p: usize, from: usize, to: usize| {
+            ((p as f32 + 0.5) * to as f32 / from as f32 - 0.5).clamp(0.0, (to - 1) as f32)
+        };
+        let (fx, fy) = (position(x, size.0, self.width), position(y, size.1, self.height));
+        let (x0, y0) = (fx as usize, fy as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+        let at = |x: usize, y: usize| self.data[y * self.width + x];
+        let (a, b, c, d) = (at(x0, y0), at(x1, y0), at(x0, y1), at(x1, y1));
+        let mut px: [f32; 4] = std::array::from_fn(|i| {
+            let top = a[i] + (b[i] - a[i]) * tx;
+            let bottom = c[i] + (d[i] - c[i]) * tx;
+            top + (bottom - top) * ty
+        });
+        for c in 0..3 {
+            px[c] = if px[3] > 0.0 { px[c] / px[3] } else { 0.0 };
+        }
+        px
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    /// Blends `layer` into an RGBA8 image through a context carrying it as the secondary input.
+    fn run(
+        buf: &mut [u8],
+        size: (u32, u32),
+        layer: &[u8],
+        layer_size: (u32, u32),
+        params: &str,
+    ) -> Result<(), PluginError> {
+        let ctx = CallContext {
+            input2_data: layer.as_ptr(),
+            input2_width: layer_size.0,
+            input2_height: layer_size.1,
+            ..CallContext::new(0)
+        };
+        testing::run_with(process, &ctx, size.0, size.1, buf, params)
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let below = [200u8, 100, 0, 255];
+        let top = [100u8, 100, 255, 255];
+        let blended = |mode: &str| {
+            let mut img = below.to_vec();
+            run(&mut img, (1, 1), &top, (1, 1), &format!(r#"{{"mode": "{mode}"}}"#)).unwrap();
+            img
+        };
+        assert_eq!(blended("normal"), top);
+        assert_eq!(blended("multiply"), [78, 39, 0, 255]);
+        assert_eq!(blended("screen"), [222, 161, 255, 255]);
+        assert_eq!(blended("darken"), [100, 100, 0, 255]);
+        assert_eq!(blended("lighten"), [200, 100, 255, 255]);
+        assert_eq!(blended("difference"), [100, 0, 255, 255]);
+        // Overlay multiplies the dark image channels and screens the light ones.
+        assert_eq!(blended("overlay"), [188, 78, 0, 255]);
+
+        let mut img = below.to_vec();
+        run(&mut img, (1, 1), &top, (1, 1), r#"{"mode": "normal", "opacity": 0.0}"#).unwrap();
+        assert_eq!(img, below);
+    }
+
+    #[test]
+    fn test_transparent_layer_and_image() {
+        // A transparent layer leaves the image alone; over a transparent image it shows as is.
+        let below = [10u8, 20, 30, 255];
+        let mut img = below.to_vec();
+        run(&mut img, (1, 1), &[255, 255, 255, 0], (1, 1), r#"{"mode": "multiply"}"#).unwrap();
+        assert_eq!(img, below);
+
+        let mut img = vec![0u8; 4];
+        run(&mut img, (1, 1), &[40, 50, 60, 128], (1, 1), r#"{"mode": "multiply"}"#).unwrap();
+        assert_eq!(img, [40, 50, 60, 128]);
+    }
+
+    #[test]
+    fn test_mask_and_stretched_layer() {
+        // A 2x1 black-to-white layer stretched over a 4x1 image.
+        let mut img = [90u8, 120, 150, 255].repeat(4);
+        let layer = [0u8, 0, 0, 255, 255, 255, 255, 255];
+        run(&mut img, (4, 1), &layer, (2, 1), r#"{"mode": "mask"}"#).unwrap();
+        let alphas: Vec<u8> = img.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alphas[0], 0);
+        assert_eq!(alphas[3], 255);
+        assert!(alphas[1] > 0 && alphas[1] < alphas[2] && alphas[2] < 255, "{alphas:?}");
+        assert!(img.chunks(4).all(|p| p[..3] == [90, 120, 150]));
+
+        // Without a secondary input the plugin fails.
+        assert!(testing::run(process, 4, 1, &mut img, "{}").is_err());
+    }
+}
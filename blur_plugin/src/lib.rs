@@ -1,130 +1,367 @@
-use std::ffi::CStr;
 use std::os::raw::c_char;
+use plugin_sdk::{
+    CallContext, Capabilities, Pixels, Sample, FORMAT_MASK_RGBA32F, FORMAT_MASK_RGBA8, PIXEL_FORMAT_RGBA8,
+};
+use rayon::prelude::*;
 use serde::Deserialize;
 
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Separable Gaussian; the cost per pixel grows with the radius.
+    #[default]
+    Gaussian,
+    /// Repeated box averages from running sums; the cost per pixel does not depend on the radius.
+    Box,
+}
+
 #[derive(Deserialize, Debug)]
 struct Params {
     radius: u32,
     iterations: u32,
+    /// Standard deviation of the Gaussian; defaults to half the radius.
+    #[serde(default)]
+    sigma: Option<f32>,
+    #[serde(default)]
+    mode: Mode,
+    /// Box passes per iteration in box mode; three approximate a Gaussian with sigma close to the radius.
+    #[serde(default = "default_passes")]
+    passes: u32,
+}
+
+fn default_passes() -> u32 {
+    3
+}
+
+impl Params {
+    fn sigma(&self) -> f32 {
+        self.sigma.filter(|s| *s > 0.0).unwrap_or(self.radius as f32 / 2.0)
+    }
+
+    /// How far one iteration reaches from each pixel.
+    fn reach(&self) -> u32 {
+        match self.mode {
+            Mode::Gaussian => self.radius,
+            Mode::Box => self.radius.saturating_mul(self.passes),
+        }
+    }
+}
+
+/// Embedded plugin manifest; the host applies `defaults` before the params file.
+#[unsafe(no_mangle)]
+pub extern "C" fn plugin_manifest() -> *const c_char {
+    cr#"name = "blur_plugin"
+version = "0.1.0"
+description = "Separable Gaussian blur, or a radius-independent box approximation"
+
+[defaults]
+radius = 3
+iterations = 1
+mode = "gaussian"
+passes = 3
+"#.as_ptr()
 }
 
 #[unsafe(no_mangle)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn process_image(
     width: u32,
     height: u32,
     rgba_data: *mut u8,
     params: *const c_char,
 ) -> u32{
-    if rgba_data.is_null() {
-        return 1;
-    }
-
-    let params_str = unsafe {
-        if params.is_null() {
-            ""
-        } else {
-            // SAFETY:
-            // - We checked `params` is not null.
-            // - FFI contract requires `params` to be a valid NUL-terminated C string
-            //   that lives at least for the duration of this call.
-            // - `from_ptr` only reads memory until the first NUL bytes.
-            CStr::from_ptr(params).to_str().unwrap_or("")
-        }
-    };
-
-    let params: Params = match toml::from_str(&params_str) {
-        Ok(config) => config,
-        Err(_) => {
-            return 1;
-        }
-    };
-
-    let w = width as usize;
-    let h = height as usize;
-    let len = w.checked_mul(h).and_then(|wh| wh.checked_mul(4));
+    process_image_ctx(std::ptr::null(), width, height, rgba_data, params)
+}
 
-    let Some(total_len) = len else {
+/// Context-aware entry point; accepts RGBA8 and linear RGBA32F buffers.
+#[unsafe(no_mangle)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn process_image_ctx(
+    ctx: *const CallContext,
+    width: u32,
+    height: u32,
+    rgba_data: *mut u8,
+    params: *const c_char,
+) -> u32 {
+    // SAFETY: the FFI contract requires `ctx` to be null or valid for the duration of this call.
+    let ctx = unsafe { CallContext::from_ptr(ctx) };
+    let format = ctx.map_or(PIXEL_FORMAT_RGBA8, CallContext::pixel_format);
+    let max_threads = ctx.map_or(0, CallContext::max_threads);
+
+    // SAFETY: the FFI contract requires `params` to be null or a valid NUL-terminated string.
+    let Ok(params) = (unsafe { plugin_sdk::parse_params::<Params>(params) }) else {
         return 1;
     };
 
     // SAFETY:
-    // - We checked `rgba_data` is not null above.
-    // - FFI contract requires `rgba_data` to point to at least `len` writable bytes.
+    // - FFI contract requires `rgba_data` to point to `width * height * 4` writable
+    //   elements of the type selected by `format`, aligned for that type.
     // - The buffer is assumed valid for the duration of this call.
-    // - `u8` has alignment 1, so alignment requirements are trivially satisfied.
     // - No other mutable references to this buffer may exist during this call
     //   (caller must ensure no aliasing).
-    let buf = unsafe { std::slice::from_raw_parts_mut(rgba_data, total_len) };
+    let pixels = unsafe { Pixels::from_raw(format, width, height, rgba_data) };
 
-    blur_in_place(w, h, buf, params.radius, params.iterations);
+    let (w, h) = (width as usize, height as usize);
+    let Some(pixels) = pixels else {
+        return 1;
+    };
+    let run = move || match (params.mode, pixels) {
+        (Mode::Gaussian, Pixels::Rgba8(buf)) => blur_in_place(w, h, buf, &params),
+        (Mode::Gaussian, Pixels::Rgba32F(buf)) => blur_in_place(w, h, buf, &params),
+        (Mode::Box, Pixels::Rgba8(buf)) => box_blur_in_place(w, h, buf, &params),
+        (Mode::Box, Pixels::Rgba32F(buf)) => box_blur_in_place(w, h, buf, &params),
+    };
 
+    if max_threads == 0 {
+        run();
+    } else {
+        match rayon::ThreadPoolBuilder::new().num_threads(max_threads as usize).build() {
+            Ok(pool) => pool.install(run),
+            Err(_) => return 1,
+        }
+    }
+
+    0
+}
+
+/// Reports that the blur accepts both 8-bit and float buffers, and that it is local:
+/// each Gaussian iteration reaches `radius` pixels and each box iteration `radius * passes`.
+#[unsafe(no_mangle)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn plugin_capabilities(params: *const c_char, caps: *mut Capabilities) -> u32 {
+    // SAFETY: the FFI contract requires `caps` to be null or point to a writable `Capabilities`.
+    let Some(caps) = (unsafe { caps.as_mut() }) else {
+        return 1;
+    };
+    caps.pixel_formats = FORMAT_MASK_RGBA8 | FORMAT_MASK_RGBA32F;
+
+    // SAFETY: the FFI contract requires `params` to be null or a valid NUL-terminated string.
+    if let Ok(params) = unsafe { plugin_sdk::parse_params::<Params>(params) } {
+        caps.set_local(params.reach().saturating_mul(params.iterations));
+    }
     0
 }
 
-fn blur_in_place(width: usize, height: usize, buf: &mut [u8], radius: u32, iterations: u32) {
-    if width == 0 || height == 0 || radius == 0 || iterations == 0 {
+/// Width of the fixed-size accumulator blocks; sized so the compiler keeps them
+/// in SIMD registers (two AVX or four SSE/NEON registers of `f32`).
+const LANES: usize = 16;
+
+/// Columns accumulated together in the vertical pass, small enough to stay in L1.
+const COLUMN_BLOCK: usize = 1024;
+
+/// Two-pass separable Gaussian blur, `iterations` times.
+///
+/// Taps outside the image are dropped and the remaining weights renormalized,
+/// so edges are not darkened. Intermediate values stay in `f32`.
+fn blur_in_place<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params) {
+    if width == 0 || height == 0 || params.radius == 0 || params.iterations == 0 {
         return;
     }
 
-    let r = radius as i32;
-    let row_bytes = width * 4;
-    let expected_len = row_bytes * height;
+    let row_len = width * 4;
+    let expected_len = row_len * height;
 
     if buf.len() < expected_len {
         return;
     }
 
-    let mut tmp = vec![0u8; expected_len];
+    let kernel = gaussian_kernel(params.radius, params.sigma());
+    let col_norms = edge_norms(width, &kernel);
+    let row_norms = edge_norms(height, &kernel);
+    let mut tmp = vec![0.0f32; expected_len];
+
+    let padded_len = row_len + (kernel.len() - 1) * 4;
+
+    // Output rows are independent within each pass, so both are split by row across threads.
+    for _ in 0..params.iterations {
+        buf[..expected_len]
+            .par_chunks_exact(row_len)
+            .zip(tmp.par_chunks_exact_mut(row_len))
+            .for_each_init(
+                || vec![0.0f32; padded_len],
+                |padded, (src, dst)| horizontal_pass(src, dst, &kernel, &col_norms, padded),
+            );
+        buf[..expected_len]
+            .par_chunks_exact_mut(row_len)
+            .enumerate()
+            .for_each_init(
+                || vec![0.0f32; row_len],
+                |acc, (y, dst)| vertical_pass(&tmp, dst, y, &kernel, row_norms[y], acc),
+            );
+    }
+}
 
-    for _ in 0..iterations {
-        let src: &[u8] = &buf[..expected_len];
-        let dst: &mut [u8] = &mut tmp[..];
+/// Box blur with `passes` box averages per iteration, each `2 * radius + 1` pixels wide.
+///
+/// Every box sum is the difference of two running sums (the separable form of a summed-area
+/// table), so the cost per pixel is the same for any radius. Boxes are clipped at the image
+/// edges and averaged over the pixels they cover. Values stay in `f32` between passes.
+fn box_blur_in_place<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params) {
+    if width == 0 || height == 0 || params.radius == 0 || params.iterations == 0 || params.passes == 0 {
+        return;
+    }
 
-        for y in 0..height as i32 {
-            for x in 0..width as i32 {
-                let mut acc = [0.0f32; 4];
-                let mut wsum = 0.0f32;
-
-                let y0 = (y - r).max(0);
-                let y1 = (y + r).min(height as i32 - 1);
-                let x0 = (x - r).max(0);
-                let x1 = (x + r).min(width as i32 - 1);
-
-                for ny in y0..=y1 {
-                    for nx in x0..=x1 {
-                        let dx = (nx - x) as f32;
-                        let dy = (ny - y) as f32;
-                        let dist = (dx * dx + dy * dy).sqrt();
-                        let w = 1.0f32 / (1.0f32 + dist);
-
-                        let idx = ((ny as usize) * width + (nx as usize)) * 4;
-                        acc[0] += src[idx + 0] as f32 * w;
-                        acc[1] += src[idx + 1] as f32 * w;
-                        acc[2] += src[idx + 2] as f32 * w;
-                        acc[3] += src[idx + 3] as f32 * w;
-                        wsum += w;
-                    }
-                }
+    let row_len = width * 4;
+    let expected_len = row_len * height;
+    if buf.len() < expected_len {
+        return;
+    }
+
+    let r = params.radius as usize;
+    let mut work: Vec<f32> = buf[..expected_len].par_iter().map(|v| v.to_f32()).collect();
+    let mut tmp = vec![0.0f32; expected_len];
+    // Each band of rows starts its vertical window from scratch, so keep the bands few and tall.
+    let band_rows = height.div_ceil(rayon::current_num_threads());
+
+    for _ in 0..params.iterations.saturating_mul(params.passes) {
+        work.par_chunks_exact(row_len)
+            .zip(tmp.par_chunks_exact_mut(row_len))
+            .for_each_init(|| vec![0.0f64; row_len + 4], |prefix, (src, dst)| horizontal_box(src, dst, r, prefix));
+        work.par_chunks_mut(band_rows * row_len)
+            .enumerate()
+            .for_each_init(
+                || vec![0.0f64; row_len],
+                |acc, (band, dst)| vertical_box(&tmp, dst, band * band_rows, r, acc),
+            );
+    }
+
+    buf[..expected_len]
+        .par_iter_mut()
+        .zip(&work)
+        .for_each(|(out, v)| *out = T::from_f32(*v));
+}
+
+/// Averages each pixel of one row over the `2 * r + 1` pixels around it, clipped to the row.
+fn horizontal_box(src: &[f32], dst: &mut [f32], r: usize, prefix: &mut [f64]) {
+    let width = src.len() / 4;
+    for (i, v) in src.iter().enumerate() {
+        prefix[i + 4] = prefix[i] + *v as f64;
+    }
+    for x in 0..width {
+        let (lo, hi) = (x.saturating_sub(r), (x + r).min(width - 1));
+        let inv = 1.0 / (hi - lo + 1) as f64;
+        for c in 0..4 {
+            dst[x * 4 + c] = ((prefix[(hi + 1) * 4 + c] - prefix[lo * 4 + c]) * inv) as f32;
+        }
+    }
+}
 
-                let out_idx = ((y as usize) * width + (x as usize)) * 4;
-                let inv = if wsum > 0.0 { 1.0 / wsum } else { 0.0 };
+/// Computes the rows of `dst`, starting at row `y0`, as averages over the `2 * r + 1` rows
+/// of `src` around them, sliding one window sum down the band.
+fn vertical_box(src: &[f32], dst: &mut [f32], y0: usize, r: usize, acc: &mut [f64]) {
+    let row_len = acc.len();
+    let height = src.len() / row_len;
+    let row = |y: usize| &src[y * row_len..(y + 1) * row_len];
+
+    let (mut lo, mut hi) = (y0.saturating_sub(r), (y0 + r).min(height - 1));
+    acc.fill(0.0);
+    for y in lo..=hi {
+        for (a, v) in acc.iter_mut().zip(row(y)) {
+            *a += *v as f64;
+        }
+    }
 
-                dst[out_idx + 0] = (acc[0] * inv).round().clamp(0.0, 255.0) as u8;
-                dst[out_idx + 1] = (acc[1] * inv).round().clamp(0.0, 255.0) as u8;
-                dst[out_idx + 2] = (acc[2] * inv).round().clamp(0.0, 255.0) as u8;
-                dst[out_idx + 3] = (acc[3] * inv).round().clamp(0.0, 255.0) as u8;
+    for (y, out) in (y0..).zip(dst.chunks_exact_mut(row_len)) {
+        let inv = 1.0 / (hi - lo + 1) as f64;
+        for (o, a) in out.iter_mut().zip(acc.iter()) {
+            *o = (a * inv) as f32;
+        }
+        if y + r + 1 < height {
+            hi += 1;
+            for (a, v) in acc.iter_mut().zip(row(hi)) {
+                *a += *v as f64;
+            }
+        }
+        if y >= r {
+            for (a, v) in acc.iter_mut().zip(row(lo)) {
+                *a -= *v as f64;
+            }
+            lo += 1;
+        }
+    }
+}
+
+/// Returns the `2 * radius + 1` unnormalized taps of a Gaussian with the given sigma.
+fn gaussian_kernel(radius: u32, sigma: f32) -> Vec<f32> {
+    let r = radius as i64;
+    let denom = 2.0 * sigma * sigma;
+    (-r..=r).map(|d| (-((d * d) as f32) / denom).exp()).collect()
+}
+
+/// Sum of the kernel taps that fall inside `0..len` for every position.
+fn edge_norms(len: usize, kernel: &[f32]) -> Vec<f32> {
+    let r = kernel.len() / 2;
+    (0..len)
+        .map(|i| {
+            let lo = r.saturating_sub(i);
+            let hi = (r + len - 1 - i).min(kernel.len() - 1);
+            kernel[lo..=hi].iter().sum()
+        })
+        .collect()
+}
+
+/// Blurs one row into `dst`; `padded` is scratch space with `radius` zero pixels on each side.
+fn horizontal_pass<T: Sample>(src: &[T], dst: &mut [f32], kernel: &[f32], norms: &[f32], padded: &mut [f32]) {
+    let pad = kernel.len() / 2 * 4;
+    for (p, s) in padded[pad..pad + src.len()].iter_mut().zip(src) {
+        *p = s.to_f32();
+    }
+
+    let len = src.len();
+    let lanes_end = len - len % LANES;
+    for (i, out) in dst[..lanes_end].chunks_exact_mut(LANES).enumerate() {
+        let base = i * LANES;
+        let mut acc = [0.0f32; LANES];
+        for (k, &w) in kernel.iter().enumerate() {
+            let taps: &[f32; LANES] = padded[base + k * 4..][..LANES].try_into().expect("LANES-sized slice");
+            for (a, t) in acc.iter_mut().zip(taps) {
+                *a += t * w;
             }
         }
+        out.copy_from_slice(&acc);
+    }
+    for (i, out) in dst.iter_mut().enumerate().skip(lanes_end) {
+        *out = kernel.iter().enumerate().map(|(k, &w)| padded[i + k * 4] * w).sum();
+    }
 
-        buf[..expected_len].copy_from_slice(&tmp);
+    for (px, norm) in dst.chunks_exact_mut(4).zip(norms) {
+        let inv = 1.0 / norm;
+        for v in px {
+            *v *= inv;
+        }
     }
 }
 
+/// Computes output row `y` from the horizontally blurred rows in `src`.
+fn vertical_pass<T: Sample>(src: &[f32], dst: &mut [T], y: usize, kernel: &[f32], norm: f32, acc: &mut [f32]) {
+    let row_len = dst.len();
+    let height = src.len() / row_len;
+    let r = kernel.len() / 2;
+
+    acc.fill(0.0);
+    let rows = y.saturating_sub(r)..=(y + r).min(height - 1);
+    for (block, acc) in acc.chunks_mut(COLUMN_BLOCK).enumerate() {
+        let start = block * COLUMN_BLOCK;
+        for ny in rows.clone() {
+            let w = kernel[ny + r - y];
+            let row = &src[ny * row_len + start..][..acc.len()];
+            for (a, v) in acc.iter_mut().zip(row) {
+                *a += v * w;
+            }
+        }
+    }
+
+    let inv = 1.0 / norm;
+    for (out, a) in dst.iter_mut().zip(acc.iter()) {
+        *out = T::from_f32(a * inv);
+    }
+}
 
 #[cfg(test)]
+#[allow(clippy::identity_op)]
 mod tests {
     use super::*;
-    use std::ffi::CString;
+    use std::ffi::{CStr, CString};
 
     fn create_test_image() -> Vec<u8> {
         let mut data = vec![0u8; 3 * 3 * 4];
@@ -137,7 +374,7 @@ mod tests {
     fn test_blur_process_success() {
         // was generated by Gemini
         let mut img = create_test_image();
-        let params_str = CString::new("radius = 1\niterations = 1").unwrap();
+        let params_str = CString::new(r#"{"radius": 1, "iterations": 1}"#).unwrap();
         let result = process_image(3, 3, img.as_mut_ptr(), params_str.as_ptr());
 
         assert_eq!(result, 0, "Plugin should return 0 (success)");
@@ -150,9 +387,9 @@ mod tests {
     }
 
     #[test]
-    fn test_invalid_toml_params() {
+    fn test_invalid_params() {
         let mut img = create_test_image();
-        let params_str = CString::new("radius = 5").unwrap();
+        let params_str = CString::new(r#"{"radius": 5}"#).unwrap();
         let result = process_image(3, 3, img.as_mut_ptr(), params_str.as_ptr());
 
         assert_eq!(result, 1, "Should return 1 on invalid params");
@@ -160,7 +397,7 @@ mod tests {
 
     #[test]
     fn test_null_pointers() {
-        let params_str = CString::new("radius = 1\niterations = 1").unwrap();
+        let params_str = CString::new(r#"{"radius": 1, "iterations": 1}"#).unwrap();
         let result = process_image(3, 3, std::ptr::null_mut(), params_str.as_ptr());
         assert_eq!(result, 1);
 
@@ -172,7 +409,7 @@ mod tests {
     #[test]
     fn test_zero_dimensions() {
         let mut img = vec![0u8; 4];
-        let params_str = CString::new("radius = 1\niterations = 1").unwrap();
+        let params_str = CString::new(r#"{"radius": 1, "iterations": 1}"#).unwrap();
         let result = process_image(0, 3, img.as_mut_ptr(), params_str.as_ptr());
 
         assert_eq!(result, 0);
@@ -182,9 +419,158 @@ mod tests {
     fn test_large_radius_no_panic() {
         let mut img = create_test_image();
         // radius more than image size
-        let params_str = CString::new("radius = 100\niterations = 1").unwrap();
+        let params_str = CString::new(r#"{"radius": 100, "iterations": 1}"#).unwrap();
         let result = process_image(3, 3, img.as_mut_ptr(), params_str.as_ptr());
 
         assert_eq!(result, 0, "Should handle large radius using clamp/min/max");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_blur_rgba32f() {
+        let mut img = vec![0.0f32; 3 * 3 * 4];
+        let center_idx = (1 * 3 + 1) * 4;
+        img[center_idx..center_idx + 4].copy_from_slice(&[1.0; 4]);
+
+        let mut ctx = CallContext::new(0);
+        ctx.pixel_format = plugin_sdk::PIXEL_FORMAT_RGBA32F;
+        let params_str = CString::new(r#"{"radius": 1, "iterations": 1}"#).unwrap();
+        let result = process_image_ctx(&ctx, 3, 3, img.as_mut_ptr().cast(), params_str.as_ptr());
+
+        assert_eq!(result, 0);
+        assert!(img[center_idx] < 1.0 && img[center_idx] > 0.0);
+        assert!(img[0] > 0.0);
+        assert!(img.iter().all(|v| (0.0..=1.0).contains(v)));
+    }
+
+    #[test]
+    fn test_separable_matches_2d_gaussian() {
+        let (w, h) = (7usize, 5usize);
+        let src: Vec<f32> = (0..w * h * 4).map(|i| ((i * 37) % 101) as f32 / 100.0).collect();
+        let params = Params { radius: 2, iterations: 1, sigma: Some(1.2), mode: Mode::Gaussian, passes: 3 };
+        let mut fast = src.clone();
+        blur_in_place(w, h, &mut fast, &params);
+
+        let kernel = gaussian_kernel(2, 1.2);
+        for y in 0..h as isize {
+            for x in 0..w as isize {
+                let mut acc = [0.0f32; 4];
+                let mut wsum = 0.0;
+                for ny in (y - 2).max(0)..=(y + 2).min(h as isize - 1) {
+                    for nx in (x - 2).max(0)..=(x + 2).min(w as isize - 1) {
+                        let wt = kernel[(nx - x + 2) as usize] * kernel[(ny - y + 2) as usize];
+                        let idx = (ny as usize * w + nx as usize) * 4;
+                        for c in 0..4 {
+                            acc[c] += src[idx + c] * wt;
+                        }
+                        wsum += wt;
+                    }
+                }
+                let idx = (y as usize * w + x as usize) * 4;
+                for c in 0..4 {
+                    assert!((fast[idx + c] - acc[c] / wsum).abs() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_thread_limit_gives_same_result() {
+        let (w, h) = (16u32, 9u32);
+        let src: Vec<u8> = (0..w * h * 4).map(|i| (i * 31 % 256) as u8).collect();
+        let params_str = CString::new(r#"{"radius": 3, "iterations": 2}"#).unwrap();
+
+        let mut single = src.clone();
+        let ctx = CallContext { max_threads: 1, ..CallContext::new(0) };
+        assert_eq!(process_image_ctx(&ctx, w, h, single.as_mut_ptr(), params_str.as_ptr()), 0);
+
+        let mut unlimited = src;
+        assert_eq!(process_image(w, h, unlimited.as_mut_ptr(), params_str.as_ptr()), 0);
+        assert_eq!(single, unlimited);
+    }
+
+    #[test]
+    fn test_crop_matches_full_image_inside_halo() {
+        let (w, h) = (40, 30);
+        let params = Params { radius: 3, iterations: 2, sigma: None, mode: Mode::Gaussian, passes: 3 };
+        let src: Vec<f32> = (0..w * h * 4).map(|i| (i * 37 % 101) as f32 / 100.0).collect();
+        let mut full = src.clone();
+        blur_in_place(w, h, &mut full, &params);
+
+        let (x0, y0, cw, ch) = (10, 5, 20, 20);
+        let mut crop: Vec<f32> = (y0..y0 + ch).flat_map(|y| src[(y * w + x0) * 4..(y * w + x0 + cw) * 4].to_vec()).collect();
+        blur_in_place(cw, ch, &mut crop, &params);
+
+        let halo = 6;
+        for y in halo..ch - halo {
+            for x in halo..cw - halo {
+                for c in 0..4 {
+                    let (a, b) = (crop[(y * cw + x) * 4 + c], full[((y0 + y) * w + x0 + x) * 4 + c]);
+                    assert!((a - b).abs() < 1e-5, "({x}, {y}): {a} vs {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_matches_clipped_average() {
+        let (w, h) = (9usize, 7usize);
+        let src: Vec<f32> = (0..w * h * 4).map(|i| ((i * 37) % 101) as f32 / 100.0).collect();
+        let params = Params { radius: 2, iterations: 1, sigma: None, mode: Mode::Box, passes: 1 };
+        let mut fast = src.clone();
+        box_blur_in_place(w, h, &mut fast, &params);
+
+        let src = &src;
+        for y in 0..h {
+            for x in 0..w {
+                let (ys, xs) = (y.saturating_sub(2)..=(y + 2).min(h - 1), x.saturating_sub(2)..=(x + 2).min(w - 1));
+                let count = (ys.clone().count() * xs.clone().count()) as f32;
+                for c in 0..4 {
+                    let sum: f32 = ys.clone().flat_map(|ny| xs.clone().map(move |nx| src[(ny * w + nx) * 4 + c])).sum();
+                    assert!((fast[(y * w + x) * 4 + c] - sum / count).abs() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_bands_give_same_result() {
+        let (w, h) = (13u32, 40u32);
+        let src: Vec<u8> = (0..w * h * 4).map(|i| (i * 31 % 256) as u8).collect();
+        let params_str = CString::new(r#"{"radius": 5, "iterations": 1, "mode": "box"}"#).unwrap();
+
+        let mut single = src.clone();
+        let ctx = CallContext { max_threads: 1, ..CallContext::new(0) };
+        assert_eq!(process_image_ctx(&ctx, w, h, single.as_mut_ptr(), params_str.as_ptr()), 0);
+
+        let mut banded = src;
+        let ctx = CallContext { max_threads: 3, ..CallContext::new(0) };
+        assert_eq!(process_image_ctx(&ctx, w, h, banded.as_mut_ptr(), params_str.as_ptr()), 0);
+        assert_eq!(single, banded);
+
+        let mut caps = Capabilities::default();
+        assert_eq!(plugin_capabilities(params_str.as_ptr(), &mut caps), 0);
+        assert_eq!(caps.local_halo(), Some(15));
+    }
+
+    #[test]
+    fn test_capabilities_include_float() {
+        let mut caps = Capabilities::default();
+        assert_eq!(plugin_capabilities(std::ptr::null(), &mut caps), 0);
+        assert!(caps.supports(plugin_sdk::PIXEL_FORMAT_RGBA32F));
+        assert_eq!(caps.local_halo(), None);
+
+        let mut caps = Capabilities::default();
+        assert_eq!(plugin_capabilities(c"{\"radius\": 3, \"iterations\": 2}".as_ptr(), &mut caps), 0);
+        assert_eq!(caps.local_halo(), Some(6));
+    }
+
+    #[test]
+    fn test_manifest_defaults_are_valid_params() {
+        // SAFETY: `plugin_manifest` returns a static NUL-terminated string.
+        let text = unsafe { CStr::from_ptr(plugin_manifest()) }.to_str().unwrap();
+        let manifest: toml::Table = toml::from_str(text).unwrap();
+        let defaults = manifest["defaults"].as_table().unwrap().clone();
+        let params: Result<Params, _> = defaults.try_into();
+        assert!(params.is_ok());
+    }
+}
@@ -0,0 +1,131 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Offset added to the sRGB-encoded values, in units of full scale (-1 to 1).
+    #[serde(default)]
+    brightness: f32,
+    /// Slope around `pivot`; 1 leaves the image unchanged, 0 makes it flat.
+    #[serde(default = "default_contrast")]
+    contrast: f32,
+    /// sRGB-encoded value that contrast leaves in place.
+    #[serde(default = "default_pivot")]
+    pivot: f32,
+    /// Exposure change in stops; each stop doubles the light.
+    #[serde(default)]
+    exposure: f32,
+}
+
+fn default_contrast() -> f32 {
+    1.0
+}
+
+fn default_pivot() -> f32 {
+    0.5
+}
+
+const MANIFEST: &CStr = cr#"name = "brightness_plugin"
+version = "0.1.0"
+description = "Adjusts exposure, brightness and contrast"
+
+[defaults]
+brightness = 0.0
+contrast = 1.0
+pivot = 0.5
+exposure = 0.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Adjusts both formats; working per pixel, the adjustment is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => adjust(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => adjust(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Applies exposure in linear light, then brightness and contrast to the sRGB-encoded values,
+/// so a given setting looks the same in either working space.
+///
+/// The curves are not linear, so premultiplied colors are divided by alpha first and
+/// multiplied again afterwards; alpha itself is never changed.
+fn adjust<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let gain = params.exposure.exp2();
+    let tone = |v: f32| {
+        let encoded = plugin_sdk::linear_to_srgb(plugin_sdk::to_linear::<T>(v) * gain);
+        let encoded = (encoded + params.brightness - params.pivot) * params.contrast + params.pivot;
+        plugin_sdk::from_linear::<T>(plugin_sdk::srgb_to_linear(encoded))
+    };
+
+    for px in buf.chunks_exact_mut(4) {
+        let alpha = if premultiplied { px[3].to_f32() / T::MAX } else { 1.0 };
+        if alpha <= 0.0 {
+            continue;
+        }
+        for v in &mut px[..3] {
+            *v = T::from_f32(tone(v.to_f32() / T::MAX / alpha) * alpha * T::MAX);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::{ALPHA_PREMULTIPLIED, testing};
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_defaults_are_identity() {
+        let src: Vec<u8> = (0..=255).flat_map(|v| [v, 255 - v, v / 2, v]).collect();
+        let mut buf = src.clone();
+        run(&mut buf, "{}").unwrap();
+        assert_eq!(buf, src);
+    }
+
+    #[test]
+    fn test_brightness_contrast_exposure() {
+        let mut bright = [100u8, 0, 250, 77];
+        run(&mut bright, r#"{"brightness": 0.1}"#).unwrap();
+        assert_eq!(bright, [126, 26, 255, 77]);
+
+        // Contrast doubles the distance from the pivot (127.5 in 8-bit levels).
+        let mut contrast = [128u8, 64, 192, 255];
+        run(&mut contrast, r#"{"contrast": 2.0, "pivot": 0.5}"#).unwrap();
+        assert_eq!(contrast, [129, 1, 255, 255]);
+
+        // One stop doubles the linear light: 0.216 (sRGB 128) becomes 0.432 (sRGB 176).
+        let mut exposure = [128u8, 0, 0, 255];
+        run(&mut exposure, r#"{"exposure": 1.0}"#).unwrap();
+        assert_eq!(exposure, [176, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_premultiplied_matches_straight() {
+        let params = r#"{"exposure": 0.5, "contrast": 1.3}"#;
+        let mut straight = [0.3f32, 0.1, 0.7, 1.0, 0.3, 0.1, 0.7, 0.5];
+        let mut premultiplied = [0.3f32, 0.1, 0.7, 1.0, 0.15, 0.05, 0.35, 0.5];
+        let ctx = CallContext { pixel_format: plugin_sdk::PIXEL_FORMAT_RGBA32F, ..CallContext::new(0) };
+        testing::run_with(process, &ctx, 2, 1, &mut straight, params).unwrap();
+        let ctx = CallContext { alpha_mode: ALPHA_PREMULTIPLIED, ..ctx };
+        testing::run_with(process, &ctx, 2, 1, &mut premultiplied, params).unwrap();
+
+        assert_eq!(straight[7], 0.5);
+        for c in 0..3 {
+            assert!((straight[4 + c] * 0.5 - premultiplied[4 + c]).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,369 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Color, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Half-width of the window of one smoothing pass.
+const SMOOTH_RADIUS: u32 = 2;
+
+/// Standard deviation of the smoothing's spatial Gaussian, in pixels.
+const SMOOTH_SIGMA_SPATIAL: f32 = 1.5;
+
+/// Standard deviation of the smoothing's range Gaussian, as a distance between sRGB-encoded colors.
+const SMOOTH_SIGMA_RANGE: f32 = 0.1;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Style {
+    /// Flat areas of few colors with ink outlines.
+    #[default]
+    Cartoon,
+    /// A grayscale pencil drawing.
+    Sketch,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    style: Style,
+    /// Cartoon: passes of edge-preserving smoothing that flatten texture before quantizing.
+    #[serde(default = "default_smoothing")]
+    smoothing: u32,
+    /// Cartoon: levels per channel the colors are quantized to.
+    #[serde(default = "default_levels")]
+    levels: u32,
+    /// Cartoon: whether edges are outlined in `ink`.
+    #[serde(default = "default_outline")]
+    outline: bool,
+    /// Cartoon: gradient magnitude of the smoothed luma above which a pixel is outlined; a step
+    /// from black to white has a magnitude of 1.
+    #[serde(default = "default_edge_threshold")]
+    edge_threshold: f32,
+    /// Cartoon: pixels the outlines are thickened by on each side.
+    #[serde(default)]
+    thickness: u32,
+    #[serde(default = "default_ink")]
+    ink: Color,
+    /// Sketch: standard deviation in pixels of the blur whose dodge gives the strokes; larger
+    /// values give broader, darker strokes.
+    #[serde(default = "default_blur")]
+    blur: f32,
+}
+
+fn default_smoothing() -> u32 {
+    2
+}
+
+fn default_levels() -> u32 {
+    6
+}
+
+fn default_outline() -> bool {
+    true
+}
+
+fn default_edge_threshold() -> f32 {
+    0.2
+}
+
+fn default_ink() -> Color {
+    Color::BLACK
+}
+
+fn default_blur() -> f32 {
+    8.0
+}
+
+impl Params {
+    /// How far from a pixel the result depends on the input.
+    fn reach(&self) -> u32 {
+        match self.style {
+            Style::Cartoon => {
+                let outline = if self.outline { 1 + self.thickness } else { 0 };
+                self.smoothing.saturating_mul(SMOOTH_RADIUS).saturating_add(outline)
+            }
+            Style::Sketch => (3.0 * self.blur).ceil().max(0.0) as u32,
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr##"name = "cartoon_plugin"
+version = "0.1.0"
+description = "Cartoon (smoothed, quantized and outlined) and pencil-sketch stylization"
+
+[defaults]
+style = "cartoon"
+smoothing = 2
+levels = 6
+outline = true
+edge_threshold = 0.2
+thickness = 0
+ink = "#000000"
+blur = 8.0
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Stylizes both formats.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if !(2..=256).contains(&params.levels) || params.edge_threshold.is_nan() || params.blur.is_nan() {
+        return Err(PluginError::Invalid("levels must be in 2..=256, edge_threshold and blur numbers"));
+    }
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => stylize(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => stylize(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// The cartoon style reaches `smoothing * 2` pixels plus the outline's, the sketch style three
+/// times `blur`.
+fn halo(params: &Params) -> Option<u32> {
+    Some(params.reach())
+}
+
+/// Works on straight, sRGB-encoded colors and writes them back in the buffer's encoding and
+/// alpha convention; alpha itself is kept.
+fn stylize<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let colors: Vec<[f32; 3]> = buf.chunks_exact(4).map(|px| straight::<T>(px, premultiplied)).collect();
+    let styled = match params.style {
+        Style::Cartoon => cartoon(colors, width, height, params),
+        Style::Sketch => sketch(&colors, width, height, params.blur),
+    };
+
+    for (px, rgb) in buf.chunks_exact_mut(4).zip(styled) {
+        let scale = if premultiplied { px[3].to_f32() } else { T::MAX };
+        for c in 0..3 {
+            px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(rgb[c]) * scale);
+        }
+    }
+}
+
+/// Smooths the colors, quantizes them to `levels` per channel and outlines the edges of the
+/// smoothed luma.
+fn cartoon(mut colors: Vec<[f32; 3]>, width: usize, height: usize, params: &Params) -> Vec<[f32; 3]> {
+    for _ in 0..params.smoothing {
+        colors = smooth(&colors, width, height);
+    }
+    let edges = if params.outline {
+        let luma: Vec<f32> = colors.iter().map(|c| LUMA[0] * c[0] + LUMA[1] * c[1] + LUMA[2] * c[2]).collect();
+        let edges: Vec<bool> = sobel(&luma, width, height).iter().map(|&m| m >= params.edge_threshold).collect();
+        dilate(&edges, width, height, params.thickness as i64)
+    } else {
+        vec![false; colors.len()]
+    };
+
+    let steps = (params.levels - 1) as f32;
+    let [ir, ig, ib, ia] = params.ink.0;
+    colors
+        .into_iter()
+        .zip(edges)
+        .map(|(rgb, edge)| {
+            let flat = rgb.map(|v| (v.clamp(0.0, 1.0) * steps).round() / steps);
+            if edge {
+                std::array::from_fn(|c| flat[c] + ([ir, ig, ib][c] - flat[c]) * ia)
+            } else {
+                flat
+            }
+        })
+        .collect()
+}
+
+/// Pencil sketch by color dodge: the luma divided by the inverse of its blurred negative, which
+/// stays white in flat areas and darkens where the brightness changes.
+fn sketch(colors: &[[f32; 3]], width: usize, height: usize, blur: f32) -> Vec<[f32; 3]> {
+    let luma: Vec<f32> = colors.iter().map(|c| LUMA[0] * c[0] + LUMA[1] * c[1] + LUMA[2] * c[2]).collect();
+    let negative: Vec<f32> = luma.iter().map(|v| 1.0 - v).collect();
+    let blurred = if blur > 0.0 { gaussian(&negative, width, height, blur) } else { negative };
+    luma.iter()
+        .zip(blurred)
+        .map(|(&v, b)| {
+            let dodge = if b >= 1.0 { 1.0 } else { (v / (1.0 - b)).min(1.0) };
+            [dodge.max(0.0); 3]
+        })
+        .collect()
+}
+
+/// One pass of a bilateral filter: every color becomes an average of its window, weighted by
+/// the distance and the color difference, so neighbors across an edge barely count.
+fn smooth(colors: &[[f32; 3]], width: usize, height: usize) -> Vec<[f32; 3]> {
+    let r = SMOOTH_RADIUS as i64;
+    let spatial = -1.0 / (2.0 * SMOOTH_SIGMA_SPATIAL * SMOOTH_SIGMA_SPATIAL);
+    let range = -1.0 / (2.0 * SMOOTH_SIGMA_RANGE * SMOOTH_SIGMA_RANGE);
+    let mut out = Vec::with_capacity(colors.len());
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let center = colors[y as usize * width + x as usize];
+            let mut acc = [0.0f32; 3];
+            let mut total = 0.0;
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let (sx, sy) = (x + dx, y + dy);
+                    if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+                        continue;
+                    }
+                    let color = colors[sy as usize * width + sx as usize];
+                    let d2: f32 = color.iter().zip(&center).map(|(a, b)| (a - b) * (a - b)).sum();
+                    let weight = (((dx * dx + dy * dy) as f32) * spatial + d2 * range).exp();
+                    for (a, v) in acc.iter_mut().zip(color) {
+                        *a += weight * v;
+                    }
+                    total += weight;
+                }
+            }
+            // The center pixel always contributes with weight 1, so `total` is positive.
+            out.push(acc.map(|a| a / total));
+        }
+    }
+    out
+}
+
+/// Sobel gradient magnitude of every pixel, clamping at the edges; a unit step gives 1.
+fn sobel(luma: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, width as i64 - 1) as usize, y.clamp(0, height as i64 - 1) as usize);
+        luma[y * width + x]
+    };
+    let mut magnitude = Vec::with_capacity(luma.len());
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let gx = (at(x + 1, y - 1) - at(x - 1, y - 1))
+                + 2.0 * (at(x + 1, y) - at(x - 1, y))
+                + (at(x + 1, y + 1) - at(x - 1, y + 1));
+            let gy = (at(x - 1, y + 1) - at(x - 1, y - 1))
+                + 2.0 * (at(x, y + 1) - at(x, y - 1))
+                + (at(x + 1, y + 1) - at(x + 1, y - 1));
+            magnitude.push(gx.hypot(gy) / 4.0);
+        }
+    }
+    magnitude
+}
+
+/// Grows the marked pixels by `radius` in every direction (a square window).
+fn dilate(mask: &[bool], width: usize, height: usize, radius: i64) -> Vec<bool> {
+    if radius == 0 {
+        return mask.to_vec();
+    }
+    let mut out = vec![false; mask.len()];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            out[y as usize * width + x as usize] = (-radius..=radius).any(|dy| {
+                (-radius..=radius).any(|dx| {
+                    let (sx, sy) = (x + dx, y + dy);
+                    (0..width as i64).contains(&sx)
+                        && (0..height as i64).contains(&sy)
+                        && mask[sy as usize * width + sx as usize]
+                })
+            });
+        }
+    }
+    out
+}
+
+/// Separable Gaussian blur of a single-channel image, clamping at the edges.
+fn gaussian(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let r = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let norm: f32 = kernel.iter().sum();
+    let pass = |src: &[f32], step: (i64, i64)| -> Vec<f32> {
+        let mut out = vec![0.0; src.len()];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let sum: f32 = kernel
+                    .iter()
+                    .zip(-r..)
+                    .map(|(k, d)| {
+                        let sx = (x + d * step.0).clamp(0, width as i64 - 1) as usize;
+                        let sy = (y + d * step.1).clamp(0, height as i64 - 1) as usize;
+                        k * src[sy * width + sx]
+                    })
+                    .sum();
+                out[y as usize * width + x as usize] = sum / norm;
+            }
+        }
+        out
+    };
+    pass(&pass(src, (1, 0)), (0, 1))
+}
+
+/// A pixel's straight-alpha color, sRGB-encoded and normalized to `[0, 1]`.
+fn straight<T: Sample>(px: &[T], premultiplied: bool) -> [f32; 3] {
+    let alpha = px[3].to_f32() / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// An 8x8 image, dark on the left half and light on the right, with a little texture.
+    fn halves() -> Vec<u8> {
+        (0..64)
+            .flat_map(|i| {
+                let v = if i % 8 < 4 { 40 } else { 220 } + [0, 4, 2, 6][i % 4] - 3;
+                [v as u8, v as u8, v as u8, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cartoon_flattens_and_outlines() {
+        let mut img = halves();
+        run(&mut img, 8, 8, "{}").unwrap();
+        // Five steps per channel: 40 and 220 snap to 51 and 204, and the step is outlined.
+        let row: Vec<u8> = img[3 * 32..4 * 32].chunks(4).map(|p| p[0]).collect();
+        assert_eq!(row, [51, 51, 51, 0, 0, 204, 204, 204]);
+        assert!(img.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+
+        let mut img = halves();
+        run(&mut img, 8, 8, r##"{"outline": false, "levels": 2}"##).unwrap();
+        let row: Vec<u8> = img[..32].chunks(4).map(|p| p[0]).collect();
+        assert_eq!(row, [0, 0, 0, 0, 255, 255, 255, 255]);
+
+        let mut img = halves();
+        run(&mut img, 8, 8, r##"{"thickness": 1, "ink": "#ff0000"}"##).unwrap();
+        let row: Vec<&[u8]> = img[..32].chunks(4).collect();
+        // The two outlined columns grow by one on each side.
+        let red = [255, 0, 0, 255];
+        assert_eq!(row[1..7], [[51, 51, 51, 255], red, red, red, red, [204, 204, 204, 255]]);
+
+        assert!(run(&mut img, 8, 8, r#"{"levels": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_sketch_draws_dark_strokes_on_white() {
+        // A dark vertical line on a light background.
+        let mut img: Vec<u8> =
+            (0..64).flat_map(|i| if i % 8 == 3 { [30, 30, 30, 255] } else { [200, 180, 160, 255] }).collect();
+        run(&mut img, 8, 8, r#"{"style": "sketch", "blur": 2.0}"#).unwrap();
+        let row: Vec<u8> = img[..32].chunks(4).map(|p| p[0]).collect();
+        assert!(row[3] < 100 && row[0] > 230, "{row:?}");
+        assert!(img.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2]));
+
+        let params = plugin_sdk::params_from_str(r#"{"style": "sketch", "blur": 2.5}"#).unwrap();
+        assert_eq!(halo(&params), Some(8));
+        let params = plugin_sdk::params_from_str(r#"{"thickness": 1}"#).unwrap();
+        assert_eq!(halo(&params), Some(6));
+    }
+}
@@ -0,0 +1,135 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+/// Ready-made matrices that replace `matrix`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Preset {
+    /// Exchanges the red and blue channels.
+    SwapRedBlue,
+    /// Infrared-film look: green foliage turns red and red turns green.
+    Infrared,
+}
+
+impl Preset {
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Self::SwapRedBlue => [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]],
+            Self::Infrared => [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// One row per output channel, giving the weights of the input red, green and blue.
+    #[serde(default = "default_matrix")]
+    matrix: [[f32; 3]; 3],
+    /// Added to each output channel, in units of full scale.
+    #[serde(default)]
+    offset: [f32; 3],
+    #[serde(default)]
+    preset: Option<Preset>,
+}
+
+fn default_matrix() -> [[f32; 3]; 3] {
+    IDENTITY
+}
+
+const MANIFEST: &CStr = cr#"name = "channel_mixer_plugin"
+version = "0.1.0"
+description = "Mixes the color channels through a 3x3 matrix with offsets, or a preset"
+
+[defaults]
+matrix = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+offset = [0.0, 0.0, 0.0]
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Mixes both formats; working per pixel, the mixer is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => mix(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => mix(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Mixes the sRGB-encoded values, as an image editor's channel mixer would, whatever the
+/// working space; alpha is kept.
+fn mix<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let matrix = params.preset.map_or(params.matrix, Preset::matrix);
+    if matrix == IDENTITY && params.offset == [0.0; 3] {
+        return;
+    }
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let encoded = rgb.map(plugin_sdk::to_encoded::<T>);
+        std::array::from_fn(|c| {
+            let row = matrix[c];
+            let mixed = row[0] * encoded[0] + row[1] * encoded[1] + row[2] * encoded[2] + params.offset[c];
+            plugin_sdk::from_encoded::<T>(mixed)
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::{ALPHA_PREMULTIPLIED, testing};
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_matrix_and_offset() {
+        // Red becomes the average of red and green; blue gets a fixed lift.
+        let params = r#"{"matrix": [[0.5, 0.5, 0], [0, 1, 0], [0, 0, 1]], "offset": [0, 0, 0.2]}"#;
+        let mut buf = [200u8, 100, 50, 128];
+        run(&mut buf, params).unwrap();
+        assert_eq!(buf, [150, 100, 101, 128]);
+
+        let mut bad = [0u8; 4];
+        assert!(run(&mut bad, r#"{"matrix": [[1, 0], [0, 1]]}"#).is_err());
+    }
+
+    #[test]
+    fn test_presets() {
+        let mut swap = [200u8, 100, 50, 255];
+        run(&mut swap, r#"{"preset": "swap_red_blue"}"#).unwrap();
+        assert_eq!(swap, [50, 100, 200, 255]);
+
+        let mut foliage = [40u8, 180, 30, 255];
+        run(&mut foliage, r#"{"preset": "infrared"}"#).unwrap();
+        assert_eq!(foliage, [180, 40, 30, 255]);
+
+        let mut bad = [0u8; 4];
+        assert!(run(&mut bad, r#"{"preset": "thermal"}"#).is_err());
+    }
+
+    #[test]
+    fn test_rgba32f_premultiplied() {
+        // Linear values, premultiplied by alpha 0.5; swapping channels keeps both properties.
+        let mut img = [0.1f32, 0.2, 0.3, 0.5];
+        let ctx = CallContext {
+            pixel_format: plugin_sdk::PIXEL_FORMAT_RGBA32F,
+            alpha_mode: ALPHA_PREMULTIPLIED,
+            ..CallContext::new(0)
+        };
+        testing::run_with(process, &ctx, 1, 1, &mut img, r#"{"preset": "swap_red_blue"}"#).unwrap();
+        for (got, want) in img.iter().zip([0.3, 0.2, 0.1, 0.5]) {
+            assert!((got - want).abs() < 1e-6, "{img:?}");
+        }
+    }
+}
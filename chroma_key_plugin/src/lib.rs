@@ -0,0 +1,165 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Color, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Color of the screen that is keyed out.
+    #[serde(default = "default_key")]
+    key: Color,
+    /// Largest chroma distance from `key` that is fully transparent.
+    #[serde(default = "default_tolerance")]
+    tolerance: f32,
+    /// Width of the chroma distance range above `tolerance` over which alpha rises to opaque;
+    /// 0 gives a hard edge.
+    #[serde(default = "default_softness")]
+    softness: f32,
+    /// How far the key's dominant channel is pulled down towards the other two, removing the
+    /// screen's color cast from edges and reflections; 0 turns it off.
+    #[serde(default = "default_spill")]
+    spill: f32,
+}
+
+fn default_key() -> Color {
+    Color([0.0, 1.0, 0.0, 1.0])
+}
+
+fn default_tolerance() -> f32 {
+    0.25
+}
+
+fn default_softness() -> f32 {
+    0.15
+}
+
+fn default_spill() -> f32 {
+    1.0
+}
+
+const MANIFEST: &CStr = cr##"name = "chroma_key_plugin"
+version = "0.1.0"
+description = "Keys out a green or blue screen into alpha, with soft edges and spill suppression"
+
+[defaults]
+key = "#00ff00"
+tolerance = 0.25
+softness = 0.15
+spill = 1.0
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Keys both formats; each pixel is keyed on its own, so keying is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    if [params.tolerance, params.softness, params.spill].iter().any(|v| v.is_nan()) {
+        return Err(PluginError::Invalid("tolerance, softness and spill must be numbers"));
+    }
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => chroma_key(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => chroma_key(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Blue- and red-difference chroma (BT.709) of an sRGB-encoded color, each within `[-0.5, 0.5]`.
+///
+/// Brightness is left out, so shadows falling on the screen are keyed along with it.
+fn chroma([r, g, b]: [f32; 3]) -> [f32; 2] {
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    [(b - y) / 1.8556, (r - y) / 1.5748]
+}
+
+/// Multiplies alpha by the matte, which is 0 within `tolerance` of the key's chroma and rises
+/// smoothly to 1 over `softness`, then suppresses spill in the colors.
+///
+/// Works on straight, sRGB-encoded colors; premultiplied colors are scaled with the new alpha.
+fn chroma_key<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let [kr, kg, kb, _] = params.key.0;
+    let key = chroma([kr, kg, kb]);
+    // The channel the screen is brightest in (green for a green screen) carries the spill.
+    let dominant = if kg >= kr && kg >= kb { 1 } else if kb >= kr { 2 } else { 0 };
+    let spill = params.spill.clamp(0.0, 1.0);
+    let softness = params.softness.max(0.0);
+
+    for px in buf.chunks_exact_mut(4) {
+        let alpha = px[3].to_f32() / T::MAX;
+        if alpha <= 0.0 {
+            continue;
+        }
+        let mut rgb: [f32; 3] = std::array::from_fn(|c| {
+            let v = px[c].to_f32() / T::MAX;
+            plugin_sdk::to_encoded::<T>(if premultiplied { v / alpha } else { v })
+        });
+
+        let [cb, cr] = chroma(rgb);
+        let distance = (cb - key[0]).hypot(cr - key[1]);
+        let matte = if distance <= params.tolerance {
+            0.0
+        } else if distance >= params.tolerance + softness {
+            1.0
+        } else {
+            let t = (distance - params.tolerance) / softness;
+            t * t * (3.0 - 2.0 * t)
+        };
+
+        let limit = (0..3).filter(|&c| c != dominant).map(|c| rgb[c]).fold(0.0, f32::max);
+        if rgb[dominant] > limit {
+            rgb[dominant] -= spill * (rgb[dominant] - limit);
+        }
+
+        let alpha = alpha * matte;
+        for c in 0..3 {
+            let v = plugin_sdk::from_encoded::<T>(rgb[c]);
+            px[c] = T::from_f32(if premultiplied { v * alpha } else { v } * T::MAX);
+        }
+        px[3] = T::from_f32(alpha * T::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    #[test]
+    fn test_green_screen_keyed_out() {
+        // Screen, screen in shadow, skin and white.
+        let mut img = [[20u8, 230, 40, 255], [15, 190, 30, 255], [230, 180, 150, 255], [255, 255, 255, 255]]
+            .concat();
+        run(&mut img, 4, 1, "{}").unwrap();
+        let alphas: Vec<u8> = img.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alphas, [0, 0, 255, 255]);
+        assert_eq!(&img[8..16], &[230, 180, 150, 255, 255, 255, 255, 255]);
+
+        // A greenish fringe stays partly visible, its green cast reduced to the red level.
+        let mut edge = [90u8, 200, 80, 255];
+        run(&mut edge, 1, 1, "{}").unwrap();
+        assert!(edge[3] > 0 && edge[3] < 255, "{edge:?}");
+        assert_eq!(edge[..3], [90, 90, 80]);
+
+        let mut kept = [90u8, 200, 80, 255];
+        run(&mut kept, 1, 1, r#"{"spill": 0.0}"#).unwrap();
+        assert_eq!(kept[..3], [90, 200, 80]);
+    }
+
+    #[test]
+    fn test_blue_key_and_hard_edge() {
+        let mut img = [[30u8, 40, 220, 255], [40, 220, 30, 128]].concat();
+        run(&mut img, 2, 1, r##"{"key": "#0000ff", "softness": 0.0}"##).unwrap();
+        assert_eq!(img[3], 0);
+        // Green is far from a blue key: alpha is kept, and blue spill does not touch it.
+        assert_eq!(&img[4..8], &[40, 220, 30, 128]);
+    }
+}
@@ -0,0 +1,184 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// The kind of dichromacy, by the cone type that is missing.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Deficiency {
+    /// No long-wavelength (red) cones: reds look dark and merge with greens.
+    Protanopia,
+    /// No medium-wavelength (green) cones: the most common form, merging reds and greens.
+    #[default]
+    Deuteranopia,
+    /// No short-wavelength (blue) cones: blues merge with greens and yellows with pinks.
+    Tritanopia,
+}
+
+impl Deficiency {
+    /// Machado, Oliveira and Fernandes (2009) simulation matrix for linear RGB at full severity.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            Self::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            Self::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            Self::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+        }
+    }
+
+    /// How daltonization redistributes the color information the viewer cannot see: into the
+    /// channels they still tell apart.
+    fn shift(self) -> [[f32; 3]; 3] {
+        match self {
+            Self::Protanopia | Self::Deuteranopia => [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]],
+            Self::Tritanopia => [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Shows the image as a viewer with the deficiency sees it.
+    #[default]
+    Simulate,
+    /// Adjusts the colors so such a viewer can tell apart more of what they would otherwise
+    /// confuse.
+    Daltonize,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    deficiency: Deficiency,
+    #[serde(default)]
+    mode: Mode,
+    /// From 0 (normal vision) to 1 (complete dichromacy); values between approximate the
+    /// milder anomalous trichromacies.
+    #[serde(default = "default_severity")]
+    severity: f32,
+}
+
+fn default_severity() -> f32 {
+    1.0
+}
+
+const MANIFEST: &CStr = cr#"name = "color_blindness_plugin"
+version = "0.1.0"
+description = "Simulates protanopia, deuteranopia or tritanopia, or daltonizes for them"
+
+[defaults]
+deficiency = "deuteranopia"
+mode = "simulate"
+severity = 1.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Simulates the deficiency on both formats; working per pixel, it is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    if params.severity.is_nan() || !(0.0..=1.0).contains(&params.severity) {
+        return Err(PluginError::Invalid("severity must be in 0..=1"));
+    }
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => apply(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => apply(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], rgb: [f32; 3]) -> [f32; 3] {
+    matrix.map(|row| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2])
+}
+
+/// Simulates the deficiency in linear light, blending the matrix with the identity by
+/// `severity`; daltonizing adds the shifted difference between the original and the simulation
+/// back to the original. Negative results are clipped; alpha is kept.
+fn apply<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let full = params.deficiency.matrix();
+    let matrix: [[f32; 3]; 3] = std::array::from_fn(|r| {
+        std::array::from_fn(|c| {
+            let identity = if r == c { 1.0 } else { 0.0 };
+            identity + params.severity * (full[r][c] - identity)
+        })
+    });
+    let shift = params.deficiency.shift();
+
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let linear = rgb.map(plugin_sdk::to_linear::<T>);
+        let simulated = multiply(&matrix, linear);
+        let out = match params.mode {
+            Mode::Simulate => simulated,
+            Mode::Daltonize => {
+                let lost: [f32; 3] = std::array::from_fn(|c| linear[c] - simulated[c]);
+                let correction = multiply(&shift, lost);
+                std::array::from_fn(|c| linear[c] + correction[c])
+            }
+        };
+        out.map(|v| plugin_sdk::from_linear::<T>(v.max(0.0)))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    #[test]
+    fn test_simulation_merges_confused_colors() {
+        // Red, green and a gray, which every deficiency leaves alone.
+        let src = [[255u8, 0, 0, 255], [0, 255, 0, 255], [128, 128, 128, 255]].concat();
+        for deficiency in ["protanopia", "deuteranopia"] {
+            let mut img = src.clone();
+            run(&mut img, 3, 1, &format!(r#"{{"deficiency": "{deficiency}"}}"#)).unwrap();
+            // Red and green both turn into yellows of some brightness.
+            for p in img[..8].chunks(4) {
+                assert!(p[0].abs_diff(p[1]) < 40 && p[2] < p[1] / 2, "{deficiency}: {p:?}");
+            }
+            assert!(img[8..11].iter().all(|&v| v.abs_diff(128) <= 1), "{deficiency}: {img:?}");
+        }
+
+        // Without blue cones, blue turns teal.
+        let mut img = [0u8, 0, 255, 255];
+        run(&mut img, 1, 1, r#"{"deficiency": "tritanopia"}"#).unwrap();
+        assert!(img[1] > 80 && img[0] < 10, "{img:?}");
+
+        let mut img = src.clone();
+        run(&mut img, 3, 1, r#"{"severity": 0.0}"#).unwrap();
+        assert_eq!(img, src);
+        assert!(run(&mut img, 3, 1, r#"{"severity": 1.5}"#).is_err());
+    }
+
+    #[test]
+    fn test_daltonize_moves_lost_contrast_into_blue() {
+        let mut img = [[255u8, 0, 0, 255], [128, 128, 128, 255]].concat();
+        run(&mut img, 2, 1, r#"{"mode": "daltonize"}"#).unwrap();
+        // Red keeps its red but gains blue, which a deuteranope sees.
+        assert_eq!(img[0], 255);
+        assert!(img[2] > 150, "{img:?}");
+        assert!(img[4..7].iter().all(|&v| v.abs_diff(128) <= 1), "{img:?}");
+    }
+}
@@ -0,0 +1,234 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// What the kernel reads past the image edges.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Edge {
+    /// The nearest edge pixel.
+    #[default]
+    Clamp,
+    /// The image repeated as a tile.
+    Wrap,
+    /// The image mirrored at its edges.
+    Mirror,
+    /// Transparent black.
+    Zero,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Square kernel with an odd number of rows, written as a TOML array of rows.
+    kernel: Vec<Vec<f32>>,
+    /// Divides the weighted sum; defaults to the sum of the kernel, or 1 if that is 0.
+    #[serde(default)]
+    divisor: Option<f32>,
+    /// Added after dividing, in units of full scale; e.g. 0.5 centers an emboss on mid gray.
+    #[serde(default)]
+    offset: f32,
+    #[serde(default)]
+    edge: Edge,
+    /// Also convolves alpha; otherwise it is kept.
+    #[serde(default)]
+    alpha: bool,
+}
+
+impl Params {
+    /// Kernel half-width, or `None` if the kernel is empty, not square or of even size.
+    fn radius(&self) -> Option<usize> {
+        let n = self.kernel.len();
+        (n % 2 == 1 && self.kernel.iter().all(|row| row.len() == n)).then_some(n / 2)
+    }
+
+    fn divisor(&self) -> f32 {
+        let sum: f32 = self.kernel.iter().flatten().sum();
+        match self.divisor {
+            Some(d) if d != 0.0 => d,
+            _ if sum != 0.0 => sum,
+            _ => 1.0,
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "convolve_plugin"
+version = "0.1.0"
+description = "Applies an arbitrary NxN convolution kernel with a divisor, offset and edge handling"
+
+[defaults]
+kernel = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 0.0]]
+offset = 0.0
+edge = "clamp"
+alpha = false
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Convolves both formats on the host's thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.radius().is_none() {
+        return Err(PluginError::Invalid("the kernel must be square with an odd size"));
+    }
+
+    let (w, h) = (width as usize, height as usize);
+    let run = move || match pixels {
+        Pixels::Rgba8(buf) => convolve(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => convolve(w, h, buf, params, premultiplied),
+    };
+
+    plugin_sdk::install(ctx, run)
+}
+
+/// Half the kernel size; wrapping edges read from the far side of the image, so `edge = "wrap"`
+/// is not local.
+fn halo(params: &Params) -> Option<u32> {
+    params.radius().filter(|_| params.edge != Edge::Wrap).map(|radius| radius as u32)
+}
+
+/// Convolves every pixel with the kernel, then divides by the divisor and adds the offset.
+///
+/// Like the color adjustments, the kernel works on straight sRGB-encoded values normalized to
+/// `[0, 1]`, so a kernel and offset give the same result in either working space. The results
+/// are clamped to `[0, 1]`.
+fn convolve<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    let Some(r) = params.radius() else {
+        return;
+    };
+    if width == 0 || height == 0 {
+        return;
+    }
+    let src: Vec<[f32; 4]> = buf.par_chunks_exact(4).map(|px| straight::<T>(px, premultiplied)).collect();
+    let taps: Vec<(i64, i64, f32)> = params
+        .kernel
+        .iter()
+        .enumerate()
+        .flat_map(|(ky, row)| row.iter().enumerate().map(move |(kx, &w)| (kx as i64, ky as i64, w)))
+        .map(|(kx, ky, w)| (kx - r as i64, ky - r as i64, w))
+        .filter(|&(_, _, w)| w != 0.0)
+        .collect();
+    let divisor = params.divisor();
+    let channels = if params.alpha { 4 } else { 3 };
+
+    buf.par_chunks_exact_mut(width * 4).enumerate().for_each(|(y, row)| {
+        for (x, px) in row.chunks_exact_mut(4).enumerate() {
+            let mut acc = [0.0f32; 4];
+            for &(dx, dy, w) in &taps {
+                let (Some(sx), Some(sy)) =
+                    (params.edge.locate(x as i64 + dx, width), params.edge.locate(y as i64 + dy, height))
+                else {
+                    continue;
+                };
+                let value = src[sy * width + sx];
+                for c in 0..channels {
+                    acc[c] += value[c] * w;
+                }
+            }
+            let mut out = src[y * width + x];
+            for c in 0..channels {
+                out[c] = (acc[c] / divisor + params.offset).clamp(0.0, 1.0);
+            }
+            let scale = if premultiplied { T::MAX * out[3] } else { T::MAX };
+            for c in 0..3 {
+                px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(out[c]) * scale);
+            }
+            px[3] = T::from_f32(out[3] * T::MAX);
+        }
+    });
+}
+
+/// A pixel's straight-alpha color, sRGB-encoded and normalized to `[0, 1]`, followed by its alpha.
+fn straight<T: Sample>(px: &[T], premultiplied: bool) -> [f32; 4] {
+    let alpha = px[3].to_f32() / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    let color = |v: T| plugin_sdk::to_encoded::<T>(v.to_f32() / scale);
+    [color(px[0]), color(px[1]), color(px[2]), alpha]
+}
+
+impl Edge {
+    /// Maps a possibly out-of-range coordinate to an index below `len`, or `None` for zero.
+    fn locate(self, v: i64, len: usize) -> Option<usize> {
+        let len = len as i64;
+        let v = match self {
+            _ if (0..len).contains(&v) => v,
+            Self::Zero => return None,
+            Self::Clamp => v.clamp(0, len - 1),
+            Self::Wrap => v.rem_euclid(len),
+            Self::Mirror => {
+                let m = v.rem_euclid(2 * len);
+                if m < len { m } else { 2 * len - 1 - m }
+            }
+        };
+        Some(v as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    fn ramp() -> Vec<u8> {
+        [10u8, 20, 30, 40].iter().flat_map(|&v| [v, v, v, 255]).collect()
+    }
+
+    fn reds(img: &[u8]) -> Vec<u8> {
+        img.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_shift_kernel_and_edges() {
+        // The kernel reads the pixel to the left, shifting the image right.
+        let shift = r#""kernel": [[0, 0, 0], [1, 0, 0], [0, 0, 0]]"#;
+        let edge = |mode: &str| {
+            let mut img = ramp();
+            run(&mut img, 4, 1, &format!(r#"{{{shift}, "edge": "{mode}"}}"#)).unwrap();
+            reds(&img)
+        };
+        assert_eq!(edge("clamp"), [10, 10, 20, 30]);
+        assert_eq!(edge("wrap"), [40, 10, 20, 30]);
+        assert_eq!(edge("mirror"), [10, 10, 20, 30]);
+        assert_eq!(edge("zero"), [0, 10, 20, 30]);
+    }
+
+    #[test]
+    fn test_divisor_offset_and_alpha() {
+        // A box kernel averages; its divisor defaults to the sum of the weights.
+        let mut img = ramp();
+        run(&mut img, 4, 1, r#"{"kernel": [[0, 0, 0], [1, 1, 1], [0, 0, 0]]}"#).unwrap();
+        assert_eq!(reds(&img), [13, 20, 30, 37]);
+
+        // A zero-sum kernel with an offset of one half: flat areas become mid gray.
+        let mut img = [90u8, 60, 30, 200].repeat(9);
+        let emboss = r#"{"kernel": [[-1, 0, 0], [0, 0, 0], [0, 0, 1]], "offset": 0.5}"#;
+        run(&mut img, 3, 3, emboss).unwrap();
+        assert!(img.chunks(4).all(|p| p == [128, 128, 128, 200]), "{img:?}");
+
+        let mut img = [90u8, 60, 30, 200].repeat(3);
+        img[3] = 0;
+        assert!(run(&mut img, 3, 1, r#"{"kernel": [[1, 1, 1]], "alpha": true}"#).is_err());
+        run(&mut img, 3, 1, r#"{"kernel": [[0, 0, 0], [1, 1, 1], [0, 0, 0]], "alpha": true}"#).unwrap();
+        assert_eq!(img[3], 67);
+    }
+
+    #[test]
+    fn test_halo() {
+        let halo = |params: &str| halo(&plugin_sdk::params_from_str(params).unwrap());
+        let five = format!(r#"{{"kernel": {:?}}}"#, vec![vec![1.0; 5]; 5]);
+        assert_eq!(halo(&five), Some(2));
+        assert_eq!(halo(r#"{"kernel": [[1]], "edge": "wrap"}"#), None);
+        assert_eq!(halo(r#"{"kernel": [[1, 1]]}"#), None);
+    }
+}
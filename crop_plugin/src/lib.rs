@@ -0,0 +1,204 @@
+use std::ffi::CStr;
+use plugin_sdk::{Allocator, CallContext, ImageRef, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+/// What `trim` removes from the edges.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Trim {
+    /// Rows and columns of the top-left pixel's color.
+    Border,
+    /// Fully transparent rows and columns.
+    Transparent,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Left edge of the crop rectangle.
+    #[serde(default)]
+    x: u32,
+    /// Top edge of the crop rectangle.
+    #[serde(default)]
+    y: u32,
+    /// Width of the crop rectangle; 0 extends it to the right edge.
+    #[serde(default)]
+    width: u32,
+    /// Height of the crop rectangle; 0 extends it to the bottom edge.
+    #[serde(default)]
+    height: u32,
+    /// Trims the edges of the crop rectangle automatically.
+    #[serde(default)]
+    trim: Option<Trim>,
+    /// Largest difference per channel, in units of full scale, still counted as border.
+    #[serde(default)]
+    tolerance: f32,
+}
+
+/// A rectangle in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+const MANIFEST: &CStr = cr#"name = "crop_plugin"
+version = "0.1.0"
+description = "Crops to a rectangle, or trims uniform or transparent borders"
+
+[defaults]
+x = 0
+y = 0
+width = 0
+height = 0
+tolerance = 0.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process_v2: process,
+}
+
+/// Crops both formats.
+fn process(
+    image: ImageRef<'_>,
+    output: &mut Allocator<'_>,
+    params: &Params,
+    _ctx: &CallContext,
+) -> Result<(), PluginError> {
+    let ImageRef { width, height, pixels } = image;
+    match pixels {
+        PixelsRef::Rgba8(src) => crop(src, width, height, params, output),
+        PixelsRef::Rgba32F(src) => crop(src, width, height, params, output),
+    }
+}
+
+/// Copies the selected part of `src` into a buffer allocated from `output`.
+///
+/// Fails if the rectangle lies outside the image or the host refused the buffer. If the
+/// selection is the whole image, or trimming would leave nothing, nothing is allocated and the
+/// image stays unchanged.
+fn crop<T: Sample>(
+    src: &[T],
+    width: u32,
+    height: u32,
+    params: &Params,
+    output: &mut Allocator<'_>,
+) -> Result<(), PluginError> {
+    let rect = selection(width, height, params).ok_or(PluginError::Invalid("the crop starts outside the image"))?;
+    let rect = match params.trim {
+        Some(trim) => match trimmed(src, width, rect, trim, params.tolerance) {
+            Some(rect) => rect,
+            None => return Ok(()),
+        },
+        None => rect,
+    };
+    if rect == (Rect { x: 0, y: 0, width, height }) {
+        return Ok(());
+    }
+
+    let dst = output.alloc::<T>(rect.width, rect.height)?;
+    let (stride, left, len) = (width as usize * 4, rect.x as usize * 4, rect.width as usize * 4);
+    for (row, out) in dst.chunks_exact_mut(len).enumerate() {
+        let start = (rect.y as usize + row) * stride + left;
+        out.copy_from_slice(&src[start..start + len]);
+    }
+    Ok(())
+}
+
+/// The crop rectangle from the params, clipped to the image; `None` if nothing is left.
+fn selection(width: u32, height: u32, params: &Params) -> Option<Rect> {
+    if params.x >= width || params.y >= height {
+        return None;
+    }
+    let extent = |size: u32, start: u32, wanted: u32| if wanted == 0 { size - start } else { wanted.min(size - start) };
+    Some(Rect {
+        x: params.x,
+        y: params.y,
+        width: extent(width, params.x, params.width),
+        height: extent(height, params.y, params.height),
+    })
+}
+
+/// Shrinks `rect` past the rows and columns that only hold border pixels; `None` if all of it does.
+fn trimmed<T: Sample>(src: &[T], width: u32, rect: Rect, trim: Trim, tolerance: f32) -> Option<Rect> {
+    let pixel = |x: u32, y: u32| -> [f32; 4] {
+        let i = (y as usize * width as usize + x as usize) * 4;
+        std::array::from_fn(|c| src[i + c].to_f32() / T::MAX)
+    };
+    let reference = pixel(rect.x, rect.y);
+    let is_border = |x: u32, y: u32| {
+        let px = pixel(x, y);
+        match trim {
+            Trim::Border => px.iter().zip(reference).all(|(v, r)| (v - r).abs() <= tolerance),
+            Trim::Transparent => px[3] <= tolerance,
+        }
+    };
+    let row_is_border = |y: u32| (rect.x..rect.x + rect.width).all(|x| is_border(x, y));
+    let column_is_border = |x: u32, top: u32, bottom: u32| (top..bottom).all(|y| is_border(x, y));
+
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+    let top = (rect.y..bottom).find(|&y| !row_is_border(y))?;
+    let bottom = (top..bottom).rev().find(|&y| !row_is_border(y))? + 1;
+    let left = (rect.x..right).find(|&x| !column_is_border(x, top, bottom))?;
+    let right = (left..right).rev().find(|&x| !column_is_border(x, top, bottom))? + 1;
+    Some(Rect { x: left, y: top, width: right - left, height: bottom - top })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing::{self, Output};
+
+    /// Crops an RGBA8 image and returns the output, if one was allocated.
+    fn run(src: &[u8], width: u32, height: u32, params: &str) -> Result<Option<Output<u8>>, PluginError> {
+        testing::run_v2(process, width, height, src, params)
+    }
+
+    /// A 6x5 image of `background` with a 2x2 block of distinct pixels at (1, 2).
+    fn framed(background: [u8; 4]) -> Vec<u8> {
+        let mut img = background.repeat(6 * 5);
+        for (i, (x, y)) in [(1, 2), (2, 2), (1, 3), (2, 3)].into_iter().enumerate() {
+            img[(y * 6 + x) * 4..][..4].copy_from_slice(&[i as u8 * 50, 10, 20, 255]);
+        }
+        img
+    }
+
+    #[test]
+    fn test_crop_rectangle() {
+        let src: Vec<u8> = (0..4 * 3).flat_map(|i| [i, 0, 0, 255]).collect();
+        let out = run(&src, 4, 3, r#"{"x": 1, "y": 1, "width": 2, "height": 5}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (2, 2));
+        let firsts: Vec<u8> = out.data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(firsts, [5, 6, 9, 10]);
+
+        assert_eq!(run(&src, 4, 3, r#"{"x": 4}"#), Err(PluginError::Invalid("the crop starts outside the image")));
+        assert_eq!(run(&src, 4, 3, "{}"), Ok(None));
+    }
+
+    #[test]
+    fn test_trim_border_with_tolerance() {
+        let mut src = framed([250, 250, 250, 255]);
+        // A slightly different border pixel is still border within the tolerance.
+        src[..4].copy_from_slice(&[247, 250, 250, 255]);
+        let out = run(&src, 6, 5, r#"{"trim": "border", "tolerance": 0.02}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (2, 2));
+
+        // Without tolerance the top-left pixel matches no other row or column: nothing to trim.
+        assert_eq!(run(&src, 6, 5, r#"{"trim": "border"}"#), Ok(None));
+    }
+
+    #[test]
+    fn test_trim_transparent_and_empty() {
+        let src = framed([0, 0, 0, 0]);
+        let out = run(&src, 6, 5, r#"{"trim": "transparent"}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (2, 2));
+        assert_eq!(&out.data[4..8], &[50, 10, 20, 255]);
+
+        // Nothing but border: the image is left unchanged.
+        let empty = [0u8; 4].repeat(9);
+        assert_eq!(run(&empty, 3, 3, r#"{"trim": "transparent"}"#), Ok(None));
+    }
+}
@@ -0,0 +1,219 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Number of intervals in a lookup table; values between entries are interpolated linearly.
+const LUT_STEPS: usize = 4096;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Params {
+    /// Control points `[in, out]` of the curve applied to all channels, after the channel's own.
+    points: Vec<[f32; 2]>,
+    red: Vec<[f32; 2]>,
+    green: Vec<[f32; 2]>,
+    blue: Vec<[f32; 2]>,
+}
+
+/// A monotone cubic (Fritsch-Carlson) curve through control points, flat beyond the outer ones.
+///
+/// Between two points the curve never overshoots them, so a rising set of points gives a
+/// rising curve without the ringing of an ordinary cubic spline.
+#[derive(Debug, Clone, PartialEq)]
+struct Curve {
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+    tangents: Vec<f32>,
+}
+
+impl Curve {
+    /// Builds the curve; no points means the identity. Inputs must be distinct and in `[0, 1]`.
+    fn new(points: &[[f32; 2]]) -> Result<Self, String> {
+        let mut points = points.to_vec();
+        if points.is_empty() {
+            points = vec![[0.0, 0.0], [1.0, 1.0]];
+        }
+        if let Some(p) = points.iter().find(|p| !(0.0..=1.0).contains(&p[0]) || !p[1].is_finite()) {
+            return Err(format!("control point {p:?} is outside the input range 0..1"));
+        }
+        points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        if let Some(w) = points.windows(2).find(|w| w[0][0] == w[1][0]) {
+            return Err(format!("two control points share the input {}", w[0][0]));
+        }
+
+        let xs: Vec<f32> = points.iter().map(|p| p[0]).collect();
+        let ys: Vec<f32> = points.iter().map(|p| p[1]).collect();
+        let slopes: Vec<f32> = (0..xs.len().saturating_sub(1))
+            .map(|k| (ys[k + 1] - ys[k]) / (xs[k + 1] - xs[k]))
+            .collect();
+
+        let mut tangents = vec![0.0; xs.len()];
+        if let (Some(first), Some(last)) = (slopes.first(), slopes.last()) {
+            tangents[0] = *first;
+            tangents[xs.len() - 1] = *last;
+        }
+        for k in 1..slopes.len() {
+            if slopes[k - 1] * slopes[k] > 0.0 {
+                tangents[k] = (slopes[k - 1] + slopes[k]) / 2.0;
+            }
+        }
+        for (k, &slope) in slopes.iter().enumerate() {
+            if slope == 0.0 {
+                tangents[k] = 0.0;
+                tangents[k + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (tangents[k] / slope, tangents[k + 1] / slope);
+            let s = a * a + b * b;
+            if s > 9.0 {
+                let t = 3.0 / s.sqrt();
+                tangents[k] = t * a * slope;
+                tangents[k + 1] = t * b * slope;
+            }
+        }
+        Ok(Self { xs, ys, tangents })
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        let last = self.xs.len() - 1;
+        if x <= self.xs[0] {
+            return self.ys[0];
+        }
+        if x >= self.xs[last] {
+            return self.ys[last];
+        }
+        let k = self.xs.partition_point(|&p| p <= x) - 1;
+        let h = self.xs[k + 1] - self.xs[k];
+        let t = (x - self.xs[k]) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * self.ys[k]
+            + (t3 - 2.0 * t2 + t) * h * self.tangents[k]
+            + (-2.0 * t3 + 3.0 * t2) * self.ys[k + 1]
+            + (t3 - t2) * h * self.tangents[k + 1]
+    }
+}
+
+/// A channel curve followed by the master curve, sampled over `[0, 1]`.
+struct Lut(Vec<f32>);
+
+impl Lut {
+    fn new(channel: &Curve, master: &Curve) -> Self {
+        Self((0..=LUT_STEPS).map(|i| master.eval(channel.eval(i as f32 / LUT_STEPS as f32))).collect())
+    }
+
+    fn lookup(&self, v: f32) -> f32 {
+        let pos = v.clamp(0.0, 1.0) * LUT_STEPS as f32;
+        let i = (pos as usize).min(LUT_STEPS - 1);
+        let frac = pos - i as f32;
+        self.0[i] + (self.0[i + 1] - self.0[i]) * frac
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "curves_plugin"
+version = "0.1.0"
+description = "Applies tone curves through control points, to all channels or per channel"
+
+[defaults]
+points = []
+red = []
+green = []
+blue = []
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Applies the curves to both formats; working per pixel, they are local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    let Ok(luts) = luts(params) else {
+        return Err(PluginError::Invalid("invalid curve points"));
+    };
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => apply(buf, &luts, premultiplied),
+        Pixels::Rgba32F(buf) => apply(buf, &luts, premultiplied),
+    }
+    Ok(())
+}
+
+fn luts(params: &Params) -> Result<[Lut; 3], String> {
+    let master = Curve::new(&params.points)?;
+    let [red, green, blue] = [&params.red, &params.green, &params.blue].map(|points| Curve::new(points));
+    Ok([Lut::new(&red?, &master), Lut::new(&green?, &master), Lut::new(&blue?, &master)])
+}
+
+/// Looks up the sRGB-encoded values in each channel's table, as an image editor's Curves
+/// dialog would, whatever the working space; alpha is kept.
+fn apply<T: Sample>(buf: &mut [T], luts: &[Lut; 3], premultiplied: bool) {
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let mut out = rgb;
+        for (v, lut) in out.iter_mut().zip(luts) {
+            *v = plugin_sdk::from_encoded::<T>(lut.lookup(plugin_sdk::to_encoded::<T>(*v)));
+        }
+        out
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_curve_passes_points_without_overshoot() {
+        let curve = Curve::new(&[[0.0, 0.0], [0.25, 0.5], [0.3, 0.55], [1.0, 1.0]]).unwrap();
+        for [x, y] in [[0.0, 0.0], [0.25, 0.5], [0.3, 0.55], [1.0, 1.0]] {
+            assert!((curve.eval(x) - y).abs() < 1e-6);
+        }
+        let samples: Vec<f32> = (0..=100).map(|i| curve.eval(i as f32 / 100.0)).collect();
+        assert!(samples.windows(2).all(|w| w[1] >= w[0]));
+
+        // Flat beyond the outer points.
+        let clipped = Curve::new(&[[0.2, 0.1], [0.8, 0.9]]).unwrap();
+        assert_eq!(clipped.eval(0.0), 0.1);
+        assert_eq!(clipped.eval(1.0), 0.9);
+
+        assert!(Curve::new(&[[0.5, 0.0], [0.5, 1.0]]).is_err());
+        assert!(Curve::new(&[[1.5, 0.0]]).is_err());
+    }
+
+    #[test]
+    fn test_defaults_are_identity() {
+        let src: Vec<u8> = (0..=255).flat_map(|v| [v, 255 - v, v / 3, v]).collect();
+        let mut buf = src.clone();
+        run(&mut buf, "{}").unwrap();
+        assert_eq!(buf, src);
+    }
+
+    #[test]
+    fn test_channel_then_master() {
+        // The red curve inverts, then the master curve halves everything.
+        let params = r#"{"red": [[0, 1], [1, 0]], "points": [[0, 0], [1, 0.5]]}"#;
+        let mut buf = [255u8, 255, 0, 90];
+        run(&mut buf, params).unwrap();
+        assert_eq!(buf, [0, 128, 0, 90]);
+
+        let mut bad = [0u8; 4];
+        assert!(run(&mut bad, r#"{"points": [[0.5, 0], [0.5, 1]]}"#).is_err());
+    }
+
+    #[test]
+    fn test_rgba32f_works_on_encoded_values() {
+        // An S-curve leaves the midpoint of the encoded range in place.
+        let mut img = [plugin_sdk::srgb_to_linear(0.5), 0.0, 1.0, 1.0];
+        let params = r#"{"points": [[0, 0], [0.25, 0.15], [0.5, 0.5], [0.75, 0.85], [1, 1]]}"#;
+        testing::run(process, 1, 1, &mut img, params).unwrap();
+        assert!((img[0] - plugin_sdk::srgb_to_linear(0.5)).abs() < 1e-5);
+        assert_eq!(&img[1..], &[0.0, 1.0, 1.0]);
+    }
+}
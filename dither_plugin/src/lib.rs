@@ -0,0 +1,232 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// How the rounding to `levels` is spread out.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum Method {
+    /// Ordered dithering with a `matrix_size` Bayer threshold matrix: a regular crosshatch
+    /// pattern that stays stable when the image changes, suiting e-ink and animation.
+    #[default]
+    Bayer,
+    /// Error diffusion spreading all of each pixel's error to four neighbors.
+    FloydSteinberg,
+    /// Error diffusion spreading three quarters of the error to six neighbors; keeps more
+    /// contrast in highlights and shadows, as on early Macintosh displays.
+    Atkinson,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    method: Method,
+    /// Output values per channel; 2 gives 1-bit output.
+    #[serde(default = "default_levels")]
+    levels: u32,
+    /// Side of the Bayer matrix: 2, 4 or 8.
+    #[serde(default = "default_matrix_size")]
+    matrix_size: u32,
+    /// Converts to luma first, so the output only has gray levels.
+    #[serde(default)]
+    grayscale: bool,
+    /// Alternates the scan direction of error diffusion on every row, avoiding the diagonal
+    /// "worms" of a plain left-to-right scan.
+    #[serde(default = "default_serpentine")]
+    serpentine: bool,
+}
+
+fn default_levels() -> u32 {
+    2
+}
+
+fn default_matrix_size() -> u32 {
+    4
+}
+
+fn default_serpentine() -> bool {
+    true
+}
+
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Neighbor offsets and weights for error diffusion, with the weights' divisor.
+const FLOYD_STEINBERG: (&[(i64, usize, f32)], f32) = (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+const ATKINSON: (&[(i64, usize, f32)], f32) =
+    (&[(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)], 8.0);
+
+const MANIFEST: &CStr = cr#"name = "dither_plugin"
+version = "0.1.0"
+description = "Reduces to a few levels per channel with Bayer, Floyd-Steinberg or Atkinson dithering"
+
+[defaults]
+method = "bayer"
+levels = 2
+matrix_size = 4
+grayscale = false
+serpentine = true
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Dithers both formats. It is not local: the Bayer pattern is anchored at the image origin,
+/// which a tile does not know, and diffused error crosses the whole image.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, pixels, .. } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.levels < 2 || ![2, 4, 8].contains(&params.matrix_size) {
+        return Err(PluginError::Invalid("levels must be at least 2 and matrix_size 2, 4 or 8"));
+    }
+
+    match pixels {
+        Pixels::Rgba8(buf) => dither(width as usize, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => dither(width as usize, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Rounds every straight sRGB-encoded channel to `levels` evenly spaced values, dithering the
+/// rounding error away. Alpha is kept; fully transparent pixels are left alone and take no
+/// diffused error.
+fn dither<T: Sample>(width: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 {
+        return;
+    }
+    let alphas: Vec<f32> = buf.chunks_exact(4).map(|px| px[3].to_f32() / T::MAX).collect();
+    let mut colors: Vec<[f32; 3]> = buf
+        .chunks_exact(4)
+        .zip(&alphas)
+        .map(|(px, &a)| {
+            let scale = if premultiplied && a > 0.0 { T::MAX * a } else { T::MAX };
+            let rgb: [f32; 3] = std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale));
+            if params.grayscale {
+                [(0..3).map(|c| LUMA[c] * rgb[c]).sum(); 3]
+            } else {
+                rgb
+            }
+        })
+        .collect();
+
+    let steps = (params.levels - 1) as f32;
+    let height = colors.len() / width;
+    match params.method {
+        Method::Bayer => {
+            let n = params.matrix_size as usize;
+            let matrix = bayer(n);
+            for (i, color) in colors.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                let offset = 1.0 - (matrix[y % n * n + x % n] + 0.5) / (n * n) as f32;
+                *color = color.map(|v| ((v.clamp(0.0, 1.0) * steps + offset).floor() / steps).min(1.0));
+            }
+        }
+        Method::FloydSteinberg | Method::Atkinson => {
+            let (kernel, divisor) = if params.method == Method::Atkinson { ATKINSON } else { FLOYD_STEINBERG };
+            for y in 0..height {
+                let reverse = params.serpentine && y % 2 == 1;
+                for step in 0..width {
+                    let x = if reverse { width - 1 - step } else { step };
+                    let i = y * width + x;
+                    if alphas[i] <= 0.0 {
+                        continue;
+                    }
+                    let old = colors[i];
+                    let new = old.map(|v| (v.clamp(0.0, 1.0) * steps).round() / steps);
+                    colors[i] = new;
+                    for &(dx, dy, weight) in kernel {
+                        let nx = x as i64 + if reverse { -dx } else { dx };
+                        if nx < 0 || nx >= width as i64 || y + dy >= height {
+                            continue;
+                        }
+                        let n = (y + dy) * width + nx as usize;
+                        for c in 0..3 {
+                            colors[n][c] += (old[c] - new[c]) * weight / divisor;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for ((px, color), a) in buf.chunks_exact_mut(4).zip(&colors).zip(&alphas) {
+        if *a <= 0.0 {
+            continue;
+        }
+        let scale = if premultiplied { T::MAX * a } else { T::MAX };
+        for c in 0..3 {
+            px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(color[c]) * scale);
+        }
+    }
+}
+
+/// The `n`x`n` Bayer index matrix (0 to n²-1, row-major), built by recursive doubling.
+fn bayer(n: usize) -> Vec<f32> {
+    let mut matrix = vec![0.0];
+    let mut size = 1;
+    while size < n {
+        let next = size * 2;
+        matrix = (0..next * next)
+            .map(|i| {
+                let (x, y) = (i % next, i / next);
+                let quadrant = [0.0, 2.0, 3.0, 1.0][(y / size) * 2 + x / size];
+                4.0 * matrix[y % size * size + x % size] + quadrant
+            })
+            .collect();
+        size = next;
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    fn mean(img: &[u8]) -> f32 {
+        img.chunks(4).map(|p| p[0] as f32).sum::<f32>() * 4.0 / img.len() as f32
+    }
+
+    #[test]
+    fn test_bayer_matrix() {
+        assert_eq!(bayer(2), [0.0, 2.0, 3.0, 1.0]);
+        let mut m4 = bayer(4);
+        assert_eq!(m4[..4], [0.0, 8.0, 2.0, 10.0]);
+        m4.sort_by(f32::total_cmp);
+        assert_eq!(m4, (0..16).map(|v| v as f32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_one_bit_keeps_average() {
+        // A flat gray dithered to black and white keeps its level with every method.
+        for method in ["bayer", "floyd_steinberg", "atkinson"] {
+            let mut img = [128u8, 128, 128, 200].repeat(256);
+            run(&mut img, 16, 16, &format!(r#"{{"method": "{method}"}}"#)).unwrap();
+            assert!(img.chunks(4).all(|p| matches!(p, [0, 0, 0, 200] | [255, 255, 255, 200])), "{method}");
+            assert!((mean(&img) - 128.0).abs() < 10.0, "{method}: {}", mean(&img));
+        }
+
+        // A 50% gray lights the two lowest-threshold cells of a 2x2 Bayer tile, on one diagonal.
+        let mut img = [128u8, 128, 128, 255].repeat(4);
+        run(&mut img, 2, 2, r#"{"matrix_size": 2}"#).unwrap();
+        assert_eq!(img.chunks(4).map(|p| p[0]).collect::<Vec<_>>(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_levels_and_grayscale() {
+        let mut img: Vec<u8> = (0..64).flat_map(|i| [i * 4, 255 - i * 4, 60, 255]).collect();
+        run(&mut img, 8, 8, r#"{"method": "atkinson", "levels": 4, "grayscale": true}"#).unwrap();
+        assert!(img.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2] && [0, 85, 170, 255].contains(&p[0])));
+
+        let mut img = [100u8; 4];
+        assert!(run(&mut img, 1, 1, r#"{"matrix_size": 3}"#).is_err());
+        assert!(run(&mut img, 1, 1, r#"{"levels": 1}"#).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Color, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Gradient operator.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Operator {
+    /// 3x3 Sobel kernels.
+    #[default]
+    Sobel,
+    /// 3x3 Scharr kernels; more accurate gradient directions than Sobel.
+    Scharr,
+}
+
+impl Operator {
+    /// Weights of the smoothing column (or row) of the kernel, and their sum, which is the
+    /// response to a unit step.
+    fn weights(self) -> ([f32; 3], f32) {
+        match self {
+            Self::Sobel => ([1.0, 2.0, 1.0], 4.0),
+            Self::Scharr => ([3.0, 10.0, 3.0], 16.0),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Gradient magnitude; soft edges whose brightness follows their contrast.
+    #[default]
+    Magnitude,
+    /// Canny: thin, binary edges from non-maximum suppression and hysteresis thresholds.
+    Canny,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Output {
+    /// White edges on black; alpha is kept.
+    #[default]
+    Edges,
+    /// Edges drawn in `color` over the original image.
+    Overlay,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    operator: Operator,
+    #[serde(default)]
+    mode: Mode,
+    /// Canny: standard deviation in pixels of the Gaussian applied first; 0 skips it.
+    #[serde(default = "default_sigma")]
+    sigma: f32,
+    /// Canny: gradient magnitude, in units of full scale, below which nothing is an edge.
+    #[serde(default = "default_low")]
+    low: f32,
+    /// Canny: gradient magnitude above which pixels are edges; pixels between `low` and `high`
+    /// are edges only if connected to one.
+    #[serde(default = "default_high")]
+    high: f32,
+    #[serde(default)]
+    output: Output,
+    /// Color of the edges with `output = "overlay"`.
+    #[serde(default = "default_color")]
+    color: Color,
+}
+
+fn default_sigma() -> f32 {
+    1.4
+}
+
+fn default_low() -> f32 {
+    0.1
+}
+
+fn default_high() -> f32 {
+    0.3
+}
+
+fn default_color() -> Color {
+    Color::WHITE
+}
+
+const MANIFEST: &CStr = cr##"name = "edges_plugin"
+version = "0.1.0"
+description = "Sobel or Scharr gradient magnitude, or Canny edges, as an image or an overlay"
+
+[defaults]
+operator = "sobel"
+mode = "magnitude"
+sigma = 1.4
+low = 0.1
+high = 0.3
+output = "edges"
+color = "#ffffff"
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Detects edges in both formats.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => detect(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => detect(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// The gradient magnitude is local with a one-pixel halo, while Canny's hysteresis can follow
+/// an edge across the whole image.
+fn halo(params: &Params) -> Option<u32> {
+    (params.mode == Mode::Magnitude).then_some(1)
+}
+
+/// Finds edges in the luma of the sRGB-encoded colors and writes them per `output`.
+///
+/// Edge strengths are in `[0, 1]`; a step from black to white has a gradient magnitude of 1.
+fn detect<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let colors: Vec<[f32; 4]> = buf.chunks_exact(4).map(|px| straight::<T>(px, premultiplied)).collect();
+    let mut luma: Vec<f32> = colors.iter().map(|c| LUMA[0] * c[0] + LUMA[1] * c[1] + LUMA[2] * c[2]).collect();
+
+    let strength = match params.mode {
+        Mode::Magnitude => gradients(&luma, width, height, params.operator).0,
+        Mode::Canny => {
+            if params.sigma > 0.0 {
+                luma = gaussian(&luma, width, height, params.sigma);
+            }
+            let (magnitude, direction) = gradients(&luma, width, height, params.operator);
+            let thin = suppress(&magnitude, &direction, width, height);
+            hysteresis(&thin, width, height, params.low, params.high)
+        }
+    };
+
+    let edge_color = params.color.normalized::<u8>();
+    for ((px, color), s) in buf.chunks_exact_mut(4).zip(&colors).zip(strength) {
+        let s = s.clamp(0.0, 1.0);
+        let rgb: [f32; 3] = match params.output {
+            Output::Edges => [s; 3],
+            Output::Overlay => {
+                let s = s * edge_color[3];
+                std::array::from_fn(|c| color[c] + (edge_color[c] - color[c]) * s)
+            }
+        };
+        let scale = if premultiplied { T::MAX * color[3] } else { T::MAX };
+        for c in 0..3 {
+            px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(rgb[c]) * scale);
+        }
+    }
+}
+
+/// Gradient magnitude and direction (radians) of every pixel, clamping at the edges.
+fn gradients(luma: &[f32], width: usize, height: usize, operator: Operator) -> (Vec<f32>, Vec<f32>) {
+    let ([a, b, c], norm) = operator.weights();
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, width as i64 - 1) as usize, y.clamp(0, height as i64 - 1) as usize);
+        luma[y * width + x]
+    };
+    let mut magnitude = vec![0.0; luma.len()];
+    let mut direction = vec![0.0; luma.len()];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let gx = a * (at(x + 1, y - 1) - at(x - 1, y - 1))
+                + b * (at(x + 1, y) - at(x - 1, y))
+                + c * (at(x + 1, y + 1) - at(x - 1, y + 1));
+            let gy = a * (at(x - 1, y + 1) - at(x - 1, y - 1))
+                + b * (at(x, y + 1) - at(x, y - 1))
+                + c * (at(x + 1, y + 1) - at(x + 1, y - 1));
+            let i = y as usize * width + x as usize;
+            // A unit step between this pixel and a neighbor gives `norm`.
+            magnitude[i] = gx.hypot(gy) / norm;
+            direction[i] = gy.atan2(gx);
+        }
+    }
+    (magnitude, direction)
+}
+
+/// Keeps only the pixels whose magnitude is a maximum across the edge, along the gradient
+/// direction rounded to 45 degrees. Ties go to the pixel on the left or top, so a step
+/// between two pixels gives a one-pixel line.
+fn suppress(magnitude: &[f32], direction: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let at = |x: i64, y: i64| {
+        let inside = (0..width as i64).contains(&x) && (0..height as i64).contains(&y);
+        if inside { magnitude[y as usize * width + x as usize] } else { 0.0 }
+    };
+    let mut out = vec![0.0; magnitude.len()];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let i = y as usize * width + x as usize;
+            let octant = (direction[i].to_degrees().rem_euclid(180.0) / 45.0).round() as i64 % 4;
+            let (dx, dy) = [(1, 0), (1, 1), (0, 1), (-1, 1)][octant as usize];
+            let m = magnitude[i];
+            if m >= at(x + dx, y + dy) && m > at(x - dx, y - dy) {
+                out[i] = m;
+            }
+        }
+    }
+    out
+}
+
+/// Marks pixels above `high` as edges, then grows them through 8-connected pixels above `low`.
+fn hysteresis(magnitude: &[f32], width: usize, height: usize, low: f32, high: f32) -> Vec<f32> {
+    let mut edges = vec![0.0; magnitude.len()];
+    let mut stack: Vec<usize> = (0..magnitude.len()).filter(|&i| magnitude[i] >= high).collect();
+    for &i in &stack {
+        edges[i] = 1.0;
+    }
+    while let Some(i) = stack.pop() {
+        let (x, y) = ((i % width) as i64, (i / width) as i64);
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                continue;
+            }
+            let n = ny as usize * width + nx as usize;
+            if edges[n] == 0.0 && magnitude[n] >= low {
+                edges[n] = 1.0;
+                stack.push(n);
+            }
+        }
+    }
+    edges
+}
+
+/// Separable Gaussian blur of a single-channel image, clamping at the edges.
+fn gaussian(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let r = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let norm: f32 = kernel.iter().sum();
+    let pass = |src: &[f32], step: (i64, i64)| -> Vec<f32> {
+        let mut out = vec![0.0; src.len()];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let sum: f32 = kernel
+                    .iter()
+                    .zip(-r..)
+                    .map(|(k, d)| {
+                        let sx = (x + d * step.0).clamp(0, width as i64 - 1) as usize;
+                        let sy = (y + d * step.1).clamp(0, height as i64 - 1) as usize;
+                        k * src[sy * width + sx]
+                    })
+                    .sum();
+                out[y as usize * width + x as usize] = sum / norm;
+            }
+        }
+        out
+    };
+    pass(&pass(src, (1, 0)), (0, 1))
+}
+
+/// A pixel's straight-alpha color, sRGB-encoded and normalized to `[0, 1]`, followed by its alpha.
+fn straight<T: Sample>(px: &[T], premultiplied: bool) -> [f32; 4] {
+    let alpha = px[3].to_f32() / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    let color = |v: T| plugin_sdk::to_encoded::<T>(v.to_f32() / scale);
+    [color(px[0]), color(px[1]), color(px[2]), alpha]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A `size` x `size` image split into a left half of gray level `left` and a right half
+    /// whose level in row `y` is `right(y)`.
+    fn step(size: usize, left: u8, right: impl Fn(usize) -> u8) -> Vec<u8> {
+        (0..size * size)
+            .flat_map(|i| {
+                let v = if i % size < size / 2 { left } else { right(i / size) };
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    fn row(img: &[u8], size: usize, y: usize) -> Vec<u8> {
+        img[y * size * 4..(y + 1) * size * 4].chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_magnitude() {
+        let mut img = step(8, 0, |_| 255);
+        run(&mut img, 8, 8, "{}").unwrap();
+        assert_eq!(row(&img, 8, 3), [0, 0, 0, 255, 255, 0, 0, 0]);
+
+        let mut scharr = step(8, 0, |_| 255);
+        run(&mut scharr, 8, 8, r#"{"operator": "scharr"}"#).unwrap();
+        assert_eq!(scharr, img);
+    }
+
+    #[test]
+    fn test_canny_thin_edges_and_hysteresis() {
+        // The edge fades towards the bottom; its strong top carries the weak rest through hysteresis.
+        let mut img = step(12, 40, |y| 200 - 12 * y as u8);
+        run(&mut img, 12, 12, r#"{"mode": "canny", "sigma": 0, "low": 0.1, "high": 0.4}"#).unwrap();
+        for y in 0..12 {
+            let edges: Vec<usize> = (0..12).filter(|&x| row(&img, 12, y)[x] == 255).collect();
+            assert!(edges == [5] || edges == [6], "row {y}: {edges:?}");
+        }
+
+        // A weak edge alone is dropped.
+        let mut weak = step(12, 40, |_| 80);
+        run(&mut weak, 12, 12, r#"{"mode": "canny", "sigma": 0, "low": 0.1, "high": 0.4}"#).unwrap();
+        assert!(weak.chunks(4).all(|p| p[0] == 0));
+
+        let params = plugin_sdk::params_from_str(r#"{"mode": "canny"}"#).unwrap();
+        assert_eq!(halo(&params), None);
+    }
+
+    #[test]
+    fn test_overlay_keeps_flat_areas() {
+        let mut img = step(8, 30, |_| 200);
+        let params = r##"{"output": "overlay", "color": "#ff0000", "mode": "canny", "sigma": 0}"##;
+        run(&mut img, 8, 8, params).unwrap();
+        assert_eq!(&img[..4], &[30, 30, 30, 255]);
+        assert_eq!(&img[(3 * 8 + 3) * 4..][..4], &[255, 0, 0, 255]);
+    }
+}
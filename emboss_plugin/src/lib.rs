@@ -0,0 +1,190 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// How the relief is combined with the image.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Blend {
+    /// The relief alone, in gray around mid gray.
+    #[default]
+    Gray,
+    /// The relief overlaid on the original colors, which shades them without graying them.
+    Overlay,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Direction the light comes from, in degrees counterclockwise from the positive x axis;
+    /// the default lights from the top left.
+    #[serde(default = "default_angle")]
+    angle: f32,
+    /// Height of the relief; at 1, a black-to-white step facing the light turns white.
+    #[serde(default = "default_depth")]
+    depth: f32,
+    #[serde(default)]
+    blend: Blend,
+    /// Mix between the original (0) and the embossed image (1).
+    #[serde(default = "default_amount")]
+    amount: f32,
+}
+
+fn default_angle() -> f32 {
+    135.0
+}
+
+fn default_depth() -> f32 {
+    1.0
+}
+
+fn default_amount() -> f32 {
+    1.0
+}
+
+const MANIFEST: &CStr = cr#"name = "emboss_plugin"
+version = "0.1.0"
+description = "Emboss and relief shading with a light direction"
+
+[defaults]
+angle = 135.0
+depth = 1.0
+blend = "gray"
+amount = 1.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(1),
+}
+
+/// Embosses both formats; the relief is local with a one-pixel halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => emboss(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => emboss(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Treats the luma of the sRGB-encoded colors as a height map and shades it from the light
+/// direction: slopes facing the light get brighter than mid gray, slopes facing away darker.
+///
+/// The slope is the Sobel gradient, clamped at the image edges; alpha is kept.
+fn emboss<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let colors: Vec<[f32; 4]> = buf.chunks_exact(4).map(|px| straight::<T>(px, premultiplied)).collect();
+    let luma: Vec<f32> = colors.iter().map(|c| LUMA[0] * c[0] + LUMA[1] * c[1] + LUMA[2] * c[2]).collect();
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, width as i64 - 1) as usize, y.clamp(0, height as i64 - 1) as usize);
+        luma[y * width + x]
+    };
+    // The y axis points down, so a counterclockwise angle has a negative y component.
+    let (sin, cos) = params.angle.to_radians().sin_cos();
+    let (lx, ly) = (cos, -sin);
+    let amount = params.amount.clamp(0.0, 1.0);
+
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let (x, y) = ((i % width) as i64, (i / width) as i64);
+        let gx = (at(x + 1, y - 1) - at(x - 1, y - 1))
+            + 2.0 * (at(x + 1, y) - at(x - 1, y))
+            + (at(x + 1, y + 1) - at(x - 1, y + 1));
+        let gy = (at(x - 1, y + 1) - at(x - 1, y - 1))
+            + 2.0 * (at(x, y + 1) - at(x, y - 1))
+            + (at(x + 1, y + 1) - at(x + 1, y - 1));
+        // The surface rises where the gradient points; it faces the light when the gradient
+        // points away from it. A unit step gives a gradient of 4.
+        let shade = (0.5 - params.depth * (gx * lx + gy * ly) / 8.0).clamp(0.0, 1.0);
+
+        let color = colors[i];
+        let scale = if premultiplied { T::MAX * color[3] } else { T::MAX };
+        for c in 0..3 {
+            let embossed = match params.blend {
+                Blend::Gray => shade,
+                Blend::Overlay => overlay(color[c], shade),
+            };
+            let v = color[c] + (embossed - color[c]) * amount;
+            px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(v) * scale);
+        }
+    }
+}
+
+/// The overlay blend mode: darkens with `shade` below one half and lightens above it.
+fn overlay(base: f32, shade: f32) -> f32 {
+    if base < 0.5 { 2.0 * base * shade } else { 1.0 - 2.0 * (1.0 - base) * (1.0 - shade) }
+}
+
+/// A pixel's straight-alpha color, sRGB-encoded and normalized to `[0, 1]`, followed by its alpha.
+fn straight<T: Sample>(px: &[T], premultiplied: bool) -> [f32; 4] {
+    let alpha = px[3].to_f32() / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    let color = |v: T| plugin_sdk::to_encoded::<T>(v.to_f32() / scale);
+    [color(px[0]), color(px[1]), color(px[2]), alpha]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A dark 7x7 image with a bright 3x3 square in the middle.
+    fn square() -> Vec<u8> {
+        (0..49)
+            .flat_map(|i| {
+                let (x, y) = (i % 7, i / 7);
+                let v = if (2..5).contains(&x) && (2..5).contains(&y) { 200 } else { 50 };
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    fn red(img: &[u8], x: usize, y: usize) -> u8 {
+        img[(y * 7 + x) * 4]
+    }
+
+    #[test]
+    fn test_lit_from_top_left() {
+        let mut img = square();
+        run(&mut img, 7, 7, "{}").unwrap();
+        // Flat areas are mid gray, edges facing the light brighter, the far edges darker.
+        assert_eq!(red(&img, 0, 6), 128);
+        assert!(red(&img, 2, 2) > 180);
+        assert!(red(&img, 4, 4) < 80);
+
+        // Light from the right: the right edge is now the lit one.
+        let mut img = square();
+        run(&mut img, 7, 7, r#"{"angle": 0}"#).unwrap();
+        assert!(red(&img, 4, 3) > 180 && red(&img, 2, 3) < 80);
+        assert_eq!(red(&img, 3, 0), 128);
+    }
+
+    #[test]
+    fn test_overlay_and_amount() {
+        let flat = [200u8, 100, 50, 255].repeat(9);
+        let mut img = flat.clone();
+        run(&mut img, 3, 3, r#"{"blend": "overlay"}"#).unwrap();
+        assert_eq!(img, flat);
+
+        let mut img = square();
+        run(&mut img, 7, 7, r#"{"amount": 0}"#).unwrap();
+        assert_eq!(img, square());
+
+        let mut img = square();
+        run(&mut img, 7, 7, r#"{"blend": "overlay", "depth": 2}"#).unwrap();
+        assert!(red(&img, 2, 2) > 200 && red(&img, 4, 4) < 200);
+    }
+}
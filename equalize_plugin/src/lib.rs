@@ -0,0 +1,262 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Number of luma histogram bins.
+const BINS: usize = 256;
+
+/// Where the histogram is taken.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// One histogram for the whole image.
+    Global,
+    /// Contrast-limited adaptive equalization: one histogram per tile, clipped at `clip_limit`,
+    /// with the mappings of neighboring tiles blended so no tile edges show.
+    #[default]
+    Clahe,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    method: Method,
+    /// Edge length of the CLAHE tiles in pixels; the image is divided into equal tiles about
+    /// this large.
+    #[serde(default = "default_tile_size")]
+    tile_size: u32,
+    /// Highest count of a CLAHE histogram bin, as a multiple of the average count; the excess is
+    /// spread over all bins, which limits how much flat areas and noise are amplified. 0 turns
+    /// clipping off.
+    #[serde(default = "default_clip_limit")]
+    clip_limit: f32,
+}
+
+fn default_tile_size() -> u32 {
+    64
+}
+
+fn default_clip_limit() -> f32 {
+    2.0
+}
+
+const MANIFEST: &CStr = cr#"name = "equalize_plugin"
+version = "0.1.0"
+description = "Histogram equalization of the luma, globally or adaptively (CLAHE)"
+
+[defaults]
+method = "clahe"
+tile_size = 64
+clip_limit = 2.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Equalizes both formats. It is not local: the histograms span the whole image or its tile grid.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.tile_size == 0 || params.clip_limit.is_nan() {
+        return Err(PluginError::Invalid("tile_size must be positive and clip_limit a number"));
+    }
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => equalize(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => equalize(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Equalizes the luma of the sRGB-encoded colors and shifts all three channels by its change,
+/// which keeps the color differences (Cb and Cr) and so the hues.
+///
+/// Only visible pixels count towards the histograms; transparent ones are left alone.
+fn equalize<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let colors: Vec<Option<[f32; 3]>> = buf
+        .chunks_exact(4)
+        .map(|px| {
+            let alpha = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied { T::MAX * alpha } else { T::MAX };
+            (alpha > 0.0).then(|| std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)))
+        })
+        .collect();
+    let luma: Vec<Option<f32>> =
+        colors.iter().map(|rgb| rgb.map(|rgb| (0..3).map(|c| LUMA[c] * rgb[c]).sum())).collect();
+
+    let mapped: Vec<f32> = match params.method {
+        Method::Global => {
+            let map = mapping(&histogram(luma.iter().flatten().copied()), 0.0);
+            luma.iter().map(|l| l.map_or(0.0, |l| lookup(&map, l))).collect()
+        }
+        Method::Clahe => clahe(width, height, &luma, params),
+    };
+
+    for ((px, rgb), (luma, new)) in buf.chunks_exact_mut(4).zip(&colors).zip(luma.iter().zip(mapped)) {
+        let (Some(rgb), Some(luma)) = (rgb, luma) else {
+            continue;
+        };
+        let alpha = px[3].to_f32() / T::MAX;
+        for c in 0..3 {
+            let v = plugin_sdk::from_encoded::<T>((rgb[c] + new - luma).clamp(0.0, 1.0));
+            px[c] = T::from_f32(if premultiplied { v * alpha } else { v } * T::MAX);
+        }
+    }
+}
+
+/// Counts luma values in `[0, 1]` into [`BINS`] bins.
+fn histogram(luma: impl Iterator<Item = f32>) -> [f32; BINS] {
+    let mut hist = [0.0; BINS];
+    for l in luma {
+        hist[(l.clamp(0.0, 1.0) * (BINS - 1) as f32).round() as usize] += 1.0;
+    }
+    hist
+}
+
+/// Turns a histogram into an equalizing map from bin to luma, clipping bins above `clip_limit`
+/// times the average count first (0 for no clipping).
+///
+/// The lowest occupied bin maps to 0 and the highest to 1; an empty or single-valued
+/// histogram maps to itself.
+fn mapping(hist: &[f32; BINS], clip_limit: f32) -> [f32; BINS] {
+    let identity = std::array::from_fn(|i| i as f32 / (BINS - 1) as f32);
+    let total: f32 = hist.iter().sum();
+    let mut hist = *hist;
+    if clip_limit > 0.0 {
+        let limit = (clip_limit * total / BINS as f32).max(1.0);
+        let excess: f32 = hist.iter().map(|&h| (h - limit).max(0.0)).sum();
+        for h in &mut hist {
+            *h = h.min(limit) + excess / BINS as f32;
+        }
+    }
+
+    let mut cdf = [0.0; BINS];
+    let mut sum = 0.0;
+    for (c, h) in cdf.iter_mut().zip(hist) {
+        sum += h;
+        *c = sum;
+    }
+    let Some(&lowest) = cdf.iter().find(|&&c| c > 0.0) else {
+        return identity;
+    };
+    if total - lowest <= 0.0 {
+        return identity;
+    }
+    cdf.map(|c| ((c - lowest) / (total - lowest)).max(0.0))
+}
+
+/// Looks up luma `l` in `map`, interpolating between bins.
+fn lookup(map: &[f32; BINS], l: f32) -> f32 {
+    let pos = l.clamp(0.0, 1.0) * (BINS - 1) as f32;
+    let i = (pos as usize).min(BINS - 2);
+    map[i] + (map[i + 1] - map[i]) * (pos - i as f32)
+}
+
+/// Maps each luma value through the clipped equalization of its tile, blended bilinearly with
+/// the neighboring tiles by the distance to their centers.
+fn clahe(width: usize, height: usize, luma: &[Option<f32>], params: &Params) -> Vec<f32> {
+    let tile = params.tile_size as usize;
+    let (tiles_x, tiles_y) = (width.div_ceil(tile), height.div_ceil(tile));
+    let (tile_w, tile_h) = (width as f32 / tiles_x as f32, height as f32 / tiles_y as f32);
+
+    let mut maps = Vec::with_capacity(tiles_x * tiles_y);
+    for ty in 0..tiles_y {
+        let rows = ty * height / tiles_y..(ty + 1) * height / tiles_y;
+        for tx in 0..tiles_x {
+            let columns = tx * width / tiles_x..(tx + 1) * width / tiles_x;
+            let values = rows.clone().flat_map(|y| luma[y * width..][columns.clone()].iter().flatten().copied());
+            maps.push(mapping(&histogram(values), params.clip_limit));
+        }
+    }
+
+    // Fractional tile coordinate of a pixel, 0 at the first tile's center.
+    let grid = |p: usize, size: f32, tiles: usize| ((p as f32 + 0.5) / size - 0.5).clamp(0.0, (tiles - 1) as f32);
+    luma.iter()
+        .enumerate()
+        .map(|(i, l)| {
+            let Some(l) = *l else {
+                return 0.0;
+            };
+            let (gx, gy) = (grid(i % width, tile_w, tiles_x), grid(i / width, tile_h, tiles_y));
+            let (x0, y0) = (gx as usize, gy as usize);
+            let (x1, y1) = ((x0 + 1).min(tiles_x - 1), (y0 + 1).min(tiles_y - 1));
+            let (fx, fy) = (gx - x0 as f32, gy - y0 as f32);
+            let at = |tx: usize, ty: usize| lookup(&maps[ty * tiles_x + tx], l);
+            let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+            let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+            top + (bottom - top) * fy
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    fn reds(img: &[u8]) -> Vec<u8> {
+        img.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_global_stretches_luma_keeping_hue() {
+        let mut img: Vec<u8> = [100u8, 110, 120, 130].iter().flat_map(|&v| [v, v, v, 255]).collect();
+        run(&mut img, 4, 1, r#"{"method": "global"}"#).unwrap();
+        assert_eq!(reds(&img), [0, 85, 170, 255]);
+
+        // A tinted pixel is shifted by the change of its luma in all channels; a transparent
+        // one is left alone and does not count.
+        let mut img = [[60u8, 60, 60, 255], [90, 60, 60, 255], [200, 200, 200, 255], [7, 8, 9, 0]].concat();
+        run(&mut img, 4, 1, r#"{"method": "global"}"#).unwrap();
+        assert_eq!(&img[..4], &[0, 0, 0, 255]);
+        assert_eq!(img[8..11], [255, 255, 255]);
+        assert_eq!(img[4] - img[5], 30);
+        assert_eq!(img[5], img[6]);
+        assert_eq!(&img[12..], &[7, 8, 9, 0]);
+    }
+
+    #[test]
+    fn test_clahe_adapts_to_tiles() {
+        // A dark left half and a bright right half, each with a small ramp.
+        let (width, height) = (64, 32);
+        let mut img = Vec::new();
+        for _ in 0..height {
+            for x in 0..width {
+                let v = if x < 32 { 20 + x as u8 } else { 200 + (x - 32) as u8 };
+                img.extend([v, v, v, 255]);
+            }
+        }
+        let left_range = |img: &[u8]| {
+            let row = reds(&img[..width * 4]);
+            row[..16].iter().max().unwrap() - row[..16].iter().min().unwrap()
+        };
+        let mut global = img.clone();
+        run(&mut global, 64, 32, r#"{"method": "global"}"#).unwrap();
+        let mut local = img.clone();
+        run(&mut local, 64, 32, r#"{"tile_size": 32, "clip_limit": 0.0}"#).unwrap();
+        // The dark half gets the whole range in its own tile, but only half of it globally.
+        assert!(left_range(&local) > left_range(&global) + 40, "{} {}", left_range(&local), left_range(&global));
+
+        // Clipping limits the stretch.
+        let mut clipped = img.clone();
+        run(&mut clipped, 64, 32, r#"{"tile_size": 32, "clip_limit": 1.5}"#).unwrap();
+        assert!(left_range(&clipped) < left_range(&local));
+
+        assert!(run(&mut img, 64, 32, r#"{"tile_size": 0}"#).is_err());
+    }
+}
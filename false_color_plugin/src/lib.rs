@@ -0,0 +1,176 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Perceptually ordered colormap the values are shown in.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Colormap {
+    /// Dark blue through green to yellow; perceptually uniform and readable with color blindness.
+    #[default]
+    Viridis,
+    /// Black through purple and orange to pale yellow.
+    Magma,
+    /// Google's improved rainbow, from dark blue through green to dark red; shows fine detail but
+    /// is not perceptually uniform.
+    Turbo,
+}
+
+/// The value of each pixel that is mapped.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Source {
+    #[default]
+    Luminance,
+    Red,
+    Green,
+    Blue,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    colormap: Colormap,
+    #[serde(default)]
+    source: Source,
+    /// Value shown with the colormap's first color; lower values clamp to it.
+    #[serde(default)]
+    min: f32,
+    /// Value shown with the colormap's last color; if below `min`, the colormap is reversed.
+    #[serde(default = "default_max")]
+    max: f32,
+}
+
+fn default_max() -> f32 {
+    1.0
+}
+
+impl Colormap {
+    /// Polynomial coefficients per channel, constant term first.
+    ///
+    /// Viridis and magma are degree-6 fits of the matplotlib tables and turbo the degree-5 fit
+    /// published alongside it; they stay within a few 8-bit steps of the tables except at turbo's
+    /// darkest ends.
+    fn coefficients(self) -> [&'static [f32]; 3] {
+        match self {
+            Self::Viridis => [
+                &[0.277_727_3, 0.105_093_04, -0.330_861_83, -4.634_230_5, 6.228_27, 4.776_385, -5.435_456],
+                &[0.005_407_345, 1.404_613_5, 0.214_847_56, -5.799_101, 14.179_933, -13.745_145, 4.645_852_6],
+                &[0.334_099_8, 1.384_590_2, 0.095_095_16, -19.332_441, 56.690_55, -65.353_03, 26.312_435],
+            ],
+            Self::Magma => [
+                &[-0.002_136_485, 0.251_660_54, 8.353_717, -27.668_733, 52.176_14, -50.768_524, 18.655_705],
+                &[-0.000_749_655, 0.677_523_2, -3.577_719_5, 14.264_731, -27.943_606, 29.046_583, -11.489_774],
+                &[-0.005_386_128, 2.494_026_6, 0.314_467_9, -13.649_213, 12.944_169, 4.234_153, -5.601_961_5],
+            ],
+            Self::Turbo => [
+                &[0.135_721_38, 4.615_392_6, -42.660_324, 132.131_08, -152.942_4, 59.286_38],
+                &[0.091_402_61, 2.194_188_4, 4.842_966_6, -14.185_033, 4.277_298_6, 2.829_566],
+                &[0.106_673_3, 12.641_946, -60.582_05, 110.362_77, -89.903_11, 27.348_25],
+            ],
+        }
+    }
+
+    /// sRGB-encoded color at `t` in `[0, 1]`.
+    fn color(self, t: f32) -> [f32; 3] {
+        self.coefficients().map(|c| c.iter().rev().fold(0.0, |acc, k| acc * t + k).clamp(0.0, 1.0))
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "false_color_plugin"
+version = "0.1.0"
+description = "Shows the luminance or a channel through the viridis, magma or turbo colormap"
+
+[defaults]
+colormap = "viridis"
+source = "luminance"
+min = 0.0
+max = 1.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Colors both formats by luminance; working per pixel, false color is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    if !params.min.is_finite() || !params.max.is_finite() || params.min == params.max {
+        return Err(PluginError::Invalid("min and max must be finite and differ"));
+    }
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => false_color(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => false_color(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Replaces each color by the colormap's color for its source value, measured on sRGB-encoded
+/// values so the result does not depend on the working space. Alpha is kept.
+fn false_color<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let encoded = rgb.map(plugin_sdk::to_encoded::<T>);
+        let value = match params.source {
+            Source::Luminance => (0..3).map(|c| LUMA[c] * encoded[c]).sum(),
+            Source::Red => encoded[0],
+            Source::Green => encoded[1],
+            Source::Blue => encoded[2],
+        };
+        let t = ((value - params.min) / (params.max - params.min)).clamp(0.0, 1.0);
+        params.colormap.color(t).map(plugin_sdk::from_encoded::<T>)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    fn close(a: &[u8], b: &[u8]) -> bool {
+        a.iter().zip(b).all(|(&x, &y)| x.abs_diff(y) <= 6)
+    }
+
+    #[test]
+    fn test_colormap_ends_match_reference() {
+        let mut img = [[0u8, 0, 0, 255], [255, 255, 255, 128]].concat();
+        run(&mut img, 2, 1, "{}").unwrap();
+        assert!(close(&img, &[68, 1, 84, 255, 253, 231, 37, 128]), "{img:?}");
+
+        let mut img = [[0u8, 0, 0, 255], [255, 255, 255, 255]].concat();
+        run(&mut img, 2, 1, r#"{"colormap": "magma"}"#).unwrap();
+        assert!(close(&img, &[0, 0, 4, 255, 252, 253, 191, 255]), "{img:?}");
+
+        // Turbo runs from blue through green to red.
+        let mut img = [[26u8, 26, 26, 255], [128, 128, 128, 255], [230, 230, 230, 255]].concat();
+        run(&mut img, 3, 1, r#"{"colormap": "turbo"}"#).unwrap();
+        let dominant: Vec<usize> = img.chunks(4).map(|p| (0..3).max_by_key(|&c| p[c]).unwrap()).collect();
+        assert_eq!(dominant, [2, 1, 0]);
+    }
+
+    #[test]
+    fn test_source_and_range() {
+        // Only red is measured, and 0.5 of it is already the top of the range.
+        let mut img = [128u8, 255, 255, 255];
+        run(&mut img, 1, 1, r#"{"source": "red", "max": 0.5}"#).unwrap();
+        assert!(close(&img, &[253, 231, 37, 255]), "{img:?}");
+
+        // A reversed range reverses the colormap.
+        let mut img = [0u8, 0, 0, 255];
+        run(&mut img, 1, 1, r#"{"min": 1.0, "max": 0.0}"#).unwrap();
+        assert!(close(&img, &[253, 231, 37, 255]), "{img:?}");
+
+        assert!(run(&mut img, 1, 1, r#"{"min": 0.5, "max": 0.5}"#).is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, ImageRef, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Power the sharpness is raised to for `weighted` blending, so the sharpest image dominates
+/// without hard seams between the images.
+const WEIGHT_POWER: f32 = 4.0;
+
+/// Alignment starts on the smallest halving of the images whose shorter side is at least this.
+const MIN_ALIGN_SIZE: usize = 16;
+
+/// How each pixel is taken from the stack.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// Average of all images, weighted steeply towards the sharpest.
+    #[default]
+    Weighted,
+    /// Only the sharpest image.
+    Pick,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Whether the inputs are shifted onto the main image first, to undo camera movement between
+    /// the shots.
+    #[serde(default = "default_align")]
+    align: bool,
+    /// Largest shift in pixels the alignment looks for.
+    #[serde(default = "default_max_shift")]
+    max_shift: u32,
+    /// Radius in pixels over which the sharpness is averaged; larger values avoid noise deciding
+    /// between the images but blur the transitions.
+    #[serde(default = "default_radius")]
+    radius: u32,
+    #[serde(default)]
+    method: Method,
+}
+
+fn default_align() -> bool {
+    true
+}
+
+fn default_max_shift() -> u32 {
+    16
+}
+
+fn default_radius() -> u32 {
+    2
+}
+
+const MANIFEST: &CStr = cr#"name = "focus_stack_plugin"
+version = "0.1.0"
+description = "Combines the sharpest parts of the image and the additional inputs (--inputs), a focus stack"
+
+[defaults]
+align = true
+max_shift = 16
+radius = 2
+method = "weighted"
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Stacks the additional inputs onto the image, in both formats. It is not local: the alignment
+/// compares the whole images.
+///
+/// Fails unless the host passed at least one additional input, all of the image's size.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    // SAFETY: the host keeps the additional inputs valid for the duration of the call.
+    let inputs = unsafe { ctx.input_images() }.ok_or(PluginError::Format)?;
+    if inputs.is_empty() || inputs.iter().any(|i| (i.width, i.height) != (width, height)) {
+        return Err(PluginError::Invalid("needs additional inputs of the image's size"));
+    }
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => {
+            let others = ImageRef::all_samples(&inputs).ok_or(PluginError::Format)?;
+            focus_stack(w, h, buf, &others, params, premultiplied)
+        }
+        Pixels::Rgba32F(buf) => {
+            let others = ImageRef::all_samples(&inputs).ok_or(PluginError::Format)?;
+            focus_stack(w, h, buf, &others, params, premultiplied)
+        }
+    }
+    Ok(())
+}
+
+/// Aligns `others` to `buf` and replaces every pixel of `buf` by the sharpest of the stack, or a
+/// sharpness-weighted average.
+///
+/// Sharpness is the magnitude of the Laplacian of the luma, averaged over `radius`. All four
+/// channels are combined with the same weights, so premultiplied data stays consistent.
+fn focus_stack<T: Sample>(
+    width: usize,
+    height: usize,
+    buf: &mut [T],
+    others: &[&[T]],
+    params: &Params,
+    premultiplied: bool,
+) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let reference = Gray::of(width, height, buf, premultiplied);
+    let mut stack: Vec<Vec<T>> = vec![buf.to_vec()];
+    for &other in others {
+        let (dx, dy) = if params.align {
+            estimate_shift(&reference, &Gray::of(width, height, other, premultiplied), params.max_shift as i64)
+        } else {
+            (0, 0)
+        };
+        stack.push(shifted(width, height, other, dx, dy));
+    }
+
+    let sharpness: Vec<Vec<f32>> = stack
+        .iter()
+        .map(|image| {
+            let gray = Gray::of(width, height, image, premultiplied);
+            let laplacian: Vec<f32> = (0..width * height)
+                .map(|i| {
+                    let (x, y) = ((i % width) as i64, (i / width) as i64);
+                    let around = gray.at(x - 1, y) + gray.at(x + 1, y) + gray.at(x, y - 1) + gray.at(x, y + 1);
+                    (around - 4.0 * gray.at(x, y)).abs()
+                })
+                .collect();
+            box_blur(&laplacian, width, height, params.radius as usize)
+        })
+        .collect();
+
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let weights: Vec<f32> = match params.method {
+            Method::Weighted => sharpness.iter().map(|s| (s[i] + 1e-6).powf(WEIGHT_POWER)).collect(),
+            Method::Pick => {
+                let best = (0..stack.len()).max_by(|&a, &b| sharpness[a][i].total_cmp(&sharpness[b][i]));
+                (0..stack.len()).map(|k| if Some(k) == best { 1.0 } else { 0.0 }).collect()
+            }
+        };
+        let total: f32 = weights.iter().sum();
+        for (c, v) in px.iter_mut().enumerate() {
+            let sum: f32 = stack.iter().zip(&weights).map(|(image, w)| w * image[i * 4 + c].to_f32()).sum();
+            *v = T::from_f32(sum / total);
+        }
+    }
+}
+
+/// Luma of an image, sRGB-encoded and straight.
+#[derive(Debug, Clone)]
+struct Gray {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Gray {
+    fn of<T: Sample>(width: usize, height: usize, data: &[T], premultiplied: bool) -> Self {
+        let data = data
+            .chunks_exact(4)
+            .map(|px| {
+                let alpha = px[3].to_f32() / T::MAX;
+                let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+                (0..3).map(|c| LUMA[c] * plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)).sum()
+            })
+            .collect();
+        Self { width, height, data }
+    }
+
+    /// Returns the value at `(x, y)`, clamped to the edges.
+    fn at(&self, x: i64, y: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.data[y * self.width + x]
+    }
+
+    /// Averages 2x2 blocks, dropping an odd last row or column.
+    fn half(&self) -> Self {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let data = (0..width * height)
+            .map(|i| {
+                let (x, y) = (2 * (i % width), 2 * (i / width));
+                let at = |x: usize, y: usize| self.data[y * self.width + x];
+                (at(x, y) + at(x + 1, y) + at(x, y + 1) + at(x + 1, y + 1)) / 4.0
+            })
+            .collect();
+        Self { width, height, data }
+    }
+
+    /// Mean absolute difference to `other` read at an offset of `(dx, dy)`, over the pixels both
+    /// cover; infinite if that is less than a quarter of the image.
+    fn difference(&self, other: &Self, dx: i64, dy: i64) -> f32 {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let (xs, ys) = ((-dx).max(0)..(w - dx).min(w), (-dy).max(0)..(h - dy).min(h));
+        let count = xs.clone().count() * ys.clone().count();
+        if count * 4 < self.data.len() {
+            return f32::INFINITY;
+        }
+        let sum: f32 = ys
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| (self.data[(y * w + x) as usize] - other.data[((y + dy) * w + x + dx) as usize]).abs())
+            .sum();
+        sum / count as f32
+    }
+}
+
+/// Finds the offset at which `image` best matches `reference`, each component at most
+/// `max_shift`: an exhaustive search on a reduced copy, refined level by level.
+fn estimate_shift(reference: &Gray, image: &Gray, max_shift: i64) -> (i64, i64) {
+    let mut levels = vec![(reference.clone(), image.clone())];
+    while let Some((r, m)) = levels.last()
+        && r.width.min(r.height) >= 2 * MIN_ALIGN_SIZE
+        && (max_shift >> (levels.len() - 1)) > 1
+    {
+        let next = (r.half(), m.half());
+        levels.push(next);
+    }
+
+    let best = |level: &(Gray, Gray), candidates: &mut dyn Iterator<Item = (i64, i64)>| {
+        candidates
+            .filter(|(dx, dy)| dx.abs() <= max_shift && dy.abs() <= max_shift)
+            .map(|(dx, dy)| (level.0.difference(&level.1, dx, dy), dx, dy))
+            .min_by(|a, b| a.0.total_cmp(&b.0).then((a.1.abs() + a.2.abs()).cmp(&(b.1.abs() + b.2.abs()))))
+            .map_or((0, 0), |(_, dx, dy)| (dx, dy))
+    };
+    let coarsest = levels.len() - 1;
+    let range = (max_shift >> coarsest) + 1;
+    let mut candidates = (-range..=range).flat_map(|dy| (-range..=range).map(move |dx| (dx, dy)));
+    let mut shift = best(&levels[coarsest], &mut candidates);
+    for level in levels[..coarsest].iter().rev() {
+        let (cx, cy) = (shift.0 * 2, shift.1 * 2);
+        shift = best(level, &mut (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (cx + dx, cy + dy))));
+    }
+    shift
+}
+
+/// Returns `data` read at an offset of `(dx, dy)`, repeating the edges where that leaves the image.
+fn shifted<T: Sample>(width: usize, height: usize, data: &[T], dx: i64, dy: i64) -> Vec<T> {
+    if (dx, dy) == (0, 0) {
+        return data.to_vec();
+    }
+    (0..width * height)
+        .flat_map(|i| {
+            let x = ((i % width) as i64 + dx).clamp(0, width as i64 - 1) as usize;
+            let y = ((i / width) as i64 + dy).clamp(0, height as i64 - 1) as usize;
+            data[(y * width + x) * 4..][..4].iter().copied()
+        })
+        .collect()
+}
+
+/// Averages over the `2 * radius + 1` square window, clamped at the image edges.
+fn box_blur(src: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    if radius == 0 {
+        return src.to_vec();
+    }
+    let pass = |src: &[f32], len: usize, lines: usize, index: &dyn Fn(usize, usize) -> usize| {
+        let mut out = vec![0.0; src.len()];
+        for line in 0..lines {
+            for i in 0..len {
+                let sum: f32 = (0..=2 * radius)
+                    .map(|k| src[index(line, (i + k).saturating_sub(radius).min(len - 1))])
+                    .sum();
+                out[index(line, i)] = sum / (2 * radius + 1) as f32;
+            }
+        }
+        out
+    };
+    let rows = pass(src, width, height, &|line, i| line * width + i);
+    pass(&rows, height, width, &|line, i| i * width + line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::InputImage;
+    use plugin_sdk::testing;
+
+    const SIZE: usize = 48;
+
+    fn run(buf: &mut [u8], others: &[Vec<u8>], params: &str) -> Result<(), PluginError> {
+        let n = SIZE as u32;
+        let inputs: Vec<InputImage> =
+            others.iter().map(|o| InputImage { data: o.as_ptr(), width: n, height: n }).collect();
+        let ctx = CallContext { inputs: inputs.as_ptr(), input_count: inputs.len() as u32, ..CallContext::new(0) };
+        testing::run_with(process, &ctx, n, n, buf, params)
+    }
+
+    /// A detailed scene, read at an offset of `shift`, blurred where `blurred` holds.
+    fn shot(shift: (i64, i64), blurred: impl Fn(usize) -> bool) -> Vec<u8> {
+        let hash = |x: i64, y: i64, n: i64| {
+            (x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663)).rem_euclid(n) as f32
+        };
+        // Value noise over 8-pixel cells plus per-pixel detail.
+        let scene = |x: i64, y: i64| {
+            let (cx, cy) = (x.div_euclid(8), y.div_euclid(8));
+            let (tx, ty) = (x.rem_euclid(8) as f32 / 8.0, y.rem_euclid(8) as f32 / 8.0);
+            let top = hash(cx, cy, 150) * (1.0 - tx) + hash(cx + 1, cy, 150) * tx;
+            let bottom = hash(cx, cy + 1, 150) * (1.0 - tx) + hash(cx + 1, cy + 1, 150) * tx;
+            top * (1.0 - ty) + bottom * ty + hash(x, y, 61)
+        };
+        (0..SIZE * SIZE)
+            .flat_map(|i| {
+                let (x, y) = ((i % SIZE) as i64 + shift.0, (i / SIZE) as i64 + shift.1);
+                let v = if blurred(i % SIZE) {
+                    let sum: f32 = (-2..=2).flat_map(|dy| (-2..=2).map(move |dx| scene(x + dx, y + dy))).sum();
+                    sum / 25.0
+                } else {
+                    scene(x, y)
+                };
+                [v as u8, v as u8, v as u8, 255]
+            })
+            .collect()
+    }
+
+    fn error(img: &[u8], truth: &[u8], columns: std::ops::Range<usize>) -> u32 {
+        (4..SIZE - 4)
+            .flat_map(|y| columns.clone().map(move |x| (y * SIZE + x) * 4))
+            .map(|i| img[i].abs_diff(truth[i]) as u32)
+            .sum()
+    }
+
+    #[test]
+    fn test_stack_takes_sharp_halves() {
+        let truth = shot((0, 0), |_| false);
+        let near = shot((0, 0), |x| x >= SIZE / 2);
+        let far = shot((0, 0), |x| x < SIZE / 2);
+        for method in ["weighted", "pick"] {
+            let mut img = near.clone();
+            run(&mut img, std::slice::from_ref(&far), &format!(r#"{{"method": "{method}"}}"#)).unwrap();
+            let (left, right) = (4..SIZE / 2 - 4, SIZE / 2 + 4..SIZE - 4);
+            assert!(error(&img, &truth, left.clone()) * 4 < error(&far, &truth, left), "{method}");
+            assert!(error(&img, &truth, right.clone()) * 4 < error(&near, &truth, right), "{method}");
+        }
+
+        // Without inputs there is nothing to stack.
+        let mut img = near.clone();
+        assert!(run(&mut img, &[], "{}").is_err());
+    }
+
+    #[test]
+    fn test_alignment_undoes_camera_shift() {
+        let reference = shot((0, 0), |_| false);
+        let moved = shot((3, -2), |_| false);
+        let (r, m) = (Gray::of(SIZE, SIZE, &reference, false), Gray::of(SIZE, SIZE, &moved, false));
+        assert_eq!(estimate_shift(&r, &m, 16), (-3, 2));
+
+        let truth = shot((0, 0), |_| false);
+        let near = shot((0, 0), |x| x >= SIZE / 2);
+        let far = shot((3, -2), |x| x < SIZE / 2);
+        let mut aligned = near.clone();
+        run(&mut aligned, std::slice::from_ref(&far), "{}").unwrap();
+        let mut unaligned = near.clone();
+        run(&mut unaligned, std::slice::from_ref(&far), r#"{"align": false}"#).unwrap();
+        let right = SIZE / 2 + 4..SIZE - 4;
+        assert!(error(&aligned, &truth, right.clone()) * 4 < error(&unaligned, &truth, right));
+    }
+}
@@ -0,0 +1,331 @@
+use std::ffi::CStr;
+use plugin_sdk::{Allocator, CallContext, Color, ImageRef, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Width of the border around the image in pixels; 0 draws none.
+    #[serde(default = "default_border")]
+    border: u32,
+    #[serde(default = "default_border_color")]
+    border_color: Color,
+    /// Radius in pixels of the rounded outer corners of the border, or of the image without
+    /// one; 0 keeps square corners.
+    #[serde(default)]
+    corner_radius: f32,
+    /// Draws a drop shadow behind the framed image.
+    #[serde(default = "default_shadow")]
+    shadow: bool,
+    /// Offset of the shadow in pixels, right and down.
+    #[serde(default = "default_shadow_offset")]
+    shadow_offset: [i32; 2],
+    /// Standard deviation of the shadow's blur in pixels.
+    #[serde(default = "default_shadow_blur")]
+    shadow_blur: f32,
+    #[serde(default = "default_shadow_color")]
+    shadow_color: Color,
+    /// Fill for the added canvas outside the border and shadow.
+    #[serde(default = "default_background")]
+    background: Color,
+}
+
+fn default_border() -> u32 {
+    10
+}
+
+fn default_border_color() -> Color {
+    Color::WHITE
+}
+
+fn default_shadow() -> bool {
+    true
+}
+
+fn default_shadow_offset() -> [i32; 2] {
+    [6, 6]
+}
+
+fn default_shadow_blur() -> f32 {
+    6.0
+}
+
+fn default_shadow_color() -> Color {
+    Color([0.0, 0.0, 0.0, 0.5])
+}
+
+fn default_background() -> Color {
+    Color::TRANSPARENT
+}
+
+const MANIFEST: &CStr = cr##"name = "frame_plugin"
+version = "0.1.0"
+description = "Expands the canvas with a solid or rounded border and a drop shadow"
+
+[defaults]
+border = 10
+border_color = "#ffffff"
+corner_radius = 0.0
+shadow = true
+shadow_offset = [6, 6]
+shadow_blur = 6.0
+shadow_color = "#00000080"
+background = "#00000000"
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process_v2: process,
+}
+
+/// Frames both formats.
+fn process(
+    image: ImageRef<'_>,
+    output: &mut Allocator<'_>,
+    params: &Params,
+    ctx: &CallContext,
+) -> Result<(), PluginError> {
+    let ImageRef { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.shadow_blur.is_nan() || params.shadow_blur < 0.0 || params.corner_radius.is_nan() {
+        return Err(PluginError::Invalid("shadow_blur and corner_radius must be numbers, shadow_blur at least 0"));
+    }
+
+    match pixels {
+        PixelsRef::Rgba8(src) => frame(src, width, height, params, premultiplied, output),
+        PixelsRef::Rgba32F(src) => frame(src, width, height, params, premultiplied, output),
+    }
+}
+
+/// Where the framed image sits on the expanded canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    width: u32,
+    height: u32,
+    /// Top-left corner of the image on the canvas.
+    left: u32,
+    top: u32,
+}
+
+impl Layout {
+    /// Adds the border on every side, and room for the shadow where it reaches past the border.
+    fn new(width: u32, height: u32, params: &Params) -> Option<Self> {
+        let (reach, [dx, dy]) = if params.shadow {
+            ((3.0 * params.shadow_blur).ceil() as i64, params.shadow_offset.map(i64::from))
+        } else {
+            (0, [0, 0])
+        };
+        let border = params.border as i64;
+        let pad = |offset: i64| (border + (reach - offset).max(0), border + (reach + offset).max(0));
+        let ((left, right), (top, bottom)) = (pad(dx), pad(dy));
+        Some(Self {
+            width: u32::try_from(width as i64 + left + right).ok()?,
+            height: u32::try_from(height as i64 + top + bottom).ok()?,
+            left: u32::try_from(left).ok()?,
+            top: u32::try_from(top).ok()?,
+        })
+    }
+}
+
+/// A color as premultiplied values in the scale of `T`.
+fn premultiplied_color<T: Sample>(color: Color) -> [f32; 4] {
+    let [r, g, b, a] = color.normalized::<T>();
+    [r * a * T::MAX, g * a * T::MAX, b * a * T::MAX, a * T::MAX]
+}
+
+/// Draws the image with its border, clipped to the rounded corners, over its shadow and the
+/// background on a canvas allocated from `output`.
+///
+/// Fails if the canvas would be too large or the host refused the buffer. Without a
+/// border, rounding or shadow nothing is allocated and the image stays unchanged.
+fn frame<T: Sample>(
+    src: &[T],
+    width: u32,
+    height: u32,
+    params: &Params,
+    premultiplied: bool,
+    output: &mut Allocator<'_>,
+) -> Result<(), PluginError> {
+    if params.border == 0 && params.corner_radius <= 0.0 && !params.shadow {
+        return Ok(());
+    }
+    let layout = Layout::new(width, height, params).ok_or(PluginError::Invalid("the framed image is too large"))?;
+    let (cw, ch) = (layout.width as usize, layout.height as usize);
+    let (w, h, border) = (width as usize, height as usize, params.border as usize);
+    let (fx, fy) = (layout.left as usize - border, layout.top as usize - border);
+    let (fw, fh) = (w + 2 * border, h + 2 * border);
+    let border_color = premultiplied_color::<T>(params.border_color);
+
+    // The framed image in premultiplied values, cut to the rounded rectangle.
+    let mut content = vec![[0.0f32; 4]; cw * ch];
+    for y in 0..fh {
+        for x in 0..fw {
+            let coverage = rounded_coverage(x as f32 + 0.5, y as f32 + 0.5, fw as f32, fh as f32, params.corner_radius);
+            if coverage <= 0.0 {
+                continue;
+            }
+            let inside = (border..border + w).contains(&x) && (border..border + h).contains(&y);
+            let px = if inside {
+                let i = ((y - border) * w + x - border) * 4;
+                let px: [f32; 4] = std::array::from_fn(|c| src[i + c].to_f32());
+                let alpha = if premultiplied { 1.0 } else { px[3] / T::MAX };
+                [px[0] * alpha, px[1] * alpha, px[2] * alpha, px[3]]
+            } else {
+                border_color
+            };
+            content[(fy + y) * cw + fx + x] = px.map(|v| v * coverage);
+        }
+    }
+
+    let shadow = if params.shadow {
+        let alpha: Vec<f32> = content.iter().map(|px| px[3] / T::MAX).collect();
+        let blurred = gaussian(&alpha, cw, ch, params.shadow_blur);
+        let [dx, dy] = params.shadow_offset.map(|d| d as i64);
+        let color = premultiplied_color::<T>(params.shadow_color);
+        (0..cw * ch)
+            .map(|i| {
+                let (x, y) = ((i % cw) as i64 - dx, (i / cw) as i64 - dy);
+                let inside = (0..cw as i64).contains(&x) && (0..ch as i64).contains(&y);
+                let mask = if inside { blurred[y as usize * cw + x as usize] } else { 0.0 };
+                color.map(|v| v * mask)
+            })
+            .collect()
+    } else {
+        vec![[0.0; 4]; cw * ch]
+    };
+
+    let dst = output.alloc::<T>(layout.width, layout.height)?;
+    let background = premultiplied_color::<T>(params.background);
+    let over = |top: [f32; 4], bottom: [f32; 4]| -> [f32; 4] {
+        let rest = 1.0 - top[3] / T::MAX;
+        std::array::from_fn(|c| top[c] + bottom[c] * rest)
+    };
+    for ((px, content), shadow) in dst.chunks_exact_mut(4).zip(&content).zip(&shadow) {
+        let value = over(*content, over(*shadow, background));
+        let alpha = value[3] / T::MAX;
+        for c in 0..3 {
+            let v = if premultiplied {
+                value[c]
+            } else if alpha > 0.0 {
+                value[c] / alpha
+            } else {
+                0.0
+            };
+            px[c] = T::from_f32(v);
+        }
+        px[3] = T::from_f32(value[3]);
+    }
+    Ok(())
+}
+
+/// How much of the pixel at `(x, y)` lies inside a `width`x`height` rectangle at the origin
+/// with corners rounded by `radius`, antialiased over one pixel.
+fn rounded_coverage(x: f32, y: f32, width: f32, height: f32, radius: f32) -> f32 {
+    let radius = radius.clamp(0.0, width.min(height) / 2.0);
+    let qx = (x - width / 2.0).abs() - (width / 2.0 - radius);
+    let qy = (y - height / 2.0).abs() - (height / 2.0 - radius);
+    let distance = qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) - radius;
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+/// Separable Gaussian blur of a single channel with standard deviation `sigma`; everything
+/// beyond the edges counts as 0.
+fn gaussian(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    if sigma <= 0.0 {
+        return src.to_vec();
+    }
+    let r = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let norm: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|k| k / norm).collect();
+    let tap = |len: usize, i: usize, k: usize| {
+        let j = i as i64 + k as i64 - r;
+        (0..len as i64).contains(&j).then_some(j as usize)
+    };
+
+    let mut rows = vec![0.0f32; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            rows[y * width + x] = kernel
+                .iter()
+                .enumerate()
+                .filter_map(|(k, w)| tap(width, x, k).map(|sx| src[y * width + sx] * w))
+                .sum();
+        }
+    }
+    let mut out = vec![0.0f32; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            out[y * width + x] = kernel
+                .iter()
+                .enumerate()
+                .filter_map(|(k, w)| tap(height, y, k).map(|sy| rows[sy * width + x] * w))
+                .sum();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing::{self, Output};
+
+    /// Frames an RGBA8 image and returns the output, if one was allocated.
+    fn run(src: &[u8], width: u32, height: u32, params: &str) -> Result<Option<Output<u8>>, PluginError> {
+        testing::run_v2(process, width, height, src, params)
+    }
+
+    fn pixel(out: &Output<u8>, x: u32, y: u32) -> [u8; 4] {
+        let i = (y * out.width + x) as usize * 4;
+        out.data[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_solid_border() {
+        let src = [10u8, 20, 30, 255].repeat(4 * 3);
+        let params = r##"{"border": 2, "border_color": "#ff0000", "shadow": false}"##;
+        let out = run(&src, 4, 3, params).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (8, 7));
+        assert_eq!(pixel(&out, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(&out, 7, 6), [255, 0, 0, 255]);
+        assert_eq!(pixel(&out, 2, 2), [10, 20, 30, 255]);
+        assert_eq!(pixel(&out, 5, 4), [10, 20, 30, 255]);
+
+        // Nothing to draw leaves the image unchanged.
+        assert_eq!(run(&src, 4, 3, r#"{"border": 0, "shadow": false}"#), Ok(None));
+    }
+
+    #[test]
+    fn test_rounded_corners() {
+        let src = [200u8, 200, 200, 255].repeat(20 * 20);
+        let out = run(&src, 20, 20, r#"{"border": 0, "corner_radius": 6.0, "shadow": false}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (20, 20));
+        assert_eq!(pixel(&out, 0, 0)[3], 0);
+        assert_eq!(pixel(&out, 19, 19)[3], 0);
+        assert_eq!(pixel(&out, 10, 0), [200, 200, 200, 255]);
+        // The edge of the curve is antialiased.
+        assert!(out.data.chunks(4).any(|p| p[3] > 0 && p[3] < 255));
+    }
+
+    #[test]
+    fn test_drop_shadow() {
+        let src = [255u8; 4].repeat(10 * 10);
+        let params = r##"{"border": 0, "shadow_offset": [4, 2], "shadow_blur": 1.0, "shadow_color": "#000000"}"##;
+        let out = run(&src, 10, 10, params).unwrap().unwrap();
+        // The blur reaches three pixels, so the canvas grows by that beyond the offset shadow:
+        // one row at the top, seven columns right and five rows below.
+        assert_eq!((out.width, out.height), (17, 16));
+        assert_eq!(pixel(&out, 0, 1), [255, 255, 255, 255]);
+        // Right of the image the shadow is opaque black where the blur does not reach its edge,
+        // fades towards the edge, and leaves the corners transparent.
+        assert_eq!(pixel(&out, 10, 8), [0, 0, 0, 255]);
+        let fade = pixel(&out, 13, 8)[3];
+        assert!(fade > 64 && fade < 192, "{fade}");
+        assert_eq!(pixel(&out, 16, 0)[3], 0);
+        assert_eq!(pixel(&out, 0, 15)[3], 0);
+
+        assert!(matches!(run(&src, 10, 10, r#"{"shadow_blur": -1.0}"#), Err(PluginError::Invalid(_))));
+    }
+}
@@ -0,0 +1,170 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Color, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Ready-made gradients that replace `colors` and `positions`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Preset {
+    /// Brown-toned monochrome, like an aged print.
+    Sepia,
+}
+
+impl Preset {
+    fn stops(self) -> Vec<(f32, [f32; 3])> {
+        match self {
+            Self::Sepia => vec![(0.0, [0.0, 0.0, 0.0]), (0.45, [0.60, 0.42, 0.23]), (1.0, [1.0, 0.98, 0.94])],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Gradient colors from shadows to highlights; their alpha is ignored.
+    #[serde(default = "default_colors")]
+    colors: Vec<Color>,
+    /// Luma of each color, rising from 0 to 1; evenly spaced if empty.
+    #[serde(default)]
+    positions: Vec<f32>,
+    #[serde(default)]
+    preset: Option<Preset>,
+    /// How far to move towards the mapped colors: 0 leaves the image unchanged, 1 maps fully.
+    #[serde(default = "default_amount")]
+    amount: f32,
+}
+
+fn default_colors() -> Vec<Color> {
+    vec![Color::BLACK, Color::WHITE]
+}
+
+fn default_amount() -> f32 {
+    1.0
+}
+
+impl Params {
+    /// Returns the gradient stops as `(position, color)`, sorted by position.
+    fn stops(&self) -> Result<Vec<(f32, [f32; 3])>, String> {
+        if let Some(preset) = self.preset {
+            return Ok(preset.stops());
+        }
+        if self.colors.is_empty() {
+            return Err("the gradient needs at least one color".to_string());
+        }
+        let positions = if self.positions.is_empty() {
+            let last = (self.colors.len() - 1).max(1) as f32;
+            (0..self.colors.len()).map(|i| i as f32 / last).collect()
+        } else if self.positions.len() == self.colors.len() {
+            self.positions.clone()
+        } else {
+            return Err(format!("{} positions for {} colors", self.positions.len(), self.colors.len()));
+        };
+        if positions.windows(2).any(|w| w[1] < w[0]) {
+            return Err("positions must not decrease".to_string());
+        }
+        Ok(positions.into_iter().zip(self.colors.iter().map(|c| [c.0[0], c.0[1], c.0[2]])).collect())
+    }
+}
+
+/// Returns the gradient color at `t`, interpolating between the stops around it.
+fn sample(stops: &[(f32, [f32; 3])], t: f32) -> [f32; 3] {
+    let next = stops.partition_point(|&(p, _)| p <= t);
+    if next == 0 {
+        return stops[0].1;
+    }
+    if next == stops.len() {
+        return stops[next - 1].1;
+    }
+    let ((p0, c0), (p1, c1)) = (stops[next - 1], stops[next]);
+    let f = (t - p0) / (p1 - p0);
+    std::array::from_fn(|c| c0[c] + (c1[c] - c0[c]) * f)
+}
+
+const MANIFEST: &CStr = cr##"name = "gradient_map_plugin"
+version = "0.1.0"
+description = "Maps luma through a color gradient, for duotones and sepia"
+
+[defaults]
+colors = ["#000000", "#ffffff"]
+positions = []
+amount = 1.0
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Maps both formats; working per pixel, the mapping is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    let Ok(stops) = params.stops() else {
+        return Err(PluginError::Invalid("invalid gradient colors or positions"));
+    };
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => map(buf, &stops, params.amount, premultiplied),
+        Pixels::Rgba32F(buf) => map(buf, &stops, params.amount, premultiplied),
+    }
+    Ok(())
+}
+
+/// Replaces each color with the gradient color at its luma, both sRGB-encoded, so a gradient
+/// looks the same in either working space; alpha is kept.
+fn map<T: Sample>(buf: &mut [T], stops: &[(f32, [f32; 3])], amount: f32, premultiplied: bool) {
+    let amount = amount.clamp(0.0, 1.0);
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let encoded = rgb.map(plugin_sdk::to_encoded::<T>);
+        let luma = LUMA[0] * encoded[0] + LUMA[1] * encoded[1] + LUMA[2] * encoded[2];
+        let mapped = sample(stops, luma);
+        std::array::from_fn(|c| plugin_sdk::from_encoded::<T>(encoded[c] + (mapped[c] - encoded[c]) * amount))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_duotone() {
+        // Black maps to navy, white to orange, mid-gray halfway between them.
+        let params = r##"{"colors": ["#000080", "#ff8000"]}"##;
+        let mut buf = [0u8, 0, 0, 255, 255, 255, 255, 128, 128, 128, 128, 10];
+        run(&mut buf, params).unwrap();
+        assert_eq!(buf, [0, 0, 128, 255, 255, 128, 0, 128, 128, 64, 64, 10]);
+
+        let mut bad = [0u8; 4];
+        assert!(run(&mut bad, r##"{"colors": ["#000", "#fff"], "positions": [0.5]}"##).is_err());
+        assert!(run(&mut bad, r#"{"colors": ["navy"]}"#).is_err());
+    }
+
+    #[test]
+    fn test_positions_and_amount() {
+        let params = r##"{"colors": ["#000", "#f00", "#fff"], "positions": [0, 0.25, 1]}"##;
+        let mut buf = [64u8, 64, 64, 255];
+        run(&mut buf, params).unwrap();
+        assert_eq!(buf, [255, 0, 0, 255]);
+
+        let mut half = [200u8, 100, 0, 255];
+        run(&mut half, r#"{"amount": 0.0}"#).unwrap();
+        assert_eq!(half, [200, 100, 0, 255]);
+    }
+
+    #[test]
+    fn test_sepia_is_warm_and_keeps_extremes() {
+        let mut buf = [0u8, 0, 0, 255, 120, 120, 120, 255];
+        run(&mut buf, r#"{"preset": "sepia"}"#).unwrap();
+        assert_eq!(&buf[..4], &[0, 0, 0, 255]);
+        assert!(buf[4] > buf[5] && buf[5] > buf[6], "{buf:?}");
+    }
+}
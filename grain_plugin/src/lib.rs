@@ -0,0 +1,221 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Rng, Sample};
+use serde::Deserialize;
+
+/// The kind of noise the grain is made of.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    /// Independent Gaussian values, smoothly interpolated between points `size` pixels apart.
+    #[default]
+    Gaussian,
+    /// Perlin gradient noise with features about `size` pixels across; softer and clumpier,
+    /// like the grain of fast film.
+    Perlin,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    kind: Kind,
+    /// Standard deviation of the grain, in units of full scale of the sRGB-encoded values.
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+    /// Size of the grain in pixels; 1 gives a different value for every pixel.
+    #[serde(default = "default_size")]
+    size: f32,
+    /// Uses the same grain for all three channels; otherwise each channel gets its own,
+    /// giving colored grain.
+    #[serde(default = "default_monochrome")]
+    monochrome: bool,
+}
+
+fn default_intensity() -> f32 {
+    0.05
+}
+
+fn default_size() -> f32 {
+    1.0
+}
+
+fn default_monochrome() -> bool {
+    true
+}
+
+const MANIFEST: &CStr = cr#"name = "grain_plugin"
+version = "0.1.0"
+description = "Adds Gaussian or Perlin film grain, monochrome or colored, seeded by the host"
+
+[defaults]
+kind = "gaussian"
+intensity = 0.05
+size = 1.0
+monochrome = true
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Adds grain to both formats. It is not local: the grain is laid out from the image origin,
+/// which a tile does not know.
+///
+/// The grain is drawn from the context's seed, so a run with the same `--seed` reproduces it;
+/// without one the seed is 0.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, pixels, .. } = image;
+    let premultiplied = ctx.premultiplied();
+    let key = Rng::from_context(ctx).next_u64();
+
+    if params.size.is_nan() || params.size < 1.0 {
+        return Err(PluginError::Invalid("size must be at least 1"));
+    }
+
+    match pixels {
+        Pixels::Rgba8(buf) => add_grain(width as usize, buf, params, key, premultiplied),
+        Pixels::Rgba32F(buf) => add_grain(width as usize, buf, params, key, premultiplied),
+    }
+    Ok(())
+}
+
+/// Adds grain to the straight sRGB-encoded colors, so it is equally visible in shadows and
+/// highlights. Alpha is kept.
+fn add_grain<T: Sample>(width: usize, buf: &mut [T], params: &Params, key: u64, premultiplied: bool) {
+    if width == 0 {
+        return;
+    }
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let a = px[3].to_f32() / T::MAX;
+        if a <= 0.0 {
+            continue;
+        }
+        let (x, y) = ((i % width) as f32 / params.size, (i / width) as f32 / params.size);
+        let mono = params.monochrome.then(|| noise(params.kind, key, x, y, 0));
+        let scale = if premultiplied { T::MAX * a } else { T::MAX };
+        for (c, v) in px[..3].iter_mut().enumerate() {
+            let n = mono.unwrap_or_else(|| noise(params.kind, key, x, y, c as u64));
+            let encoded = plugin_sdk::to_encoded::<T>(v.to_f32() / scale) + params.intensity * n;
+            *v = T::from_f32(plugin_sdk::from_encoded::<T>(encoded.clamp(0.0, 1.0)) * scale);
+        }
+    }
+}
+
+/// Generator for the lattice point `(ix, iy)` of one channel, mixed from the run's key so
+/// that every point gets its own reproducible values.
+fn lattice(key: u64, ix: i64, iy: i64, channel: u64) -> Rng {
+    let mut rng = Rng::new(key ^ (ix as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    let mut rng = Rng::new(rng.next_u64() ^ (iy as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F));
+    Rng::new(rng.next_u64() ^ channel)
+}
+
+/// Standard normal value for a lattice point (Box-Muller).
+fn gaussian(mut rng: Rng) -> f32 {
+    let u = 1.0 - rng.next_f32();
+    let v = rng.next_f32();
+    (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos()
+}
+
+/// Noise with a standard deviation of about 1 at `(x, y)`, in lattice units.
+fn noise(kind: Kind, key: u64, x: f32, y: f32, channel: u64) -> f32 {
+    let (ix, iy) = (x.floor() as i64, y.floor() as i64);
+    let (fx, fy) = (x - x.floor(), y - y.floor());
+    let corner = |dx: i64, dy: i64| {
+        let rng = lattice(key, ix + dx, iy + dy, channel);
+        match kind {
+            Kind::Gaussian => gaussian(rng),
+            Kind::Perlin => {
+                let (sin, cos) = (std::f32::consts::TAU * { rng }.next_f32()).sin_cos();
+                cos * (fx - dx as f32) + sin * (fy - dy as f32)
+            }
+        }
+    };
+    let (sx, sy) = match kind {
+        Kind::Gaussian => (fx, fy),
+        Kind::Perlin => (fade(fx), fade(fy)),
+    };
+    let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * sx;
+    let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * sx;
+    let value = top + (bottom - top) * sy;
+    match kind {
+        // Interpolating independent values lowers their spread between lattice points; this
+        // restores it, so the grain is equally strong everywhere.
+        Kind::Gaussian => {
+            let spread = |t: f32| (1.0 - t).powi(2) + t * t;
+            value / (spread(fx) * spread(fy)).sqrt()
+        }
+        // Unit gradients give values with a standard deviation of about 0.22.
+        Kind::Perlin => value / 0.22,
+    }
+}
+
+/// Perlin's quintic fade curve, which keeps the noise smooth across lattice cells.
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, seed: u64, params: &str) -> Result<(), PluginError> {
+        testing::run_with(process, &CallContext::new(seed), width, height, buf, params)
+    }
+
+    fn stats(values: impl Iterator<Item = f32>) -> (f32, f32) {
+        let values: Vec<f32> = values.collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn test_noise_is_normalized() {
+        for kind in [Kind::Gaussian, Kind::Perlin] {
+            let values = (0..10_000).map(|i| noise(kind, 5, (i % 100) as f32 * 0.37, (i / 100) as f32 * 0.61, 0));
+            let (mean, std) = stats(values);
+            assert!(mean.abs() < 0.1, "{kind:?}: {mean}");
+            assert!((std - 1.0).abs() < 0.2, "{kind:?}: {std}");
+        }
+    }
+
+    #[test]
+    fn test_seeded_grain() {
+        let gray = [128u8, 128, 128, 255].repeat(64 * 64);
+        let mut a = gray.clone();
+        run(&mut a, 64, 64, 1, r#"{"intensity": 0.1}"#).unwrap();
+        let mut b = gray.clone();
+        run(&mut b, 64, 64, 1, r#"{"intensity": 0.1}"#).unwrap();
+        let mut c = gray.clone();
+        run(&mut c, 64, 64, 2, r#"{"intensity": 0.1}"#).unwrap();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+
+        // Monochrome grain keeps pixels gray; 0.1 of full scale is about 25 levels.
+        assert!(a.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+        let (mean, std) = stats(a.chunks(4).map(|p| p[0] as f32));
+        assert!((mean - 128.0).abs() < 2.0, "{mean}");
+        assert!((std - 25.5).abs() < 3.0, "{std}");
+    }
+
+    #[test]
+    fn test_color_and_size() {
+        let mut img = [128u8, 128, 128, 255].repeat(32 * 32);
+        run(&mut img, 32, 32, 3, r#"{"monochrome": false}"#).unwrap();
+        assert!(img.chunks(4).any(|p| p[0] != p[1]));
+
+        // Larger grain changes less from one pixel to the next.
+        let roughness = |size: f32| {
+            let mut img = [128u8, 128, 128, 255].repeat(64 * 64);
+            let params = format!(r#"{{"kind": "perlin", "intensity": 0.2, "size": {size}}}"#);
+            run(&mut img, 64, 64, 4, &params).unwrap();
+            img.chunks(4).zip(img.chunks(4).skip(1)).map(|(a, b)| a[0].abs_diff(b[0]) as u32).sum::<u32>()
+        };
+        assert!(roughness(8.0) * 2 < roughness(2.0));
+
+        let mut img = [0u8; 4];
+        assert!(run(&mut img, 1, 1, 0, r#"{"size": 0.5}"#).is_err());
+    }
+}
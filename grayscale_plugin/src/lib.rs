@@ -0,0 +1,132 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// How the gray value is computed from the color channels.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Weights {
+    /// Rec. 709 luminance weights, matching perceived brightness.
+    #[default]
+    Luminosity,
+    /// Equal weights for red, green and blue.
+    Average,
+    /// Only the red channel.
+    Red,
+    /// Only the green channel.
+    Green,
+    /// Only the blue channel.
+    Blue,
+}
+
+impl Weights {
+    fn coefficients(self) -> [f32; 3] {
+        match self {
+            Self::Luminosity => [0.2126, 0.7152, 0.0722],
+            Self::Average => [1.0 / 3.0; 3],
+            Self::Red => [1.0, 0.0, 0.0],
+            Self::Green => [0.0, 1.0, 0.0],
+            Self::Blue => [0.0, 0.0, 1.0],
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    weights: Weights,
+    /// How far to desaturate: 0 leaves the image unchanged, 1 makes it fully gray.
+    #[serde(default = "default_amount")]
+    amount: f32,
+}
+
+fn default_amount() -> f32 {
+    1.0
+}
+
+const MANIFEST: &CStr = cr#"name = "grayscale_plugin"
+version = "0.1.0"
+description = "Converts to grayscale with selectable channel weights, fully or partially"
+
+[defaults]
+weights = "luminosity"
+amount = 1.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Desaturates both formats; working per pixel, the conversion is local with no halo.
+fn process(image: Image<'_>, params: &Params, _ctx: &CallContext) -> Result<(), PluginError> {
+    match image.pixels {
+        Pixels::Rgba8(buf) => desaturate(buf, params),
+        Pixels::Rgba32F(buf) => desaturate(buf, params),
+    }
+    Ok(())
+}
+
+/// Moves every pixel's color towards its gray value by `amount`; alpha is kept.
+///
+/// The gray value is a weighted sum of the channels with weights adding up to one, so
+/// premultiplied data stays premultiplied.
+fn desaturate<T: Sample>(buf: &mut [T], params: &Params) {
+    let [wr, wg, wb] = params.weights.coefficients();
+    let amount = params.amount.clamp(0.0, 1.0);
+    for px in buf.chunks_exact_mut(4) {
+        let (r, g, b) = (px[0].to_f32(), px[1].to_f32(), px[2].to_f32());
+        let gray = wr * r + wg * g + wb * b;
+        px[0] = T::from_f32(r + (gray - r) * amount);
+        px[1] = T::from_f32(g + (gray - g) * amount);
+        px[2] = T::from_f32(b + (gray - b) * amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    #[test]
+    fn test_weights() {
+        let px = [200u8, 100, 40, 128];
+
+        let mut lum = px;
+        testing::run(process, 1, 1, &mut lum, r#"{"weights": "luminosity", "amount": 1.0}"#).unwrap();
+        assert_eq!(lum, [117, 117, 117, 128]);
+
+        let mut avg = px;
+        testing::run(process, 1, 1, &mut avg, r#"{"weights": "average"}"#).unwrap();
+        assert_eq!(avg, [113, 113, 113, 128]);
+
+        let mut green = px;
+        testing::run(process, 1, 1, &mut green, r#"{"weights": "green"}"#).unwrap();
+        assert_eq!(green, [100, 100, 100, 128]);
+
+        assert!(testing::run(process, 1, 1, &mut [0u8; 4], r#"{"weights": "purple"}"#).is_err());
+    }
+
+    #[test]
+    fn test_partial_amount() {
+        let mut none = [200u8, 100, 40, 255];
+        testing::run(process, 1, 1, &mut none, r#"{"amount": 0.0}"#).unwrap();
+        assert_eq!(none, [200, 100, 40, 255]);
+
+        let mut half = [200u8, 100, 40, 255];
+        testing::run(process, 1, 1, &mut half, r#"{"weights": "red", "amount": 0.5}"#).unwrap();
+        assert_eq!(half, [200, 150, 120, 255]);
+    }
+
+    #[test]
+    fn test_rgba32f() {
+        let mut img = [0.5f32, 0.25, 1.0, 0.75];
+        testing::run(process, 1, 1, &mut img, r#"{"weights": "average"}"#).unwrap();
+        for c in &img[..3] {
+            assert!((c - 1.75 / 3.0).abs() < 1e-6);
+        }
+        assert_eq!(img[3], 0.75);
+    }
+}
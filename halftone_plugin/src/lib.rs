@@ -0,0 +1,235 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Which inks are screened.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Black dots on white, sized by luma, on one screen at `angle`.
+    #[default]
+    Mono,
+    /// Cyan, magenta, yellow and black dots on white, each on its own screen at `angles`.
+    Cmyk,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// Distance between dot centers in pixels.
+    #[serde(default = "default_cell")]
+    cell: f32,
+    /// Screen angle in degrees for `mono`.
+    #[serde(default = "default_angle")]
+    angle: f32,
+    /// Screen angles in degrees for cyan, magenta, yellow and black. The defaults are the
+    /// traditional print angles, which keep the screens from forming visible moiré.
+    #[serde(default = "default_angles")]
+    angles: [f32; 4],
+}
+
+fn default_cell() -> f32 {
+    8.0
+}
+
+fn default_angle() -> f32 {
+    45.0
+}
+
+fn default_angles() -> [f32; 4] {
+    [15.0, 75.0, 0.0, 45.0]
+}
+
+const MANIFEST: &CStr = cr#"name = "halftone_plugin"
+version = "0.1.0"
+description = "Renders the image as print-style halftone dots, in black or on CMYK screens"
+
+[defaults]
+mode = "mono"
+cell = 8.0
+angle = 45.0
+angles = [15.0, 75.0, 0.0, 45.0]
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Halftones both formats. It is not local: the screens are anchored at the image origin, which
+/// a tile does not know.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, pixels, .. } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.cell.is_nan() || params.cell < 1.0 {
+        return Err(PluginError::Invalid("cell must be at least 1"));
+    }
+
+    match pixels {
+        Pixels::Rgba8(buf) => halftone(width as usize, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => halftone(width as usize, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// A rotated grid of dot centers.
+struct Screen {
+    cell: f32,
+    cos: f32,
+    sin: f32,
+}
+
+impl Screen {
+    fn new(cell: f32, degrees: f32) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self { cell, cos, sin }
+    }
+
+    /// Calls `f` with the image position of each dot center around `(x, y)` and the distance to
+    /// it; neighboring dots are included because large dots spill into adjacent cells.
+    fn dots(&self, x: f32, y: f32, mut f: impl FnMut(f32, f32, f32)) {
+        let u = (x * self.cos + y * self.sin) / self.cell;
+        let v = (y * self.cos - x * self.sin) / self.cell;
+        for dv in -1..=1 {
+            for du in -1..=1 {
+                let cu = (u.floor() + du as f32 + 0.5) * self.cell;
+                let cv = (v.floor() + dv as f32 + 0.5) * self.cell;
+                let (cx, cy) = (cu * self.cos - cv * self.sin, cu * self.sin + cv * self.cos);
+                f(cx, cy, (cx - x).hypot(cy - y));
+            }
+        }
+    }
+}
+
+/// Covers each screen with dots sized by the ink it needs at the dot center, so a solid ink
+/// fills its cell and no ink leaves the paper white. Dot edges are antialiased over one pixel.
+///
+/// Ink is taken from straight sRGB-encoded colors; alpha is kept.
+fn halftone<T: Sample>(width: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 {
+        return;
+    }
+    let height = buf.len() / 4 / width;
+    let colors: Vec<[f32; 3]> = buf
+        .chunks_exact(4)
+        .map(|px| {
+            let a = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied && a > 0.0 { T::MAX * a } else { T::MAX };
+            std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale).clamp(0.0, 1.0))
+        })
+        .collect();
+    let sample = |x: f32, y: f32| {
+        let x = (x.floor().max(0.0) as usize).min(width - 1);
+        let y = (y.floor().max(0.0) as usize).min(height - 1);
+        colors[y * width + x]
+    };
+
+    // Each screen's ink at a color: mono uses 1 - luma, CMYK removes the shared gray component
+    // into black.
+    let inks = |rgb: [f32; 3]| -> [f32; 4] {
+        match params.mode {
+            Mode::Mono => [1.0 - (0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]), 0.0, 0.0, 0.0],
+            Mode::Cmyk => {
+                let k = 1.0 - rgb[0].max(rgb[1]).max(rgb[2]);
+                let cmy = |v: f32| if k < 1.0 { (1.0 - v - k) / (1.0 - k) } else { 0.0 };
+                [cmy(rgb[0]), cmy(rgb[1]), cmy(rgb[2]), k]
+            }
+        }
+    };
+    let screens: Vec<Screen> = match params.mode {
+        Mode::Mono => vec![Screen::new(params.cell, params.angle)],
+        Mode::Cmyk => params.angles.iter().map(|&a| Screen::new(params.cell, a)).collect(),
+    };
+
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let (x, y) = ((i % width) as f32 + 0.5, (i / width) as f32 + 0.5);
+        let mut coverage = [0.0f32; 4];
+        for (s, screen) in screens.iter().enumerate() {
+            screen.dots(x, y, |cx, cy, distance| {
+                let ink = inks(sample(cx, cy))[s].clamp(0.0, 1.0);
+                let radius = dot_radius(ink, params.cell);
+                // Dots smaller than a pixel cover no more than their area.
+                let dot = (radius - distance + 0.5).clamp(0.0, 1.0).min(std::f32::consts::PI * radius * radius);
+                coverage[s] = coverage[s].max(dot);
+            });
+        }
+        let rgb = match params.mode {
+            Mode::Mono => [1.0 - coverage[0]; 3],
+            Mode::Cmyk => std::array::from_fn(|c| (1.0 - coverage[c]) * (1.0 - coverage[3])),
+        };
+        let a = px[3].to_f32() / T::MAX;
+        let scale = if premultiplied { T::MAX * a } else { T::MAX };
+        for c in 0..3 {
+            px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(rgb[c]) * scale);
+        }
+    }
+}
+
+/// Radius of the dot for `ink`: its area matches the ink until the dot touches the cell edges,
+/// and beyond that it grows to cover the cell corners, antialiasing included, at full ink.
+fn dot_radius(ink: f32, cell: f32) -> f32 {
+    let touching = std::f32::consts::FRAC_PI_4;
+    if ink <= touching {
+        cell * (ink / std::f32::consts::PI).sqrt()
+    } else {
+        let full = cell * std::f32::consts::FRAC_1_SQRT_2 + 0.5;
+        cell / 2.0 + (full - cell / 2.0) * (ink - touching) / (1.0 - touching)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    fn flat(rgb: [u8; 3], n: usize) -> Vec<u8> {
+        [rgb[0], rgb[1], rgb[2], 255].repeat(n)
+    }
+
+    #[test]
+    fn test_solid_inks() {
+        // White stays white and black fills every cell, on either screen set.
+        for mode in ["mono", "cmyk"] {
+            let params = format!(r#"{{"mode": "{mode}"}}"#);
+            let mut white = flat([255; 3], 256);
+            run(&mut white, 16, 16, &params).unwrap();
+            assert_eq!(white, flat([255; 3], 256), "{mode}");
+            let mut black = flat([0; 3], 256);
+            run(&mut black, 16, 16, &params).unwrap();
+            assert_eq!(black, flat([0; 3], 256), "{mode}");
+        }
+        let mut img = flat([0; 3], 1);
+        assert!(run(&mut img, 1, 1, r#"{"cell": 0.5}"#).is_err());
+    }
+
+    #[test]
+    fn test_mono_dots() {
+        // A 50% gray becomes black dots covering about half of the paper, with white paper
+        // between the dots of an unrotated screen.
+        let mut img = flat([128; 3], 32 * 32);
+        run(&mut img, 32, 32, r#"{"angle": 0.0, "cell": 8.0}"#).unwrap();
+        let inked = img.chunks(4).filter(|p| p[0] < 128).count() as f32 / 1024.0;
+        assert!((inked - 0.5).abs() < 0.1, "{inked}");
+        assert!(img.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2]));
+        assert_eq!(img[..4], [255, 255, 255, 255]);
+        assert_eq!(img[(4 * 32 + 4) * 4..][..4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_cmyk_separation() {
+        // Orange is solid yellow and half magenta: no cyan or black dots leave red full,
+        // yellow covers all the blue, and the magenta dots show in green only.
+        let mut img = flat([255, 128, 0], 256);
+        run(&mut img, 16, 16, r#"{"mode": "cmyk"}"#).unwrap();
+        assert!(img.chunks(4).all(|p| p[0] == 255 && p[2] == 0));
+        assert!(img.chunks(4).any(|p| p[1] == 255));
+        assert!(img.chunks(4).any(|p| p[1] == 0));
+    }
+}
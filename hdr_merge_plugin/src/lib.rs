@@ -0,0 +1,430 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, ImageRef, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Fewest and most exposures, counting the main image, that can be merged.
+const EXPOSURES: std::ops::RangeInclusive<usize> = 3..=7;
+
+/// Standard deviation of the well-exposedness curve around mid-gray.
+const EXPOSEDNESS_SIGMA: f32 = 0.2;
+
+/// Encoded values outside this range are taken as clipped when estimating exposures.
+const WELL_EXPOSED: std::ops::RangeInclusive<f32> = 0.05..=0.95;
+
+/// How the exposures are combined.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Exposure fusion (Mertens): blends the best-exposed parts of each image into an ordinary
+    /// image, without exposure times or tone mapping.
+    #[default]
+    Fusion,
+    /// Radiance merge (Debevec): a weighted average of each image's linear values divided by
+    /// its exposure, giving values beyond 1 for a tone mapper to compress.
+    Hdr,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// Exposure times in seconds, for the main image followed by the `--inputs` in order. Empty
+    /// to estimate the ratios from the images; only used by `hdr`.
+    #[serde(default)]
+    exposures: Vec<f32>,
+    /// Exponent of the local-contrast weight in fusion.
+    #[serde(default = "default_weight")]
+    contrast_weight: f32,
+    /// Exponent of the saturation weight in fusion.
+    #[serde(default = "default_weight")]
+    saturation_weight: f32,
+    /// Exponent of the well-exposedness weight in fusion.
+    #[serde(default = "default_weight")]
+    exposure_weight: f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+const MANIFEST: &CStr = cr#"name = "hdr_merge_plugin"
+version = "0.1.0"
+description = "Merges the image and the additional inputs (--inputs), bracketed exposures, by fusion or into HDR"
+
+[defaults]
+mode = "fusion"
+exposures = []
+contrast_weight = 1.0
+saturation_weight = 1.0
+exposure_weight = 1.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Merges the additional inputs with the image, in both formats. It is not local: fusion blends
+/// across a pyramid spanning the whole image.
+///
+/// Fails unless the host passed two to six additional inputs, all of the image's size.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    // SAFETY: the host keeps the additional inputs valid for the duration of the call.
+    let inputs = unsafe { ctx.input_images() }.ok_or(PluginError::Format)?;
+    if !EXPOSURES.contains(&(inputs.len() + 1)) || inputs.iter().any(|i| (i.width, i.height) != (width, height)) {
+        return Err(PluginError::Invalid("needs two to six additional inputs of the image's size"));
+    }
+
+    let weights = [params.contrast_weight, params.saturation_weight, params.exposure_weight];
+    let exposures_valid = params.exposures.iter().all(|&t| t.is_finite() && t > 0.0)
+        && (params.exposures.is_empty() || params.exposures.len() == inputs.len() + 1);
+    if !exposures_valid || weights.iter().any(|w| !w.is_finite() || *w < 0.0) {
+        return Err(PluginError::Invalid("exposures must be positive, one per image, and weights non-negative"));
+    }
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    let merged = match pixels {
+        Pixels::Rgba8(buf) => {
+            let others = ImageRef::all_samples(&inputs).ok_or(PluginError::Format)?;
+            merge(w, h, buf, &others, params, premultiplied)
+        }
+        Pixels::Rgba32F(buf) => {
+            let others = ImageRef::all_samples(&inputs).ok_or(PluginError::Format)?;
+            merge(w, h, buf, &others, params, premultiplied)
+        }
+    };
+    if merged { Ok(()) } else { Err(PluginError::Invalid("cannot estimate the exposures")) }
+}
+
+/// Merges `buf` and `others` into `buf`, keeping the alpha of `buf`.
+///
+/// Returns `false` if `hdr` has to estimate the exposures but an image shares no well-exposed
+/// pixels with the main one.
+fn merge<T: Sample>(
+    width: usize,
+    height: usize,
+    buf: &mut [T],
+    others: &[&[T]],
+    params: &Params,
+    premultiplied: bool,
+) -> bool {
+    if width == 0 || height == 0 {
+        return true;
+    }
+    let images: Vec<Vec<[f32; 3]>> = std::iter::once(&*buf)
+        .chain(others.iter().copied())
+        .map(|data| encoded(data, premultiplied))
+        .collect();
+
+    let (merged, linear) = match params.mode {
+        Mode::Fusion => (fuse(width, height, &images, params), false),
+        Mode::Hdr => {
+            let exposures = if params.exposures.is_empty() {
+                match estimate_exposures(&images) {
+                    Some(exposures) => exposures,
+                    None => return false,
+                }
+            } else {
+                params.exposures.clone()
+            };
+            (radiance(&images, &exposures), true)
+        }
+    };
+
+    for (px, color) in buf.chunks_exact_mut(4).zip(merged) {
+        let scale = if premultiplied { px[3].to_f32() } else { T::MAX };
+        for (c, v) in color.into_iter().enumerate() {
+            let v = if linear { plugin_sdk::from_linear::<T>(v) } else { plugin_sdk::from_encoded::<T>(v) };
+            px[c] = T::from_f32(v * scale);
+        }
+    }
+    true
+}
+
+/// Returns the straight, sRGB-encoded colors of `data`, normalized to `[0, 1]`.
+fn encoded<T: Sample>(data: &[T], premultiplied: bool) -> Vec<[f32; 3]> {
+    data.chunks_exact(4)
+        .map(|px| {
+            let alpha = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+            std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale).clamp(0.0, 1.0))
+        })
+        .collect()
+}
+
+/// Exposure fusion after Mertens, Kautz and Van Reeth: each image is weighted per pixel by its
+/// local contrast, saturation and closeness to mid-gray, and the images are blended band by band
+/// in Laplacian pyramids of the weights' Gaussian pyramids, so the seams between the parts
+/// taken from different exposures do not show.
+fn fuse(width: usize, height: usize, images: &[Vec<[f32; 3]>], params: &Params) -> Vec<[f32; 3]> {
+    let mut weights: Vec<Vec<f32>> = images.iter().map(|image| fusion_weights(width, height, image, params)).collect();
+    for i in 0..width * height {
+        let total: f32 = weights.iter().map(|w| w[i]).sum();
+        for w in &mut weights {
+            w[i] = if total > 0.0 { w[i] / total } else { 1.0 / images.len() as f32 };
+        }
+    }
+
+    let levels = (width.min(height) as f32).log2().floor() as usize + 1;
+    let mut out: [Vec<Plane>; 3] = Default::default();
+    for (image, weight) in images.iter().zip(weights) {
+        let weight = gaussian_pyramid(Plane { width, height, data: weight }, levels);
+        for (c, bands) in out.iter_mut().enumerate() {
+            let channel = Plane { width, height, data: image.iter().map(|px| px[c]).collect() };
+            let laplacian = laplacian_pyramid(channel, levels);
+            if bands.is_empty() {
+                *bands = laplacian.iter().map(|l| Plane { data: vec![0.0; l.data.len()], ..*l }).collect();
+            }
+            for ((band, l), g) in bands.iter_mut().zip(&laplacian).zip(&weight) {
+                for ((b, l), g) in band.data.iter_mut().zip(&l.data).zip(&g.data) {
+                    *b += l * g;
+                }
+            }
+        }
+    }
+
+    let channels = out.map(|bands| collapse(bands).data);
+    (0..width * height)
+        .map(|i| std::array::from_fn(|c| channels[c][i].clamp(0.0, 1.0)))
+        .collect()
+}
+
+/// Returns the unnormalized fusion weight of every pixel of `image`.
+fn fusion_weights(width: usize, height: usize, image: &[[f32; 3]], params: &Params) -> Vec<f32> {
+    let gray: Vec<f32> = image.iter().map(|px| px.iter().sum::<f32>() / 3.0).collect();
+    let at = |x: usize, y: usize| gray[y * width + x];
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let neighbours = at(x.saturating_sub(1), y)
+                + at((x + 1).min(width - 1), y)
+                + at(x, y.saturating_sub(1))
+                + at(x, (y + 1).min(height - 1));
+            let contrast = (neighbours - 4.0 * gray[i]).abs();
+            let px = image[i];
+            let saturation = (px.iter().map(|v| (v - gray[i]).powi(2)).sum::<f32>() / 3.0).sqrt();
+            let exposedness: f32 =
+                px.iter().map(|v| (-(v - 0.5).powi(2) / (2.0 * EXPOSEDNESS_SIGMA.powi(2))).exp()).product();
+            // The small floor keeps flat, gray areas from getting no weight in every image.
+            contrast.powf(params.contrast_weight) * saturation.powf(params.saturation_weight)
+                * exposedness.powf(params.exposure_weight)
+                + 1e-12
+        })
+        .collect()
+}
+
+/// Merges the images into linear radiance, scaled so that the main image's well-exposed values
+/// keep their brightness.
+///
+/// The camera's response is taken to be the sRGB curve rather than recovered from the images;
+/// each value is weighted by a hat function peaking at mid-gray, which leaves out clipped
+/// highlights and noisy shadows.
+fn radiance(images: &[Vec<[f32; 3]>], exposures: &[f32]) -> Vec<[f32; 3]> {
+    (0..images[0].len())
+        .map(|i| {
+            std::array::from_fn(|c| {
+                let (mut sum, mut total) = (0.0, 0.0);
+                for (image, &t) in images.iter().zip(exposures) {
+                    let v = image[i][c];
+                    let weight = 1.0 - (2.0 * v - 1.0).abs() + 1e-4;
+                    sum += weight * plugin_sdk::srgb_to_linear(v) * exposures[0] / t;
+                    total += weight;
+                }
+                sum / total
+            })
+        })
+        .collect()
+}
+
+/// Estimates each image's exposure relative to the main image's from the ratio of their linear
+/// values where both are well exposed.
+fn estimate_exposures(images: &[Vec<[f32; 3]>]) -> Option<Vec<f32>> {
+    images
+        .iter()
+        .map(|image| {
+            let (mut sum, mut reference) = (0.0, 0.0);
+            for (px, base) in image.iter().flatten().zip(images[0].iter().flatten()) {
+                if WELL_EXPOSED.contains(px) && WELL_EXPOSED.contains(base) {
+                    sum += plugin_sdk::srgb_to_linear(*px);
+                    reference += plugin_sdk::srgb_to_linear(*base);
+                }
+            }
+            (reference > 0.0).then(|| sum / reference)
+        })
+        .collect()
+}
+
+/// One channel of an image, or one level of a pyramid.
+#[derive(Debug, Clone)]
+struct Plane {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+/// Binomial filter used to build and expand the pyramids.
+const KERNEL: [f32; 5] = [1.0 / 16.0, 4.0 / 16.0, 6.0 / 16.0, 4.0 / 16.0, 1.0 / 16.0];
+
+impl Plane {
+    /// Smooths with [`KERNEL`] and keeps every other sample, rounding the size up.
+    fn reduce(&self) -> Plane {
+        let (w, h) = (self.width, self.height);
+        let (nw, nh) = (w.div_ceil(2), h.div_ceil(2));
+        let taps = |center: usize, len: usize| {
+            KERNEL.iter().enumerate().map(move |(k, weight)| ((center + k).saturating_sub(2).min(len - 1), *weight))
+        };
+        let rows: Vec<f32> = (0..h * nw)
+            .map(|i| taps(2 * (i % nw), w).map(|(x, k)| k * self.data[(i / nw) * w + x]).sum())
+            .collect();
+        let data = (0..nh * nw)
+            .map(|i| taps(2 * (i / nw), h).map(|(y, k)| k * rows[y * nw + i % nw]).sum())
+            .collect();
+        Plane { width: nw, height: nh, data }
+    }
+
+    /// Interpolates to `width` x `height`, the size of the level this one was reduced from.
+    fn expand(&self, width: usize, height: usize) -> Plane {
+        // Even targets sit on a source sample, odd ones halfway between two.
+        let taps = |target: usize, len: usize| -> Vec<(usize, f32)> {
+            let clamp = |i: usize| i.min(len - 1);
+            if target.is_multiple_of(2) {
+                let i = target / 2;
+                vec![(i.saturating_sub(1), 0.125), (clamp(i), 0.75), (clamp(i + 1), 0.125)]
+            } else {
+                vec![(clamp(target / 2), 0.5), (clamp(target / 2 + 1), 0.5)]
+            }
+        };
+        let rows: Vec<f32> = (0..self.height * width)
+            .map(|i| {
+                let row = &self.data[(i / width) * self.width..][..self.width];
+                taps(i % width, self.width).iter().map(|&(x, k)| k * row[x]).sum()
+            })
+            .collect();
+        let data = (0..height * width)
+            .map(|i| taps(i / width, self.height).iter().map(|&(y, k)| k * rows[y * width + i % width]).sum())
+            .collect();
+        Plane { width, height, data }
+    }
+}
+
+fn gaussian_pyramid(base: Plane, levels: usize) -> Vec<Plane> {
+    let mut pyramid = vec![base];
+    while pyramid.len() < levels {
+        let next = pyramid[pyramid.len() - 1].reduce();
+        pyramid.push(next);
+    }
+    pyramid
+}
+
+/// Splits `base` into band-pass levels and the low-pass remainder, which [`collapse`] sums back
+/// to `base` exactly.
+fn laplacian_pyramid(base: Plane, levels: usize) -> Vec<Plane> {
+    let mut pyramid = gaussian_pyramid(base, levels);
+    for l in 0..pyramid.len() - 1 {
+        let (width, height) = (pyramid[l].width, pyramid[l].height);
+        let expanded = pyramid[l + 1].expand(width, height);
+        for (v, e) in pyramid[l].data.iter_mut().zip(expanded.data) {
+            *v -= e;
+        }
+    }
+    pyramid
+}
+
+fn collapse(mut pyramid: Vec<Plane>) -> Plane {
+    let mut image = pyramid.pop().expect("pyramids have at least one level");
+    while let Some(mut band) = pyramid.pop() {
+        let expanded = image.expand(band.width, band.height);
+        for (v, e) in band.data.iter_mut().zip(expanded.data) {
+            *v += e;
+        }
+        image = band;
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::InputImage;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, others: &[Vec<u8>], params: &str) -> Result<(), PluginError> {
+        let inputs: Vec<InputImage> = others.iter().map(|o| InputImage { data: o.as_ptr(), width, height }).collect();
+        let ctx = CallContext { inputs: inputs.as_ptr(), input_count: inputs.len() as u32, ..CallContext::new(0) };
+        testing::run_with(process, &ctx, width, height, buf, params)
+    }
+
+    /// A 16x8 scene, dark on the left and bright on the right, with texture on both halves,
+    /// taken with `exposure` relative to the middle one.
+    fn shot(exposure: f32) -> Vec<u8> {
+        (0..128)
+            .flat_map(|i| {
+                let (x, y) = (i % 16, i / 16);
+                let base = if x < 8 { 0.01 } else { 0.5 };
+                let radiance = base * if (x + y) % 2 == 0 { 1.0 } else { 1.5 };
+                let v = (plugin_sdk::linear_to_srgb(radiance * exposure) * 255.0).round() as u8;
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    /// Difference between neighbouring texture pixels, summed over one half.
+    fn detail(img: &[u8], right: bool) -> i32 {
+        (0..8)
+            .flat_map(|y| (0..7).map(move |x| (x + if right { 8 } else { 0 }, y)))
+            .map(|(x, y)| (img[(y * 16 + x) * 4] as i32 - img[(y * 16 + x + 1) * 4] as i32).abs())
+            .sum()
+    }
+
+    #[test]
+    fn test_fusion_keeps_detail_of_every_exposure() {
+        let (dark, bright) = (shot(0.25), shot(8.0));
+        let mut img = shot(1.0);
+        run(&mut img, 16, 8, &[dark.clone(), bright.clone()], "{}").unwrap();
+        // The shadows take their texture from the bright shot and the highlights from the dark one.
+        assert!(detail(&img, false) > detail(&shot(1.0), false), "{img:?}");
+        assert!(detail(&img, true) > detail(&bright, true));
+        assert!(img.chunks(4).all(|p| p[3] == 255));
+
+        // Too few exposures, or one of the wrong size.
+        let mut img = shot(1.0);
+        assert!(run(&mut img, 16, 8, std::slice::from_ref(&dark), "{}").is_err());
+        assert!(testing::run(process, 16, 8, &mut img, "{}").is_err());
+        let inputs = [InputImage { data: dark.as_ptr(), width: 16, height: 8 }, InputImage {
+            data: bright.as_ptr(),
+            width: 8,
+            height: 16,
+        }];
+        let ctx = CallContext { inputs: inputs.as_ptr(), input_count: 2, ..CallContext::new(0) };
+        assert!(testing::run_with(process, &ctx, 16, 8, &mut img, "{}").is_err());
+    }
+
+    #[test]
+    fn test_hdr_recovers_radiance() {
+        let others = [shot(0.25), shot(8.0)];
+        let mut given = shot(1.0);
+        run(&mut given, 16, 8, &others, r#"{"mode": "hdr", "exposures": [0.01, 0.0025, 0.08]}"#).unwrap();
+        let mut estimated = shot(1.0);
+        run(&mut estimated, 16, 8, &others, r#"{"mode": "hdr"}"#).unwrap();
+        for img in [&given, &estimated] {
+            // The shadows come out at the main exposure's brightness but with the bright shot's
+            // precision, and the highlights are as in the main shot.
+            let shadow = plugin_sdk::srgb_to_linear(img[0] as f32 / 255.0);
+            assert!((shadow - 0.01).abs() < 0.001, "{shadow}");
+            let highlight = plugin_sdk::srgb_to_linear(img[8 * 4] as f32 / 255.0);
+            assert!((highlight - 0.5).abs() < 0.02, "{highlight}");
+        }
+        let mut img = shot(1.0);
+        assert!(run(&mut img, 16, 8, &others, r#"{"mode": "hdr", "exposures": [1, 2]}"#).is_err());
+    }
+
+    #[test]
+    fn test_pyramid_round_trip() {
+        let base = Plane { width: 7, height: 5, data: (0..35).map(|i| (i * 37 % 11) as f32).collect() };
+        let restored = collapse(laplacian_pyramid(base.clone(), 3));
+        assert!(base.data.iter().zip(&restored.data).all(|(a, b)| (a - b).abs() < 1e-4));
+    }
+}
@@ -0,0 +1,202 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Hue, saturation and lightness changes; all zero leaves colors unchanged.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(default)]
+struct Adjust {
+    /// Hue rotation in degrees.
+    hue: f32,
+    /// -1 removes all saturation, 1 saturates fully; 0 keeps it.
+    saturation: f32,
+    /// -1 makes black, 1 makes white; 0 keeps the lightness.
+    lightness: f32,
+}
+
+impl Adjust {
+    fn scaled(self, weight: f32) -> Self {
+        Self { hue: self.hue * weight, saturation: self.saturation * weight, lightness: self.lightness * weight }
+    }
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            hue: self.hue + other.hue,
+            saturation: self.saturation + other.saturation,
+            lightness: self.lightness + other.lightness,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Params {
+    /// Change applied to every color.
+    #[serde(flatten)]
+    global: Adjust,
+    reds: Adjust,
+    yellows: Adjust,
+    greens: Adjust,
+    cyans: Adjust,
+    blues: Adjust,
+    magentas: Adjust,
+}
+
+impl Params {
+    /// Bands in hue order, centered 60 degrees apart starting at red.
+    fn bands(&self) -> [Adjust; 6] {
+        [self.reds, self.yellows, self.greens, self.cyans, self.blues, self.magentas]
+    }
+
+    /// Returns the change for a color of the given hue and saturation.
+    ///
+    /// A band's weight falls off linearly to zero at the centers of its neighbours, so the
+    /// weights always add up to one; it is also scaled by saturation, leaving grays to the
+    /// global change only.
+    fn adjust_for(&self, hue: f32, saturation: f32) -> Adjust {
+        let pos = hue / 60.0;
+        let lower = pos.floor() as usize % 6;
+        let frac = pos - pos.floor();
+        let bands = self.bands();
+        let band = bands[lower].scaled(1.0 - frac).add(bands[(lower + 1) % 6].scaled(frac));
+        self.global.add(band.scaled(saturation))
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "hsl_plugin"
+version = "0.1.0"
+description = "Adjusts hue, saturation and lightness, globally or per hue band"
+
+[defaults]
+hue = 0.0
+saturation = 0.0
+lightness = 0.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Adjusts both formats; working per pixel, the adjustment is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => adjust(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => adjust(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Converts sRGB-encoded RGB in `[0, 1]` to hue in degrees `[0, 360)`, saturation and lightness.
+fn rgb_to_hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let chroma = max - min;
+    if chroma <= 0.0 {
+        return [0.0, 0.0, l];
+    }
+    let s = chroma / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    [h * 60.0, s, l]
+}
+
+fn hsl_to_rgb([h, s, l]: [f32; 3]) -> [f32; 3] {
+    let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = l - chroma / 2.0;
+    [r + m, g + m, b + m]
+}
+
+/// Moves `v` towards 0 for negative `amount` and towards 1 for positive, proportionally.
+fn push(v: f32, amount: f32) -> f32 {
+    let amount = amount.clamp(-1.0, 1.0);
+    if amount < 0.0 { v * (1.0 + amount) } else { v + (1.0 - v) * amount }
+}
+
+/// Works on the sRGB-encoded values, whatever the working space; alpha is kept.
+fn adjust<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let encoded = rgb.map(|v| plugin_sdk::to_encoded::<T>(v).clamp(0.0, 1.0));
+        let [h, s, l] = rgb_to_hsl(encoded);
+        let change = params.adjust_for(h, s);
+        if change == Adjust::default() {
+            return rgb;
+        }
+        let hsl = [h + change.hue, push(s, change.saturation), push(l, change.lightness)];
+        hsl_to_rgb(hsl).map(plugin_sdk::from_encoded::<T>)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_round_trip_is_exact_in_8_bit() {
+        for r in (0..=255u8).step_by(5) {
+            for g in (0..=255u8).step_by(15) {
+                for b in (0..=255u8).step_by(17) {
+                    let rgb = [r, g, b].map(|v| v as f32 / 255.0);
+                    let back = hsl_to_rgb(rgb_to_hsl(rgb)).map(|v| (v * 255.0).round() as u8);
+                    assert_eq!(back, [r, g, b]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_global_adjustments() {
+        let mut hue = [255u8, 0, 0, 200];
+        run(&mut hue, r#"{"hue": 120}"#).unwrap();
+        assert_eq!(hue, [0, 255, 0, 200]);
+
+        let mut gray = [200u8, 100, 100, 255];
+        run(&mut gray, r#"{"saturation": -1}"#).unwrap();
+        assert_eq!(gray, [150, 150, 150, 255]);
+
+        let mut white = [10u8, 20, 30, 255];
+        run(&mut white, r#"{"lightness": 1}"#).unwrap();
+        assert_eq!(white, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_hue_bands_leave_other_colors_alone() {
+        // Reds are desaturated; blue and gray are outside the band.
+        let params = r#"{"reds": {"saturation": -1}}"#;
+        let mut buf = [255u8, 0, 0, 255, 0, 0, 255, 255, 90, 90, 90, 255];
+        run(&mut buf, params).unwrap();
+        assert_eq!(buf, [128, 128, 128, 255, 0, 0, 255, 255, 90, 90, 90, 255]);
+
+        // Orange sits halfway between reds and yellows and gets half of the red shift.
+        let params = r#"{"reds": {"hue": -20}}"#;
+        let mut orange = [255u8, 128, 0, 255];
+        run(&mut orange, params).unwrap();
+        let [h, _, _] = rgb_to_hsl(orange.map(|v| v as f32 / 255.0)[..3].try_into().unwrap());
+        assert!((h - 20.0).abs() < 1.0, "hue {h}");
+    }
+}
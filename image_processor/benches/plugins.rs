@@ -0,0 +1,111 @@
+//! Plugin call overhead, parallel vs serial blur, and tiled vs whole-image processing.
+//!
+//! The plugin libraries are loaded from `IMAGE_PROCESSOR_BENCH_PLUGINS` (default
+//! `target/release`), so build them first: `cargo build --release --workspace`, or run
+//! `image_processor bench`, which does both and passes criterion's baseline options along.
+
+use std::hint::black_box;
+use std::path::PathBuf;
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use image_processor::incremental::{Incremental, TileSize};
+use image_processor::pipeline::{PixelBuffer, Step, StepSpec};
+use plugin_sdk::CallContext;
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 1024;
+
+fn plugin_dir() -> PathBuf {
+    std::env::var_os("IMAGE_PROCESSOR_BENCH_PLUGINS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../target/release")))
+}
+
+fn load(spec: &str) -> Step {
+    let spec: StepSpec = spec.parse().expect("valid step spec");
+    // SAFETY:
+    // - The libraries come from this workspace's build output and export the expected symbols.
+    // - A stale or foreign library in that directory would break the FFI contract, which is
+    //   why the bench subcommand rebuilds the plugins before running.
+    unsafe { Step::load(&plugin_dir(), &spec, &[]) }
+        .unwrap_or_else(|e| panic!("{e}; build the plugins with `cargo build --release --workspace` first"))
+}
+
+fn image() -> PixelBuffer {
+    PixelBuffer::Rgba8((0..WIDTH * HEIGHT * 4).map(|i| (i * 37 % 251) as u8).collect())
+}
+
+/// Cost of one call that does no pixel work: params handling and the call itself.
+fn call_overhead(c: &mut Criterion) {
+    let ctx = CallContext::new(0);
+    let mut group = c.benchmark_group("call_overhead");
+    let mut pixel = PixelBuffer::Rgba8(vec![0; 4]);
+
+    let dynamic = load("blur_plugin:radius=0");
+    group.bench_function("dynamic", |b| b.iter(|| black_box(dynamic.run(&ctx, 1, 1, &mut pixel))));
+
+    #[cfg(feature = "gpu")]
+    {
+        // The built-in needs a GPU device to load; without one there is nothing to compare.
+        let spec: StepSpec = "gpu_blur:radius=0".parse().expect("valid step spec");
+        // SAFETY: built-ins are not loaded from a library.
+        match unsafe { Step::load(&plugin_dir(), &spec, &[]) } {
+            Ok(builtin) => {
+                group.bench_function("builtin", |b| b.iter(|| black_box(builtin.run(&ctx, 1, 1, &mut pixel))));
+            }
+            Err(e) => eprintln!("skipping call_overhead/builtin: {e}"),
+        }
+    }
+    group.finish();
+}
+
+/// The same blur with one thread and with all cores.
+fn blur_threads(c: &mut Criterion) {
+    let step = load("blur_plugin:radius=8");
+    let mut group = c.benchmark_group("blur_threads");
+    group.sample_size(10);
+    for (name, max_threads) in [("serial", 1), ("parallel", 0)] {
+        let ctx = CallContext { max_threads, ..CallContext::new(0) };
+        group.bench_function(name, |b| {
+            b.iter_batched_ref(image, |data| step.run(&ctx, WIDTH, HEIGHT, data), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+/// A full run against an incremental re-run after a change inside one tile.
+fn tiled_vs_whole(c: &mut Criterion) {
+    let step = load("blur_plugin:radius=4");
+    let ctx = CallContext::new(0);
+    let halo = step.halo().expect("blur_plugin is local");
+    let chain = |w, h, data: &mut PixelBuffer| match step.run(&ctx, w, h, data) {
+        0 => Ok(()),
+        code => Err(code),
+    };
+    let edited = || {
+        let mut data = image();
+        if let PixelBuffer::Rgba8(bytes) = &mut data {
+            bytes[(100 * WIDTH as usize + 100) * 4] ^= 0xff;
+        }
+        data
+    };
+
+    let mut group = c.benchmark_group("tiled_vs_whole");
+    group.sample_size(10);
+    group.bench_function("whole", |b| b.iter_batched_ref(edited, |data| chain(WIDTH, HEIGHT, data), BatchSize::LargeInput));
+    group.bench_function("tiled", |b| {
+        b.iter_batched(
+            || {
+                let mut state = Incremental::with_tile_size(TileSize::Fixed(256));
+                state.run(WIDTH, HEIGHT, halo, &mut image(), chain).expect("blur succeeds");
+                (state, edited())
+            },
+            |(mut state, mut data)| state.run(WIDTH, HEIGHT, halo, &mut data, chain),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, call_overhead, blur_threads, tiled_vs_whole);
+criterion_main!(benches);
@@ -0,0 +1,340 @@
+use color_quant::NeuQuant;
+use gif::{DisposalMethod, Encoder, Frame, Repeat};
+use image::RgbaImage;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::convert::{BAYER_8X8, DitherMode};
+use crate::error::AppError;
+
+/// NeuQuant sampling factor: 1 learns from every pixel, 30 from every 30th.
+const QUANT_SAMPLING: i32 = 10;
+/// Upper bound on the pixels a global palette is learnt from.
+const MAX_PALETTE_SAMPLES: usize = 1 << 22;
+/// Pixels with less alpha than this become transparent in GIF output.
+const MIN_ALPHA: u8 = 128;
+
+/// Container of the `assemble` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    /// Animated GIF, limited to 256 colours per frame and 1-bit transparency.
+    Gif,
+    /// Animated PNG, lossless.
+    Apng,
+}
+
+impl FromStr for AnimationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gif" => Ok(Self::Gif),
+            "apng" => Ok(Self::Apng),
+            other => Err(format!("unknown animation format `{other}` (expected gif or apng)")),
+        }
+    }
+}
+
+impl AnimationFormat {
+    /// Format implied by the extension of `path`: `.gif`, or `.png` and `.apng`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gif" => Some(Self::Gif),
+            "png" | "apng" => Some(Self::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// Where the colours of GIF frames come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaletteMode {
+    /// One palette learnt from all frames, so colours do not shimmer between frames.
+    #[default]
+    Global,
+    /// A palette per frame, closer to each frame at the cost of a larger file.
+    Local,
+}
+
+impl FromStr for PaletteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "global" => Ok(Self::Global),
+            "local" => Ok(Self::Local),
+            other => Err(format!("unknown palette mode `{other}` (expected global or local)")),
+        }
+    }
+}
+
+/// Colour reduction of GIF output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GifOptions {
+    /// Palette size, 2 to 256, including the transparent entry if any frame needs one.
+    pub colors: u16,
+    /// Global or per-frame palettes.
+    pub palette: PaletteMode,
+    /// Dithering when mapping pixels to the palette.
+    pub dither: DitherMode,
+}
+
+/// Compares names with runs of digits taken as numbers, so `frame9` sorts before `frame10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(ca), Some(cb)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if ca.is_ascii_digit() && cb.is_ascii_digit() {
+            let (da, ra) = a.split_at(a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len()));
+            let (db, rb) = b.split_at(b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len()));
+            let (ta, tb) = (da.trim_start_matches('0'), db.trim_start_matches('0'));
+            let order = ta.len().cmp(&tb.len()).then_with(|| ta.cmp(tb)).then_with(|| da.len().cmp(&db.len()));
+            if order != Ordering::Equal {
+                return order;
+            }
+            (a, b) = (ra, rb);
+        } else {
+            if ca != cb {
+                return ca.cmp(&cb);
+            }
+            (a, b) = (&a[ca.len_utf8()..], &b[cb.len_utf8()..]);
+        }
+    }
+}
+
+/// Delay of each of `frames` frames: `delays` in order, its last entry repeating.
+pub fn frame_delays(delays: &[u32], frames: usize) -> Vec<u32> {
+    let last = delays.last().copied().unwrap_or(0);
+    (0..frames).map(|i| delays.get(i).copied().unwrap_or(last)).collect()
+}
+
+/// Checks that there is at least one frame and that all frames share the size of the first.
+pub fn check_frames(frames: &[RgbaImage], names: &[String]) -> Result<(), AppError> {
+    let Some(first) = frames.first() else {
+        return Err(AppError::Assemble("no frames to assemble".into()));
+    };
+    for (frame, name) in frames.iter().zip(names).skip(1) {
+        if frame.dimensions() != first.dimensions() {
+            return Err(AppError::Assemble(format!(
+                "{name} is {}x{} but the first frame is {}x{}",
+                frame.width(),
+                frame.height(),
+                first.width(),
+                first.height()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Encodes `frames`, which must share one size, as an animated GIF.
+///
+/// Delays are rounded to the 10 ms steps of GIF. `plays` is how often the animation runs,
+/// 0 meaning forever. Pixels below half alpha become fully transparent, taking one palette
+/// entry, and every frame replaces the previous one entirely.
+pub fn encode_gif(frames: &[RgbaImage], delays_ms: &[u32], plays: u16, opts: &GifOptions) -> Result<Vec<u8>, AppError> {
+    let (width, height) = frames.first().map_or((0, 0), |f| f.dimensions());
+    let (Ok(w), Ok(h)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(AppError::Assemble(format!("{width}x{height} exceeds the 65535x65535 limit of GIF")));
+    };
+    let gif_err = |e: gif::EncodingError| AppError::Assemble(e.to_string());
+
+    let transparent = frames.iter().any(|f| f.pixels().any(|p| p[3] < MIN_ALPHA));
+    let colors = (opts.colors.clamp(2, 256) - u16::from(transparent)) as usize;
+    let global = match opts.palette {
+        PaletteMode::Global => Some(Quantizer::new(frames, colors, transparent)),
+        PaletteMode::Local => None,
+    };
+
+    let mut out = Vec::new();
+    let global_map = global.as_ref().map_or_else(Vec::new, Quantizer::color_map);
+    let mut encoder = Encoder::new(&mut out, w, h, &global_map).map_err(gif_err)?;
+    match plays {
+        0 => encoder.set_repeat(Repeat::Infinite).map_err(gif_err)?,
+        // The loop count of the NETSCAPE extension counts repeats after the first play.
+        n => encoder.set_repeat(Repeat::Finite(n - 1)).map_err(gif_err)?,
+    }
+    for (i, frame) in frames.iter().enumerate() {
+        let local = match &global {
+            Some(_) => None,
+            None => Some(Quantizer::new(std::slice::from_ref(frame), colors, transparent)),
+        };
+        let quantizer = global.as_ref().or(local.as_ref()).expect("either palette is set");
+        let delay = (delays_ms.get(i).copied().unwrap_or(0) as f64 / 10.0).round().min(u16::MAX as f64) as u16;
+        encoder
+            .write_frame(&Frame {
+                width: w,
+                height: h,
+                delay,
+                dispose: if transparent { DisposalMethod::Background } else { DisposalMethod::Keep },
+                transparent: quantizer.transparent,
+                palette: local.as_ref().map(Quantizer::color_map),
+                buffer: Cow::Owned(quantizer.indices(frame, opts.dither)),
+                ..Frame::default()
+            })
+            .map_err(gif_err)?;
+    }
+    drop(encoder);
+    Ok(out)
+}
+
+/// A palette of at most `colors` entries, with an extra transparent entry after them when needed.
+///
+/// Frames with few enough distinct colours get exactly those; others are reduced with NeuQuant.
+struct Quantizer {
+    colors: Vec<[u8; 3]>,
+    exact: HashMap<[u8; 3], u8>,
+    quant: Option<NeuQuant>,
+    transparent: Option<u8>,
+}
+
+impl Quantizer {
+    /// Learns up to `colors` colours from the opaque pixels of `frames`, evenly subsampled.
+    fn new(frames: &[RgbaImage], colors: usize, transparent: bool) -> Self {
+        let opaque = || frames.iter().flat_map(|f| f.pixels()).filter(|p| p[3] >= MIN_ALPHA);
+        let mut exact = HashMap::new();
+        for p in opaque() {
+            let next = exact.len();
+            exact.entry([p[0], p[1], p[2]]).or_insert(next as u8);
+            if exact.len() > colors {
+                break;
+            }
+        }
+        let (palette, quant) = if exact.len() <= colors {
+            let mut palette = vec![[0; 3]; exact.len()];
+            exact.iter().for_each(|(&rgb, &i)| palette[i as usize] = rgb);
+            (palette, None)
+        } else {
+            exact.clear();
+            let step = opaque().count().div_ceil(MAX_PALETTE_SAMPLES).max(1);
+            let samples: Vec<u8> = opaque().step_by(step).flat_map(|p| [p[0], p[1], p[2], 255]).collect();
+            let quant = NeuQuant::new(QUANT_SAMPLING, colors, &samples);
+            let palette = quant.color_map_rgb().chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+            (palette, Some(quant))
+        };
+        Self { transparent: transparent.then_some(palette.len() as u8), colors: palette, exact, quant }
+    }
+
+    /// RGB triplets of the palette, the transparent entry included.
+    fn color_map(&self) -> Vec<u8> {
+        let mut map: Vec<u8> = self.colors.iter().flatten().copied().collect();
+        if self.transparent.is_some() || map.is_empty() {
+            map.extend_from_slice(&[0, 0, 0]);
+        }
+        map
+    }
+
+    /// Index of the palette colour closest to `rgb`.
+    fn index_of(&self, rgb: [u8; 3]) -> usize {
+        if let Some(quant) = &self.quant {
+            return quant.index_of(&[rgb[0], rgb[1], rgb[2], 255]);
+        }
+        if let Some(&i) = self.exact.get(&rgb) {
+            return i as usize;
+        }
+        let distance = |c: &[u8; 3]| (0..3).map(|i| (c[i] as i32 - rgb[i] as i32).pow(2)).sum::<i32>();
+        (0..self.colors.len()).min_by_key(|&i| distance(&self.colors[i])).unwrap_or(0)
+    }
+
+    /// Palette indices of the pixels of `frame`.
+    fn indices(&self, frame: &RgbaImage, dither: DitherMode) -> Vec<u8> {
+        let (w, h) = (frame.width() as usize, frame.height() as usize);
+        // Ordered dithering spreads values over roughly the gap between neighbouring colours.
+        let spread = 255.0 / (self.colors.len().max(1) as f32).cbrt();
+        let mut error = vec![[0.0f32; 3]; if dither == DitherMode::FloydSteinberg { 2 * (w + 2) } else { 0 }];
+        let mut out = Vec::with_capacity(w * h);
+        for y in 0..h {
+            if !error.is_empty() {
+                // Row y reads the first half; row y + 1 accumulates into the second.
+                error.copy_within(w + 2.., 0);
+                error[w + 2..].fill([0.0; 3]);
+            }
+            for x in 0..w {
+                let p = frame.get_pixel(x as u32, y as u32);
+                if let Some(index) = self.transparent.filter(|_| p[3] < MIN_ALPHA) {
+                    out.push(index);
+                    continue;
+                }
+                let offset = match dither {
+                    DitherMode::None => [0.0; 3],
+                    DitherMode::Ordered => [((BAYER_8X8[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5) * spread; 3],
+                    DitherMode::FloydSteinberg => error[x + 1],
+                };
+                let wanted: [f32; 3] = std::array::from_fn(|c| (p[c] as f32 + offset[c]).clamp(0.0, 255.0));
+                let index = self.index_of(wanted.map(|v| v.round() as u8));
+                out.push(index as u8);
+                if dither == DitherMode::FloydSteinberg {
+                    let got = self.colors.get(index).copied().unwrap_or_default();
+                    for c in 0..3 {
+                        let err = wanted[c] - got[c] as f32;
+                        error[x + 2][c] += err * 7.0 / 16.0;
+                        error[w + 2 + x][c] += err * 3.0 / 16.0;
+                        error[w + 2 + x + 1][c] += err * 5.0 / 16.0;
+                        error[w + 2 + x + 2][c] += err / 16.0;
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{AnimationDecoder, Rgba};
+
+    #[test]
+    fn test_natural_order() {
+        let mut names = vec!["frame10.png", "frame9.png", "frame001.png", "frame1.png", "a.png", "frame2b.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a.png", "frame1.png", "frame001.png", "frame2b.png", "frame9.png", "frame10.png"]);
+        assert_eq!(frame_delays(&[50, 200], 4), [50, 200, 200, 200]);
+        assert_eq!(AnimationFormat::from_path(Path::new("out.GIF")), Some(AnimationFormat::Gif));
+    }
+
+    #[test]
+    fn test_rejects_mismatched_sizes() {
+        let frames = [RgbaImage::new(4, 4), RgbaImage::new(4, 5)];
+        let names = ["a".to_string(), "b".to_string()];
+        assert!(matches!(check_frames(&frames, &names), Err(AppError::Assemble(msg)) if msg.starts_with("b is 4x5")));
+        assert!(check_frames(&[], &[]).is_err());
+    }
+
+    #[test]
+    fn test_gif_round_trip() {
+        let red = RgbaImage::from_pixel(16, 8, Rgba([255, 0, 0, 255]));
+        let mut blue = RgbaImage::from_pixel(16, 8, Rgba([0, 0, 255, 255]));
+        blue.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        for palette in [PaletteMode::Global, PaletteMode::Local] {
+            let opts = GifOptions { colors: 16, palette, dither: DitherMode::FloydSteinberg };
+            let bytes = encode_gif(&[red.clone(), blue.clone()], &[100, 250], 0, &opts).unwrap();
+            let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(bytes)).unwrap();
+            let frames = decoder.into_frames().collect_frames().unwrap();
+            assert_eq!(frames.len(), 2);
+            assert_eq!(frames[1].delay().numer_denom_ms(), (250, 1));
+            assert_eq!(frames[0].buffer(), &red, "{palette:?}");
+            assert_eq!(frames[1].buffer().get_pixel(0, 0)[3], 0);
+            assert_eq!(frames[1].buffer().get_pixel(1, 0), &Rgba([0, 0, 255, 255]));
+        }
+    }
+
+    #[test]
+    fn test_gif_reduces_colors() {
+        let ramp = RgbaImage::from_fn(256, 16, |x, y| Rgba([x as u8, (y * 16) as u8, 128, 255]));
+        let opts = GifOptions { colors: 32, palette: PaletteMode::Global, dither: DitherMode::None };
+        let bytes = encode_gif(std::slice::from_ref(&ramp), &[100], 1, &opts).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(std::io::Cursor::new(bytes)).unwrap();
+        let frame = decoder.into_frames().collect_frames().unwrap().remove(0).into_buffer();
+        let distinct: std::collections::HashSet<_> = frame.pixels().collect();
+        assert!(distinct.len() <= 32);
+        let error: f64 = frame.pixels().zip(ramp.pixels()).map(|(a, b)| (a[0] as f64 - b[0] as f64).abs()).sum();
+        assert!(error / (256.0 * 16.0) < 20.0, "{}", error / (256.0 * 16.0));
+    }
+}
@@ -0,0 +1,122 @@
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
+use serde::Serialize;
+
+/// Images are reduced to this longest side first, so scores of different resolutions compare.
+const ANALYSIS_SIDE: u32 = 1024;
+/// Luma, in 8-bit levels, at or above which a pixel counts as blown out.
+const OVEREXPOSED: f32 = 250.0;
+/// Luma, in 8-bit levels, at or below which a pixel counts as crushed.
+const UNDEREXPOSED: f32 = 5.0;
+
+/// Quality scores of one image.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Assessment {
+    /// Variance of the Laplacian of luma; low values mean a blurry image.
+    pub sharpness: f64,
+    /// Estimated standard deviation of noise, in 8-bit levels.
+    pub noise: f64,
+    /// Percentage of pixels at or near white.
+    pub overexposed: f64,
+    /// Percentage of pixels at or near black.
+    pub underexposed: f64,
+}
+
+/// Scores `img` on luma in 8-bit levels, reduced to at most 1024 pixels on its longest side.
+///
+/// Sharpness is the variance of the 4-neighbour Laplacian; noise follows Immerkær's fast
+/// estimate, which filters out structure with the difference of two Laplacians before averaging
+/// what is left. Images smaller than 3x3 score zero everywhere except for exposure.
+pub fn assess(img: &DynamicImage) -> Assessment {
+    let mut luma = img.to_luma32f();
+    if luma.width().max(luma.height()) > ANALYSIS_SIDE {
+        let scale = ANALYSIS_SIDE as f64 / luma.width().max(luma.height()) as f64;
+        let w = ((luma.width() as f64 * scale).round() as u32).max(1);
+        let h = ((luma.height() as f64 * scale).round() as u32).max(1);
+        luma = imageops::resize(&luma, w, h, FilterType::Triangle);
+    }
+    let (w, h) = (luma.width() as usize, luma.height() as usize);
+    let px: Vec<f32> = luma.into_raw().into_iter().map(|v| v * 255.0).collect();
+
+    let count = px.len().max(1) as f64;
+    let overexposed = px.iter().filter(|&&v| v >= OVEREXPOSED).count() as f64 / count * 100.0;
+    let underexposed = px.iter().filter(|&&v| v <= UNDEREXPOSED).count() as f64 / count * 100.0;
+    if w < 3 || h < 3 {
+        return Assessment { sharpness: 0.0, noise: 0.0, overexposed, underexposed };
+    }
+
+    let (mut sum, mut sum_sq, mut noise) = (0.0f64, 0.0f64, 0.0f64);
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let at = |dx: usize, dy: usize| px[(y + dy - 1) * w + x + dx - 1] as f64;
+            let laplacian = at(1, 0) + at(0, 1) + at(2, 1) + at(1, 2) - 4.0 * at(1, 1);
+            sum += laplacian;
+            sum_sq += laplacian * laplacian;
+            let corners = at(0, 0) + at(2, 0) + at(0, 2) + at(2, 2);
+            let edges = at(1, 0) + at(0, 1) + at(2, 1) + at(1, 2);
+            noise += (corners - 2.0 * edges + 4.0 * at(1, 1)).abs();
+        }
+    }
+    let inner = ((w - 2) * (h - 2)) as f64;
+    let mean = sum / inner;
+    Assessment {
+        sharpness: sum_sq / inner - mean * mean,
+        noise: noise * (std::f64::consts::FRAC_PI_2).sqrt() / (6.0 * inner),
+        overexposed,
+        underexposed,
+    }
+}
+
+/// One line of the `assess` output.
+#[derive(Debug, Clone, Serialize)]
+pub struct Scored {
+    /// Where the image was read from.
+    pub path: String,
+    /// Scores of the image.
+    #[serde(flatten)]
+    pub scores: Assessment,
+    /// Whether the sharpness is below `--reject-below`; absent without a threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma};
+    use plugin_sdk::Rng;
+
+    fn checkerboard(cell: u32) -> DynamicImage {
+        let img = GrayImage::from_fn(64, 64, |x, y| {
+            Luma([if (x / cell + y / cell).is_multiple_of(2) { 40 } else { 200 }])
+        });
+        DynamicImage::ImageLuma8(img)
+    }
+
+    #[test]
+    fn test_blur_lowers_sharpness() {
+        let sharp = checkerboard(4);
+        let blurred = sharp.blur(2.0);
+        let (a, b) = (assess(&sharp), assess(&blurred));
+        assert!(a.sharpness > 10.0 * b.sharpness, "{a:?} vs {b:?}");
+        assert_eq!(assess(&DynamicImage::ImageLuma8(GrayImage::from_pixel(8, 8, Luma([90])))).sharpness, 0.0);
+    }
+
+    #[test]
+    fn test_estimates_noise_level() {
+        // Uniform noise of ±10 levels has a standard deviation of about 5.8.
+        let mut rng = Rng::new(3);
+        let noisy = GrayImage::from_fn(256, 256, |_, _| Luma([118 + (rng.next_u64() % 21) as u8]));
+        let noise = assess(&DynamicImage::ImageLuma8(noisy)).noise;
+        assert!((4.8..6.8).contains(&noise), "{noise}");
+        let ramp = GrayImage::from_fn(256, 64, |x, y| Luma([(x / 2 + y) as u8]));
+        assert!(assess(&DynamicImage::ImageLuma8(ramp)).noise < 1.0);
+    }
+
+    #[test]
+    fn test_exposure_percentages() {
+        let img = GrayImage::from_fn(10, 10, |x, _| Luma([match x { 0 => 255, 1..=3 => 0, _ => 128 }]));
+        let scores = assess(&DynamicImage::ImageLuma8(img));
+        assert_eq!((scores.overexposed, scores.underexposed), (10.0, 30.0));
+    }
+}
@@ -0,0 +1,363 @@
+use image::RgbaImage;
+use serde::Serialize;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::error::AppError;
+
+/// Format of the coordinate map written next to the atlases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MapFormat {
+    /// A JSON object listing the atlases and the sprites in them.
+    #[default]
+    Json,
+    /// One CSS class per sprite, with the atlas as background image.
+    Css,
+}
+
+impl MapFormat {
+    /// File extension of the map.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Css => "css",
+        }
+    }
+}
+
+impl FromStr for MapFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "css" => Ok(Self::Css),
+            other => Err(format!("unknown map format `{other}` (expected json or css)")),
+        }
+    }
+}
+
+/// Options of the `pack` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PackOptions {
+    /// Largest width and height of an atlas.
+    pub max_size: u32,
+    /// Empty pixels between neighbouring sprites.
+    pub padding: u32,
+    /// Round atlas sizes up to powers of two.
+    pub power_of_two: bool,
+}
+
+/// A sprite to pack.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sprite {
+    /// Name the sprite is listed under in the map.
+    pub name: String,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+}
+
+/// Where a sprite ended up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    /// Index of the atlas.
+    pub atlas: usize,
+    /// Left edge in the atlas.
+    pub x: u32,
+    /// Top edge in the atlas.
+    pub y: u32,
+}
+
+/// Result of [`pack`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packing {
+    /// Width and height of each atlas.
+    pub atlases: Vec<(u32, u32)>,
+    /// Placement of each sprite, in input order.
+    pub placements: Vec<Placement>,
+}
+
+/// A rectangle in an atlas, padding included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Area {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+}
+
+impl Area {
+    fn contains(&self, other: &Area) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.w <= self.x + self.w
+            && other.y + other.h <= self.y + self.h
+    }
+
+    fn intersects(&self, other: &Area) -> bool {
+        self.x < other.x + other.w
+            && other.x < self.x + self.w
+            && self.y < other.y + other.h
+            && other.y < self.y + self.h
+    }
+}
+
+/// Free space of one atlas as maximal, possibly overlapping rectangles (the MaxRects scheme).
+struct Bin {
+    free: Vec<Area>,
+}
+
+impl Bin {
+    fn new(side: u32) -> Self {
+        Self { free: vec![Area { x: 0, y: 0, w: side, h: side }] }
+    }
+
+    /// The spot for a `w`x`h` rectangle leaving the least space on its shorter side
+    /// ("best short side fit"), ties going to the top left.
+    fn find(&self, w: u32, h: u32) -> Option<Area> {
+        self.free
+            .iter()
+            .filter(|f| f.w >= w && f.h >= h)
+            .min_by_key(|f| {
+                let (dw, dh) = (f.w - w, f.h - h);
+                (dw.min(dh), dw.max(dh), f.y, f.x)
+            })
+            .map(|f| Area { x: f.x, y: f.y, w, h })
+    }
+
+    fn place(&mut self, used: Area) {
+        let mut free = Vec::with_capacity(self.free.len() + 4);
+        for f in self.free.drain(..) {
+            if !f.intersects(&used) {
+                free.push(f);
+                continue;
+            }
+            // Keep the parts of `f` left, right, above and below the used area.
+            if used.x > f.x {
+                free.push(Area { w: used.x - f.x, ..f });
+            }
+            if used.x + used.w < f.x + f.w {
+                free.push(Area { x: used.x + used.w, w: f.x + f.w - used.x - used.w, ..f });
+            }
+            if used.y > f.y {
+                free.push(Area { h: used.y - f.y, ..f });
+            }
+            if used.y + used.h < f.y + f.h {
+                free.push(Area { y: used.y + used.h, h: f.y + f.h - used.y - used.h, ..f });
+            }
+        }
+        // Drop rectangles lying inside others; of identical ones, keep the first.
+        let redundant: Vec<bool> = (0..free.len())
+            .map(|i| (0..free.len()).any(|j| i != j && free[j].contains(&free[i]) && (free[i] != free[j] || j < i)))
+            .collect();
+        self.free = free.into_iter().zip(redundant).filter(|(_, r)| !r).map(|(f, _)| f).collect();
+    }
+}
+
+/// Packs `sprites` into as few atlases of at most `max_size` pixels per side as this greedy
+/// scheme manages.
+///
+/// Sprites are placed largest first with MaxRects best short side fit; whatever does not fit
+/// into an atlas moves on to the next one. Atlases are trimmed to the area in use, then
+/// rounded up to powers of two if requested.
+///
+/// # Errors
+/// Returns [`AppError::Pack`] for a sprite larger than `max_size`, or with `power_of_two` for a
+/// `max_size` that is not a power of two.
+pub fn pack(sprites: &[Sprite], opts: &PackOptions) -> Result<Packing, AppError> {
+    if opts.power_of_two && !opts.max_size.is_power_of_two() {
+        return Err(AppError::Pack(format!("--max-size {} is not a power of two", opts.max_size)));
+    }
+    if let Some(s) = sprites.iter().find(|s| s.width > opts.max_size || s.height > opts.max_size) {
+        return Err(AppError::Pack(format!(
+            "{} is {}x{}, larger than --max-size {}",
+            s.name, s.width, s.height, opts.max_size
+        )));
+    }
+
+    // Every sprite reserves `padding` to its right and bottom; so does the bin, which keeps
+    // the padding from being required at the atlas edges.
+    let side = opts.max_size + opts.padding;
+    let mut order: Vec<usize> = (0..sprites.len()).collect();
+    order.sort_by_key(|&i| {
+        let s = &sprites[i];
+        std::cmp::Reverse((s.width.max(s.height), s.width * s.height))
+    });
+
+    let mut placements = vec![Placement { atlas: 0, x: 0, y: 0 }; sprites.len()];
+    let mut atlases = Vec::new();
+    while !order.is_empty() {
+        let atlas = atlases.len();
+        let mut bin = Bin::new(side);
+        let (mut width, mut height) = (0, 0);
+        order.retain(|&i| {
+            let s = &sprites[i];
+            let Some(area) = bin.find(s.width + opts.padding, s.height + opts.padding) else {
+                return true;
+            };
+            bin.place(area);
+            placements[i] = Placement { atlas, x: area.x, y: area.y };
+            (width, height) = (width.max(area.x + s.width), height.max(area.y + s.height));
+            false
+        });
+        if opts.power_of_two {
+            (width, height) = (width.next_power_of_two(), height.next_power_of_two());
+        }
+        atlases.push((width, height));
+    }
+    Ok(Packing { atlases, placements })
+}
+
+/// Draws `images` at their placements; returns one image per atlas.
+///
+/// # Panics
+/// Panics if `images` does not match the sprites `packing` was computed for.
+pub fn render(images: &[RgbaImage], packing: &Packing) -> Vec<RgbaImage> {
+    let mut atlases: Vec<RgbaImage> = packing.atlases.iter().map(|&(w, h)| RgbaImage::new(w, h)).collect();
+    for (img, p) in images.iter().zip(&packing.placements) {
+        image::imageops::replace(&mut atlases[p.atlas], img, p.x as i64, p.y as i64);
+    }
+    atlases
+}
+
+/// An atlas image in the map.
+#[derive(Debug, Clone, Serialize)]
+struct AtlasEntry<'a> {
+    file: &'a str,
+    width: u32,
+    height: u32,
+}
+
+/// A sprite in the map.
+#[derive(Debug, Clone, Serialize)]
+struct SpriteEntry<'a> {
+    name: &'a str,
+    atlas: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// JSON form of the map.
+#[derive(Debug, Clone, Serialize)]
+struct AtlasMap<'a> {
+    atlases: Vec<AtlasEntry<'a>>,
+    sprites: Vec<SpriteEntry<'a>>,
+}
+
+/// Renders the coordinate map of `packing`, `files` naming the atlas images as referenced from
+/// the map.
+pub fn map(sprites: &[Sprite], packing: &Packing, files: &[String], format: MapFormat) -> String {
+    match format {
+        MapFormat::Json => {
+            let map = AtlasMap {
+                atlases: packing
+                    .atlases
+                    .iter()
+                    .zip(files)
+                    .map(|(&(width, height), file)| AtlasEntry { file, width, height })
+                    .collect(),
+                sprites: sprites
+                    .iter()
+                    .zip(&packing.placements)
+                    .map(|(s, p)| SpriteEntry {
+                        name: &s.name,
+                        atlas: p.atlas,
+                        x: p.x,
+                        y: p.y,
+                        width: s.width,
+                        height: s.height,
+                    })
+                    .collect(),
+            };
+            serde_json::to_string_pretty(&map).expect("map serializes") + "\n"
+        }
+        MapFormat::Css => {
+            let mut out = String::new();
+            for (s, p) in sprites.iter().zip(&packing.placements) {
+                let _ = writeln!(
+                    out,
+                    ".sprite-{} {{ background: url({}) {}px {}px; width: {}px; height: {}px; }}",
+                    css_identifier(&s.name),
+                    files[p.atlas],
+                    -i64::from(p.x),
+                    -i64::from(p.y),
+                    s.width,
+                    s.height
+                );
+            }
+            out
+        }
+    }
+}
+
+/// `name` without its extension, with everything but ASCII letters, digits, `-` and `_`
+/// replaced by `-`.
+fn css_identifier(name: &str) -> String {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    stem.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprites(sizes: &[(u32, u32)]) -> Vec<Sprite> {
+        let sprite = |(i, &(width, height)): (usize, &(u32, u32))| Sprite { name: format!("s{i}.png"), width, height };
+        sizes.iter().enumerate().map(sprite).collect()
+    }
+
+    fn assert_valid(sprites: &[Sprite], packing: &Packing, padding: u32) {
+        for (i, (a, pa)) in sprites.iter().zip(&packing.placements).enumerate() {
+            let (w, h) = packing.atlases[pa.atlas];
+            assert!(pa.x + a.width <= w && pa.y + a.height <= h, "{} outside its atlas", a.name);
+            for (b, pb) in sprites.iter().zip(&packing.placements).skip(i + 1) {
+                let ra = Area { x: pa.x, y: pa.y, w: a.width + padding, h: a.height + padding };
+                let rb = Area { x: pb.x, y: pb.y, w: b.width + padding, h: b.height + padding };
+                assert!(pa.atlas != pb.atlas || !ra.intersects(&rb), "{} and {} overlap", a.name, b.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_packs_without_overlap() {
+        let sprites = sprites(&[(30, 20), (64, 64), (10, 50), (25, 25), (40, 12), (7, 7), (64, 10), (20, 33)]);
+        let opts = PackOptions { max_size: 128, padding: 2, power_of_two: false };
+        let packing = pack(&sprites, &opts).unwrap();
+        assert_eq!(packing.atlases.len(), 1);
+        assert_valid(&sprites, &packing, 2);
+        // The largest sprite goes first, into the top left corner.
+        assert_eq!(packing.placements[1], Placement { atlas: 0, x: 0, y: 0 });
+    }
+
+    #[test]
+    fn test_spills_into_more_atlases() {
+        let sprites = sprites(&[(60, 60); 5]);
+        let opts = PackOptions { max_size: 128, padding: 4, power_of_two: true };
+        let packing = pack(&sprites, &opts).unwrap();
+        // Padding only goes between sprites, so four fit into one atlas.
+        assert_eq!(packing.atlases, vec![(128, 128), (64, 64)]);
+        assert_valid(&sprites, &packing, 4);
+    }
+
+    #[test]
+    fn test_rejects_oversized_sprites() {
+        let opts = PackOptions { max_size: 64, padding: 0, power_of_two: false };
+        assert!(matches!(pack(&sprites(&[(65, 1)]), &opts), Err(AppError::Pack(_))));
+        let opts = PackOptions { max_size: 100, padding: 0, power_of_two: true };
+        assert!(matches!(pack(&sprites(&[(1, 1)]), &opts), Err(AppError::Pack(_))));
+    }
+
+    #[test]
+    fn test_css_map() {
+        let sprites = vec![Sprite { name: "hero idle.png".into(), width: 16, height: 24 }];
+        let packing = Packing { atlases: vec![(40, 24)], placements: vec![Placement { atlas: 0, x: 24, y: 0 }] };
+        assert_eq!(
+            map(&sprites, &packing, &["atlas.png".into()], MapFormat::Css),
+            ".sprite-hero-idle { background: url(atlas.png) -24px 0px; width: 16px; height: 24px; }\n"
+        );
+    }
+}
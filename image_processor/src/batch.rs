@@ -0,0 +1,102 @@
+use crate::error::AppError;
+use crate::input::{DownloadLimits, InputSource};
+use crate::output::{OutputTarget, is_image_path};
+#[cfg(feature = "s3")]
+use crate::s3::S3Client;
+
+/// A single input/output pair processed by one run of the plugin chain.
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// Image to read.
+    pub input: InputSource,
+    /// Where to write the result.
+    pub output: OutputTarget,
+}
+
+/// Expands the CLI input and output into the list of jobs to run.
+///
+/// A local directory or an S3 key prefix as input turns the run into a batch:
+/// every image below it is processed, and the output must then be a directory
+/// or key prefix that receives results under the same relative names. Listing a key prefix is
+/// subject to `limits`.
+pub fn expand(input: &InputSource, output: &OutputTarget, limits: &DownloadLimits) -> Result<Vec<Job>, AppError> {
+    let Some(names) = list(input, limits)? else {
+        return Ok(vec![Job { input: input.clone(), output: output.clone() }]);
+    };
+
+    if let Some(dir) = output.as_path() {
+        if dir.is_file() {
+            return Err(AppError::BatchOutput(dir.display().to_string()));
+        }
+        std::fs::create_dir_all(dir)?;
+    }
+
+    tracing::info!(count = names.len(), input = input.to_string(), "batch input expanded");
+
+    Ok(names
+        .iter()
+        .map(|name| Job { input: join_input(input, name), output: join_output(output, name) })
+        .collect())
+}
+
+/// Lists the images an input stands for: every image below a directory or key prefix, in
+/// name order, or just the input itself.
+pub fn inputs(input: &InputSource, limits: &DownloadLimits) -> Result<Vec<InputSource>, AppError> {
+    Ok(match list(input, limits)? {
+        Some(names) => names.iter().map(|name| join_input(input, name)).collect(),
+        None => vec![input.clone()],
+    })
+}
+
+/// Sorted names of the images below a directory or key prefix, or `None` for a single image.
+#[cfg_attr(not(feature = "s3"), allow(unused_variables))]
+fn list(input: &InputSource, limits: &DownloadLimits) -> Result<Option<Vec<String>>, AppError> {
+    let mut names = match input {
+        InputSource::File(dir) if dir.is_dir() => {
+            let mut names = Vec::new();
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_file() && is_image_path(&path) {
+                    names.push(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+                }
+            }
+            names
+        }
+        #[cfg(feature = "s3")]
+        InputSource::S3(prefix) if prefix.is_prefix() => S3Client::from_env(limits)?
+            .list_objects(prefix)?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix(&prefix.key).map(str::to_string))
+            .filter(|name| !name.is_empty() && is_image_path(std::path::Path::new(name)))
+            .collect(),
+        _ => return Ok(None),
+    };
+    names.sort();
+    Ok(Some(names))
+}
+
+fn join_input(base: &InputSource, name: &str) -> InputSource {
+    match base {
+        InputSource::File(dir) => InputSource::File(dir.join(name)),
+        #[cfg(feature = "s3")]
+        InputSource::S3(prefix) => InputSource::S3(prefix.join(name)),
+        InputSource::Url(_) => unreachable!("URLs are never expanded into batches"),
+    }
+}
+
+fn join_output(base: &OutputTarget, name: &str) -> OutputTarget {
+    match base {
+        OutputTarget::File(dir) => {
+            let path = dir.join(name);
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            OutputTarget::File(path)
+        }
+        #[cfg(feature = "s3")]
+        OutputTarget::S3(prefix) => {
+            let prefix = if prefix.is_prefix() { prefix.clone() } else { prefix.join("/") };
+            OutputTarget::S3(prefix.join(name))
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use image::DynamicImage;
+use image::imageops::FilterType;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::error::AppError;
+use crate::input::InputSource;
+use crate::run_record::{LOG_FILE, RECORD_FILE, RunRecord};
+
+/// Name of the entry holding the URL or S3 location of a remote input.
+const INPUT_REFERENCE: &str = "input-reference.txt";
+
+/// Block size in pixels used when redacting the input image.
+const REDACT_BLOCK: u32 = 16;
+
+/// Controls how the input image is included in a bug report.
+#[derive(Debug, Clone, Default)]
+pub struct BugReportOptions {
+    /// Downscale the input so that its longest side is at most this many pixels.
+    pub downscale: Option<u32>,
+    /// Replace the input with a coarse mosaic that keeps dimensions and rough
+    /// colors but hides the content.
+    pub redact: bool,
+    /// Leave the input image out of the report entirely.
+    pub skip_input: bool,
+}
+
+/// Packages the run recorded in `run_dir` into a gzip-compressed tarball at `out`.
+///
+/// The archive contains the run record (host and plugin identity, command line,
+/// outcome), the captured log, the params used and the input image, processed
+/// according to `opts`. Remote inputs are not fetched again; their URL or S3 location is
+/// recorded instead.
+pub fn write_bug_report(run_dir: &Path, out: &Path, opts: &BugReportOptions) -> Result<RunRecord, AppError> {
+    let record = RunRecord::load(run_dir)?;
+
+    let file = File::create(out)?;
+    let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    tar.append_path_with_name(run_dir.join(RECORD_FILE), RECORD_FILE)?;
+    let log_path = run_dir.join(LOG_FILE);
+    if log_path.exists() {
+        tar.append_path_with_name(&log_path, LOG_FILE)?;
+    }
+
+    if let Some(params) = &record.params {
+        append_bytes(&mut tar, "params.txt", params.as_bytes())?;
+    }
+
+    if !opts.skip_input {
+        append_input(&mut tar, &record, opts)?;
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(record)
+}
+
+fn append_input<W: std::io::Write>(
+    tar: &mut tar::Builder<W>,
+    record: &RunRecord,
+    opts: &BugReportOptions,
+) -> Result<(), AppError> {
+    let input = match record.input.parse() {
+        Ok(InputSource::File(path)) => path,
+        _ => return append_bytes(tar, INPUT_REFERENCE, format!("{}\n", record.input).as_bytes()),
+    };
+    let input = input.as_path();
+    if !input.exists() {
+        tracing::warn!(input = record.input, "input no longer exists, not included in report");
+        return Ok(());
+    }
+
+    if opts.downscale.is_none() && !opts.redact {
+        let name = match input.extension() {
+            Some(ext) => format!("input.{}", ext.to_string_lossy()),
+            None => "input".to_string(),
+        };
+        tar.append_path_with_name(input, name)?;
+        return Ok(());
+    }
+
+    let mut img = image::open(input)?;
+    if let Some(max_side) = opts.downscale
+        && (img.width() > max_side || img.height() > max_side)
+    {
+        img = img.resize(max_side, max_side, FilterType::Triangle);
+    }
+    if opts.redact {
+        img = redact(&img);
+    }
+
+    let mut encoded = Cursor::new(Vec::new());
+    img.write_to(&mut encoded, image::ImageFormat::Png)?;
+    append_bytes(tar, "input.png", encoded.get_ref())
+}
+
+/// Pixelates the image with large blocks so the content is no longer recognizable.
+fn redact(img: &DynamicImage) -> DynamicImage {
+    let (w, h) = (img.width(), img.height());
+    let small_w = w.div_ceil(REDACT_BLOCK).max(1);
+    let small_h = h.div_ceil(REDACT_BLOCK).max(1);
+    img.resize_exact(small_w, small_h, FilterType::Triangle)
+        .resize_exact(w, h, FilterType::Nearest)
+}
+
+fn append_bytes<W: std::io::Write>(tar: &mut tar::Builder<W>, name: &str, bytes: &[u8]) -> Result<(), AppError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    );
+    header.set_cksum();
+    tar.append_data(&mut header, name, bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    /// Names and contents of the entries of a `.tar.gz` archive.
+    fn read_archive(path: &Path) -> Vec<(String, Vec<u8>)> {
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(path).unwrap()));
+        let mut entries = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().display().to_string();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            entries.push((name, data));
+        }
+        entries
+    }
+
+    #[test]
+    fn test_write_bug_report() {
+        let dir = std::env::temp_dir().join(format!("bugreport-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("photo.png");
+        image::RgbaImage::from_pixel(40, 20, image::Rgba([10, 20, 30, 255])).save(&input).unwrap();
+        std::fs::write(dir.join(LOG_FILE), "ERROR plugin failed\n").unwrap();
+        let record = RunRecord {
+            input: input.display().to_string(),
+            params: Some("radius = 3\n".to_string()),
+            ..RunRecord::default()
+        };
+        record.save(&dir).unwrap();
+
+        let out = dir.join("report.tar.gz");
+        let opts = BugReportOptions { downscale: Some(10), ..Default::default() };
+        write_bug_report(&dir, &out, &opts).unwrap();
+        let entries = read_archive(&out);
+        let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, [RECORD_FILE, LOG_FILE, "params.txt", "input.png"]);
+        assert_eq!(entries[1].1, b"ERROR plugin failed\n");
+        assert_eq!(entries[2].1, b"radius = 3\n");
+        let img = image::load_from_memory(&entries[3].1).unwrap();
+        assert_eq!((img.width(), img.height()), (10, 5));
+
+        // A remote input is recorded as a reference, and skipping the input leaves it out.
+        let record = RunRecord { input: "https://example.com/photo.png".to_string(), ..record };
+        record.save(&dir).unwrap();
+        write_bug_report(&dir, &out, &BugReportOptions::default()).unwrap();
+        let entries = read_archive(&out);
+        let (name, data) = entries.last().unwrap();
+        assert_eq!((name.as_str(), data.as_slice()), (INPUT_REFERENCE, b"https://example.com/photo.png\n".as_slice()));
+        write_bug_report(&dir, &out, &BugReportOptions { skip_input: true, ..Default::default() }).unwrap();
+        assert_eq!(read_archive(&out).len(), 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
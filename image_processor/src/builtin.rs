@@ -0,0 +1,143 @@
+use plugin_sdk::Capabilities;
+
+use crate::error::AppError;
+use crate::pipeline::PixelBuffer;
+
+/// A step implemented by the host itself; used in place of a plugin library of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// Separable Gaussian blur on the GPU, with the same params as `blur_plugin`.
+    #[cfg(feature = "gpu")]
+    GpuBlur,
+}
+
+impl Builtin {
+    /// Returns the built-in step called `name`, if this build has one.
+    pub fn lookup(name: &str) -> Option<Self> {
+        match name {
+            #[cfg(feature = "gpu")]
+            "gpu_blur" => Some(Self::GpuBlur),
+            _ => None,
+        }
+    }
+
+    /// Returns the manifest of the built-in, in the same format plugins embed.
+    pub fn manifest(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gpu")]
+            Self::GpuBlur => {
+                r#"name = "gpu_blur"
+version = "0.1.0"
+description = "Separable Gaussian blur computed on the GPU"
+
+[defaults]
+radius = 3
+iterations = 1
+"#
+            }
+        }
+    }
+
+    /// Returns what the built-in supports when run with the resolved canonical `params`.
+    #[cfg_attr(not(feature = "gpu"), allow(unused_variables))]
+    pub fn capabilities(self, params: &str) -> Capabilities {
+        match self {
+            #[cfg(feature = "gpu")]
+            Self::GpuBlur => {
+                let mut caps = Capabilities {
+                    pixel_formats: plugin_sdk::FORMAT_MASK_RGBA8 | plugin_sdk::FORMAT_MASK_RGBA32F,
+                    ..Capabilities::default()
+                };
+                // The shader renormalizes at buffer edges like `blur_plugin`, so it is local too.
+                if let Ok(params) = plugin_sdk::params_from_str::<BlurParams>(params) {
+                    caps.set_local(params.radius.saturating_mul(params.iterations));
+                }
+                caps
+            }
+        }
+    }
+
+    /// Acquires whatever the built-in needs before running, so failures surface at load time.
+    pub fn prepare(self) -> Result<(), AppError> {
+        match self {
+            #[cfg(feature = "gpu")]
+            Self::GpuBlur => crate::gpu::shared().map(|_| ()),
+        }
+    }
+
+    /// Runs the built-in over `data` with the resolved canonical `params`; returns a status
+    /// code with the same meaning as a plugin's.
+    #[cfg_attr(not(feature = "gpu"), allow(unused_variables))]
+    pub fn run(self, params: &str, width: u32, height: u32, data: &mut PixelBuffer) -> u32 {
+        match self {
+            #[cfg(feature = "gpu")]
+            Self::GpuBlur => status(self, gpu_blur(params, width, height, data)),
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+fn status(builtin: Builtin, result: Result<(), AppError>) -> u32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            tracing::error!(builtin = ?builtin, error = e.to_string(), "built-in step failed");
+            1
+        }
+    }
+}
+
+#[cfg(feature = "gpu")]
+#[derive(serde::Deserialize)]
+struct BlurParams {
+    radius: u32,
+    iterations: u32,
+    #[serde(default)]
+    sigma: Option<f32>,
+}
+
+#[cfg(feature = "gpu")]
+fn gpu_blur(params: &str, width: u32, height: u32, data: &mut PixelBuffer) -> Result<(), AppError> {
+    use plugin_sdk::Sample;
+
+    let params: BlurParams =
+        plugin_sdk::params_from_str(params).map_err(|e| AppError::InvalidParams(e.to_string()))?;
+    if params.radius == 0 {
+        return Ok(());
+    }
+    let sigma = params.sigma.filter(|s| *s > 0.0).unwrap_or(params.radius as f32 / 2.0);
+    let r = params.radius as i64;
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+
+    let gpu = crate::gpu::shared()?;
+    match data {
+        PixelBuffer::Rgba32F(values) => gpu.gaussian_blur(width, height, values, &kernel, params.iterations),
+        PixelBuffer::Rgba8(bytes) => {
+            let mut values: Vec<f32> = bytes.iter().map(|b| b.to_f32()).collect();
+            gpu.gaussian_blur(width, height, &mut values, &kernel, params.iterations)?;
+            for (b, v) in bytes.iter_mut().zip(values) {
+                *b = u8::from_f32(v);
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(Builtin::lookup("blur_plugin"), None);
+
+        #[cfg(feature = "gpu")]
+        {
+            let gpu_blur = Builtin::lookup("gpu_blur").unwrap();
+            let manifest = crate::manifest::PluginManifest::parse(gpu_blur.manifest()).unwrap();
+            assert_eq!(manifest.defaults["radius"].as_integer(), Some(3));
+            let caps = gpu_blur.capabilities(r#"{"radius": 3, "iterations": 2}"#);
+            assert_eq!(caps.local_halo(), Some(6));
+        }
+    }
+}
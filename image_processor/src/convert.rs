@@ -0,0 +1,443 @@
+use image::{ColorType, DynamicImage, Rgba32FImage, RgbaImage};
+use std::str::FromStr;
+
+use crate::pool::BufferPool;
+
+/// 8x8 Bayer threshold matrix with values in `0..64`.
+pub const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Dithering applied when reducing high bit depth images to 8 bits per channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DitherMode {
+    /// Plain rounding.
+    #[default]
+    None,
+    /// 8x8 Bayer ordered dithering.
+    Ordered,
+    /// Floyd–Steinberg error diffusion.
+    FloydSteinberg,
+}
+
+impl FromStr for DitherMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "ordered" => Ok(Self::Ordered),
+            "floyd-steinberg" => Ok(Self::FloydSteinberg),
+            other => Err(format!("unknown dither mode `{other}` (expected none, ordered or floyd-steinberg)")),
+        }
+    }
+}
+
+/// Color space the plugin chain operates in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WorkingSpace {
+    /// Gamma-encoded sRGB, 8 bits per channel (the historical behavior).
+    #[default]
+    Srgb,
+    /// Linear light, `f32` per channel.
+    Linear,
+}
+
+impl FromStr for WorkingSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" => Ok(Self::Srgb),
+            "linear" => Ok(Self::Linear),
+            other => Err(format!("unknown working space `{other}` (expected srgb or linear)")),
+        }
+    }
+}
+
+/// Alpha convention of the buffer handed to the plugin chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    /// Color channels are independent of alpha (how images are stored on disk).
+    #[default]
+    Straight,
+    /// Color channels are multiplied by alpha before the chain and divided out afterwards.
+    Premultiplied,
+}
+
+impl AlphaMode {
+    /// Returns the matching `plugin_sdk::ALPHA_*` constant.
+    pub fn to_ffi(self) -> u32 {
+        match self {
+            Self::Straight => plugin_sdk::ALPHA_STRAIGHT,
+            Self::Premultiplied => plugin_sdk::ALPHA_PREMULTIPLIED,
+        }
+    }
+}
+
+impl FromStr for AlphaMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "straight" => Ok(Self::Straight),
+            "premultiplied" => Ok(Self::Premultiplied),
+            other => Err(format!("unknown alpha mode `{other}` (expected straight or premultiplied)")),
+        }
+    }
+}
+
+/// Multiplies the color channels of RGBA8 pixels by their alpha, rounding.
+pub fn premultiply_rgba8(data: &mut [u8]) {
+    for px in data.chunks_exact_mut(4) {
+        let a = px[3] as u32;
+        for v in &mut px[..3] {
+            *v = ((*v as u32 * a + 127) / 255) as u8;
+        }
+    }
+}
+
+/// Divides the color channels of premultiplied RGBA8 pixels by their alpha.
+///
+/// Fully transparent pixels become transparent black.
+pub fn unpremultiply_rgba8(data: &mut [u8]) {
+    for px in data.chunks_exact_mut(4) {
+        let a = px[3] as u32;
+        for v in &mut px[..3] {
+            *v = (*v as u32 * 255 + a / 2).checked_div(a).map_or(0, |q| q.min(255) as u8);
+        }
+    }
+}
+
+/// Multiplies the color channels of RGBA32F pixels by their alpha.
+pub fn premultiply_rgba32f(data: &mut [f32]) {
+    for px in data.chunks_exact_mut(4) {
+        let a = px[3];
+        for v in &mut px[..3] {
+            *v *= a;
+        }
+    }
+}
+
+/// Divides the color channels of premultiplied RGBA32F pixels by their alpha.
+///
+/// Pixels with zero (or negative) alpha become transparent black.
+pub fn unpremultiply_rgba32f(data: &mut [f32]) {
+    for px in data.chunks_exact_mut(4) {
+        let a = px[3];
+        for v in &mut px[..3] {
+            *v = if a > 0.0 { *v / a } else { 0.0 };
+        }
+    }
+}
+
+/// Decodes an sRGB transfer-encoded value in `[0, 1]` to linear light.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear-light value with the sRGB transfer function; clamps to `[0, 1]`.
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts an image to straight-alpha linear RGBA32F, keeping the full precision of the input.
+///
+/// Only the color channels are decoded; alpha is already linear. Float images (OpenEXR,
+/// Radiance HDR) already hold linear light and are passed through, values above 1 included.
+pub fn to_linear_rgba32f(img: &DynamicImage) -> Vec<f32> {
+    let mut data = img.to_rgba32f().into_raw();
+    if matches!(img, DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)) {
+        return data;
+    }
+    for px in data.chunks_exact_mut(4) {
+        for v in &mut px[..3] {
+            *v = srgb_to_linear(*v);
+        }
+    }
+    data
+}
+
+/// Converts sRGB RGBA8 to linear RGBA32F.
+pub fn rgba8_to_linear(data: &[u8]) -> Vec<f32> {
+    let mut out = Vec::with_capacity(data.len());
+    rgba8_to_linear_into(data, &mut out);
+    out
+}
+
+/// Appends the linear RGBA32F form of sRGB RGBA8 `data` to `out`.
+pub fn rgba8_to_linear_into(data: &[u8], out: &mut Vec<f32>) {
+    let lut: [f32; 256] = std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0));
+    out.extend(
+        data.chunks_exact(4)
+            .flat_map(|px| [lut[px[0] as usize], lut[px[1] as usize], lut[px[2] as usize], px[3] as f32 / 255.0]),
+    );
+}
+
+/// Converts linear RGBA32F to sRGB RGBA8 with plain rounding.
+pub fn linear_to_rgba8(data: &[f32]) -> Vec<u8> {
+    data.chunks_exact(4)
+        .flat_map(|px| {
+            let [r, g, b, a] = [px[0], px[1], px[2], px[3]];
+            [linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a.clamp(0.0, 1.0)].map(|v| quantize(v * 255.0))
+        })
+        .collect()
+}
+
+/// Encodes a linear RGBA32F buffer to sRGB in place, keeping the float precision.
+///
+/// # Panics
+/// Panics if `data` is not exactly `width * height * 4` values long.
+pub fn linear_to_srgb32f(mut data: Vec<f32>, width: u32, height: u32) -> Rgba32FImage {
+    for px in data.chunks_exact_mut(4) {
+        for v in &mut px[..3] {
+            *v = linear_to_srgb(*v);
+        }
+        px[3] = px[3].clamp(0.0, 1.0);
+    }
+    Rgba32FImage::from_raw(width, height, data).expect("buffer matches dimensions")
+}
+
+/// Encodes a linear RGBA32F buffer back to sRGB RGBA8, dithering per `mode`.
+///
+/// # Panics
+/// Panics if `data` is not exactly `width * height * 4` values long.
+pub fn linear_to_rgba8_dithered(data: Vec<f32>, width: u32, height: u32, mode: DitherMode) -> RgbaImage {
+    to_rgba8_dithered(&DynamicImage::ImageRgba32F(linear_to_srgb32f(data, width, height)), mode)
+}
+
+/// Returns `true` if images of this color type store more than 8 bits per channel.
+pub fn is_high_bit_depth(color: ColorType) -> bool {
+    color.bytes_per_pixel() / color.channel_count() > 1
+}
+
+/// Like [`to_rgba8_dithered`], but takes ownership so an RGBA8 input is returned without a copy.
+///
+/// Other 8-bit inputs are expanded into a buffer from `pool`, and the input's storage is
+/// returned to it.
+pub fn into_rgba8_dithered(img: DynamicImage, mode: DitherMode, pool: &mut BufferPool) -> RgbaImage {
+    let (width, height) = (img.width(), img.height());
+    let len = width as usize * height as usize * 4;
+    let expanded = match &img {
+        DynamicImage::ImageRgba8(_) => {
+            let DynamicImage::ImageRgba8(rgba) = img else { unreachable!("matched above") };
+            return rgba;
+        }
+        DynamicImage::ImageRgb8(src) => {
+            let mut out = pool.take_bytes(len);
+            out.extend(src.pixels().flat_map(|p| [p[0], p[1], p[2], 255]));
+            out
+        }
+        DynamicImage::ImageLuma8(src) => {
+            let mut out = pool.take_bytes(len);
+            out.extend(src.pixels().flat_map(|p| [p[0], p[0], p[0], 255]));
+            out
+        }
+        DynamicImage::ImageLumaA8(src) => {
+            let mut out = pool.take_bytes(len);
+            out.extend(src.pixels().flat_map(|p| [p[0], p[0], p[0], p[1]]));
+            out
+        }
+        other => to_rgba8_dithered(other, mode).into_raw(),
+    };
+    pool.put_image(img);
+    RgbaImage::from_raw(width, height, expanded).expect("buffer matches dimensions")
+}
+
+/// Like [`to_linear_rgba32f`], but takes ownership and works in buffers from `pool`.
+pub fn into_linear_rgba32f(img: DynamicImage, pool: &mut BufferPool) -> Vec<f32> {
+    if is_high_bit_depth(img.color()) {
+        let data = to_linear_rgba32f(&img);
+        pool.put_image(img);
+        return data;
+    }
+    let rgba = into_rgba8_dithered(img, DitherMode::None, pool).into_raw();
+    let mut data = pool.take_floats(rgba.len());
+    rgba8_to_linear_into(&rgba, &mut data);
+    pool.put_bytes(rgba);
+    data
+}
+
+/// Converts an image to RGBA8, dithering if it has more than 8 bits per channel.
+pub fn to_rgba8_dithered(img: &DynamicImage, mode: DitherMode) -> RgbaImage {
+    if mode == DitherMode::None || !is_high_bit_depth(img.color()) {
+        return img.to_rgba8();
+    }
+
+    let src = img.to_rgba32f();
+    let (w, h) = src.dimensions();
+    let mut out = RgbaImage::new(w, h);
+
+    match mode {
+        DitherMode::None => unreachable!("handled above"),
+        DitherMode::Ordered => {
+            for (x, y, px) in src.enumerate_pixels() {
+                let threshold = (BAYER_8X8[(y % 8) as usize][(x % 8) as usize] as f32 + 0.5) / 64.0 - 0.5;
+                let q = px.0.map(|v| quantize(v * 255.0 + threshold));
+                out.put_pixel(x, y, image::Rgba(q));
+            }
+        }
+        DitherMode::FloydSteinberg => {
+            let (w, h) = (w as usize, h as usize);
+            let mut buf: Vec<f32> = src.into_raw().into_iter().map(|v| v * 255.0).collect();
+            for y in 0..h {
+                for x in 0..w {
+                    for c in 0..4 {
+                        let i = (y * w + x) * 4 + c;
+                        let old = buf[i];
+                        let new = quantize(old);
+                        buf[i] = new as f32;
+                        let err = old - new as f32;
+
+                        if x + 1 < w {
+                            buf[i + 4] += err * 7.0 / 16.0;
+                        }
+                        if y + 1 < h {
+                            let below = i + w * 4;
+                            if x > 0 {
+                                buf[below - 4] += err * 3.0 / 16.0;
+                            }
+                            buf[below] += err * 5.0 / 16.0;
+                            if x + 1 < w {
+                                buf[below + 4] += err / 16.0;
+                            }
+                        }
+                    }
+                }
+            }
+            let bytes = buf.into_iter().map(|v| v as u8).collect();
+            out = RgbaImage::from_raw(w as u32, h as u32, bytes).expect("buffer matches dimensions");
+        }
+    }
+
+    out
+}
+
+fn quantize(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn flat_16bit(value: u16) -> DynamicImage {
+        DynamicImage::ImageRgba16(ImageBuffer::from_pixel(16, 16, Rgba([value, value, value, 65535])))
+    }
+
+    #[test]
+    fn test_dither_preserves_mean() {
+        // 100.5 in 8-bit terms: plain rounding would give a flat 101 everywhere.
+        let img = flat_16bit((100.5f32 * 257.0) as u16);
+        for mode in [DitherMode::Ordered, DitherMode::FloydSteinberg] {
+            let out = to_rgba8_dithered(&img, mode);
+            let mean = out.pixels().map(|p| p[0] as f32).sum::<f32>() / 256.0;
+            assert!((mean - 100.5).abs() < 0.1, "{mode:?}: mean {mean}");
+            assert!(out.pixels().all(|p| p[0] == 100 || p[0] == 101));
+            assert!(out.pixels().all(|p| p[3] == 255));
+        }
+    }
+
+    #[test]
+    fn test_floyd_steinberg_gradient_has_no_bands() {
+        // A 16-bit ramp from 100 to 104 in 8-bit terms, taken through the chain's float format.
+        let (width, height) = (256, 32);
+        let level = |x: u32| 100.0 + 4.0 * x as f32 / (width - 1) as f32;
+        let ramp = ImageBuffer::from_fn(width, height, |x, _| {
+            let v = (level(x) * 257.0).round() as u16;
+            Rgba([v, v, v, 65535])
+        });
+        let linear = to_linear_rgba32f(&DynamicImage::ImageRgba16(ramp));
+
+        // Mean error of 8-column strips against the ramp; rounding leaves steps 64 columns wide.
+        let worst_strip = |out: &RgbaImage| {
+            let mut errors = vec![0.0f32; (width / 8) as usize];
+            for (x, _, px) in out.enumerate_pixels() {
+                errors[(x / 8) as usize] += (px[0] as f32 - level(x)) / (8 * height) as f32;
+            }
+            errors.into_iter().map(f32::abs).fold(0.0, f32::max)
+        };
+        let rounded = linear_to_rgba8_dithered(linear.clone(), width, height, DitherMode::None);
+        assert!(worst_strip(&rounded) > 0.3, "rounding should band");
+        let dithered = linear_to_rgba8_dithered(linear, width, height, DitherMode::FloydSteinberg);
+        let worst = worst_strip(&dithered);
+        assert!(worst < 0.05, "strip off the ramp by {worst}");
+    }
+
+    #[test]
+    fn test_srgb_linear_round_trip() {
+        let data: Vec<u8> = (0..=255).flat_map(|v| [v, v, v, v]).collect();
+        assert_eq!(linear_to_rgba8(&rgba8_to_linear(&data)), data);
+
+        // Mid-gray in sRGB is about 21% in linear light.
+        assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_linear_encode_clamps() {
+        let out = linear_to_rgba8_dithered(vec![2.0, -1.0, 0.5, 1.5], 1, 1, DitherMode::None);
+        assert_eq!(out.get_pixel(0, 0).0, [255, 0, 188, 255]);
+    }
+
+    #[test]
+    fn test_float_input_stays_linear() {
+        let hdr = DynamicImage::ImageRgb32F(ImageBuffer::from_pixel(1, 1, image::Rgb([4.0, 0.5, 0.0])));
+        assert_eq!(to_linear_rgba32f(&hdr), [4.0, 0.5, 0.0, 1.0]);
+
+        let deep = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(1, 1, Rgba([32768, 0, 65535, 65535])));
+        assert!((to_linear_rgba32f(&deep)[0] - srgb_to_linear(32768.0 / 65535.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_premultiply_round_trip() {
+        let mut data = vec![200, 100, 50, 128, 255, 255, 255, 0, 10, 20, 30, 255];
+        premultiply_rgba8(&mut data);
+        assert_eq!(data, [100, 50, 25, 128, 0, 0, 0, 0, 10, 20, 30, 255]);
+        unpremultiply_rgba8(&mut data);
+        assert_eq!(data, [199, 100, 50, 128, 0, 0, 0, 0, 10, 20, 30, 255]);
+
+        let mut float = vec![0.8, 0.4, 0.2, 0.5];
+        premultiply_rgba32f(&mut float);
+        assert_eq!(float, [0.4, 0.2, 0.1, 0.5]);
+        unpremultiply_rgba32f(&mut float);
+        assert_eq!(float, [0.8, 0.4, 0.2, 0.5]);
+    }
+
+    #[test]
+    fn test_8bit_input_unchanged() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([1, 2, 3, 4])));
+        assert_eq!(to_rgba8_dithered(&img, DitherMode::FloydSteinberg), img.to_rgba8());
+    }
+
+    #[test]
+    fn test_into_rgba8_reuses_buffer() {
+        let rgba = RgbaImage::from_pixel(4, 4, Rgba([1, 2, 3, 4]));
+        let ptr = rgba.as_ptr();
+        let mut pool = BufferPool::new();
+        let out = into_rgba8_dithered(DynamicImage::ImageRgba8(rgba), DitherMode::None, &mut pool);
+        assert_eq!(out.as_ptr(), ptr);
+
+        let rgb = DynamicImage::ImageRgb8(ImageBuffer::from_pixel(2, 2, image::Rgb([9, 8, 7])));
+        let expected = to_linear_rgba32f(&rgb);
+        let out = into_rgba8_dithered(rgb.clone(), DitherMode::None, &mut pool);
+        assert_eq!(out, rgb.to_rgba8());
+        assert_eq!(into_linear_rgba32f(rgb, &mut pool), expected);
+    }
+}
@@ -7,6 +7,10 @@ pub enum AppError {
     #[error("Input file does not exist: {0}")]
     MissingInput(String),
 
+    /// `--input2` and `--inputs` must be single images, but a directory was given.
+    #[error("Secondary input must be a single image: {0}")]
+    Input2Directory(String),
+
     /// Params file does not exist.
     #[error("Params file does not exist: {0}")]
     MissingParams(String),
@@ -23,10 +27,107 @@ pub enum AppError {
     #[error("Image error: {0}")]
     Image(#[from] image::ImageError),
 
+    /// Error occurred while reading a region of a TIFF image.
+    #[error("TIFF error: {0}")]
+    Tiff(#[from] tiff::TiffError),
+
+    /// The `--roi` rectangle does not fit into the image.
+    #[error("Region of interest {roi} is not inside the {width}x{height} image")]
+    RoiOutside {
+        /// The requested region.
+        roi: String,
+        /// Image width.
+        width: u32,
+        /// Image height.
+        height: u32,
+    },
+
     /// Error occurred while loading a dynamic plugin library.
     #[error("Plugin load error: {0}")]
     Plugin(#[from] libloading::Error),
 
+    /// Error occurred while downloading a remote input.
+    #[error("Download error: {0}")]
+    Download(#[from] ureq::Error),
+
+    /// Error reported by S3-compatible object storage.
+    #[error("S3 error: {0}")]
+    S3(String),
+
+    /// Batch output must be a directory or key prefix, but a file was given.
+    #[error("Batch output must be a directory: {0}")]
+    BatchOutput(String),
+
+    /// A plugin returned a non-zero status code.
+    #[error("Plugin {plugin} failed with code {code}")]
+    PluginFailed {
+        /// Name of the failing plugin.
+        plugin: String,
+        /// Status code returned by the plugin.
+        code: u32,
+    },
+
+    /// Some items of a batch run failed; each failure is logged separately.
+    #[error("{failed} of {total} batch items failed")]
+    BatchFailed {
+        /// Number of failed items.
+        failed: usize,
+        /// Total number of items in the batch.
+        total: usize,
+    },
+
+    /// The preview window could not be opened or updated.
+    #[error("Preview error: {0}")]
+    Preview(String),
+
+    /// No GPU device could be created, or a GPU operation failed.
+    #[error("GPU error: {0}")]
+    Gpu(String),
+
+    /// Watch mode needs a local input file.
+    #[error("Watch mode requires a local input file: {0}")]
+    WatchInput(String),
+
+    /// `--benchmark-matrix` needs a single input image and a local CSV file as output.
+    #[error("Benchmark matrix requires a single input image and a local output file: {0}")]
+    BenchmarkTarget(String),
+
+    /// `--tiles` writes a directory tree, so it needs a local output.
+    #[error("Tile pyramids require a local output file: {0}")]
+    TilesTarget(String),
+
+    /// A cargo invocation of the `bench` subcommand failed.
+    #[error("Benchmark run failed: {0}")]
+    BenchFailed(String),
+
+    /// The `stitch` subcommand could not assemble a panorama.
+    #[error("Stitching failed: {0}")]
+    Stitch(String),
+
+    /// The `pack` subcommand could not fit the sprites into atlases.
+    #[error("Packing failed: {0}")]
+    Pack(String),
+
+    /// The `assemble` subcommand could not build an animation from the frames.
+    #[error("Assembly failed: {0}")]
+    Assemble(String),
+
+    /// A camera RAW file could not be decoded.
+    #[error("RAW decode error: {0}")]
+    Raw(String),
+
+    /// An SVG document could not be parsed or rendered.
+    #[error("SVG error: {0}")]
+    Svg(String),
+
+    /// Params are not valid TOML.
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+
+    /// A plugin's embedded manifest could not be parsed.
+    #[error("Invalid plugin manifest: {0}")]
+    InvalidManifest(String),
+
     /// Params file contains invalid UTF-8 data.
     #[error("Invalid UTF-8 in params file")]
     InvalidParamsUtf8,
@@ -35,4 +136,12 @@ pub enum AppError {
     /// conversion into a C-compatible string required by the plugin FFI.
     #[error("Params contain NUL byte")]
     InvalidParamsNul,
+
+    /// No recorded run was found in the given state directory.
+    #[error("No recorded run found in: {0}")]
+    MissingLastRun(String),
+
+    /// The run record could not be serialized or parsed.
+    #[error("Run record error: {0}")]
+    RunRecord(String),
 }
@@ -0,0 +1,171 @@
+use std::sync::OnceLock;
+use wgpu::util::DeviceExt;
+
+use crate::error::AppError;
+
+const BLUR_SHADER: &str = include_str!("shaders/gaussian_blur.wgsl");
+
+/// Work group edge length, matching `@workgroup_size` in the shader.
+const WORKGROUP: u32 = 8;
+
+static SHARED: OnceLock<Result<GpuContext, String>> = OnceLock::new();
+
+/// The host's GPU device together with its compiled compute pipelines.
+pub struct GpuContext {
+    /// The wgpu device; its address is what plugins receive as `gpu_device`.
+    pub device: wgpu::Device,
+    /// Queue of `device`; its address is what plugins receive as `gpu_queue`.
+    pub queue: wgpu::Queue,
+    blur: wgpu::ComputePipeline,
+    max_buffer_size: u64,
+}
+
+/// Returns the process-wide GPU context, creating it on first use.
+///
+/// Adapter selection honours the usual `WGPU_BACKEND` and `WGPU_ADAPTER_NAME`
+/// environment variables. A failure is remembered, so later calls fail fast.
+pub fn shared() -> Result<&'static GpuContext, AppError> {
+    SHARED
+        .get_or_init(|| GpuContext::new().map_err(|e| e.to_string()))
+        .as_ref()
+        .map_err(|e| AppError::Gpu(e.clone()))
+}
+
+impl GpuContext {
+    fn new() -> Result<Self, AppError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .map_err(|e| AppError::Gpu(e.to_string()))?;
+
+        let info = adapter.get_info();
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("image_processor"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        }))
+        .map_err(|e| AppError::Gpu(e.to_string()))?;
+        tracing::info!(adapter = info.name, backend = ?info.backend, "gpu device ready");
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gaussian_blur"),
+            source: wgpu::ShaderSource::Wgsl(BLUR_SHADER.into()),
+        });
+        let blur = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("gaussian_blur"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let max_buffer_size = limits.max_storage_buffer_binding_size.min(limits.max_buffer_size);
+        Ok(Self { device, queue, blur, max_buffer_size })
+    }
+
+    /// Runs `iterations` separable Gaussian passes over straight RGBA `f32` pixels in place.
+    ///
+    /// `kernel` holds the `2 * radius + 1` unnormalized taps.
+    ///
+    /// # Panics
+    /// Panics if `data` is not exactly `width * height * 4` values long.
+    pub fn gaussian_blur(
+        &self,
+        width: u32,
+        height: u32,
+        data: &mut [f32],
+        kernel: &[f32],
+        iterations: u32,
+    ) -> Result<(), AppError> {
+        assert_eq!(data.len(), width as usize * height as usize * 4, "RGBA buffer length mismatch");
+        let size = std::mem::size_of_val(data) as u64;
+        if size > self.max_buffer_size {
+            return Err(AppError::Gpu(format!(
+                "image needs a {size} byte buffer, the device allows {}",
+                self.max_buffer_size
+            )));
+        }
+        if data.is_empty() || iterations == 0 {
+            return Ok(());
+        }
+
+        let device = &self.device;
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        let ping = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("blur_ping"),
+            contents: bytemuck::cast_slice(data),
+            usage: storage,
+        });
+        let pong = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("blur_pong"),
+            size,
+            usage: storage,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("blur_readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let taps = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("blur_kernel"),
+            contents: bytemuck::cast_slice(kernel),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let radius = (kernel.len() / 2) as u32;
+        let pass = |horizontal: bool, src: &wgpu::Buffer, dst: &wgpu::Buffer| {
+            let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("blur_params"),
+                contents: bytemuck::cast_slice(&[width, height, radius, horizontal as u32]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("blur_pass"),
+                layout: &self.blur.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: params.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 1, resource: taps.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 2, resource: src.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 3, resource: dst.as_entire_binding() },
+                ],
+            })
+        };
+        let passes = [pass(true, &ping, &pong), pass(false, &pong, &ping)];
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("blur") });
+        for _ in 0..iterations {
+            for bind_group in &passes {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                cpass.set_pipeline(&self.blur);
+                cpass.set_bind_group(0, bind_group, &[]);
+                cpass.dispatch_workgroups(width.div_ceil(WORKGROUP), height.div_ceil(WORKGROUP), 1);
+            }
+        }
+        encoder.copy_buffer_to_buffer(&ping, 0, &readback, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        readback.map_async(wgpu::MapMode::Read, .., move |r| {
+            let _ = tx.send(r);
+        });
+        device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|e| AppError::Gpu(e.to_string()))?;
+        rx.recv()
+            .map_err(|e| AppError::Gpu(e.to_string()))?
+            .map_err(|e| AppError::Gpu(e.to_string()))?;
+
+        {
+            let view = readback.get_mapped_range(..).map_err(|e| AppError::Gpu(e.to_string()))?;
+            data.copy_from_slice(bytemuck::cast_slice(&view));
+        }
+        readback.unmap();
+        Ok(())
+    }
+}
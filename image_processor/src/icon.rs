@@ -0,0 +1,131 @@
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ExtendedColorType, RgbaImage};
+
+use crate::convert::{premultiply_rgba8, unpremultiply_rgba8};
+use crate::error::AppError;
+use crate::png::{self, PngCompression};
+
+/// Default sizes of the `.ico`, from browser tabs up to the Windows large icon view.
+pub const ICO_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+/// Apple touch icon sizes: iPhone, iPad, iPad Pro and Retina iPhone.
+pub const TOUCH_SIZES: [u32; 4] = [120, 152, 167, 180];
+/// Size iOS looks for under the plain `apple-touch-icon.png` name.
+const DEFAULT_TOUCH_SIZE: u32 = 180;
+/// Blur radius of the unsharp mask, in output pixels.
+const SHARPEN_SIGMA: f32 = 0.6;
+/// Reduction at which sharpening reaches its full amount.
+const FULL_SHARPEN_RATIO: f32 = 16.0;
+
+/// Renders `img` as a `size` x `size` icon.
+///
+/// The image is scaled to fit and centred on a transparent square. Resampling and sharpening
+/// run on premultiplied pixels so that transparent surroundings do not bleed into the edges.
+pub fn render(img: &RgbaImage, size: u32, sharpen: f32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let scale = size as f64 / w.max(h).max(1) as f64;
+    let fit_w = ((w as f64 * scale).round() as u32).clamp(1, size);
+    let fit_h = ((h as f64 * scale).round() as u32).clamp(1, size);
+
+    let mut premultiplied = img.clone();
+    premultiply_rgba8(&mut premultiplied);
+    let mut scaled = imageops::resize(&premultiplied, fit_w, fit_h, FilterType::Lanczos3);
+    let amount = sharpen_amount(w.max(h), size, sharpen);
+    if amount > 0.0 {
+        scaled = unsharp(&scaled, amount);
+    }
+    // Lanczos rings, so colours can overshoot their alpha.
+    for px in scaled.pixels_mut() {
+        let a = px[3];
+        px.0[..3].iter_mut().for_each(|v| *v = (*v).min(a));
+    }
+    unpremultiply_rgba8(&mut scaled);
+
+    let mut icon = RgbaImage::new(size, size);
+    imageops::replace(&mut icon, &scaled, ((size - fit_w) / 2).into(), ((size - fit_h) / 2).into());
+    icon
+}
+
+/// Unsharp mask amount for an icon of `size` rendered from a source `source_side` pixels across.
+///
+/// The more an icon is reduced, the more detail averages away, so `sharpen` is scaled by the
+/// reduction on a log scale: nothing at 1:1 or when enlarging, all of it from 16:1 down.
+pub fn sharpen_amount(source_side: u32, size: u32, sharpen: f32) -> f32 {
+    let ratio = source_side as f32 / size.max(1) as f32;
+    sharpen * (ratio.log2() / FULL_SHARPEN_RATIO.log2()).clamp(0.0, 1.0)
+}
+
+/// Adds `amount` times the difference from a slightly blurred copy to the colour channels.
+fn unsharp(img: &RgbaImage, amount: f32) -> RgbaImage {
+    let blurred = imageops::blur(img, SHARPEN_SIGMA);
+    let mut out = img.clone();
+    for (px, b) in out.pixels_mut().zip(blurred.pixels()) {
+        for c in 0..3 {
+            let v = px[c] as f32;
+            px[c] = (v + amount * (v - b[c] as f32)).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    out
+}
+
+/// Packs square `icons` of 1 to 256 pixels into an `.ico`, each entry stored as PNG.
+pub fn ico(icons: &[RgbaImage], compression: PngCompression) -> Result<Vec<u8>, AppError> {
+    let frames = icons
+        .iter()
+        .map(|icon| {
+            let encoded = png::encode(&DynamicImage::ImageRgba8(icon.clone()), compression)?;
+            Ok(IcoFrame::with_encoded(encoded, icon.width(), icon.height(), ExtendedColorType::Rgba8)?)
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+    let mut out = Vec::new();
+    IcoEncoder::new(&mut out).encode_images(&frames)?;
+    Ok(out)
+}
+
+/// File names of the Apple touch icon of `size`: the sized name, and for the 180 px icon
+/// also the plain `apple-touch-icon.png` iOS falls back to.
+pub fn touch_names(size: u32) -> Vec<String> {
+    let mut names = vec![format!("apple-touch-icon-{size}x{size}.png")];
+    if size == DEFAULT_TOUCH_SIZE {
+        names.push("apple-touch-icon.png".to_string());
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_render_centres_on_square() {
+        let img = RgbaImage::from_pixel(200, 100, Rgba([200, 40, 40, 255]));
+        let icon = render(&img, 32, 0.5);
+        assert_eq!(icon.dimensions(), (32, 32));
+        assert_eq!(icon.get_pixel(16, 2)[3], 0);
+        assert_eq!(icon.get_pixel(16, 16), &Rgba([200, 40, 40, 255]));
+        // Sharpening must not darken an edge against transparency.
+        assert_eq!(icon.get_pixel(16, 8), &Rgba([200, 40, 40, 255]));
+    }
+
+    #[test]
+    fn test_sharpen_amount_follows_reduction() {
+        assert_eq!(sharpen_amount(256, 256, 0.8), 0.0);
+        assert_eq!(sharpen_amount(64, 256, 0.8), 0.0);
+        assert_eq!(sharpen_amount(512, 128, 0.8), 0.4);
+        assert_eq!(sharpen_amount(1024, 16, 0.8), 0.8);
+    }
+
+    #[test]
+    fn test_ico_lists_every_size() {
+        let img = RgbaImage::from_fn(300, 300, |x, y| Rgba([x as u8, y as u8, 90, 255]));
+        let icons: Vec<RgbaImage> = [16, 48, 256].iter().map(|&size| render(&img, size, 0.5)).collect();
+        let bytes = ico(&icons, PngCompression::Fast).unwrap();
+        assert_eq!(u16::from_le_bytes([bytes[4], bytes[5]]), 3);
+        // Directory entries store the width in one byte, 0 meaning 256.
+        assert_eq!([bytes[6], bytes[22], bytes[38]], [16, 48, 0]);
+        let decoded = image::load_from_memory_with_format(&bytes, image::ImageFormat::Ico).unwrap();
+        assert_eq!(decoded.to_rgba8(), icons[2]);
+        assert_eq!(touch_names(180), ["apple-touch-icon-180x180.png", "apple-touch-icon.png"]);
+    }
+}
@@ -0,0 +1,336 @@
+use std::hash::{DefaultHasher, Hasher};
+use std::str::FromStr;
+
+use crate::pipeline::{PixelBuffer, Rect};
+
+/// Smallest tile edge picked automatically.
+const MIN_TILE: u32 = 64;
+
+/// Largest tile edge picked automatically.
+const MAX_TILE: u32 = 2048;
+
+/// Automatically picked tile edges are multiples of this many pixels.
+const TILE_ALIGN: u32 = 32;
+
+/// How the edge length of the tiles whose hashes are compared between runs is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileSize {
+    /// Picked per image by [`auto_tile_size`].
+    #[default]
+    Auto,
+    /// A fixed edge length in pixels.
+    Fixed(u32),
+}
+
+impl FromStr for TileSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            n => match n.parse::<u32>() {
+                Ok(size) if size > 0 => Ok(Self::Fixed(size)),
+                _ => Err(format!("invalid tile size `{n}` (expected auto or a positive number of pixels)")),
+            },
+        }
+    }
+}
+
+/// Chain input hashes and chain output of the previous run.
+struct Frame {
+    width: u32,
+    height: u32,
+    pixel_format: u32,
+    tile: u32,
+    hashes: Vec<u64>,
+    output: PixelBuffer,
+}
+
+/// Watch-mode state for re-running a plugin chain only where its input changed.
+///
+/// The chain input is hashed in square tiles. On the next run, the changed tiles are
+/// re-processed together with enough surrounding pixels for the chain's halo and
+/// composited into the previous output. This is only valid for chains whose every step is
+/// local (see [`plugin_sdk::CAP_LOCAL`]).
+#[derive(Default)]
+pub struct Incremental {
+    chain: String,
+    tile_size: TileSize,
+    frame: Option<Frame>,
+}
+
+impl Incremental {
+    /// Creates a state without a previous frame that picks tile sizes automatically.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a state without a previous frame that uses `tile_size`.
+    pub fn with_tile_size(tile_size: TileSize) -> Self {
+        Self { tile_size, ..Self::default() }
+    }
+
+    /// Sets the identity of the chain about to run (plugins, library hashes and params).
+    ///
+    /// A chain different from the previous one discards its output.
+    pub fn set_chain(&mut self, chain: String) {
+        if self.chain != chain {
+            self.frame = None;
+            self.chain = chain;
+        }
+    }
+
+    /// Runs `chain` over `data` in place, only on the changed regions if the previous frame
+    /// has the same size and format.
+    ///
+    /// `chain` is called with the size and pixels of each region; `halo` is the sum of the
+    /// halos of its steps. On error the previous frame is discarded, so the next run is a full one.
+    pub fn run<E>(
+        &mut self,
+        width: u32,
+        height: u32,
+        halo: u32,
+        data: &mut PixelBuffer,
+        mut chain: impl FnMut(u32, u32, &mut PixelBuffer) -> Result<(), E>,
+    ) -> Result<(), E> {
+        let previous = self.frame.take().filter(|frame| {
+            frame.width == width && frame.height == height && frame.pixel_format == data.pixel_format()
+        });
+        // Hashes are only comparable with the tile size of the previous frame.
+        let tile = match (&previous, self.tile_size) {
+            (Some(frame), _) => frame.tile,
+            (None, TileSize::Fixed(size)) => size,
+            (None, TileSize::Auto) => {
+                let cores = std::thread::available_parallelism().map_or(1, |n| n.get() as u32);
+                let available = available_memory();
+                let pixel_size = match data {
+                    PixelBuffer::Rgba8(_) => 4,
+                    PixelBuffer::Rgba32F(_) => 16,
+                };
+                let tile = auto_tile_size(width, height, halo, pixel_size, cores, available);
+                tracing::info!(
+                    tile_size = tile,
+                    halo,
+                    cores,
+                    available_mib = available.map(|b| b / (1024 * 1024)),
+                    "picked incremental tile size"
+                );
+                tile
+            }
+        };
+        let hashes = tile_hashes(data, width, height, tile);
+
+        match previous {
+            Some(frame) => {
+                let dirty = dirty_regions(&frame.hashes, &hashes, width, height, tile);
+                tracing::info!(regions = dirty.len(), "re-processing changed regions");
+                let mut output = frame.output;
+                for rect in dirty {
+                    // Output pixels within `halo` of a changed tile may change, and computing
+                    // them exactly needs another `halo` of input around them.
+                    let target = rect.expand(halo, width, height);
+                    let source = rect.expand(halo.saturating_mul(2), width, height);
+                    let mut region = data.crop(width, source);
+                    chain(source.width, source.height, &mut region)?;
+                    output.paste(width, &region, source, target);
+                }
+                *data = output;
+            }
+            None => chain(width, height, data)?,
+        }
+
+        self.frame = Some(Frame { width, height, pixel_format: data.pixel_format(), tile, hashes, output: data.clone() });
+        Ok(())
+    }
+}
+
+/// Picks the tile edge for a `width` x `height` image with `pixel_size` bytes per pixel,
+/// processed by a chain with the given `halo`.
+///
+/// Smaller tiles detect changes more finely, but every re-processed region carries
+/// `2 * halo` extra pixels on each side. The tile is therefore made at least ten times the
+/// halo, which keeps that overhead near twice the tile's area, and at least eight rows per
+/// core, so a re-processed region still keeps every core busy. One region must fit in an
+/// eighth of the `available` memory, if known. Tiles never exceed the image.
+pub fn auto_tile_size(width: u32, height: u32, halo: u32, pixel_size: u64, cores: u32, available: Option<u64>) -> u32 {
+    let wanted = MIN_TILE.max(halo.saturating_mul(10)).max(cores.saturating_mul(8));
+    let fits_memory = available.map_or(u32::MAX, |bytes| {
+        let region_edge = ((bytes / 8 / pixel_size.max(1)) as f64).sqrt() as u64;
+        region_edge.saturating_sub(4 * halo as u64).min(u32::MAX as u64) as u32
+    });
+    let image = width.max(height).next_multiple_of(TILE_ALIGN);
+    let fits_memory = fits_memory / TILE_ALIGN * TILE_ALIGN;
+    wanted.next_multiple_of(TILE_ALIGN).min(MAX_TILE).min(fits_memory).min(image).max(TILE_ALIGN)
+}
+
+/// Returns the memory available to new allocations, where the platform reports it.
+#[cfg(target_os = "linux")]
+fn available_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Returns the memory available to new allocations, where the platform reports it.
+#[cfg(not(target_os = "linux"))]
+fn available_memory() -> Option<u64> {
+    None
+}
+
+/// Hashes every `tile` x `tile` tile of a `width` pixels wide buffer, in row-major tile order.
+pub fn tile_hashes(data: &PixelBuffer, width: u32, height: u32, tile: u32) -> Vec<u64> {
+    let bytes: &[u8] = match data {
+        PixelBuffer::Rgba8(values) => values,
+        // SAFETY:
+        // - `f32` has no padding or invalid bit patterns, so every byte of the slice is initialized.
+        // - `u8` has alignment 1, and the byte length covers exactly the same memory.
+        // - The borrow of `values` keeps the memory alive and unmodified while `bytes` is used.
+        PixelBuffer::Rgba32F(values) => unsafe {
+            std::slice::from_raw_parts(values.as_ptr().cast(), std::mem::size_of_val(values.as_slice()))
+        },
+    };
+    let pixel_size = bytes.len() / (width as usize * height as usize).max(1);
+    let stride = width as usize * pixel_size;
+
+    let mut hashes = Vec::new();
+    for tile_y in (0..height).step_by(tile as usize) {
+        for tile_x in (0..width).step_by(tile as usize) {
+            let tile_width = tile.min(width - tile_x) as usize;
+            let mut hasher = DefaultHasher::new();
+            for y in tile_y..(tile_y + tile).min(height) {
+                let start = y as usize * stride + tile_x as usize * pixel_size;
+                hasher.write(&bytes[start..start + tile_width * pixel_size]);
+            }
+            hashes.push(hasher.finish());
+        }
+    }
+    hashes
+}
+
+/// Returns the rectangles covering the tiles whose hashes differ.
+///
+/// Horizontally adjacent changed tiles form one rectangle, which grows downwards while the
+/// rows below change over the same span.
+pub fn dirty_regions(old: &[u64], new: &[u64], width: u32, height: u32, tile: u32) -> Vec<Rect> {
+    let columns = width.div_ceil(tile) as usize;
+    let mut regions: Vec<Rect> = Vec::new();
+    if columns == 0 {
+        return regions;
+    }
+
+    for (row, (old_row, new_row)) in old.chunks(columns).zip(new.chunks(columns)).enumerate() {
+        let y = row as u32 * tile;
+        let tile_height = tile.min(height - y);
+        let mut column = 0;
+        while column < columns {
+            if old_row[column] == new_row[column] {
+                column += 1;
+                continue;
+            }
+            let start = column;
+            while column < columns && old_row[column] != new_row[column] {
+                column += 1;
+            }
+            let x = start as u32 * tile;
+            let span = (column as u32 * tile).min(width) - x;
+
+            match regions.iter_mut().find(|r| r.x == x && r.width == span && r.y + r.height == y) {
+                Some(above) => above.height += tile_height,
+                None => regions.push(Rect { x, y, width: span, height: tile_height }),
+            }
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Averages each value with its 3x3 neighborhood, renormalizing at the edges (halo 1).
+    fn smooth(width: u32, height: u32, data: &mut PixelBuffer) {
+        let PixelBuffer::Rgba32F(values) = data else { unreachable!() };
+        let src = values.clone();
+        let (w, h) = (width as i64, height as i64);
+        for y in 0..h {
+            for x in 0..w {
+                for c in 0..4 {
+                    let (mut sum, mut n) = (0.0, 0.0);
+                    for (nx, ny) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy))) {
+                        if (0..w).contains(&nx) && (0..h).contains(&ny) {
+                            sum += src[((ny * w + nx) * 4 + c) as usize];
+                            n += 1.0;
+                        }
+                    }
+                    values[((y * w + x) * 4 + c) as usize] = sum / n;
+                }
+            }
+        }
+    }
+
+    const TILE: u32 = 256;
+
+    #[test]
+    fn test_incremental_matches_full_run() {
+        let (width, height) = (600, 300);
+        let input: Vec<f32> = (0..width * height * 4).map(|i| (i as u64 * 7919 % 1000) as f32 / 1000.0).collect();
+        let mut state = Incremental::with_tile_size(TileSize::Fixed(TILE));
+        let twice = |w, h, data: &mut PixelBuffer| {
+            smooth(w, h, data);
+            smooth(w, h, data);
+            Ok::<_, ()>(())
+        };
+
+        let mut first = PixelBuffer::Rgba32F(input.clone());
+        state.run(width, height, 2, &mut first, twice).unwrap();
+
+        // Change a pixel right next to a tile boundary.
+        let mut edited = input;
+        edited[(10 * width as usize + 256) * 4] = 5.0;
+
+        let mut calls = Vec::new();
+        let mut incremental = PixelBuffer::Rgba32F(edited.clone());
+        state
+            .run(width, height, 2, &mut incremental, |w, h, data| {
+                calls.push((w, h));
+                twice(w, h, data)
+            })
+            .unwrap();
+        assert_eq!(calls, [(264, 260)]);
+
+        let mut full = PixelBuffer::Rgba32F(edited);
+        twice(width, height, &mut full).unwrap();
+        assert_eq!(incremental, full);
+    }
+
+    #[test]
+    fn test_dirty_regions_merge() {
+        let (width, height) = (3 * TILE, 2 * TILE + 10);
+        let old = vec![0; 9];
+        let new = [1, 1, 0, 1, 1, 0, 0, 0, 1];
+        assert_eq!(
+            dirty_regions(&old, &new, width, height, TILE),
+            [
+                Rect { x: 0, y: 0, width: 2 * TILE, height: 2 * TILE },
+                Rect { x: 2 * TILE, y: 2 * TILE, width: TILE, height: 10 },
+            ]
+        );
+        assert!(dirty_regions(&old, &old, width, height, TILE).is_empty());
+    }
+
+    #[test]
+    fn test_auto_tile_size() {
+        // Small halos on few cores get the smallest tiles.
+        assert_eq!(auto_tile_size(4000, 3000, 2, 4, 4, None), MIN_TILE);
+        // Tiles grow with the halo and with the core count.
+        assert_eq!(auto_tile_size(4000, 3000, 25, 4, 4, None), 256);
+        assert_eq!(auto_tile_size(4000, 3000, 2, 4, 64, None), 512);
+        // They are capped by memory and by the image.
+        assert_eq!(auto_tile_size(4000, 3000, 100, 16, 4, Some(8 * 16 * 600 * 600)), 192);
+        assert_eq!(auto_tile_size(100, 40, 50, 4, 4, None), 128);
+        assert_eq!("auto".parse::<TileSize>(), Ok(TileSize::Auto));
+        assert_eq!("128".parse::<TileSize>(), Ok(TileSize::Fixed(128)));
+        assert!("0".parse::<TileSize>().is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use image::{ColorType, DynamicImage, ImageBuffer, ImageDecoder, ImageFormat, ImageReader};
+use std::fmt;
+use std::io::{BufRead, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::AppError;
+use crate::pipeline::Rect;
+use crate::pool::BufferPool;
+use crate::raw::{self, RawOptions};
+#[cfg(feature = "s3")]
+use crate::s3::{S3Client, S3Uri};
+use crate::svg::{self, SvgOptions};
+
+/// Where an input image is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    /// A local file.
+    File(PathBuf),
+    /// An `http://` or `https://` URL downloaded into memory.
+    Url(String),
+    /// An object (or, for batch runs, a key prefix) in S3-compatible storage.
+    #[cfg(feature = "s3")]
+    S3(S3Uri),
+}
+
+/// Limits applied when downloading remote inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadLimits {
+    /// Maximum accepted response body size in bytes.
+    pub max_bytes: u64,
+    /// Timeout for the whole request, including reading the body.
+    pub timeout: Duration,
+}
+
+impl Default for DownloadLimits {
+    /// The CLI defaults: 100 MiB within 30 seconds.
+    fn default() -> Self {
+        Self { max_bytes: 100 * 1024 * 1024, timeout: Duration::from_secs(30) }
+    }
+}
+
+/// Options controlling how inputs are fetched and decoded.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadOptions {
+    /// Limits for remote inputs.
+    pub download: DownloadLimits,
+    /// Apply the EXIF orientation tag so the pixels are stored upright.
+    pub auto_orient: bool,
+    /// Keep only this region of the upright image.
+    pub roi: Option<Rect>,
+    /// How camera RAW inputs are developed.
+    pub raw: RawOptions,
+    /// How SVG inputs are rasterized.
+    pub svg: SvgOptions,
+}
+
+impl FromStr for InputSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Ok(Self::Url(s.to_string()))
+        } else if s.starts_with("s3://") {
+            #[cfg(feature = "s3")]
+            return s.parse().map(Self::S3);
+            #[cfg(not(feature = "s3"))]
+            return Err("s3:// inputs require the `s3` feature".to_string());
+        } else {
+            Ok(Self::File(PathBuf::from(s)))
+        }
+    }
+}
+
+impl fmt::Display for InputSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => f.write_str(url),
+            #[cfg(feature = "s3")]
+            Self::S3(uri) => write!(f, "{uri}"),
+        }
+    }
+}
+
+impl InputSource {
+    /// Returns the local path of a file input.
+    pub fn as_path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Checks that a file input exists. Remote inputs are checked when fetched.
+    pub fn check_exists(&self) -> Result<(), AppError> {
+        match self {
+            Self::File(path) if !path.exists() => Err(AppError::MissingInput(path.display().to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads and decodes the image.
+    ///
+    /// With `auto_orient`, the EXIF orientation is applied to the pixels. Outputs
+    /// are written without EXIF data, so the tag is effectively cleared. 8-bit images are
+    /// decoded into a buffer taken from `pool`.
+    ///
+    /// With a region of interest, only that part of the image is returned. TIFF images stored
+    /// in strips or tiles then only have the chunks overlapping the region decoded; other
+    /// formats are decoded in full and cropped.
+    ///
+    /// Camera RAW files, recognized by their extension, are developed into linear RGB
+    /// as set by `opts.raw`; this needs the `raw` feature. SVG documents are rasterized into
+    /// RGBA at the size set by `opts.svg`.
+    pub fn load(&self, opts: &LoadOptions, pool: &mut BufferPool) -> Result<DynamicImage, AppError> {
+        let name = self.to_string();
+        if raw::is_raw_path(Path::new(&name)) {
+            let img = raw::decode(&self.read_bytes(&opts.download)?, &opts.raw, opts.auto_orient)?;
+            return crop_to_roi(img, opts.roi);
+        }
+        if svg::is_svg_path(Path::new(&name)) {
+            let resources_dir = match self {
+                Self::File(path) => path.parent(),
+                _ => None,
+            };
+            let img = svg::rasterize(&self.read_bytes(&opts.download)?, &opts.svg, resources_dir)?;
+            return crop_to_roi(img, opts.roi);
+        }
+        match self {
+            Self::File(path) => decode(ImageReader::open(path)?, opts, pool),
+            Self::Url(url) => {
+                let bytes = download(url, &opts.download)?;
+                decode(ImageReader::new(Cursor::new(bytes)), opts, pool)
+            }
+            #[cfg(feature = "s3")]
+            Self::S3(uri) => {
+                let bytes = S3Client::from_env(&opts.download)?.get_object(uri)?;
+                decode(ImageReader::new(Cursor::new(bytes)), opts, pool)
+            }
+        }
+    }
+
+    /// Reads the encoded image into memory.
+    fn read_bytes(&self, limits: &DownloadLimits) -> Result<Vec<u8>, AppError> {
+        match self {
+            Self::File(path) => Ok(std::fs::read(path)?),
+            Self::Url(url) => download(url, limits),
+            #[cfg(feature = "s3")]
+            Self::S3(uri) => S3Client::from_env(limits)?.get_object(uri),
+        }
+    }
+}
+
+/// Crops an image decoded in full to the region of interest, if any.
+fn crop_to_roi(img: DynamicImage, roi: Option<Rect>) -> Result<DynamicImage, AppError> {
+    let Some(roi) = roi else {
+        return Ok(img);
+    };
+    check_roi(roi, img.width(), img.height())?;
+    Ok(img.crop_imm(roi.x, roi.y, roi.width, roi.height))
+}
+
+fn decode<R: BufRead + Seek>(
+    reader: ImageReader<R>,
+    opts: &LoadOptions,
+    pool: &mut BufferPool,
+) -> Result<DynamicImage, AppError> {
+    let mut reader = reader.with_guessed_format()?;
+    if let Some(roi) = opts.roi
+        && reader.format() == Some(ImageFormat::Tiff)
+    {
+        let mut inner = reader.into_inner();
+        let start = inner.stream_position()?;
+        if let Some(img) = decode_tiff_region(&mut inner, roi, opts.auto_orient)? {
+            tracing::debug!(%roi, "decoded region of interest only");
+            return Ok(img);
+        }
+        inner.seek(SeekFrom::Start(start))?;
+        reader = ImageReader::with_format(inner, ImageFormat::Tiff);
+    }
+
+    let auto_orient = opts.auto_orient;
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut img = match decoder.color_type() {
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => decode_pooled(decoder, pool)?,
+        _ => DynamicImage::from_decoder(decoder)?,
+    };
+
+    if auto_orient && orientation != image::metadata::Orientation::NoTransforms {
+        tracing::debug!(?orientation, "applying EXIF orientation");
+        img.apply_orientation(orientation);
+    }
+
+    if let Some(roi) = opts.roi {
+        check_roi(roi, img.width(), img.height())?;
+        let cropped = img.crop_imm(roi.x, roi.y, roi.width, roi.height);
+        pool.put_image(img);
+        img = cropped;
+    }
+    Ok(img)
+}
+
+fn check_roi(roi: Rect, width: u32, height: u32) -> Result<(), AppError> {
+    if roi.fits(width, height) {
+        Ok(())
+    } else {
+        Err(AppError::RoiOutside { roi: roi.to_string(), width, height })
+    }
+}
+
+/// Decodes only the strips or tiles of a TIFF image that overlap `roi`.
+///
+/// Returns `None` for layouts this does not handle (planar data, sample types other than
+/// 8 or 16-bit gray, gray-alpha, RGB and RGBA, or an orientation that would have to be
+/// applied first); the caller then decodes the whole image.
+fn decode_tiff_region<R: Read + Seek>(reader: R, roi: Rect, auto_orient: bool) -> Result<Option<DynamicImage>, AppError> {
+    use tiff::decoder::{Decoder, DecodingResult};
+    use tiff::tags::Tag;
+
+    let mut decoder = Decoder::new(reader)?;
+    let (width, height) = decoder.dimensions()?;
+    let orientation = decoder.find_tag_unsigned::<u16>(Tag::Orientation)?.unwrap_or(1);
+    let planar = decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration)?.unwrap_or(1);
+    if (auto_orient && orientation != 1) || planar != 1 {
+        return Ok(None);
+    }
+    let (channels, bits) = match decoder.colortype()? {
+        tiff::ColorType::Gray(bits) => (1, bits),
+        tiff::ColorType::GrayA(bits) => (2, bits),
+        tiff::ColorType::RGB(bits) => (3, bits),
+        tiff::ColorType::RGBA(bits) => (4, bits),
+        _ => return Ok(None),
+    };
+    check_roi(roi, width, height)?;
+
+    let img = match bits {
+        8 => read_chunks(&mut decoder, roi, channels, |chunk| match chunk {
+            DecodingResult::U8(data) => Some(data),
+            _ => None,
+        })?
+        .and_then(|data| match channels {
+            1 => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageLuma8),
+            2 => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageLumaA8),
+            3 => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageRgb8),
+            _ => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageRgba8),
+        }),
+        16 => read_chunks(&mut decoder, roi, channels, |chunk| match chunk {
+            DecodingResult::U16(data) => Some(data),
+            _ => None,
+        })?
+        .and_then(|data| match channels {
+            1 => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageLuma16),
+            2 => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageLumaA16),
+            3 => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageRgb16),
+            _ => ImageBuffer::from_raw(roi.width, roi.height, data).map(DynamicImage::ImageRgba16),
+        }),
+        _ => None,
+    };
+    Ok(img)
+}
+
+/// Copies the parts of all chunks overlapping `roi` into a tightly packed buffer.
+fn read_chunks<R: Read + Seek, T: Copy + Default>(
+    decoder: &mut tiff::decoder::Decoder<R>,
+    roi: Rect,
+    channels: usize,
+    samples: impl Fn(tiff::decoder::DecodingResult) -> Option<Vec<T>>,
+) -> Result<Option<Vec<T>>, AppError> {
+    let (chunk_width, chunk_height) = decoder.chunk_dimensions();
+    let (width, _) = decoder.dimensions()?;
+    let across = width.div_ceil(chunk_width);
+    let mut out = vec![T::default(); roi.width as usize * roi.height as usize * channels];
+
+    for chunk_y in roi.y / chunk_height..=(roi.y + roi.height - 1) / chunk_height {
+        for chunk_x in roi.x / chunk_width..=(roi.x + roi.width - 1) / chunk_width {
+            let index = chunk_y * across + chunk_x;
+            let (data_width, data_height) = decoder.chunk_data_dimensions(index);
+            let Some(chunk) = samples(decoder.read_chunk(index)?) else {
+                return Ok(None);
+            };
+
+            let (left, top) = (chunk_x * chunk_width, chunk_y * chunk_height);
+            let (x0, x1) = (left.max(roi.x), (left + data_width).min(roi.x + roi.width));
+            let len = (x1 - x0) as usize * channels;
+            for y in top.max(roi.y)..(top + data_height).min(roi.y + roi.height) {
+                let from = ((y - top) as usize * data_width as usize + (x0 - left) as usize) * channels;
+                let to = ((y - roi.y) as usize * roi.width as usize + (x0 - roi.x) as usize) * channels;
+                out[to..to + len].copy_from_slice(&chunk[from..from + len]);
+            }
+        }
+    }
+    Ok(Some(out))
+}
+
+/// Decodes an 8-bit image into a pooled buffer instead of a fresh allocation.
+fn decode_pooled(decoder: impl ImageDecoder, pool: &mut BufferPool) -> Result<DynamicImage, AppError> {
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    let len = usize::try_from(decoder.total_bytes())
+        .map_err(|_| image::ImageError::Limits(image::error::LimitError::from_kind(
+            image::error::LimitErrorKind::InsufficientMemory,
+        )))?;
+
+    let mut buf = pool.take_bytes(len);
+    buf.resize(len, 0);
+    decoder.read_image(&mut buf)?;
+
+    let img = match color {
+        ColorType::L8 => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLuma8),
+        ColorType::La8 => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageLumaA8),
+        ColorType::Rgb8 => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgb8),
+        _ => ImageBuffer::from_raw(width, height, buf).map(DynamicImage::ImageRgba8),
+    };
+    Ok(img.expect("decoder output matches its dimensions"))
+}
+
+/// Downloads `url` into memory, enforcing the size limit and timeout.
+pub fn download(url: &str, limits: &DownloadLimits) -> Result<Vec<u8>, AppError> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(limits.timeout))
+        .build()
+        .into();
+
+    let mut response = agent.get(url).call()?;
+    let bytes = response
+        .body_mut()
+        .with_config()
+        .limit(limits.max_bytes)
+        .read_to_vec()?;
+
+    tracing::debug!(url, bytes = bytes.len(), "input downloaded");
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input_source() {
+        assert_eq!(
+            "https://example.com/a.jpg".parse::<InputSource>(),
+            Ok(InputSource::Url("https://example.com/a.jpg".to_string()))
+        );
+        assert_eq!("in.png".parse::<InputSource>(), Ok(InputSource::File(PathBuf::from("in.png"))));
+    }
+
+    #[test]
+    fn test_tiff_region_matches_crop() {
+        use tiff::encoder::{colortype, TiffEncoder};
+
+        let (width, height) = (37, 29);
+        let pixels: Vec<u8> = (0..width * height * 3).map(|i| (i * 7 % 256) as u8).collect();
+        let mut tiff = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut tiff).unwrap();
+        let mut image = encoder.new_image::<colortype::RGB8>(width, height).unwrap();
+        image.rows_per_strip(4).unwrap();
+        image.write_data(&pixels).unwrap();
+
+        let roi = Rect { x: 5, y: 6, width: 20, height: 11 };
+        let region = decode_tiff_region(Cursor::new(tiff.get_ref()), roi, true).unwrap().unwrap();
+        let full = image::load_from_memory(tiff.get_ref()).unwrap();
+        assert_eq!(region, full.crop_imm(roi.x, roi.y, roi.width, roi.height));
+
+        let outside = Rect { x: 30, ..roi };
+        assert!(matches!(
+            decode_tiff_region(Cursor::new(tiff.get_ref()), outside, true),
+            Err(AppError::RoiOutside { .. })
+        ));
+    }
+}
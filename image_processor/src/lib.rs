@@ -5,5 +5,109 @@
 /// Error types used by the image processor.
 pub mod error;
 
+/// Plugin params parsing and layered resolution.
+pub mod params;
+
+/// Plugin manifests embedded in plugin libraries.
+pub mod manifest;
+
+/// Steps implemented by the host instead of a plugin library.
+pub mod builtin;
+
+/// GPU device and compute pipelines (wgpu).
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+/// Plugin chains built from `--plugin`/`--params` or `--step` arguments.
+pub mod pipeline;
+
+/// Input sources (local files and remote URLs).
+pub mod input;
+
+/// Camera RAW decoding and development (rawloader, behind the `raw` feature).
+pub mod raw;
+/// SVG rasterization with resvg.
+pub mod svg;
+
+/// S3-compatible object storage client.
+#[cfg(feature = "s3")]
+pub mod s3;
+
+/// Retry policy for transient job failures.
+pub mod retry;
+
+/// Expansion of directory and prefix inputs into batch jobs.
+pub mod batch;
+
 /// Dynamic plugin loading and FFI bindings.
 pub mod plugin_loader;
+
+/// Persisted record of the most recent run.
+pub mod run_record;
+
+/// Bug-report bundle generation from a recorded run.
+pub mod bugreport;
+
+/// Incremental re-processing of changed tiles in watch mode.
+pub mod incremental;
+
+/// Buffers reused across batch items.
+pub mod pool;
+
+/// Overlapping decode, plugin and encode stages for batch runs.
+pub mod stages;
+
+/// Pixel format conversions (bit depth reduction with dithering).
+pub mod convert;
+
+/// PNG encoding with strips compressed in parallel.
+pub mod png;
+
+/// Auxiliary outputs derived from the processed image (thumbnails, montages, contact sheets).
+pub mod output;
+
+/// Embedded bitmap font for rendering labels.
+pub mod font;
+
+/// File change detection for watch mode.
+pub mod watch;
+
+/// Live preview window.
+#[cfg(feature = "preview")]
+pub mod preview;
+
+/// Histograms and statistics for `--stats-out`.
+pub mod stats;
+
+/// Per-stage timing reports.
+pub mod timings;
+
+/// Panorama stitching for the `stitch` subcommand.
+pub mod stitch;
+
+/// Perceptual image hashes and near-duplicate grouping.
+pub mod phash;
+
+/// Dominant colour extraction for the `palette` subcommand.
+pub mod palette;
+
+/// Content-aware cropping for `--smart-crop`.
+pub mod smart_crop;
+
+/// Sharpness, noise and exposure scores for the `assess` subcommand.
+pub mod assess;
+
+/// Texture atlas packing for the `pack` subcommand.
+pub mod atlas;
+
+/// Animated GIF and APNG assembly for the `assemble` subcommand.
+pub mod animation;
+
+/// Multi-resolution `.ico` and Apple touch icons for the `icon` subcommand.
+pub mod icon;
+
+/// Deep-zoom tile pyramids for `--tiles`.
+pub mod pyramid;
+
+/// Param sweeps for `--benchmark-matrix`.
+pub mod sweep;
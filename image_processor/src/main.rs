@@ -1,120 +1,1635 @@
-use clap::Parser;
-use image::{ImageBuffer, Rgba};
-use std::ffi::CString;
+use clap::{ArgAction, Parser, Subcommand};
+use image::{ColorType, DynamicImage, ImageBuffer, Rgba, Rgba32FImage, RgbaImage};
+use plugin_sdk::{CallContext, PIXEL_FORMAT_RGBA32F};
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::Write;
+use std::os::raw::c_void;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant};
 
+use image_processor::bugreport::{BugReportOptions, write_bug_report};
+use image_processor::convert::{self, AlphaMode, DitherMode, WorkingSpace};
 use image_processor::error::AppError;
-use image_processor::plugin_loader::Plugin;
+use image_processor::input::{DownloadLimits, InputSource, LoadOptions};
+use image_processor::animation::{self, AnimationFormat, GifOptions, PaletteMode};
+use image_processor::assess::{self, Scored};
+use image_processor::atlas::{self, MapFormat, PackOptions, Sprite};
+use image_processor::batch::{self, Job};
+use image_processor::output::{self, EncodeOptions, OutputTarget};
+use image_processor::params::ParamOverride;
+use image_processor::palette::{self, PaletteFormat};
+use image_processor::phash::{self, HashedImage};
+use image_processor::pipeline::{self, Input2, PixelBuffer, Rect, Step, StepSpec};
+use image_processor::icon;
+use image_processor::incremental::{Incremental, TileSize};
+use image_processor::png::{self, PngCompression};
+use image_processor::pool::BufferPool;
+use image_processor::retry::{RetryPolicy, Stage, StageError};
+use image_processor::pyramid::{self, PyramidOptions, TileLayout};
+use image_processor::raw::{RawOptions, WhiteBalance};
+use image_processor::svg::{self, SvgOptions};
+use image_processor::run_record::{self, ItemRecord, PluginIdentity, RunRecord};
+use image_processor::stages;
+use image_processor::smart_crop::{self, CropSize};
+use image_processor::stats::{ImageStats, StatsRecord};
+use image_processor::stitch;
+use image_processor::sweep::{self, Metrics, Row, Sweep};
+use image_processor::timings::{Timings, TimingsFormat};
+use image_processor::watch::FileWatcher;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{fmt, EnvFilter};
 
 #[derive(Parser, Debug)]
-#[command(name = "image_processor")]
-struct Args {
-    /// path to input PNG
+#[command(name = "image_processor", args_conflicts_with_subcommands = true, arg_required_else_help = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// package a recorded run into a tarball for attaching to issues
+    Bugreport(BugreportArgs),
+    /// build the plugins and run the criterion benchmarks (needs the source tree and cargo)
+    Bench(BenchArgs),
+    /// stitch overlapping images, given left to right, into one panorama
+    Stitch(StitchArgs),
+    /// print perceptual hashes of images, or group near-duplicates with --dedupe
+    Hash(HashArgs),
+    /// extract the dominant colors of an image
+    Palette(PaletteArgs),
+    /// score sharpness, noise and exposure of images, e.g. to cull blurry shots from a batch
+    Assess(AssessArgs),
+    /// lay out the images of a directory as a grid in a single image
+    ContactSheet(ContactSheetArgs),
+    /// pack images into texture atlases with a JSON or CSS map of their coordinates
+    Pack(PackArgs),
+    /// assemble a numbered frame sequence into an animated GIF or APNG
+    Assemble(AssembleArgs),
+    /// render an image at standard icon sizes into one .ico, optionally with Apple touch icons
+    Icon(IconArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct IconArgs {
+    /// path or http(s) URL of the image, ideally square and at least 256 pixels across
+    input: InputSource,
+
+    /// path of the .ico
     #[arg(long)]
-    input: PathBuf,
+    output: PathBuf,
+
+    /// comma-separated icon sizes in the .ico, each 1 to 256
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "16,24,32,48,64,128,256",
+        value_parser = clap::value_parser!(u32).range(1..=256)
+    )]
+    sizes: Vec<u32>,
+
+    /// directory to also write 120, 152, 167 and 180 pixel Apple touch icons to
+    #[arg(long, value_name = "DIR")]
+    apple_touch: Option<PathBuf>,
+
+    /// unsharp mask amount for the most reduced sizes, scaled down for the others; 0 disables
+    #[arg(long, default_value_t = 0.5)]
+    sharpen: f32,
+
+    /// PNG compression effort: fast, default, best, or a zlib level 0-9
+    #[arg(long, default_value = "fast", value_name = "EFFORT")]
+    png_compression: PngCompression,
 
-    /// path to output PNG
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct AssembleArgs {
+    /// frames, or directories (or s3:// prefixes) whose images are taken in the order of the
+    /// numbers in their names, e.g. frame9.png before frame10.png
+    #[arg(required = true)]
+    inputs: Vec<InputSource>,
+
+    /// path of the animation
     #[arg(long)]
     output: PathBuf,
 
-    /// plugin name without extension (e.g. mirror_plugin or blur_plugin)
+    /// gif or apng; by default taken from the --output extension (.gif, or .png and .apng)
     #[arg(long)]
-    plugin: String,
+    format: Option<AnimationFormat>,
 
-    /// path to params text file
+    /// milliseconds each frame is shown; a comma-separated list sets the frames in turn,
+    /// its last value applying to the rest
+    #[arg(long, value_delimiter = ',', default_value = "100")]
+    delay: Vec<u32>,
+
+    /// number of times the animation plays, 0 for forever
+    #[arg(long, default_value_t = 0)]
+    loops: u16,
+
+    /// GIF palette: global (one for all frames) or local (one per frame)
+    #[arg(long, default_value = "global")]
+    palette: PaletteMode,
+
+    /// GIF palette size, including the transparent entry
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u16).range(2..=256))]
+    colors: u16,
+
+    /// GIF dithering: none, ordered or floyd-steinberg
+    #[arg(long, default_value = "none")]
+    dither: DitherMode,
+
+    /// APNG compression effort: fast, default, best, or a zlib level 0-9
+    #[arg(long, default_value = "fast", value_name = "EFFORT")]
+    png_compression: PngCompression,
+
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct PackArgs {
+    /// images, or directories (or s3:// prefixes) whose images are all packed
+    #[arg(required = true)]
+    inputs: Vec<InputSource>,
+
+    /// path of the first atlas; further atlases get `_1`, `_2`, ... appended to the file stem,
+    /// and the map is written next to it with a .json or .css extension
+    #[arg(long)]
+    output: PathBuf,
+
+    /// largest width and height of an atlas
+    #[arg(long, default_value_t = 2048, value_parser = clap::value_parser!(u32).range(1..=16384))]
+    max_size: u32,
+
+    /// empty pixels between neighbouring sprites
+    #[arg(long, default_value_t = 2)]
+    padding: u32,
+
+    /// round atlas sizes up to powers of two (--max-size must then be one)
+    #[arg(long)]
+    power_of_two: bool,
+
+    /// format of the coordinate map: json or css
+    #[arg(long, default_value = "json")]
+    format: MapFormat,
+
+    /// PNG compression effort: fast, default, best, or a zlib level 0-9
+    #[arg(long, default_value = "fast", value_name = "EFFORT")]
+    png_compression: PngCompression,
+
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ContactSheetArgs {
+    /// directory (or s3:// prefix) with the images, laid out in name order
+    input: InputSource,
+
+    /// path of the contact sheet
+    #[arg(long)]
+    output: OutputTarget,
+
+    /// number of cells per row
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+    columns: u32,
+
+    /// side of the square cell each image is fitted into, in pixels
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..=4096))]
+    cell: u32,
+
+    /// render each file name under its cell
+    #[arg(long)]
+    labels: bool,
+
+    /// PNG compression effort: fast, default, best, or a zlib level 0-9
+    #[arg(long, default_value = "fast", value_name = "EFFORT")]
+    png_compression: PngCompression,
+
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct AssessArgs {
+    /// path or http(s) URL of an image, or a directory (or s3:// prefix) to score every image in
+    input: InputSource,
+
+    /// mark images whose sharpness (variance of the Laplacian) is below this value as rejected
+    #[arg(long, value_name = "SHARPNESS")]
+    reject_below: Option<f64>,
+
+    /// print one JSON object per image instead of a line of text
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct HashArgs {
+    /// path or http(s) URL of an image, or a directory (or s3:// prefix) to hash every image in
+    input: InputSource,
+
+    /// hash algorithm: phash (DCT, most robust), dhash (gradients) or ahash (mean)
+    #[arg(long, default_value = "phash")]
+    algorithm: phash::HashAlgorithm,
+
+    /// print a JSON report of near-duplicate groups instead of one hash per image
+    #[arg(long)]
+    dedupe: bool,
+
+    /// largest number of differing hash bits for two images to count as near-duplicates
+    #[arg(long, default_value_t = 8, requires = "dedupe")]
+    threshold: u32,
+
+    /// write the --dedupe report to this file instead of standard output
+    #[arg(long, requires = "dedupe")]
+    report: Option<PathBuf>,
+
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+/// Input options shared by the processing run and the subcommands that read images.
+#[derive(clap::Args, Debug)]
+struct LoadArgs {
+    /// rotate/flip according to the EXIF orientation tag after decoding
+    #[arg(long, default_value_t = true, action = ArgAction::Set, num_args = 0..=1, default_missing_value = "true")]
+    auto_orient: bool,
+
+    /// maximum size in bytes of a downloaded input
+    #[arg(long, default_value_t = 100 * 1024 * 1024)]
+    max_download_bytes: u64,
+
+    /// timeout in seconds for downloading an input
+    #[arg(long, default_value_t = 30)]
+    download_timeout: u64,
+
+    /// white balance of camera RAW inputs (requires the `raw` feature): camera (as shot),
+    /// auto (gray world) or none
+    #[arg(long, default_value = "camera", value_name = "MODE")]
+    raw_white_balance: WhiteBalance,
+
+    /// exposure correction of camera RAW inputs, in stops
+    #[arg(long, default_value_t = 0.0, value_name = "EV", allow_negative_numbers = true)]
+    raw_exposure: f32,
+
+    /// resolution at which SVG inputs are rasterized (requires the `svg` feature); 96 renders
+    /// one SVG unit to one pixel
+    #[arg(long, default_value_t = svg::CSS_DPI)]
+    dpi: f32,
+
+    /// rasterize SVG inputs to this width in pixels instead, keeping the aspect ratio (requires
+    /// the `svg` feature)
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+}
+
+impl LoadArgs {
+    /// Load options for these args; callers that crop to a region set `roi` on the result.
+    fn options(&self) -> LoadOptions {
+        LoadOptions {
+            download: DownloadLimits {
+                max_bytes: self.max_download_bytes,
+                timeout: Duration::from_secs(self.download_timeout),
+            },
+            auto_orient: self.auto_orient,
+            roi: None,
+            raw: RawOptions { white_balance: self.raw_white_balance, exposure: self.raw_exposure },
+            svg: SvgOptions { dpi: self.dpi, width: self.width },
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct PaletteArgs {
+    /// path or http(s) URL of the image
+    input: InputSource,
+
+    /// largest number of colors to extract
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..=256))]
+    colors: u32,
+
+    /// output format: json, css or png-swatch
+    #[arg(long, default_value = "json")]
+    format: palette::PaletteFormat,
+
+    /// write the palette to this file instead of standard output (required for png-swatch)
+    #[arg(long, required_if_eq("format", "png-swatch"))]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct StitchArgs {
+    /// paths or http(s) URLs of the images, in order; each must overlap the next
+    #[arg(required = true, num_args = 2..)]
+    inputs: Vec<InputSource>,
+
+    /// path of the panorama
+    #[arg(long)]
+    output: OutputTarget,
+
+    /// how overlaps are combined: feather (smooth transitions) or none (hard seams)
+    #[arg(long, default_value = "feather")]
+    blend: stitch::Blend,
+
+    /// PNG compression effort: fast, default, best, or a zlib level 0-9
+    #[arg(long, default_value = "fast", value_name = "EFFORT")]
+    png_compression: PngCompression,
+
+    #[command(flatten)]
+    load: LoadArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// only run benchmarks whose name contains this text, e.g. blur_threads
+    filter: Option<String>,
+
+    /// save the results under this name for later comparison
+    #[arg(long, conflicts_with = "baseline")]
+    save_baseline: Option<String>,
+
+    /// compare the results with a baseline saved earlier
+    #[arg(long)]
+    baseline: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct BugreportArgs {
+    /// use the most recent run
+    #[arg(long, required = true)]
+    last: bool,
+
+    /// path of the tarball to write (default bugreport-<timestamp>.tar.gz)
     #[arg(long)]
-    params: PathBuf,
+    output: Option<PathBuf>,
+
+    /// downscale the input so its longest side is at most this many pixels
+    #[arg(long)]
+    downscale: Option<u32>,
+
+    /// replace the input with a coarse mosaic hiding its content
+    #[arg(long)]
+    redact: bool,
+
+    /// do not include the input image
+    #[arg(long)]
+    no_input: bool,
+}
+
+// clap leaves the derived group of a struct with flattened fields empty, which would make the
+// `Option<Args>` in `Cli` always `None`; the required `--input` marks that the args were given.
+#[derive(clap::Args, Debug)]
+#[group(args = ["input"])]
+struct Args {
+    /// path or http(s) URL of the input image; a directory (or s3:// prefix) processes every image in it
+    #[arg(long)]
+    input: InputSource,
+
+    /// path or http(s) URL of a secondary input image handed to every plugin (e.g. the other
+    /// layer for blend_plugin); loaded once per run, without --roi
+    #[arg(long, value_name = "INPUT")]
+    input2: Option<InputSource>,
+
+    /// comma-separated paths or http(s) URLs of additional input images handed to every plugin
+    /// (e.g. the other exposures for hdr_merge_plugin); loaded once per run, without --roi
+    #[arg(long, value_name = "INPUT", value_delimiter = ',')]
+    inputs: Vec<InputSource>,
+
+    #[command(flatten)]
+    load: LoadArgs,
+
+    /// path of the output image, or a directory when the input is a directory / key prefix
+    #[arg(long)]
+    output: OutputTarget,
+
+    /// plugin name without extension (e.g. mirror_plugin or blur_plugin)
+    #[arg(long, requires = "params", conflicts_with = "step", required_unless_present = "step")]
+    plugin: Option<String>,
+
+    /// path to params text file
+    #[arg(long, requires = "plugin")]
+    params: Option<PathBuf>,
+
+    /// plugin step `name:key=value;key=value`; repeat to chain plugins in order
+    #[arg(long, value_name = "SPEC")]
+    step: Vec<StepSpec>,
+
+    /// override a param (`key=value`, or `plugin:key=value` for one step); applied after the params file
+    #[arg(long, value_name = "KEY=VALUE")]
+    param: Vec<ParamOverride>,
 
     /// directory with plugins (default target/debug)
     #[arg(long, default_value = "target/debug")]
     plugin_path: String,
+
+    /// crop the result to this aspect ratio where it has the most detail, then scale it to exactly
+    /// this size; for thumbnails that keep the subject
+    #[arg(long, value_name = "WxH")]
+    smart_crop: Option<CropSize>,
+
+    /// make --smart-crop favor skin tones, keeping faces and heads inside the crop
+    #[arg(long, requires = "smart_crop")]
+    smart_crop_faces: bool,
+
+    /// also write a downscaled copy whose longest side is this many pixels (<output>_thumb.<ext>)
+    #[arg(long, value_name = "SIZE")]
+    thumbnail: Option<u32>,
+
+    /// also write a before/after montage (<output>_montage.<ext>): side-by-side or slider
+    #[arg(long, value_name = "MODE")]
+    montage: Option<output::MontageMode>,
+
+    /// write a tile pyramid in the --output image format instead of the output: dzi (<output>.dzi
+    /// and <output>_files/, for OpenSeadragon) or xyz (<output>/z/x/y tiles, for web map viewers)
+    #[arg(long, value_name = "LAYOUT")]
+    tiles: Option<TileLayout>,
+
+    /// edge of pyramid tiles in pixels [default: 254 for dzi, 256 for xyz]
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..))]
+    tiles_edge: Option<u32>,
+
+    /// pixels each dzi tile repeats from its neighbours
+    #[arg(long, default_value_t = 1, value_name = "PIXELS")]
+    tiles_overlap: u32,
+
+    /// PNG compression effort: fast, default, best, or a zlib level 0-9; large images are
+    /// compressed in parallel strips
+    #[arg(long, default_value = "fast", value_name = "EFFORT")]
+    png_compression: PngCompression,
+
+    /// process only this region (`x,y,width,height` of the upright image); strip and tiled TIFF
+    /// inputs then only decode the parts overlapping it
+    #[arg(long, value_name = "X,Y,W,H")]
+    roi: Option<Rect>,
+
+    /// dithering when saving 16-bit or float results at 8 bits: none, ordered or floyd-steinberg
+    #[arg(long, default_value = "none")]
+    dither: DitherMode,
+
+    /// color space the plugin chain runs in: srgb (8-bit) or linear (32-bit float)
+    #[arg(long, default_value = "srgb", value_name = "SPACE")]
+    working_space: WorkingSpace,
+
+    /// alpha convention for the plugin chain: straight or premultiplied
+    #[arg(long, default_value = "straight", value_name = "MODE")]
+    alpha: AlphaMode,
+
+    /// upper bound on threads a plugin may use (passed as a hint in the call context); 0 means all cores
+    #[arg(long, default_value_t = 0, value_name = "N")]
+    threads: u32,
+
+    /// retry a failed image up to N times, with exponential backoff, if it failed in a --retry-on stage
+    #[arg(long, default_value_t = 0, value_name = "N")]
+    retry: u32,
+
+    /// stages whose failures are transient and retried: decode, plugin, encode
+    #[arg(long, value_delimiter = ',', default_value = "decode", value_name = "STAGES")]
+    retry_on: Vec<Stage>,
+
+    /// print a per-stage timing breakdown and peak RSS for each image: text or json
+    #[arg(long, value_name = "FORMAT")]
+    timings: Option<TimingsFormat>,
+
+    /// write per-channel histograms, min/max/mean/stddev and clipping percentages of each image
+    /// before and after the plugin chain to this file, one JSON object per line
+    #[arg(long, value_name = "PATH")]
+    stats_out: Option<PathBuf>,
+
+    /// re-run whenever the input, params file or plugin library changes
+    #[arg(long)]
+    watch: bool,
+
+    /// edge length of the tiles compared between watch-mode runs: auto (from the chain's halo,
+    /// the core count and available memory) or a number of pixels
+    #[arg(long, default_value = "auto", value_name = "SIZE")]
+    tile_size: TileSize,
+
+    /// show the result in a window (requires the `preview` feature); refreshed on each run in watch mode
+    #[arg(long)]
+    #[cfg_attr(not(feature = "preview"), arg(value_parser = parse_preview))]
+    preview: bool,
+
+    /// sweep one param (`key=START..END [step N]`, e.g. `radius=1..32 step 2`) and write a CSV
+    /// of plugin timings per value to --output instead of an image
+    #[arg(long, value_name = "SWEEP", conflicts_with_all = ["watch", "preview"])]
+    benchmark_matrix: Option<Sweep>,
+
+    /// timed runs of the plugin chain for each value of --benchmark-matrix
+    #[arg(long, default_value_t = 3, value_name = "N")]
+    benchmark_runs: u32,
+
+    /// add the mean absolute error and PSNR of the output against the input to the CSV
+    #[arg(long, requires = "benchmark_matrix")]
+    benchmark_metrics: bool,
+
+    /// seed for stochastic plugins (random if omitted; the used seed is logged)
+    #[arg(long)]
+    seed: Option<u64>,
 }
 
-fn lib_filename(plugin_name: &str) -> String {
-    if cfg!(target_os = "windows") {
-        format!("{plugin_name}.dll")
-    } else if cfg!(target_os = "macos") {
-        format!("lib{plugin_name}.dylib")
-    } else {
-        format!("lib{plugin_name}.so")
-    }
+/// How often watch mode checks the watched files for changes.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Delay before the first retry of a transiently failed job.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Number of images each batch stage may hand on before it waits for the next stage.
+const PIPELINE_DEPTH: usize = 2;
+
+/// State carried from one job to the next.
+#[derive(Default)]
+struct JobState {
+    /// Buffers reused across batch items.
+    pool: BufferPool,
+    /// Previous frame for incremental re-processing; only kept in watch mode.
+    incremental: Option<Incremental>,
+}
+
+fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
 }
 
 fn main() -> Result<(), AppError> {
-    init_tracing();
+    let cli = Cli::parse();
+
+    match (cli.command, cli.args) {
+        (Some(Command::Bugreport(args)), _) => {
+            init_tracing(None);
+            bugreport(&args)
+        }
+        (Some(Command::Bench(args)), _) => {
+            init_tracing(None);
+            bench(&args)
+        }
+        (Some(Command::Stitch(args)), _) => {
+            init_tracing(None);
+            stitch(&args)
+        }
+        (Some(Command::Hash(args)), _) => {
+            init_tracing(None);
+            hash(&args)
+        }
+        (Some(Command::Palette(args)), _) => {
+            init_tracing(None);
+            palette(&args)
+        }
+        (Some(Command::Assess(args)), _) => {
+            init_tracing(None);
+            assess(&args)
+        }
+        (Some(Command::ContactSheet(args)), _) => {
+            init_tracing(None);
+            contact_sheet(&args)
+        }
+        (Some(Command::Pack(args)), _) => {
+            init_tracing(None);
+            pack(&args)
+        }
+        (Some(Command::Assemble(args)), _) => {
+            init_tracing(None);
+            assemble(&args)
+        }
+        (Some(Command::Icon(args)), _) => {
+            init_tracing(None);
+            icon(&args)
+        }
+        (None, Some(args)) => {
+            let reset = run_record::reset_last_run_dir();
+            init_tracing(reset.as_deref().ok());
+            // Losing the run record must not stop the processing.
+            let run_dir = reset.unwrap_or_else(|e| {
+                tracing::warn!(error = e.to_string(), "cannot reset the last-run directory, run not recorded");
+                run_record::last_run_dir()
+            });
+
+            if args.watch {
+                return watch(&args, &run_dir);
+            }
+
+            let out = run(&args, &run_dir, &mut JobState::default())?;
+            if let (true, Some(out)) = (args.preview, out) {
+                let mut preview = open_preview(&args, &out)?;
+                while preview.is_open() {
+                    preview.refresh()?;
+                }
+            }
+            Ok(())
+        }
+        (None, None) => unreachable!("clap requires arguments or a subcommand"),
+    }
+}
+
+/// Runs the processing once and persists the run record.
+fn run(args: &Args, run_dir: &Path, state: &mut JobState) -> Result<Option<RgbaImage>, AppError> {
+    let mut record = RunRecord::start(std::env::args().collect());
+    let result = process(args, &mut record, state);
+    record.finish(&result);
+    if let Err(e) = record.save(run_dir) {
+        tracing::warn!(error = e.to_string(), "failed to save run record");
+    }
+    if result.is_err() {
+        tracing::error!("run failed; `image_processor bugreport --last` packages it for an issue");
+    }
+    result
+}
+
+/// Re-runs the processing whenever the input, params or plugin library changes.
+///
+/// If every step is local, only the tiles of the input that changed are re-processed.
+fn watch(args: &Args, run_dir: &Path) -> Result<(), AppError> {
+    let Some(input) = args.input.as_path().filter(|p| p.is_file()) else {
+        return Err(AppError::WatchInput(args.input.to_string()));
+    };
+    let plugin_dir = PathBuf::from(&args.plugin_path);
+    let mut files = vec![input.to_path_buf()];
+    files.extend(args.input2.iter().chain(&args.inputs).filter_map(InputSource::as_path).map(Path::to_path_buf));
+    files.extend(args.params.clone());
+    files.extend(plugin_names(args).map(|p| pipeline::plugin_path(&plugin_dir, p)));
+    let mut watcher = FileWatcher::new(files);
+    let mut preview: Option<PreviewWindow> = None;
+    let mut state = JobState { incremental: Some(Incremental::with_tile_size(args.tile_size)), ..JobState::default() };
+
+    tracing::info!("watching for changes, press Ctrl+C to stop");
+    loop {
+        match run(args, run_dir, &mut state) {
+            Ok(Some(out)) if args.preview => match &mut preview {
+                Some(window) => window.show(&out)?,
+                None => preview = Some(open_preview(args, &out)?),
+            },
+            Ok(_) => {}
+            Err(e) => tracing::error!(error = e.to_string(), "run failed, waiting for changes"),
+        }
+
+        let mut last_poll = Instant::now();
+        loop {
+            match &mut preview {
+                Some(window) if !window.is_open() => return Ok(()),
+                Some(window) => window.refresh()?,
+                None => std::thread::sleep(WATCH_POLL_INTERVAL),
+            }
+            if last_poll.elapsed() >= WATCH_POLL_INTERVAL {
+                last_poll = Instant::now();
+                if watcher.changed() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "preview")]
+type PreviewWindow = image_processor::preview::Preview;
+
+#[cfg(feature = "preview")]
+fn open_preview(args: &Args, out: &RgbaImage) -> Result<PreviewWindow, AppError> {
+    let title = plugin_names(args).collect::<Vec<_>>().join("+");
+    PreviewWindow::open(&format!("image_processor - {title}"), out)
+}
+
+#[cfg(not(feature = "preview"))]
+enum PreviewWindow {}
+
+#[cfg(not(feature = "preview"))]
+impl PreviewWindow {
+    fn show(&mut self, _img: &RgbaImage) -> Result<(), AppError> {
+        match *self {}
+    }
+
+    fn refresh(&mut self) -> Result<(), AppError> {
+        match *self {}
+    }
+
+    fn is_open(&self) -> bool {
+        match *self {}
+    }
+}
+
+#[cfg(not(feature = "preview"))]
+fn open_preview(_args: &Args, _out: &RgbaImage) -> Result<PreviewWindow, AppError> {
+    Err(AppError::Preview("built without the `preview` feature".to_string()))
+}
+
+/// Rejects `--preview` while the arguments are parsed, rather than after the run.
+#[cfg(not(feature = "preview"))]
+fn parse_preview(value: &str) -> Result<bool, String> {
+    match value.parse::<bool>() {
+        Ok(true) => Err("built without the `preview` feature".to_string()),
+        other => other.map_err(|e| e.to_string()),
+    }
+}
+
+fn bugreport(args: &BugreportArgs) -> Result<(), AppError> {
+    let run_dir = run_record::last_run_dir();
+    let record = RunRecord::load(&run_dir)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("bugreport-{}.tar.gz", record.started_at)));
+
+    if record.success {
+        tracing::warn!("the last run succeeded; packaging it anyway");
+    }
+
+    let opts = BugReportOptions {
+        downscale: args.downscale,
+        redact: args.redact,
+        skip_input: args.no_input,
+    };
+    write_bug_report(&run_dir, &output, &opts)?;
+
+    tracing::info!(output_file = output.display().to_string(), "bug report written");
+    Ok(())
+}
+
+/// Builds the plugin libraries in release mode, then runs the benchmark suite through cargo.
+fn bench(args: &BenchArgs) -> Result<(), AppError> {
+    let mut build = std::process::Command::new("cargo");
+    build.args(["build", "--release", "-p", "blur_plugin", "-p", "mirror_plugin"]);
+    run_cargo(build)?;
+
+    let mut bench = std::process::Command::new("cargo");
+    bench.args(["bench", "-p", "image_processor", "--bench", "plugins"]);
+    if cfg!(feature = "gpu") {
+        bench.args(["--features", "gpu"]);
+    }
+    bench.arg("--");
+    bench.args(&args.filter);
+    if let Some(name) = &args.save_baseline {
+        bench.args(["--save-baseline", name]);
+    }
+    if let Some(name) = &args.baseline {
+        bench.args(["--baseline", name]);
+    }
+    run_cargo(bench)
+}
+
+fn stitch(args: &StitchArgs) -> Result<(), AppError> {
+    let load_opts = args.load.options();
+    let pool = &mut BufferPool::new();
+    let mut images = Vec::with_capacity(args.inputs.len());
+    for source in &args.inputs {
+        source.check_exists()?;
+        let img = source.load(&load_opts, pool)?;
+        tracing::info!(width = img.width(), height = img.height(), input_file = source.to_string(), "input loaded");
+        images.push(convert::into_rgba8_dithered(img, DitherMode::None, pool));
+    }
+
+    let start = Instant::now();
+    let panorama = stitch::stitch(&images, args.blend)?;
+    tracing::info!(
+        width = panorama.width(),
+        height = panorama.height(),
+        elapsed = ?start.elapsed(),
+        "panorama stitched"
+    );
+    let encode_opts = EncodeOptions { png_compression: args.png_compression };
+    args.output.save(&DynamicImage::ImageRgba8(panorama), &encode_opts)
+}
+
+fn hash(args: &HashArgs) -> Result<(), AppError> {
+    args.input.check_exists()?;
+    let load_opts = args.load.options();
+    let sources = batch::inputs(&args.input, &load_opts.download)?;
+    let pool = &mut BufferPool::new();
+    let mut hashed = Vec::with_capacity(sources.len());
+    for source in &sources {
+        match source.load(&load_opts, pool) {
+            Ok(img) => {
+                let hash = phash::hash(&img, args.algorithm);
+                pool.put_image(img);
+                if !args.dedupe {
+                    println!("{hash}  {source}");
+                }
+                hashed.push(HashedImage { path: source.to_string(), hash });
+            }
+            Err(e) => tracing::error!(input_file = source.to_string(), error = e.to_string(), "hashing failed"),
+        }
+    }
+
+    let failed = sources.len() - hashed.len();
+    if args.dedupe {
+        let report = phash::dedupe(hashed, args.algorithm, args.threshold);
+        tracing::info!(images = report.images, groups = report.groups.len(), "near-duplicates grouped");
+        let json = serde_json::to_string_pretty(&report).map_err(|e| AppError::Io(e.into()))?;
+        match &args.report {
+            Some(path) => std::fs::write(path, json + "\n")?,
+            None => println!("{json}"),
+        }
+    }
+    if failed > 0 {
+        return Err(AppError::BatchFailed { failed, total: sources.len() });
+    }
+    Ok(())
+}
+
+fn palette(args: &PaletteArgs) -> Result<(), AppError> {
+    args.input.check_exists()?;
+    let pool = &mut BufferPool::new();
+    let img = args.input.load(&args.load.options(), pool)?;
+    let palette = palette::extract(&convert::into_rgba8_dithered(img, DitherMode::None, pool), args.colors as usize);
+    tracing::info!(colors = palette.colors.len(), input_file = args.input.to_string(), "palette extracted");
+
+    let text = match args.format {
+        PaletteFormat::Json => serde_json::to_string_pretty(&palette).map_err(|e| AppError::Io(e.into()))? + "\n",
+        PaletteFormat::Css => palette::css(&palette),
+        PaletteFormat::PngSwatch => {
+            let target = OutputTarget::File(args.output.clone().expect("clap requires --output for png-swatch"));
+            let encode_opts = EncodeOptions { png_compression: PngCompression::default() };
+            return target.save(&DynamicImage::ImageRgba8(palette::swatch_image(&palette)), &encode_opts);
+        }
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, text)?,
+        None => print!("{text}"),
+    }
+    Ok(())
+}
+
+fn assess(args: &AssessArgs) -> Result<(), AppError> {
+    args.input.check_exists()?;
+    let load_opts = args.load.options();
+    let sources = batch::inputs(&args.input, &load_opts.download)?;
+    let pool = &mut BufferPool::new();
+    let (mut failed, mut rejected) = (0, 0);
+    for source in &sources {
+        let img = match source.load(&load_opts, pool) {
+            Ok(img) => img,
+            Err(e) => {
+                tracing::error!(input_file = source.to_string(), error = e.to_string(), "assessment failed");
+                failed += 1;
+                continue;
+            }
+        };
+        let scores = assess::assess(&img);
+        pool.put_image(img);
+        let scored = Scored {
+            path: source.to_string(),
+            scores,
+            rejected: args.reject_below.map(|threshold| scores.sharpness < threshold),
+        };
+        rejected += usize::from(scored.rejected == Some(true));
+        if args.json {
+            println!("{}", serde_json::to_string(&scored).map_err(|e| AppError::Io(e.into()))?);
+        } else {
+            println!(
+                "{}  sharpness {:.1}  noise {:.2}  over {:.1}%  under {:.1}%{}",
+                scored.path,
+                scores.sharpness,
+                scores.noise,
+                scores.overexposed,
+                scores.underexposed,
+                if scored.rejected == Some(true) { "  REJECTED" } else { "" }
+            );
+        }
+    }
+
+    if let Some(threshold) = args.reject_below {
+        tracing::info!(rejected, total = sources.len() - failed, threshold, "images below the sharpness threshold");
+    }
+    if failed > 0 {
+        return Err(AppError::BatchFailed { failed, total: sources.len() });
+    }
+    Ok(())
+}
+
+fn contact_sheet(args: &ContactSheetArgs) -> Result<(), AppError> {
+    args.input.check_exists()?;
+    let load_opts = args.load.options();
+    let sources = batch::inputs(&args.input, &load_opts.download)?;
+    let pool = &mut BufferPool::new();
+    let mut cells = Vec::with_capacity(sources.len());
+    for source in &sources {
+        match source.load(&load_opts, pool) {
+            Ok(img) => {
+                // Only the fitted copy is kept, so large directories do not pile up full images.
+                let fitted = output::thumbnail(&convert::into_rgba8_dithered(img, DitherMode::None, pool), args.cell);
+                let name = source.to_string();
+                let name = name.rsplit(['/', '\\']).next().unwrap_or_default().to_string();
+                cells.push((fitted, name));
+            }
+            Err(e) => tracing::error!(input_file = source.to_string(), error = e.to_string(), "image skipped"),
+        }
+    }
+
+    let sheet = output::contact_sheet(&cells, args.columns, args.cell, args.labels);
+    let encode_opts = EncodeOptions { png_compression: args.png_compression };
+    args.output.save(&DynamicImage::ImageRgba8(sheet), &encode_opts)?;
+    tracing::info!(images = cells.len(), output_file = args.output.to_string(), "contact sheet saved");
+
+    let failed = sources.len() - cells.len();
+    if failed > 0 {
+        return Err(AppError::BatchFailed { failed, total: sources.len() });
+    }
+    Ok(())
+}
+
+fn pack(args: &PackArgs) -> Result<(), AppError> {
+    let load_opts = args.load.options();
+    let pool = &mut BufferPool::new();
+    let (mut images, mut sprites) = (Vec::new(), Vec::new());
+    for input in &args.inputs {
+        input.check_exists()?;
+        for source in batch::inputs(input, &load_opts.download)? {
+            let img = convert::into_rgba8_dithered(source.load(&load_opts, pool)?, DitherMode::None, pool);
+            let name = source.to_string();
+            let name = name.rsplit(['/', '\\']).next().unwrap_or_default().to_string();
+            sprites.push(Sprite { name, width: img.width(), height: img.height() });
+            images.push(img);
+        }
+    }
+
+    let opts = PackOptions { max_size: args.max_size, padding: args.padding, power_of_two: args.power_of_two };
+    let packing = atlas::pack(&sprites, &opts)?;
+    let paths: Vec<PathBuf> = (0..packing.atlases.len())
+        .map(|i| if i == 0 { args.output.clone() } else { output::sibling_path(&args.output, &format!("_{i}")) })
+        .collect();
+    let encode_opts = EncodeOptions { png_compression: args.png_compression };
+    for (img, path) in atlas::render(&images, &packing).into_iter().zip(&paths) {
+        OutputTarget::File(path.clone()).save(&DynamicImage::ImageRgba8(img), &encode_opts)?;
+    }
+
+    let files: Vec<String> =
+        paths.iter().map(|p| p.file_name().unwrap_or_default().to_string_lossy().into_owned()).collect();
+    let map_path = args.output.with_extension(args.format.extension());
+    std::fs::write(&map_path, atlas::map(&sprites, &packing, &files, args.format))?;
+    tracing::info!(
+        sprites = sprites.len(),
+        atlases = packing.atlases.len(),
+        map_file = map_path.display().to_string(),
+        "atlases saved"
+    );
+    Ok(())
+}
+
+fn assemble(args: &AssembleArgs) -> Result<(), AppError> {
+    let Some(format) = args.format.or_else(|| AnimationFormat::from_path(&args.output)) else {
+        return Err(AppError::Assemble(format!(
+            "cannot tell the format of {} (use a .gif or .png extension, or --format)",
+            args.output.display()
+        )));
+    };
+    let load_opts = args.load.options();
+    let pool = &mut BufferPool::new();
+    let (mut frames, mut names) = (Vec::new(), Vec::new());
+    for input in &args.inputs {
+        input.check_exists()?;
+        let mut sources = batch::inputs(input, &load_opts.download)?;
+        sources.sort_by(|a, b| animation::natural_cmp(&a.to_string(), &b.to_string()));
+        for source in sources {
+            frames.push(convert::into_rgba8_dithered(source.load(&load_opts, pool)?, DitherMode::None, pool));
+            names.push(source.to_string());
+        }
+    }
+    animation::check_frames(&frames, &names)?;
+
+    let delays = animation::frame_delays(&args.delay, frames.len());
+    let bytes = match format {
+        AnimationFormat::Gif => {
+            let opts = GifOptions { colors: args.colors, palette: args.palette, dither: args.dither };
+            animation::encode_gif(&frames, &delays, args.loops, &opts)?
+        }
+        AnimationFormat::Apng => png::encode_apng(&frames, &delays, args.loops.into(), args.png_compression)?,
+    };
+    std::fs::write(&args.output, bytes)?;
+    tracing::info!(frames = frames.len(), output_file = args.output.display().to_string(), "animation saved");
+    Ok(())
+}
+
+fn icon(args: &IconArgs) -> Result<(), AppError> {
+    args.input.check_exists()?;
+    let pool = &mut BufferPool::new();
+    let img = convert::into_rgba8_dithered(args.input.load(&args.load.options(), pool)?, DitherMode::None, pool);
+    let mut sizes = args.sizes.clone();
+    sizes.sort_unstable();
+    sizes.dedup();
+
+    let icons: Vec<RgbaImage> = sizes.iter().map(|&size| icon::render(&img, size, args.sharpen)).collect();
+    std::fs::write(&args.output, icon::ico(&icons, args.png_compression)?)?;
+    tracing::info!(sizes = ?sizes, output_file = args.output.display().to_string(), "icon saved");
+
+    if let Some(dir) = &args.apple_touch {
+        std::fs::create_dir_all(dir)?;
+        let encode_opts = EncodeOptions { png_compression: args.png_compression };
+        for size in icon::TOUCH_SIZES {
+            let touch = DynamicImage::ImageRgba8(icon::render(&img, size, args.sharpen));
+            for name in icon::touch_names(size) {
+                OutputTarget::File(dir.join(name)).save(&touch, &encode_opts)?;
+            }
+        }
+        tracing::info!(dir = dir.display().to_string(), "apple touch icons saved");
+    }
+    Ok(())
+}
+
+fn run_cargo(mut command: std::process::Command) -> Result<(), AppError> {
+    tracing::info!(command = ?command, "running");
+    let status = command.status()?;
+    if !status.success() {
+        return Err(AppError::BenchFailed(format!("{command:?} exited with {status}")));
+    }
+    Ok(())
+}
+
+fn process(args: &Args, record: &mut RunRecord, state: &mut JobState) -> Result<Option<RgbaImage>, AppError> {
+    record.input = args.input.to_string();
+    record.output = args.output.to_string();
+
+    args.input.check_exists()?;
+    if args.tiles.is_some() && args.output.as_path().is_none() {
+        return Err(AppError::TilesTarget(args.output.to_string()));
+    }
+
+    let specs = step_specs(args, record)?;
+    if let Some(sweep) = &args.benchmark_matrix {
+        return benchmark_matrix(args, sweep, &specs).map(|()| None);
+    }
+
+    let input2 = load_input2(args)?;
+    let inputs = load_inputs(args)?;
+    let steps = load_steps(args, &specs, &args.param, input2.as_ref(), &inputs)?;
+    for step in &steps {
+        record.plugins.push(if step.is_builtin() {
+            PluginIdentity::builtin(&step.name)
+        } else {
+            PluginIdentity::of(&step.name, &step.path)?
+        });
+        if args.working_space == WorkingSpace::Linear && !step.supports(PIXEL_FORMAT_RGBA32F) {
+            tracing::warn!(plugin = step.name, "plugin only accepts 8-bit data; this step runs on sRGB-encoded values");
+        }
+    }
 
-    let args = Args::parse();
+    if let Some(incremental) = &mut state.incremental {
+        incremental.set_chain(chain_identity(&steps, &record.plugins));
+    }
+
+    let seed = args.seed.unwrap_or_else(random_seed);
+    tracing::info!(seed, "using rng seed");
+    let ctx = call_context(args, &steps, seed)?;
+
+    if let Some(path) = &args.stats_out {
+        File::create(path)?;
+    }
+
+    let policy = RetryPolicy { retries: args.retry, on: args.retry_on.clone(), base_delay: RETRY_BASE_DELAY };
+    let jobs = batch::expand(&args.input, &args.output, &args.load.options().download)?;
+    if jobs.len() == 1 && jobs[0].input == args.input {
+        return run_job(args, &jobs[0], &steps, &ctx, &policy, state, record).map(Some);
+    }
+
+    let failed = run_batch(args, &jobs, &steps, &ctx, &policy, state, record);
+    if failed > 0 {
+        return Err(AppError::BatchFailed { failed, total: jobs.len() });
+    }
+    Ok(None)
+}
+
+/// Loads the plugins of the chain, resolving their params with `overrides` and handing each
+/// the secondary and additional inputs, if any.
+fn load_steps(
+    args: &Args,
+    specs: &[StepSpec],
+    overrides: &[ParamOverride],
+    input2: Option<&Arc<Input2>>,
+    inputs: &Arc<[Input2]>,
+) -> Result<Vec<Step>, AppError> {
+    let plugin_dir = PathBuf::from(&args.plugin_path);
+    specs
+        .iter()
+        .map(|spec| {
+            // SAFETY:
+            // - `Step::load` only loads from a path inside the user-selected plugin directory.
+            // - Loading is unsafe because Rust can't verify at compile time that the loaded
+            //   dynamic library exports the expected symbol with the expected ABI/signature.
+            // - If the library is not compatible (wrong symbol, wrong signature, wrong ABI),
+            //   calling through the obtained function pointer would be Undefined Behavior.
+            let step = unsafe { Step::load(&plugin_dir, spec, overrides) }?.with_inputs(Arc::clone(inputs));
+            Ok(match input2 {
+                Some(input2) => step.with_input2(Arc::clone(input2)),
+                None => step,
+            })
+        })
+        .collect()
+}
+
+/// Decodes `--input2` into the working format and alpha convention of the chain.
+fn load_input2(args: &Args) -> Result<Option<Arc<Input2>>, AppError> {
+    args.input2.as_ref().map(|source| load_secondary(args, source).map(Arc::new)).transpose()
+}
+
+/// Decodes the `--inputs` images into the working format and alpha convention of the chain.
+fn load_inputs(args: &Args) -> Result<Arc<[Input2]>, AppError> {
+    args.inputs.iter().map(|source| load_secondary(args, source)).collect()
+}
+
+/// Decodes a secondary input image into the working format and alpha convention of the chain.
+///
+/// The EXIF orientation is applied as for the main input, but `--roi` is not. 16-bit and float
+/// images are kept as float, also as for the main input.
+fn load_secondary(args: &Args, source: &InputSource) -> Result<Input2, AppError> {
+    source.check_exists()?;
+    if source.as_path().is_some_and(Path::is_dir) {
+        return Err(AppError::Input2Directory(source.to_string()));
+    }
+
+    let load_opts = args.load.options();
+    let pool = &mut BufferPool::new();
+    let img = source.load(&load_opts, pool)?;
+    let (width, height) = (img.width(), img.height());
+    let mut data = match args.working_space {
+        WorkingSpace::Srgb if !convert::is_high_bit_depth(img.color()) => {
+            PixelBuffer::Rgba8(convert::into_rgba8_dithered(img, DitherMode::None, pool).into_raw())
+        }
+        _ => PixelBuffer::Rgba32F(convert::into_linear_rgba32f(img, pool)),
+    };
+    if args.alpha == AlphaMode::Premultiplied {
+        data.premultiply();
+    }
+    tracing::info!(width, height, input_file = source.to_string(), "secondary input loaded");
+    Ok(Input2::new(width, height, data, args.alpha.to_ffi()))
+}
+
+/// Builds the call context passed to every step, with GPU handles if a step asks for them.
+fn call_context(args: &Args, steps: &[Step], seed: u64) -> Result<CallContext, AppError> {
+    let mut ctx = CallContext { alpha_mode: args.alpha.to_ffi(), max_threads: args.threads, ..CallContext::new(seed) };
+    if let Some(step) = steps.iter().find(|s| s.wants_gpu()) {
+        (ctx.gpu_device, ctx.gpu_queue) = gpu_handles(&step.name)?;
+    }
+    Ok(ctx)
+}
+
+/// Sweeps one param over its range, timing the plugin chain for each value, and writes a CSV.
+///
+/// The input is decoded once and every run starts from a copy of the decoded pixels, so only
+/// the plugins are measured. With `--benchmark-metrics`, the output of the last run of each
+/// value is also compared with the unprocessed input.
+fn benchmark_matrix(args: &Args, sweep: &Sweep, specs: &[StepSpec]) -> Result<(), AppError> {
+    let Some(csv_path) = args.output.as_path() else {
+        return Err(AppError::BenchmarkTarget(args.output.to_string()));
+    };
+    if args.input.as_path().is_some_and(Path::is_dir) {
+        return Err(AppError::BenchmarkTarget(args.input.to_string()));
+    }
+
+    let job = Job { input: args.input.clone(), output: args.output.clone() };
+    let decoded = decode_job(args, &job, &mut BufferPool::new()).map_err(|e| e.error)?;
+    let reference = args.benchmark_metrics.then(|| finish(args, decoded.clone()).out);
+    let seed = args.seed.unwrap_or_else(random_seed);
+    let input2 = load_input2(args)?;
+    let inputs = load_inputs(args)?;
+
+    let mut rows = Vec::new();
+    for value in sweep.values() {
+        let mut overrides = args.param.clone();
+        overrides.push(sweep.override_with(value.clone()));
+        let steps = load_steps(args, specs, &overrides, input2.as_ref(), &inputs)?;
+        let ctx = call_context(args, &steps, seed)?;
+
+        let mut runs = Vec::new();
+        let mut last = None;
+        for _ in 0..args.benchmark_runs.max(1) {
+            let mut run = decoded.clone();
+            if args.alpha == AlphaMode::Premultiplied {
+                run.data.premultiply();
+            }
+            let start = Instant::now();
+            for step in &steps {
+                run_step(step, &ctx, &mut run.width, &mut run.height, &mut run.data).map_err(|e| e.error)?;
+            }
+            runs.push(start.elapsed());
+            if args.alpha == AlphaMode::Premultiplied {
+                run.data.unpremultiply();
+            }
+            last = Some(run);
+        }
 
-    if !Path::new(&args.input).exists() {
-        return Err(AppError::MissingInput(args.input.display().to_string()));
+        let metrics = match (&reference, last) {
+            (Some(reference), Some(last)) => Some(Metrics::compare(reference.as_raw(), finish(args, last).out.as_raw())),
+            _ => None,
+        };
+        let mean_ms = runs.iter().sum::<Duration>().as_secs_f64() * 1000.0 / runs.len() as f64;
+        tracing::info!(param = sweep.key, value = %value, mean_ms, "benchmarked");
+        rows.push(Row { value, runs, metrics });
     }
-    if !Path::new(&args.params).exists() {
-        return Err(AppError::MissingParams(args.params.display().to_string()));
+
+    std::fs::write(csv_path, sweep::to_csv(&sweep.key, &rows))?;
+    tracing::info!(output_file = csv_path.display().to_string(), "benchmark matrix written");
+    Ok(())
+}
+
+/// Returns pointers to the shared GPU device and queue for plugins that request them.
+#[cfg(feature = "gpu")]
+fn gpu_handles(_plugin: &str) -> Result<(*const c_void, *const c_void), AppError> {
+    let gpu = image_processor::gpu::shared()?;
+    Ok(((&raw const gpu.device).cast(), (&raw const gpu.queue).cast()))
+}
+
+#[cfg(not(feature = "gpu"))]
+fn gpu_handles(plugin: &str) -> Result<(*const c_void, *const c_void), AppError> {
+    tracing::warn!(plugin, "plugin requested a GPU device, but this build has no `gpu` feature");
+    Ok((std::ptr::null(), std::ptr::null()))
+}
+
+/// Builds the step list from either `--plugin`/`--params` or the `--step` arguments.
+fn step_specs(args: &Args, record: &mut RunRecord) -> Result<Vec<StepSpec>, AppError> {
+    let (Some(plugin), Some(params_path)) = (&args.plugin, &args.params) else {
+        return Ok(args.step.clone());
+    };
+
+    record.params_path = params_path.display().to_string();
+    if !params_path.exists() {
+        return Err(AppError::MissingParams(params_path.display().to_string()));
+    }
+    let params = std::fs::read_to_string(params_path).map_err(|_| AppError::InvalidParamsUtf8)?;
+    record.params = Some(params.clone());
+
+    Ok(vec![StepSpec { plugin: plugin.clone(), params }])
+}
+
+/// Plugin names of the chain in the order given on the command line.
+fn plugin_names(args: &Args) -> impl Iterator<Item = &str> {
+    args.plugin.iter().chain(args.step.iter().map(|s| &s.plugin)).map(String::as_str)
+}
+
+/// Identity of the chain for incremental processing: names, canonical params and library hashes.
+fn chain_identity(steps: &[Step], plugins: &[PluginIdentity]) -> String {
+    let parts = steps.iter().zip(plugins).map(|(step, id)| format!("{}:{}:{}", step.name, step.params(), id.fnv1a64));
+    parts.collect::<Vec<_>>().join("\n")
+}
+
+/// Name of the chain used in labels, e.g. `blur_plugin+mirror_plugin`.
+fn chain_name(steps: &[Step]) -> String {
+    steps.iter().map(|s| s.name.as_str()).collect::<Vec<_>>().join("+")
+}
+
+/// Processes one job under the retry policy and records its outcome.
+fn run_job(
+    args: &Args,
+    job: &Job,
+    steps: &[Step],
+    ctx: &CallContext,
+    policy: &RetryPolicy,
+    state: &mut JobState,
+    record: &mut RunRecord,
+) -> Result<RgbaImage, AppError> {
+    let outcome = policy.run(|| process_job(args, job, steps, ctx, state));
+    let result = outcome.result.map_err(|e| e.error);
+    record.items.push(ItemRecord {
+        input: job.input.to_string(),
+        attempts: outcome.attempts,
+        error: result.as_ref().err().map(|e| e.to_string()),
+    });
+    result
+}
+
+/// Runs the jobs of a batch with decoding, plugins and encoding of consecutive images overlapping.
+///
+/// Decoding and encoding run on threads of their own and the plugins on this one, so the plugin
+/// chain does not wait for I/O. Each stage retries its own transient failures; a plugin failure
+/// is retried from a fresh decode, since the plugins have already modified the pixels. Returns
+/// the number of failed jobs.
+fn run_batch(
+    args: &Args,
+    jobs: &[Job],
+    steps: &[Step],
+    ctx: &CallContext,
+    policy: &RetryPolicy,
+    state: &mut JobState,
+    record: &mut RunRecord,
+) -> usize {
+    let label = chain_name(steps);
+    let pool = &mut state.pool;
+    // Output buffers travel back from the encoder to the decoder's pool.
+    let (spent_tx, spent_rx) = mpsc::channel();
+
+    let outcomes = stages::run(
+        jobs,
+        PIPELINE_DEPTH,
+        move |job| {
+            for buf in spent_rx.try_iter() {
+                pool.put_bytes(buf);
+            }
+            let outcome = policy.run(|| decode_job(args, job, pool));
+            (job, outcome.attempts, outcome.result)
+        },
+        |(job, attempts, decoded)| {
+            let mut decoded = match decoded {
+                Ok(decoded) => Some(decoded),
+                Err(e) => return (job, attempts, Err(e)),
+            };
+            let outcome = policy.run(|| {
+                let mut decoded = match decoded.take() {
+                    Some(decoded) => decoded,
+                    None => decode_job(args, job, &mut BufferPool::new())?,
+                };
+                process_decoded(args, job, &mut decoded, steps, ctx, None)?;
+                Ok(decoded)
+            });
+            (job, attempts + outcome.attempts - 1, outcome.result)
+        },
+        |(job, attempts, decoded)| {
+            let Ok(decoded) = decoded else {
+                return (job, attempts, decoded.map(|_| ()));
+            };
+            let mut processed = finish(args, decoded);
+            let outcome = policy.run(|| save_outputs(args, job, &mut processed, &label));
+            let _ = spent_tx.send(processed.out.into_raw());
+            (job, attempts + outcome.attempts - 1, outcome.result)
+        },
+    );
+
+    let mut failed = 0;
+    for (job, attempts, result) in outcomes {
+        let error = result.err().map(|e| e.error);
+        if let Some(e) = &error {
+            tracing::error!(input_file = job.input.to_string(), error = e.to_string(), "batch item failed");
+            failed += 1;
+        }
+        record.items.push(ItemRecord {
+            input: job.input.to_string(),
+            attempts,
+            error: error.map(|e| e.to_string()),
+        });
     }
+    failed
+}
 
-    let params_str =
-        std::fs::read_to_string(&args.params).map_err(|_| AppError::InvalidParamsUtf8)?;
-    let params_c =
-        CString::new(params_str).map_err(|_| AppError::InvalidParamsNul)?;
+fn process_job(
+    args: &Args,
+    job: &Job,
+    steps: &[Step],
+    ctx: &CallContext,
+    state: &mut JobState,
+) -> Result<RgbaImage, StageError> {
+    let mut decoded = decode_job(args, job, &mut state.pool)?;
+    process_decoded(args, job, &mut decoded, steps, ctx, state.incremental.as_mut())?;
+    let mut processed = finish(args, decoded);
+    save_outputs(args, job, &mut processed, &chain_name(steps))?;
+    Ok(processed.out)
+}
 
-    let img = image::open(&args.input)?;
-    let rgba = img.to_rgba8();
-    let (width, height) = rgba.dimensions();
-    let mut data: Vec<u8> = rgba.into_raw();
+/// A decoded input on its way through the plugin chain.
+#[derive(Clone)]
+struct Decoded {
+    color: ColorType,
+    width: u32,
+    height: u32,
+    /// The input as 8-bit RGBA, kept for the montage.
+    original: Option<RgbaImage>,
+    /// Statistics of the input, kept for `--stats-out`.
+    before: Option<ImageStats>,
+    data: PixelBuffer,
+    timings: Timings,
+}
 
-    let mut plugin_path = PathBuf::from(&args.plugin_path);
-    plugin_path.push(lib_filename(&args.plugin));
+/// The result of the plugin chain, ready to be encoded.
+struct Processed {
+    color: ColorType,
+    original: Option<RgbaImage>,
+    before: Option<ImageStats>,
+    /// The result at 8 bits per channel, dithered from `deep` if there is one.
+    out: RgbaImage,
+    /// The sRGB-encoded result of a 16-bit or float input, saved by outputs that store 16 bits.
+    deep: Option<Rgba32FImage>,
+    timings: Timings,
+}
+
+/// Loads the input of `job` and converts it to the working format.
+fn decode_job(args: &Args, job: &Job, pool: &mut BufferPool) -> Result<Decoded, StageError> {
+    let load_opts = LoadOptions { roi: args.roi, ..args.load.options() };
+    let mut timings = Timings::new(job.input.to_string());
 
-    if !plugin_path.exists(){
-        return Err(AppError::MissingPlugin(plugin_path.display().to_string()));
+    let img = timings.time("decode", || job.input.load(&load_opts, pool)).map_err(|e| Stage::Decode.wrap(e))?;
+    let color = img.color();
+    let high_bit_depth = convert::is_high_bit_depth(color);
+    if high_bit_depth && args.working_space == WorkingSpace::Srgb {
+        tracing::debug!("keeping the 16-bit or float input as float until it is encoded");
     }
+    let (width, height) = (img.width(), img.height());
+    let original = args.montage.map(|_| convert::to_rgba8_dithered(&img, args.dither));
+    let before = args.stats_out.is_some().then(|| timings.time("stats", || ImageStats::of_image(&img)));
+    let data = timings.time("convert", || match args.working_space {
+        WorkingSpace::Srgb if !high_bit_depth => {
+            PixelBuffer::Rgba8(convert::into_rgba8_dithered(img, DitherMode::None, pool).into_raw())
+        }
+        _ => PixelBuffer::Rgba32F(convert::into_linear_rgba32f(img, pool)),
+    });
+
+    Ok(Decoded { color, width, height, original, before, data, timings })
+}
+
+/// Runs the plugin chain over the decoded pixels, only over changed tiles if `incremental` allows.
+fn process_decoded(
+    args: &Args,
+    job: &Job,
+    decoded: &mut Decoded,
+    steps: &[Step],
+    ctx: &CallContext,
+    incremental: Option<&mut Incremental>,
+) -> Result<(), StageError> {
+    let Decoded { width, height, data, timings, .. } = decoded;
 
     tracing::info!(
-        width,
-        height,
-        input_file=args.input.display().to_string(),
-        plugin=plugin_path.display().to_string(),
+        width = *width,
+        height = *height,
+        input_file=job.input.to_string(),
+        plugin=chain_name(steps),
         "image processing.."
     );
 
-    // SAFETY:
-    // - We only load from a path we constructed and checked exists.
-    // - `Plugin::load` is unsafe because Rust can't verify at compile time that the loaded
-    //   dynamic library exports the expected symbol with the expected ABI/signature.
-    // - If the library is not compatible (wrong symbol, wrong signature, wrong ABI),
-    //   calling through the obtained function pointer would be Undefined Behavior.
-    let plugin = unsafe { Plugin::load(&plugin_path)? };
-    let process = plugin.process_ptr();
+    if args.alpha == AlphaMode::Premultiplied {
+        data.premultiply();
+    }
+
+    let halo = steps.iter().try_fold(0u32, |sum, step| Some(sum.saturating_add(step.halo()?)));
+    match (incremental, halo) {
+        (Some(incremental), Some(halo)) => timings.time(format!("plugins:{}", chain_name(steps)), || {
+            incremental.run(*width, *height, halo, data, |mut w, mut h, region| {
+                steps.iter().try_for_each(|step| run_step(step, ctx, &mut w, &mut h, region))
+            })
+        })?,
+        (incremental, _) => {
+            if incremental.is_some() {
+                tracing::debug!("chain has steps that are not local; re-processing the whole image");
+            }
+            for step in steps {
+                timings.time(format!("plugin:{}", step.name), || run_step(step, ctx, width, height, data))?;
+            }
+        }
+    }
+
+    if args.alpha == AlphaMode::Premultiplied {
+        data.unpremultiply();
+    }
+    Ok(())
+}
 
-    // SAFETY:
-    // - `data` is a Vec<u8> of length exactly `width * height * 4` (RGBA8), produced by `into_raw()`.
-    // - `data.as_mut_ptr()` is non-null for non-empty vectors; if empty, width/height would be 0
-    //   and the plugin contract should handle it (or we can early-return).
-    // - The pointer remains valid for the duration of the call because `data` is not reallocated
-    //   or moved while the call is in progress.
-    // - `params_c.as_ptr()` is a valid NUL-terminated C string that lives for the duration of the call.
-    // - We assume the plugin follows the FFI contract: it will only read/write within the provided
-    //   buffer bounds and will not store the pointers for later use.
-    unsafe {
-        let code =process(width, height, data.as_mut_ptr(), params_c.as_ptr());
-        if code != 0 {
-            tracing::error!(code, "plugin failed to process");
+/// Converts the processed pixels to the images that are saved.
+///
+/// Float data is encoded to sRGB and smart-cropped at full precision, and only dithered
+/// down to 8 bits (per `--dither`) here, after the whole chain has run.
+fn finish(args: &Args, decoded: Decoded) -> Processed {
+    let Decoded { color, width, height, original, before, data, mut timings } = decoded;
+    let (out, deep) = match data {
+        PixelBuffer::Rgba8(bytes) => {
+            let out: ImageBuffer<Rgba<u8>, Vec<u8>> =
+                ImageBuffer::from_raw(width, height, bytes).expect("Invalid RGBA buffer length");
+            let out = match args.smart_crop {
+                Some(size) => timings.time("smart-crop", || smart_crop::smart_crop(&out, size, args.smart_crop_faces)),
+                None => out,
+            };
+            (out, None)
         }
+        PixelBuffer::Rgba32F(linear) => {
+            let encoded = timings.time("to-srgb", || convert::linear_to_srgb32f(linear, width, height));
+            let encoded = DynamicImage::ImageRgba32F(encoded);
+            let encoded = match args.smart_crop {
+                Some(size) => timings.time("smart-crop", || {
+                    smart_crop::smart_crop_dynamic(&encoded, size, args.smart_crop_faces)
+                }),
+                None => encoded,
+            };
+            let out = timings.time("dither", || convert::to_rgba8_dithered(&encoded, args.dither));
+            (out, convert::is_high_bit_depth(color).then(|| encoded.into_rgba32f()))
+        }
+    };
+    Processed { color, original, before, out, deep, timings }
+}
+
+/// Writes the output of `job` and its thumbnail and montage, then prints the timings.
+///
+/// `label` names the chain in the montage.
+fn save_outputs(args: &Args, job: &Job, processed: &mut Processed, label: &str) -> Result<(), StageError> {
+    let encode_opts = EncodeOptions { png_compression: args.png_compression };
+    let Processed { color, original, before, out, deep, timings } = processed;
+    let color = *color;
+
+    if let Some(layout) = args.tiles {
+        let path =
+            job.output.as_path().ok_or_else(|| Stage::Encode.wrap(AppError::TilesTarget(job.output.to_string())))?;
+        let default_edge = match layout {
+            TileLayout::Dzi => pyramid::DZI_TILE_SIZE,
+            TileLayout::Xyz => pyramid::XYZ_TILE_SIZE,
+        };
+        let opts = PyramidOptions {
+            layout,
+            tile_size: args.tiles_edge.unwrap_or(default_edge),
+            overlap: args.tiles_overlap,
+        };
+        let summary = timings
+            .time("tiles", || pyramid::write(&output::restore_color_type(out, color), path, &opts, &encode_opts))
+            .map_err(|e| Stage::Encode.wrap(e))?;
+        tracing::info!(
+            levels = summary.levels,
+            tiles = summary.tiles,
+            output_file = summary.path.display().to_string(),
+            "tile pyramid saved"
+        );
+    } else {
+        timings
+            .time("encode", || {
+                let img = match deep {
+                    Some(deep) if job.output.stores_16_bit() => output::restore_color_type16(deep, color),
+                    _ => output::restore_color_type(out, color),
+                };
+                job.output.save(&img, &encode_opts)
+            })
+            .map_err(|e| Stage::Encode.wrap(e))?;
+
+        tracing::info!(output_file=job.output.to_string(), "output file saved");
     }
 
+    if let Some(size) = args.thumbnail {
+        let thumb = job.output.sibling("_thumb");
+        timings.time("thumbnail", || {
+            let small = output::thumbnail(out, size);
+            thumb.save(&output::restore_color_type(&small, color), &encode_opts)
+        })
+        .map_err(|e| Stage::Encode.wrap(e))?;
+        tracing::info!(thumbnail_file = thumb.to_string(), "thumbnail saved");
+    }
 
-    let out: ImageBuffer<Rgba<u8>, Vec<u8>> =
-        ImageBuffer::from_raw(width, height, data).expect("Invalid RGBA buffer length");
-    out.save(&args.output)?;
+    if let (Some(_), Some(original)) = (args.montage, &original)
+        && original.dimensions() != out.dimensions()
+    {
+        tracing::warn!("the chain changed the image size; skipping the montage");
+    } else if let (Some(mode), Some(original)) = (args.montage, &original) {
+        let target = job.output.sibling("_montage");
+        timings.time("montage", || {
+            let montage = output::montage(original, out, mode, ["original", label]);
+            target.save(&DynamicImage::ImageRgba8(montage), &encode_opts)
+        })
+        .map_err(|e| Stage::Encode.wrap(e))?;
+        tracing::info!(montage_file = target.to_string(), "montage saved");
+    }
 
-    tracing::info!(output_file=args.output.display().to_string(), "output file saved");
+    if let (Some(path), Some(before)) = (&args.stats_out, before.clone()) {
+        let record = StatsRecord {
+            input: job.input.to_string(),
+            output: job.output.to_string(),
+            before,
+            after: timings.time("stats", || ImageStats::of(out)),
+        };
+        append_stats(path, &record).map_err(|e| Stage::Encode.wrap(e))?;
+    }
 
+    if let Some(format) = args.timings {
+        print!("{}", timings.render(format));
+        if format == TimingsFormat::Json {
+            println!();
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends `record` as one JSON line to the `--stats-out` file.
+fn append_stats(path: &Path, record: &StatsRecord) -> Result<(), AppError> {
+    let json = serde_json::to_string(record).map_err(|e| AppError::Io(e.into()))?;
+    let mut file = std::fs::OpenOptions::new().append(true).create(true).open(path)?;
+    writeln!(file, "{json}")?;
     Ok(())
 }
 
-fn init_tracing() {
+/// Runs one step, turning a non-zero status into a plugin-stage error; a step that changes the
+/// image size updates `width` and `height`.
+fn run_step(
+    step: &Step,
+    ctx: &CallContext,
+    width: &mut u32,
+    height: &mut u32,
+    data: &mut PixelBuffer,
+) -> Result<(), StageError> {
+    match step.apply(ctx, width, height, data) {
+        0 => Ok(()),
+        code => Err(Stage::Plugin.wrap(AppError::PluginFailed { plugin: step.name.clone(), code })),
+    }
+}
+
+fn init_tracing(log_dir: Option<&Path>) {
     let filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("info"));
-    fmt().with_env_filter(filter).init();
+
+    let log_file = log_dir.and_then(|dir| File::create(dir.join(run_record::LOG_FILE)).ok());
+    let file_layer = log_file.map(|file| fmt::layer().with_ansi(false).with_writer(Mutex::new(file)));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(file_layer)
+        .init();
 }
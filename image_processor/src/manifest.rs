@@ -0,0 +1,40 @@
+use serde::Deserialize;
+use toml::Table;
+
+use crate::error::AppError;
+
+/// Metadata a plugin embeds via its optional `plugin_manifest` export.
+///
+/// The export returns a NUL-terminated TOML document such as:
+///
+/// ```toml
+/// name = "blur_plugin"
+/// version = "0.1.0"
+/// description = "Weighted box blur"
+///
+/// [defaults]
+/// radius = 3
+/// iterations = 1
+/// ```
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PluginManifest {
+    /// Plugin name.
+    #[serde(default)]
+    pub name: String,
+    /// Plugin version.
+    #[serde(default)]
+    pub version: String,
+    /// One-line description.
+    #[serde(default)]
+    pub description: String,
+    /// Default params, applied before the params file and CLI overrides.
+    #[serde(default)]
+    pub defaults: Table,
+}
+
+impl PluginManifest {
+    /// Parses a manifest document.
+    pub fn parse(text: &str) -> Result<Self, AppError> {
+        toml::from_str(text).map_err(|e| AppError::InvalidManifest(e.to_string()))
+    }
+}
@@ -0,0 +1,397 @@
+use image::{ColorType, DynamicImage, ImageFormat, Primitive, Rgba, Rgba32FImage, RgbaImage};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::AppError;
+use crate::font;
+#[cfg(feature = "s3")]
+use crate::input::DownloadLimits;
+use crate::png::{self, PngCompression};
+use crate::raw;
+use crate::svg;
+#[cfg(feature = "s3")]
+use crate::s3::{S3Client, S3Uri};
+
+/// Width in pixels of the divider line between the two montage panels.
+const DIVIDER_WIDTH: u32 = 4;
+
+/// Padding in pixels around labels in the montage label bar.
+const LABEL_PADDING: u32 = 4;
+
+/// Gap in pixels between contact sheet cells and around the sheet.
+const SHEET_GAP: u32 = 8;
+
+const BAR_COLOR: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const DIVIDER_COLOR: Rgba<u8> = Rgba([230, 230, 230, 255]);
+const LABEL_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// Layout of a before/after montage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MontageMode {
+    /// Original and processed images placed next to each other.
+    SideBySide,
+    /// A single frame showing the left half of the original and the right
+    /// half of the processed image, split by a divider.
+    Slider,
+}
+
+impl FromStr for MontageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "side-by-side" => Ok(Self::SideBySide),
+            "slider" => Ok(Self::Slider),
+            other => Err(format!("unknown montage mode `{other}` (expected side-by-side or slider)")),
+        }
+    }
+}
+
+/// Options controlling how outputs are encoded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodeOptions {
+    /// Compression effort for PNG outputs.
+    pub png_compression: PngCompression,
+}
+
+/// Where an output image is written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputTarget {
+    /// A local file (or, for batch runs, a directory).
+    File(PathBuf),
+    /// An object (or, for batch runs, a key prefix) in S3-compatible storage.
+    #[cfg(feature = "s3")]
+    S3(S3Uri),
+}
+
+impl FromStr for OutputTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("s3://") {
+            #[cfg(feature = "s3")]
+            return s.parse().map(Self::S3);
+            #[cfg(not(feature = "s3"))]
+            return Err("s3:// outputs require the `s3` feature".to_string());
+        }
+        Ok(Self::File(PathBuf::from(s)))
+    }
+}
+
+impl fmt::Display for OutputTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            #[cfg(feature = "s3")]
+            Self::S3(uri) => write!(f, "{uri}"),
+        }
+    }
+}
+
+impl OutputTarget {
+    /// Returns the local path of a file output.
+    pub fn as_path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) => Some(path),
+            #[cfg(feature = "s3")]
+            Self::S3(_) => None,
+        }
+    }
+
+    /// Returns the target with `suffix` appended to the file stem (see [`sibling_path`]).
+    pub fn sibling(&self, suffix: &str) -> Self {
+        match self {
+            Self::File(path) => Self::File(sibling_path(path, suffix)),
+            #[cfg(feature = "s3")]
+            Self::S3(uri) => {
+                let key = sibling_path(Path::new(&uri.key), suffix);
+                Self::S3(S3Uri { bucket: uri.bucket.clone(), key: key.to_string_lossy().into_owned() })
+            }
+        }
+    }
+
+    /// Returns `true` if the format of the target, chosen by extension, stores 16 bits per channel.
+    pub fn stores_16_bit(&self) -> bool {
+        let path = match self {
+            Self::File(path) => path.as_path(),
+            #[cfg(feature = "s3")]
+            Self::S3(uri) => Path::new(&uri.key),
+        };
+        matches!(ImageFormat::from_path(path), Ok(ImageFormat::Png | ImageFormat::Tiff))
+    }
+
+    /// Encodes and writes `img`, choosing the format from the file extension.
+    ///
+    /// PNG outputs are encoded with [`png::encode`].
+    pub fn save(&self, img: &DynamicImage, opts: &EncodeOptions) -> Result<(), AppError> {
+        match self {
+            Self::File(path) if ImageFormat::from_path(path).ok() == Some(ImageFormat::Png) => {
+                Ok(std::fs::write(path, png::encode(img, opts.png_compression)?)?)
+            }
+            Self::File(path) => Ok(img.save(path)?),
+            #[cfg(feature = "s3")]
+            Self::S3(uri) => {
+                let format = ImageFormat::from_path(&uri.key)?;
+                let encoded = if format == ImageFormat::Png {
+                    png::encode(img, opts.png_compression)?
+                } else {
+                    let mut encoded = std::io::Cursor::new(Vec::new());
+                    img.write_to(&mut encoded, format)?;
+                    encoded.into_inner()
+                };
+                S3Client::from_env(&DownloadLimits::default())?.put_object(uri, &encoded)
+            }
+        }
+    }
+}
+
+/// Converts a processed RGBA8 image back to the channel layout of the input.
+///
+/// Grayscale inputs stay grayscale and inputs without alpha stay opaque, unless
+/// the plugin chain introduced color or transparency, in which case the
+/// channel is kept rather than discarding the plugin's work. 16-bit and float
+/// inputs come back as 8-bit here; see [`restore_color_type16`] for formats that
+/// can store more. Palette images are decoded as RGB(A) and are therefore saved as such.
+pub fn restore_color_type(img: &RgbaImage, original: ColorType) -> DynamicImage {
+    let (gray, alpha) = channel_layout(img.as_raw(), original);
+    let rgba = DynamicImage::ImageRgba8(img.clone());
+    match (gray, alpha) {
+        (true, false) => DynamicImage::ImageLuma8(rgba.to_luma8()),
+        (true, true) => DynamicImage::ImageLumaA8(rgba.to_luma_alpha8()),
+        (false, false) => DynamicImage::ImageRgb8(rgba.to_rgb8()),
+        (false, true) => rgba,
+    }
+}
+
+/// Like [`restore_color_type`], but at 16 bits per channel, for the sRGB-encoded float
+/// result of a 16-bit or float input.
+pub fn restore_color_type16(img: &Rgba32FImage, original: ColorType) -> DynamicImage {
+    let rgba = DynamicImage::ImageRgba16(DynamicImage::ImageRgba32F(img.clone()).into_rgba16());
+    let (gray, alpha) = channel_layout(rgba.as_rgba16().expect("converted above").as_raw(), original);
+    match (gray, alpha) {
+        (true, false) => DynamicImage::ImageLuma16(rgba.to_luma16()),
+        (true, true) => DynamicImage::ImageLumaA16(rgba.to_luma_alpha16()),
+        (false, false) => DynamicImage::ImageRgb16(rgba.to_rgb16()),
+        (false, true) => rgba,
+    }
+}
+
+/// Returns whether RGBA `data` can be saved as grayscale and whether it needs alpha,
+/// given the color type of the input it came from.
+fn channel_layout<T: Primitive>(data: &[T], original: ColorType) -> (bool, bool) {
+    let has_color = data.chunks_exact(4).any(|p| p[0] != p[1] || p[1] != p[2]);
+    let has_alpha = data.chunks_exact(4).any(|p| p[3] != T::DEFAULT_MAX_VALUE);
+    (!original.has_color() && !has_color, original.has_alpha() || has_alpha)
+}
+
+/// Returns `true` if `path` has an extension of an image format the host can decode.
+pub fn is_image_path(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok_and(|f| f.reading_enabled())
+        || (cfg!(feature = "raw") && raw::is_raw_path(path))
+        || (cfg!(feature = "svg") && svg::is_svg_path(path))
+}
+
+/// Returns `path` with `suffix` appended to the file stem, keeping the extension.
+///
+/// `out/foo.png` with suffix `_thumb` becomes `out/foo_thumb.png`.
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}{suffix}.{}", ext.to_string_lossy()),
+        None => format!("{stem}{suffix}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Downscales `img` so that it fits into a `max_side` x `max_side` box, keeping
+/// the aspect ratio. Images already small enough are returned unchanged.
+pub fn thumbnail(img: &RgbaImage, max_side: u32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let max_side = max_side.max(1);
+    if w <= max_side && h <= max_side {
+        return img.clone();
+    }
+
+    let scale = max_side as f64 / w.max(h) as f64;
+    let tw = ((w as f64 * scale).round() as u32).max(1);
+    let th = ((h as f64 * scale).round() as u32).max(1);
+    image::imageops::thumbnail(img, tw, th)
+}
+
+/// Builds a labelled before/after montage of two images with equal dimensions.
+pub fn montage(before: &RgbaImage, after: &RgbaImage, mode: MontageMode, labels: [&str; 2]) -> RgbaImage {
+    let (w, h) = before.dimensions();
+    let scale = (w / 256).clamp(1, 4);
+    let bar = font::text_height(scale) + 2 * LABEL_PADDING;
+
+    let canvas_w = match mode {
+        MontageMode::SideBySide => 2 * w + DIVIDER_WIDTH,
+        MontageMode::Slider => w,
+    };
+    let mut canvas = RgbaImage::from_pixel(canvas_w, h + bar, BAR_COLOR);
+
+    let split = match mode {
+        MontageMode::SideBySide => {
+            image::imageops::replace(&mut canvas, before, 0, bar as i64);
+            image::imageops::replace(&mut canvas, after, (w + DIVIDER_WIDTH) as i64, bar as i64);
+            w
+        }
+        MontageMode::Slider => {
+            let half = w / 2;
+            image::imageops::replace(&mut canvas, before, 0, bar as i64);
+            let right = image::imageops::crop_imm(after, half, 0, w - half, h).to_image();
+            image::imageops::replace(&mut canvas, &right, half as i64, bar as i64);
+            half.saturating_sub(DIVIDER_WIDTH / 2)
+        }
+    };
+
+    for y in 0..canvas.height() {
+        for x in split..(split + DIVIDER_WIDTH).min(canvas_w) {
+            canvas.put_pixel(x, y, DIVIDER_COLOR);
+        }
+    }
+
+    let (left_end, right_start) = (split, split + DIVIDER_WIDTH);
+    let left_x = left_end.saturating_sub(font::text_width(labels[0], scale)) / 2;
+    let right_x = right_start + canvas_w.saturating_sub(right_start).saturating_sub(font::text_width(labels[1], scale)) / 2;
+    font::draw_text(&mut canvas, left_x as i64, LABEL_PADDING as i64, labels[0], scale, LABEL_COLOR);
+    font::draw_text(&mut canvas, right_x as i64, LABEL_PADDING as i64, labels[1], scale, LABEL_COLOR);
+
+    canvas
+}
+
+/// Lays out `images` in a grid of `columns` square cells of `cell` pixels, left to right and
+/// top to bottom.
+///
+/// Each image is shrunk to fit its cell and centred in it. With `labels`, each name is drawn
+/// under its cell, shortened with `...` when it is wider than the cell.
+pub fn contact_sheet(images: &[(RgbaImage, String)], columns: u32, cell: u32, labels: bool) -> RgbaImage {
+    let columns = columns.clamp(1, (images.len() as u32).max(1));
+    let rows = (images.len() as u32).div_ceil(columns).max(1);
+    let bar = if labels { font::text_height(1) + 2 * LABEL_PADDING } else { 0 };
+    let (pitch_x, pitch_y) = (cell + SHEET_GAP, cell + bar + SHEET_GAP);
+    let mut sheet = RgbaImage::from_pixel(columns * pitch_x + SHEET_GAP, rows * pitch_y + SHEET_GAP, BAR_COLOR);
+
+    for (i, (img, name)) in images.iter().enumerate() {
+        let (x0, y0) = (SHEET_GAP + i as u32 % columns * pitch_x, SHEET_GAP + i as u32 / columns * pitch_y);
+        let fitted = thumbnail(img, cell);
+        let (w, h) = fitted.dimensions();
+        image::imageops::overlay(&mut sheet, &fitted, (x0 + (cell - w) / 2) as i64, (y0 + (cell - h) / 2) as i64);
+        if labels {
+            let label = fit_label(name, cell);
+            let x = x0 + (cell - font::text_width(&label, 1)) / 2;
+            font::draw_text(&mut sheet, x as i64, (y0 + cell + LABEL_PADDING) as i64, &label, 1, LABEL_COLOR);
+        }
+    }
+    sheet
+}
+
+/// Shortens `name` with a trailing `...` until it is at most `width` pixels wide at scale 1.
+fn fit_label(name: &str, width: u32) -> String {
+    if font::text_width(name, 1) <= width {
+        return name.to_string();
+    }
+    let mut chars: Vec<char> = name.chars().collect();
+    while !chars.is_empty() && font::text_width(&format!("{}...", chars.iter().collect::<String>()), 1) > width {
+        chars.pop();
+    }
+    format!("{}...", chars.into_iter().collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sibling_path() {
+        assert_eq!(sibling_path(Path::new("out/foo.png"), "_thumb"), PathBuf::from("out/foo_thumb.png"));
+        assert_eq!(sibling_path(Path::new("foo"), "_thumb"), PathBuf::from("foo_thumb"));
+    }
+
+    #[test]
+    fn test_thumbnail_keeps_aspect_ratio() {
+        let img = RgbaImage::new(400, 100);
+        let thumb = thumbnail(&img, 256);
+        assert_eq!(thumb.dimensions(), (256, 64));
+
+        let small = RgbaImage::new(10, 20);
+        assert_eq!(thumbnail(&small, 256).dimensions(), (10, 20));
+    }
+
+    #[test]
+    fn test_montage_dimensions() {
+        let before = RgbaImage::from_pixel(40, 30, Rgba([255, 0, 0, 255]));
+        let after = RgbaImage::from_pixel(40, 30, Rgba([0, 0, 255, 255]));
+        let bar = font::text_height(1) + 2 * LABEL_PADDING;
+
+        let side = montage(&before, &after, MontageMode::SideBySide, ["a", "b"]);
+        assert_eq!(side.dimensions(), (80 + DIVIDER_WIDTH, 30 + bar));
+        assert_eq!(side.get_pixel(0, bar + 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(side.get_pixel(79 + DIVIDER_WIDTH, bar + 1), &Rgba([0, 0, 255, 255]));
+
+        let slider = montage(&before, &after, MontageMode::Slider, ["a", "b"]);
+        assert_eq!(slider.dimensions(), (40, 30 + bar));
+        assert_eq!(slider.get_pixel(0, bar + 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(slider.get_pixel(39, bar + 1), &Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn test_contact_sheet_layout() {
+        let images: Vec<_> = (0..5)
+            .map(|i| (RgbaImage::from_pixel(100, 50, Rgba([i * 50, 0, 0, 255])), format!("image_{i}.png")))
+            .collect();
+        let bar = font::text_height(1) + 2 * LABEL_PADDING;
+
+        let sheet = contact_sheet(&images, 3, 40, true);
+        assert_eq!(sheet.dimensions(), (3 * 48 + SHEET_GAP, 2 * (48 + bar) + SHEET_GAP));
+        // The fifth image sits in the middle of the second row, fitted to 40x20 and centred.
+        let (x, y) = (SHEET_GAP + 48 + 20, SHEET_GAP + 48 + bar + 20);
+        assert_eq!(sheet.get_pixel(x, y), &Rgba([200, 0, 0, 255]));
+        assert_eq!(sheet.get_pixel(x, y - 11), &BAR_COLOR);
+
+        assert_eq!(contact_sheet(&images[..2], 6, 40, false).dimensions(), (2 * 48 + SHEET_GAP, 48 + SHEET_GAP));
+    }
+
+    #[test]
+    fn test_fit_label() {
+        assert_eq!(fit_label("a.png", 60), "a.png");
+        let label = fit_label("a_very_long_file_name.png", 60);
+        assert_eq!(label, "a_very_...");
+        assert!(font::text_width(&label, 1) <= 60);
+    }
+
+    #[test]
+    fn test_restore_color_type() {
+        let gray = RgbaImage::from_pixel(2, 2, Rgba([10, 10, 10, 255]));
+        assert_eq!(restore_color_type(&gray, ColorType::L8).color(), ColorType::L8);
+        assert_eq!(restore_color_type(&gray, ColorType::La8).color(), ColorType::La8);
+        assert_eq!(restore_color_type(&gray, ColorType::Rgb16).color(), ColorType::Rgb8);
+
+        let tinted = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 10, 255]));
+        assert_eq!(restore_color_type(&tinted, ColorType::L8).color(), ColorType::Rgb8);
+
+        let transparent = RgbaImage::from_pixel(2, 2, Rgba([10, 20, 10, 0]));
+        assert_eq!(restore_color_type(&transparent, ColorType::Rgb8).color(), ColorType::Rgba8);
+
+        let deep_gray = Rgba32FImage::from_pixel(2, 2, Rgba([0.5, 0.5, 0.5, 1.0]));
+        let out = restore_color_type16(&deep_gray, ColorType::L16);
+        assert_eq!(out.color(), ColorType::L16);
+        assert_eq!(out.as_luma16().unwrap().get_pixel(0, 0)[0], 32768);
+        let deep_tinted = Rgba32FImage::from_pixel(2, 2, Rgba([0.5, 0.6, 0.5, 0.5]));
+        assert_eq!(restore_color_type16(&deep_tinted, ColorType::Rgb16).color(), ColorType::Rgba16);
+    }
+
+    #[test]
+    fn test_stores_16_bit() {
+        assert!(OutputTarget::File("out.png".into()).stores_16_bit());
+        assert!(OutputTarget::File("out.tif".into()).stores_16_bit());
+        assert!(!OutputTarget::File("out.jpg".into()).stores_16_bit());
+    }
+
+    #[test]
+    fn test_montage_mode_from_str() {
+        assert_eq!("slider".parse::<MontageMode>(), Ok(MontageMode::Slider));
+        assert!("diagonal".parse::<MontageMode>().is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use image::{Rgba, RgbaImage};
+use plugin_sdk::Rng;
+use serde::Serialize;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::convert::{linear_to_srgb, srgb_to_linear};
+use crate::font;
+use crate::output;
+
+/// Longest side the input is reduced to before clustering.
+const SAMPLE_SIDE: u32 = 200;
+/// Pixels with less alpha than this are left out.
+const MIN_ALPHA: u8 = 128;
+/// Upper bound on k-means iterations.
+const MAX_ITERATIONS: usize = 100;
+/// Clustering stops once no centre moves further than this in Lab.
+const CONVERGED: f32 = 0.01;
+/// Side of one square in the PNG swatch.
+const SWATCH_SIZE: u32 = 96;
+/// Distance of the hex label from the bottom left corner of a swatch.
+const LABEL_PADDING: u32 = 6;
+
+/// D65 reference white in XYZ.
+const WHITE: [f32; 3] = [0.950_47, 1.0, 1.088_83];
+
+/// Output format of the `palette` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaletteFormat {
+    /// A JSON object listing the colours.
+    #[default]
+    Json,
+    /// CSS custom properties on `:root`.
+    Css,
+    /// A PNG image with one labelled square per colour.
+    PngSwatch,
+}
+
+impl FromStr for PaletteFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "css" => Ok(Self::Css),
+            "png-swatch" => Ok(Self::PngSwatch),
+            other => Err(format!("unknown palette format `{other}` (expected json, css or png-swatch)")),
+        }
+    }
+}
+
+/// One colour of a palette.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Swatch {
+    /// sRGB colour as `#rrggbb`.
+    pub hex: String,
+    /// sRGB colour.
+    pub rgb: [u8; 3],
+    /// CIELAB colour (D65).
+    pub lab: [f32; 3],
+    /// Fraction of the sampled pixels closest to this colour.
+    pub share: f64,
+}
+
+/// JSON form of a palette.
+#[derive(Debug, Clone, Serialize)]
+pub struct Palette {
+    /// Colours, most common first.
+    pub colors: Vec<Swatch>,
+}
+
+/// Extracts up to `colors` dominant colours of `img` by k-means clustering in CIELAB.
+///
+/// The image is downscaled first and mostly transparent pixels are ignored. Seeding is
+/// deterministic, so the same image always gives the same palette. Fewer colours are returned
+/// when the image has fewer distinct ones, and none when it is fully transparent.
+pub fn extract(img: &RgbaImage, colors: usize) -> Palette {
+    let sample = output::thumbnail(img, SAMPLE_SIDE);
+    let points: Vec<[f32; 3]> =
+        sample.pixels().filter(|p| p[3] >= MIN_ALPHA).map(|p| to_lab([p[0], p[1], p[2]])).collect();
+    if points.is_empty() || colors == 0 {
+        return Palette { colors: Vec::new() };
+    }
+
+    let mut centres = seed(&points, colors);
+    let mut labels = vec![0usize; points.len()];
+    for _ in 0..MAX_ITERATIONS {
+        for (label, p) in labels.iter_mut().zip(&points) {
+            *label = nearest(&centres, p).0;
+        }
+        let mut sums = vec![([0.0f32; 3], 0usize); centres.len()];
+        for (&label, p) in labels.iter().zip(&points) {
+            let (sum, n) = &mut sums[label];
+            (0..3).for_each(|c| sum[c] += p[c]);
+            *n += 1;
+        }
+        let mut moved = 0.0f32;
+        for (centre, (sum, n)) in centres.iter_mut().zip(sums) {
+            if n > 0 {
+                let mean = sum.map(|v| v / n as f32);
+                moved = moved.max(distance(centre, &mean).sqrt());
+                *centre = mean;
+            }
+        }
+        if moved < CONVERGED {
+            break;
+        }
+    }
+
+    let mut counts = vec![0usize; centres.len()];
+    for p in &points {
+        counts[nearest(&centres, p).0] += 1;
+    }
+    let mut swatches: Vec<Swatch> = centres
+        .iter()
+        .zip(counts)
+        .filter(|&(_, n)| n > 0)
+        .map(|(lab, n)| {
+            let rgb = from_lab(lab);
+            Swatch {
+                hex: format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2]),
+                rgb,
+                lab: *lab,
+                share: n as f64 / points.len() as f64,
+            }
+        })
+        .collect();
+    swatches.sort_by(|a, b| b.share.total_cmp(&a.share));
+    Palette { colors: swatches }
+}
+
+/// Picks `k` initial centres with k-means++: each next centre is drawn with probability
+/// proportional to its squared distance from the centres chosen so far.
+fn seed(points: &[[f32; 3]], k: usize) -> Vec<[f32; 3]> {
+    let mut rng = Rng::new(points.len() as u64);
+    let mut centres = vec![points[(rng.next_u64() % points.len() as u64) as usize]];
+    let mut nearest_sq: Vec<f32> = points.iter().map(|p| distance(p, &centres[0])).collect();
+    while centres.len() < k {
+        let total: f64 = nearest_sq.iter().map(|&d| d as f64).sum();
+        if total <= 0.0 {
+            // Every point coincides with a centre already.
+            break;
+        }
+        let mut target = rng.next_f32() as f64 * total;
+        let next = nearest_sq
+            .iter()
+            .position(|&d| {
+                target -= d as f64;
+                target < 0.0
+            })
+            .unwrap_or_else(|| nearest_sq.iter().rposition(|&d| d > 0.0).expect("total is positive"));
+        let centre = points[next];
+        for (d, p) in nearest_sq.iter_mut().zip(points) {
+            *d = d.min(distance(p, &centre));
+        }
+        centres.push(centre);
+    }
+    centres
+}
+
+/// Index of and squared distance to the centre nearest to `p`.
+fn nearest(centres: &[[f32; 3]], p: &[f32; 3]) -> (usize, f32) {
+    centres
+        .iter()
+        .map(|c| distance(c, p))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("at least one centre")
+}
+
+/// Squared Euclidean distance, i.e. squared CIE76 ΔE in Lab.
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c]).powi(2)).sum()
+}
+
+fn to_lab(rgb: [u8; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|v| srgb_to_linear(v as f32 / 255.0));
+    let xyz = [
+        0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+        0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b,
+        0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b,
+    ];
+    let f = |t: f32| if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 };
+    let [fx, fy, fz] = std::array::from_fn(|c| f(xyz[c] / WHITE[c]));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn from_lab(lab: &[f32; 3]) -> [u8; 3] {
+    let fy = (lab[0] + 16.0) / 116.0;
+    let (fx, fz) = (fy + lab[1] / 500.0, fy - lab[2] / 200.0);
+    let f_inv = |f: f32| if f.powi(3) > 216.0 / 24389.0 { f.powi(3) } else { (116.0 * f - 16.0) * 27.0 / 24389.0 };
+    let [x, y, z] = [f_inv(fx) * WHITE[0], f_inv(fy) * WHITE[1], f_inv(fz) * WHITE[2]];
+    let rgb = [
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    ];
+    rgb.map(|v| (linear_to_srgb(v.clamp(0.0, 1.0)) * 255.0).round() as u8)
+}
+
+/// Renders the palette as CSS custom properties, `--palette-1` being the most common colour.
+pub fn css(palette: &Palette) -> String {
+    let mut out = String::from(":root {\n");
+    for (i, swatch) in palette.colors.iter().enumerate() {
+        let _ = writeln!(out, "  --palette-{}: {}; /* {:.1}% */", i + 1, swatch.hex, swatch.share * 100.0);
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Renders the palette as a row of squares labelled with their hex codes, most common first.
+pub fn swatch_image(palette: &Palette) -> RgbaImage {
+    let width = SWATCH_SIZE * palette.colors.len().max(1) as u32;
+    let mut img = RgbaImage::new(width, SWATCH_SIZE);
+    for (i, swatch) in palette.colors.iter().enumerate() {
+        let x0 = i as u32 * SWATCH_SIZE;
+        let [r, g, b] = swatch.rgb;
+        for y in 0..SWATCH_SIZE {
+            for x in x0..x0 + SWATCH_SIZE {
+                img.put_pixel(x, y, Rgba([r, g, b, 255]));
+            }
+        }
+        let label = if swatch.lab[0] > 55.0 { Rgba([0, 0, 0, 255]) } else { Rgba([255, 255, 255, 255]) };
+        let y = SWATCH_SIZE - LABEL_PADDING - font::text_height(1);
+        font::draw_text(&mut img, (x0 + LABEL_PADDING) as i64, y as i64, &swatch.hex, 1, label);
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lab_round_trip() {
+        for rgb in [[0, 0, 0], [255, 255, 255], [200, 30, 90], [12, 180, 240]] {
+            assert_eq!(from_lab(&to_lab(rgb)), rgb);
+        }
+        let white = to_lab([255, 255, 255]);
+        assert!((white[0] - 100.0).abs() < 0.01 && white[1].abs() < 0.01 && white[2].abs() < 0.01);
+    }
+
+    #[test]
+    fn test_extracts_dominant_colors() {
+        // Three quarters red, one quarter blue, and a transparent stripe that must be ignored.
+        let img = RgbaImage::from_fn(40, 40, |x, y| match (x, y) {
+            (_, 0..4) => Rgba([0, 255, 0, 0]),
+            (0..30, _) => Rgba([220, 20, 20, 255]),
+            _ => Rgba([20, 40, 200, 255]),
+        });
+        let palette = extract(&img, 4);
+        assert_eq!(palette.colors.len(), 2);
+        assert_eq!(palette.colors[0].hex, "#dc1414");
+        assert_eq!(palette.colors[1].rgb, [20, 40, 200]);
+        assert!((palette.colors[0].share - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_renders_css_and_swatch() {
+        let img = RgbaImage::from_fn(10, 10, |x, _| if x < 6 { Rgba([255; 4]) } else { Rgba([0, 0, 0, 255]) });
+        let palette = extract(&img, 2);
+        assert_eq!(
+            css(&palette),
+            ":root {\n  --palette-1: #ffffff; /* 60.0% */\n  --palette-2: #000000; /* 40.0% */\n}\n"
+        );
+        let swatch = swatch_image(&palette);
+        assert_eq!(swatch.dimensions(), (2 * SWATCH_SIZE, SWATCH_SIZE));
+        assert_eq!(swatch.get_pixel(SWATCH_SIZE + 1, 1), &Rgba([0, 0, 0, 255]));
+    }
+}
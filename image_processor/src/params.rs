@@ -0,0 +1,166 @@
+use std::str::FromStr;
+use toml::{Table, Value};
+
+use crate::error::AppError;
+
+/// A `--param` override: `key=value` for every step or `plugin:key=value` for one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamOverride {
+    /// Plugin the override is restricted to, if any.
+    pub plugin: Option<String>,
+    /// Dotted key path, e.g. `radius` or `shadow.color`.
+    pub key: String,
+    /// Parsed value; anything that is not a valid TOML value is taken as a string.
+    pub value: Value,
+}
+
+impl FromStr for ParamOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, value) = s.split_once('=').ok_or_else(|| format!("expected key=value, got `{s}`"))?;
+        let (plugin, key) = match target.split_once(':') {
+            Some((plugin, key)) => (Some(plugin.trim().to_string()), key.trim()),
+            None => (None, target.trim()),
+        };
+        if key.is_empty() {
+            return Err(format!("missing key in `{s}`"));
+        }
+        Ok(Self { plugin, key: key.to_string(), value: parse_value(value.trim()) })
+    }
+}
+
+impl ParamOverride {
+    /// Returns `true` if the override applies to `plugin`.
+    pub fn applies_to(&self, plugin: &str) -> bool {
+        self.plugin.as_deref().is_none_or(|p| p == plugin)
+    }
+}
+
+/// Parses a single params layer (params file or step params).
+///
+/// Accepts a JSON object, a TOML document, or plain `key=value` lines whose
+/// values need not be valid TOML (e.g. `mode=box`).
+pub fn parse_layer(text: &str) -> Result<Table, AppError> {
+    if text.trim_start().starts_with('{') {
+        return serde_json::from_str(text).map_err(|e| AppError::InvalidParams(format!("JSON: {e}")));
+    }
+
+    let toml_err = match text.parse::<Table>() {
+        Ok(table) => return Ok(table),
+        Err(e) => e,
+    };
+
+    let mut table = Table::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let Ok(o) = line.parse::<ParamOverride>() else {
+            return Err(AppError::InvalidParams(toml_err.to_string()));
+        };
+        if o.plugin.is_some() {
+            return Err(AppError::InvalidParams(format!("unexpected plugin prefix in `{line}`")));
+        }
+        insert_dotted(&mut table, &o.key, o.value);
+    }
+    Ok(table)
+}
+
+/// Serializes resolved params in the canonical format handed to plugins: a JSON object.
+pub fn to_canonical(params: &Table) -> Result<String, AppError> {
+    serde_json::to_string(params).map_err(|e| AppError::InvalidParams(e.to_string()))
+}
+
+/// Merges `over` into `base`; nested tables merge key by key, other values replace.
+pub fn merge(base: &mut Table, over: Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(nested)) => merge(existing, nested),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Resolves the params of one step: manifest defaults, then the params layer,
+/// then the `--param` overrides that apply to `plugin`.
+pub fn resolve(defaults: Option<&Table>, layer: Table, overrides: &[ParamOverride], plugin: &str) -> Table {
+    let mut params = defaults.cloned().unwrap_or_default();
+    merge(&mut params, layer);
+
+    for o in overrides.iter().filter(|o| o.applies_to(plugin)) {
+        insert_dotted(&mut params, &o.key, o.value.clone());
+    }
+
+    params
+}
+
+/// Sets `value` at the dotted `key` path, creating (or replacing non-table) intermediate entries.
+fn insert_dotted(params: &mut Table, key: &str, value: Value) {
+    let mut parts = key.split('.').peekable();
+    let mut table = params;
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            table.insert(part.to_string(), value);
+            return;
+        }
+        let entry = table.entry(part.to_string()).or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        table = entry.as_table_mut().expect("entry was just made a table");
+    }
+}
+
+fn parse_value(raw: &str) -> Value {
+    format!("v = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolution_order() {
+        let defaults = parse_layer("radius = 1\niterations = 1\n[edge]\nmode = \"clamp\"\nfill = 0").unwrap();
+        let file = parse_layer("radius = 4\n[edge]\nmode = \"wrap\"").unwrap();
+        let overrides = vec![
+            "iterations=3".parse().unwrap(),
+            "other_plugin:radius=9".parse().unwrap(),
+            "edge.fill=255".parse().unwrap(),
+        ];
+
+        let params = resolve(Some(&defaults), file, &overrides, "blur_plugin");
+        assert_eq!(params["radius"].as_integer(), Some(4));
+        assert_eq!(params["iterations"].as_integer(), Some(3));
+        assert_eq!(params["edge"]["mode"].as_str(), Some("wrap"));
+        assert_eq!(params["edge"]["fill"].as_integer(), Some(255));
+    }
+
+    #[test]
+    fn test_override_values() {
+        let o: ParamOverride = "blur_plugin:sigma=1.5".parse().unwrap();
+        assert_eq!(o.plugin.as_deref(), Some("blur_plugin"));
+        assert_eq!(o.value.as_float(), Some(1.5));
+
+        let s: ParamOverride = "mode=box".parse().unwrap();
+        assert_eq!(s.value.as_str(), Some("box"));
+        assert!("novalue".parse::<ParamOverride>().is_err());
+    }
+
+    #[test]
+    fn test_input_formats_normalize_alike() {
+        let expected = parse_layer("radius = 4\nmode = \"box\"\n[edge]\nfill = 0").unwrap();
+        let json = parse_layer(r#"{"radius": 4, "mode": "box", "edge": {"fill": 0}}"#).unwrap();
+        let kv = parse_layer("radius=4\nmode=box\nedge.fill=0").unwrap();
+        assert_eq!(json, expected);
+        assert_eq!(kv, expected);
+
+        assert_eq!(to_canonical(&expected).unwrap(), r#"{"edge":{"fill":0},"mode":"box","radius":4}"#);
+        assert!(parse_layer("{ not json").is_err());
+        assert!(parse_layer("blur_plugin:radius=1").is_err());
+    }
+}
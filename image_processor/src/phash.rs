@@ -0,0 +1,235 @@
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Side of the grayscale copy the DCT of `phash` is computed on.
+const DCT_SIZE: usize = 32;
+
+/// Perceptual hash algorithm of the `hash` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// Signs of the lowest frequencies of a 32x32 DCT; robust to rescaling, compression and
+    /// mild colour changes.
+    #[default]
+    Phash,
+    /// Brightness gradients between horizontally adjacent cells of a 9x8 copy.
+    Dhash,
+    /// Cells of an 8x8 copy brighter than their mean; fastest, but easily fooled.
+    Ahash,
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "phash" => Ok(Self::Phash),
+            "dhash" => Ok(Self::Dhash),
+            "ahash" => Ok(Self::Ahash),
+            other => Err(format!("unknown hash algorithm `{other}` (expected phash, dhash or ahash)")),
+        }
+    }
+}
+
+/// A 64-bit perceptual hash; similar images differ in few bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageHash(pub u64);
+
+impl ImageHash {
+    /// Number of differing bits.
+    pub fn distance(self, other: Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl Serialize for ImageHash {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Hashes `img` with `algorithm`.
+///
+/// All algorithms work on luma of the encoded values, with transparency ignored.
+pub fn hash(img: &DynamicImage, algorithm: HashAlgorithm) -> ImageHash {
+    let bits = match algorithm {
+        HashAlgorithm::Ahash => {
+            let cells = gray(img, 8, 8);
+            let mean = cells.iter().sum::<f32>() / 64.0;
+            cells.iter().map(|&v| v > mean).collect::<Vec<_>>()
+        }
+        HashAlgorithm::Dhash => {
+            let cells = gray(img, 9, 8);
+            (0..64).map(|i| cells[i / 8 * 9 + i % 8] > cells[i / 8 * 9 + i % 8 + 1]).collect()
+        }
+        HashAlgorithm::Phash => {
+            let coefficients = low_frequencies(&gray(img, DCT_SIZE as u32, DCT_SIZE as u32));
+            // The DC term only reflects overall brightness, so it stays out of the median.
+            let mut sorted = coefficients[1..].to_vec();
+            sorted.sort_by(f32::total_cmp);
+            let median = sorted[sorted.len() / 2];
+            coefficients.iter().map(|&v| v > median).collect()
+        }
+    };
+    ImageHash(bits.iter().fold(0, |acc, &bit| acc << 1 | bit as u64))
+}
+
+/// Luma of `img` resized to `width`x`height`.
+fn gray(img: &DynamicImage, width: u32, height: u32) -> Vec<f32> {
+    imageops::resize(&img.to_luma32f(), width, height, FilterType::Triangle).into_raw()
+}
+
+/// The 8x8 lowest-frequency coefficients of the 2D DCT-II of a square plane, row by row.
+fn low_frequencies(plane: &[f32]) -> [f32; 64] {
+    let basis: Vec<f32> = (0..8 * DCT_SIZE)
+        .map(|i| {
+            let (k, n) = (i / DCT_SIZE, i % DCT_SIZE);
+            (std::f32::consts::PI / DCT_SIZE as f32 * (n as f32 + 0.5) * k as f32).cos()
+        })
+        .collect();
+    // Rows first, keeping only the eight lowest horizontal frequencies.
+    let mut rows = vec![0.0f32; DCT_SIZE * 8];
+    for y in 0..DCT_SIZE {
+        for k in 0..8 {
+            rows[y * 8 + k] = (0..DCT_SIZE).map(|x| plane[y * DCT_SIZE + x] * basis[k * DCT_SIZE + x]).sum();
+        }
+    }
+    std::array::from_fn(|i| {
+        let (ky, kx) = (i / 8, i % 8);
+        (0..DCT_SIZE).map(|y| rows[y * 8 + kx] * basis[ky * DCT_SIZE + y]).sum()
+    })
+}
+
+/// An image of a dedupe run and its hash.
+#[derive(Debug, Clone, Serialize)]
+pub struct HashedImage {
+    /// Where the image was read from.
+    pub path: String,
+    /// Hash of the image.
+    pub hash: ImageHash,
+}
+
+/// Near-duplicates found by [`dedupe`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    /// Largest distance between any two members.
+    pub max_distance: u32,
+    /// Members, in input order.
+    pub images: Vec<HashedImage>,
+}
+
+/// JSON report of the `hash --dedupe` subcommand.
+#[derive(Debug, Clone, Serialize)]
+pub struct DedupeReport {
+    /// Algorithm the hashes were computed with.
+    pub algorithm: HashAlgorithm,
+    /// Distance up to which two images count as near-duplicates.
+    pub threshold: u32,
+    /// Number of images hashed.
+    pub images: usize,
+    /// Groups of two or more near-duplicates, largest first.
+    pub groups: Vec<DuplicateGroup>,
+}
+
+/// Groups `images` whose hashes are within `threshold` bits of each other.
+///
+/// Grouping is transitive: when A is near B and B is near C, all three form one group even if
+/// A and C are further apart, which `max_distance` of the group then shows.
+pub fn dedupe(images: Vec<HashedImage>, algorithm: HashAlgorithm, threshold: u32) -> DedupeReport {
+    let count = images.len();
+    let mut parent: Vec<usize> = (0..count).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..count {
+        for j in i + 1..count {
+            if images[i].hash.distance(images[j].hash) <= threshold {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut members: Vec<Vec<HashedImage>> = vec![Vec::new(); count];
+    for (i, image) in images.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        members[r].push(image);
+    }
+    let mut groups: Vec<DuplicateGroup> = members
+        .into_iter()
+        .filter(|group| group.len() > 1)
+        .map(|images| {
+            let max_distance = images
+                .iter()
+                .enumerate()
+                .flat_map(|(i, a)| images[i + 1..].iter().map(|b| a.hash.distance(b.hash)))
+                .max()
+                .unwrap_or(0);
+            DuplicateGroup { max_distance, images }
+        })
+        .collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.images.len()));
+    DedupeReport { algorithm, threshold, images: count, groups }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn scene(width: u32, height: u32, phase: f32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let (u, v) = (x as f32 / width as f32, y as f32 / height as f32);
+            let value = 128.0 + 100.0 * (u * 7.0 + phase).sin() * (v * 5.0 - phase).cos();
+            Rgb([value as u8, (value * 0.8) as u8, 255 - value as u8])
+        }))
+    }
+
+    #[test]
+    fn test_resized_copy_hashes_close() {
+        let original = scene(320, 240, 0.0);
+        let small = original.resize_exact(120, 90, FilterType::Lanczos3);
+        let other = scene(320, 240, 2.5);
+        for algorithm in [HashAlgorithm::Phash, HashAlgorithm::Dhash, HashAlgorithm::Ahash] {
+            let (a, b, c) = (hash(&original, algorithm), hash(&small, algorithm), hash(&other, algorithm));
+            assert!(a.distance(b) <= 4, "{algorithm:?}: {a} vs {b}");
+            assert!(a.distance(c) > 12, "{algorithm:?}: {a} vs {c}");
+        }
+    }
+
+    #[test]
+    fn test_dedupe_groups_transitively() {
+        let image = |path: &str, hash| HashedImage { path: path.into(), hash: ImageHash(hash) };
+        let images = vec![image("a", 0), image("b", 0b111), image("c", u64::MAX), image("d", 0b111_111)];
+        let report = dedupe(images, HashAlgorithm::Phash, 3);
+        assert_eq!(report.images, 4);
+        assert_eq!(report.groups.len(), 1);
+        let group = &report.groups[0];
+        assert_eq!(group.images.iter().map(|i| i.path.as_str()).collect::<Vec<_>>(), ["a", "b", "d"]);
+        assert_eq!(group.max_distance, 6);
+    }
+
+    #[test]
+    fn test_report_serializes_hex_hashes() {
+        let images = vec![
+            HashedImage { path: "x.png".into(), hash: ImageHash(0xff) },
+            HashedImage { path: "y.png".into(), hash: ImageHash(0xfe) },
+        ];
+        let json = serde_json::to_value(dedupe(images, HashAlgorithm::Dhash, 1)).unwrap();
+        assert_eq!(json["algorithm"], "dhash");
+        assert_eq!(json["groups"][0]["images"][1]["hash"], "00000000000000fe");
+    }
+}
@@ -0,0 +1,666 @@
+use plugin_sdk::{
+    CallContext, Capabilities, InputImage, OutputBuffer, ProcessV2Fn, ALPHA_PREMULTIPLIED, CAP_GPU,
+    PIXEL_FORMAT_RGBA32F, PIXEL_FORMAT_RGBA8,
+};
+use std::ffi::{CString, c_void};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+use crate::builtin::Builtin;
+use crate::convert;
+use crate::error::AppError;
+use crate::manifest::PluginManifest;
+use crate::params::{self, ParamOverride};
+use crate::plugin_loader::Plugin;
+
+/// Largest output, in pixels, that a geometry-changing plugin may allocate.
+pub const MAX_OUTPUT_PIXELS: u64 = 1 << 28;
+
+/// A plugin step as given on the command line: `name` or `name:key=value;key=value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepSpec {
+    /// Plugin name without extension.
+    pub plugin: String,
+    /// Params text passed to the plugin.
+    pub params: String,
+}
+
+impl FromStr for StepSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (plugin, params) = s.split_once(':').unwrap_or((s, ""));
+        if plugin.is_empty() {
+            return Err(format!("missing plugin name in step `{s}`"));
+        }
+        let params = params
+            .split(';')
+            .map(str::trim)
+            .filter(|kv| !kv.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(Self { plugin: plugin.to_string(), params })
+    }
+}
+
+/// Returns the platform-specific file name of a plugin library.
+pub fn lib_filename(plugin_name: &str) -> String {
+    if cfg!(target_os = "windows") {
+        format!("{plugin_name}.dll")
+    } else if cfg!(target_os = "macos") {
+        format!("lib{plugin_name}.dylib")
+    } else {
+        format!("lib{plugin_name}.so")
+    }
+}
+
+/// Returns the path of the plugin library `plugin_name` inside `plugin_dir`.
+pub fn plugin_path(plugin_dir: &Path, plugin_name: &str) -> PathBuf {
+    plugin_dir.join(lib_filename(plugin_name))
+}
+
+/// A rectangle of pixels within an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    /// Left edge.
+    pub x: u32,
+    /// Top edge.
+    pub y: u32,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+}
+
+impl FromStr for Rect {
+    type Err = String;
+
+    /// Parses `x,y,width,height`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<u32> = s
+            .split(',')
+            .map(|v| v.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid rectangle `{s}` (expected x,y,width,height)"))?;
+        match parts[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Self { x, y, width, height }),
+            _ => Err(format!("invalid rectangle `{s}` (expected x,y,width,height with a non-zero size)")),
+        }
+    }
+}
+
+impl fmt::Display for Rect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl Rect {
+    /// Returns `true` if the rectangle lies within a `width` x `height` image.
+    pub fn fits(&self, width: u32, height: u32) -> bool {
+        self.x.checked_add(self.width).is_some_and(|right| right <= width)
+            && self.y.checked_add(self.height).is_some_and(|bottom| bottom <= height)
+    }
+
+    /// Grows the rectangle by `margin` on every side, clipped to a `width` x `height` image.
+    pub fn expand(self, margin: u32, width: u32, height: u32) -> Self {
+        let (x, y) = (self.x.saturating_sub(margin), self.y.saturating_sub(margin));
+        let right = (self.x + self.width).saturating_add(margin).min(width);
+        let bottom = (self.y + self.height).saturating_add(margin).min(height);
+        Self { x, y, width: right - x, height: bottom - y }
+    }
+}
+
+/// Pixel data flowing through the plugin chain.
+///
+/// 8-bit data is always sRGB-encoded; float data is always linear light.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PixelBuffer {
+    /// sRGB RGBA8 ([`PIXEL_FORMAT_RGBA8`]).
+    Rgba8(Vec<u8>),
+    /// Linear RGBA32F ([`PIXEL_FORMAT_RGBA32F`]).
+    Rgba32F(Vec<f32>),
+}
+
+impl PixelBuffer {
+    /// Returns the SDK pixel format constant for this buffer.
+    pub fn pixel_format(&self) -> u32 {
+        match self {
+            Self::Rgba8(_) => PIXEL_FORMAT_RGBA8,
+            Self::Rgba32F(_) => PIXEL_FORMAT_RGBA32F,
+        }
+    }
+
+    /// Returns the number of channel values (four per pixel).
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Rgba8(data) => data.len(),
+            Self::Rgba32F(data) => data.len(),
+        }
+    }
+
+    /// Returns `true` if the buffer holds no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a copy in the other format: sRGB RGBA8 decoded to linear, or linear encoded to sRGB.
+    ///
+    /// With [`ALPHA_PREMULTIPLIED`] data, alpha is divided out around the transfer function
+    /// and the copy is premultiplied again.
+    pub fn converted(&self, alpha_mode: u32) -> Self {
+        let premultiplied = alpha_mode == ALPHA_PREMULTIPLIED;
+        let mut src = self.clone();
+        if premultiplied {
+            src.unpremultiply();
+        }
+        let mut out = match &src {
+            Self::Rgba8(data) => Self::Rgba32F(convert::rgba8_to_linear(data)),
+            Self::Rgba32F(data) => Self::Rgba8(convert::linear_to_rgba8(data)),
+        };
+        if premultiplied {
+            out.premultiply();
+        }
+        out
+    }
+
+    /// Multiplies the color channels by alpha in place.
+    pub fn premultiply(&mut self) {
+        match self {
+            Self::Rgba8(data) => convert::premultiply_rgba8(data),
+            Self::Rgba32F(data) => convert::premultiply_rgba32f(data),
+        }
+    }
+
+    /// Divides premultiplied color channels by alpha in place.
+    pub fn unpremultiply(&mut self) {
+        match self {
+            Self::Rgba8(data) => convert::unpremultiply_rgba8(data),
+            Self::Rgba32F(data) => convert::unpremultiply_rgba32f(data),
+        }
+    }
+
+    /// Copies the pixels of `rect` out of this buffer, which is `width` pixels wide.
+    pub fn crop(&self, width: u32, rect: Rect) -> Self {
+        match self {
+            Self::Rgba8(data) => Self::Rgba8(crop_rows(data, width, rect)),
+            Self::Rgba32F(data) => Self::Rgba32F(crop_rows(data, width, rect)),
+        }
+    }
+
+    /// Copies the `inner` part of `src` into this buffer, which is `width` pixels wide.
+    ///
+    /// `src` holds the pixels of `src_rect`; both rectangles are in this buffer's coordinates.
+    ///
+    /// # Panics
+    /// Panics if the formats differ or `inner` is not inside `src_rect`.
+    pub fn paste(&mut self, width: u32, src: &Self, src_rect: Rect, inner: Rect) {
+        assert!(
+            inner.x >= src_rect.x
+                && inner.y >= src_rect.y
+                && inner.x + inner.width <= src_rect.x + src_rect.width
+                && inner.y + inner.height <= src_rect.y + src_rect.height,
+            "pasted rectangle outside the source"
+        );
+        match (self, src) {
+            (Self::Rgba8(dst), Self::Rgba8(src)) => paste_rows(dst, width, src, src_rect, inner),
+            (Self::Rgba32F(dst), Self::Rgba32F(src)) => paste_rows(dst, width, src, src_rect, inner),
+            _ => panic!("pixel format mismatch"),
+        }
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        match self {
+            Self::Rgba8(data) => data.as_ptr(),
+            Self::Rgba32F(data) => data.as_ptr().cast(),
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut u8 {
+        match self {
+            Self::Rgba8(data) => data.as_mut_ptr(),
+            Self::Rgba32F(data) => data.as_mut_ptr().cast(),
+        }
+    }
+}
+
+fn crop_rows<T: Copy>(data: &[T], width: u32, rect: Rect) -> Vec<T> {
+    let (stride, left, len) = (width as usize * 4, rect.x as usize * 4, rect.width as usize * 4);
+    (rect.y as usize..(rect.y + rect.height) as usize)
+        .flat_map(|y| &data[y * stride + left..][..len])
+        .copied()
+        .collect()
+}
+
+fn paste_rows<T: Copy>(dst: &mut [T], width: u32, src: &[T], src_rect: Rect, inner: Rect) {
+    let len = inner.width as usize * 4;
+    for y in inner.y..inner.y + inner.height {
+        let from = ((y - src_rect.y) as usize * src_rect.width as usize + (inner.x - src_rect.x) as usize) * 4;
+        let to = (y as usize * width as usize + inner.x as usize) * 4;
+        dst[to..to + len].copy_from_slice(&src[from..from + len]);
+    }
+}
+
+/// A secondary input image (`--input2` or one of `--inputs`) handed to plugins through the call
+/// context.
+///
+/// Kept in the chain's working format; a copy in the other format is made the first time a
+/// step that only accepts that format asks for it.
+#[derive(Debug)]
+pub struct Input2 {
+    width: u32,
+    height: u32,
+    alpha_mode: u32,
+    rgba8: OnceLock<PixelBuffer>,
+    rgba32f: OnceLock<PixelBuffer>,
+}
+
+impl Input2 {
+    /// Wraps `width` x `height` pixels in the chain's format and alpha convention (`alpha_mode`).
+    ///
+    /// # Panics
+    /// Panics if `data` does not hold exactly `width * height * 4` values.
+    pub fn new(width: u32, height: u32, data: PixelBuffer, alpha_mode: u32) -> Self {
+        assert_eq!(data.len(), width as usize * height as usize * 4, "RGBA buffer length mismatch");
+        let (rgba8, rgba32f) = match data.pixel_format() {
+            PIXEL_FORMAT_RGBA32F => (OnceLock::new(), OnceLock::from(data)),
+            _ => (OnceLock::from(data), OnceLock::new()),
+        };
+        Self { width, height, alpha_mode, rgba8, rgba32f }
+    }
+
+    /// Returns the pixels in `pixel_format`, converting them on first use.
+    fn buffer(&self, pixel_format: u32) -> &PixelBuffer {
+        let (wanted, other) = match pixel_format {
+            PIXEL_FORMAT_RGBA32F => (&self.rgba32f, &self.rgba8),
+            _ => (&self.rgba8, &self.rgba32f),
+        };
+        wanted.get_or_init(|| other.get().expect("one format is always present").converted(self.alpha_mode))
+    }
+}
+
+/// What executes a step.
+enum Backend {
+    Library(Plugin),
+    Builtin(Builtin),
+}
+
+/// A loaded plugin (or built-in) together with the params it runs with.
+pub struct Step {
+    /// Plugin name without extension.
+    pub name: String,
+    /// Path of the loaded library; empty for built-ins.
+    pub path: PathBuf,
+    backend: Backend,
+    params: CString,
+    caps: Capabilities,
+    input2: Option<Arc<Input2>>,
+    inputs: Arc<[Input2]>,
+}
+
+impl Step {
+    /// Loads the plugin named in `spec` from `plugin_dir` and resolves its params:
+    /// manifest defaults, then the step's params, then the matching `overrides`.
+    ///
+    /// Names of host built-ins (see [`Builtin::lookup`]) take precedence over libraries.
+    ///
+    /// # SAFETY
+    /// Same contract as [`Plugin::load`]: the library must export the expected
+    /// symbols with the exact signatures and follow the FFI contract.
+    pub unsafe fn load(plugin_dir: &Path, spec: &StepSpec, overrides: &[ParamOverride]) -> Result<Self, AppError> {
+        let (path, backend) = match Builtin::lookup(&spec.plugin) {
+            Some(builtin) => {
+                builtin.prepare()?;
+                (PathBuf::new(), Backend::Builtin(builtin))
+            }
+            None => {
+                let path = plugin_path(plugin_dir, &spec.plugin);
+                if !path.exists() {
+                    return Err(AppError::MissingPlugin(path.display().to_string()));
+                }
+
+                // SAFETY:
+                // - We only load from a path we constructed and checked exists.
+                // - The caller upholds the `Plugin::load` contract for this library.
+                let plugin = unsafe { Plugin::load(&path)? };
+                (path, Backend::Library(plugin))
+            }
+        };
+
+        let manifest_text = match &backend {
+            Backend::Library(plugin) => plugin.manifest(),
+            Backend::Builtin(builtin) => Some(builtin.manifest()),
+        };
+        let manifest = manifest_text.map(PluginManifest::parse).transpose()?;
+        let layer = params::parse_layer(&spec.params)?;
+        let resolved = params::resolve(manifest.as_ref().map(|m| &m.defaults), layer, overrides, &spec.plugin);
+
+        let text = params::to_canonical(&resolved)?;
+        tracing::debug!(plugin = spec.plugin, params = text, "resolved params");
+        let params = CString::new(text).map_err(|_| AppError::InvalidParamsNul)?;
+
+        let caps = match &backend {
+            Backend::Library(plugin) => query_capabilities(plugin, &params, &spec.plugin),
+            Backend::Builtin(builtin) => {
+                builtin.capabilities(params.to_str().expect("params were built from a String"))
+            }
+        };
+
+        Ok(Self { name: spec.plugin.clone(), path, backend, params, caps, input2: None, inputs: Arc::new([]) })
+    }
+
+    /// Hands `input` to the plugin as its secondary input on every call.
+    ///
+    /// Built-ins ignore it.
+    pub fn with_input2(self, input: Arc<Input2>) -> Self {
+        Self { input2: Some(input), ..self }
+    }
+
+    /// Hands `inputs` to the plugin as its additional inputs on every call.
+    ///
+    /// Built-ins ignore them.
+    pub fn with_inputs(self, inputs: Arc<[Input2]>) -> Self {
+        Self { inputs, ..self }
+    }
+
+    /// Returns `true` if the step is a host built-in rather than a plugin library.
+    pub fn is_builtin(&self) -> bool {
+        matches!(self.backend, Backend::Builtin(_))
+    }
+
+    /// Returns `true` if the plugin asked for the host's GPU device ([`CAP_GPU`]).
+    pub fn wants_gpu(&self) -> bool {
+        matches!(self.backend, Backend::Library(_)) && self.caps.requested(CAP_GPU)
+    }
+
+    /// Returns the resolved params in canonical form.
+    pub fn params(&self) -> &str {
+        self.params.to_str().expect("params were built from a String")
+    }
+
+    /// Returns the step's halo if it declared itself local ([`plugin_sdk::CAP_LOCAL`]).
+    ///
+    /// Steps that change the image size are never local.
+    pub fn halo(&self) -> Option<u32> {
+        if self.changes_geometry() {
+            return None;
+        }
+        self.caps.local_halo()
+    }
+
+    /// Returns `true` if the plugin may change the image size (it exports `process_image_v2`).
+    pub fn changes_geometry(&self) -> bool {
+        matches!(&self.backend, Backend::Library(plugin) if plugin.process_v2_ptr().is_some())
+    }
+
+    /// Returns `true` if the plugin accepts buffers in `pixel_format`.
+    ///
+    /// Plugins without `process_image_ctx` cannot be told the format and only get RGBA8.
+    pub fn supports(&self, pixel_format: u32) -> bool {
+        if let Backend::Library(plugin) = &self.backend
+            && plugin.process_ctx_ptr().is_none()
+            && plugin.process_v2_ptr().is_none()
+        {
+            return pixel_format == PIXEL_FORMAT_RGBA8;
+        }
+        self.caps.supports(pixel_format)
+    }
+
+    /// Runs the plugin over `data` in place and returns its status code.
+    ///
+    /// If the plugin does not accept the buffer's format, the data is converted to the other
+    /// format for the call and back afterwards.
+    ///
+    /// # Panics
+    /// Panics if `data` does not hold exactly `width * height * 4` values, or if the step
+    /// changes the image size; use [`Step::apply`] for chains that may contain such steps.
+    pub fn run(&self, ctx: &CallContext, width: u32, height: u32, data: &mut PixelBuffer) -> u32 {
+        let (mut new_width, mut new_height) = (width, height);
+        let code = self.apply(ctx, &mut new_width, &mut new_height, data);
+        assert!((new_width, new_height) == (width, height), "step `{}` changed the image size", self.name);
+        code
+    }
+
+    /// Runs the plugin over `data` and returns its status code, updating `width` and `height`
+    /// if the step changed the image size.
+    ///
+    /// On a non-zero status, `data` and the size are left as the plugin's in-place processing,
+    /// if any, left them.
+    ///
+    /// # Panics
+    /// Panics if `data` does not hold exactly `width * height * 4` values.
+    pub fn apply(&self, ctx: &CallContext, width: &mut u32, height: &mut u32, data: &mut PixelBuffer) -> u32 {
+        assert_eq!(data.len(), *width as usize * *height as usize * 4, "RGBA buffer length mismatch");
+
+        if !self.supports(data.pixel_format()) {
+            let mut converted = data.converted(ctx.alpha_mode);
+            let code = self.apply(ctx, width, height, &mut converted);
+            *data = converted.converted(ctx.alpha_mode);
+            return code;
+        }
+
+        let plugin = match &self.backend {
+            Backend::Library(plugin) => plugin,
+            Backend::Builtin(builtin) => return builtin.run(self.params(), *width, *height, data),
+        };
+        if let Some(process) = plugin.process_v2_ptr() {
+            return self.run_v2(process, ctx, width, height, data);
+        }
+        let (width, height) = (*width, *height);
+
+        let (ctx, _inputs) = self.plugin_context(ctx, data.pixel_format());
+        let ptr = data.as_mut_ptr();
+
+        // SAFETY:
+        // - `data` holds exactly `width * height * 4` values of the type named by
+        //   `ctx.pixel_format` (asserted above), is properly aligned as it comes from a `Vec`
+        //   of that type, and is exclusively borrowed, so the plugin may read and write all
+        //   of it without aliasing.
+        // - The pointer stays valid for the duration of the call since `data` cannot be moved
+        //   or reallocated while borrowed.
+        // - `self.params` is a valid NUL-terminated C string owned by `self`.
+        // - `ctx` is a fully initialized `CallContext` on our stack that outlives the call; its
+        //   secondary and additional inputs, if any, are owned by `self` and never written to, and
+        //   the array describing the latter lives in `_inputs` until the call returns.
+        // - `Step::load`'s contract guarantees the function pointers match the plugin's exports.
+        // - The legacy entry point is only reached with RGBA8 data (see `supports`).
+        unsafe {
+            match (plugin.process_ctx_ptr(), plugin.process_ptr()) {
+                (Some(process), _) => process(&ctx, width, height, ptr, self.params.as_ptr()),
+                (None, Some(process)) => process(width, height, ptr, self.params.as_ptr()),
+                (None, None) => unreachable!("plugins without process_image export process_image_v2"),
+            }
+        }
+    }
+
+    /// Calls a geometry-changing plugin, replacing `data` with the buffer it allocated, if any.
+    fn run_v2(
+        &self,
+        process: ProcessV2Fn,
+        ctx: &CallContext,
+        width: &mut u32,
+        height: &mut u32,
+        data: &mut PixelBuffer,
+    ) -> u32 {
+        let (ctx, _inputs) = self.plugin_context(ctx, data.pixel_format());
+        let mut allocation = Allocation { pixel_format: data.pixel_format(), output: None };
+        let mut output = OutputBuffer { host: (&raw mut allocation).cast(), alloc: alloc_output };
+
+        // SAFETY:
+        // - `data` holds exactly `width * height * 4` values of the type named by
+        //   `ctx.pixel_format` (asserted by `apply`), is aligned as it comes from a `Vec` of that
+        //   type, and is not modified while the plugin reads it.
+        // - `output.host` points to `allocation`, which outlives the call and is only accessed
+        //   through `alloc_output` until the call returns.
+        // - `self.params` is a valid NUL-terminated C string owned by `self`, and `ctx` is a fully
+        //   initialized `CallContext` on our stack that outlives the call; its secondary and
+        //   additional inputs, if any, are owned by `self` and never written to, and the array
+        //   describing the latter lives in `_inputs` until the call returns.
+        // - `Step::load`'s contract guarantees `process` matches the plugin's `process_image_v2`.
+        let code = unsafe { process(&ctx, *width, *height, data.as_ptr(), &mut output, self.params.as_ptr()) };
+
+        if code == 0
+            && let Some((new_width, new_height, buffer)) = allocation.output
+        {
+            (*width, *height, *data) = (new_width, new_height, buffer);
+        }
+        code
+    }
+
+    /// Returns the context for a call into the plugin with data in `pixel_format`, together with
+    /// the array its `inputs` points to, which must be kept alive for the call.
+    ///
+    /// The secondary and additional inputs, if any, are passed in the same format; their pixels
+    /// stay valid for as long as `self` does.
+    fn plugin_context(&self, ctx: &CallContext, pixel_format: u32) -> (CallContext, Vec<InputImage>) {
+        let mut ctx = CallContext { pixel_format, ..*ctx };
+        if !self.wants_gpu() {
+            ctx.gpu_device = std::ptr::null();
+            ctx.gpu_queue = std::ptr::null();
+        }
+        if let Some(input2) = &self.input2 {
+            ctx.input2_data = input2.buffer(pixel_format).as_ptr();
+            (ctx.input2_width, ctx.input2_height) = (input2.width, input2.height);
+        }
+        let inputs: Vec<InputImage> = self
+            .inputs
+            .iter()
+            .map(|input| {
+                InputImage { data: input.buffer(pixel_format).as_ptr(), width: input.width, height: input.height }
+            })
+            .collect();
+        if !inputs.is_empty() {
+            (ctx.inputs, ctx.input_count) = (inputs.as_ptr(), inputs.len() as u32);
+        }
+        (ctx, inputs)
+    }
+}
+
+/// Output of a geometry-changing plugin, allocated on its request by [`alloc_output`].
+struct Allocation {
+    pixel_format: u32,
+    output: Option<(u32, u32, PixelBuffer)>,
+}
+
+/// The host allocator handed to `process_image_v2` through [`OutputBuffer`].
+unsafe extern "C" fn alloc_output(host: *mut c_void, width: u32, height: u32) -> *mut u8 {
+    // SAFETY: `run_v2` passes a pointer to its `Allocation`, which is live and otherwise
+    // untouched for the duration of the plugin call this function is called from.
+    let allocation = unsafe { &mut *host.cast::<Allocation>() };
+    let pixels = width as u64 * height as u64;
+    if pixels == 0 || pixels > MAX_OUTPUT_PIXELS {
+        return std::ptr::null_mut();
+    }
+    let len = pixels as usize * 4;
+    let mut buffer = match allocation.pixel_format {
+        PIXEL_FORMAT_RGBA32F => PixelBuffer::Rgba32F(vec![0.0; len]),
+        _ => PixelBuffer::Rgba8(vec![0; len]),
+    };
+    // Moving the buffer into `allocation` keeps its heap storage, so the pointer stays valid.
+    let ptr = buffer.as_mut_ptr();
+    allocation.output = Some((width, height, buffer));
+    ptr
+}
+
+/// Asks the plugin what it supports, falling back to RGBA8-only defaults.
+fn query_capabilities(plugin: &Plugin, params: &CString, name: &str) -> Capabilities {
+    let mut caps = Capabilities::default();
+    let Some(query) = plugin.capabilities_ptr() else {
+        return caps;
+    };
+
+    // SAFETY:
+    // - `params` is a valid NUL-terminated C string that outlives the call.
+    // - `caps` is a fully initialized `Capabilities` with `struct_size` set for this SDK.
+    // - The `Plugin::load` contract guarantees `query` matches the `CapabilitiesFn` ABI.
+    let code = unsafe { query(params.as_ptr(), &mut caps) };
+    if code != 0 {
+        tracing::warn!(plugin = name, code, "plugin_capabilities failed; assuming RGBA8 only");
+        return Capabilities::default();
+    }
+    if !caps.supports(PIXEL_FORMAT_RGBA8) && !caps.supports(PIXEL_FORMAT_RGBA32F) {
+        tracing::warn!(plugin = name, mask = caps.pixel_formats, "no known pixel format declared; assuming RGBA8 only");
+        return Capabilities::default();
+    }
+    caps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_step_spec() {
+        let spec: StepSpec = "blur_plugin:radius=4;iterations=2".parse().unwrap();
+        assert_eq!(spec.plugin, "blur_plugin");
+        assert_eq!(spec.params, "radius=4\niterations=2");
+
+        let bare: StepSpec = "mirror_plugin".parse().unwrap();
+        assert_eq!(bare.params, "");
+
+        assert!(":radius=1".parse::<StepSpec>().is_err());
+    }
+
+    #[test]
+    fn test_pixel_buffer_conversion_round_trip() {
+        let srgb = PixelBuffer::Rgba8(vec![0, 64, 128, 255, 255, 10, 3, 0]);
+        let linear = srgb.converted(plugin_sdk::ALPHA_STRAIGHT);
+        assert_eq!(linear.pixel_format(), PIXEL_FORMAT_RGBA32F);
+        assert_eq!(linear.converted(plugin_sdk::ALPHA_STRAIGHT), srgb);
+    }
+
+    #[test]
+    fn test_input2_converts_on_demand() {
+        let srgb = PixelBuffer::Rgba8(vec![0, 64, 128, 255, 255, 10, 3, 0]);
+        let input2 = Input2::new(2, 1, srgb.clone(), plugin_sdk::ALPHA_STRAIGHT);
+        assert!(input2.rgba32f.get().is_none());
+        assert_eq!(input2.buffer(PIXEL_FORMAT_RGBA32F), &srgb.converted(plugin_sdk::ALPHA_STRAIGHT));
+        assert_eq!(input2.buffer(PIXEL_FORMAT_RGBA8), &srgb);
+    }
+
+    #[test]
+    fn test_parse_rect() {
+        let rect: Rect = "10, 20,300,40".parse().unwrap();
+        assert_eq!(rect, Rect { x: 10, y: 20, width: 300, height: 40 });
+        assert_eq!(rect.to_string(), "10,20,300,40");
+        assert!(rect.fits(310, 60) && !rect.fits(309, 60));
+        assert!("1,2,3".parse::<Rect>().is_err());
+        assert!("0,0,0,5".parse::<Rect>().is_err());
+    }
+
+    #[test]
+    fn test_alloc_output() {
+        let mut allocation = Allocation { pixel_format: PIXEL_FORMAT_RGBA32F, output: None };
+        let host = (&raw mut allocation).cast();
+        // SAFETY: `host` points to a live `Allocation` that is not otherwise accessed meanwhile.
+        unsafe {
+            assert!(alloc_output(host, 0, 5).is_null());
+            assert!(alloc_output(host, u32::MAX, u32::MAX).is_null());
+            assert!(!alloc_output(host, 2, 3).is_null());
+        }
+        let Some((2, 3, PixelBuffer::Rgba32F(data))) = allocation.output else { panic!("no float output") };
+        assert_eq!(data, vec![0.0; 24]);
+    }
+
+    #[test]
+    fn test_crop_and_paste() {
+        let (width, height) = (5, 4);
+        let image = PixelBuffer::Rgba8((0..width * height * 4).map(|i| i as u8).collect());
+        let outer = Rect { x: 1, y: 1, width: 2, height: 2 }.expand(1, width, height);
+        assert_eq!(outer, Rect { x: 0, y: 0, width: 4, height: 4 });
+
+        let crop = image.crop(width, outer);
+        assert_eq!(crop.len(), 4 * 4 * 4);
+        let mut pasted = PixelBuffer::Rgba8(vec![0; image.len()]);
+        pasted.paste(width, &crop, outer, outer);
+
+        // Everything but the last column, which lies outside `outer`, is copied back.
+        let PixelBuffer::Rgba8(mut expected) = image else { unreachable!() };
+        for y in 0..height as usize {
+            expected[(y * 5 + 4) * 4..][..4].fill(0);
+        }
+        assert_eq!(pasted, PixelBuffer::Rgba8(expected));
+    }
+}
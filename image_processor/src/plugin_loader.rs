@@ -1,4 +1,6 @@
 use libloading::{Library, Symbol};
+use plugin_sdk::{CallContext, CapabilitiesFn, ProcessV2Fn};
+use std::ffi::CStr;
 use std::path::Path;
 
 /// FFI function signature exported by image processing plugins.
@@ -11,18 +13,48 @@ pub type ProcessFn = unsafe extern "C" fn(
     params: *const std::os::raw::c_char
 ) -> u32;
 
+/// Optional context-aware FFI function signature exported as `process_image_ctx`.
+///
+/// Identical to [`ProcessFn`] but additionally receives a [`CallContext`] that is
+/// valid for the duration of the call.
+pub type ProcessCtxFn = unsafe extern "C" fn(
+    ctx: *const CallContext,
+    width: u32,
+    height: u32,
+    rgba_data: *mut u8,
+    params: *const std::os::raw::c_char
+) -> u32;
+
+/// Optional FFI function exported as `plugin_manifest`.
+///
+/// Returns a pointer to a static NUL-terminated UTF-8 TOML document describing
+/// the plugin (see [`crate::manifest::PluginManifest`]).
+pub type ManifestFn = unsafe extern "C" fn() -> *const std::os::raw::c_char;
+
 /// Dynamically loaded image processing plugin.
 pub struct Plugin {
     _lib: Library,
-    process: ProcessFn,
+    process: Option<ProcessFn>,
+    process_ctx: Option<ProcessCtxFn>,
+    process_v2: Option<ProcessV2Fn>,
+    manifest: Option<String>,
+    capabilities: Option<CapabilitiesFn>,
 }
 
 impl Plugin {
     /// Loads a plugin dynamic library and resolves the `process_image` symbol.
     ///
+    /// The `process_image_ctx` symbol is resolved as well if the library exports it.
+    /// `process_image` may be missing if the library exports `process_image_v2` instead.
+    ///
     /// # SAFETY
     /// The caller must ensure that the library at `path`:
     /// - exports a `process_image` symbol with the exact `ProcessFn` ABI and signature,
+    ///   or a `process_image_v2` symbol with the exact [`ProcessV2Fn`] ABI and signature,
+    /// - if it exports `process_image_ctx`, that symbol has the exact `ProcessCtxFn` ABI and signature,
+    /// - if it exports `plugin_manifest`, that symbol has the exact `ManifestFn` ABI and signature
+    ///   and returns a pointer to a static NUL-terminated string,
+    /// - if it exports `plugin_capabilities`, that symbol has the exact [`CapabilitiesFn`] ABI and signature,
     /// - follows the FFI contract for the function (buffer size, lifetimes, no aliasing),
     /// - remains compatible for the lifetime of the returned `Plugin`.
     pub unsafe fn load(path: &Path) -> Result<Self, libloading::Error> {
@@ -34,20 +66,90 @@ impl Plugin {
             //   exports the expected symbols with the correct ABI.
             Library::new(path)?
         };
-        let sym: Symbol<ProcessFn> = unsafe {
+        let process_v2: Option<ProcessV2Fn> = unsafe {
+            // SAFETY:
+            // - `lib` is kept alive inside `Plugin`, so the pointer stays valid.
+            // - The caller must ensure that, if present, `process_image_v2` has the exact
+            //   `ProcessV2Fn` signature and ABI.
+            lib.get::<ProcessV2Fn>(b"process_image_v2").ok().map(|sym| *sym)
+        };
+
+        let sym: Result<Symbol<ProcessFn>, _> = unsafe {
             // SAFETY:
             // - We just loaded `lib`, and it is kept alive inside `Plugin`.
             // - The caller must ensure the library exports `process_image` with the exact `ProcessFn`
             //   signature and ABI; otherwise using the resulting function pointer would be UB.
-            lib.get(b"process_image")?
+            lib.get(b"process_image")
+        };
+        let process: Option<ProcessFn> = match sym {
+            Ok(sym) => Some(*sym),
+            Err(_) if process_v2.is_some() => None,
+            Err(e) => return Err(e),
+        };
+
+        let process_ctx: Option<ProcessCtxFn> = unsafe {
+            // SAFETY:
+            // - `lib` is kept alive inside `Plugin`, so the pointer stays valid.
+            // - The caller must ensure that, if present, `process_image_ctx` has the exact
+            //   `ProcessCtxFn` signature and ABI.
+            lib.get::<ProcessCtxFn>(b"process_image_ctx").ok().map(|sym| *sym)
+        };
+
+        let manifest_fn: Option<ManifestFn> = unsafe {
+            // SAFETY:
+            // - `lib` is still alive here; we call the function right away and copy the result.
+            // - The caller must ensure that, if present, `plugin_manifest` has the exact `ManifestFn`
+            //   signature and ABI.
+            lib.get::<ManifestFn>(b"plugin_manifest").ok().map(|sym| *sym)
+        };
+        let manifest = manifest_fn.and_then(|f| {
+            // SAFETY:
+            // - The caller guarantees `plugin_manifest` returns null or a pointer to a static
+            //   NUL-terminated string, which stays valid while `lib` is loaded.
+            // - The string is copied into an owned `String` before we return.
+            unsafe {
+                let ptr = f();
+                if ptr.is_null() {
+                    None
+                } else {
+                    CStr::from_ptr(ptr).to_str().ok().map(str::to_string)
+                }
+            }
+        });
+
+        let capabilities: Option<CapabilitiesFn> = unsafe {
+            // SAFETY:
+            // - `lib` is kept alive inside `Plugin`, so the pointer stays valid.
+            // - The caller must ensure that, if present, `plugin_capabilities` has the exact
+            //   `CapabilitiesFn` signature and ABI.
+            lib.get::<CapabilitiesFn>(b"plugin_capabilities").ok().map(|sym| *sym)
         };
-        let process: ProcessFn = *sym;
 
-        Ok(Self { _lib: lib, process })
+        Ok(Self { _lib: lib, process, process_ctx, process_v2, manifest, capabilities })
     }
 
-    /// Returns a reference to the plugin's image processing function pointer.
-    pub fn process_ptr(&self) -> ProcessFn {
+    /// Returns the plugin's in-place image processing function pointer, if it exports one.
+    pub fn process_ptr(&self) -> Option<ProcessFn> {
         self.process
     }
+
+    /// Returns the manifest text embedded in the plugin, if it exports one.
+    pub fn manifest(&self) -> Option<&str> {
+        self.manifest.as_deref()
+    }
+
+    /// Returns the capabilities query function pointer, if the plugin exports one.
+    pub fn capabilities_ptr(&self) -> Option<CapabilitiesFn> {
+        self.capabilities
+    }
+
+    /// Returns the context-aware processing function pointer, if the plugin exports one.
+    pub fn process_ctx_ptr(&self) -> Option<ProcessCtxFn> {
+        self.process_ctx
+    }
+
+    /// Returns the geometry-changing processing function pointer, if the plugin exports one.
+    pub fn process_v2_ptr(&self) -> Option<ProcessV2Fn> {
+        self.process_v2
+    }
 }
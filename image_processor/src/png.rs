@@ -0,0 +1,396 @@
+use flate2::{Compress, Compression, FlushCompress, Status};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, RgbaImage};
+use rayon::prelude::*;
+use std::str::FromStr;
+
+use crate::error::AppError;
+
+/// Strips shorter than this are not worth compressing on their own thread.
+const MIN_STRIP_ROWS: usize = 64;
+
+/// Largest payload written into a single `IDAT` chunk.
+const MAX_IDAT_LEN: usize = 1 << 30;
+
+/// Largest number of bytes that can be summed before the Adler-32 sums must be reduced.
+const ADLER_NMAX: usize = 5552;
+const ADLER_BASE: u32 = 65521;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// Compression effort for PNG outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PngCompression {
+    /// Fastest compression with reasonable ratios.
+    #[default]
+    Fast,
+    /// Balanced speed and size (zlib level 6).
+    Default,
+    /// Smallest files (zlib level 9).
+    Best,
+    /// An explicit zlib level from 0 (stored) to 9.
+    Level(u8),
+}
+
+impl FromStr for PngCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fast" => Ok(Self::Fast),
+            "default" => Ok(Self::Default),
+            "best" => Ok(Self::Best),
+            level => match level.parse() {
+                Ok(n @ 0..=9) => Ok(Self::Level(n)),
+                _ => Err(format!("unknown PNG compression `{s}` (expected fast, default, best or 0-9)")),
+            },
+        }
+    }
+}
+
+impl PngCompression {
+    fn zlib_level(self) -> u32 {
+        match self {
+            Self::Fast => 1,
+            Self::Default => 6,
+            Self::Best => 9,
+            Self::Level(n) => n.into(),
+        }
+    }
+
+    fn image_type(self) -> CompressionType {
+        match self {
+            Self::Fast => CompressionType::Fast,
+            Self::Default => CompressionType::Default,
+            Self::Best => CompressionType::Best,
+            Self::Level(0) => CompressionType::Uncompressed,
+            Self::Level(n) => CompressionType::Level(n),
+        }
+    }
+}
+
+/// Encodes `img` as PNG.
+///
+/// 8-bit images large enough to split are filtered and deflated in horizontal strips on the
+/// rayon thread pool; the strips are joined into one zlib stream with sync flushes, so the
+/// output is a regular single-image PNG. Other images go through the `image` crate encoder.
+pub fn encode(img: &DynamicImage, compression: PngCompression) -> Result<Vec<u8>, AppError> {
+    let strips = (img.height() as usize / MIN_STRIP_ROWS).min(rayon::current_num_threads());
+    let Some(color_type) = png_color_type(img).filter(|_| strips > 1) else {
+        let mut out = Vec::new();
+        let encoder = PngEncoder::new_with_quality(&mut out, compression.image_type(), FilterType::Adaptive);
+        img.write_with_encoder(encoder)?;
+        return Ok(out);
+    };
+
+    let bpp = img.color().bytes_per_pixel() as usize;
+    let stride = img.width() as usize * bpp;
+    let raw = img.as_bytes();
+    let height = img.height() as usize;
+    let rows_per_strip = height.div_ceil(strips);
+    let level = compression.zlib_level();
+
+    let segments = (0..height)
+        .step_by(rows_per_strip)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|start| {
+            let end = (start + rows_per_strip).min(height);
+            let zeros = vec![0; stride];
+            let mut scratch = vec![0; stride];
+            let mut filtered = Vec::with_capacity((end - start) * (stride + 1));
+            for y in start..end {
+                let row = &raw[y * stride..(y + 1) * stride];
+                if level == 0 {
+                    // Stored data does not get smaller with filtering.
+                    filtered.push(0);
+                    filtered.extend_from_slice(row);
+                    continue;
+                }
+                let prev = if y > 0 { &raw[(y - 1) * stride..y * stride] } else { &zeros };
+                filter_row(row, prev, bpp, &mut scratch, &mut filtered);
+            }
+            let deflated = deflate_segment(&filtered, level, end == height)?;
+            Ok((deflated, adler32(&filtered), filtered.len()))
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+
+    let mut zlib = vec![0x78, 0x9c];
+    let mut checksum = 1;
+    for (deflated, adler, len) in &segments {
+        zlib.extend_from_slice(deflated);
+        checksum = adler32_combine(checksum, *adler, *len as u64);
+    }
+    zlib.extend_from_slice(&checksum.to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&img.width().to_be_bytes());
+    ihdr.extend_from_slice(&img.height().to_be_bytes());
+    // Bit depth 8, then compression, filter and interlace methods 0.
+    ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    let mut out = Vec::with_capacity(zlib.len() + 64);
+    out.extend_from_slice(&PNG_SIGNATURE);
+    write_chunk(&mut out, b"IHDR", &ihdr);
+    for part in zlib.chunks(MAX_IDAT_LEN) {
+        write_chunk(&mut out, b"IDAT", part);
+    }
+    write_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+/// Encodes `frames`, which must share one size, as an animated PNG.
+///
+/// Each frame covers the whole canvas and replaces the previous one, and is shown for its entry
+/// of `delays_ms` (capped at 65.5 s). `plays` is how often the animation runs, 0 meaning forever.
+/// Viewers without APNG support show the first frame. Frames are compressed on the rayon pool.
+pub fn encode_apng(
+    frames: &[RgbaImage],
+    delays_ms: &[u32],
+    plays: u32,
+    compression: PngCompression,
+) -> Result<Vec<u8>, AppError> {
+    let (width, height) = frames.first().map_or((0, 0), |f| f.dimensions());
+    let level = compression.zlib_level();
+    let streams = frames.par_iter().map(|frame| zlib_rgba(frame, level)).collect::<Result<Vec<_>, AppError>>()?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+    let mut actl = (frames.len() as u32).to_be_bytes().to_vec();
+    actl.extend_from_slice(&plays.to_be_bytes());
+
+    let mut out = Vec::with_capacity(streams.iter().map(Vec::len).sum::<usize>() + 64 * frames.len());
+    out.extend_from_slice(&PNG_SIGNATURE);
+    write_chunk(&mut out, b"IHDR", &ihdr);
+    write_chunk(&mut out, b"acTL", &actl);
+    // fcTL and fdAT chunks share one sequence counter.
+    let mut sequence = 0u32;
+    for (i, zlib) in streams.iter().enumerate() {
+        let mut fctl = Vec::with_capacity(26);
+        fctl.extend_from_slice(&sequence.to_be_bytes());
+        fctl.extend_from_slice(&width.to_be_bytes());
+        fctl.extend_from_slice(&height.to_be_bytes());
+        // Offset 0,0; delay in milliseconds; no disposal, and the frame replaces the canvas.
+        fctl.extend_from_slice(&[0; 8]);
+        fctl.extend_from_slice(&(delays_ms.get(i).copied().unwrap_or(0).min(u16::MAX as u32) as u16).to_be_bytes());
+        fctl.extend_from_slice(&1000u16.to_be_bytes());
+        fctl.extend_from_slice(&[0, 0]);
+        write_chunk(&mut out, b"fcTL", &fctl);
+        sequence += 1;
+        for part in zlib.chunks(MAX_IDAT_LEN) {
+            if i == 0 {
+                write_chunk(&mut out, b"IDAT", part);
+            } else {
+                let mut fdat = Vec::with_capacity(part.len() + 4);
+                fdat.extend_from_slice(&sequence.to_be_bytes());
+                fdat.extend_from_slice(part);
+                write_chunk(&mut out, b"fdAT", &fdat);
+                sequence += 1;
+            }
+        }
+    }
+    write_chunk(&mut out, b"IEND", &[]);
+    Ok(out)
+}
+
+/// Filters and deflates `img` into a single zlib stream.
+fn zlib_rgba(img: &RgbaImage, level: u32) -> Result<Vec<u8>, AppError> {
+    let stride = img.width() as usize * 4;
+    let raw = img.as_raw();
+    let zeros = vec![0; stride];
+    let mut scratch = vec![0; stride];
+    let mut filtered = Vec::with_capacity(img.height() as usize * (stride + 1));
+    for (y, row) in raw.chunks_exact(stride.max(1)).enumerate() {
+        if level == 0 {
+            filtered.push(0);
+            filtered.extend_from_slice(row);
+            continue;
+        }
+        let prev = if y > 0 { &raw[(y - 1) * stride..y * stride] } else { &zeros };
+        filter_row(row, prev, 4, &mut scratch, &mut filtered);
+    }
+    let mut zlib = vec![0x78, 0x9c];
+    zlib.extend_from_slice(&deflate_segment(&filtered, level, true)?);
+    zlib.extend_from_slice(&adler32(&filtered).to_be_bytes());
+    Ok(zlib)
+}
+
+/// Returns the PNG color type of 8-bit images; `None` for the rest.
+fn png_color_type(img: &DynamicImage) -> Option<u8> {
+    match img {
+        DynamicImage::ImageLuma8(_) => Some(0),
+        DynamicImage::ImageRgb8(_) => Some(2),
+        DynamicImage::ImageLumaA8(_) => Some(4),
+        DynamicImage::ImageRgba8(_) => Some(6),
+        _ => None,
+    }
+}
+
+/// Appends `row` filtered with whichever PNG filter gives the smallest sum of absolute
+/// values (the usual adaptive heuristic), preceded by the filter type byte.
+///
+/// `prev` is the unfiltered previous row, all zeros for the first row of the image.
+fn filter_row(row: &[u8], prev: &[u8], bpp: usize, scratch: &mut [u8], out: &mut Vec<u8>) {
+    let mut best = (u64::MAX, 0);
+    for kind in 0..5 {
+        apply_filter(kind, row, prev, bpp, scratch);
+        let cost = scratch.iter().map(|&v| (v as i8).unsigned_abs() as u64).sum();
+        if cost < best.0 {
+            best = (cost, kind);
+        }
+    }
+
+    out.push(best.1);
+    let start = out.len();
+    out.resize(start + row.len(), 0);
+    apply_filter(best.1, row, prev, bpp, &mut out[start..]);
+}
+
+fn apply_filter(kind: u8, row: &[u8], prev: &[u8], bpp: usize, out: &mut [u8]) {
+    let len = row.len();
+    match kind {
+        0 => out.copy_from_slice(row),
+        1 => {
+            out[..bpp].copy_from_slice(&row[..bpp]);
+            for i in bpp..len {
+                out[i] = row[i].wrapping_sub(row[i - bpp]);
+            }
+        }
+        2 => {
+            for i in 0..len {
+                out[i] = row[i].wrapping_sub(prev[i]);
+            }
+        }
+        3 => {
+            for i in 0..bpp {
+                out[i] = row[i].wrapping_sub(prev[i] / 2);
+            }
+            for i in bpp..len {
+                out[i] = row[i].wrapping_sub(((row[i - bpp] as u16 + prev[i] as u16) / 2) as u8);
+            }
+        }
+        _ => {
+            for i in 0..bpp {
+                out[i] = row[i].wrapping_sub(prev[i]);
+            }
+            for i in bpp..len {
+                out[i] = row[i].wrapping_sub(paeth(row[i - bpp], prev[i], prev[i - bpp]));
+            }
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Deflates `data` as raw deflate blocks; all but the last segment end in a sync flush so
+/// the segments can be concatenated into one stream.
+fn deflate_segment(data: &[u8], level: u32, last: bool) -> Result<Vec<u8>, AppError> {
+    let flush = if last { FlushCompress::Finish } else { FlushCompress::Sync };
+    let mut compress = Compress::new(Compression::new(level), false);
+    let mut out = Vec::with_capacity(data.len() / 2 + 1024);
+    loop {
+        let consumed = compress.total_in() as usize;
+        let status = compress
+            .compress_vec(&data[consumed..], &mut out, flush)
+            .map_err(std::io::Error::other)?;
+        let flushed = compress.total_in() as usize == data.len() && out.len() < out.capacity();
+        if status == Status::StreamEnd || (!last && flushed) {
+            return Ok(out);
+        }
+        out.reserve(out.capacity().max(64 * 1024));
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(ADLER_NMAX) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= ADLER_BASE;
+        b %= ADLER_BASE;
+    }
+    (b << 16) | a
+}
+
+/// Returns the Adler-32 of two concatenated inputs from their checksums and the second's length.
+fn adler32_combine(first: u32, second: u32, second_len: u64) -> u32 {
+    let base = ADLER_BASE as u64;
+    let rem = second_len % base;
+    let a1 = (first & 0xffff) as u64;
+    let b1 = (first >> 16) as u64;
+    let (a2, b2) = ((second & 0xffff) as u64, (second >> 16) as u64);
+
+    let a = (a1 + a2 + base - 1) % base;
+    let b = (rem * a1 % base + b1 + b2 + base - rem) % base;
+    ((b << 16) | a) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn test_adler32_combine() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let (head, tail) = data.split_at(7_777);
+        assert_eq!(adler32_combine(adler32(head), adler32(tail), tail.len() as u64), adler32(&data));
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_parallel_encode_round_trip() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(97, 300, |x, y| Rgb([x as u8, (x * y) as u8, y as u8])));
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        for compression in [PngCompression::Fast, PngCompression::Level(0), PngCompression::Best] {
+            let bytes = pool.install(|| encode(&img, compression)).unwrap();
+            let decoded = image::load_from_memory_with_format(&bytes, image::ImageFormat::Png).unwrap();
+            assert_eq!(decoded, img, "{compression:?}");
+        }
+    }
+
+    #[test]
+    fn test_apng_round_trip() {
+        use image::AnimationDecoder;
+        let frames: Vec<RgbaImage> =
+            (0..3u8).map(|i| RgbaImage::from_fn(70, 20, |x, y| image::Rgba([x as u8, y as u8, i * 80, 255]))).collect();
+        let bytes = encode_apng(&frames, &[40, 40, 500], 0, PngCompression::Fast).unwrap();
+        let decoder = image::codecs::png::PngDecoder::new(std::io::Cursor::new(bytes)).unwrap();
+        assert!(decoder.is_apng().unwrap());
+        let decoded = decoder.apng().unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(decoded[2].buffer(), &frames[2]);
+        assert_eq!(decoded[2].delay().numer_denom_ms(), (500, 1));
+    }
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!("best".parse(), Ok(PngCompression::Best));
+        assert_eq!("3".parse(), Ok(PngCompression::Level(3)));
+        assert!("10".parse::<PngCompression>().is_err());
+    }
+}
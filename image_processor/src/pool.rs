@@ -0,0 +1,125 @@
+use image::DynamicImage;
+
+use crate::pipeline::PixelBuffer;
+
+/// Maximum number of idle buffers kept per element type.
+const MAX_IDLE: usize = 4;
+
+/// Decode and scratch buffers reused across the items of a batch run.
+///
+/// Returned buffers keep their capacity, so after the first items the pool holds allocations
+/// sized to the largest image seen so far and later items no longer allocate or fault in pages.
+#[derive(Debug, Default)]
+pub struct BufferPool {
+    bytes: Vec<Vec<u8>>,
+    floats: Vec<Vec<f32>>,
+}
+
+impl BufferPool {
+    /// Creates an empty pool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an empty byte buffer with room for at least `len` values.
+    pub fn take_bytes(&mut self, len: usize) -> Vec<u8> {
+        take(&mut self.bytes, len)
+    }
+
+    /// Returns an empty float buffer with room for at least `len` values.
+    pub fn take_floats(&mut self, len: usize) -> Vec<f32> {
+        take(&mut self.floats, len)
+    }
+
+    /// Returns a byte buffer to the pool.
+    pub fn put_bytes(&mut self, buf: Vec<u8>) {
+        put(&mut self.bytes, buf);
+    }
+
+    /// Returns a float buffer to the pool.
+    pub fn put_floats(&mut self, buf: Vec<f32>) {
+        put(&mut self.floats, buf);
+    }
+
+    /// Returns the storage of a pixel buffer to the pool.
+    pub fn put_pixels(&mut self, buf: PixelBuffer) {
+        match buf {
+            PixelBuffer::Rgba8(data) => self.put_bytes(data),
+            PixelBuffer::Rgba32F(data) => self.put_floats(data),
+        }
+    }
+
+    /// Returns the storage of a decoded image to the pool, if it uses one of the pooled types.
+    pub fn put_image(&mut self, img: DynamicImage) {
+        match img {
+            DynamicImage::ImageLuma8(buf) => self.put_bytes(buf.into_raw()),
+            DynamicImage::ImageLumaA8(buf) => self.put_bytes(buf.into_raw()),
+            DynamicImage::ImageRgb8(buf) => self.put_bytes(buf.into_raw()),
+            DynamicImage::ImageRgba8(buf) => self.put_bytes(buf.into_raw()),
+            DynamicImage::ImageRgb32F(buf) => self.put_floats(buf.into_raw()),
+            DynamicImage::ImageRgba32F(buf) => self.put_floats(buf.into_raw()),
+            _ => {}
+        }
+    }
+}
+
+/// Takes the smallest idle buffer that fits `len`, or else the largest one to grow in place.
+fn take<T>(idle: &mut Vec<Vec<T>>, len: usize) -> Vec<T> {
+    let fitting = idle
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| b.capacity() >= len)
+        .min_by_key(|(_, b)| b.capacity());
+    let pick = fitting.or_else(|| idle.iter().enumerate().max_by_key(|(_, b)| b.capacity())).map(|(i, _)| i);
+
+    let mut buf = pick.map(|i| idle.swap_remove(i)).unwrap_or_default();
+    buf.clear();
+    buf.reserve(len);
+    buf
+}
+
+/// Keeps `buf` for reuse; when the pool is full, the smallest buffer is dropped.
+fn put<T>(idle: &mut Vec<Vec<T>>, buf: Vec<T>) {
+    if buf.capacity() == 0 {
+        return;
+    }
+    idle.push(buf);
+    if idle.len() > MAX_IDLE {
+        let smallest = idle.iter().enumerate().min_by_key(|(_, b)| b.capacity()).map(|(i, _)| i);
+        if let Some(i) = smallest {
+            idle.swap_remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let mut pool = BufferPool::new();
+        let small = pool.take_bytes(16);
+        let large = pool.take_bytes(1024);
+        let (small_ptr, large_ptr) = (small.as_ptr(), large.as_ptr());
+        pool.put_bytes(small);
+        pool.put_bytes(large);
+
+        // The best fit is picked, and buffers come back empty.
+        let buf = pool.take_bytes(512);
+        assert_eq!(buf.as_ptr(), large_ptr);
+        assert!(buf.is_empty());
+        let buf = pool.take_bytes(8);
+        assert_eq!(buf.as_ptr(), small_ptr);
+    }
+
+    #[test]
+    fn test_idle_buffers_are_bounded() {
+        let mut pool = BufferPool::new();
+        for len in 1..=MAX_IDLE + 2 {
+            pool.put_floats(Vec::with_capacity(len * 10));
+        }
+        assert_eq!(pool.floats.len(), MAX_IDLE);
+        assert!(pool.floats.iter().all(|b| b.capacity() >= 30));
+    }
+}
@@ -0,0 +1,62 @@
+use image::RgbaImage;
+use minifb::{Key, Scale, ScaleMode, Window, WindowOptions};
+
+use crate::error::AppError;
+
+/// Background the preview composites transparent pixels onto (0RGB).
+const BACKGROUND: [u8; 3] = [128, 128, 128];
+
+/// Window displaying the most recent processed image.
+pub struct Preview {
+    window: Window,
+    buffer: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl Preview {
+    /// Opens a preview window sized to `img`.
+    pub fn open(title: &str, img: &RgbaImage) -> Result<Self, AppError> {
+        let opts = WindowOptions {
+            resize: true,
+            scale: Scale::FitScreen,
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        };
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let mut window =
+            Window::new(title, width.max(1), height.max(1), opts).map_err(|e| AppError::Preview(e.to_string()))?;
+        window.set_target_fps(30);
+
+        let mut preview = Self { window, buffer: Vec::new(), width, height };
+        preview.show(img)?;
+        Ok(preview)
+    }
+
+    /// Replaces the displayed image.
+    pub fn show(&mut self, img: &RgbaImage) -> Result<(), AppError> {
+        self.width = img.width() as usize;
+        self.height = img.height() as usize;
+        self.buffer = img
+            .pixels()
+            .map(|p| {
+                let a = p[3] as u32;
+                let [r, g, b] = [0, 1, 2].map(|c| (p[c] as u32 * a + BACKGROUND[c] as u32 * (255 - a)) / 255);
+                (r << 16) | (g << 8) | b
+            })
+            .collect();
+        self.refresh()
+    }
+
+    /// Pumps window events and redraws; call regularly to keep the window responsive.
+    pub fn refresh(&mut self) -> Result<(), AppError> {
+        self.window
+            .update_with_buffer(&self.buffer, self.width.max(1), self.height.max(1))
+            .map_err(|e| AppError::Preview(e.to_string()))
+    }
+
+    /// Returns `false` once the user closed the window or pressed Escape.
+    pub fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+}
@@ -0,0 +1,206 @@
+use image::imageops::{self, FilterType};
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::AppError;
+use crate::output::{EncodeOptions, OutputTarget};
+use crate::pipeline::Rect;
+
+/// Tile edge of DZI pyramids when not given; with the default overlap, tiles are 256 pixels.
+pub const DZI_TILE_SIZE: u32 = 254;
+/// Tile edge of XYZ pyramids, which web map viewers expect.
+pub const XYZ_TILE_SIZE: u32 = 256;
+
+/// Directory layout of `--tiles`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileLayout {
+    /// Deep Zoom: a `.dzi` descriptor and `<name>_files/<level>/<column>_<row>` tiles, as read by
+    /// OpenSeadragon.
+    Dzi,
+    /// `<name>/<z>/<x>/<y>` tiles as used by web maps, every tile full size.
+    Xyz,
+}
+
+impl FromStr for TileLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dzi" => Ok(Self::Dzi),
+            "xyz" => Ok(Self::Xyz),
+            other => Err(format!("unknown tile layout `{other}` (expected dzi or xyz)")),
+        }
+    }
+}
+
+/// Shape of a tile pyramid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PyramidOptions {
+    /// Directory layout.
+    pub layout: TileLayout,
+    /// Edge of a tile without its overlap.
+    pub tile_size: u32,
+    /// Pixels each DZI tile repeats from its neighbours on every inner side; XYZ tiles have none.
+    pub overlap: u32,
+}
+
+/// What [`write`] produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PyramidSummary {
+    /// Number of levels, from a single pixel (DZI) or tile (XYZ) up to full resolution.
+    pub levels: u32,
+    /// Number of tiles written.
+    pub tiles: usize,
+    /// The DZI descriptor, or the XYZ tile directory.
+    pub path: PathBuf,
+}
+
+/// Number of halvings from full resolution down to the smallest level of the pyramid.
+///
+/// DZI pyramids go down to a single pixel; XYZ ones stop where the image fits in one tile.
+pub fn max_level(width: u32, height: u32, opts: &PyramidOptions) -> u32 {
+    let side = u64::from(width.max(height).max(1));
+    let smallest = match opts.layout {
+        TileLayout::Dzi => 1,
+        TileLayout::Xyz => u64::from(opts.tile_size.max(1)),
+    };
+    let mut level = 0;
+    while side.div_ceil(1 << level) > smallest {
+        level += 1;
+    }
+    level
+}
+
+/// The tiles of a `width` x `height` level as (column, row, pixel area) in row-major order.
+///
+/// DZI tiles reach `overlap` pixels into their neighbours and are cut off at the image edge;
+/// XYZ areas are cut off too, the tiles being padded when written.
+pub fn tiles(width: u32, height: u32, opts: &PyramidOptions) -> Vec<(u32, u32, Rect)> {
+    let size = opts.tile_size.max(1);
+    let overlap = if opts.layout == TileLayout::Dzi { opts.overlap } else { 0 };
+    let mut out = Vec::new();
+    for row in 0..height.div_ceil(size) {
+        for col in 0..width.div_ceil(size) {
+            let x = (col * size).saturating_sub(overlap);
+            let y = (row * size).saturating_sub(overlap);
+            let right = ((col + 1) * size + overlap).min(width);
+            let bottom = ((row + 1) * size + overlap).min(height);
+            out.push((col, row, Rect { x, y, width: right - x, height: bottom - y }));
+        }
+    }
+    out
+}
+
+/// The `.dzi` descriptor of a `width` x `height` image with tiles stored as `format`.
+pub fn dzi_descriptor(width: u32, height: u32, format: &str, opts: &PyramidOptions) -> String {
+    let (overlap, tile_size) = (opts.overlap, opts.tile_size);
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\"\n       \
+         Format=\"{format}\" Overlap=\"{overlap}\" TileSize=\"{tile_size}\">\n  \
+         <Size Width=\"{width}\" Height=\"{height}\"/>\n\
+         </Image>\n"
+    )
+}
+
+/// Writes `img` as a tile pyramid next to `output`, in the image format of its extension.
+///
+/// For `scan.png`, a DZI pyramid is `scan.dzi` plus `scan_files/`, and an XYZ pyramid the
+/// `scan/` directory; `scan.png` itself is not written. Each level halves the one above, and
+/// the tiles of a level are encoded in parallel on the rayon pool. The full-resolution level is
+/// tiled from `img` itself; only the smaller levels are allocated.
+pub fn write(
+    img: &DynamicImage,
+    output: &Path,
+    opts: &PyramidOptions,
+    encode_opts: &EncodeOptions,
+) -> Result<PyramidSummary, AppError> {
+    let format = output.extension().and_then(|e| e.to_str()).unwrap_or("png").to_ascii_lowercase();
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let (root, path) = match opts.layout {
+        TileLayout::Dzi => (output.with_file_name(format!("{stem}_files")), output.with_extension("dzi")),
+        TileLayout::Xyz => (output.with_file_name(&stem), output.with_file_name(&stem)),
+    };
+
+    let top = max_level(img.width(), img.height(), opts);
+    let mut level_img = Cow::Borrowed(img);
+    let mut count = 0;
+    for level in (0..=top).rev() {
+        if level < top {
+            let (w, h) = (level_img.width().div_ceil(2), level_img.height().div_ceil(2));
+            level_img = Cow::Owned(level_img.resize_exact(w.max(1), h.max(1), FilterType::Triangle));
+        }
+        let level_tiles = tiles(level_img.width(), level_img.height(), opts);
+        count += level_tiles.len();
+        let dir = root.join(level.to_string());
+        level_tiles.into_par_iter().try_for_each(|(col, row, area)| {
+            let mut tile = level_img.crop_imm(area.x, area.y, area.width, area.height);
+            let file = match opts.layout {
+                TileLayout::Dzi => dir.join(format!("{col}_{row}.{format}")),
+                TileLayout::Xyz => {
+                    if area.width < opts.tile_size || area.height < opts.tile_size {
+                        let mut padded = DynamicImage::new(opts.tile_size, opts.tile_size, img.color());
+                        imageops::replace(&mut padded, &tile, 0, 0);
+                        tile = padded;
+                    }
+                    dir.join(col.to_string()).join(format!("{row}.{format}"))
+                }
+            };
+            std::fs::create_dir_all(file.parent().expect("tile paths have a directory"))?;
+            OutputTarget::File(file).save(&tile, encode_opts)
+        })?;
+    }
+
+    if opts.layout == TileLayout::Dzi {
+        std::fs::write(&path, dzi_descriptor(img.width(), img.height(), &format, opts))?;
+    }
+    Ok(PyramidSummary { levels: top + 1, tiles: count, path })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::PngCompression;
+    use image::{GenericImageView, Rgba, RgbaImage};
+
+    const DZI: PyramidOptions = PyramidOptions { layout: TileLayout::Dzi, tile_size: 254, overlap: 1 };
+    const XYZ: PyramidOptions = PyramidOptions { layout: TileLayout::Xyz, tile_size: 256, overlap: 0 };
+
+    #[test]
+    fn test_levels_and_tiles() {
+        assert_eq!(max_level(1, 1, &DZI), 0);
+        assert_eq!(max_level(1000, 600, &DZI), 10);
+        assert_eq!(max_level(1000, 600, &XYZ), 2);
+        assert_eq!(max_level(256, 100, &XYZ), 0);
+        assert_eq!(max_level(u32::MAX, 1, &DZI), 32);
+        let grid = tiles(600, 254, &DZI);
+        assert_eq!(grid.len(), 3);
+        assert_eq!(grid[0], (0, 0, Rect { x: 0, y: 0, width: 255, height: 254 }));
+        assert_eq!(grid[1], (1, 0, Rect { x: 253, y: 0, width: 256, height: 254 }));
+        assert_eq!(grid[2], (2, 0, Rect { x: 507, y: 0, width: 93, height: 254 }));
+    }
+
+    #[test]
+    fn test_writes_dzi_and_xyz() {
+        let dir = std::env::temp_dir().join(format!("pyramid-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(300, 40, |x, _| Rgba([x as u8, 0, 0, 255])));
+        let encode_opts = EncodeOptions { png_compression: PngCompression::Fast };
+
+        let dzi = write(&img, &dir.join("scan.png"), &DZI, &encode_opts).unwrap();
+        assert_eq!((dzi.levels, dzi.tiles), (10, 11));
+        assert!(std::fs::read_to_string(&dzi.path).unwrap().contains("<Size Width=\"300\" Height=\"40\"/>"));
+        let top = image::open(dir.join("scan_files/9/1_0.png")).unwrap();
+        assert_eq!(top.dimensions(), (47, 40));
+        assert!(dir.join("scan_files/0/0_0.png").is_file());
+
+        let xyz = write(&img, &dir.join("scan.png"), &XYZ, &encode_opts).unwrap();
+        assert_eq!((xyz.levels, xyz.tiles), (2, 3));
+        assert_eq!(image::open(dir.join("scan/1/1/0.png")).unwrap().dimensions(), (256, 256));
+        assert!(dir.join("scan/0/0/0.png").is_file());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
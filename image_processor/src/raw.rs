@@ -0,0 +1,308 @@
+use image::DynamicImage;
+use rayon::prelude::*;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::AppError;
+
+/// Extensions of the camera RAW formats rawloader reads.
+pub const RAW_EXTENSIONS: [&str; 21] = [
+    "dng", "nef", "nrw", "cr2", "crw", "arw", "srf", "sr2", "orf", "rw2", "raf", "pef", "srw", "3fr", "mef", "mos",
+    "kdc", "dcr", "erf", "mrw", "iiq",
+];
+
+/// Linear sRGB primaries from CIE XYZ (D65).
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266, 1.876_010_8, 0.041_556],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+/// White balance applied when developing a RAW file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhiteBalance {
+    /// The multipliers the camera recorded, or daylight where it recorded none.
+    #[default]
+    Camera,
+    /// Gray world: scale the channels to equal means.
+    Auto,
+    /// The sensor's own response, usually green.
+    None,
+}
+
+impl FromStr for WhiteBalance {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "camera" => Ok(Self::Camera),
+            "auto" => Ok(Self::Auto),
+            "none" => Ok(Self::None),
+            other => Err(format!("unknown white balance `{other}` (expected camera, auto or none)")),
+        }
+    }
+}
+
+/// How camera RAW inputs are developed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RawOptions {
+    /// White balance.
+    pub white_balance: WhiteBalance,
+    /// Exposure correction in stops.
+    pub exposure: f32,
+}
+
+/// Returns `true` if the extension of `path` is that of a camera RAW format.
+///
+/// RAW formats are mostly TIFF containers, so they are told apart by name rather than content.
+pub fn is_raw_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| RAW_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Interpolates the two missing colours of every pixel of a single-sample mosaic.
+///
+/// Each channel is the mean of the samples of that colour in the 3x3 neighbourhood, the pixel's
+/// own sample standing for itself: bilinear interpolation on Bayer sensors, and a passable
+/// approximation on other layouts such as X-Trans. `color_at(row, col)` names the filter over a
+/// pixel (0 red, 1 green, 2 blue; 3, a second green or emerald, counts as green).
+pub fn demosaic(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    color_at: impl Fn(usize, usize) -> usize + Sync,
+) -> Vec<[f32; 3]> {
+    let channel = |row: usize, col: usize| match color_at(row, col) {
+        3 => 1,
+        c => c.min(2),
+    };
+    let mut out = vec![[0.0f32; 3]; width * height];
+    out.par_chunks_mut(width.max(1)).enumerate().for_each(|(y, row)| {
+        for (x, px) in row.iter_mut().enumerate() {
+            let own = channel(y, x);
+            let (mut sum, mut count) = ([0.0f32; 3], [0u32; 3]);
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    let c = channel(ny, nx);
+                    sum[c] += data[ny * width + nx];
+                    count[c] += 1;
+                }
+            }
+            for c in 0..3 {
+                px[c] = if c == own {
+                    data[y * width + x]
+                } else if count[c] > 0 {
+                    sum[c] / count[c] as f32
+                } else {
+                    0.0
+                };
+            }
+        }
+    });
+    out
+}
+
+/// Gray-world white balance multipliers of `pixels`, normalized to green.
+pub fn gray_world(pixels: &[[f32; 3]]) -> [f32; 3] {
+    let mut sum = [0.0f64; 3];
+    for px in pixels {
+        (0..3).for_each(|c| sum[c] += px[c] as f64);
+    }
+    if sum.iter().any(|&s| s <= 0.0) {
+        return [1.0; 3];
+    }
+    sum.map(|s| (sum[1] / s) as f32)
+}
+
+/// Camera-to-linear-sRGB matrix from the camera-to-XYZ matrix of a RAW file.
+///
+/// Rows are normalized to sum to one, so a white-balanced neutral stays neutral. Returns `None`
+/// for cameras without colour data, whose matrix is all zeros.
+pub fn rgb_cam(cam_to_xyz: [[f32; 4]; 3]) -> Option<[[f32; 3]; 3]> {
+    let mut m = [[0.0f32; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| XYZ_TO_SRGB[i][k] * cam_to_xyz[k][j]).sum();
+        }
+        let sum: f32 = row.iter().sum();
+        if !sum.is_normal() {
+            return None;
+        }
+        row.iter_mut().for_each(|v| *v /= sum);
+    }
+    Some(m)
+}
+
+/// Turns demosaiced camera values in `0..=1` into linear sRGB in place.
+///
+/// The channels are scaled by `wb` and clipped at one, so blown highlights stay white instead of
+/// turning magenta, then converted with `rgb_cam` if given and scaled by `2^exposure`.
+pub fn develop(pixels: &mut [[f32; 3]], wb: [f32; 3], rgb_cam: Option<[[f32; 3]; 3]>, exposure: f32) {
+    let gain = exposure.exp2();
+    pixels.par_iter_mut().for_each(|px| {
+        let cam: [f32; 3] = std::array::from_fn(|c| (px[c] * wb[c]).min(1.0));
+        *px = match rgb_cam {
+            Some(m) => std::array::from_fn(|i| (0..3).map(|j| m[i][j] * cam[j]).sum::<f32>().max(0.0) * gain),
+            None => cam.map(|v| v * gain),
+        };
+    });
+}
+
+/// Decodes a camera RAW file into linear sRGB (an `Rgb32F` image), developed per `opts`.
+///
+/// The sensor data is cropped to the area the camera declares valid, scaled from its black and
+/// white levels, demosaiced, white balanced and converted with the camera's colour matrix.
+/// Monochrome sensors give gray images. With `auto_orient`, the recorded orientation is applied.
+#[cfg(feature = "raw")]
+pub fn decode(bytes: &[u8], opts: &RawOptions, auto_orient: bool) -> Result<DynamicImage, AppError> {
+    use image::metadata::Orientation;
+    use image::{ImageBuffer, Rgb};
+    use rawloader::RawImageData;
+
+    let raw = rawloader::decode(&mut std::io::Cursor::new(bytes)).map_err(|e| AppError::Raw(e.to_string()))?;
+    let [top, right, bottom, left] = raw.crops;
+    let (width, height) = (raw.width - left - right, raw.height - top - bottom);
+    let cpp = raw.cpp;
+    let sample = |i: usize| match &raw.data {
+        RawImageData::Integer(data) => data[i] as f32,
+        RawImageData::Float(data) => data[i],
+    };
+    let cfa = raw.cropped_cfa();
+    let color_at = |row: usize, col: usize| if cpp == 1 && cfa.is_valid() { cfa.color_at(row, col) } else { 1 };
+    let scale = |v: f32, c: usize| {
+        let (black, white) = (raw.blacklevels[c] as f32, raw.whitelevels[c] as f32);
+        ((v - black) / (white - black).max(1.0)).max(0.0)
+    };
+
+    let mut pixels = if cpp == 1 {
+        let mosaic: Vec<f32> = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (y, x) = (i / width, i % width);
+                scale(sample((y + top) * raw.width + x + left), color_at(y, x))
+            })
+            .collect();
+        if raw.is_monochrome() {
+            mosaic.into_iter().map(|v| [v; 3]).collect()
+        } else {
+            demosaic(&mosaic, width, height, color_at)
+        }
+    } else {
+        (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let base = (((i / width) + top) * raw.width + i % width + left) * cpp;
+                std::array::from_fn(|c| scale(sample(base + c.min(cpp - 1)), c))
+            })
+            .collect()
+    };
+
+    let camera_wb = || {
+        let wb = [raw.wb_coeffs[0], raw.wb_coeffs[1], raw.wb_coeffs[2]];
+        let wb = if wb.iter().all(|v| v.is_normal()) { wb } else { raw.neutralwb()[..3].try_into().expect("3 of 4") };
+        if wb.iter().all(|v| v.is_normal() && *v > 0.0) { wb.map(|v| v / wb[1]) } else { [1.0; 3] }
+    };
+    let wb = match opts.white_balance {
+        _ if raw.is_monochrome() => [1.0; 3],
+        WhiteBalance::Camera => camera_wb(),
+        WhiteBalance::Auto => gray_world(&pixels),
+        WhiteBalance::None => [1.0; 3],
+    };
+    let matrix = if raw.is_monochrome() { None } else { rgb_cam(raw.cam_to_xyz_normalized()) };
+    develop(&mut pixels, wb, matrix, opts.exposure);
+    tracing::debug!(make = raw.clean_make, model = raw.clean_model, ?wb, "developed RAW image");
+
+    let data: Vec<f32> = pixels.into_iter().flatten().collect();
+    let buffer = ImageBuffer::<Rgb<f32>, _>::from_raw(width as u32, height as u32, data).expect("buffer matches size");
+    let mut img = DynamicImage::ImageRgb32F(buffer);
+    if auto_orient && let Some(orientation) = Orientation::from_exif(raw.orientation.to_u16() as u8) {
+        img.apply_orientation(orientation);
+    }
+    Ok(img)
+}
+
+/// Stand-in for builds without the `raw` feature.
+#[cfg(not(feature = "raw"))]
+pub fn decode(_bytes: &[u8], _opts: &RawOptions, _auto_orient: bool) -> Result<DynamicImage, AppError> {
+    Err(AppError::Raw("built without the `raw` feature".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RGGB Bayer pattern.
+    fn rggb(row: usize, col: usize) -> usize {
+        [[0, 1], [1, 2]][row % 2][col % 2]
+    }
+
+    #[test]
+    fn test_demosaic_flat_color() {
+        // A uniform orange scene seen through an RGGB filter.
+        let scene = [0.8, 0.5, 0.2];
+        let mosaic: Vec<f32> = (0..36).map(|i| scene[rggb(i / 6, i % 6)]).collect();
+        for px in demosaic(&mosaic, 6, 6, rggb) {
+            assert_eq!(px, scene);
+        }
+        assert!(is_raw_path(Path::new("IMG_0001.CR2")));
+        assert!(!is_raw_path(Path::new("photo.tif")));
+    }
+
+    #[test]
+    fn test_develop_balances_and_clips() {
+        let mut pixels = vec![[0.25, 0.5, 0.4], [0.9, 1.0, 0.8]];
+        develop(&mut pixels, [2.0, 1.0, 1.25], None, 1.0);
+        assert_eq!(pixels, [[1.0, 1.0, 1.0], [2.0, 2.0, 2.0]]);
+        assert_eq!(gray_world(&[[0.2, 0.4, 0.8], [0.2, 0.4, 0.8]]), [2.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_rgb_cam_keeps_neutrals() {
+        // A camera that sees exactly XYZ.
+        let identity = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]];
+        let m = rgb_cam(identity).unwrap();
+        for row in m {
+            assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        }
+        assert_eq!(rgb_cam([[0.0; 4]; 3]), None);
+    }
+
+    #[cfg(feature = "raw")]
+    #[test]
+    fn test_decodes_dng() {
+        use tiff::encoder::{colortype, Rational, TiffEncoder};
+        use tiff::tags::Tag;
+
+        // A uniform gray card under warm light on an RGGB sensor, the camera having recorded the
+        // light as the neutral.
+        let levels = [1000u16, 2000, 500];
+        let mosaic: Vec<u16> = (0..64).map(|i| levels[rggb(i / 8, i % 8)]).collect();
+        let mut dng = std::io::Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut dng).unwrap();
+        let mut image = encoder.new_image::<colortype::Gray16>(8, 8).unwrap();
+        let neutral = [Rational { n: 1, d: 2 }, Rational { n: 1, d: 1 }, Rational { n: 1, d: 4 }];
+        let dir = image.encoder();
+        dir.write_tag(Tag::Make, "Test").unwrap();
+        dir.write_tag(Tag::Model, "Sensor").unwrap();
+        dir.write_tag(Tag::Unknown(50706), &[1u8, 4, 0, 0][..]).unwrap(); // DNGVersion
+        dir.write_tag(Tag::Unknown(33421), &[2u16, 2][..]).unwrap(); // CFARepeatPatternDim
+        dir.write_tag(Tag::Unknown(33422), &[0u8, 1, 1, 2][..]).unwrap(); // CFAPattern
+        dir.write_tag(Tag::Unknown(50717), 4000u16).unwrap(); // WhiteLevel
+        dir.write_tag(Tag::Unknown(50728), &neutral[..]).unwrap(); // AsShotNeutral
+        image.write_data(&mosaic).unwrap();
+
+        for white_balance in [WhiteBalance::Camera, WhiteBalance::Auto] {
+            let opts = RawOptions { white_balance, exposure: 1.0 };
+            let img = decode(dng.get_ref(), &opts, true).unwrap().into_rgb32f();
+            assert_eq!(img.dimensions(), (8, 8));
+            for px in img.pixels() {
+                assert!(px.0.iter().all(|v| (v - 1.0).abs() < 1e-3), "{white_balance:?}: {px:?}");
+            }
+        }
+        // Left unbalanced, the green channel of the sensor dominates.
+        let none = RawOptions { white_balance: WhiteBalance::None, exposure: 0.0 };
+        let px = *decode(dng.get_ref(), &none, true).unwrap().into_rgb32f().get_pixel(3, 3);
+        assert!(px[1] > px[0].max(px[2]), "{px:?}");
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::error::AppError;
+
+/// Upper bound for a single backoff delay.
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Stage of a job in which an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Loading the input: reading or downloading and decoding.
+    Decode,
+    /// Running a plugin step.
+    Plugin,
+    /// Encoding and writing the outputs.
+    Encode,
+}
+
+impl Stage {
+    /// Tags `error` with this stage.
+    pub fn wrap(self, error: AppError) -> StageError {
+        StageError { stage: self, error }
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "decode" => Ok(Self::Decode),
+            "plugin" => Ok(Self::Plugin),
+            "encode" => Ok(Self::Encode),
+            other => Err(format!("unknown stage `{other}` (expected decode, plugin or encode)")),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Decode => "decode",
+            Self::Plugin => "plugin",
+            Self::Encode => "encode",
+        })
+    }
+}
+
+/// An error together with the stage it occurred in.
+#[derive(Debug)]
+pub struct StageError {
+    /// Stage that failed.
+    pub stage: Stage,
+    /// The underlying error.
+    pub error: AppError,
+}
+
+/// Which failures are retried, how often, and how long to wait in between.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub retries: u32,
+    /// Stages whose failures are considered transient.
+    pub on: Vec<Stage>,
+    /// Delay before the first retry; doubled for every further one.
+    pub base_delay: Duration,
+}
+
+/// Result of running a job under a [`RetryPolicy`].
+#[derive(Debug)]
+pub struct Outcome<T> {
+    /// Result of the last attempt.
+    pub result: Result<T, StageError>,
+    /// Number of attempts made, including the first.
+    pub attempts: u32,
+}
+
+impl RetryPolicy {
+    /// Returns the delay before retry number `retry` (starting at 1).
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.base_delay.saturating_mul(1 << (retry - 1).min(16)).min(MAX_DELAY)
+    }
+
+    /// Runs `job`, retrying failures in the configured stages with exponential backoff.
+    ///
+    /// Failures in other stages are permanent and returned after the first attempt.
+    pub fn run<T>(&self, mut job: impl FnMut() -> Result<T, StageError>) -> Outcome<T> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let result = job();
+            match &result {
+                Err(e) if attempts <= self.retries && self.on.contains(&e.stage) => {
+                    let delay = self.delay(attempts);
+                    tracing::warn!(
+                        stage = %e.stage,
+                        error = e.error.to_string(),
+                        attempt = attempts,
+                        delay_ms = delay.as_millis() as u64,
+                        "transient failure, retrying"
+                    );
+                    std::thread::sleep(delay);
+                }
+                _ => return Outcome { result, attempts },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(retries: u32, on: &[Stage]) -> RetryPolicy {
+        RetryPolicy { retries, on: on.to_vec(), base_delay: Duration::ZERO }
+    }
+
+    fn failing(stage: Stage, failures: u32) -> impl FnMut() -> Result<(), StageError> {
+        let mut calls = 0;
+        move || {
+            calls += 1;
+            if calls <= failures {
+                Err(stage.wrap(AppError::MissingInput("x".into())))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_transient_failure_is_retried() {
+        let outcome = policy(3, &[Stage::Decode]).run(failing(Stage::Decode, 2));
+        assert!(outcome.result.is_ok());
+        assert_eq!(outcome.attempts, 3);
+
+        let exhausted = policy(1, &[Stage::Decode]).run(failing(Stage::Decode, 5));
+        assert!(exhausted.result.is_err());
+        assert_eq!(exhausted.attempts, 2);
+    }
+
+    #[test]
+    fn test_permanent_failure_fails_immediately() {
+        let outcome = policy(3, &[Stage::Decode]).run(failing(Stage::Encode, 1));
+        assert_eq!(outcome.result.unwrap_err().stage, Stage::Encode);
+        assert_eq!(outcome.attempts, 1);
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let p = RetryPolicy { base_delay: Duration::from_millis(500), ..policy(0, &[]) };
+        assert_eq!(p.delay(1), Duration::from_millis(500));
+        assert_eq!(p.delay(3), Duration::from_secs(2));
+        assert_eq!(p.delay(40), MAX_DELAY);
+    }
+}
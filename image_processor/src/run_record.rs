@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+
+/// Environment variable overriding the directory where run state is kept.
+pub const STATE_DIR_ENV: &str = "IMAGE_PROCESSOR_STATE_DIR";
+
+/// File name of the serialized [`RunRecord`] inside the last-run directory.
+pub const RECORD_FILE: &str = "run.toml";
+
+/// File name of the captured log output inside the last-run directory.
+pub const LOG_FILE: &str = "run.log";
+
+/// Identity of a plugin library used in a run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PluginIdentity {
+    /// Plugin name as given on the command line.
+    pub name: String,
+    /// Full path of the loaded dynamic library.
+    pub path: String,
+    /// Library file size in bytes.
+    pub size: u64,
+    /// Library modification time in seconds since the Unix epoch.
+    pub modified: u64,
+    /// FNV-1a 64-bit hash of the library contents, hex encoded.
+    pub fnv1a64: String,
+}
+
+/// Outcome of one input image of a run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ItemRecord {
+    /// Input image of the item.
+    pub input: String,
+    /// Number of attempts made, including retries.
+    pub attempts: u32,
+    /// Error message of the last attempt, if the item failed.
+    pub error: Option<String>,
+}
+
+/// Everything needed to reproduce a single run, persisted after each run.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RunRecord {
+    /// Host version (`CARGO_PKG_VERSION`).
+    pub host_version: String,
+    /// Target operating system of the host build.
+    pub host_os: String,
+    /// Target architecture of the host build.
+    pub host_arch: String,
+    /// Run start time in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Full command line the host was invoked with.
+    pub command_line: Vec<String>,
+    /// Input image path.
+    pub input: String,
+    /// Output image path.
+    pub output: String,
+    /// Params file path.
+    pub params_path: String,
+    /// Params file contents at the time of the run.
+    pub params: Option<String>,
+    /// Plugins used by the run, when they could be resolved.
+    pub plugins: Vec<PluginIdentity>,
+    /// Per-input outcomes, including retry attempts.
+    #[serde(default)]
+    pub items: Vec<ItemRecord>,
+    /// Whether the run completed successfully.
+    pub success: bool,
+    /// Error message of a failed run.
+    pub error: Option<String>,
+}
+
+impl RunRecord {
+    /// Creates a record for a run starting now.
+    pub fn start(command_line: Vec<String>) -> Self {
+        Self {
+            host_version: env!("CARGO_PKG_VERSION").to_string(),
+            host_os: std::env::consts::OS.to_string(),
+            host_arch: std::env::consts::ARCH.to_string(),
+            started_at: unix_secs(SystemTime::now()),
+            command_line,
+            ..Self::default()
+        }
+    }
+
+    /// Marks the record with the outcome of the run.
+    pub fn finish<T>(&mut self, result: &Result<T, AppError>) {
+        self.success = result.is_ok();
+        self.error = result.as_ref().err().map(|e| e.to_string());
+    }
+
+    /// Writes the record into `dir`.
+    pub fn save(&self, dir: &Path) -> Result<(), AppError> {
+        let text = toml::to_string(self).map_err(|e| AppError::RunRecord(e.to_string()))?;
+        std::fs::write(dir.join(RECORD_FILE), text)?;
+        Ok(())
+    }
+
+    /// Reads a record previously written by [`RunRecord::save`] from `dir`.
+    pub fn load(dir: &Path) -> Result<Self, AppError> {
+        let path = dir.join(RECORD_FILE);
+        if !path.exists() {
+            return Err(AppError::MissingLastRun(dir.display().to_string()));
+        }
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| AppError::RunRecord(e.to_string()))
+    }
+}
+
+impl PluginIdentity {
+    /// Identity of a host built-in step, which has no library file of its own.
+    pub fn builtin(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            path: format!("builtin (image_processor {})", env!("CARGO_PKG_VERSION")),
+            size: 0,
+            modified: 0,
+            fnv1a64: String::new(),
+        }
+    }
+
+    /// Collects identity information of the plugin library at `path`.
+    pub fn of(name: &str, path: &Path) -> Result<Self, AppError> {
+        let bytes = std::fs::read(path)?;
+        let meta = std::fs::metadata(path)?;
+        let modified = meta.modified().map(unix_secs).unwrap_or(0);
+
+        Ok(Self {
+            name: name.to_string(),
+            path: path.display().to_string(),
+            size: meta.len(),
+            modified,
+            fnv1a64: format!("{:016x}", fnv1a64(&bytes)),
+        })
+    }
+}
+
+/// Returns the directory holding state of the most recent run.
+///
+/// Uses `$IMAGE_PROCESSOR_STATE_DIR`, then `$XDG_STATE_HOME/image_processor`,
+/// then `~/.local/state/image_processor`, falling back to the system temp dir.
+pub fn last_run_dir() -> PathBuf {
+    let base = if let Some(dir) = std::env::var_os(STATE_DIR_ENV) {
+        PathBuf::from(dir)
+    } else if let Some(dir) = std::env::var_os("XDG_STATE_HOME") {
+        PathBuf::from(dir).join("image_processor")
+    } else if let Some(home) = std::env::var_os("HOME") {
+        PathBuf::from(home).join(".local/state/image_processor")
+    } else {
+        std::env::temp_dir().join("image_processor")
+    };
+    base.join("last_run")
+}
+
+/// Clears and recreates the last-run directory, returning its path.
+pub fn reset_last_run_dir() -> Result<PathBuf, AppError> {
+    let dir = last_run_dir();
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn fnv1a64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("run-record-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(matches!(RunRecord::load(&dir), Err(AppError::MissingLastRun(_))));
+
+        let mut record = RunRecord::start(vec!["image_processor".to_string(), "--input".to_string()]);
+        record.input = "photo.png".to_string();
+        record.params = Some("radius = 3\n".to_string());
+        record.plugins.push(PluginIdentity::builtin("resize"));
+        let item = ItemRecord { input: "photo.png".to_string(), attempts: 2, error: Some("timeout".to_string()) };
+        record.items.push(item);
+        record.finish::<()>(&Err(AppError::MissingInput("photo.png".to_string())));
+        record.save(&dir).unwrap();
+
+        let loaded = RunRecord::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.command_line, record.command_line);
+        assert_eq!((loaded.started_at, loaded.host_version.as_str()), (record.started_at, env!("CARGO_PKG_VERSION")));
+        assert_eq!((loaded.input.as_str(), loaded.params), ("photo.png", record.params));
+        assert_eq!(loaded.plugins[0].path, record.plugins[0].path);
+        assert_eq!((loaded.items[0].attempts, loaded.items[0].error.as_deref()), (2, Some("timeout")));
+        assert!(!loaded.success);
+        assert_eq!(loaded.error, record.error);
+    }
+}
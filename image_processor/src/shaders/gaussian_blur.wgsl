@@ -0,0 +1,43 @@
+// One pass of a separable Gaussian blur over straight RGBA `f32` pixels.
+//
+// Taps outside the image are dropped and the remaining weights renormalized,
+// matching `blur_plugin` on the CPU.
+
+struct Params {
+    width: u32,
+    height: u32,
+    radius: u32,
+    horizontal: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> kernel: array<f32>;
+@group(0) @binding(2) var<storage, read> src: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read_write> dst: array<vec4<f32>>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= params.width || id.y >= params.height {
+        return;
+    }
+
+    let r = i32(params.radius);
+    var acc = vec4<f32>(0.0);
+    var wsum = 0.0;
+    for (var k = -r; k <= r; k++) {
+        var x = i32(id.x);
+        var y = i32(id.y);
+        if params.horizontal == 1u {
+            x += k;
+        } else {
+            y += k;
+        }
+        if x < 0 || y < 0 || x >= i32(params.width) || y >= i32(params.height) {
+            continue;
+        }
+        let w = kernel[k + r];
+        acc += src[u32(y) * params.width + u32(x)] * w;
+        wsum += w;
+    }
+    dst[id.y * params.width + id.x] = acc / wsum;
+}
@@ -0,0 +1,208 @@
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::str::FromStr;
+
+use crate::pipeline::Rect;
+
+/// Longest side of the copy the saliency map is computed on.
+const ANALYSIS_SIDE: u32 = 256;
+/// Weight of skin-toned pixels relative to the strongest edge when faces are favoured.
+const SKIN_WEIGHT: f32 = 1.5;
+/// Extra weight of skin in the top third of a window, where heads usually are.
+const HEAD_BONUS: f32 = 0.5;
+
+/// Output size of `--smart-crop`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropSize {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+}
+
+impl FromStr for CropSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = s.split_once('x').and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)));
+        match size {
+            Some((width, height)) if width > 0 && height > 0 => Ok(Self { width, height }),
+            _ => Err(format!("invalid crop size `{s}` (expected WIDTHxHEIGHT, e.g. 400x300)")),
+        }
+    }
+}
+
+/// Crops `img` to the aspect ratio of `size` where it is busiest and scales the crop to `size`.
+///
+/// The window is as large as the aspect ratio allows, so it spans the full width or height of
+/// the image and only slides along the other axis. Its position maximizes the edge energy
+/// inside; with `faces`, skin-toned pixels count extra, more so in the top third of the window,
+/// so that heads are kept rather than cut.
+pub fn smart_crop(img: &RgbaImage, size: CropSize, faces: bool) -> RgbaImage {
+    let window = best_window(img, size, faces);
+    let cropped = imageops::crop_imm(img, window.x, window.y, window.width, window.height).to_image();
+    if cropped.dimensions() == (size.width, size.height) {
+        return cropped;
+    }
+    imageops::resize(&cropped, size.width, size.height, FilterType::Lanczos3)
+}
+
+/// Like [`smart_crop`], for an image of any pixel format; the window is chosen on its 8-bit version.
+pub fn smart_crop_dynamic(img: &DynamicImage, size: CropSize, faces: bool) -> DynamicImage {
+    let window = best_window(&img.to_rgba8(), size, faces);
+    let cropped = img.crop_imm(window.x, window.y, window.width, window.height);
+    if cropped.dimensions() == (size.width, size.height) {
+        return cropped;
+    }
+    cropped.resize_exact(size.width, size.height, FilterType::Lanczos3)
+}
+
+/// The crop window of `img` chosen by [`smart_crop`], in pixels of `img`.
+pub fn best_window(img: &RgbaImage, size: CropSize, faces: bool) -> Rect {
+    let (w, h) = img.dimensions();
+    let aspect = size.width as f64 / size.height as f64;
+    let (win_w, win_h) = if w as f64 / h as f64 > aspect {
+        (((h as f64 * aspect).round() as u32).clamp(1, w), h)
+    } else {
+        (w, ((w as f64 / aspect).round() as u32).clamp(1, h))
+    };
+    let (slack_x, slack_y) = (w - win_w, h - win_h);
+    if slack_x == 0 && slack_y == 0 {
+        return Rect { x: 0, y: 0, width: w, height: h };
+    }
+
+    let scale = (ANALYSIS_SIDE as f64 / w.max(h) as f64).min(1.0);
+    let small_w = ((w as f64 * scale).round() as u32).max(1);
+    let small_h = ((h as f64 * scale).round() as u32).max(1);
+    let small = imageops::resize(img, small_w, small_h, FilterType::Triangle);
+    let (edges, skin) = saliency(&small, faces);
+    let (sw, sh) = (small_w as usize, small_h as usize);
+
+    // Collapse the maps onto the free axis, the window then being a range of it.
+    let horizontal = slack_x > 0;
+    let (len, other, win) = if horizontal {
+        (sw, sh, ((win_w as f64 * scale).round() as usize).clamp(1, sw))
+    } else {
+        (sh, sw, ((win_h as f64 * scale).round() as usize).clamp(1, sh))
+    };
+    let index = |along: usize, across: usize| if horizontal { across * sw + along } else { along * sw + across };
+    let third = other.div_ceil(3);
+    let mut energy = vec![0.0f64; len + 1];
+    let mut skin_rows = vec![0.0f64; len + 1];
+    for along in 0..len {
+        let (mut e, mut s) = (0.0f32, 0.0f32);
+        for across in 0..other {
+            let i = index(along, across);
+            e += edges[i] + skin[i] * SKIN_WEIGHT;
+            // A window sliding sideways spans the full height, so its top third is fixed.
+            if horizontal && across < third {
+                e += skin[i] * HEAD_BONUS;
+            }
+            s += skin[i];
+        }
+        energy[along + 1] = energy[along] + e as f64;
+        skin_rows[along + 1] = skin_rows[along] + s as f64;
+    }
+
+    let offsets = len - win + 1;
+    let centre = (offsets - 1) as f64 / 2.0;
+    let mut best = (f64::MIN, 0usize);
+    for offset in 0..offsets {
+        let mut score = energy[offset + win] - energy[offset];
+        if !horizontal {
+            score += (skin_rows[offset + win.div_ceil(3)] - skin_rows[offset]) * HEAD_BONUS as f64;
+        }
+        // Ties go to the most central window.
+        let score = score - (offset as f64 - centre).abs() * 1e-9;
+        if score > best.0 {
+            best = (score, offset);
+        }
+    }
+
+    let slack = if horizontal { slack_x } else { slack_y };
+    let free = if offsets > 1 { best.1 as f64 / (offsets - 1) as f64 } else { 0.5 };
+    let offset = (free * slack as f64).round() as u32;
+    if horizontal {
+        Rect { x: offset, y: 0, width: win_w, height: win_h }
+    } else {
+        Rect { x: 0, y: offset, width: win_w, height: win_h }
+    }
+}
+
+/// Edge magnitude normalized to the strongest edge, and a skin-tone mask that is all zero
+/// unless `faces` is set, of `img`.
+fn saliency(img: &RgbaImage, faces: bool) -> (Vec<f32>, Vec<f32>) {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let luma: Vec<f32> =
+        img.pixels().map(|p| 0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32).collect();
+    let at = |x: usize, y: usize| luma[y.min(h - 1) * w + x.min(w - 1)];
+    let mut edges: Vec<f32> = (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            let gx = at(x + 1, y) - at(x.saturating_sub(1), y);
+            let gy = at(x, y + 1) - at(x, y.saturating_sub(1));
+            gx.hypot(gy)
+        })
+        .collect();
+    let max = edges.iter().copied().fold(0.0f32, f32::max);
+    if max > 0.0 {
+        edges.iter_mut().for_each(|e| *e /= max);
+    }
+    let skin = img.pixels().map(|p| if faces && is_skin(p.0) { 1.0 } else { 0.0 }).collect();
+    (edges, skin)
+}
+
+/// Chai & Ngan's YCbCr skin-tone box.
+fn is_skin([r, g, b, a]: [u8; 4]) -> bool {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let cb = 128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b;
+    let cr = 128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+    a >= 128 && y > 60.0 && (77.0..=127.0).contains(&cb) && (133.0..=173.0).contains(&cr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use plugin_sdk::Rng;
+
+    /// Gray image with random noise in the given rows and columns.
+    fn noisy(width: u32, height: u32, xs: std::ops::Range<u32>, ys: std::ops::Range<u32>) -> RgbaImage {
+        let mut rng = Rng::new(1);
+        RgbaImage::from_fn(width, height, |x, y| {
+            let v = if xs.contains(&x) && ys.contains(&y) { (rng.next_u64() % 256) as u8 } else { 128 };
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    #[test]
+    fn test_parse_crop_size() {
+        assert_eq!("400x300".parse(), Ok(CropSize { width: 400, height: 300 }));
+        assert!("400".parse::<CropSize>().is_err());
+        assert!("0x300".parse::<CropSize>().is_err());
+    }
+
+    #[test]
+    fn test_follows_detail() {
+        let img = noisy(400, 100, 300..380, 0..100);
+        let window = best_window(&img, CropSize { width: 1, height: 1 }, false);
+        assert_eq!((window.width, window.height), (100, 100));
+        assert!((280..=300).contains(&window.x), "{window:?}");
+        assert_eq!(smart_crop(&img, CropSize { width: 50, height: 40 }, false).dimensions(), (50, 40));
+    }
+
+    #[test]
+    fn test_faces_outweigh_texture() {
+        let mut img = noisy(100, 300, 0..100, 0..30);
+        for y in 200..260 {
+            for x in 20..80 {
+                img.put_pixel(x, y, Rgba([224, 172, 140, 255]));
+            }
+        }
+        let size = CropSize { width: 1, height: 1 };
+        assert_eq!(best_window(&img, size, false).y, 0);
+        let window = best_window(&img, size, true);
+        assert!((160..=200).contains(&window.y), "{window:?}");
+    }
+}
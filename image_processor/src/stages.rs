@@ -0,0 +1,87 @@
+use std::sync::mpsc;
+use std::thread;
+
+/// Runs `decode`, `process` and `encode` over `items` as overlapping stages.
+///
+/// `decode` and `encode` each get their own thread, while `process` runs on the calling thread,
+/// so it may use state that cannot be sent between threads (plugin handles, the call context).
+/// The stages are connected by channels holding at most `depth` values each, which bounds how
+/// many items are in flight. Items pass through every stage in order, and the results of
+/// `encode` are returned in the order of `items`.
+pub fn run<T, D, P, R>(
+    items: impl IntoIterator<Item = T, IntoIter: Send>,
+    depth: usize,
+    mut decode: impl FnMut(T) -> D + Send,
+    mut process: impl FnMut(D) -> P,
+    encode: impl FnMut(P) -> R + Send,
+) -> Vec<R>
+where
+    D: Send,
+    P: Send,
+    R: Send,
+{
+    let items = items.into_iter();
+    thread::scope(|s| {
+        let (decoded_tx, decoded_rx) = mpsc::sync_channel(depth);
+        let (processed_tx, processed_rx) = mpsc::sync_channel(depth);
+
+        s.spawn(move || {
+            for item in items {
+                // The receiver is only gone if a later stage stopped early.
+                if decoded_tx.send(decode(item)).is_err() {
+                    break;
+                }
+            }
+        });
+        let encoder = s.spawn(move || processed_rx.into_iter().map(encode).collect());
+
+        for decoded in decoded_rx {
+            if processed_tx.send(process(decoded)).is_err() {
+                break;
+            }
+        }
+        drop(processed_tx);
+
+        encoder.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_results_keep_item_order() {
+        let out = run(0..20, 2, |i| i * 2, |d| d + 1, |p: i32| p.to_string());
+        let expected: Vec<String> = (0..20).map(|i| (i * 2 + 1).to_string()).collect();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_decode_runs_ahead_of_process() {
+        let decoded = AtomicUsize::new(0);
+        let out = run(
+            0..3,
+            1,
+            |i| {
+                decoded.fetch_add(1, Ordering::SeqCst);
+                i
+            },
+            |i| {
+                // While the first item is being processed, the next one is decoded concurrently.
+                if i == 0 {
+                    let start = Instant::now();
+                    while decoded.load(Ordering::SeqCst) < 2 {
+                        assert!(start.elapsed() < Duration::from_secs(5), "decode did not overlap");
+                        thread::yield_now();
+                    }
+                }
+                i
+            },
+            |i| i,
+        );
+        assert_eq!(out, vec![0, 1, 2]);
+    }
+}
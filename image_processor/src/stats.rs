@@ -0,0 +1,118 @@
+use image::{DynamicImage, RgbaImage};
+use serde::Serialize;
+use std::borrow::Cow;
+
+/// Histogram and summary statistics of one 8-bit channel.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChannelStats {
+    /// Number of samples with each value 0..=255.
+    pub histogram: Vec<u64>,
+    /// Smallest value.
+    pub min: u8,
+    /// Largest value.
+    pub max: u8,
+    /// Mean value.
+    pub mean: f64,
+    /// Population standard deviation.
+    pub stddev: f64,
+    /// Percentage of samples at 0.
+    pub clipped_low: f64,
+    /// Percentage of samples at 255.
+    pub clipped_high: f64,
+}
+
+impl ChannelStats {
+    fn from_histogram(histogram: Vec<u64>) -> Self {
+        let count = histogram.iter().sum::<u64>().max(1) as f64;
+        let mean = histogram.iter().enumerate().map(|(v, &n)| v as f64 * n as f64).sum::<f64>() / count;
+        let variance =
+            histogram.iter().enumerate().map(|(v, &n)| (v as f64 - mean).powi(2) * n as f64).sum::<f64>() / count;
+        Self {
+            min: histogram.iter().position(|&n| n > 0).unwrap_or(0) as u8,
+            max: histogram.iter().rposition(|&n| n > 0).unwrap_or(0) as u8,
+            mean,
+            stddev: variance.sqrt(),
+            clipped_low: histogram[0] as f64 / count * 100.0,
+            clipped_high: histogram[255] as f64 / count * 100.0,
+            histogram,
+        }
+    }
+}
+
+/// Statistics of an image in 8-bit encoded values.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImageStats {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Red channel.
+    pub red: ChannelStats,
+    /// Green channel.
+    pub green: ChannelStats,
+    /// Blue channel.
+    pub blue: ChannelStats,
+    /// Alpha channel.
+    pub alpha: ChannelStats,
+}
+
+impl ImageStats {
+    /// Computes the statistics of `img`.
+    pub fn of(img: &RgbaImage) -> Self {
+        let mut histograms = [(); 4].map(|_| vec![0u64; 256]);
+        for px in img.pixels() {
+            for (histogram, &v) in histograms.iter_mut().zip(&px.0) {
+                histogram[v as usize] += 1;
+            }
+        }
+        let [red, green, blue, alpha] = histograms.map(ChannelStats::from_histogram);
+        Self { width: img.width(), height: img.height(), red, green, blue, alpha }
+    }
+
+    /// Computes the statistics of `img`, rounding deeper images to 8 bits first.
+    pub fn of_image(img: &DynamicImage) -> Self {
+        let rgba = img.as_rgba8().map_or_else(|| Cow::Owned(img.to_rgba8()), Cow::Borrowed);
+        Self::of(&rgba)
+    }
+}
+
+/// One line of the `--stats-out` file: an image before and after the plugin chain.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsRecord {
+    /// Image that was read.
+    pub input: String,
+    /// Where the result was written.
+    pub output: String,
+    /// The decoded input.
+    pub before: ImageStats,
+    /// The saved result.
+    pub after: ImageStats,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_channel_stats() {
+        let img = RgbaImage::from_fn(4, 1, |x, _| Rgba([[0, 0, 100, 255][x as usize], 50, 255, 255]));
+        let stats = ImageStats::of(&img);
+        assert_eq!((stats.width, stats.height), (4, 1));
+        assert_eq!((stats.red.min, stats.red.max), (0, 255));
+        assert_eq!(stats.red.mean, 88.75);
+        assert_eq!(stats.red.clipped_low, 50.0);
+        assert_eq!(stats.red.clipped_high, 25.0);
+        assert_eq!(stats.red.histogram[100], 1);
+        assert_eq!(stats.green.stddev, 0.0);
+        assert_eq!(stats.blue.clipped_high, 100.0);
+        assert_eq!(stats.alpha.histogram[255], 4);
+    }
+
+    #[test]
+    fn test_deep_images_are_rounded() {
+        let img = DynamicImage::ImageRgba16(image::ImageBuffer::from_pixel(2, 2, Rgba([65535u16, 257, 0, 65535])));
+        let stats = ImageStats::of_image(&img);
+        assert_eq!((stats.red.min, stats.green.max, stats.blue.max), (255, 1, 0));
+    }
+}
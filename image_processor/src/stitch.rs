@@ -0,0 +1,547 @@
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use plugin_sdk::Rng;
+use rayon::prelude::*;
+use std::str::FromStr;
+
+use crate::convert;
+use crate::error::AppError;
+
+/// Longest side, in pixels, of the downscaled copies features are detected on.
+const DETECT_SIDE: u32 = 800;
+/// Largest number of corners kept per input, strongest first.
+const MAX_FEATURES: usize = 1000;
+/// Descriptors sample an 8x8 grid with this spacing around each corner.
+const DESCRIPTOR_SPACING: isize = 2;
+/// Distance from the image border a corner needs for its descriptor to fit.
+const MARGIN: usize = 8;
+/// A match is kept only when its distance is below this fraction of the second best one.
+const MATCH_RATIO: f32 = 0.8;
+/// Random four-point samples tried when estimating a homography.
+const RANSAC_ITERATIONS: usize = 2000;
+/// Reprojection error, in detection pixels, up to which a match counts as an inlier.
+const INLIER_DISTANCE: f64 = 2.0;
+/// Inliers needed before two inputs are considered overlapping.
+const MIN_INLIERS: usize = 12;
+/// Fraction of a pixel the projected borders may overshoot the canvas by.
+const BOUNDS_TOLERANCE: f64 = 0.05;
+/// Panoramas above this many pixels are refused; a degenerate homography can otherwise
+/// request an enormous canvas.
+const MAX_PANORAMA_PIXELS: u64 = 1 << 28;
+
+/// How overlapping inputs are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Blend {
+    /// Weighted average that fades each input out towards its borders.
+    #[default]
+    Feather,
+    /// Each pixel is taken from the input it lies deepest inside, leaving hard seams.
+    None,
+}
+
+impl FromStr for Blend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "feather" => Ok(Self::Feather),
+            "none" => Ok(Self::None),
+            other => Err(format!("unknown blend mode `{other}` (expected feather or none)")),
+        }
+    }
+}
+
+/// Row-major 3x3 projective transform.
+type Homography = [f64; 9];
+
+/// A point in one input and the corresponding point in another.
+type Match = ((f64, f64), (f64, f64));
+
+const IDENTITY: Homography = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+/// Stitches `images`, given in order with each overlapping the next, into one panorama.
+///
+/// Corners are matched between neighbouring inputs and a homography is fitted to the matches
+/// with RANSAC; all inputs are then projected onto the plane of the middle one. Blending
+/// happens in linear light on premultiplied values, and canvas pixels no input covers are
+/// left transparent.
+///
+/// # Errors
+/// Returns [`AppError::Stitch`] when fewer than two images are given, when two neighbours
+/// share too few consistent features, or when the projected panorama is unreasonably large.
+pub fn stitch(images: &[RgbaImage], blend: Blend) -> Result<RgbaImage, AppError> {
+    if images.len() < 2 {
+        return Err(AppError::Stitch("at least two inputs are needed".into()));
+    }
+
+    let detected: Vec<(Vec<Feature>, Homography)> = images.par_iter().map(detect).collect();
+    let mut pairwise = Vec::with_capacity(images.len() - 1);
+    for (i, pair) in detected.windows(2).enumerate() {
+        let (a, to_a) = &pair[0];
+        let (b, to_b) = &pair[1];
+        let h = match_pair(a, b).map_err(|inliers| {
+            AppError::Stitch(format!(
+                "inputs {} and {} do not overlap enough ({inliers} consistent matches, {MIN_INLIERS} needed)",
+                i + 1,
+                i + 2
+            ))
+        })?;
+        let from_a = invert(to_a).expect("scaling is invertible");
+        pairwise.push(mul(&mul(&from_a, &h), to_b));
+    }
+
+    // Map every input onto the plane of the middle one to spread the distortion evenly.
+    let reference = images.len() / 2;
+    let mut to_canvas = vec![IDENTITY; images.len()];
+    for i in reference + 1..images.len() {
+        to_canvas[i] = mul(&to_canvas[i - 1], &pairwise[i - 1]);
+    }
+    for i in (0..reference).rev() {
+        let back = invert(&pairwise[i]).ok_or_else(|| AppError::Stitch(format!("input {} is degenerate", i + 1)))?;
+        to_canvas[i] = mul(&to_canvas[i + 1], &back);
+    }
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (f64::MAX, f64::MAX, f64::MIN, f64::MIN);
+    for (i, (img, h)) in images.iter().zip(&to_canvas).enumerate() {
+        let (w, ht) = (img.width() as f64, img.height() as f64);
+        for (x, y) in [(-0.5, -0.5), (w - 0.5, -0.5), (-0.5, ht - 0.5), (w - 0.5, ht - 0.5)] {
+            let (cx, cy) = apply(h, x, y).ok_or_else(|| {
+                AppError::Stitch(format!("input {} cannot be projected onto the panorama plane", i + 1))
+            })?;
+            (min_x, min_y, max_x, max_y) = (min_x.min(cx), min_y.min(cy), max_x.max(cx), max_y.max(cy));
+        }
+    }
+    // The tolerance keeps estimation noise from adding a mostly empty row or column.
+    let (origin_x, origin_y) = ((min_x + 0.5 + BOUNDS_TOLERANCE).floor(), (min_y + 0.5 + BOUNDS_TOLERANCE).floor());
+    let width = (max_x + 0.5 - BOUNDS_TOLERANCE).ceil() - origin_x;
+    let height = (max_y + 0.5 - BOUNDS_TOLERANCE).ceil() - origin_y;
+    if !(width * height).is_finite() || width * height > MAX_PANORAMA_PIXELS as f64 {
+        return Err(AppError::Stitch(format!("the panorama would be {width}x{height} pixels")));
+    }
+    let (width, height) = (width as usize, height as usize);
+
+    // Premultiplied linear RGBA followed by the accumulated weight.
+    let mut canvas = vec![0.0f32; width * height * 5];
+    for (img, h) in images.iter().zip(&to_canvas) {
+        let shift = [1.0, 0.0, -origin_x, 0.0, 1.0, -origin_y, 0.0, 0.0, 1.0];
+        let from_canvas = invert(&mul(&shift, h)).ok_or_else(|| AppError::Stitch("degenerate homography".into()))?;
+        let mut linear = convert::rgba8_to_linear(img.as_raw());
+        convert::premultiply_rgba32f(&mut linear);
+        let source = Source { data: &linear, width: img.width() as usize, height: img.height() as usize };
+        canvas.par_chunks_mut(width * 5).enumerate().for_each(|(y, row)| {
+            for (x, px) in row.chunks_exact_mut(5).enumerate() {
+                let Some((sx, sy)) = apply(&from_canvas, x as f64, y as f64) else {
+                    continue;
+                };
+                let weight = source.weight(sx, sy);
+                if weight <= 0.0 {
+                    continue;
+                }
+                let value = source.sample(sx, sy);
+                match blend {
+                    Blend::Feather => {
+                        for c in 0..4 {
+                            px[c] += weight * value[c];
+                        }
+                        px[4] += weight;
+                    }
+                    Blend::None if weight > px[4] => {
+                        px[..4].copy_from_slice(&value);
+                        px[4] = weight;
+                    }
+                    Blend::None => {}
+                }
+            }
+        });
+    }
+
+    let mut linear: Vec<f32> = canvas
+        .chunks_exact(5)
+        .flat_map(|px| match (blend, px[4]) {
+            (_, 0.0) => [0.0; 4],
+            (Blend::Feather, w) => [px[0] / w, px[1] / w, px[2] / w, px[3] / w],
+            (Blend::None, _) => [px[0], px[1], px[2], px[3]],
+        })
+        .collect();
+    convert::unpremultiply_rgba32f(&mut linear);
+    let data = convert::linear_to_rgba8(&linear);
+    Ok(RgbaImage::from_raw(width as u32, height as u32, data).expect("buffer matches dimensions"))
+}
+
+/// A corner and the normalized patch around it, in detection pixels.
+struct Feature {
+    x: f64,
+    y: f64,
+    descriptor: [f32; 64],
+}
+
+/// Grayscale plane in encoded values in `[0, 1]`.
+struct Gray {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+/// Detects features on a downscaled copy of `img`, returning them together with the transform
+/// from full resolution pixel coordinates to detection coordinates.
+fn detect(img: &RgbaImage) -> (Vec<Feature>, Homography) {
+    let scale = (DETECT_SIDE as f64 / img.width().max(img.height()) as f64).min(1.0);
+    let small = (scale < 1.0).then(|| {
+        let w = ((img.width() as f64 * scale).round() as u32).max(1);
+        let h = ((img.height() as f64 * scale).round() as u32).max(1);
+        imageops::resize(img, w, h, FilterType::Triangle)
+    });
+    let detected = small.as_ref().unwrap_or(img);
+    let gray = Gray {
+        width: detected.width() as usize,
+        height: detected.height() as usize,
+        data: detected
+            .pixels()
+            .map(|p| (0.299 * p[0] as f32 + 0.587 * p[1] as f32 + 0.114 * p[2] as f32) / 255.0)
+            .collect(),
+    };
+    // Pixel centres: a full resolution x maps to (x + 0.5) * sx - 0.5.
+    let sx = detected.width() as f64 / img.width() as f64;
+    let sy = detected.height() as f64 / img.height() as f64;
+    (features(&gray), [sx, 0.0, 0.5 * sx - 0.5, 0.0, sy, 0.5 * sy - 0.5, 0.0, 0.0, 1.0])
+}
+
+/// Harris corners of `gray` with their descriptors.
+fn features(gray: &Gray) -> Vec<Feature> {
+    let (w, h) = (gray.width, gray.height);
+    if w <= 2 * MARGIN || h <= 2 * MARGIN {
+        return Vec::new();
+    }
+    let mut xx = vec![0.0f32; w * h];
+    let mut yy = vec![0.0f32; w * h];
+    let mut xy = vec![0.0f32; w * h];
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let at = |dx: usize, dy: usize| gray.data[(y + dy - 1) * w + x + dx - 1];
+            let gx = (at(2, 0) + 2.0 * at(2, 1) + at(2, 2)) - (at(0, 0) + 2.0 * at(0, 1) + at(0, 2));
+            let gy = (at(0, 2) + 2.0 * at(1, 2) + at(2, 2)) - (at(0, 0) + 2.0 * at(1, 0) + at(2, 0));
+            let i = y * w + x;
+            (xx[i], yy[i], xy[i]) = (gx * gx, gy * gy, gx * gy);
+        }
+    }
+    let (xx, yy, xy) = (box_blur(&xx, w, h, 2), box_blur(&yy, w, h, 2), box_blur(&xy, w, h, 2));
+    let response: Vec<f32> =
+        (0..w * h).map(|i| xx[i] * yy[i] - xy[i] * xy[i] - 0.04 * (xx[i] + yy[i]).powi(2)).collect();
+    let threshold = response.iter().copied().fold(0.0f32, f32::max) * 1e-3;
+
+    let mut corners = Vec::new();
+    for y in MARGIN..h - MARGIN {
+        for x in MARGIN..w - MARGIN {
+            let r = response[y * w + x];
+            if r <= threshold {
+                continue;
+            }
+            // Non-maximum suppression; ties go to the first pixel in scan order.
+            let is_max = (y - 2..=y + 2).all(|ny| {
+                (x - 2..=x + 2).all(|nx| {
+                    let other = response[ny * w + nx];
+                    other < r || (other == r && (ny, nx) >= (y, x))
+                })
+            });
+            if is_max {
+                corners.push((r, x, y));
+            }
+        }
+    }
+    corners.sort_by(|a, b| b.0.total_cmp(&a.0));
+    corners.truncate(MAX_FEATURES);
+
+    let smooth = box_blur(&gray.data, w, h, 1);
+    corners
+        .into_iter()
+        .filter_map(|(_, x, y)| {
+            let mut descriptor = [0.0f32; 64];
+            for (i, d) in descriptor.iter_mut().enumerate() {
+                let dx = (i % 8) as isize * DESCRIPTOR_SPACING - 7 * DESCRIPTOR_SPACING / 2;
+                let dy = (i / 8) as isize * DESCRIPTOR_SPACING - 7 * DESCRIPTOR_SPACING / 2;
+                *d = smooth[(y as isize + dy) as usize * w + (x as isize + dx) as usize];
+            }
+            let mean = descriptor.iter().sum::<f32>() / 64.0;
+            descriptor.iter_mut().for_each(|d| *d -= mean);
+            let norm = descriptor.iter().map(|d| d * d).sum::<f32>().sqrt();
+            if norm < 1e-4 {
+                return None;
+            }
+            descriptor.iter_mut().for_each(|d| *d /= norm);
+            Some(Feature { x: x as f64, y: y as f64, descriptor })
+        })
+        .collect()
+}
+
+/// Separable box blur of radius `r` with clamped edges.
+fn box_blur(data: &[f32], w: usize, h: usize, r: usize) -> Vec<f32> {
+    let norm = 1.0 / (2 * r + 1) as f32;
+    let mut rows = vec![0.0f32; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let sum: f32 = (0..=2 * r).map(|k| data[y * w + (x + k).saturating_sub(r).min(w - 1)]).sum();
+            rows[y * w + x] = sum * norm;
+        }
+    }
+    let mut out = vec![0.0f32; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let sum: f32 = (0..=2 * r).map(|k| rows[(y + k).saturating_sub(r).min(h - 1) * w + x]).sum();
+            out[y * w + x] = sum * norm;
+        }
+    }
+    out
+}
+
+/// Estimates the homography mapping detection coordinates of `b` onto those of `a`.
+///
+/// Fails with the number of inliers found when the overlap is not convincing.
+fn match_pair(a: &[Feature], b: &[Feature]) -> Result<Homography, usize> {
+    let matches: Vec<Match> = a
+        .par_iter()
+        .filter_map(|fa| {
+            let (mut best, mut second, mut best_j) = (f32::MAX, f32::MAX, 0);
+            for (j, fb) in b.iter().enumerate() {
+                let d: f32 = fa.descriptor.iter().zip(&fb.descriptor).map(|(p, q)| (p - q) * (p - q)).sum();
+                if d < best {
+                    (second, best, best_j) = (best, d, j);
+                } else if d < second {
+                    second = d;
+                }
+            }
+            (best < MATCH_RATIO * MATCH_RATIO * second).then(|| ((b[best_j].x, b[best_j].y), (fa.x, fa.y)))
+        })
+        .collect();
+    if matches.len() < 4 {
+        return Err(matches.len());
+    }
+
+    let inliers_of = |h: &Homography| -> Vec<Match> {
+        matches
+            .iter()
+            .copied()
+            .filter(|&((x, y), (ax, ay))| {
+                apply(h, x, y).is_some_and(|(px, py)| (px - ax).powi(2) + (py - ay).powi(2) < INLIER_DISTANCE.powi(2))
+            })
+            .collect()
+    };
+    let mut rng = Rng::new(matches.len() as u64);
+    let mut best: Vec<Match> = Vec::new();
+    for _ in 0..RANSAC_ITERATIONS {
+        let mut sample = [0usize; 4];
+        for k in 0..4 {
+            sample[k] = loop {
+                let i = (rng.next_u64() % matches.len() as u64) as usize;
+                if !sample[..k].contains(&i) {
+                    break i;
+                }
+            };
+        }
+        let Some(h) = fit(&sample.map(|i| matches[i])) else {
+            continue;
+        };
+        let inliers = inliers_of(&h);
+        if inliers.len() > best.len() {
+            best = inliers;
+        }
+    }
+    if best.len() < MIN_INLIERS {
+        return Err(best.len());
+    }
+    let h = fit(&best).ok_or(best.len())?;
+    // One refinement round on the inliers of the least-squares fit.
+    let refined = inliers_of(&h);
+    if refined.len() < MIN_INLIERS {
+        return Err(refined.len());
+    }
+    fit(&refined).ok_or(refined.len())
+}
+
+/// Least-squares homography mapping the first point of each pair onto the second.
+///
+/// Points are normalized first for numerical stability. Returns `None` for degenerate
+/// configurations, such as fewer than four points or three of four being collinear.
+fn fit(pairs: &[Match]) -> Option<Homography> {
+    if pairs.len() < 4 {
+        return None;
+    }
+    let from = normalization(pairs.iter().map(|p| p.0));
+    let to = normalization(pairs.iter().map(|p| p.1));
+    let mut normal = [[0.0f64; 9]; 8];
+    for &((x, y), (u, v)) in pairs {
+        let (x, y) = apply(&from, x, y)?;
+        let (u, v) = apply(&to, u, v)?;
+        let rows = [
+            ([x, y, 1.0, 0.0, 0.0, 0.0, -x * u, -y * u], u),
+            ([0.0, 0.0, 0.0, x, y, 1.0, -x * v, -y * v], v),
+        ];
+        for (row, rhs) in rows {
+            for i in 0..8 {
+                for j in 0..8 {
+                    normal[i][j] += row[i] * row[j];
+                }
+                normal[i][8] += row[i] * rhs;
+            }
+        }
+    }
+    let h = solve(normal)?;
+    let h = [h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0];
+    let h = mul(&mul(&invert(&to)?, &h), &from);
+    Some(h.map(|v| v / h[8]))
+}
+
+/// Similarity transform moving `points` to their centroid and scaling their mean distance
+/// from it to √2.
+fn normalization(points: impl Iterator<Item = (f64, f64)> + Clone) -> Homography {
+    let n = points.clone().count() as f64;
+    let (cx, cy) = points.clone().fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x / n, sy + y / n));
+    let spread = points.map(|(x, y)| (x - cx).hypot(y - cy)).sum::<f64>() / n;
+    let s = if spread > 0.0 { std::f64::consts::SQRT_2 / spread } else { 1.0 };
+    [s, 0.0, -s * cx, 0.0, s, -s * cy, 0.0, 0.0, 1.0]
+}
+
+/// Solves an 8x8 system given as an augmented matrix by Gaussian elimination with partial
+/// pivoting.
+fn solve(mut m: [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let pivot_row = m[col];
+        for row in m.iter_mut().skip(col + 1) {
+            let f = row[col] / pivot_row[col];
+            for (v, p) in row.iter_mut().zip(&pivot_row).skip(col) {
+                *v -= f * p;
+            }
+        }
+    }
+    let mut x = [0.0f64; 8];
+    for i in (0..8).rev() {
+        let sum: f64 = (i + 1..8).map(|j| m[i][j] * x[j]).sum();
+        x[i] = (m[i][8] - sum) / m[i][i];
+    }
+    Some(x)
+}
+
+/// Matrix product `a * b`: applies `b` first.
+fn mul(a: &Homography, b: &Homography) -> Homography {
+    std::array::from_fn(|i| (0..3).map(|k| a[i / 3 * 3 + k] * b[k * 3 + i % 3]).sum())
+}
+
+/// Maps a point, or returns `None` when it lands on or behind the horizon.
+fn apply(h: &Homography, x: f64, y: f64) -> Option<(f64, f64)> {
+    let w = h[6] * x + h[7] * y + h[8];
+    (w > 1e-9).then(|| ((h[0] * x + h[1] * y + h[2]) / w, (h[3] * x + h[4] * y + h[5]) / w))
+}
+
+/// Inverse via the adjugate, or `None` when `h` is singular.
+fn invert(h: &Homography) -> Option<Homography> {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| h[r0 * 3 + c0] * h[r1 * 3 + c1] - h[r0 * 3 + c1] * h[r1 * 3 + c0];
+    let adj = [
+        cofactor(1, 2, 1, 2),
+        -cofactor(0, 2, 1, 2),
+        cofactor(0, 1, 1, 2),
+        -cofactor(1, 2, 0, 2),
+        cofactor(0, 2, 0, 2),
+        -cofactor(0, 1, 0, 2),
+        cofactor(1, 2, 0, 1),
+        -cofactor(0, 2, 0, 1),
+        cofactor(0, 1, 0, 1),
+    ];
+    let det = h[0] * adj[0] + h[1] * adj[3] + h[2] * adj[6];
+    (det.abs() > 1e-12).then(|| adj.map(|v| v / det))
+}
+
+/// Premultiplied linear RGBA input being projected onto the canvas.
+struct Source<'a> {
+    data: &'a [f32],
+    width: usize,
+    height: usize,
+}
+
+impl Source<'_> {
+    /// Blend weight at a source position: 1 in the centre, falling linearly to 0 at the
+    /// borders, and 0 outside.
+    fn weight(&self, x: f64, y: f64) -> f32 {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let dx = (x + 0.5).min(w - 0.5 - x) / (w / 2.0);
+        let dy = (y + 0.5).min(h - 0.5 - y) / (h / 2.0);
+        if dx <= 0.0 || dy <= 0.0 { 0.0 } else { (dx * dy) as f32 }
+    }
+
+    /// Bilinear sample with clamped edges.
+    fn sample(&self, x: f64, y: f64) -> [f32; 4] {
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = ((x - x0 as f64) as f32, (y - y0 as f64) as f32);
+        let px = |x: usize, y: usize| &self.data[(y * self.width + x) * 4..][..4];
+        std::array::from_fn(|c| {
+            let top = px(x0, y0)[c] * (1.0 - fx) + px(x1, y0)[c] * fx;
+            let bottom = px(x0, y1)[c] * (1.0 - fx) + px(x1, y1)[c] * fx;
+            top * (1.0 - fy) + bottom * fy
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blocks of random colour, giving plenty of distinct corners.
+    fn scene(width: u32, height: u32, seed: u64) -> RgbaImage {
+        let mut rng = Rng::new(seed);
+        let cells: Vec<[u8; 3]> = (0..(width / 6 + 1) * (height / 6 + 1))
+            .map(|_| [0; 3].map(|_| (rng.next_u64() % 256) as u8))
+            .collect();
+        RgbaImage::from_fn(width, height, |x, y| {
+            let [r, g, b] = cells[((y / 6) * (width / 6 + 1) + x / 6) as usize];
+            image::Rgba([r, g, b, 255])
+        })
+    }
+
+    #[test]
+    fn test_fit_recovers_homography() {
+        let h = [1.1, 0.05, 12.0, -0.02, 0.95, -7.0, 1e-4, -2e-4, 1.0];
+        let pairs: Vec<_> = [(0.0, 0.0), (200.0, 10.0), (30.0, 150.0), (180.0, 170.0), (90.0, 60.0)]
+            .into_iter()
+            .map(|(x, y)| ((x, y), apply(&h, x, y).unwrap()))
+            .collect();
+        let fitted = fit(&pairs).unwrap();
+        for (a, b) in fitted.iter().zip(&h) {
+            assert!((a - b).abs() < 1e-6, "{fitted:?}");
+        }
+        assert!(fit(&pairs[..3]).is_none());
+    }
+
+    #[test]
+    fn test_stitches_overlapping_crops() {
+        let full = scene(240, 120, 7);
+        let left = imageops::crop_imm(&full, 0, 0, 150, 120).to_image();
+        let right = imageops::crop_imm(&full, 85, 0, 155, 120).to_image();
+        let panorama = stitch(&[left, right], Blend::Feather).unwrap();
+        assert_eq!(panorama.dimensions(), (240, 120));
+        let error: u64 = panorama
+            .pixels()
+            .zip(full.pixels())
+            .map(|(a, b)| (0..4).map(|c| a[c].abs_diff(b[c]) as u64).sum::<u64>())
+            .sum();
+        assert!(error / (240 * 120 * 4) < 2, "mean error {}", error / (240 * 120 * 4));
+    }
+
+    #[test]
+    fn test_unrelated_inputs_fail() {
+        let err = stitch(&[scene(120, 120, 1), scene(120, 120, 2)], Blend::None).unwrap_err();
+        assert!(matches!(err, AppError::Stitch(_)), "{err}");
+    }
+
+    #[test]
+    fn test_blend_from_str() {
+        assert_eq!("none".parse::<Blend>(), Ok(Blend::None));
+        assert!("linear".parse::<Blend>().is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use image::DynamicImage;
+use std::path::Path;
+
+use crate::error::AppError;
+
+/// Extensions of SVG inputs; `.svgz` is gzip-compressed SVG.
+pub const SVG_EXTENSIONS: [&str; 2] = ["svg", "svgz"];
+/// Resolution at which one SVG user unit is one pixel, as in CSS.
+pub const CSS_DPI: f32 = 96.0;
+
+/// How SVG inputs are rasterized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvgOptions {
+    /// Resolution to render at; 96 renders one user unit to one pixel.
+    pub dpi: f32,
+    /// Render to this width in pixels instead, keeping the aspect ratio; overrides `dpi`.
+    pub width: Option<u32>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self { dpi: CSS_DPI, width: None }
+    }
+}
+
+/// Returns `true` if the extension of `path` is that of an SVG document.
+pub fn is_svg_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| SVG_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Pixel size and scale factor of a document `width` x `height` user units large.
+pub fn output_size(width: f32, height: f32, opts: &SvgOptions) -> (u32, u32, f32) {
+    let scale = match opts.width {
+        Some(w) => w as f32 / width,
+        None => opts.dpi / CSS_DPI,
+    };
+    let pixels = |v: f32| ((v * scale).round() as u32).max(1);
+    (opts.width.unwrap_or_else(|| pixels(width)), pixels(height), scale)
+}
+
+/// System fonts for `<text>`, loaded once per process.
+#[cfg(feature = "svg")]
+fn fonts() -> std::sync::Arc<resvg::usvg::fontdb::Database> {
+    use resvg::usvg;
+    use std::sync::{Arc, OnceLock};
+
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            db.load_system_fonts();
+            Arc::new(db)
+        })
+        .clone()
+}
+
+/// Rasterizes an SVG (or gzip-compressed SVGZ) document into an RGBA8 image.
+///
+/// Relative references to images are resolved against `resources_dir`, the directory of the
+/// document when it is a local file. Areas the drawing does not cover stay transparent.
+#[cfg(feature = "svg")]
+pub fn rasterize(bytes: &[u8], opts: &SvgOptions, resources_dir: Option<&Path>) -> Result<DynamicImage, AppError> {
+    use image::RgbaImage;
+    use resvg::tiny_skia::{Pixmap, Transform};
+    use resvg::usvg;
+
+    use crate::convert::unpremultiply_rgba8;
+
+    if !(opts.dpi.is_finite() && opts.dpi > 0.0) || opts.width == Some(0) {
+        return Err(AppError::Svg(format!("cannot render at {} dpi, width {:?}", opts.dpi, opts.width)));
+    }
+    let options = usvg::Options {
+        resources_dir: resources_dir.map(Path::to_path_buf),
+        fontdb: fonts(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_data(bytes, &options).map_err(|e| AppError::Svg(e.to_string()))?;
+    let size = tree.size();
+    let (width, height, scale) = output_size(size.width(), size.height(), opts);
+    let mut pixmap = Pixmap::new(width, height)
+        .ok_or_else(|| AppError::Svg(format!("cannot allocate a {width}x{height} canvas")))?;
+    resvg::render(&tree, Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    let mut data = pixmap.take();
+    unpremultiply_rgba8(&mut data);
+    let img = RgbaImage::from_raw(width, height, data).expect("pixmap matches size");
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+/// Stand-in for builds without the `svg` feature.
+#[cfg(not(feature = "svg"))]
+pub fn rasterize(_bytes: &[u8], _opts: &SvgOptions, _resources_dir: Option<&Path>) -> Result<DynamicImage, AppError> {
+    Err(AppError::Svg("built without the `svg` feature".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "svg")]
+    const DOC: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="20">
+        <rect x="0" y="0" width="20" height="20" fill="#ff0000"/>
+        <rect x="20" y="0" width="20" height="20" fill="#0000ff" fill-opacity="0.5"/>
+    </svg>"##;
+
+    #[test]
+    fn test_output_size() {
+        assert_eq!(output_size(40.0, 20.0, &SvgOptions::default()), (40, 20, 1.0));
+        assert_eq!(output_size(40.0, 20.0, &SvgOptions { dpi: 192.0, width: None }), (80, 40, 2.0));
+        assert_eq!(output_size(40.0, 20.0, &SvgOptions { dpi: 300.0, width: Some(100) }), (100, 50, 2.5));
+        assert_eq!(output_size(40.0, 0.1, &SvgOptions::default()).1, 1);
+        assert!(is_svg_path(Path::new("logo.SVG")) && !is_svg_path(Path::new("logo.png")));
+    }
+
+    #[cfg(feature = "svg")]
+    #[test]
+    fn test_rasterize() {
+        use image::Rgba;
+
+        let img = rasterize(DOC.as_bytes(), &SvgOptions { dpi: 192.0, width: None }, None).unwrap().into_rgba8();
+        assert_eq!(img.dimensions(), (80, 40));
+        assert_eq!(img.get_pixel(10, 10), &Rgba([255, 0, 0, 255]));
+        // Half-transparent fills come out straight, not premultiplied.
+        let px = img.get_pixel(60, 10);
+        assert_eq!((px[0], px[1], px[3]), (0, 0, 128));
+        assert!(px[2] >= 254, "{px:?}");
+
+        assert!(matches!(rasterize(b"<svg", &SvgOptions::default(), None), Err(AppError::Svg(_))));
+    }
+}
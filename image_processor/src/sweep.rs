@@ -0,0 +1,179 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Duration;
+use toml::Value;
+
+use crate::params::ParamOverride;
+
+/// A `--benchmark-matrix` sweep: one param stepped over an inclusive range.
+///
+/// Written `[plugin:]key=START..END [step STEP]`, e.g. `radius=1..32 step 2`. The step
+/// defaults to 1. If all three numbers are integers, the param is swept as an integer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    /// Plugin the swept param belongs to, if restricted to one step.
+    pub plugin: Option<String>,
+    /// Dotted key path of the param.
+    pub key: String,
+    /// First value.
+    pub start: f64,
+    /// Last value; included if the steps land on it.
+    pub end: f64,
+    /// Distance between consecutive values.
+    pub step: f64,
+    integer: bool,
+}
+
+impl FromStr for Sweep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, range) = s.split_once('=').ok_or_else(|| format!("expected key=START..END, got `{s}`"))?;
+        let (plugin, key) = match target.split_once(':') {
+            Some((plugin, key)) => (Some(plugin.trim().to_string()), key.trim()),
+            None => (None, target.trim()),
+        };
+        if key.is_empty() {
+            return Err(format!("missing key in `{s}`"));
+        }
+
+        let (range, step) = match range.split_once("step") {
+            Some((range, step)) => (range.trim(), step.trim()),
+            None => (range.trim(), "1"),
+        };
+        let (start, end) = range.split_once("..").ok_or_else(|| format!("expected START..END, got `{range}`"))?;
+        let number = |text: &str| text.trim().parse::<f64>().map_err(|_| format!("invalid number `{}`", text.trim()));
+        let (start, end, step) = (number(start)?, number(end)?, number(step)?);
+        if !step.is_finite() || step <= 0.0 || !start.is_finite() || !end.is_finite() {
+            return Err(format!("step must be positive and the range finite in `{s}`"));
+        }
+        if end < start {
+            return Err(format!("range end is below its start in `{s}`"));
+        }
+
+        let integer = [start, end, step].iter().all(|v| v.fract() == 0.0);
+        Ok(Self { plugin, key: key.to_string(), start, end, step, integer })
+    }
+}
+
+impl Sweep {
+    /// Returns the values of the sweep in increasing order.
+    pub fn values(&self) -> Vec<Value> {
+        let count = ((self.end - self.start) / self.step + 1e-9).floor() as u64 + 1;
+        (0..count)
+            .map(|i| self.start + i as f64 * self.step)
+            .map(|v| if self.integer { Value::Integer(v as i64) } else { Value::Float(v) })
+            .collect()
+    }
+
+    /// Returns the `--param` override setting the swept param to `value`.
+    pub fn override_with(&self, value: Value) -> ParamOverride {
+        ParamOverride { plugin: self.plugin.clone(), key: self.key.clone(), value }
+    }
+}
+
+/// Difference between a processed image and the input it was made from, over all channels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    /// Mean absolute difference in 8-bit levels.
+    pub mae: f64,
+    /// Peak signal-to-noise ratio in dB; infinite for identical images.
+    pub psnr: f64,
+}
+
+impl Metrics {
+    /// Compares two RGBA8 buffers of the same size.
+    pub fn compare(reference: &[u8], output: &[u8]) -> Self {
+        let n = reference.len().min(output.len()).max(1) as f64;
+        let (abs, sq) = reference.iter().zip(output).fold((0u64, 0u64), |(abs, sq), (a, b)| {
+            let d = a.abs_diff(*b) as u64;
+            (abs + d, sq + d * d)
+        });
+        let mse = sq as f64 / n;
+        let psnr = if mse == 0.0 { f64::INFINITY } else { 10.0 * (255.0 * 255.0 / mse).log10() };
+        Self { mae: abs as f64 / n, psnr }
+    }
+}
+
+/// Measurements for one value of a sweep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    /// Value of the swept param.
+    pub value: Value,
+    /// Duration of the plugin chain in each run.
+    pub runs: Vec<Duration>,
+    /// Output metrics, if requested.
+    pub metrics: Option<Metrics>,
+}
+
+/// Renders the rows as CSV with one line per value: timings in milliseconds, then the metrics.
+pub fn to_csv(key: &str, rows: &[Row]) -> String {
+    let with_metrics = rows.iter().any(|r| r.metrics.is_some());
+    let mut out = format!("{key},runs,mean_ms,min_ms,max_ms");
+    if with_metrics {
+        out.push_str(",mae,psnr_db");
+    }
+    out.push('\n');
+
+    for row in rows {
+        let millis: Vec<f64> = row.runs.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        let mean = millis.iter().sum::<f64>() / millis.len().max(1) as f64;
+        let min = millis.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = millis.iter().copied().fold(0.0, f64::max);
+        let _ = write!(out, "{},{},{mean:.3},{min:.3},{max:.3}", row.value, millis.len());
+        if with_metrics {
+            match row.metrics {
+                Some(m) => {
+                    let _ = write!(out, ",{:.4},{:.3}", m.mae, m.psnr);
+                }
+                None => out.push_str(",,"),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_values() {
+        let sweep: Sweep = "radius=1..32 step 2".parse().unwrap();
+        assert_eq!(sweep.plugin, None);
+        let values = sweep.values();
+        assert_eq!(values.len(), 16);
+        assert_eq!(values[0], Value::Integer(1));
+        assert_eq!(values[15], Value::Integer(31));
+
+        let sweep: Sweep = "blur_plugin:sigma=0.5..1.5 step 0.25".parse().unwrap();
+        assert_eq!(sweep.plugin.as_deref(), Some("blur_plugin"));
+        assert_eq!(sweep.values().last(), Some(&Value::Float(1.5)));
+        assert_eq!(sweep.override_with(Value::Float(1.0)).key, "sigma");
+
+        assert!("radius=4..1".parse::<Sweep>().is_err());
+        assert!("radius=1..4 step 0".parse::<Sweep>().is_err());
+        assert!("radius".parse::<Sweep>().is_err());
+    }
+
+    #[test]
+    fn test_metrics_and_csv() {
+        let same = Metrics::compare(&[10, 20, 30, 40], &[10, 20, 30, 40]);
+        assert_eq!(same.mae, 0.0);
+        assert!(same.psnr.is_infinite());
+
+        let off = Metrics::compare(&[0; 4], &[2; 4]);
+        assert_eq!(off.mae, 2.0);
+        assert!((off.psnr - 10.0 * (255.0f64 * 255.0 / 4.0).log10()).abs() < 1e-9);
+
+        let rows = vec![Row {
+            value: Value::Integer(3),
+            runs: vec![Duration::from_millis(2), Duration::from_millis(4)],
+            metrics: Some(off),
+        }];
+        let csv = to_csv("radius", &rows);
+        assert_eq!(csv.lines().next(), Some("radius,runs,mean_ms,min_ms,max_ms,mae,psnr_db"));
+        assert!(csv.lines().nth(1).unwrap().starts_with("3,2,3.000,2.000,4.000,2.0000,"));
+    }
+}
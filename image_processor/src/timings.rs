@@ -0,0 +1,133 @@
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Output format of the `--timings` report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingsFormat {
+    /// Aligned human-readable table.
+    Text,
+    /// A single JSON object per processed image.
+    Json,
+}
+
+impl FromStr for TimingsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!("unknown timings format `{other}` (expected text or json)")),
+        }
+    }
+}
+
+/// Duration of a single pipeline stage.
+#[derive(Debug, Clone, Serialize)]
+pub struct Stage {
+    /// Stage name, e.g. `decode`, `plugin:blur_plugin` or `encode`.
+    pub name: String,
+    /// Wall-clock duration in milliseconds.
+    pub millis: f64,
+}
+
+/// Per-stage timing breakdown of processing one image.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Timings {
+    /// Image the timings belong to.
+    pub input: String,
+    /// Stages in execution order.
+    pub stages: Vec<Stage>,
+    /// Sum of all stage durations in milliseconds.
+    pub total_millis: f64,
+    /// Peak resident set size of the process in bytes, where the platform reports it.
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl Timings {
+    /// Creates an empty report for `input`.
+    pub fn new(input: String) -> Self {
+        Self { input, ..Self::default() }
+    }
+
+    /// Runs `f` and records its duration under `name`.
+    pub fn time<T>(&mut self, name: impl Into<String>, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = f();
+        self.push(name, start.elapsed());
+        value
+    }
+
+    /// Records a stage that was measured elsewhere.
+    pub fn push(&mut self, name: impl Into<String>, elapsed: Duration) {
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        self.total_millis += millis;
+        self.stages.push(Stage { name: name.into(), millis });
+    }
+
+    /// Renders the report in the requested format, sampling peak RSS now.
+    pub fn render(&mut self, format: TimingsFormat) -> String {
+        self.peak_rss_bytes = peak_rss_bytes();
+        match format {
+            TimingsFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+            TimingsFormat::Text => {
+                let width = self.stages.iter().map(|s| s.name.len()).max().unwrap_or(0).max(5);
+                let mut out = format!("timings for {}\n", self.input);
+                for stage in &self.stages {
+                    let share = if self.total_millis > 0.0 { stage.millis / self.total_millis * 100.0 } else { 0.0 };
+                    out.push_str(&format!("  {:<width$}  {:>10.2} ms  {:>5.1}%\n", stage.name, stage.millis, share));
+                }
+                out.push_str(&format!("  {:<width$}  {:>10.2} ms\n", "total", self.total_millis));
+                if let Some(rss) = self.peak_rss_bytes {
+                    out.push_str(&format!("  {:<width$}  {:>10.1} MiB\n", "peak rss", rss as f64 / (1024.0 * 1024.0)));
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Returns the peak resident set size of the current process in bytes.
+#[cfg(unix)]
+pub fn peak_rss_bytes() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY:
+    // - `getrusage` only writes into the provided, properly sized and aligned `rusage` struct.
+    // - We only call `assume_init` after checking that the call succeeded.
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None;
+        }
+        usage.assume_init()
+    };
+    let max_rss = usage.ru_maxrss as u64;
+    // Linux reports kilobytes, macOS reports bytes.
+    if cfg!(target_os = "macos") { Some(max_rss) } else { Some(max_rss * 1024) }
+}
+
+/// Returns the peak resident set size of the current process in bytes.
+#[cfg(not(unix))]
+pub fn peak_rss_bytes() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings_render() {
+        let mut t = Timings::new("in.png".to_string());
+        t.push("decode", Duration::from_millis(3));
+        assert_eq!(t.time("encode", || 42), 42);
+
+        let text = t.render(TimingsFormat::Text);
+        assert!(text.contains("decode"));
+        assert!(text.contains("encode"));
+
+        let json: serde_json::Value = serde_json::from_str(&t.render(TimingsFormat::Json)).unwrap();
+        assert_eq!(json["stages"][0]["name"], "decode");
+        assert_eq!(json["input"], "in.png");
+    }
+}
@@ -0,0 +1,36 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Polls modification times of a set of files to detect changes.
+pub struct FileWatcher {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileWatcher {
+    /// Starts watching `paths`, recording their current modification times.
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths.into_iter().map(|p| {
+            let mtime = modified(&p);
+            (p, mtime)
+        });
+        Self { files: files.collect() }
+    }
+
+    /// Returns `true` if any watched file changed since the previous call.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, last) in &mut self.files {
+            let now = modified(path);
+            if now != *last {
+                tracing::debug!(file = path.display().to_string(), "change detected");
+                *last = now;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
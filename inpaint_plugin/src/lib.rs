@@ -0,0 +1,418 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Which pixels are filled in.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum MaskSource {
+    /// The secondary input if the host passes one, else the transparent pixels.
+    #[default]
+    Auto,
+    /// The fully transparent pixels of the image.
+    Alpha,
+    /// The light pixels of the secondary input (`--input2`), stretched to the image size.
+    Input2,
+}
+
+/// How the marked pixels are filled.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// Telea (2004): fills from the boundary inward by fast marching, each pixel a weighted
+    /// average of the known pixels near it, favouring those close to it and along the direction
+    /// the fill advances; continues edges that run into the region.
+    #[default]
+    Telea,
+    /// The Telea fill relaxed into the smoothest surface matching the boundary, as heat diffusion
+    /// would settle; seamless on gradients and soft backgrounds, but blurs edges.
+    Diffusion,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mask: MaskSource,
+    #[serde(default)]
+    method: Method,
+    /// Telea: distance in pixels within which known pixels contribute.
+    #[serde(default = "default_radius")]
+    radius: u32,
+    /// Pixels the marked region is grown by on each side, to cover antialiased or compressed
+    /// edges of scratches and logos.
+    #[serde(default)]
+    padding: u32,
+    /// Diffusion: relaxation sweeps over the region.
+    #[serde(default = "default_iterations")]
+    iterations: u32,
+}
+
+fn default_radius() -> u32 {
+    5
+}
+
+fn default_iterations() -> u32 {
+    200
+}
+
+const MANIFEST: &CStr = cr#"name = "inpaint_plugin"
+version = "0.1.0"
+description = "Fills transparent or masked regions from their surroundings, removing scratches and logos"
+
+[defaults]
+mask = "auto"
+method = "telea"
+radius = 5
+padding = 0
+iterations = 200
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Inpaints both formats. It is not local: a large region is filled from its whole boundary.
+///
+/// Fails if `mask = "input2"` and the host passed no secondary input.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    if params.radius == 0 {
+        return Err(PluginError::Invalid("radius must be positive"));
+    }
+    // SAFETY: the host keeps the secondary input valid for the duration of the call.
+    let input2 = unsafe { ctx.input2_image() };
+    let mask = match params.mask {
+        MaskSource::Auto => input2,
+        MaskSource::Alpha => None,
+        MaskSource::Input2 => Some(input2.ok_or(PluginError::Invalid("no secondary input to use as the mask"))?),
+    };
+    let premultiplied = ctx.premultiplied();
+
+    let size = (width as usize, height as usize);
+    let marked = match (mask, &pixels) {
+        (Some(mask), _) => {
+            let mask_size = (mask.width as usize, mask.height as usize);
+            match mask.pixels {
+                PixelsRef::Rgba8(layer) => marked_by_layer(size, mask_size, layer, premultiplied),
+                PixelsRef::Rgba32F(layer) => marked_by_layer(size, mask_size, layer, premultiplied),
+            }
+        }
+        (None, Pixels::Rgba8(buf)) => marked_by_alpha(buf),
+        (None, Pixels::Rgba32F(buf)) => marked_by_alpha(buf),
+    };
+    let marked = grow(marked, size, params.padding as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => inpaint(size, buf, marked, params, premultiplied),
+        Pixels::Rgba32F(buf) => inpaint(size, buf, marked, params, premultiplied),
+    }
+    Ok(())
+}
+
+fn marked_by_alpha<T: Sample>(buf: &[T]) -> Vec<bool> {
+    buf.chunks_exact(4).map(|px| px[3].to_f32() <= 0.0).collect()
+}
+
+/// Marks the pixels whose nearest mask pixel has an sRGB-encoded luma, times alpha, of at
+/// least one half.
+fn marked_by_layer<T: Sample>(
+    (width, height): (usize, usize),
+    (mw, mh): (usize, usize),
+    layer: &[T],
+    premultiplied: bool,
+) -> Vec<bool> {
+    if mw == 0 || mh == 0 {
+        return vec![false; width * height];
+    }
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = ((i % width) * mw / width, (i / width) * mh / height);
+            let px = &layer[(y * mw + x) * 4..][..4];
+            let alpha = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+            let luma: f32 = (0..3).map(|c| LUMA[c] * plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)).sum();
+            luma * alpha >= 0.5
+        })
+        .collect()
+}
+
+/// Grows the marked area by `radius` pixels in every direction (a square window).
+fn grow(marked: Vec<bool>, (width, height): (usize, usize), radius: usize) -> Vec<bool> {
+    if radius == 0 {
+        return marked;
+    }
+    let mut rows = vec![false; marked.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(radius), (x + radius + 1).min(width));
+            rows[y * width + x] = marked[y * width + x0..y * width + x1].iter().any(|&m| m);
+        }
+    }
+    (0..marked.len())
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            (y.saturating_sub(radius)..(y + radius + 1).min(height)).any(|sy| rows[sy * width + x])
+        })
+        .collect()
+}
+
+/// Fast-marching state of a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Original or already filled.
+    Known,
+    /// On the front: filled, with its arrival time final once popped from the heap.
+    Band,
+    /// Still to fill.
+    Inside,
+}
+
+/// Fills the marked pixels, all four channels, working on premultiplied values so that
+/// transparent holes take both color and alpha from their surroundings. Leaves the image alone
+/// if nothing or everything is marked.
+fn inpaint<T: Sample>(
+    (width, height): (usize, usize),
+    buf: &mut [T],
+    marked: Vec<bool>,
+    params: &Params,
+    premultiplied: bool,
+) {
+    if !marked.contains(&true) || !marked.contains(&false) {
+        return;
+    }
+    let mut image: Vec<[f32; 4]> = buf
+        .chunks_exact(4)
+        .map(|px| {
+            let alpha = if premultiplied { 1.0 } else { px[3].to_f32() / T::MAX };
+            [px[0].to_f32() * alpha, px[1].to_f32() * alpha, px[2].to_f32() * alpha, px[3].to_f32()]
+        })
+        .collect();
+
+    telea((width, height), &mut image, &marked, params.radius as usize);
+    if params.method == Method::Diffusion {
+        diffuse((width, height), &mut image, &marked, params.iterations);
+    }
+
+    for ((px, value), _) in buf.chunks_exact_mut(4).zip(&image).zip(&marked).filter(|(_, m)| **m) {
+        let scale = value[3] / T::MAX;
+        for c in 0..3 {
+            let v = if premultiplied { value[c] } else if scale > 0.0 { value[c] / scale } else { 0.0 };
+            px[c] = T::from_f32(v.max(0.0));
+        }
+        px[3] = T::from_f32(value[3].max(0.0));
+    }
+}
+
+/// Telea's fast-marching inpainting.
+///
+/// The arrival time `t` of the front, the distance from the region's boundary, is propagated
+/// inward by solving the eikonal equation; each pixel reached is filled with the average of the
+/// known pixels within `radius`, weighted by how well their direction matches the front's
+/// normal, their inverse squared distance, and how close their arrival times are.
+fn telea((width, height): (usize, usize), image: &mut [[f32; 4]], marked: &[bool], radius: usize) {
+    let mut state: Vec<State> = marked.iter().map(|&m| if m { State::Inside } else { State::Known }).collect();
+    let mut time = vec![0.0f32; image.len()];
+    let mut heap = BinaryHeap::new();
+    let neighbours = |i: usize| {
+        let (x, y) = (i % width, i / width);
+        [
+            (x > 0).then(|| i - 1),
+            (x + 1 < width).then(|| i + 1),
+            (y > 0).then(|| i - width),
+            (y + 1 < height).then(|| i + width),
+        ]
+        .into_iter()
+        .flatten()
+    };
+    for i in 0..image.len() {
+        if state[i] == State::Inside {
+            time[i] = f32::INFINITY;
+        } else if neighbours(i).any(|n| marked[n]) {
+            state[i] = State::Band;
+            heap.push(Reverse((0u32, i)));
+        }
+    }
+
+    // Times are non-negative, so their bit patterns order like the values.
+    while let Some(Reverse((_, i))) = heap.pop() {
+        if state[i] == State::Known {
+            continue;
+        }
+        state[i] = State::Known;
+        for n in neighbours(i).collect::<Vec<_>>() {
+            if state[n] != State::Inside {
+                continue;
+            }
+            time[n] = arrival(n, (width, height), &time, &state);
+            image[n] = fill(n, (width, height), image, &time, &state, radius);
+            state[n] = State::Band;
+            heap.push(Reverse((time[n].to_bits(), n)));
+        }
+    }
+}
+
+/// Solves `|grad t| = 1` at pixel `i` from the finished times of its neighbours.
+fn arrival(i: usize, (width, height): (usize, usize), time: &[f32], state: &[State]) -> f32 {
+    let (x, y) = (i % width, i / width);
+    let finished = |n: Option<usize>| n.filter(|&n| state[n] == State::Known).map_or(f32::INFINITY, |n| time[n]);
+    let a = finished((x > 0).then(|| i - 1)).min(finished((x + 1 < width).then(|| i + 1)));
+    let b = finished((y > 0).then(|| i - width)).min(finished((y + 1 < height).then(|| i + width)));
+    if (a - b).abs() >= 1.0 || !a.is_finite() || !b.is_finite() {
+        a.min(b) + 1.0
+    } else {
+        (a + b + (2.0 - (a - b) * (a - b)).sqrt()) / 2.0
+    }
+}
+
+/// The weighted average of the known pixels within `radius` of pixel `i`.
+fn fill(
+    i: usize,
+    (width, height): (usize, usize),
+    image: &[[f32; 4]],
+    time: &[f32],
+    state: &[State],
+    radius: usize,
+) -> [f32; 4] {
+    let (x, y) = (i % width, i / width);
+    let finite = |j: usize| state[j] != State::Inside && time[j].is_finite();
+    // The front's normal: the gradient of the arrival time, from whichever neighbours have one.
+    let slope = |before: Option<usize>, after: Option<usize>| {
+        match (before.filter(|&j| finite(j)), after.filter(|&j| finite(j))) {
+            (Some(b), Some(a)) => (time[a] - time[b]) / 2.0,
+            (Some(b), None) => time[i] - time[b],
+            (None, Some(a)) => time[a] - time[i],
+            (None, None) => 0.0,
+        }
+    };
+    let gx = slope((x > 0).then(|| i - 1), (x + 1 < width).then(|| i + 1));
+    let gy = slope((y > 0).then(|| i - width), (y + 1 < height).then(|| i + width));
+
+    let r = radius as i64;
+    let mut sum = [0.0f32; 4];
+    let mut total = 0.0f32;
+    for dy in -r..=r {
+        for dx in -r..=r {
+            let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+            if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 || dx * dx + dy * dy > r * r {
+                continue;
+            }
+            let j = ny as usize * width + nx as usize;
+            if state[j] != State::Known || j == i {
+                continue;
+            }
+            let (vx, vy) = (-dx as f32, -dy as f32);
+            let length2 = vx * vx + vy * vy;
+            let direction = ((vx * gx + vy * gy) / length2.sqrt()).abs().max(1e-6);
+            let distance = 1.0 / length2;
+            let level = 1.0 / (1.0 + (time[j] - time[i]).abs());
+            let weight = direction * distance * level;
+            for c in 0..4 {
+                sum[c] += weight * image[j][c];
+            }
+            total += weight;
+        }
+    }
+    if total > 0.0 { sum.map(|s| s / total) } else { image[i] }
+}
+
+/// Relaxes the marked pixels toward the average of their four neighbours (Gauss-Seidel sweeps
+/// of Laplace's equation), with the unmarked pixels held fixed.
+fn diffuse((width, height): (usize, usize), image: &mut [[f32; 4]], marked: &[bool], iterations: u32) {
+    let region: Vec<usize> = (0..image.len()).filter(|&i| marked[i]).collect();
+    for _ in 0..iterations {
+        for &i in &region {
+            let (x, y) = (i % width, i / width);
+            let around = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+            let mut sum = [0.0f32; 4];
+            let mut count = 0.0;
+            for j in around.into_iter().flatten() {
+                for c in 0..4 {
+                    sum[c] += image[j][c];
+                }
+                count += 1.0;
+            }
+            image[i] = sum.map(|s| s / count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 12x8 horizontal gray ramp with a transparent 3x4 hole in the middle.
+    fn ramp_with_hole() -> Vec<u8> {
+        (0..96)
+            .flat_map(|i| {
+                let (x, y) = (i % 12, i / 12);
+                let v = (x * 20) as u8;
+                if (5..8).contains(&x) && (2..6).contains(&y) { [0, 0, 0, 0] } else { [v, v, v, 255] }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_holes_are_filled_from_surroundings() {
+        for method in ["telea", "diffusion"] {
+            let mut img = [[30u8, 140, 90, 255]; 36].concat();
+            for i in [14, 15, 20, 21] {
+                img[i * 4..i * 4 + 4].copy_from_slice(&[0, 0, 0, 0]);
+            }
+            run(&mut img, 6, 6, &format!(r#"{{"method": "{method}"}}"#)).unwrap();
+            assert!(img.chunks(4).all(|p| p == [30, 140, 90, 255]), "{method}: {img:?}");
+
+            // The gap in the ramp is bridged in order, within the range of its edges.
+            let src = ramp_with_hole();
+            let mut img = src.clone();
+            run(&mut img, 12, 8, &format!(r#"{{"method": "{method}"}}"#)).unwrap();
+            for y in 2..6 {
+                let row: Vec<u8> = (4..9).map(|x| img[(y * 12 + x) * 4]).collect();
+                assert!(row.windows(2).all(|w| w[0] <= w[1]), "{method} row {y}: {row:?}");
+                assert!(row[1] >= 80 && row[3] <= 160, "{method} row {y}: {row:?}");
+                assert!((5..8).all(|x| img[(y * 12 + x) * 4 + 3] == 255));
+            }
+            let untouched = |i: usize| !((5..8).contains(&(i % 12)) && (2..6).contains(&(i / 12)));
+            assert!((0..96).filter(|&i| untouched(i)).all(|i| img[i * 4..i * 4 + 4] == src[i * 4..i * 4 + 4]));
+        }
+    }
+
+    #[test]
+    fn test_secondary_input_mask_and_padding() {
+        // A red logo on gray, marked by a white square on a half-size black mask.
+        let square = |i: usize, size: usize, within: std::ops::Range<usize>| {
+            within.contains(&(i % size)) && within.contains(&(i / size))
+        };
+        let mut img: Vec<u8> =
+            (0..64).flat_map(|i| if square(i, 8, 2..6) { [255, 0, 0, 255] } else { [90, 90, 90, 255] }).collect();
+        let mask: Vec<u8> = (0..16).flat_map(|i| if square(i, 4, 1..3) { [255; 4] } else { [0, 0, 0, 255] }).collect();
+        let ctx = CallContext { input2_data: mask.as_ptr(), input2_width: 4, input2_height: 4, ..CallContext::new(0) };
+        testing::run_with(process, &ctx, 8, 8, &mut img, r#"{"mask": "input2"}"#).unwrap();
+        assert!(img.chunks(4).all(|p| p == [90, 90, 90, 255]), "{img:?}");
+
+        // Without a secondary input, `input2` fails; padding grows a one-pixel hole to cover a
+        // ring of stray pixels around it.
+        assert!(run(&mut img, 8, 8, r#"{"mask": "input2"}"#).is_err());
+        let mut img = [90u8, 90, 90, 255].repeat(49);
+        for i in [16, 17, 18, 23, 25, 30, 31, 32] {
+            img[i * 4] = 250;
+        }
+        img[24 * 4 + 3] = 0;
+        run(&mut img, 7, 7, r#"{"padding": 1}"#).unwrap();
+        assert!(img.chunks(4).all(|p| p == [90, 90, 90, 255]), "{img:?}");
+    }
+}
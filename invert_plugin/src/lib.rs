@@ -0,0 +1,142 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Replaces every value `v` with `1 - v`.
+    #[default]
+    Invert,
+    /// Inverts only values above `threshold`, like a print briefly exposed to light.
+    Solarize,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// sRGB-encoded value above which `solarize` inverts, in units of full scale.
+    #[serde(default = "default_threshold")]
+    threshold: f32,
+    /// Applies the same operation to alpha as well.
+    #[serde(default)]
+    alpha: bool,
+}
+
+fn default_threshold() -> f32 {
+    0.5
+}
+
+impl Params {
+    fn apply(&self, v: f32) -> f32 {
+        match self.mode {
+            Mode::Invert => 1.0 - v,
+            Mode::Solarize if v > self.threshold => 1.0 - v,
+            Mode::Solarize => v,
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "invert_plugin"
+version = "0.1.0"
+description = "Inverts or solarizes the colors, and optionally alpha"
+
+[defaults]
+mode = "invert"
+threshold = 0.5
+alpha = false
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Inverts both formats; working per pixel, the operation is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => invert(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => invert(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Inverts the sRGB-encoded colors, so a negative looks the same in either working space.
+///
+/// Alpha, which is never encoded, is inverted directly when requested; premultiplied colors
+/// are divided by the old alpha and multiplied by the new one.
+fn invert<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    if !params.alpha {
+        plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+            rgb.map(|v| plugin_sdk::from_encoded::<T>(params.apply(plugin_sdk::to_encoded::<T>(v))))
+        });
+        return;
+    }
+
+    for px in buf.chunks_exact_mut(4) {
+        let alpha = px[3].to_f32() / T::MAX;
+        let new_alpha = params.apply(alpha);
+        let (from, to) = if premultiplied { (alpha, new_alpha) } else { (1.0, 1.0) };
+        for v in &mut px[..3] {
+            let straight = if from > 0.0 { v.to_f32() / T::MAX / from } else { 0.0 };
+            let encoded = params.apply(plugin_sdk::to_encoded::<T>(straight));
+            *v = T::from_f32(plugin_sdk::from_encoded::<T>(encoded) * to * T::MAX);
+        }
+        px[3] = T::from_f32(new_alpha * T::MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::{ALPHA_PREMULTIPLIED, testing};
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_invert_twice_is_identity() {
+        let src: Vec<u8> = (0..=255).flat_map(|v| [v, 255 - v, v / 3, v]).collect();
+        let mut buf = src.clone();
+        run(&mut buf, "{}").unwrap();
+        assert_eq!(&buf[..8], &[255, 0, 255, 0, 254, 1, 255, 1]);
+        run(&mut buf, r#"{"alpha": true}"#).unwrap();
+        run(&mut buf, r#"{"alpha": true}"#).unwrap();
+        run(&mut buf, "{}").unwrap();
+        assert_eq!(buf, src);
+    }
+
+    #[test]
+    fn test_solarize_threshold() {
+        let mut buf = [50u8, 128, 200, 200];
+        run(&mut buf, r#"{"mode": "solarize", "threshold": 0.5}"#).unwrap();
+        assert_eq!(buf, [50, 127, 55, 200]);
+
+        let mut alpha = [50u8, 128, 200, 200];
+        run(&mut alpha, r#"{"mode": "solarize", "threshold": 0.6, "alpha": true}"#).unwrap();
+        assert_eq!(alpha, [50, 128, 55, 55]);
+    }
+
+    #[test]
+    fn test_premultiplied_alpha_inversion() {
+        // Straight color (0.2, 0.4, 1.0) at alpha 0.25 becomes (0.8, 0.6, 0.0) at alpha 0.75.
+        let mut img = [0.05f32, 0.1, 0.25, 0.25];
+        let ctx = CallContext { alpha_mode: ALPHA_PREMULTIPLIED, ..CallContext::new(0) };
+        let ctx = CallContext { pixel_format: plugin_sdk::PIXEL_FORMAT_RGBA32F, ..ctx };
+        testing::run_with(process, &ctx, 1, 1, &mut img, r#"{"alpha": true}"#).unwrap();
+
+        let straight = [0.2f32, 0.4, 1.0].map(|v| {
+            let inverted = 1.0 - plugin_sdk::linear_to_srgb(v);
+            plugin_sdk::srgb_to_linear(inverted) * 0.75
+        });
+        for (got, want) in img.iter().zip(straight.iter().chain([&0.75])) {
+            assert!((got - want).abs() < 1e-6, "{img:?}");
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::f32::consts::PI;
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Standard deviation in pixels of the Gaussian smoothing the structure tensor.
+const TENSOR_SIGMA: f32 = 2.0;
+
+/// How far the structure tensor reaches: the Sobel kernel plus the smoothing Gaussian.
+const TENSOR_REACH: u32 = 1 + (3.0 * TENSOR_SIGMA) as u32;
+
+/// The shape of the regions whose means are blended.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// The original filter: the mean of whichever of the four square quadrants varies least.
+    /// Blocky, with hard transitions.
+    Classic,
+    /// Overlapping sectors of a disc, blended by how little each varies; round brush strokes.
+    #[default]
+    Generalized,
+    /// Sectors of an ellipse stretched along the local edge direction; strokes follow the
+    /// image's structure.
+    Anisotropic,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    method: Method,
+    /// Radius in pixels of the window; larger radii give larger strokes. The anisotropic
+    /// ellipse stretches up to twice as long.
+    #[serde(default = "default_radius")]
+    radius: u32,
+    /// Number of sectors of the generalized and anisotropic filters.
+    #[serde(default = "default_sectors")]
+    sectors: u32,
+    /// How strongly sectors with less variation are preferred; higher values give crisper edges.
+    #[serde(default = "default_sharpness")]
+    sharpness: f32,
+}
+
+fn default_radius() -> u32 {
+    6
+}
+
+fn default_sectors() -> u32 {
+    8
+}
+
+fn default_sharpness() -> f32 {
+    8.0
+}
+
+impl Params {
+    /// How far from a pixel the result depends on the input.
+    fn reach(&self) -> u32 {
+        match self.method {
+            Method::Classic | Method::Generalized => self.radius,
+            Method::Anisotropic => self.radius.saturating_mul(2).saturating_add(TENSOR_REACH),
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "kuwahara_plugin"
+version = "0.1.0"
+description = "Painterly Kuwahara filter: classic, generalized or anisotropic"
+
+[defaults]
+method = "generalized"
+radius = 6
+sectors = 8
+sharpness = 8.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Filters both formats on the host's thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if !(2..=16).contains(&params.sectors) || params.sharpness.is_nan() || !(0.0..=16.0).contains(&params.sharpness) {
+        return Err(PluginError::Invalid("sectors must be in 2..=16 and sharpness in 0..=16"));
+    }
+
+    let (w, h) = (width as usize, height as usize);
+    let run = move || match pixels {
+        Pixels::Rgba8(buf) => kuwahara(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => kuwahara(w, h, buf, params, premultiplied),
+    };
+
+    plugin_sdk::install(ctx, run)
+}
+
+/// `radius` pixels, or for the anisotropic filter twice that plus the reach of the structure tensor.
+fn halo(params: &Params) -> Option<u32> {
+    Some(params.reach())
+}
+
+/// Running weighted sums over one sector of a pixel's window.
+#[derive(Clone, Copy, Default)]
+struct Sector {
+    weight: f32,
+    /// Sum of the stored values, alpha included.
+    values: [f32; 4],
+    /// Sums of the sRGB-encoded straight colors and of their squares, for the variance.
+    colors: [f32; 3],
+    squares: [f32; 3],
+}
+
+impl Sector {
+    fn add(&mut self, weight: f32, values: &[f32], color: &[f32; 3]) {
+        self.weight += weight;
+        for (sum, v) in self.values.iter_mut().zip(values) {
+            *sum += weight * v;
+        }
+        for ((sum, square), v) in self.colors.iter_mut().zip(&mut self.squares).zip(color) {
+            *sum += weight * v;
+            *square += weight * v * v;
+        }
+    }
+
+    /// Variance of the sector's colors, summed over the channels.
+    fn variance(&self) -> f32 {
+        (0..3)
+            .map(|c| {
+                let mean = self.colors[c] / self.weight;
+                (self.squares[c] / self.weight - mean * mean).max(0.0)
+            })
+            .sum()
+    }
+}
+
+/// Replaces every pixel with the mean of the least varying regions of its window, flattening
+/// texture into strokes while edges, which only cross the more varying regions, stay sharp.
+///
+/// Variation is measured on straight sRGB-encoded colors, so a setting behaves the same in either
+/// working space; the stored values are averaged as they are, which keeps premultiplied data
+/// premultiplied.
+fn kuwahara<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 || params.radius == 0 {
+        return;
+    }
+    let src: Vec<f32> = buf.par_iter().map(|v| v.to_f32()).collect();
+    let guide: Vec<[f32; 3]> = src.par_chunks_exact(4).map(|px| encoded::<T>(px, premultiplied)).collect();
+    let orientation = (params.method == Method::Anisotropic).then(|| structure(&guide, width, height));
+    let sectors = match params.method {
+        Method::Classic => 4,
+        Method::Generalized | Method::Anisotropic => params.sectors as usize,
+    };
+    let r = params.radius as f32;
+
+    buf.par_chunks_exact_mut(width * 4).enumerate().for_each(|(y, row)| {
+        let mut sums = vec![Sector::default(); sectors];
+        let mut weights = vec![0.0; sectors];
+        for (x, out) in row.chunks_exact_mut(4).enumerate() {
+            sums.fill(Sector::default());
+            // Offsets are scaled into the unit disc: `(u, v) = (p / a, q / b)` after rotating
+            // `(dx, dy)` by `-angle` into `(p, q)`.
+            let (angle, a, b) = match &orientation {
+                Some(orientation) => {
+                    let (angle, anisotropy) = orientation[y * width + x];
+                    (angle, r * (1.0 + anisotropy), r / (1.0 + anisotropy))
+                }
+                None => (0.0, r, r),
+            };
+            let (sin, cos) = angle.sin_cos();
+            let reach = a.max(b).ceil() as i64;
+            // Offsets beyond the image are mirrored back into it; repeating the edge pixels
+            // instead would make sectors pointing outwards look flat.
+            for dy in -reach..=reach {
+                let sy = mirror(y as i64 + dy, height);
+                for dx in -reach..=reach {
+                    let sx = mirror(x as i64 + dx, width);
+                    let (dx, dy) = (dx as f32, dy as f32);
+                    let u = (cos * dx + sin * dy) / a;
+                    let v = (cos * dy - sin * dx) / b;
+                    let inside = match params.method {
+                        Method::Classic => classic_weights(u, v, &mut weights),
+                        Method::Generalized | Method::Anisotropic => sector_weights(u, v, &mut weights),
+                    };
+                    if !inside {
+                        continue;
+                    }
+                    let i = sy * width + sx;
+                    for (sum, &weight) in sums.iter_mut().zip(&weights) {
+                        if weight > 0.0 {
+                            sum.add(weight, &src[i * 4..i * 4 + 4], &guide[i]);
+                        }
+                    }
+                }
+            }
+
+            // The center pixel is in every sector, so every weight is positive.
+            let mean = |s: &Sector| s.values.map(|v| v / s.weight);
+            let result = match params.method {
+                Method::Classic => {
+                    let calmest = sums.iter().min_by(|p, q| p.variance().total_cmp(&q.variance()));
+                    calmest.map_or([0.0; 4], mean)
+                }
+                Method::Generalized | Method::Anisotropic => {
+                    // Kyprianidis et al.'s weighting, in f64: a large variance raised to the
+                    // sharpness overflows f32. Variations within a few 8-bit steps barely count,
+                    // so texture is averaged across sectors while edges decide.
+                    let exponent = params.sharpness as f64 / 2.0;
+                    let blend: Vec<f64> =
+                        sums.iter().map(|s| 1.0 / (1.0 + (255.0 * s.variance() as f64).powf(exponent))).collect();
+                    let total: f64 = blend.iter().sum();
+                    let mut acc = [0.0; 4];
+                    for (s, &k) in sums.iter().zip(&blend) {
+                        for (a, m) in acc.iter_mut().zip(mean(s)) {
+                            *a += (k / total) as f32 * m;
+                        }
+                    }
+                    acc
+                }
+            };
+            for (o, v) in out.iter_mut().zip(result) {
+                *o = T::from_f32(v);
+            }
+        }
+    });
+}
+
+/// Reflects a coordinate into `0..len` at the edges, without repeating the edge itself.
+fn mirror(i: i64, len: usize) -> usize {
+    let last = len as i64 - 1;
+    let i = if i < 0 { -i } else if i > last { 2 * last - i } else { i };
+    i.clamp(0, last) as usize
+}
+
+/// Weights of the four overlapping square quadrants of the classic filter at `(u, v)`; the axes
+/// belong to both quadrants beside them. Returns whether the offset is inside the window.
+fn classic_weights(u: f32, v: f32, weights: &mut [f32]) -> bool {
+    if u.abs() > 1.0 || v.abs() > 1.0 {
+        return false;
+    }
+    weights[0] = f32::from(u >= 0.0 && v >= 0.0);
+    weights[1] = f32::from(u <= 0.0 && v >= 0.0);
+    weights[2] = f32::from(u <= 0.0 && v <= 0.0);
+    weights[3] = f32::from(u >= 0.0 && v <= 0.0);
+    true
+}
+
+/// Weights of the sectors of the unit disc at `(u, v)`: a Gaussian falloff from the center
+/// times a smooth bump around each sector's direction, twice the sector's width so neighbors
+/// overlap. The bumps of all sectors add up to 1, and the center, which has no direction, is
+/// shared equally. Returns whether the offset is inside the disc.
+fn sector_weights(u: f32, v: f32, weights: &mut [f32]) -> bool {
+    let distance2 = u * u + v * v;
+    if distance2 > 1.0 {
+        return false;
+    }
+    let n = weights.len() as f32;
+    if distance2 == 0.0 {
+        weights.fill(1.0 / n);
+        return true;
+    }
+    let radial = (-2.0 * distance2).exp();
+    let angle = v.atan2(u);
+    for (k, weight) in weights.iter_mut().enumerate() {
+        let offset = (angle - 2.0 * PI * k as f32 / n + PI).rem_euclid(2.0 * PI) - PI;
+        let bump = if offset.abs() < 2.0 * PI / n { (n / 4.0 * offset).cos().powi(2) } else { 0.0 };
+        *weight = radial * bump;
+    }
+    true
+}
+
+/// Direction along the edges and anisotropy in `[0, 1]` of every pixel, from the smoothed
+/// structure tensor of the luma; flat areas have no anisotropy.
+fn structure(guide: &[[f32; 3]], width: usize, height: usize) -> Vec<(f32, f32)> {
+    let luma: Vec<f32> = guide.iter().map(|c| LUMA[0] * c[0] + LUMA[1] * c[1] + LUMA[2] * c[2]).collect();
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, width as i64 - 1) as usize, y.clamp(0, height as i64 - 1) as usize);
+        luma[y * width + x]
+    };
+    let mut tensor = [vec![0.0; luma.len()], vec![0.0; luma.len()], vec![0.0; luma.len()]];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let gx = (at(x + 1, y - 1) - at(x - 1, y - 1))
+                + 2.0 * (at(x + 1, y) - at(x - 1, y))
+                + (at(x + 1, y + 1) - at(x - 1, y + 1));
+            let gy = (at(x - 1, y + 1) - at(x - 1, y - 1))
+                + 2.0 * (at(x, y + 1) - at(x, y - 1))
+                + (at(x + 1, y + 1) - at(x + 1, y - 1));
+            let i = y as usize * width + x as usize;
+            tensor[0][i] = gx * gx;
+            tensor[1][i] = gx * gy;
+            tensor[2][i] = gy * gy;
+        }
+    }
+    let [e, f, g] = tensor.map(|t| gaussian(&t, width, height, TENSOR_SIGMA));
+
+    (0..luma.len())
+        .map(|i| {
+            let root = ((e[i] - g[i]).powi(2) + 4.0 * f[i] * f[i]).sqrt();
+            let (major, minor) = ((e[i] + g[i] + root) / 2.0, (e[i] + g[i] - root) / 2.0);
+            if major + minor <= f32::EPSILON {
+                return (0.0, 0.0);
+            }
+            // The eigenvector of the larger eigenvalue points across the edge; this one along it.
+            let angle = (-f[i]).atan2(major - e[i]);
+            let angle = if f[i] == 0.0 && major == e[i] { PI / 2.0 } else { angle };
+            (angle, (major - minor) / (major + minor))
+        })
+        .collect()
+}
+
+/// Separable Gaussian blur of a single-channel image, clamping at the edges.
+fn gaussian(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let r = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let norm: f32 = kernel.iter().sum();
+    let pass = |src: &[f32], step: (i64, i64)| -> Vec<f32> {
+        let mut out = vec![0.0; src.len()];
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let sum: f32 = kernel
+                    .iter()
+                    .zip(-r..)
+                    .map(|(k, d)| {
+                        let sx = (x + d * step.0).clamp(0, width as i64 - 1) as usize;
+                        let sy = (y + d * step.1).clamp(0, height as i64 - 1) as usize;
+                        k * src[sy * width + sx]
+                    })
+                    .sum();
+                out[y as usize * width + x as usize] = sum / norm;
+            }
+        }
+        out
+    };
+    pass(&pass(src, (1, 0)), (0, 1))
+}
+
+/// A pixel's straight-alpha color, sRGB-encoded and normalized to `[0, 1]`.
+fn encoded<T: Sample>(px: &[f32], premultiplied: bool) -> [f32; 3] {
+    let alpha = px[3] / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c] / scale))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 12x12 image, dark on the left half and bright on the right, with a little noise.
+    fn noisy_edge() -> Vec<u8> {
+        (0..144u32)
+            .flat_map(|i| {
+                let base = if i % 12 < 6 { 60 } else { 200 };
+                let hash = (i.wrapping_mul(2_654_435_761) ^ (i << 13)).wrapping_mul(2_246_822_519);
+                let v = base + (hash >> 16) % 17 - 8;
+                [v as u8, v as u8, v as u8, 255]
+            })
+            .collect()
+    }
+
+    fn spread(img: &[u8], columns: std::ops::Range<usize>) -> u8 {
+        let values: Vec<u8> =
+            img.chunks(4).enumerate().filter(|(i, _)| columns.contains(&(i % 12))).map(|(_, p)| p[0]).collect();
+        values.iter().max().unwrap() - values.iter().min().unwrap()
+    }
+
+    #[test]
+    fn test_flattens_texture_and_keeps_edge() {
+        for method in ["classic", "generalized", "anisotropic"] {
+            let mut img = noisy_edge();
+            let params = format!(r#"{{"method": "{method}", "radius": 3}}"#);
+            run(&mut img, 12, 12, &params).unwrap();
+            assert!(spread(&img, 0..6) < spread(&noisy_edge(), 0..6) / 2, "{method}");
+            // The pixels next to the edge stay on their side of it.
+            let row = &img[5 * 48..6 * 48];
+            assert!(row[5 * 4] < 80 && row[6 * 4] > 180, "{method}: {row:?}");
+        }
+    }
+
+    #[test]
+    fn test_flat_image_unchanged() {
+        let src = [90u8, 60, 30, 200].repeat(25);
+        for method in ["classic", "generalized", "anisotropic"] {
+            let mut img = src.clone();
+            run(&mut img, 5, 5, &format!(r#"{{"method": "{method}"}}"#)).unwrap();
+            assert_eq!(img, src, "{method}");
+        }
+        assert!(run(&mut src.clone(), 5, 5, r#"{"sectors": 1}"#).is_err());
+
+        let params = plugin_sdk::params_from_str(r#"{"method": "anisotropic", "radius": 4}"#).unwrap();
+        assert_eq!(halo(&params), Some(8 + TENSOR_REACH));
+    }
+}
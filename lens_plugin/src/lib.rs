@@ -0,0 +1,272 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// How far red and blue are pushed apart at the corners, in pixels; red moves outwards and
+    /// blue inwards. 0 turns the aberration off.
+    #[serde(default = "default_aberration")]
+    aberration: f32,
+    /// Center of the aberration as fractions of the width and height.
+    #[serde(default = "default_center")]
+    center: [f32; 2],
+    /// Strength of the bloom layer added on top; 0 turns bloom off.
+    #[serde(default = "default_bloom")]
+    bloom: f32,
+    /// Linear luminance above which light blooms.
+    #[serde(default = "default_threshold")]
+    threshold: f32,
+    /// Standard deviation of the bloom blur in pixels.
+    #[serde(default = "default_radius")]
+    radius: f32,
+}
+
+fn default_aberration() -> f32 {
+    2.0
+}
+
+fn default_center() -> [f32; 2] {
+    [0.5, 0.5]
+}
+
+fn default_bloom() -> f32 {
+    0.5
+}
+
+fn default_threshold() -> f32 {
+    0.8
+}
+
+fn default_radius() -> f32 {
+    8.0
+}
+
+impl Params {
+    /// Pixels the bloom blur reaches, or `None` when the radial aberration needs the whole image.
+    fn reach(&self) -> Option<u32> {
+        if self.aberration != 0.0 {
+            None
+        } else if self.bloom != 0.0 && self.radius > 0.0 {
+            Some((3.0 * self.radius).ceil() as u32)
+        } else {
+            Some(0)
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "lens_plugin"
+version = "0.1.0"
+description = "Adds radial chromatic aberration and a bloom around bright light"
+
+[defaults]
+aberration = 2.0
+center = [0.5, 0.5]
+bloom = 0.5
+threshold = 0.8
+radius = 8.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Applies the effect to both formats on the host's thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    let run = move || match pixels {
+        Pixels::Rgba8(buf) => lens(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => lens(w, h, buf, params, premultiplied),
+    };
+
+    plugin_sdk::install(ctx, run)
+}
+
+/// Bloom alone is local, reaching three times its radius; the aberration depends on the distance
+/// from the image center and is not.
+fn halo(params: &Params) -> Option<u32> {
+    params.reach()
+}
+
+/// Shifts red and blue radially, then adds the blurred light above `threshold`.
+///
+/// Both work on premultiplied linear light, like a real lens; alpha is kept. RGBA8 output is
+/// clipped at white, while RGBA32F keeps bloom above 1.
+fn lens<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let mut light: Vec<f32> = buf
+        .par_chunks_exact(4)
+        .flat_map_iter(|px| {
+            let a = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied && a > 0.0 { T::MAX * a } else { T::MAX };
+            let rgb: [f32; 3] = std::array::from_fn(|c| plugin_sdk::to_linear::<T>(px[c].to_f32() / scale) * a);
+            [rgb[0], rgb[1], rgb[2], a]
+        })
+        .collect();
+
+    if params.aberration != 0.0 {
+        light = aberrate(&light, width, height, params);
+    }
+    if params.bloom != 0.0 && params.radius > 0.0 {
+        let bright: Vec<f32> = light
+            .par_chunks_exact(4)
+            .flat_map_iter(|px| {
+                let lum = if px[3] > 0.0 { (0.2126 * px[0] + 0.7152 * px[1] + 0.0722 * px[2]) / px[3] } else { 0.0 };
+                let keep = if lum > params.threshold { (lum - params.threshold) / lum } else { 0.0 };
+                [px[0] * keep, px[1] * keep, px[2] * keep, 0.0]
+            })
+            .collect();
+        let glow = gaussian(&bright, width, height, params.radius);
+        light.par_iter_mut().zip(&glow).for_each(|(v, g)| *v += params.bloom * g);
+    }
+
+    buf.par_chunks_exact_mut(4).zip(light.par_chunks_exact(4)).for_each(|(px, v)| {
+        let a = v[3];
+        if a <= 0.0 {
+            return;
+        }
+        let scale = if premultiplied { T::MAX * a } else { T::MAX };
+        for c in 0..3 {
+            let straight = (v[c] / a).max(0.0);
+            let straight = if T::LINEAR { straight } else { straight.min(1.0) };
+            px[c] = T::from_f32(plugin_sdk::from_linear::<T>(straight) * scale);
+        }
+    });
+}
+
+/// Resamples red from closer to the center and blue from further out, which spreads red
+/// outwards and blue inwards by `aberration` pixels at the farthest corner and proportionally
+/// less closer in. Colors are capped at the pixel's own alpha.
+fn aberrate(src: &[f32], width: usize, height: usize, params: &Params) -> Vec<f32> {
+    let (cx, cy) = (params.center[0] * width as f32, params.center[1] * height as f32);
+    let corner = cx.max(width as f32 - cx).hypot(cy.max(height as f32 - cy)).max(1.0);
+    let k = params.aberration / corner;
+    let sample = |x: f32, y: f32, c: usize| {
+        let (x, y) = ((x - 0.5).clamp(0.0, (width - 1) as f32), (y - 0.5).clamp(0.0, (height - 1) as f32));
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: usize, y: usize| src[(y * width + x) * 4 + c];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+        top + (bottom - top) * fy
+    };
+
+    let mut out = src.to_vec();
+    out.par_chunks_exact_mut(width * 4).enumerate().for_each(|(y, row)| {
+        for (x, px) in row.chunks_exact_mut(4).enumerate() {
+            let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            px[0] = sample(cx + dx * (1.0 - k), cy + dy * (1.0 - k), 0).min(px[3]);
+            px[2] = sample(cx + dx * (1.0 + k), cy + dy * (1.0 + k), 2).min(px[3]);
+        }
+    });
+    out
+}
+
+/// Separable Gaussian blur with standard deviation `sigma`, clamping at the edges.
+fn gaussian(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let r = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let norm: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|k| k / norm).collect();
+    let row_len = width * 4;
+
+    let mut rows = vec![0.0f32; src.len()];
+    rows.par_chunks_exact_mut(row_len).zip(src.par_chunks_exact(row_len)).for_each(|(dst, src)| {
+        for x in 0..width {
+            for (k, w) in kernel.iter().enumerate() {
+                let sx = (x as i64 + k as i64 - r).clamp(0, width as i64 - 1) as usize;
+                for c in 0..4 {
+                    dst[x * 4 + c] += src[sx * 4 + c] * w;
+                }
+            }
+        }
+    });
+    let mut out = vec![0.0f32; src.len()];
+    out.par_chunks_exact_mut(row_len).enumerate().for_each(|(y, dst)| {
+        for (k, w) in kernel.iter().enumerate() {
+            let sy = (y as i64 + k as i64 - r).clamp(0, height as i64 - 1) as usize;
+            for (d, v) in dst.iter_mut().zip(&rows[sy * row_len..(sy + 1) * row_len]) {
+                *d += v * w;
+            }
+        }
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 21x21 dark image with a white 3x3 square at (x, y).
+    fn square(x: usize, y: usize) -> Vec<u8> {
+        let mut img = [20u8, 20, 20, 255].repeat(21 * 21);
+        for sy in y..y + 3 {
+            for sx in x..x + 3 {
+                img[(sy * 21 + sx) * 4..][..3].copy_from_slice(&[255; 3]);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn test_off_is_identity() {
+        let src: Vec<u8> = (0..64u8).flat_map(|i| [i * 4, 255 - i * 4, i, 200]).collect();
+        let mut img = src.clone();
+        run(&mut img, 8, 8, r#"{"aberration": 0.0, "bloom": 0.0}"#).unwrap();
+        assert_eq!(img, src);
+
+        let params = plugin_sdk::params_from_str(r#"{"aberration": 0.0, "radius": 4.0}"#).unwrap();
+        assert_eq!(halo(&params), Some(12));
+        let params = plugin_sdk::params_from_str("{}").unwrap();
+        assert_eq!(halo(&params), None);
+    }
+
+    #[test]
+    fn test_aberration_splits_channels_radially() {
+        // A white square near the right edge gets a red fringe outside and a blue one inside,
+        // while the center of the image does not move.
+        let mut img = square(16, 9);
+        run(&mut img, 21, 21, r#"{"aberration": 3.0, "bloom": 0.0}"#).unwrap();
+        let px = |x: usize, y: usize| &img[(y * 21 + x) * 4..][..4];
+        assert!(px(15, 10)[2] > 128 && px(15, 10)[0] < 128, "{:?}", px(15, 10));
+        assert!(px(19, 10)[0] > 128 && px(19, 10)[2] < 128, "{:?}", px(19, 10));
+        assert_eq!(px(17, 10)[1], 255);
+
+        let mut img = square(9, 9);
+        let src = img.clone();
+        run(&mut img, 21, 21, r#"{"aberration": 3.0, "bloom": 0.0}"#).unwrap();
+        assert_eq!(img[(10 * 21 + 10) * 4..][..4], src[(10 * 21 + 10) * 4..][..4]);
+    }
+
+    #[test]
+    fn test_bloom_spreads_bright_light() {
+        let src = square(9, 9);
+        let mut img = src.clone();
+        run(&mut img, 21, 21, r#"{"aberration": 0.0, "bloom": 1.0, "radius": 2.0}"#).unwrap();
+        let at = |img: &[u8], x: usize| img[(10 * 21 + x) * 4];
+        assert!(at(&img, 7) > at(&src, 7) + 10);
+        assert!(at(&img, 5) > at(&src, 5));
+        assert_eq!(at(&img, 0), at(&src, 0));
+
+        // Dark images below the threshold do not bloom.
+        let gray = [100u8, 100, 100, 255].repeat(16);
+        let mut img = gray.clone();
+        run(&mut img, 4, 4, r#"{"aberration": 0.0}"#).unwrap();
+        assert_eq!(img, gray);
+    }
+}
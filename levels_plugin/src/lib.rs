@@ -0,0 +1,182 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// One Levels mapping; all points are sRGB-encoded values in units of full scale.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+struct Levels {
+    /// Input value mapped to `out_black`; everything below is clipped.
+    in_black: f32,
+    /// Input value mapped to `out_white`; everything above is clipped.
+    in_white: f32,
+    /// Midtone exponent; above 1 brightens the midtones, below 1 darkens them.
+    gamma: f32,
+    /// Output value for `in_black`.
+    out_black: f32,
+    /// Output value for `in_white`.
+    out_white: f32,
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self { in_black: 0.0, in_white: 1.0, gamma: 1.0, out_black: 0.0, out_white: 1.0 }
+    }
+}
+
+impl Levels {
+    fn apply(&self, v: f32) -> f32 {
+        let range = self.in_white - self.in_black;
+        let t = if range.abs() > f32::EPSILON {
+            (v - self.in_black) / range
+        } else if v >= self.in_white {
+            1.0
+        } else {
+            0.0
+        };
+        let t = t.clamp(0.0, 1.0).powf(1.0 / self.gamma.max(1e-3));
+        self.out_black + t * (self.out_white - self.out_black)
+    }
+}
+
+/// Per-channel override; fields left out fall back to the global mapping.
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(default)]
+struct ChannelLevels {
+    in_black: Option<f32>,
+    in_white: Option<f32>,
+    gamma: Option<f32>,
+    out_black: Option<f32>,
+    out_white: Option<f32>,
+}
+
+impl ChannelLevels {
+    fn over(&self, global: Levels) -> Levels {
+        Levels {
+            in_black: self.in_black.unwrap_or(global.in_black),
+            in_white: self.in_white.unwrap_or(global.in_white),
+            gamma: self.gamma.unwrap_or(global.gamma),
+            out_black: self.out_black.unwrap_or(global.out_black),
+            out_white: self.out_white.unwrap_or(global.out_white),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Params {
+    /// Mapping applied to all three color channels.
+    #[serde(flatten)]
+    global: Levels,
+    #[serde(default)]
+    red: ChannelLevels,
+    #[serde(default)]
+    green: ChannelLevels,
+    #[serde(default)]
+    blue: ChannelLevels,
+}
+
+impl Params {
+    fn channels(&self) -> [Levels; 3] {
+        [self.red, self.green, self.blue].map(|c| c.over(self.global))
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "levels_plugin"
+version = "0.1.0"
+description = "Remaps input black and white points, gamma and output range, globally or per channel"
+
+[defaults]
+in_black = 0.0
+in_white = 1.0
+gamma = 1.0
+out_black = 0.0
+out_white = 1.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Applies the mapping to both formats; working per pixel, it is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => levels(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => levels(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Applies each channel's mapping to the sRGB-encoded values, as the Levels dialog of an
+/// image editor would, whatever the working space; alpha is kept.
+fn levels<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let channels = params.channels();
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let mut out = rgb;
+        for (v, levels) in out.iter_mut().zip(&channels) {
+            *v = plugin_sdk::from_encoded::<T>(levels.apply(plugin_sdk::to_encoded::<T>(*v)));
+        }
+        out
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_defaults_are_identity() {
+        let src: Vec<u8> = (0..=255).flat_map(|v| [v, 255 - v, v / 3, v]).collect();
+        let mut buf = src.clone();
+        run(&mut buf, "{}").unwrap();
+        assert_eq!(buf, src);
+    }
+
+    #[test]
+    fn test_global_points_and_gamma() {
+        // Input 0.2..0.7 is stretched to the full range; values outside it clip.
+        let mut stretch = [51u8, 102, 230, 200];
+        run(&mut stretch, r#"{"in_black": 0.2, "in_white": 0.7}"#).unwrap();
+        assert_eq!(stretch, [0, 102, 255, 200]);
+
+        // Gamma 2 takes the square root of the normalized value.
+        let mut gamma = [64u8, 0, 255, 255];
+        run(&mut gamma, r#"{"gamma": 2.0}"#).unwrap();
+        assert_eq!(gamma, [128, 0, 255, 255]);
+
+        let mut output = [0u8, 255, 128, 255];
+        run(&mut output, r#"{"out_black": 0.2, "out_white": 0.8}"#).unwrap();
+        assert_eq!(output, [51, 204, 128, 255]);
+    }
+
+    #[test]
+    fn test_per_channel_overrides() {
+        let params = r#"{"out_white": 0.5, "red": {"out_white": 1.0}, "blue": {"in_white": 0.5}}"#;
+        let mut buf = [255u8, 255, 64, 255];
+        run(&mut buf, params).unwrap();
+        // Red overrides the output range, green uses the global one, blue keeps the global
+        // output range but has its own white point.
+        assert_eq!(buf, [255, 128, 64, 255]);
+
+        let mut bad = [0u8; 4];
+        assert!(run(&mut bad, r#"{"red": {"gamma": "high"}}"#).is_err());
+    }
+
+    #[test]
+    fn test_rgba32f_works_on_encoded_values() {
+        // Linear 0.214 is sRGB 0.5; stretching 0..0.5 to the full range makes it white.
+        let mut img = [plugin_sdk::srgb_to_linear(0.5), 0.0, 1.0, 0.5];
+        testing::run(process, 1, 1, &mut img, r#"{"in_white": 0.5}"#).unwrap();
+        assert!((img[0] - 1.0).abs() < 1e-5);
+        assert_eq!(&img[1..], &[0.0, 1.0, 0.5]);
+    }
+}
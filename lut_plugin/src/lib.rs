@@ -0,0 +1,317 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// How colors between the lattice points of a 3D LUT are interpolated.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Interpolation {
+    /// Blends the eight corners of the enclosing cube.
+    Trilinear,
+    /// Blends the four corners of the enclosing tetrahedron; keeps neutral grays on the gray axis
+    /// and is what grading applications use.
+    #[default]
+    Tetrahedral,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Path of the `.cube` file.
+    path: String,
+    #[serde(default)]
+    interpolation: Interpolation,
+    /// Mix between the input (0) and the LUT's output (1).
+    #[serde(default = "default_strength")]
+    strength: f32,
+    /// Feeds the LUT linear light instead of sRGB-encoded values, for LUTs built for linear input.
+    #[serde(default)]
+    linear: bool,
+}
+
+fn default_strength() -> f32 {
+    1.0
+}
+
+/// Largest accepted `LUT_3D_SIZE`; 65 is the common maximum.
+const MAX_3D_SIZE: usize = 256;
+
+/// Largest accepted `LUT_1D_SIZE`.
+const MAX_1D_SIZE: usize = 65536;
+
+const MANIFEST: &CStr = cr#"name = "lut_plugin"
+version = "0.1.0"
+description = "Applies a 1D or 3D LUT from an Adobe/Resolve .cube file"
+
+[defaults]
+interpolation = "tetrahedral"
+strength = 1.0
+linear = false
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+    required: r#"path = "look.cube""#,
+}
+
+/// Applies the LUT to both formats; working per pixel, it is local with no halo.
+///
+/// Fails if the `.cube` file cannot be read or parsed.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    if params.strength.is_nan() {
+        return Err(PluginError::Invalid("strength must be a number"));
+    }
+    let Some(lut) = std::fs::read_to_string(&params.path).ok().and_then(|text| Cube::parse(&text)) else {
+        return Err(PluginError::Invalid("cannot read or parse the .cube file"));
+    };
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => apply(buf, &lut, params, premultiplied),
+        Pixels::Rgba32F(buf) => apply(buf, &lut, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// A table of output colors with the input range its first and last entries stand for.
+#[derive(Debug)]
+struct Table {
+    size: usize,
+    min: [f32; 3],
+    max: [f32; 3],
+    /// `size` (1D) or `size³` (3D) colors; in 3D, red changes fastest, then green, then blue.
+    data: Vec<[f32; 3]>,
+}
+
+impl Table {
+    /// Position of `v` on channel `c`'s axis, in lattice steps, clamped to the table.
+    fn coordinate(&self, v: f32, c: usize) -> f32 {
+        let t = (v - self.min[c]) / (self.max[c] - self.min[c]);
+        t.clamp(0.0, 1.0) * (self.size - 1) as f32
+    }
+
+    /// Splits a lattice coordinate into the lower lattice index and the fraction towards the next.
+    fn cell(&self, x: f32) -> (usize, f32) {
+        let i = (x as usize).min(self.size - 2);
+        (i, x - i as f32)
+    }
+
+    /// Interpolates each channel through its column of a 1D table.
+    fn lookup_1d(&self, rgb: [f32; 3]) -> [f32; 3] {
+        std::array::from_fn(|c| {
+            let (i, f) = self.cell(self.coordinate(rgb[c], c));
+            self.data[i][c] + f * (self.data[i + 1][c] - self.data[i][c])
+        })
+    }
+
+    fn lookup_3d(&self, rgb: [f32; 3], interpolation: Interpolation) -> [f32; 3] {
+        let n = self.size;
+        let [(r, fr), (g, fg), (b, fb)] = std::array::from_fn(|c| self.cell(self.coordinate(rgb[c], c)));
+        let at = |dr: usize, dg: usize, db: usize| self.data[(r + dr) + (g + dg) * n + (b + db) * n * n];
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| -> [f32; 3] { std::array::from_fn(|c| a[c] + t * (b[c] - a[c])) };
+
+        match interpolation {
+            Interpolation::Trilinear => {
+                let face = |db| {
+                    let near = lerp(at(0, 0, db), at(1, 0, db), fr);
+                    let far = lerp(at(0, 1, db), at(1, 1, db), fr);
+                    lerp(near, far, fg)
+                };
+                lerp(face(0), face(1), fb)
+            }
+            Interpolation::Tetrahedral => {
+                // Walk from the lower corner to the upper one along the edges of the tetrahedron
+                // containing the point, largest fraction first.
+                let (c000, c111) = (at(0, 0, 0), at(1, 1, 1));
+                let (steps, weights) = if fr > fg {
+                    if fg > fb {
+                        ([at(1, 0, 0), at(1, 1, 0)], [fr, fg, fb])
+                    } else if fr > fb {
+                        ([at(1, 0, 0), at(1, 0, 1)], [fr, fb, fg])
+                    } else {
+                        ([at(0, 0, 1), at(1, 0, 1)], [fb, fr, fg])
+                    }
+                } else if fb > fg {
+                    ([at(0, 0, 1), at(0, 1, 1)], [fb, fg, fr])
+                } else if fb > fr {
+                    ([at(0, 1, 0), at(0, 1, 1)], [fg, fb, fr])
+                } else {
+                    ([at(0, 1, 0), at(1, 1, 0)], [fg, fr, fb])
+                };
+                let corners = [c000, steps[0], steps[1], c111];
+                std::array::from_fn(|c| {
+                    corners[0][c]
+                        + weights[0] * (corners[1][c] - corners[0][c])
+                        + weights[1] * (corners[2][c] - corners[1][c])
+                        + weights[2] * (corners[3][c] - corners[2][c])
+                })
+            }
+        }
+    }
+}
+
+/// A parsed `.cube` file: an optional 1D shaper table followed by an optional 3D table, at least
+/// one of them present.
+#[derive(Debug)]
+struct Cube {
+    shaper: Option<Table>,
+    lattice: Option<Table>,
+}
+
+impl Cube {
+    /// Parses the Adobe `.cube` format, including Resolve's `LUT_1D_INPUT_RANGE` and
+    /// `LUT_3D_INPUT_RANGE`; returns `None` on malformed files.
+    fn parse(text: &str) -> Option<Self> {
+        let (mut size_1d, mut size_3d) = (0, 0);
+        let (mut domain_min, mut domain_max) = ([0.0f32; 3], [1.0f32; 3]);
+        let (mut range_1d, mut range_3d) = (None, None);
+        let mut values = Vec::new();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyword = words.next()?;
+            let numbers = || -> Option<Vec<f32>> {
+                let numbers: Vec<f32> = line.split_whitespace().skip(1).map(|w| w.parse().ok()).collect::<Option<_>>()?;
+                numbers.iter().all(|v| v.is_finite()).then_some(numbers)
+            };
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => size_1d = words.next()?.parse().ok()?,
+                "LUT_3D_SIZE" => size_3d = words.next()?.parse().ok()?,
+                "DOMAIN_MIN" => domain_min = numbers()?.try_into().ok()?,
+                "DOMAIN_MAX" => domain_max = numbers()?.try_into().ok()?,
+                "LUT_1D_INPUT_RANGE" => range_1d = Some(<[f32; 2]>::try_from(numbers()?).ok()?),
+                "LUT_3D_INPUT_RANGE" => range_3d = Some(<[f32; 2]>::try_from(numbers()?).ok()?),
+                _ if keyword.starts_with(|c: char| c == '-' || c == '.' || c.is_ascii_digit()) => {
+                    let rgb: [f32; 3] = line
+                        .split_whitespace()
+                        .map(|w| w.parse().ok().filter(|v: &f32| v.is_finite()))
+                        .collect::<Option<Vec<_>>>()?
+                        .try_into()
+                        .ok()?;
+                    values.push(rgb);
+                }
+                // Other keywords are vendor extensions that do not change the mapping.
+                _ => {}
+            }
+        }
+
+        let valid_1d = size_1d == 0 || (2..=MAX_1D_SIZE).contains(&size_1d);
+        let valid_3d = size_3d == 0 || (2..=MAX_3D_SIZE).contains(&size_3d);
+        let count_3d = size_3d * size_3d * size_3d;
+        if !valid_1d || !valid_3d || size_1d + size_3d == 0 || values.len() != size_1d + count_3d {
+            return None;
+        }
+        let table = |size, range: Option<[f32; 2]>, data: &[[f32; 3]]| {
+            let (min, max) = range.map_or((domain_min, domain_max), |[lo, hi]| ([lo; 3], [hi; 3]));
+            (0..3).all(|c| max[c] > min[c]).then(|| Table { size, min, max, data: data.to_vec() })
+        };
+        let shaper = match size_1d {
+            0 => None,
+            _ => Some(table(size_1d, range_1d, &values[..size_1d])?),
+        };
+        let lattice = match size_3d {
+            0 => None,
+            _ => Some(table(size_3d, range_3d, &values[size_1d..])?),
+        };
+        Some(Self { shaper, lattice })
+    }
+
+    fn lookup(&self, rgb: [f32; 3], interpolation: Interpolation) -> [f32; 3] {
+        let rgb = self.shaper.as_ref().map_or(rgb, |t| t.lookup_1d(rgb));
+        self.lattice.as_ref().map_or(rgb, |t| t.lookup_3d(rgb, interpolation))
+    }
+}
+
+/// Maps the straight colors of each visible pixel through the LUT, on sRGB-encoded values or, with
+/// `linear`, on linear light, and mixes the result with the input by `strength`.
+fn apply<T: Sample>(buf: &mut [T], lut: &Cube, params: &Params, premultiplied: bool) {
+    let decode = |v| if params.linear { plugin_sdk::to_linear::<T>(v) } else { plugin_sdk::to_encoded::<T>(v) };
+    let encode = |v| if params.linear { plugin_sdk::from_linear::<T>(v) } else { plugin_sdk::from_encoded::<T>(v) };
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        let input = rgb.map(decode);
+        let output = lut.lookup(input, params.interpolation);
+        std::array::from_fn(|c| encode(input[c] + params.strength * (output[c] - input[c])))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+    use std::path::{Path, PathBuf};
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// Creates a new directory for the test `name`; the test removes it at the end.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lut-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes `name.cube` into `dir` and returns its path.
+    fn cube_file(dir: &Path, name: &str, text: &str) -> String {
+        let path = dir.join(format!("{name}.cube"));
+        std::fs::write(&path, text).unwrap();
+        path.to_str().unwrap().replace('\\', "/")
+    }
+
+    /// A 2×2×2 LUT with black and white kept and every other corner turned red.
+    const RED_CORNERS: &str = "# Test\nTITLE \"red corners\"\nLUT_3D_SIZE 2\n\
+        0 0 0\n1 0 0\n1 0 0\n1 0 0\n1 0 0\n1 0 0\n1 0 0\n1 1 1\n";
+
+    #[test]
+    fn test_interpolation_of_3d_lut() {
+        let dir = test_dir("interpolation");
+        let inverted = "LUT_3D_SIZE 2\n1 1 1\n0 1 1\n1 0 1\n0 0 1\n1 1 0\n0 1 0\n1 0 0\n0 0 0\n";
+        let invert = cube_file(&dir, "invert", inverted);
+        for interpolation in ["trilinear", "tetrahedral"] {
+            let mut img = [10u8, 128, 250, 255];
+            let params = format!(r#"{{"path": "{invert}", "interpolation": "{interpolation}"}}"#);
+            run(&mut img, 1, 1, &params).unwrap();
+            assert_eq!(img, [245, 127, 5, 255], "{interpolation}");
+        }
+
+        // Tetrahedral interpolation keeps grays neutral whatever the off-diagonal corners hold.
+        let red = cube_file(&dir, "red", RED_CORNERS);
+        let mut gray = [128u8, 128, 128, 255];
+        run(&mut gray, 1, 1, &format!(r#"{{"path": "{red}"}}"#)).unwrap();
+        assert_eq!(gray, [128, 128, 128, 255]);
+        let mut gray = [128u8, 128, 128, 255];
+        run(&mut gray, 1, 1, &format!(r#"{{"path": "{red}", "interpolation": "trilinear"}}"#)).unwrap();
+        assert!(gray[0] > 128 && gray[1] < 128, "{gray:?}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_shaper_domain_and_strength() {
+        // A 1D curve over [0, 2]: input 1 sits in the middle of the table and maps to 0.25.
+        let dir = test_dir("shaper");
+        let curve = cube_file(&dir, "curve", "LUT_1D_SIZE 3\nDOMAIN_MAX 2 2 2\n0 0 0\n0.25 0.25 0.25\n1 1 1\n");
+        let mut img = [255u8, 0, 0, 255];
+        run(&mut img, 1, 1, &format!(r#"{{"path": "{curve}"}}"#)).unwrap();
+        assert_eq!(img, [64, 0, 0, 255]);
+
+        let mut img = [255u8, 0, 0, 255];
+        run(&mut img, 1, 1, &format!(r#"{{"path": "{curve}", "strength": 0.5}}"#)).unwrap();
+        assert_eq!(img, [159, 0, 0, 255]);
+
+        // Wrong entry counts and missing files fail.
+        let short = cube_file(&dir, "short", "LUT_3D_SIZE 2\n0 0 0\n1 1 1\n");
+        assert!(run(&mut img, 1, 1, &format!(r#"{{"path": "{short}"}}"#)).is_err());
+        assert!(run(&mut img, 1, 1, r#"{"path": "/nonexistent/look.cube"}"#).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// The shape that is kept; everything outside it becomes transparent.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Shape {
+    /// The image rectangle with corners rounded by `radius`.
+    #[default]
+    Rounded,
+    /// The ellipse touching all four edges; a circle for square images, as for avatars.
+    Ellipse,
+    /// The curve `|x|^n + |y|^n = 1` with `n = exponent` touching all four edges, between an
+    /// ellipse and a rectangle; 4 gives the "squircle" of app icons.
+    Superellipse,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    shape: Shape,
+    /// Corner radius in pixels for `rounded`, limited to half the shorter side.
+    #[serde(default = "default_radius")]
+    radius: f32,
+    /// Exponent for `superellipse`; 2 is an ellipse, larger values are squarer.
+    #[serde(default = "default_exponent")]
+    exponent: f32,
+}
+
+fn default_radius() -> f32 {
+    16.0
+}
+
+fn default_exponent() -> f32 {
+    4.0
+}
+
+/// Samples per pixel side used to antialias the edge.
+const SUBSAMPLES: usize = 4;
+
+const MANIFEST: &CStr = cr#"name = "mask_plugin"
+version = "0.1.0"
+description = "Masks the image to rounded corners, an ellipse or a superellipse with smooth edges"
+
+[defaults]
+shape = "rounded"
+radius = 16.0
+exponent = 4.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Masks both formats. It is not local: the shape spans the whole image.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.radius.is_nan() || params.exponent.is_nan() || params.exponent <= 0.0 {
+        return Err(PluginError::Invalid("radius and exponent must be numbers, exponent positive"));
+    }
+
+    match pixels {
+        Pixels::Rgba8(buf) => mask(width, height, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => mask(width, height, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Multiplies alpha by the fraction of each pixel inside the shape, and premultiplied colors
+/// with it, so the edges stay valid for either alpha convention.
+fn mask<T: Sample>(width: u32, height: u32, buf: &mut [T], params: &Params, premultiplied: bool) {
+    let (w, h) = (width as f32, height as f32);
+    let radius = params.radius.clamp(0.0, w.min(h) / 2.0);
+    // Whether the point `(x, y)`, relative to the image center, lies inside the shape.
+    let inside = |x: f32, y: f32| match params.shape {
+        Shape::Rounded => {
+            let qx = (x.abs() - (w / 2.0 - radius)).max(0.0);
+            let qy = (y.abs() - (h / 2.0 - radius)).max(0.0);
+            qx * qx + qy * qy <= radius * radius
+        }
+        Shape::Ellipse => (x / (w / 2.0)).powi(2) + (y / (h / 2.0)).powi(2) <= 1.0,
+        Shape::Superellipse => {
+            (x / (w / 2.0)).abs().powf(params.exponent) + (y / (h / 2.0)).abs().powf(params.exponent) <= 1.0
+        }
+    };
+    let step = 1.0 / SUBSAMPLES as f32;
+    let offsets: Vec<f32> = (0..SUBSAMPLES).map(|i| (i as f32 + 0.5) * step).collect();
+
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let x = (i % width as usize) as f32 - w / 2.0;
+        let y = (i / width as usize) as f32 - h / 2.0;
+        // Pixels whose four corners agree are entirely inside or outside for these convex shapes.
+        let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(dx, dy)| inside(x + dx, y + dy));
+        let coverage = if corners.iter().all(|&c| c) {
+            continue;
+        } else if corners.iter().all(|&c| !c) && !inside(x + 0.5, y + 0.5) {
+            0.0
+        } else {
+            let hits = offsets.iter().flat_map(|&sy| offsets.iter().map(move |&sx| (sx, sy)));
+            hits.filter(|&(sx, sy)| inside(x + sx, y + sy)).count() as f32 / (SUBSAMPLES * SUBSAMPLES) as f32
+        };
+        let channels = if premultiplied { 0..4 } else { 3..4 };
+        for c in channels {
+            px[c] = T::from_f32(px[c].to_f32() * coverage);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::{ALPHA_PREMULTIPLIED, testing};
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    fn alpha(img: &[u8], width: usize, x: usize, y: usize) -> u8 {
+        img[(y * width + x) * 4 + 3]
+    }
+
+    #[test]
+    fn test_rounded_corners() {
+        let mut img = [90u8, 120, 150, 255].repeat(40 * 20);
+        run(&mut img, 40, 20, r#"{"radius": 8.0}"#).unwrap();
+        for (x, y) in [(0, 0), (39, 0), (0, 19), (39, 19), (1, 1)] {
+            assert_eq!(alpha(&img, 40, x, y), 0, "({x}, {y})");
+        }
+        for (x, y) in [(20, 0), (0, 10), (20, 10), (8, 8)] {
+            assert_eq!(alpha(&img, 40, x, y), 255, "({x}, {y})");
+        }
+        // The curve is antialiased, and straight colors are kept under it.
+        assert!(img.chunks(4).any(|p| p[3] > 0 && p[3] < 255));
+        assert!(img.chunks(4).all(|p| p[..3] == [90, 120, 150]));
+
+        // A radius larger than half the side makes a stadium.
+        let mut img = [255u8; 4].repeat(40 * 20);
+        run(&mut img, 40, 20, r#"{"radius": 100.0}"#).unwrap();
+        assert_eq!(alpha(&img, 40, 0, 10), 255);
+        assert_eq!(alpha(&img, 40, 2, 2), 0);
+    }
+
+    #[test]
+    fn test_ellipse_and_superellipse() {
+        let area = |params: &str| {
+            let mut img = [255u8; 4].repeat(64 * 64);
+            run(&mut img, 64, 64, params).unwrap();
+            img.chunks(4).map(|p| p[3] as f32 / 255.0).sum::<f32>() / (64.0 * 64.0)
+        };
+        // A circle covers pi/4 of its square; a squircle about 0.927.
+        let circle = area(r#"{"shape": "ellipse"}"#);
+        assert!((circle - std::f32::consts::FRAC_PI_4).abs() < 0.01, "{circle}");
+        let squircle = area(r#"{"shape": "superellipse", "exponent": 4.0}"#);
+        assert!((squircle - 0.927).abs() < 0.01, "{squircle}");
+        let two = area(r#"{"shape": "superellipse", "exponent": 2.0}"#);
+        assert!((two - circle).abs() < 1e-6);
+
+        let mut img = [0u8; 4];
+        assert!(run(&mut img, 1, 1, r#"{"shape": "superellipse", "exponent": 0.0}"#).is_err());
+    }
+
+    #[test]
+    fn test_premultiplied_edges() {
+        let mut img = [0.5f32, 0.25, 1.0, 1.0].repeat(16 * 16);
+        let ctx = CallContext {
+            pixel_format: plugin_sdk::PIXEL_FORMAT_RGBA32F,
+            alpha_mode: ALPHA_PREMULTIPLIED,
+            ..CallContext::new(0)
+        };
+        testing::run_with(process, &ctx, 16, 16, &mut img, r#"{"shape": "ellipse"}"#).unwrap();
+        assert!(img.chunks(4).any(|p| p[3] > 0.0 && p[3] < 1.0));
+        for p in img.chunks(4) {
+            assert!((p[0] - 0.5 * p[3]).abs() < 1e-6 && (p[2] - p[3]).abs() < 1e-6);
+        }
+    }
+}
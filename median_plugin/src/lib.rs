@@ -0,0 +1,183 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, FORMAT_MASK_RGBA8};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// Which value of each window is kept.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// The middle value; removes isolated outliers while keeping edges sharp.
+    #[default]
+    Median,
+    /// The smallest value (erosion); grows dark areas.
+    Min,
+    /// The largest value (dilation); grows bright areas.
+    Max,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Half-width of the square window; 0 leaves the image unchanged.
+    #[serde(default = "default_radius")]
+    radius: u32,
+    #[serde(default)]
+    mode: Mode,
+}
+
+fn default_radius() -> u32 {
+    1
+}
+
+const MANIFEST: &CStr = cr#"name = "median_plugin"
+version = "0.1.0"
+description = "Median, min or max filter over a square window"
+
+[defaults]
+radius = 1
+mode = "median"
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    formats: FORMAT_MASK_RGBA8,
+    halo: halo,
+}
+
+/// Filters RGBA8 buffers only, since the histograms have one bin per 8-bit level, on the host's
+/// thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let Pixels::Rgba8(buf) = pixels else {
+        return Err(PluginError::Format);
+    };
+    let (w, h) = (width as usize, height as usize);
+    plugin_sdk::install(ctx, move || filter(w, h, buf, params))
+}
+
+/// The filter reaches `radius` pixels.
+fn halo(params: &Params) -> Option<u32> {
+    Some(params.radius)
+}
+
+/// One 256-bin histogram per channel of the current window.
+type Histograms = [[u32; 256]; 4];
+
+/// Filters every channel, alpha included, over the `2 * radius + 1` square window around each
+/// pixel, clipped to the image.
+///
+/// Each row slides its window to the right, adding the entering column to the histograms and
+/// removing the leaving one (Huang's algorithm), so the cost per pixel grows with the radius
+/// rather than its square. Rows are filtered in parallel from a copy of the input.
+fn filter(width: usize, height: usize, buf: &mut [u8], params: &Params) {
+    if width == 0 || height == 0 || params.radius == 0 {
+        return;
+    }
+    let r = params.radius as usize;
+    let src = buf.to_vec();
+    let row_len = width * 4;
+
+    buf.par_chunks_exact_mut(row_len).enumerate().for_each_init(
+        || Box::new([[0u32; 256]; 4]),
+        |hist, (y, row)| {
+            let rows = y.saturating_sub(r)..=(y + r).min(height - 1);
+            let column = |hist: &mut Histograms, x: usize, delta: i32| {
+                for yy in rows.clone() {
+                    let px = &src[(yy * width + x) * 4..][..4];
+                    for (h, &v) in hist.iter_mut().zip(px) {
+                        h[v as usize] = h[v as usize].wrapping_add_signed(delta);
+                    }
+                }
+            };
+
+            **hist = [[0; 256]; 4];
+            for x in 0..=r.min(width - 1) {
+                column(hist, x, 1);
+            }
+            for (x, out) in row.chunks_exact_mut(4).enumerate() {
+                let count = ((x + r).min(width - 1) - x.saturating_sub(r) + 1) * rows.clone().count();
+                for (o, h) in out.iter_mut().zip(hist.iter()) {
+                    *o = select(h, count as u32, params.mode);
+                }
+                if x + r + 1 < width {
+                    column(hist, x + r + 1, 1);
+                }
+                if x >= r {
+                    column(hist, x - r, -1);
+                }
+            }
+        },
+    );
+}
+
+/// Picks the value for `mode` from a histogram of `count` samples; an even count takes the
+/// lower of the two middle values.
+fn select(hist: &[u32; 256], count: u32, mode: Mode) -> u8 {
+    let mut bins = hist.iter().enumerate().filter(|(_, n)| **n > 0);
+    let found = match mode {
+        Mode::Min => bins.next(),
+        Mode::Max => bins.next_back(),
+        Mode::Median => {
+            let rank = (count - 1) / 2;
+            let mut seen = 0;
+            bins.find(|(_, n)| {
+                seen += **n;
+                seen > rank
+            })
+        }
+    };
+    found.map_or(0, |(v, _)| v as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    #[test]
+    fn test_removes_salt_and_pepper() {
+        let mut img = [100u8, 100, 100, 255].repeat(25);
+        img[(2 * 5 + 2) * 4..][..3].fill(255);
+        img[4 * 4..][..3].fill(0);
+        run(&mut img, 5, 5, "{}").unwrap();
+        assert!(img.chunks(4).all(|p| p == [100, 100, 100, 255]));
+    }
+
+    #[test]
+    fn test_keeps_edges() {
+        // Vertical step edge: a median leaves it in place where a blur would soften it.
+        let src: Vec<u8> = (0..6 * 6)
+            .flat_map(|i| if i % 6 < 3 { [20, 20, 20, 255] } else { [220, 220, 220, 255] })
+            .collect();
+        let mut img = src.clone();
+        run(&mut img, 6, 6, r#"{"radius": 2}"#).unwrap();
+        assert_eq!(img, src);
+
+        // Min and max move the edge by the radius.
+        let mut min = src.clone();
+        run(&mut min, 6, 6, r#"{"mode": "min"}"#).unwrap();
+        assert_eq!(min.chunks(4).take(6).map(|p| p[0]).collect::<Vec<_>>(), [20, 20, 20, 20, 220, 220]);
+        let mut max = src.clone();
+        run(&mut max, 6, 6, r#"{"mode": "max"}"#).unwrap();
+        assert_eq!(max.chunks(4).take(6).map(|p| p[0]).collect::<Vec<_>>(), [20, 20, 220, 220, 220, 220]);
+    }
+
+    #[test]
+    fn test_median_of_window() {
+        // 3x1 row: the middle pixel's window holds all three values.
+        let mut img = [10u8, 200, 0, 255, 50, 100, 0, 255, 30, 0, 0, 255];
+        run(&mut img, 3, 1, r#"{"radius": 1}"#).unwrap();
+        assert_eq!(&img[4..8], &[30, 100, 0, 255]);
+        // Edge windows hold two values, taking the lower one.
+        assert_eq!(&img[0..4], &[10, 100, 0, 255]);
+
+        assert_eq!(testing::run(process, 1, 1, &mut [0.5f32; 4], "{}"), Err(PluginError::Format));
+        assert_eq!(halo(&plugin_sdk::params_from_str(r#"{"radius": 3}"#).unwrap()), Some(3));
+    }
+}
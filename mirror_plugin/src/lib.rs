@@ -1,4 +1,3 @@
-use std::ffi::{CStr};
 use std::os::raw::c_char;
 use serde::Deserialize;
 
@@ -8,7 +7,21 @@ struct Params {
     vertical: bool,
 }
 
+/// Embedded plugin manifest; the host applies `defaults` before the params file.
 #[unsafe(no_mangle)]
+pub extern "C" fn plugin_manifest() -> *const c_char {
+    cr#"name = "mirror_plugin"
+version = "0.1.0"
+description = "Flips the image top-bottom and/or mirrors it left-right"
+
+[defaults]
+horizontal = false
+vertical = false
+"#.as_ptr()
+}
+
+#[unsafe(no_mangle)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn process_image(
     width: u32,
     height: u32,
@@ -20,27 +33,13 @@ pub extern "C" fn process_image(
     }
 
     // SAFETY:
-    // - We checked `params` is not NULL.
-    // - FFI contract requires `params` to be a valid NUL-terminated C string
+    // - FFI contract requires `params` to be null or a valid NUL-terminated C string
     //   that remains valid for the duration of this call.
-    // - `CStr::from_ptr` will read memory until the first NUL byte; if the pointer
-    //   is invalid or not NUL-terminated, that would be UB, hence the contract.
-    let params_str = unsafe {
-        if params.is_null() {
-            ""
-        } else {
-            CStr::from_ptr(params).to_str().unwrap_or("")
-        }
-    };
-
-    let params: Params = match toml::from_str(&params_str) {
-        Ok(config) => config,
-        Err(_) => {
-            return 1;
-        }
+    // - `parse_params` handles the null case and only reads up to the first NUL byte.
+    let Ok(params) = (unsafe { plugin_sdk::parse_params::<Params>(params) }) else {
+        return 1;
     };
 
-
     let w = width as usize;
     let h = height as usize;
     let len = w.checked_mul(h).and_then(|wh| wh.checked_mul(4));
@@ -71,23 +70,17 @@ pub extern "C" fn process_image(
 
 fn flip_top_bottom_in_place(width: usize, height: usize, buf: &mut [u8]) {
     let row_bytes = width * 4;
-    if row_bytes == 0 || height == 0 {
+    if row_bytes == 0 || height < 2 {
         return;
     }
 
-    let mut tmp = vec![0u8; row_bytes];
-
-    for y in 0..(height / 2) {
-        let top = y * row_bytes;
-        let bottom = (height - 1 - y) * row_bytes;
-
-        let (head, tail) = buf.split_at_mut(bottom);
-        let top_row = &mut head[top..top + row_bytes];
-        let bottom_row = &mut tail[..row_bytes];
-
-        tmp.copy_from_slice(top_row);
-        top_row.copy_from_slice(bottom_row);
-        bottom_row.copy_from_slice(&tmp);
+    // Swapping the rows of the two halves directly reads and writes each byte once,
+    // instead of twice through a temporary row.
+    let half = height / 2;
+    let (top, rest) = buf[..row_bytes * height].split_at_mut(half * row_bytes);
+    let bottom = &mut rest[(height % 2) * row_bytes..];
+    for (top_row, bottom_row) in top.chunks_exact_mut(row_bytes).zip(bottom.chunks_exact_mut(row_bytes).rev()) {
+        top_row.swap_with_slice(bottom_row);
     }
 }
 
@@ -97,16 +90,18 @@ fn mirror_left_right_in_place(width: usize, height: usize, buf: &mut [u8]) {
         return;
     }
 
-    for y in 0..height {
-        let row_start = y * row_bytes;
-        for x in 0..(width / 2) {
-            let left = row_start + x * 4;
-            let right = row_start + (width - 1 - x) * 4;
-
-            buf.swap(left + 0, right + 0);
-            buf.swap(left + 1, right + 1);
-            buf.swap(left + 2, right + 2);
-            buf.swap(left + 3, right + 3);
+    for row in buf[..row_bytes * height].chunks_exact_mut(row_bytes) {
+        // SAFETY:
+        // - Every bit pattern is a valid `u32`, and `align_to_mut` only puts correctly
+        //   aligned, fully covered words into the middle slice.
+        // - The middle slice borrows `row` mutably, so nothing else aliases it.
+        let (head, words, tail) = unsafe { row.align_to_mut::<u32>() };
+        if head.is_empty() && tail.is_empty() {
+            // One pixel per word: swapping words moves whole pixels in single loads and stores.
+            words.reverse();
+        } else {
+            let (pixels, _) = row.as_chunks_mut::<4>();
+            pixels.reverse();
         }
     }
 }
@@ -115,14 +110,14 @@ fn mirror_left_right_in_place(width: usize, height: usize, buf: &mut [u8]) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::ffi::CString;
+    use std::ffi::{CStr, CString};
     #[test]
     fn test_horizontal_flip() {
         let mut buf = vec![
             255, 0, 0, 255, 0, 255, 0, 255,
             0, 0, 255, 255, 255, 255, 0, 255,
         ];
-        let params_str = CString::new("horizontal = true\nvertical = false").unwrap();
+        let params_str = CString::new(r#"{"horizontal": true, "vertical": false}"#).unwrap();
         let result = process_image(2, 2, buf.as_mut_ptr(), params_str.as_ptr());
 
         assert_eq!(result, 0, "Plugin should return 0 (success)");
@@ -141,7 +136,7 @@ mod tests {
             255, 0, 0, 255, 0, 255, 0, 255,
             0, 0, 255, 255, 255, 255, 0, 255,
         ];
-        let params_str = CString::new("horizontal = false\nvertical = true").unwrap();
+        let params_str = CString::new(r#"{"horizontal": false, "vertical": true}"#).unwrap();
         let result = process_image(2, 2, buf.as_mut_ptr(), params_str.as_ptr());
 
         assert_eq!(result, 0, "Plugin should return 0 (success)");
@@ -154,10 +149,41 @@ mod tests {
         assert_eq!(buf, expected);
     }
 
+    #[test]
+    fn test_odd_sizes_match_naive() {
+        let (w, h) = (5usize, 7usize);
+        let src: Vec<u8> = (0..w * h * 4).map(|i| (i * 13 % 251) as u8).collect();
+        // Offset by one byte so the row starts are not word-aligned.
+        for offset in [0, 1] {
+            let mut buf = vec![0u8; offset];
+            buf.extend_from_slice(&src);
+            let data = &mut buf[offset..];
+            flip_top_bottom_in_place(w, h, data);
+            mirror_left_right_in_place(w, h, data);
+
+            for y in 0..h {
+                for x in 0..w {
+                    let from = ((h - 1 - y) * w + (w - 1 - x)) * 4;
+                    assert_eq!(data[(y * w + x) * 4..][..4], src[from..from + 4]);
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_null_buffer() {
-        let params_str = CString::new("horizontal = true").unwrap();
+        let params_str = CString::new(r#"{"horizontal": true, "vertical": false}"#).unwrap();
         let result = process_image(2, 2, std::ptr::null_mut(), params_str.as_ptr());
         assert_eq!(result, 1);
     }
+
+    #[test]
+    fn test_manifest_defaults_are_valid_params() {
+        // SAFETY: `plugin_manifest` returns a static NUL-terminated string.
+        let text = unsafe { CStr::from_ptr(plugin_manifest()) }.to_str().unwrap();
+        let manifest: toml::Table = toml::from_str(text).unwrap();
+        let defaults = manifest["defaults"].as_table().unwrap().clone();
+        let params: Result<Params, _> = defaults.try_into();
+        assert!(params.is_ok());
+    }
 }
@@ -0,0 +1,254 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Operation {
+    /// Shrinks bright or opaque areas: each pixel takes the minimum under the element.
+    #[default]
+    Erode,
+    /// Grows bright or opaque areas: each pixel takes the maximum under the element.
+    Dilate,
+    /// Erode, then dilate; removes specks and thin protrusions smaller than the element.
+    Open,
+    /// Dilate, then erode; fills holes and gaps smaller than the element.
+    Close,
+}
+
+/// Shape of the structuring element.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Shape {
+    /// All pixels within `radius` on both axes.
+    Square,
+    /// All pixels within `radius` of the center.
+    #[default]
+    Disk,
+    /// The center row and column up to `radius` away.
+    Cross,
+}
+
+impl Shape {
+    /// Offsets of the element's pixels from its center.
+    fn offsets(self, radius: u32) -> Vec<(i64, i64)> {
+        let r = radius as i64;
+        (-r..=r)
+            .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| match self {
+                Self::Square => true,
+                Self::Disk => dx * dx + dy * dy <= r * r,
+                Self::Cross => dx == 0 || dy == 0,
+            })
+            .collect()
+    }
+}
+
+/// What the operation compares.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Target {
+    /// Alpha alone, as a mask; colors are kept.
+    #[default]
+    Alpha,
+    /// The luma of the colors; each pixel is replaced whole by the darkest or brightest
+    /// pixel under the element, so no new colors appear.
+    Luma,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    operation: Operation,
+    #[serde(default)]
+    shape: Shape,
+    /// Radius of the structuring element; 0 leaves the image unchanged.
+    #[serde(default = "default_radius")]
+    radius: u32,
+    #[serde(default)]
+    target: Target,
+}
+
+fn default_radius() -> u32 {
+    1
+}
+
+impl Params {
+    /// How far the operation reaches from each pixel.
+    fn reach(&self) -> u32 {
+        match self.operation {
+            Operation::Erode | Operation::Dilate => self.radius,
+            Operation::Open | Operation::Close => self.radius.saturating_mul(2),
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "morphology_plugin"
+version = "0.1.0"
+description = "Erode, dilate, open and close on alpha or luma"
+
+[defaults]
+operation = "erode"
+shape = "disk"
+radius = 1
+target = "alpha"
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Applies the operation to both formats.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => morph(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => morph(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// The radius for erode and dilate, twice that for open and close.
+fn halo(params: &Params) -> Option<u32> {
+    Some(params.reach())
+}
+
+/// Runs the operation as one or two min/max passes over the structuring element.
+///
+/// The element is clipped at the image edges, so the edges neither erode nor dilate the image.
+fn morph<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 || params.radius == 0 {
+        return;
+    }
+    let offsets = params.shape.offsets(params.radius);
+    let passes: &[bool] = match params.operation {
+        Operation::Erode => &[false],
+        Operation::Dilate => &[true],
+        Operation::Open => &[false, true],
+        Operation::Close => &[true, false],
+    };
+    for &max in passes {
+        let src = buf.to_vec();
+        let key: Vec<f32> = src
+            .chunks_exact(4)
+            .map(|px| match params.target {
+                Target::Alpha => px[3].to_f32(),
+                Target::Luma => luma::<T>(px, premultiplied),
+            })
+            .collect();
+
+        for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+            let (x, y) = ((i % width) as i64, (i / width) as i64);
+            let mut best = i;
+            for &(dx, dy) in &offsets {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let n = ny as usize * width + nx as usize;
+                if (max && key[n] > key[best]) || (!max && key[n] < key[best]) {
+                    best = n;
+                }
+            }
+            if best == i {
+                continue;
+            }
+            match params.target {
+                Target::Luma => px.copy_from_slice(&src[best * 4..best * 4 + 4]),
+                Target::Alpha => set_alpha(px, src[best * 4 + 3], premultiplied),
+            }
+        }
+    }
+}
+
+/// Replaces a pixel's alpha, rescaling premultiplied colors to match; a premultiplied pixel
+/// that was fully transparent has no color to keep and becomes black.
+fn set_alpha<T: Sample>(px: &mut [T], alpha: T, premultiplied: bool) {
+    if premultiplied {
+        let old = px[3].to_f32();
+        let ratio = if old > 0.0 { alpha.to_f32() / old } else { 0.0 };
+        for v in &mut px[..3] {
+            *v = T::from_f32(v.to_f32() * ratio);
+        }
+    }
+    px[3] = alpha;
+}
+
+/// Luma of a pixel's straight sRGB-encoded color.
+fn luma<T: Sample>(px: &[T], premultiplied: bool) -> f32 {
+    let alpha = px[3].to_f32() / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    (0..3).map(|c| LUMA[c] * plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 7x7 mask: white, opaque where `opaque(x, y)` holds and transparent elsewhere.
+    fn mask(opaque: impl Fn(usize, usize) -> bool) -> Vec<u8> {
+        (0..49).flat_map(|i| [255, 255, 255, if opaque(i % 7, i / 7) { 255 } else { 0 }]).collect()
+    }
+
+    fn alphas(img: &[u8]) -> Vec<bool> {
+        img.chunks(4).map(|p| p[3] == 255).collect()
+    }
+
+    fn square(x: usize, y: usize) -> bool {
+        (2..5).contains(&x) && (2..5).contains(&y)
+    }
+
+    #[test]
+    fn test_erode_and_dilate() {
+        let mut img = mask(square);
+        run(&mut img, 7, 7, r#"{"operation": "erode", "shape": "square"}"#).unwrap();
+        assert_eq!(alphas(&img), alphas(&mask(|x, y| (x, y) == (3, 3))));
+
+        let mut img = mask(|x, y| (x, y) == (3, 3));
+        run(&mut img, 7, 7, r#"{"operation": "dilate", "shape": "cross", "radius": 2}"#).unwrap();
+        let plus = |x: usize, y: usize| (x == 3 && (1..6).contains(&y)) || (y == 3 && (1..6).contains(&x));
+        assert_eq!(alphas(&img), alphas(&mask(plus)));
+
+        let mut img = mask(|x, y| (x, y) == (3, 3));
+        run(&mut img, 7, 7, r#"{"operation": "dilate", "shape": "disk", "radius": 2}"#).unwrap();
+        let disk = |x: usize, y: usize| (x as i64 - 3).pow(2) + (y as i64 - 3).pow(2) <= 4;
+        assert_eq!(alphas(&img), alphas(&mask(disk)));
+    }
+
+    #[test]
+    fn test_open_and_close() {
+        // Opening removes a lone speck but keeps the square.
+        let mut img = mask(|x, y| square(x, y) || (x, y) == (0, 6));
+        run(&mut img, 7, 7, r#"{"operation": "open", "shape": "square"}"#).unwrap();
+        assert_eq!(alphas(&img), alphas(&mask(square)));
+
+        // Closing fills a one-pixel hole.
+        let mut img = mask(|x, y| square(x, y) && (x, y) != (3, 3));
+        run(&mut img, 7, 7, r#"{"operation": "close", "shape": "square"}"#).unwrap();
+        assert_eq!(alphas(&img), alphas(&mask(square)));
+    }
+
+    #[test]
+    fn test_luma_copies_whole_pixels() {
+        let mut img = [10u8, 20, 30, 255, 200, 150, 100, 128, 40, 40, 40, 255].to_vec();
+        run(&mut img, 3, 1, r#"{"operation": "dilate", "target": "luma", "shape": "square"}"#).unwrap();
+        assert_eq!(img, [200, 150, 100, 128].repeat(3));
+
+        let params = plugin_sdk::params_from_str(r#"{"operation": "close", "radius": 3}"#).unwrap();
+        assert_eq!(halo(&params), Some(6));
+    }
+}
@@ -0,0 +1,231 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// Upper bound on the samples taken per pixel when `samples` is 0.
+const MAX_SAMPLES: usize = 256;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Straight streaks of `length` pixels in the direction of `angle`.
+    #[default]
+    Motion,
+    /// Streaks towards `center`, as if zooming during the exposure.
+    Zoom,
+    /// Arcs around `center`, as if rotating during the exposure.
+    Spin,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// Direction of the motion in degrees, counterclockwise from the positive x axis.
+    #[serde(default)]
+    angle: f32,
+    /// Length of the motion streaks in pixels.
+    #[serde(default)]
+    length: f32,
+    /// Center of zoom and spin, as fractions of the width and height.
+    #[serde(default = "default_center")]
+    center: [f32; 2],
+    /// Zoom: fraction of the distance to the center each streak covers (0 to 1).
+    /// Spin: arc in degrees.
+    #[serde(default)]
+    strength: f32,
+    /// Samples per pixel; 0 takes about one per pixel of path.
+    #[serde(default)]
+    samples: u32,
+}
+
+fn default_center() -> [f32; 2] {
+    [0.5, 0.5]
+}
+
+impl Params {
+    /// How far motion blur reaches from each pixel; `None` for zoom and spin, which reach
+    /// further the further a pixel is from the center.
+    fn reach(&self) -> Option<u32> {
+        (self.mode == Mode::Motion).then(|| (self.length.abs() / 2.0).ceil() as u32 + 1)
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "motion_blur_plugin"
+version = "0.1.0"
+description = "Directional motion blur and radial zoom or spin blur"
+
+[defaults]
+mode = "motion"
+angle = 0.0
+length = 0.0
+center = [0.5, 0.5]
+strength = 0.0
+samples = 0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Blurs both formats on the host's thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+
+    let (w, h) = (width as usize, height as usize);
+    let run = move || match pixels {
+        Pixels::Rgba8(buf) => blur(w, h, buf, params),
+        Pixels::Rgba32F(buf) => blur(w, h, buf, params),
+    };
+
+    plugin_sdk::install(ctx, run)
+}
+
+/// Motion blur reaches half the streak length, while zoom and spin are not local.
+fn halo(params: &Params) -> Option<u32> {
+    params.reach()
+}
+
+/// Averages each pixel over evenly spaced samples along its path, read with bilinear
+/// interpolation from a copy of the input and clamped at the image edges.
+///
+/// Motion streaks are centered on the pixel; zoom streaks run from the pixel towards the
+/// center, and spin arcs are centered on the pixel. Values are averaged as stored, so
+/// premultiplied data stays premultiplied.
+fn blur<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let row_len = width * 4;
+    let src: Vec<f32> = buf[..row_len * height].par_iter().map(|v| v.to_f32()).collect();
+    let (cx, cy) = (params.center[0] * width as f32, params.center[1] * height as f32);
+    // The y axis points down, so a counterclockwise angle moves towards negative y.
+    let (sin, cos) = params.angle.to_radians().sin_cos();
+    let motion = [cos * params.length, -sin * params.length];
+    let spin = params.strength.to_radians();
+
+    buf[..row_len * height].par_chunks_exact_mut(row_len).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.chunks_exact_mut(4).enumerate() {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let (dx, dy) = (px - cx, py - cy);
+            // Point at fraction `t` of the path, for `t` from 0 to 1.
+            let point = |t: f32| match params.mode {
+                Mode::Motion => [px + motion[0] * (t - 0.5), py + motion[1] * (t - 0.5)],
+                Mode::Zoom => [px - dx * params.strength * t, py - dy * params.strength * t],
+                Mode::Spin => {
+                    let (s, c) = (spin * (t - 0.5)).sin_cos();
+                    [cx + dx * c - dy * s, cy + dx * s + dy * c]
+                }
+            };
+            let length = match params.mode {
+                Mode::Motion => params.length.abs(),
+                Mode::Zoom => dx.hypot(dy) * params.strength.abs(),
+                Mode::Spin => dx.hypot(dy) * spin.abs(),
+            };
+            let count = match params.samples {
+                0 => (length.ceil() as usize + 1).min(MAX_SAMPLES),
+                n => n as usize,
+            };
+            if count < 2 || length < 1e-3 {
+                continue;
+            }
+
+            let mut acc = [0.0f32; 4];
+            for i in 0..count {
+                let [sx, sy] = point(i as f32 / (count - 1) as f32);
+                let value = bilinear(&src, width, height, sx - 0.5, sy - 0.5);
+                for c in 0..4 {
+                    acc[c] += value[c];
+                }
+            }
+            for (o, a) in out.iter_mut().zip(acc) {
+                *o = T::from_f32(a / count as f32);
+            }
+        }
+    });
+}
+
+/// Samples `src` at `(x, y)` in pixel-center coordinates, clamping to the edges.
+fn bilinear(src: &[f32], width: usize, height: usize, x: f32, y: f32) -> [f32; 4] {
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let at = |x: usize, y: usize, c: usize| src[(y * width + x) * 4 + c];
+    std::array::from_fn(|c| {
+        let top = at(x0, y0, c) * (1.0 - fx) + at(x1, y0, c) * fx;
+        let bottom = at(x0, y1, c) * (1.0 - fx) + at(x1, y1, c) * fx;
+        top * (1.0 - fy) + bottom * fy
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A black 9x9 image with a white dot in the middle.
+    fn dot() -> Vec<u8> {
+        let mut img = [0u8, 0, 0, 255].repeat(81);
+        img[(4 * 9 + 4) * 4..][..3].fill(255);
+        img
+    }
+
+    fn red(img: &[u8], x: usize, y: usize) -> u8 {
+        img[(y * 9 + x) * 4]
+    }
+
+    #[test]
+    fn test_motion_streaks_along_angle() {
+        let mut img = dot();
+        run(&mut img, 9, 9, r#"{"length": 4}"#).unwrap();
+        assert!(red(&img, 2, 4) > 0 && red(&img, 6, 4) > 0);
+        assert_eq!(red(&img, 4, 2), 0);
+        assert!(red(&img, 4, 4) < 255);
+
+        let mut vertical = dot();
+        run(&mut vertical, 9, 9, r#"{"length": 4, "angle": 90}"#).unwrap();
+        assert!(red(&vertical, 4, 2) > 0);
+        assert_eq!(red(&vertical, 2, 4), 0);
+
+        let mut still = dot();
+        run(&mut still, 9, 9, "{}").unwrap();
+        assert_eq!(still, dot());
+    }
+
+    #[test]
+    fn test_zoom_and_spin_keep_center() {
+        for params in [r#"{"mode": "zoom", "strength": 0.5}"#, r#"{"mode": "spin", "strength": 30}"#] {
+            let mut img = dot();
+            run(&mut img, 9, 9, params).unwrap();
+            assert_eq!(red(&img, 4, 4), 255, "{params}");
+
+            let mut flat = [90u8, 60, 30, 255].repeat(81);
+            run(&mut flat, 9, 9, params).unwrap();
+            assert!(flat.chunks(4).all(|p| p == [90, 60, 30, 255]), "{params}");
+        }
+
+        // A dot off the center is smeared outwards by zoom, not sideways.
+        let mut img = [0u8, 0, 0, 255].repeat(81);
+        img[(4 * 9 + 6) * 4..][..3].fill(255);
+        run(&mut img, 9, 9, r#"{"mode": "zoom", "strength": 0.5}"#).unwrap();
+        assert!(red(&img, 7, 4) > 0);
+        assert_eq!(red(&img, 6, 2), 0);
+    }
+
+    #[test]
+    fn test_halo() {
+        let halo = |params: &str| halo(&plugin_sdk::params_from_str(params).unwrap());
+        assert_eq!(halo(r#"{"length": 10}"#), Some(6));
+        assert_eq!(halo(r#"{"mode": "zoom", "strength": 0.2}"#), None);
+    }
+}
@@ -0,0 +1,218 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Half-width of the patches compared around each pixel.
+    #[serde(default = "default_patch_radius")]
+    patch_radius: u32,
+    /// Half-width of the window searched for similar patches.
+    #[serde(default = "default_search_radius")]
+    search_radius: u32,
+    /// Filtering strength: the RMS difference between sRGB-encoded patches, in units of full
+    /// scale, at which a patch's weight drops to 1/e. Raise it with the noise level; 0 disables it.
+    #[serde(default = "default_strength")]
+    strength: f32,
+}
+
+fn default_patch_radius() -> u32 {
+    1
+}
+
+fn default_search_radius() -> u32 {
+    7
+}
+
+fn default_strength() -> f32 {
+    0.05
+}
+
+const MANIFEST: &CStr = cr#"name = "nlm_plugin"
+version = "0.1.0"
+description = "Non-local-means denoising"
+
+[defaults]
+patch_radius = 1
+search_radius = 7
+strength = 0.05
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Denoises both formats on the host's thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    let run = move || match pixels {
+        Pixels::Rgba8(buf) => denoise(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => denoise(w, h, buf, params, premultiplied),
+    };
+
+    plugin_sdk::install(ctx, run)
+}
+
+/// The search radius plus the patch radius.
+fn halo(params: &Params) -> Option<u32> {
+    Some(params.search_radius.saturating_add(params.patch_radius))
+}
+
+/// Replaces every pixel with an average of the pixels in its search window, each weighted by
+/// how similar the patch around it is to the patch around the pixel being denoised.
+///
+/// The work is organized by offset rather than by pixel: for each offset in the search window,
+/// the squared differences between the image and its shifted copy are box-averaged over the
+/// patch size in one pass, so the cost does not grow with the patch area. Patches are compared
+/// on straight sRGB-encoded colors and alpha; the stored values are averaged as they are.
+fn denoise<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 || params.search_radius == 0 || params.strength <= 0.0 {
+        return;
+    }
+    let (w, h) = (width as i64, height as i64);
+    let s = params.search_radius as i64;
+    let p = params.patch_radius as usize;
+    let src: Vec<f32> = buf.par_iter().map(|v| v.to_f32()).collect();
+    let guide: Vec<[f32; 4]> = src.par_chunks_exact(4).map(|px| encoded::<T>(px, premultiplied)).collect();
+    let inv_h2 = 1.0 / (params.strength * params.strength);
+
+    let mut acc = vec![[0.0f32; 5]; width * height];
+    let mut diff = vec![0.0f32; width * height];
+    for dy in -s..=s {
+        for dx in -s..=s {
+            // Neighbors past the edges are clamped for the patch comparison but never averaged in.
+            diff.par_chunks_exact_mut(width).enumerate().for_each(|(y, row)| {
+                let sy = (y as i64 + dy).clamp(0, h - 1) as usize;
+                for (x, d) in row.iter_mut().enumerate() {
+                    let sx = (x as i64 + dx).clamp(0, w - 1) as usize;
+                    let (a, b) = (guide[y * width + x], guide[sy * width + sx]);
+                    *d = a.iter().zip(&b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>() / 4.0;
+                }
+            });
+            let patch = box_mean(&diff, width, height, p);
+
+            acc.par_chunks_exact_mut(width).enumerate().for_each(|(y, row)| {
+                let sy = y as i64 + dy;
+                if !(0..h).contains(&sy) {
+                    return;
+                }
+                for (x, a) in row.iter_mut().enumerate() {
+                    let sx = x as i64 + dx;
+                    if !(0..w).contains(&sx) {
+                        continue;
+                    }
+                    let weight = (-patch[y * width + x] * inv_h2).exp();
+                    let i = (sy as usize * width + sx as usize) * 4;
+                    for c in 0..4 {
+                        a[c] += src[i + c] * weight;
+                    }
+                    a[4] += weight;
+                }
+            });
+        }
+    }
+
+    // The zero offset always contributes with weight 1, so every total is positive.
+    buf.par_chunks_exact_mut(4).zip(&acc).for_each(|(px, a)| {
+        for c in 0..4 {
+            px[c] = T::from_f32(a[c] / a[4]);
+        }
+    });
+}
+
+/// Averages `src` over the `2 * r + 1` square around each pixel, clipped to the image.
+fn box_mean(src: &[f32], width: usize, height: usize, r: usize) -> Vec<f32> {
+    let mut rows = vec![0.0f32; src.len()];
+    rows.par_chunks_exact_mut(width).zip(src.par_chunks_exact(width)).for_each(|(dst, src)| {
+        let mut prefix = vec![0.0f32; width + 1];
+        for (i, v) in src.iter().enumerate() {
+            prefix[i + 1] = prefix[i] + v;
+        }
+        for (x, d) in dst.iter_mut().enumerate() {
+            let (lo, hi) = (x.saturating_sub(r), (x + r + 1).min(width));
+            *d = (prefix[hi] - prefix[lo]) / (hi - lo) as f32;
+        }
+    });
+    let mut out = vec![0.0f32; src.len()];
+    out.par_chunks_exact_mut(width).enumerate().for_each(|(y, dst)| {
+        let (lo, hi) = (y.saturating_sub(r), (y + r + 1).min(height));
+        for row in rows[lo * width..hi * width].chunks_exact(width) {
+            for (d, v) in dst.iter_mut().zip(row) {
+                *d += v;
+            }
+        }
+        let inv = 1.0 / (hi - lo) as f32;
+        dst.iter_mut().for_each(|d| *d *= inv);
+    });
+    out
+}
+
+/// A pixel's straight-alpha color, sRGB-encoded and normalized to `[0, 1]`, followed by its alpha.
+fn encoded<T: Sample>(px: &[f32], premultiplied: bool) -> [f32; 4] {
+    let alpha = px[3] / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    let color = |v: f32| plugin_sdk::to_encoded::<T>(v / scale);
+    [color(px[0]), color(px[1]), color(px[2]), alpha]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 12x12 image, dark on the left half and bright on the right, with pseudo-random noise.
+    fn noisy_edge() -> Vec<u8> {
+        let mut rng = plugin_sdk::Rng::new(7);
+        (0..144)
+            .flat_map(|i| {
+                let base = if i % 12 < 6 { 60.0 } else { 190.0 };
+                let v = (base + (rng.next_f32() - 0.5) * 20.0) as u8;
+                [v, v, v, 255]
+            })
+            .collect()
+    }
+
+    fn deviation(img: &[u8], columns: std::ops::Range<usize>) -> f32 {
+        let values: Vec<f32> =
+            img.chunks(4).enumerate().filter(|(i, _)| columns.contains(&(i % 12))).map(|(_, p)| p[0] as f32).collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        (values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / values.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn test_reduces_noise_and_keeps_edge() {
+        let mut img = noisy_edge();
+        run(&mut img, 12, 12, r#"{"search_radius": 4, "strength": 0.08}"#).unwrap();
+        assert!(deviation(&img, 0..6) < deviation(&noisy_edge(), 0..6) / 2.0);
+        assert!(deviation(&img, 6..12) < deviation(&noisy_edge(), 6..12) / 2.0);
+        for row in img.chunks(12 * 4) {
+            assert!(row[5 * 4] < 80 && row[6 * 4] > 170, "{:?}", &row[20..28]);
+        }
+    }
+
+    #[test]
+    fn test_flat_and_disabled() {
+        let src = [90u8, 60, 30, 200].repeat(25);
+        let mut img = src.clone();
+        run(&mut img, 5, 5, r#"{"search_radius": 2}"#).unwrap();
+        assert_eq!(img, src);
+
+        let mut img = noisy_edge();
+        run(&mut img, 12, 12, r#"{"strength": 0}"#).unwrap();
+        assert_eq!(img, noisy_edge());
+
+        let params = plugin_sdk::params_from_str(r#"{"search_radius": 5, "patch_radius": 2}"#).unwrap();
+        assert_eq!(halo(&params), Some(7));
+    }
+}
@@ -0,0 +1,189 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Which way the green channel points, which differs between engines.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Convention {
+    /// Green up (Y+), as in OpenGL, Blender, Unity and Godot.
+    #[default]
+    OpenGl,
+    /// Green down (Y-), as in DirectX and Unreal.
+    DirectX,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Height difference, in pixels, between black and white; steeper slopes tilt the normals
+    /// further.
+    #[serde(default = "default_strength")]
+    strength: f32,
+    #[serde(default)]
+    convention: Convention,
+    /// Whether dark rather than bright areas are high.
+    #[serde(default)]
+    invert: bool,
+    /// Whether the neighbours of edge pixels are taken from the opposite edge, so a tileable
+    /// texture gives a tileable normal map.
+    #[serde(default)]
+    wrap: bool,
+}
+
+fn default_strength() -> f32 {
+    2.0
+}
+
+const MANIFEST: &CStr = cr#"name = "normal_map_plugin"
+version = "0.1.0"
+description = "Turns the luminance, taken as height, into a tangent-space normal map"
+
+[defaults]
+strength = 2.0
+convention = "opengl"
+invert = false
+wrap = false
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Turns both formats into a normal map.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if !params.strength.is_finite() {
+        return Err(PluginError::Invalid("strength must be finite"));
+    }
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => normal_map(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => normal_map(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// A one-pixel halo, unless `wrap` reaches across the image.
+fn halo(params: &Params) -> Option<u32> {
+    (!params.wrap).then_some(1)
+}
+
+/// Treats the luma of the sRGB-encoded colors as a height map and replaces each color by its
+/// surface normal, each component mapped from `[-1, 1]` to `[0, 1]`; a flat area becomes
+/// (0.5, 0.5, 1).
+///
+/// The slope is the Sobel gradient. The components are written as they are, without the sRGB
+/// curve, since a normal map is data rather than color; alpha is kept.
+fn normal_map<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let heights: Vec<f32> = buf
+        .chunks_exact(4)
+        .map(|px| {
+            let alpha = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+            let luma: f32 = (0..3).map(|c| LUMA[c] * plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)).sum();
+            if params.invert { 1.0 - luma } else { luma }
+        })
+        .collect();
+    let (w, h) = (width as i64, height as i64);
+    let at = |x: i64, y: i64| {
+        let (x, y) = if params.wrap {
+            (x.rem_euclid(w), y.rem_euclid(h))
+        } else {
+            (x.clamp(0, w - 1), y.clamp(0, h - 1))
+        };
+        heights[(y * w + x) as usize]
+    };
+    // Green points up in OpenGL, against the image's downward y axis.
+    let flip = match params.convention {
+        Convention::OpenGl => 1.0,
+        Convention::DirectX => -1.0,
+    };
+
+    for (i, px) in buf.chunks_exact_mut(4).enumerate() {
+        let (x, y) = ((i % width) as i64, (i / width) as i64);
+        let gx = (at(x + 1, y - 1) - at(x - 1, y - 1))
+            + 2.0 * (at(x + 1, y) - at(x - 1, y))
+            + (at(x + 1, y + 1) - at(x - 1, y + 1));
+        let gy = (at(x - 1, y + 1) - at(x - 1, y - 1))
+            + 2.0 * (at(x, y + 1) - at(x, y - 1))
+            + (at(x + 1, y + 1) - at(x + 1, y - 1));
+        // The Sobel sums are eight times the slope per pixel. The surface tilts away from the
+        // direction it rises in.
+        let (dx, dy) = (params.strength * gx / 8.0, params.strength * gy / 8.0);
+        let length = (dx * dx + dy * dy + 1.0).sqrt();
+        let normal = [-dx / length, flip * dy / length, 1.0 / length];
+
+        let scale = if premultiplied { px[3].to_f32() } else { T::MAX };
+        for (c, n) in normal.into_iter().enumerate() {
+            px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(0.5 + 0.5 * n) * scale);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 5x5 image that brightens from top to bottom.
+    fn ramp() -> Vec<u8> {
+        (0..25u8).flat_map(|i| [i / 5 * 50, i / 5 * 50, i / 5 * 50, 255]).collect()
+    }
+
+    fn px(img: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+        &img[(y * width + x) * 4..][..4]
+    }
+
+    #[test]
+    fn test_flat_faces_up_and_slopes_tilt() {
+        let mut img = [90u8, 30, 200, 77].repeat(9);
+        run(&mut img, 3, 3, "{}").unwrap();
+        assert!(img.chunks(4).all(|p| p == [128, 128, 255, 77]), "{img:?}");
+
+        // Rising downward, the surface faces up the image: green up in OpenGL, down in DirectX.
+        let mut img = ramp();
+        run(&mut img, 5, 5, "{}").unwrap();
+        let center = px(&img, 5, 2, 2);
+        assert!(center[0] == 128 && center[1] > 150 && center[2] > 200, "{center:?}");
+        let mut img = ramp();
+        run(&mut img, 5, 5, r#"{"convention": "directx"}"#).unwrap();
+        assert!(px(&img, 5, 2, 2)[1] < 106);
+        let mut img = ramp();
+        run(&mut img, 5, 5, r#"{"invert": true}"#).unwrap();
+        assert!(px(&img, 5, 2, 2)[1] < 106);
+
+        // More strength, steeper normals.
+        let mut steep = ramp();
+        run(&mut steep, 5, 5, r#"{"strength": 8}"#).unwrap();
+        assert!(px(&steep, 5, 2, 2)[2] < center[2]);
+    }
+
+    #[test]
+    fn test_wrap_reads_across_edges() {
+        // A bright column at the left edge of a 4x1 image.
+        let src = [[255u8, 255, 255, 255], [0, 0, 0, 255], [0, 0, 0, 255], [0, 0, 0, 255]].concat();
+        let mut img = src.clone();
+        run(&mut img, 4, 1, "{}").unwrap();
+        assert_eq!(px(&img, 4, 3, 0), [128, 128, 255, 255]);
+        // Wrapped, the last pixel has the bright column to its right and faces left.
+        let mut img = src.clone();
+        run(&mut img, 4, 1, r#"{"wrap": true}"#).unwrap();
+        assert!(px(&img, 4, 3, 0)[0] < 64, "{img:?}");
+    }
+}
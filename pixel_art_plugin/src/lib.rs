@@ -0,0 +1,320 @@
+use std::ffi::CStr;
+use plugin_sdk::{Allocator, CallContext, ImageRef, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+/// Distance below which two colors count as equal, on the scale of [`distance`]: a luma step of
+/// 15 in 8 bits.
+const EQUAL: f32 = 48.0 * 15.0 / 255.0;
+
+/// Samples per axis used to measure how much of an output pixel lies beyond an edge.
+const SUBSAMPLES: usize = 4;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Integer enlargement factor, from 2 to 8.
+    #[serde(default = "default_scale")]
+    scale: u32,
+    /// Whether output pixels cut by an edge mix both colors; without it, each output pixel takes
+    /// the color covering most of it and the image keeps its palette.
+    #[serde(default = "default_blend")]
+    blend: bool,
+}
+
+fn default_scale() -> u32 {
+    2
+}
+
+fn default_blend() -> bool {
+    true
+}
+
+const MANIFEST: &CStr = cr#"name = "pixel_art_plugin"
+version = "0.1.0"
+description = "Enlarges pixel art with the xBR edge-directed scaler"
+
+[defaults]
+scale = 2
+blend = true
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process_v2: process,
+}
+
+/// Upscales both formats.
+fn process(
+    image: ImageRef<'_>,
+    output: &mut Allocator<'_>,
+    params: &Params,
+    ctx: &CallContext,
+) -> Result<(), PluginError> {
+    let ImageRef { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if !(2..=8).contains(&params.scale)
+        || width.checked_mul(params.scale).is_none()
+        || height.checked_mul(params.scale).is_none()
+    {
+        return Err(PluginError::Invalid("scale must be 2 to 8 and keep the size in range"));
+    }
+
+    match pixels {
+        PixelsRef::Rgba8(src) => upscale(src, (width, height), params, premultiplied, output),
+        PixelsRef::Rgba32F(src) => upscale(src, (width, height), params, premultiplied, output),
+    }
+}
+
+/// A source pixel: its premultiplied value, blended in the buffer's scale, and the weighted
+/// YUV and alpha of its encoded straight color, compared by [`distance`].
+#[derive(Clone, Copy)]
+struct Texel {
+    value: [f32; 4],
+    key: [f32; 4],
+}
+
+/// xBR's perceptual color distance: luma weighs 48, the chroma differences 7 and 6. Alpha
+/// weighs like luma, so sprites keep their outlines against transparency.
+fn distance(a: &Texel, b: &Texel) -> f32 {
+    a.key.iter().zip(&b.key).map(|(x, y)| (x - y).abs()).sum()
+}
+
+fn equal(a: &Texel, b: &Texel) -> bool {
+    distance(a, b) < EQUAL
+}
+
+/// Which edge, if any, cuts the bottom-right corner of a pixel, by xBR level 2's rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Corner {
+    /// The 45° edge; the base of the shallow and steep ones.
+    diagonal: bool,
+    /// An edge twice as wide as high, reaching back across the pixel's bottom side.
+    shallow: bool,
+    /// An edge twice as high as wide, reaching back across the pixel's right side.
+    steep: bool,
+}
+
+impl Corner {
+    /// Classifies the corner from the pixel `e` and its neighbours, named as in xBR:
+    ///
+    /// ```text
+    ///    .  b  c  .
+    ///    d  e  f  f4
+    ///    g  h  i  i4
+    ///    .  h5 i5
+    /// ```
+    fn classify(
+        [b, c, d, e, f, g, h, i]: [&Texel; 8],
+        [f4, h5, i4, i5]: [&Texel; 4],
+    ) -> Self {
+        let none = Self { diagonal: false, shallow: false, steep: false };
+        // Only where `e` differs from both neighbours facing the corner, and not inside a
+        // checkerboard or a one-pixel line.
+        let restricted = !equal(e, f)
+            && !equal(e, h)
+            && ((!equal(f, b) && !equal(h, d))
+                || (equal(e, i) && !equal(f, i4) && !equal(h, i5))
+                || equal(e, g)
+                || equal(e, c));
+        if !restricted {
+            return none;
+        }
+        // An edge runs along `f`-`h` if colors change less along it than across it.
+        let along = distance(e, c) + distance(e, g) + distance(i, h5) + distance(i, f4) + 4.0 * distance(h, f);
+        let across = distance(h, d) + distance(h, i5) + distance(f, i4) + distance(f, b) + 4.0 * distance(e, i);
+        if along >= across {
+            return none;
+        }
+        let (fg, hc) = (distance(f, g), distance(h, c));
+        Self {
+            diagonal: true,
+            shallow: 2.0 * fg <= hc && !equal(e, g) && !equal(d, g),
+            steep: fg >= 2.0 * hc && !equal(e, c) && !equal(b, c),
+        }
+    }
+
+    /// Signed distance of the point `(u, v)` of the pixel, from `(0, 0)` at its top left to
+    /// `(1, 1)` at its bottom right, beyond the farthest-reaching edge; negative before it.
+    fn depth(self, u: f32, v: f32) -> f32 {
+        let mut depth = f32::NEG_INFINITY;
+        if self.diagonal {
+            depth = depth.max((u + v - 1.5) / 2.0f32.sqrt());
+        }
+        if self.shallow {
+            depth = depth.max((v + u / 2.0 - 1.0) / 1.25f32.sqrt());
+        }
+        if self.steep {
+            depth = depth.max((u + v / 2.0 - 1.0) / 1.25f32.sqrt());
+        }
+        depth
+    }
+}
+
+/// Enlarges `src` by `params.scale` into a buffer allocated from `output`.
+///
+/// Each output block starts as a copy of its source pixel. At each of the pixel's four corners
+/// (handled as the bottom-right one of the neighbourhood turned in quarter steps), a detected
+/// edge paints the area beyond it with whichever of the two neighbours facing the corner is
+/// closer in color. Fails if the host refused the buffer.
+fn upscale<T: Sample>(
+    src: &[T],
+    (width, height): (u32, u32),
+    params: &Params,
+    premultiplied: bool,
+    output: &mut Allocator<'_>,
+) -> Result<(), PluginError> {
+    let texels: Vec<Texel> = src.chunks_exact(4).map(|px| texel(px, premultiplied)).collect();
+    let (w, h) = (width as i64, height as i64);
+    let at = |x: i64, y: i64| &texels[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+
+    let scale = params.scale as usize;
+    let (ow, oh) = (width * params.scale, height * params.scale);
+    let dst = output.alloc::<T>(ow, oh)?;
+
+    // Coverage of an output pixel of a block, in the bottom-right frame, by the area beyond the
+    // corner's edge.
+    let coverage = |corner: Corner, sx: usize, sy: usize| -> f32 {
+        let n = (scale * SUBSAMPLES) as f32;
+        let hits: f32 = (0..SUBSAMPLES * SUBSAMPLES)
+            .map(|k| {
+                let u = ((sx * SUBSAMPLES + k % SUBSAMPLES) as f32 + 0.5) / n;
+                let v = ((sy * SUBSAMPLES + k / SUBSAMPLES) as f32 + 0.5) / n;
+                // Samples on the edge count half, so a pixel cut through its diagonal is half covered.
+                match corner.depth(u, v) {
+                    d if d > 1e-5 => 1.0,
+                    d if d > -1e-5 => 0.5,
+                    _ => 0.0,
+                }
+            })
+            .sum();
+        hits / (SUBSAMPLES * SUBSAMPLES) as f32
+    };
+
+    let mut block = vec![[0.0f32; 4]; scale * scale];
+    for y in 0..h {
+        for x in 0..w {
+            let e = at(x, y);
+            block.fill(e.value);
+            for turn in 0..4 {
+                // Maps an offset in the bottom-right frame to the image: a quarter turn
+                // clockwise per step, so the frame's bottom-right corner visits all four.
+                let rotate = |dx: i64, dy: i64| (0..turn).fold((dx, dy), |(a, b), _| (-b, a));
+                let n = |dx: i64, dy: i64| {
+                    let (rx, ry) = rotate(dx, dy);
+                    at(x + rx, y + ry)
+                };
+                let (b, c, d, f, g, hh, i) = (n(0, -1), n(1, -1), n(-1, 0), n(1, 0), n(-1, 1), n(0, 1), n(1, 1));
+                let corner = Corner::classify([b, c, d, e, f, g, hh, i], [n(2, 0), n(0, 2), n(2, 1), n(1, 2)]);
+                if !corner.diagonal {
+                    continue;
+                }
+                let paint = if distance(e, f) <= distance(e, hh) { f } else { hh };
+                for (k, out) in block.iter_mut().enumerate() {
+                    // The output pixel's position in the bottom-right frame: turned back.
+                    let (mut sx, mut sy) = (k % scale, k / scale);
+                    for _ in 0..turn {
+                        (sx, sy) = (sy, scale - 1 - sx);
+                    }
+                    let mut amount = coverage(corner, sx, sy);
+                    if !params.blend {
+                        amount = if amount > 0.5 { 1.0 } else { 0.0 };
+                    }
+                    if amount > 0.0 {
+                        *out = std::array::from_fn(|ch| out[ch] + (paint.value[ch] - out[ch]) * amount);
+                    }
+                }
+            }
+            for (k, value) in block.iter().enumerate() {
+                let (ox, oy) = (x as usize * scale + k % scale, y as usize * scale + k / scale);
+                let i = (oy * ow as usize + ox) * 4;
+                store(value, &mut dst[i..i + 4], premultiplied);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn texel<T: Sample>(px: &[T], premultiplied: bool) -> Texel {
+    let alpha = px[3].to_f32() / T::MAX;
+    let weight = if premultiplied { 1.0 } else { alpha };
+    let value = [px[0].to_f32() * weight, px[1].to_f32() * weight, px[2].to_f32() * weight, px[3].to_f32()];
+    let straight: [f32; 3] = std::array::from_fn(|c| {
+        let v = if premultiplied && alpha > 0.0 { px[c].to_f32() / alpha } else { px[c].to_f32() };
+        plugin_sdk::to_encoded::<T>(v) / T::MAX
+    });
+    let [r, g, b] = straight;
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let key = [48.0 * y, 7.0 * (0.492 * (b - y)), 6.0 * (0.877 * (r - y)), 48.0 * alpha];
+    Texel { value, key }
+}
+
+/// Writes a premultiplied sample in the buffer's alpha convention.
+fn store<T: Sample>(px: &[f32; 4], out: &mut [T], premultiplied: bool) {
+    let scale = px[3] / T::MAX;
+    for c in 0..3 {
+        out[c] = T::from_f32(if premultiplied {
+            px[c]
+        } else if scale > 0.0 {
+            px[c] / scale
+        } else {
+            0.0
+        });
+    }
+    out[3] = T::from_f32(px[3]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing::{self, Output};
+
+    /// Runs an RGBA8 image and returns the output, if one was allocated.
+    fn run(src: &[u8], width: u32, height: u32, params: &str) -> Result<Option<Output<u8>>, PluginError> {
+        testing::run_v2(process, width, height, src, params)
+    }
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    /// A 6x6 staircase: black above the anti-diagonal `x + y < 6`, white below.
+    fn staircase() -> Vec<u8> {
+        (0..36).flat_map(|i| if i % 6 + i / 6 < 6 { BLACK } else { WHITE }).collect()
+    }
+
+    fn px(out: &Output<u8>, x: u32, y: u32) -> &[u8] {
+        let i = ((y * out.width + x) * 4) as usize;
+        &out.data[i..i + 4]
+    }
+
+    #[test]
+    fn test_flat_areas_stay_flat() {
+        let src = [[10u8, 200, 30, 255], [10, 200, 30, 255], [0, 0, 0, 0], [0, 0, 0, 0]].concat();
+        let out = run(&src, 2, 2, r#"{"scale": 3}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (6, 6));
+        for y in 0..6 {
+            let expected: &[u8] = if y < 3 { &[10, 200, 30, 255] } else { &[0, 0, 0, 0] };
+            assert!((0..6).all(|x| px(&out, x, y) == expected), "row {y}: {:?}", out.data);
+        }
+        assert!(matches!(run(&src, 2, 2, r#"{"scale": 1}"#), Err(PluginError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_diagonal_edges_are_smoothed() {
+        // Pixel (2, 3) is black with white to its right and below: its bottom-right corner is
+        // cut, so the last output pixel of its 3x3 block turns white where nearest stays black.
+        let out = run(&staircase(), 6, 6, r#"{"scale": 3, "blend": false}"#).unwrap().unwrap();
+        assert_eq!(px(&out, 8, 11), WHITE);
+        // White (3, 3) has black to its left and above: its top-left corner turns black.
+        assert_eq!(px(&out, 9, 9), BLACK);
+        // Inside the areas, and without blending, nothing else appears.
+        assert_eq!(px(&out, 7, 10), BLACK);
+        assert_eq!(px(&out, 10, 10), WHITE);
+        assert!(out.data.chunks(4).all(|p| p == BLACK || p == WHITE));
+
+        // With blending, the corner pixel at 2x lies half beyond the edge.
+        let out = run(&staircase(), 6, 6, "{}").unwrap().unwrap();
+        let corner = px(&out, 5, 7);
+        assert!(corner[0] > 100 && corner[0] < 155, "{corner:?}");
+    }
+}
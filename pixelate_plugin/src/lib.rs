@@ -0,0 +1,151 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Side of the square blocks in pixels.
+    #[serde(default = "default_block")]
+    block: u32,
+    /// Left edge of the pixelated rectangle.
+    #[serde(default)]
+    x: u32,
+    /// Top edge of the pixelated rectangle.
+    #[serde(default)]
+    y: u32,
+    /// Width of the pixelated rectangle; 0 extends it to the right edge.
+    #[serde(default)]
+    width: u32,
+    /// Height of the pixelated rectangle; 0 extends it to the bottom edge.
+    #[serde(default)]
+    height: u32,
+}
+
+fn default_block() -> u32 {
+    8
+}
+
+const MANIFEST: &CStr = cr#"name = "pixelate_plugin"
+version = "0.1.0"
+description = "Replaces the image or a rectangle of it by blocks of their average color"
+
+[defaults]
+block = 8
+x = 0
+y = 0
+width = 0
+height = 0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Pixelates both formats. It is not local: the block grid starts at the rectangle's corner,
+/// which a tile does not know.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.block == 0 {
+        return Err(PluginError::Invalid("block must be positive"));
+    }
+
+    match pixels {
+        Pixels::Rgba8(buf) => pixelate(width, height, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => pixelate(width, height, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Fills each block of the rectangle, clipped to the image, with the block's average color.
+///
+/// Colors are weighted by alpha, so fully transparent pixels do not tint their block; blocks at
+/// the far edges of the rectangle may be narrower than `block`.
+fn pixelate<T: Sample>(width: u32, height: u32, buf: &mut [T], params: &Params, premultiplied: bool) {
+    let (width, height) = (width as usize, height as usize);
+    let x0 = (params.x as usize).min(width);
+    let y0 = (params.y as usize).min(height);
+    let x1 = if params.width == 0 { width } else { (x0 + params.width as usize).min(width) };
+    let y1 = if params.height == 0 { height } else { (y0 + params.height as usize).min(height) };
+    let block = params.block as usize;
+
+    for by in (y0..y1).step_by(block) {
+        let rows = by..(by + block).min(y1);
+        for bx in (x0..x1).step_by(block) {
+            let columns = bx..(bx + block).min(x1);
+            let pixels = || rows.clone().flat_map(|y| columns.clone().map(move |x| (y * width + x) * 4));
+
+            let mut sum = [0.0f64; 4];
+            for i in pixels() {
+                let a = buf[i + 3].to_f32() as f64;
+                let weight = if premultiplied { 1.0 } else { a };
+                for c in 0..3 {
+                    sum[c] += buf[i + c].to_f32() as f64 * weight;
+                }
+                sum[3] += a;
+            }
+            let count = (rows.len() * columns.len()) as f64;
+            let divisor = if premultiplied { count } else { sum[3] };
+            let average: [f32; 4] = std::array::from_fn(|c| match c {
+                3 => (sum[3] / count) as f32,
+                _ if divisor > 0.0 => (sum[c] / divisor) as f32,
+                _ => 0.0,
+            });
+            for i in pixels() {
+                for c in 0..4 {
+                    buf[i + c] = T::from_f32(average[c]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    #[test]
+    fn test_blocks_average() {
+        // A 3x2 image in blocks of 2: one 2x2 block and a 1x2 block at the right edge.
+        let mut img = [
+            [0u8, 0, 0, 255], [100, 40, 200, 255], [10, 10, 10, 255],
+            [40, 0, 0, 255], [100, 40, 200, 255], [30, 50, 70, 255],
+        ]
+        .concat();
+        run(&mut img, 3, 2, r#"{"block": 2}"#).unwrap();
+        let expected = [[60u8, 20, 100, 255], [60, 20, 100, 255], [20, 30, 40, 255]].concat();
+        assert_eq!(img[..12], expected);
+        assert_eq!(img[12..], expected);
+        assert!(run(&mut img, 3, 2, r#"{"block": 0}"#).is_err());
+    }
+
+    #[test]
+    fn test_rectangle_only() {
+        let src: Vec<u8> = (0..64u8).flat_map(|i| [i * 4, 255 - i * 4, i, 255]).collect();
+        let mut img = src.clone();
+        run(&mut img, 8, 8, r#"{"block": 2, "x": 2, "y": 4, "width": 4, "height": 2}"#).unwrap();
+        for (i, (a, b)) in img.chunks(4).zip(src.chunks(4)).enumerate() {
+            let (x, y) = (i % 8, i / 8);
+            let inside = (2..6).contains(&x) && (4..6).contains(&y);
+            assert_eq!(a == b, !inside, "({x}, {y})");
+        }
+        // Blocks start at the rectangle's corner.
+        assert_eq!(img[(4 * 8 + 2) * 4..][..8], img[(5 * 8 + 2) * 4..][..8]);
+        assert_ne!(img[(4 * 8 + 3) * 4..][..4], img[(4 * 8 + 4) * 4..][..4]);
+    }
+
+    #[test]
+    fn test_transparent_pixels_do_not_tint() {
+        let mut img = [255u8, 0, 0, 255, 0, 255, 0, 0];
+        run(&mut img, 2, 1, r#"{"block": 2}"#).unwrap();
+        assert_eq!(img, [255, 0, 0, 128, 255, 0, 0, 128]);
+    }
+}
@@ -0,0 +1,474 @@
+//! Entry points generated by [`export_plugin!`](crate::export_plugin).
+
+use std::fmt;
+use std::os::raw::c_char;
+
+use serde::de::DeserializeOwned;
+
+use crate::{CallContext, Capabilities, OutputBuffer, ParamsError, Pixels, PixelsRef, Sample};
+
+/// The image handed to an in-place plugin, to be modified where it lies.
+#[derive(Debug)]
+pub struct Image<'a> {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// `width * height * 4` values in the call's pixel format.
+    pub pixels: Pixels<'a>,
+}
+
+/// The read-only input of a geometry-changing plugin, or an additional input image.
+#[derive(Debug, Clone, Copy)]
+pub struct ImageRef<'a> {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// `width * height * 4` values in the call's pixel format.
+    pub pixels: PixelsRef<'a>,
+}
+
+impl<'a> ImageRef<'a> {
+    /// Returns the pixel values if they are of type `T`.
+    pub fn samples<T: Sample>(&self) -> Option<&'a [T]> {
+        T::from_pixels_ref(self.pixels)
+    }
+
+    /// Returns the pixel values of all `images` if they are of type `T`.
+    pub fn all_samples<T: Sample>(images: &[Self]) -> Option<Vec<&'a [T]>> {
+        images.iter().map(Self::samples).collect()
+    }
+}
+
+/// Allocates the output of a geometry-changing plugin through the host.
+#[derive(Debug)]
+pub struct Allocator<'a> {
+    output: &'a mut OutputBuffer,
+    pixel_format: u32,
+}
+
+impl<'a> Allocator<'a> {
+    /// Wraps the call's `output`, whose buffers hold `pixel_format` values.
+    ///
+    /// # Safety
+    /// `output` must be the allocator of the current `process_image_v2` call, in `pixel_format`.
+    pub(crate) unsafe fn new(output: &'a mut OutputBuffer, pixel_format: u32) -> Self {
+        Self { output, pixel_format }
+    }
+
+    /// Allocates a zeroed `width` x `height` output and returns its values.
+    ///
+    /// Fails with [`PluginError::Format`] if `T` is not the sample type of the call and with
+    /// [`PluginError::Alloc`] if the host refused the size. Allocating again replaces the output.
+    pub fn alloc<T: Sample>(&mut self, width: u32, height: u32) -> Result<&mut [T], PluginError> {
+        if T::PIXEL_FORMAT != self.pixel_format {
+            return Err(PluginError::Format);
+        }
+        // SAFETY: `new` guarantees this call's allocator in the format of `T`; the slice borrows
+        // `self`, so it is gone before the next `alloc` and before the call returns.
+        unsafe { self.output.alloc::<T>(width, height) }.ok_or(PluginError::Alloc)
+    }
+}
+
+/// Why a plugin call failed. The host only sees a non-zero status; the variants are for tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginError {
+    /// The params could not be parsed.
+    Params(ParamsError),
+    /// The buffer is null or in a pixel format the plugin did not declare.
+    Format,
+    /// The params or inputs cannot be applied, for the given reason.
+    Invalid(&'static str),
+    /// The host refused to allocate the output buffer.
+    Alloc,
+    /// A file named in the params could not be read or written.
+    Io(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Params(e) => e.fmt(f),
+            Self::Format => write!(f, "unsupported pixel buffer"),
+            Self::Invalid(reason) => write!(f, "{reason}"),
+            Self::Alloc => write!(f, "the host refused the output buffer"),
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+impl From<ParamsError> for PluginError {
+    fn from(e: ParamsError) -> Self {
+        Self::Params(e)
+    }
+}
+
+impl From<std::io::Error> for PluginError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+/// An in-place plugin: modifies the image according to its params.
+pub type InPlaceFn<P> = fn(Image<'_>, &P, &CallContext) -> Result<(), PluginError>;
+
+/// A geometry-changing plugin: writes its result to a buffer from the [`Allocator`], or leaves the
+/// image unchanged by not allocating one.
+pub type GeometryFn<P> = fn(ImageRef<'_>, &mut Allocator<'_>, &P, &CallContext) -> Result<(), PluginError>;
+
+/// Runs `f` on a rayon pool limited to the context's [`max_threads`](CallContext::max_threads),
+/// or on the global pool if there is no limit.
+#[cfg(feature = "rayon")]
+pub fn install<R: Send>(ctx: &CallContext, f: impl FnOnce() -> R + Send) -> Result<R, PluginError> {
+    match ctx.max_threads() {
+        0 => Ok(f()),
+        threads => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build()
+            .map(|pool| pool.install(f))
+            .map_err(|_| PluginError::Invalid("cannot start the thread pool")),
+    }
+}
+
+/// Converts a result to the status returned over FFI.
+fn status(result: Result<(), PluginError>) -> u32 {
+    u32::from(result.is_err())
+}
+
+/// Body of the generated `process_image_ctx`.
+///
+/// # Safety
+/// The arguments must satisfy the `process_image_ctx` FFI contract: `ctx` null or valid, `data`
+/// pointing to `width * height * 4` writable, unaliased elements of the context's pixel format,
+/// and `params` null or a NUL-terminated string, all for the duration of the call.
+#[doc(hidden)]
+pub unsafe fn process_in_place<P: DeserializeOwned>(
+    formats: u32,
+    process: InPlaceFn<P>,
+    ctx: *const CallContext,
+    width: u32,
+    height: u32,
+    data: *mut u8,
+    params: *const c_char,
+) -> u32 {
+    let fallback = CallContext::new(0);
+    // SAFETY: the caller guarantees `ctx` is null or valid for the call.
+    let ctx = unsafe { CallContext::from_ptr(ctx) }.unwrap_or(&fallback);
+    let run = || {
+        // SAFETY: the caller guarantees `params` is null or a valid NUL-terminated string.
+        let params = unsafe { crate::parse_params::<P>(params) }?;
+        let format = ctx.pixel_format();
+        if format >= 32 || formats & (1 << format) == 0 {
+            return Err(PluginError::Format);
+        }
+        // SAFETY: the caller guarantees `data` holds the call's pixels, exclusively ours for the call.
+        let pixels = unsafe { Pixels::from_raw(format, width, height, data) }.ok_or(PluginError::Format)?;
+        process(Image { width, height, pixels }, &params, ctx)
+    };
+    status(run())
+}
+
+/// Body of the generated `process_image_v2`.
+///
+/// # Safety
+/// The arguments must satisfy the `process_image_v2` FFI contract: `ctx` null or valid, `data`
+/// pointing to `width * height * 4` readable elements of the context's pixel format, `output`
+/// the host's allocator for this call, and `params` null or a NUL-terminated string.
+#[doc(hidden)]
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn process_geometry<P: DeserializeOwned>(
+    formats: u32,
+    process: GeometryFn<P>,
+    ctx: *const CallContext,
+    width: u32,
+    height: u32,
+    data: *const u8,
+    output: *mut OutputBuffer,
+    params: *const c_char,
+) -> u32 {
+    let fallback = CallContext::new(0);
+    // SAFETY: the caller guarantees `ctx` is null or valid for the call.
+    let ctx = unsafe { CallContext::from_ptr(ctx) }.unwrap_or(&fallback);
+    let run = || {
+        // SAFETY: the caller guarantees `params` is null or a valid NUL-terminated string.
+        let params = unsafe { crate::parse_params::<P>(params) }?;
+        let format = ctx.pixel_format();
+        if format >= 32 || formats & (1 << format) == 0 {
+            return Err(PluginError::Format);
+        }
+        // SAFETY: the caller guarantees `output` is null or this call's allocator.
+        let output = unsafe { output.as_mut() }.ok_or(PluginError::Alloc)?;
+        // SAFETY: the caller guarantees `data` holds the call's pixels, unmodified during the call.
+        let pixels = unsafe { PixelsRef::from_raw(format, width, height, data) }.ok_or(PluginError::Format)?;
+        // SAFETY: the caller guarantees `output` is this call's allocator, in the call's format.
+        let mut output = unsafe { Allocator::new(output, format) };
+        process(ImageRef { width, height, pixels }, &mut output, &params, ctx)
+    };
+    status(run())
+}
+
+/// Body of the generated `plugin_capabilities`.
+///
+/// # Safety
+/// `params` must be null or a NUL-terminated string and `caps` null or writable for the call.
+#[doc(hidden)]
+pub unsafe fn capabilities<P: DeserializeOwned>(
+    formats: u32,
+    halo: fn(&P) -> Option<u32>,
+    params: *const c_char,
+    caps: *mut Capabilities,
+) -> u32 {
+    // SAFETY: the caller guarantees `caps` is null or writable.
+    let Some(caps) = (unsafe { caps.as_mut() }) else {
+        return 1;
+    };
+    caps.pixel_formats = formats;
+    // SAFETY: the caller guarantees `params` is null or a valid NUL-terminated string.
+    if let Some(halo) = unsafe { crate::parse_params::<P>(params) }.ok().as_ref().and_then(halo) {
+        caps.set_local(halo);
+    }
+    0
+}
+
+/// Exports a plugin's C entry points from its manifest, params type and processing function.
+///
+/// ```ignore
+/// const MANIFEST: &CStr = cr#"name = "invert_plugin" ... [defaults] ..."#;
+///
+/// plugin_sdk::export_plugin! {
+///     manifest: MANIFEST,
+///     params: Params,
+///     process: invert,
+///     halo: |_| Some(0),
+/// }
+/// ```
+///
+/// `process` is an [`InPlaceFn`] and yields `process_image` and `process_image_ctx`; a
+/// geometry-changing plugin gives a [`GeometryFn`] as `process_v2` instead and gets
+/// `process_image_v2`. Both also export `plugin_manifest` and `plugin_capabilities`.
+///
+/// - `formats`: the `FORMAT_MASK_*` bits accepted, RGBA8 and RGBA32F if left out. Calls in other
+///   formats fail before `process` runs.
+/// - `halo`: `fn(&Params) -> Option<u32>` declaring the plugin local with the returned halo for
+///   the given params; without it the plugin is never local. Geometry-changing plugins are not.
+/// - `required`: TOML giving the params without a default, for the generated manifest test.
+///
+/// Params are parsed before `process` is called and a call without a context gets
+/// [`CallContext::new(0)`](CallContext::new). Test builds also get a test checking that the
+/// manifest `defaults` parse as the params type.
+#[macro_export]
+macro_rules! export_plugin {
+    (
+        manifest: $manifest:expr,
+        params: $params:ty,
+        process: $process:expr
+        $(, formats: $formats:expr)?
+        $(, halo: $halo:expr)?
+        $(, required: $required:expr)?
+        $(,)?
+    ) => {
+        $crate::export_plugin!(@common $manifest, $params, $crate::__or!($($formats)?; $crate::FORMAT_MASK_ALL),
+            $crate::__or!($($halo)?; |_| None), $crate::__or!($($required)?; ""));
+
+        /// Entry point for hosts without a call context.
+        #[unsafe(no_mangle)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn process_image(
+            width: u32,
+            height: u32,
+            rgba_data: *mut u8,
+            params: *const ::std::os::raw::c_char,
+        ) -> u32 {
+            process_image_ctx(::std::ptr::null(), width, height, rgba_data, params)
+        }
+
+        /// Context-aware entry point.
+        #[unsafe(no_mangle)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn process_image_ctx(
+            ctx: *const $crate::CallContext,
+            width: u32,
+            height: u32,
+            rgba_data: *mut u8,
+            params: *const ::std::os::raw::c_char,
+        ) -> u32 {
+            let process: $crate::InPlaceFn<$params> = $process;
+            // SAFETY: the arguments come straight from the host, which upholds the FFI contract.
+            unsafe { $crate::export::process_in_place(PLUGIN_FORMATS, process, ctx, width, height, rgba_data, params) }
+        }
+    };
+    (
+        manifest: $manifest:expr,
+        params: $params:ty,
+        process_v2: $process:expr
+        $(, formats: $formats:expr)?
+        $(, required: $required:expr)?
+        $(,)?
+    ) => {
+        $crate::export_plugin!(@common $manifest, $params, $crate::__or!($($formats)?; $crate::FORMAT_MASK_ALL),
+            |_| None, $crate::__or!($($required)?; ""));
+
+        /// Geometry-changing entry point.
+        #[unsafe(no_mangle)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn process_image_v2(
+            ctx: *const $crate::CallContext,
+            width: u32,
+            height: u32,
+            rgba_data: *const u8,
+            output: *mut $crate::OutputBuffer,
+            params: *const ::std::os::raw::c_char,
+        ) -> u32 {
+            let process: $crate::GeometryFn<$params> = $process;
+            // SAFETY: the arguments come straight from the host, which upholds the FFI contract.
+            unsafe {
+                $crate::export::process_geometry(PLUGIN_FORMATS, process, ctx, width, height, rgba_data, output, params)
+            }
+        }
+    };
+    (@common $manifest:expr, $params:ty, $formats:expr, $halo:expr, $required:expr) => {
+        const PLUGIN_FORMATS: u32 = $formats;
+
+        /// Embedded plugin manifest; the host applies `defaults` before the params file.
+        #[unsafe(no_mangle)]
+        pub extern "C" fn plugin_manifest() -> *const ::std::os::raw::c_char {
+            $manifest.as_ptr()
+        }
+
+        /// Reports the accepted pixel formats and, if the params make the plugin local, its halo.
+        #[unsafe(no_mangle)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        pub extern "C" fn plugin_capabilities(
+            params: *const ::std::os::raw::c_char,
+            caps: *mut $crate::Capabilities,
+        ) -> u32 {
+            let halo: fn(&$params) -> Option<u32> = $halo;
+            // SAFETY: the arguments come straight from the host, which upholds the FFI contract.
+            unsafe { $crate::export::capabilities(PLUGIN_FORMATS, halo, params, caps) }
+        }
+
+        #[cfg(test)]
+        #[test]
+        fn test_manifest_defaults_parse() {
+            $crate::testing::assert_manifest_defaults_parse_with::<$params>($manifest, $required);
+        }
+    };
+}
+
+/// The first argument if given, else the second.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __or {
+    ($value:expr; $default:expr) => {
+        $value
+    };
+    (; $default:expr) => {
+        $default
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ALPHA_PREMULTIPLIED, FORMAT_MASK_RGBA8, PIXEL_FORMAT_RGBA32F, Sample, testing};
+    use std::ffi::{CStr, CString};
+
+    #[derive(serde::Deserialize)]
+    struct Params {
+        add: u8,
+        #[serde(default)]
+        reach: Option<u32>,
+    }
+
+    const MANIFEST: &CStr = c"name = \"add_plugin\"\n[defaults]\nadd = 1\n";
+
+    /// Adds `add` to every value of an RGBA8 buffer, or fails on premultiplied data.
+    fn add(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+        if ctx.premultiplied() {
+            return Err(PluginError::Invalid("premultiplied"));
+        }
+        if let Pixels::Rgba8(buf) = image.pixels {
+            buf.iter_mut().for_each(|v| *v = v.saturating_add(params.add));
+        }
+        Ok(())
+    }
+
+    crate::export_plugin! {
+        manifest: MANIFEST,
+        params: Params,
+        process: add,
+        formats: FORMAT_MASK_RGBA8,
+        halo: |p| p.reach,
+    }
+
+    #[test]
+    fn test_generated_entry_points() {
+        let mut buf = [1u8, 2, 3, 4];
+        let params = CString::new(r#"{"add": 2}"#).unwrap();
+        assert_eq!(process_image(1, 1, buf.as_mut_ptr(), params.as_ptr()), 0);
+        assert_eq!(buf, [3, 4, 5, 6]);
+        assert_eq!(process_image(1, 1, buf.as_mut_ptr(), std::ptr::null()), 1);
+        assert_eq!(process_image(1, 1, std::ptr::null_mut(), params.as_ptr()), 1);
+
+        let mut ctx = CallContext { alpha_mode: ALPHA_PREMULTIPLIED, ..CallContext::new(0) };
+        assert_eq!(process_image_ctx(&ctx, 1, 1, buf.as_mut_ptr(), params.as_ptr()), 1);
+        ctx = CallContext { pixel_format: PIXEL_FORMAT_RGBA32F, ..CallContext::new(0) };
+        let mut float = [0.0f32; 4];
+        assert_eq!(process_image_ctx(&ctx, 1, 1, float.as_mut_ptr().cast(), params.as_ptr()), 1);
+
+        let mut caps = Capabilities::default();
+        let local = CString::new(r#"{"add": 0, "reach": 3}"#).unwrap();
+        assert_eq!(plugin_capabilities(local.as_ptr(), &mut caps), 0);
+        assert_eq!((caps.pixel_formats, caps.local_halo()), (FORMAT_MASK_RGBA8, Some(3)));
+        let mut caps = Capabilities::default();
+        assert_eq!(plugin_capabilities(params.as_ptr(), &mut caps), 0);
+        assert_eq!(caps.local_halo(), None);
+        // SAFETY: `plugin_manifest` returns `MANIFEST`.
+        assert_eq!(unsafe { CStr::from_ptr(plugin_manifest()) }, MANIFEST);
+    }
+
+    /// Doubles the image width by repeating every pixel, or leaves it unchanged for `add == 0`.
+    fn widen(
+        image: ImageRef<'_>,
+        output: &mut Allocator<'_>,
+        params: &Params,
+        _: &CallContext,
+    ) -> Result<(), PluginError> {
+        if params.add == 0 {
+            return Ok(());
+        }
+        let PixelsRef::Rgba8(src) = image.pixels else {
+            return Err(PluginError::Format);
+        };
+        let dst = output.alloc::<u8>(image.width * 2, image.height)?;
+        for (px, out) in src.chunks_exact(4).zip(dst.chunks_exact_mut(8)) {
+            out[..4].copy_from_slice(px);
+            out[4..].copy_from_slice(px);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_testing_helpers() {
+        let mut buf = [250u8, 0, 0, 255];
+        assert_eq!(testing::run(add, 1, 1, &mut buf, r#"{"add": 10}"#), Ok(()));
+        assert_eq!(buf, [255, 10, 10, 255]);
+        assert!(matches!(testing::run(add, 1, 1, &mut buf, "{}"), Err(PluginError::Params(_))));
+
+        let out = testing::run_v2(widen, 2, 1, &[1u8, 2, 3, 4, 5, 6, 7, 8], r#"{"add": 1}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (4, 1));
+        assert_eq!(out.data, [1, 2, 3, 4, 1, 2, 3, 4, 5, 6, 7, 8, 5, 6, 7, 8]);
+        assert_eq!(testing::run_v2(widen, 1, 1, &[0u8; 4], r#"{"add": 0}"#), Ok(None));
+        assert_eq!(testing::run_v2(widen, 1, 1, &[0.0f32; 4], r#"{"add": 1}"#), Err(PluginError::Format));
+        assert_eq!(<f32 as Sample>::PIXEL_FORMAT, PIXEL_FORMAT_RGBA32F);
+        let image = ImageRef { width: 1, height: 1, pixels: PixelsRef::Rgba8(&[1, 2, 3, 4]) };
+        assert_eq!((image.samples::<u8>(), image.samples::<f32>()), (Some(&[1, 2, 3, 4][..]), None));
+
+        let bare = c"name = \"add_plugin\"\n[defaults]\nreach = 2\n";
+        testing::assert_manifest_defaults_parse_with::<Params>(bare, "add = 3");
+        assert!(std::panic::catch_unwind(|| testing::assert_manifest_defaults_parse::<Params>(bare)).is_err());
+    }
+}
@@ -0,0 +1,850 @@
+#![deny(missing_docs)]
+
+//! Shared FFI types for image processing plugins and the host.
+
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer};
+use std::ffi::CStr;
+use std::fmt;
+use std::str::FromStr;
+use std::mem::offset_of;
+use std::os::raw::{c_char, c_void};
+
+#[doc(hidden)]
+pub mod export;
+pub mod testing;
+
+#[cfg(feature = "rayon")]
+pub use export::install;
+pub use export::{Allocator, GeometryFn, Image, ImageRef, InPlaceFn, PluginError};
+
+/// 8-bit sRGB-encoded RGBA, four `u8` per pixel.
+pub const PIXEL_FORMAT_RGBA8: u32 = 0;
+
+/// Linear-light RGBA, four `f32` per pixel, nominally in `[0, 1]` (straight alpha).
+pub const PIXEL_FORMAT_RGBA32F: u32 = 1;
+
+/// Bit in [`Capabilities::pixel_formats`] for [`PIXEL_FORMAT_RGBA8`].
+pub const FORMAT_MASK_RGBA8: u32 = 1 << PIXEL_FORMAT_RGBA8;
+
+/// Bit in [`Capabilities::pixel_formats`] for [`PIXEL_FORMAT_RGBA32F`].
+pub const FORMAT_MASK_RGBA32F: u32 = 1 << PIXEL_FORMAT_RGBA32F;
+
+/// Both pixel formats.
+pub const FORMAT_MASK_ALL: u32 = FORMAT_MASK_RGBA8 | FORMAT_MASK_RGBA32F;
+
+/// Bit in [`Capabilities::flags`]: the plugin wants the host's GPU device.
+pub const CAP_GPU: u32 = 1 << 0;
+
+/// Bit in [`Capabilities::flags`]: the plugin is local. Running it over any sub-rectangle
+/// of an image yields the matching part of the full-image result, except for a border of
+/// [`Capabilities::halo`] pixels along edges that are not image edges.
+pub const CAP_LOCAL: u32 = 1 << 1;
+
+/// Color channels are independent of alpha.
+pub const ALPHA_STRAIGHT: u32 = 0;
+
+/// Color channels have already been multiplied by alpha.
+pub const ALPHA_PREMULTIPLIED: u32 = 1;
+
+/// Per-call context passed from the host to `process_image_ctx`.
+///
+/// Fields are only ever appended. A plugin built against a newer SDK must not
+/// read fields that lie beyond `struct_size` bytes, since an older host does
+/// not provide them.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CallContext {
+    /// Size of this struct in bytes, as known by the host.
+    pub struct_size: u32,
+    /// Seed for deterministic pseudo-random generation.
+    ///
+    /// Stochastic plugins (noise, grain, dithering) must derive all randomness
+    /// from this value so that runs with the same seed are reproducible.
+    pub seed: u64,
+    /// Format of the pixel buffer, one of the `PIXEL_FORMAT_*` constants.
+    ///
+    /// The host only passes formats the plugin declared in its [`Capabilities`].
+    pub pixel_format: u32,
+    /// Alpha convention of the buffer, [`ALPHA_STRAIGHT`] or [`ALPHA_PREMULTIPLIED`].
+    pub alpha_mode: u32,
+    /// Maximum number of threads the plugin should use; 0 means no limit.
+    pub max_threads: u32,
+    /// The host's `wgpu::Device`, or null if the plugin did not request [`CAP_GPU`]
+    /// or no device is available.
+    ///
+    /// Only usable by plugins built with the same compiler and `wgpu` version as the host.
+    pub gpu_device: *const c_void,
+    /// The `wgpu::Queue` belonging to `gpu_device`, or null alongside it.
+    pub gpu_queue: *const c_void,
+    /// Pixels of the secondary input image (`--input2`), or null if there is none.
+    ///
+    /// Holds `input2_width * input2_height * 4` values in the same pixel format and alpha
+    /// convention as the main buffer. It is read-only and valid for the duration of the call.
+    pub input2_data: *const u8,
+    /// Width of the secondary input in pixels; 0 without one.
+    pub input2_width: u32,
+    /// Height of the secondary input in pixels; 0 without one.
+    pub input2_height: u32,
+    /// The additional input images (`--inputs`), `input_count` of them, or null if there are none.
+    ///
+    /// Their pixels are in the same pixel format and alpha convention as the main buffer. The
+    /// array and the pixels are read-only and valid for the duration of the call.
+    pub inputs: *const InputImage,
+    /// Number of entries in `inputs`.
+    pub input_count: u32,
+}
+
+/// One of the additional input images of a [`CallContext`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InputImage {
+    /// Pixels, `width * height * 4` values.
+    pub data: *const u8,
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+}
+
+impl CallContext {
+    /// Creates a context with the given seed and `struct_size` set for this SDK version.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            struct_size: std::mem::size_of::<Self>() as u32,
+            seed,
+            pixel_format: PIXEL_FORMAT_RGBA8,
+            alpha_mode: ALPHA_STRAIGHT,
+            max_threads: 0,
+            gpu_device: std::ptr::null(),
+            gpu_queue: std::ptr::null(),
+            input2_data: std::ptr::null(),
+            input2_width: 0,
+            input2_height: 0,
+            inputs: std::ptr::null(),
+            input_count: 0,
+        }
+    }
+
+    /// Returns the buffer's pixel format, defaulting to RGBA8 for hosts that predate the field.
+    pub fn pixel_format(&self) -> u32 {
+        if self.has_field(offset_of!(Self, pixel_format), std::mem::size_of::<u32>()) {
+            self.pixel_format
+        } else {
+            PIXEL_FORMAT_RGBA8
+        }
+    }
+
+    /// Returns the buffer's alpha convention, defaulting to straight for hosts that predate the field.
+    pub fn alpha_mode(&self) -> u32 {
+        if self.has_field(offset_of!(Self, alpha_mode), std::mem::size_of::<u32>()) {
+            self.alpha_mode
+        } else {
+            ALPHA_STRAIGHT
+        }
+    }
+
+    /// Returns `true` if the color channels are premultiplied by alpha.
+    pub fn premultiplied(&self) -> bool {
+        self.alpha_mode() == ALPHA_PREMULTIPLIED
+    }
+
+    /// Returns the thread limit (0 for none), defaulting to no limit for hosts that predate the field.
+    pub fn max_threads(&self) -> u32 {
+        if self.has_field(offset_of!(Self, max_threads), std::mem::size_of::<u32>()) {
+            self.max_threads
+        } else {
+            0
+        }
+    }
+
+    /// Returns the host GPU device and queue pointers, or `None` if the host did not provide them.
+    pub fn gpu(&self) -> Option<(*const c_void, *const c_void)> {
+        let provided = self.has_field(offset_of!(Self, gpu_queue), std::mem::size_of::<*const c_void>());
+        (provided && !self.gpu_device.is_null() && !self.gpu_queue.is_null()).then_some((self.gpu_device, self.gpu_queue))
+    }
+
+    /// Returns the width, height and pixels of the secondary input, or `None` if the host passed
+    /// none or predates the fields.
+    ///
+    /// View the pixels with [`PixelsRef::from_raw`] and the call's [`pixel_format`](Self::pixel_format).
+    pub fn input2(&self) -> Option<(u32, u32, *const u8)> {
+        let provided = self.has_field(offset_of!(Self, input2_height), std::mem::size_of::<u32>());
+        (provided && !self.input2_data.is_null()).then_some((self.input2_width, self.input2_height, self.input2_data))
+    }
+
+    /// Returns the additional input images, empty if the host passed none or predates the fields.
+    ///
+    /// View each image's pixels with [`PixelsRef::from_raw`] and the call's
+    /// [`pixel_format`](Self::pixel_format).
+    ///
+    /// # Safety
+    /// `inputs` must be null or point to `input_count` valid entries that outlive `'a`, as the
+    /// host guarantees for the contexts it passes.
+    pub unsafe fn inputs<'a>(&self) -> &'a [InputImage] {
+        let provided = self.has_field(offset_of!(Self, input_count), std::mem::size_of::<u32>());
+        if !provided || self.inputs.is_null() {
+            return &[];
+        }
+        // SAFETY: the caller guarantees `inputs` points to `input_count` entries valid for `'a`.
+        unsafe { std::slice::from_raw_parts(self.inputs, self.input_count as usize) }
+    }
+
+    /// Returns the secondary input as an image in the call's pixel format, or `None` if there is
+    /// none or it cannot be viewed.
+    ///
+    /// # Safety
+    /// The input pointers must be valid for `'a` as described on the fields, as the host
+    /// guarantees for the contexts it passes.
+    pub unsafe fn input2_image<'a>(&self) -> Option<ImageRef<'a>> {
+        let (width, height, data) = self.input2()?;
+        // SAFETY: the caller guarantees `input2_data` holds the secondary input's pixels for `'a`.
+        let pixels = unsafe { PixelsRef::from_raw(self.pixel_format(), width, height, data) }?;
+        Some(ImageRef { width, height, pixels })
+    }
+
+    /// Returns the additional input images in the call's pixel format, or `None` if one of them
+    /// cannot be viewed.
+    ///
+    /// # Safety
+    /// As for [`inputs`](Self::inputs), and each entry's pixels must be valid for `'a`.
+    pub unsafe fn input_images<'a>(&self) -> Option<Vec<ImageRef<'a>>> {
+        // SAFETY: the caller guarantees the entries and their pixels are valid for `'a`.
+        unsafe { self.inputs() }
+            .iter()
+            .map(|input| {
+                // SAFETY: as above.
+                let pixels = unsafe { PixelsRef::from_raw(self.pixel_format(), input.width, input.height, input.data) };
+                pixels.map(|pixels| ImageRef { width: input.width, height: input.height, pixels })
+            })
+            .collect()
+    }
+
+    fn has_field(&self, offset: usize, size: usize) -> bool {
+        self.struct_size as usize >= offset + size
+    }
+
+    /// Borrows a context from a raw pointer received over FFI.
+    ///
+    /// Returns `None` if `ctx` is null.
+    ///
+    /// # Safety
+    /// `ctx` must be null or point to a valid `CallContext` that outlives `'a`.
+    pub unsafe fn from_ptr<'a>(ctx: *const CallContext) -> Option<&'a CallContext> {
+        // SAFETY:
+        // - The caller guarantees `ctx` is either null or valid for reads for `'a`.
+        // - `as_ref` handles the null case by returning `None`.
+        unsafe { ctx.as_ref() }
+    }
+}
+
+/// Optional FFI function exported as `plugin_capabilities`.
+///
+/// Receives the resolved params (a NUL-terminated string, possibly null) and
+/// fills `caps`, which the host pre-initializes with defaults. Returns 0 on
+/// success; any other value makes the host fall back to the defaults.
+pub type CapabilitiesFn = unsafe extern "C" fn(params: *const c_char, caps: *mut Capabilities) -> u32;
+
+/// What a plugin supports, reported through `plugin_capabilities`.
+///
+/// Like [`CallContext`], fields are only ever appended; check `struct_size`
+/// before writing fields added after the first version.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    /// Size of this struct in bytes, as known by the host.
+    pub struct_size: u32,
+    /// Bit mask of accepted pixel formats (`FORMAT_MASK_*`).
+    pub pixel_formats: u32,
+    /// Bit mask of requested host services and declared properties (`CAP_*`).
+    pub flags: u32,
+    /// With [`CAP_LOCAL`], how far in pixels an output pixel's inputs may lie from it.
+    pub halo: u32,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            struct_size: std::mem::size_of::<Self>() as u32,
+            pixel_formats: FORMAT_MASK_RGBA8,
+            flags: 0,
+            halo: 0,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Returns `true` if the plugin accepts `pixel_format`.
+    pub fn supports(&self, pixel_format: u32) -> bool {
+        pixel_format < 32 && self.pixel_formats & (1 << pixel_format) != 0
+    }
+
+    /// Sets `flag` if the host's struct has room for `flags`; returns whether it was set.
+    pub fn request(&mut self, flag: u32) -> bool {
+        if (self.struct_size as usize) < offset_of!(Self, flags) + std::mem::size_of::<u32>() {
+            return false;
+        }
+        self.flags |= flag;
+        true
+    }
+
+    /// Returns `true` if `flag` was requested.
+    pub fn requested(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Declares the plugin local ([`CAP_LOCAL`]) with the given halo, if the host's struct has
+    /// room for `halo`; returns whether it was declared.
+    pub fn set_local(&mut self, halo: u32) -> bool {
+        if (self.struct_size as usize) < offset_of!(Self, halo) + std::mem::size_of::<u32>() {
+            return false;
+        }
+        self.flags |= CAP_LOCAL;
+        self.halo = halo;
+        true
+    }
+
+    /// Returns the halo if the plugin declared itself local.
+    pub fn local_halo(&self) -> Option<u32> {
+        self.requested(CAP_LOCAL).then_some(self.halo)
+    }
+}
+
+/// Mutable view of the pixel buffer handed to a plugin.
+#[derive(Debug)]
+pub enum Pixels<'a> {
+    /// [`PIXEL_FORMAT_RGBA8`] data.
+    Rgba8(&'a mut [u8]),
+    /// [`PIXEL_FORMAT_RGBA32F`] data.
+    Rgba32F(&'a mut [f32]),
+}
+
+impl Pixels<'_> {
+    /// Builds a typed view over the raw buffer passed to `process_image_ctx`.
+    ///
+    /// Returns `None` if `data` is null, the format is unknown, or the length
+    /// overflows `usize`.
+    ///
+    /// # Safety
+    /// `data` must point to `width * height * 4` writable elements of the type
+    /// selected by `pixel_format`, suitably aligned, valid and not aliased while
+    /// the returned view is alive.
+    pub unsafe fn from_raw(pixel_format: u32, width: u32, height: u32, data: *mut u8) -> Option<Self> {
+        if data.is_null() {
+            return None;
+        }
+        let len = (width as usize).checked_mul(height as usize)?.checked_mul(4)?;
+
+        // SAFETY:
+        // - `data` is non-null (checked above).
+        // - The caller guarantees `len` elements of the selected type are valid, writable,
+        //   aligned and exclusively ours for the lifetime of the view.
+        unsafe {
+            match pixel_format {
+                PIXEL_FORMAT_RGBA8 => Some(Self::Rgba8(std::slice::from_raw_parts_mut(data, len))),
+                PIXEL_FORMAT_RGBA32F => Some(Self::Rgba32F(std::slice::from_raw_parts_mut(data.cast::<f32>(), len))),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Read-only view of the input buffer handed to `process_image_v2`.
+#[derive(Debug, Clone, Copy)]
+pub enum PixelsRef<'a> {
+    /// [`PIXEL_FORMAT_RGBA8`] data.
+    Rgba8(&'a [u8]),
+    /// [`PIXEL_FORMAT_RGBA32F`] data.
+    Rgba32F(&'a [f32]),
+}
+
+impl PixelsRef<'_> {
+    /// Builds a typed view over the raw input passed to `process_image_v2`.
+    ///
+    /// Returns `None` if `data` is null, the format is unknown, or the length
+    /// overflows `usize`.
+    ///
+    /// # Safety
+    /// `data` must point to `width * height * 4` readable elements of the type
+    /// selected by `pixel_format`, suitably aligned, valid and not written to while
+    /// the returned view is alive.
+    pub unsafe fn from_raw(pixel_format: u32, width: u32, height: u32, data: *const u8) -> Option<Self> {
+        if data.is_null() {
+            return None;
+        }
+        let len = (width as usize).checked_mul(height as usize)?.checked_mul(4)?;
+
+        // SAFETY:
+        // - `data` is non-null (checked above).
+        // - The caller guarantees `len` elements of the selected type are valid, readable,
+        //   aligned and not mutated for the lifetime of the view.
+        unsafe {
+            match pixel_format {
+                PIXEL_FORMAT_RGBA8 => Some(Self::Rgba8(std::slice::from_raw_parts(data, len))),
+                PIXEL_FORMAT_RGBA32F => Some(Self::Rgba32F(std::slice::from_raw_parts(data.cast::<f32>(), len))),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Host allocator for the result of a plugin that changes the image size.
+///
+/// Passed to `process_image_v2`, whose input is read-only: the plugin decides the output
+/// size, asks the host for a buffer of that size and writes its result there.
+#[repr(C)]
+#[derive(Debug)]
+pub struct OutputBuffer {
+    /// Opaque host state, passed back to `alloc`.
+    pub host: *mut c_void,
+    /// Returns a zeroed `width * height * 4` element buffer in the call's pixel format, or null
+    /// if the size is zero or too large. The buffer stays valid until the call returns; calling
+    /// `alloc` again replaces it, and the earlier buffer must no longer be used.
+    pub alloc: unsafe extern "C" fn(host: *mut c_void, width: u32, height: u32) -> *mut u8,
+}
+
+impl OutputBuffer {
+    /// Allocates the output through the host and returns it as a slice of `T`.
+    ///
+    /// Returns `None` if the host refused the size.
+    ///
+    /// # Safety
+    /// - `self` must be the output passed to the current `process_image_v2` call.
+    /// - `T` must be the element type of the call's pixel format (`u8` for RGBA8, `f32` for RGBA32F).
+    /// - The slice must not be used after the call returns or after `alloc` is called again.
+    pub unsafe fn alloc<'a, T: Sample>(&mut self, width: u32, height: u32) -> Option<&'a mut [T]> {
+        let len = (width as usize).checked_mul(height as usize)?.checked_mul(4)?;
+        // SAFETY: the caller guarantees `self` is the host's live allocator for this call.
+        let ptr = unsafe { (self.alloc)(self.host, width, height) };
+        if ptr.is_null() {
+            return None;
+        }
+        // SAFETY:
+        // - The host returned a non-null buffer of `len` elements of the call's pixel format,
+        //   which the caller guarantees is `T`, allocated as such and therefore aligned.
+        // - The buffer is exclusively the plugin's until the call returns or `alloc` is called
+        //   again, which the caller guarantees outlives the use of the slice.
+        Some(unsafe { std::slice::from_raw_parts_mut(ptr.cast::<T>(), len) })
+    }
+}
+
+/// FFI signature of the optional `process_image_v2` export, for plugins that change the image size.
+///
+/// Receives the input read-only and writes its result to a buffer allocated through `output`.
+/// Returning 0 without allocating leaves the image unchanged. A plugin exporting this entry
+/// point need not export `process_image`; the host never treats it as local.
+pub type ProcessV2Fn = unsafe extern "C" fn(
+    ctx: *const CallContext,
+    width: u32,
+    height: u32,
+    rgba_data: *const u8,
+    output: *mut OutputBuffer,
+    params: *const c_char,
+) -> u32;
+
+/// A channel value a plugin can operate on generically.
+pub trait Sample: Copy + Send + Sync {
+    /// The `PIXEL_FORMAT_*` whose buffers hold this type.
+    const PIXEL_FORMAT: u32;
+    /// Full-scale value in the sample's own scale: 255 for `u8`, 1 for `f32`.
+    const MAX: f32;
+    /// Whether color values of this type are linear light ([`PIXEL_FORMAT_RGBA32F`]) rather
+    /// than sRGB-encoded ([`PIXEL_FORMAT_RGBA8`]).
+    const LINEAR: bool;
+    /// Converts the value to `f32` in the sample's own scale (`0..=255` for `u8`).
+    fn to_f32(self) -> f32;
+    /// Converts from `f32` in the sample's own scale, rounding and clamping as needed.
+    fn from_f32(v: f32) -> Self;
+    /// Views a buffer of this type as [`Pixels`].
+    fn pixels(buf: &mut [Self]) -> Pixels<'_>;
+    /// Views a buffer of this type as [`PixelsRef`].
+    fn pixels_ref(buf: &[Self]) -> PixelsRef<'_>;
+    /// Returns the buffer viewed by `pixels` if it holds this type.
+    fn from_pixels_ref(pixels: PixelsRef<'_>) -> Option<&[Self]>;
+}
+
+/// Converts a color value of `T`, normalized to `[0, 1]`, to linear light.
+pub fn to_linear<T: Sample>(v: f32) -> f32 {
+    if T::LINEAR { v } else { srgb_to_linear(v) }
+}
+
+/// Converts linear light to a normalized color value of `T`.
+pub fn from_linear<T: Sample>(v: f32) -> f32 {
+    if T::LINEAR { v } else { linear_to_srgb(v) }
+}
+
+/// Converts a normalized color value of `T` to its sRGB-encoded (display) form.
+pub fn to_encoded<T: Sample>(v: f32) -> f32 {
+    if T::LINEAR { linear_to_srgb(v) } else { v }
+}
+
+/// Converts a normalized sRGB-encoded value to the encoding of `T`.
+pub fn from_encoded<T: Sample>(v: f32) -> f32 {
+    if T::LINEAR { srgb_to_linear(v) } else { v }
+}
+
+/// Replaces the color of every RGBA pixel in `buf` with `f` applied to it; alpha is kept.
+///
+/// `f` receives and returns straight-alpha colors normalized to `[0, 1]`, in the encoding of
+/// `T`. With `premultiplied` data, colors are divided by alpha before the call and multiplied
+/// again afterwards, so non-linear adjustments do not shift the colors of translucent edges;
+/// fully transparent pixels are left alone.
+pub fn map_colors<T: Sample>(buf: &mut [T], premultiplied: bool, mut f: impl FnMut([f32; 3]) -> [f32; 3]) {
+    for px in buf.chunks_exact_mut(4) {
+        let alpha = if premultiplied { px[3].to_f32() / T::MAX } else { 1.0 };
+        if alpha <= 0.0 {
+            continue;
+        }
+        let scale = T::MAX * alpha;
+        let out = f([px[0].to_f32() / scale, px[1].to_f32() / scale, px[2].to_f32() / scale]);
+        for (v, o) in px.iter_mut().zip(out) {
+            *v = T::from_f32(o * scale);
+        }
+    }
+}
+
+/// Decodes an sRGB transfer-encoded value to linear light.
+///
+/// Values outside `[0, 1]` follow the curve's extension (mirrored below zero), so
+/// out-of-range float data survives a round trip.
+pub fn srgb_to_linear(v: f32) -> f32 {
+    let a = v.abs();
+    let linear = if a <= 0.04045 { a / 12.92 } else { ((a + 0.055) / 1.055).powf(2.4) };
+    linear.copysign(v)
+}
+
+/// Encodes a linear-light value with the sRGB transfer function; the inverse of [`srgb_to_linear`].
+pub fn linear_to_srgb(v: f32) -> f32 {
+    let a = v.abs();
+    let encoded = if a <= 0.003_130_8 { a * 12.92 } else { 1.055 * a.powf(1.0 / 2.4) - 0.055 };
+    encoded.copysign(v)
+}
+
+impl Sample for u8 {
+    const PIXEL_FORMAT: u32 = PIXEL_FORMAT_RGBA8;
+    const MAX: f32 = 255.0;
+    const LINEAR: bool = false;
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(v: f32) -> Self {
+        v.round().clamp(0.0, 255.0) as u8
+    }
+
+    fn pixels(buf: &mut [Self]) -> Pixels<'_> {
+        Pixels::Rgba8(buf)
+    }
+
+    fn pixels_ref(buf: &[Self]) -> PixelsRef<'_> {
+        PixelsRef::Rgba8(buf)
+    }
+
+    fn from_pixels_ref(pixels: PixelsRef<'_>) -> Option<&[Self]> {
+        match pixels {
+            PixelsRef::Rgba8(buf) => Some(buf),
+            PixelsRef::Rgba32F(_) => None,
+        }
+    }
+}
+
+impl Sample for f32 {
+    const PIXEL_FORMAT: u32 = PIXEL_FORMAT_RGBA32F;
+    const MAX: f32 = 1.0;
+    const LINEAR: bool = true;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(v: f32) -> Self {
+        v
+    }
+
+    fn pixels(buf: &mut [Self]) -> Pixels<'_> {
+        Pixels::Rgba32F(buf)
+    }
+
+    fn pixels_ref(buf: &[Self]) -> PixelsRef<'_> {
+        PixelsRef::Rgba32F(buf)
+    }
+
+    fn from_pixels_ref(pixels: PixelsRef<'_>) -> Option<&[Self]> {
+        match pixels {
+            PixelsRef::Rgba32F(buf) => Some(buf),
+            PixelsRef::Rgba8(_) => None,
+        }
+    }
+}
+
+/// A straight-alpha, sRGB-encoded color given in params as a hex string.
+///
+/// Accepts `#rgb`, `#rgba`, `#rrggbb` and `#rrggbbaa`, with or without the `#`; alpha
+/// defaults to opaque.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color(pub [f32; 4]);
+
+impl Color {
+    /// Opaque black.
+    pub const BLACK: Self = Self([0.0, 0.0, 0.0, 1.0]);
+    /// Opaque white.
+    pub const WHITE: Self = Self([1.0, 1.0, 1.0, 1.0]);
+    /// Fully transparent black.
+    pub const TRANSPARENT: Self = Self([0.0; 4]);
+
+    /// Returns the color channels normalized to `[0, 1]` in the encoding of `T`, alpha last.
+    pub fn normalized<T: Sample>(self) -> [f32; 4] {
+        let [r, g, b, a] = self.0;
+        [from_encoded::<T>(r), from_encoded::<T>(g), from_encoded::<T>(b), a]
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<_>>()
+            .ok_or_else(|| format!("invalid hex color `{s}`"))?;
+        let channels: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|d| d * 17).collect(),
+            6 | 8 => digits.chunks(2).map(|p| p[0] * 16 + p[1]).collect(),
+            _ => return Err(format!("expected #rgb, #rgba, #rrggbb or #rrggbbaa, got `{s}`")),
+        };
+        let mut rgba = [1.0; 4];
+        for (out, v) in rgba.iter_mut().zip(channels) {
+            *out = v as f32 / 255.0;
+        }
+        Ok(Self(rgba))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Why a params string could not be turned into a plugin's params type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamsError {
+    /// The host passed a null pointer.
+    Null,
+    /// The string is not valid UTF-8.
+    Utf8,
+    /// The string is not valid JSON or does not match the params type.
+    Json(String),
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "params pointer is null"),
+            Self::Utf8 => write!(f, "params are not valid UTF-8"),
+            Self::Json(e) => write!(f, "invalid params: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+/// Parses params in the canonical format: a JSON object, as produced by the host.
+///
+/// The host accepts TOML, JSON and `key=value` input, applies manifest defaults
+/// and overrides, and always hands plugins the result as JSON.
+pub fn params_from_str<T: DeserializeOwned>(text: &str) -> Result<T, ParamsError> {
+    serde_json::from_str(text).map_err(|e| ParamsError::Json(e.to_string()))
+}
+
+/// Parses the params pointer received over FFI; see [`params_from_str`].
+///
+/// # Safety
+/// `params` must be null or point to a NUL-terminated string that stays valid
+/// for the duration of the call.
+pub unsafe fn parse_params<T: DeserializeOwned>(params: *const c_char) -> Result<T, ParamsError> {
+    if params.is_null() {
+        return Err(ParamsError::Null);
+    }
+
+    // SAFETY:
+    // - We checked `params` is not null.
+    // - The caller guarantees it points to a valid NUL-terminated string for the duration of the call.
+    let text = unsafe { CStr::from_ptr(params) }.to_str().map_err(|_| ParamsError::Utf8)?;
+    params_from_str(text)
+}
+
+/// Small deterministic pseudo-random generator (SplitMix64) for plugins.
+///
+/// Not suitable for cryptographic use.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a generator from an explicit seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Creates a generator seeded from the host-provided call context.
+    pub fn from_context(ctx: &CallContext) -> Self {
+        Self::new(ctx.seed)
+    }
+
+    /// Returns the next pseudo-random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a pseudo-random `f32` uniformly distributed in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::from_context(&CallContext::new(42));
+        for _ in 0..16 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_next_f32_in_unit_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let v = rng.next_f32();
+            assert!((0.0..1.0).contains(&v));
+        }
+    }
+
+    #[test]
+    fn test_older_host_context_defaults() {
+        let mut ctx = CallContext::new(1);
+        assert_eq!(ctx.input2(), None);
+        let pixels = [0u8; 8];
+        (ctx.input2_data, ctx.input2_width, ctx.input2_height) = (pixels.as_ptr(), 2, 1);
+        assert_eq!(ctx.input2(), Some((2, 1, pixels.as_ptr())));
+        let images = [InputImage { data: pixels.as_ptr(), width: 2, height: 1 }; 2];
+        (ctx.inputs, ctx.input_count) = (images.as_ptr(), 2);
+        // SAFETY: `images` outlives every use of the returned slice.
+        assert_eq!(unsafe { ctx.inputs() }.len(), 2);
+        ctx.struct_size = offset_of!(CallContext, inputs) as u32;
+        // SAFETY: as above.
+        assert!(unsafe { ctx.inputs() }.is_empty());
+        ctx.struct_size = offset_of!(CallContext, input2_data) as u32;
+        assert_eq!(ctx.input2(), None);
+
+        ctx.pixel_format = PIXEL_FORMAT_RGBA32F;
+        assert_eq!(ctx.pixel_format(), PIXEL_FORMAT_RGBA32F);
+
+        ctx.alpha_mode = ALPHA_PREMULTIPLIED;
+        ctx.max_threads = 4;
+        assert_eq!(ctx.max_threads(), 4);
+        ctx.struct_size = offset_of!(CallContext, max_threads) as u32;
+        assert_eq!(ctx.max_threads(), 0);
+        assert_eq!(ctx.alpha_mode(), ALPHA_PREMULTIPLIED);
+
+        ctx.struct_size = offset_of!(CallContext, alpha_mode) as u32;
+        assert_eq!(ctx.pixel_format(), PIXEL_FORMAT_RGBA32F);
+        assert_eq!(ctx.alpha_mode(), ALPHA_STRAIGHT);
+
+        ctx.struct_size = offset_of!(CallContext, pixel_format) as u32;
+        assert_eq!(ctx.pixel_format(), PIXEL_FORMAT_RGBA8);
+    }
+
+    #[test]
+    fn test_transfer_round_trip() {
+        for v in [-0.5f32, 0.0, 0.002, 0.04, 0.2, 0.5, 1.0, 3.0] {
+            assert!((srgb_to_linear(linear_to_srgb(v)) - v).abs() < 1e-5);
+        }
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-5);
+        assert_eq!(to_linear::<f32>(0.5), 0.5);
+        assert_eq!(to_linear::<u8>(0.5), srgb_to_linear(0.5));
+    }
+
+    #[test]
+    fn test_map_colors_unpremultiplies() {
+        let mut straight = [100u8, 50, 0, 255, 0, 0, 0, 0];
+        map_colors(&mut straight, false, |[r, g, b]| [g, r, b]);
+        assert_eq!(straight, [50, 100, 0, 255, 0, 0, 0, 0]);
+
+        let mut premultiplied = [0.25f32, 0.125, 0.0, 0.5, 0.3, 0.3, 0.3, 0.0];
+        map_colors(&mut premultiplied, true, |c| c.map(|v| v * v));
+        assert_eq!(premultiplied, [0.125, 0.03125, 0.0, 0.5, 0.3, 0.3, 0.3, 0.0]);
+    }
+
+    #[test]
+    fn test_color_from_hex() {
+        assert_eq!("#fff".parse(), Ok(Color::WHITE));
+        assert_eq!("000000".parse(), Ok(Color::BLACK));
+        assert_eq!("#ff000080".parse::<Color>().unwrap().0, [1.0, 0.0, 0.0, 128.0 / 255.0]);
+        assert_eq!("#0000".parse(), Ok(Color::TRANSPARENT));
+        assert!("#ff00".parse::<Color>().is_ok());
+        assert!("#ff0g00".parse::<Color>().is_err());
+        assert!("#ff00000".parse::<Color>().is_err());
+
+        let color: Color = params_from_str(r##""#804020""##).unwrap();
+        assert_eq!(color.normalized::<u8>()[0], 128.0 / 255.0);
+        assert!((color.normalized::<f32>()[0] - srgb_to_linear(128.0 / 255.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_capabilities_supports() {
+        let caps = Capabilities::default();
+        assert!(caps.supports(PIXEL_FORMAT_RGBA8));
+        assert!(!caps.supports(PIXEL_FORMAT_RGBA32F));
+        assert!(!caps.supports(99));
+
+        let mut caps = Capabilities::default();
+        assert!(caps.request(CAP_GPU));
+        assert!(caps.requested(CAP_GPU));
+        caps = Capabilities { struct_size: offset_of!(Capabilities, flags) as u32, ..Capabilities::default() };
+        assert!(!caps.request(CAP_GPU));
+
+        let mut caps = Capabilities::default();
+        assert_eq!(caps.local_halo(), None);
+        assert!(caps.set_local(6));
+        assert_eq!(caps.local_halo(), Some(6));
+        caps = Capabilities { struct_size: offset_of!(Capabilities, halo) as u32, ..Capabilities::default() };
+        assert!(!caps.set_local(6));
+        assert_eq!(caps.local_halo(), None);
+    }
+
+    #[test]
+    fn test_parse_params() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct P {
+            radius: u32,
+        }
+
+        let text = c"{\"radius\": 3}";
+        // SAFETY: `text` is a static NUL-terminated string.
+        assert_eq!(unsafe { parse_params::<P>(text.as_ptr()) }, Ok(P { radius: 3 }));
+        // SAFETY: null is explicitly allowed.
+        assert_eq!(unsafe { parse_params::<P>(std::ptr::null()) }, Err(ParamsError::Null));
+        assert!(matches!(params_from_str::<P>("radius = 3"), Err(ParamsError::Json(_))));
+    }
+
+    #[test]
+    fn test_null_context() {
+        // SAFETY: null is explicitly allowed by `from_ptr`.
+        let ctx = unsafe { CallContext::from_ptr(std::ptr::null()) };
+        assert!(ctx.is_none());
+    }
+}
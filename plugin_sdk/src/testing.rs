@@ -0,0 +1,102 @@
+//! Helpers for plugin tests: run a processing function on a buffer and check manifests.
+
+use std::ffi::{CStr, c_void};
+
+use serde::de::DeserializeOwned;
+
+use crate::{Allocator, CallContext, GeometryFn, Image, ImageRef, InPlaceFn, OutputBuffer, PluginError, Sample};
+
+/// Panics unless `manifest` is valid TOML whose `defaults` table parses as `P`.
+pub fn assert_manifest_defaults_parse<P: DeserializeOwned>(manifest: &CStr) {
+    assert_manifest_defaults_parse_with::<P>(manifest, "");
+}
+
+/// Like [`assert_manifest_defaults_parse`], with the params that have no default given as TOML.
+pub fn assert_manifest_defaults_parse_with<P: DeserializeOwned>(manifest: &CStr, required: &str) {
+    let text = manifest.to_str().expect("manifest is UTF-8");
+    let manifest: toml::Table = toml::from_str(text).expect("manifest is valid TOML");
+    let mut defaults = manifest.get("defaults").and_then(toml::Value::as_table).cloned().unwrap_or_default();
+    defaults.extend(toml::from_str::<toml::Table>(required).expect("required params are valid TOML"));
+    if let Err(e) = defaults.try_into::<P>() {
+        panic!("manifest defaults do not parse as params: {e}");
+    }
+}
+
+/// Runs `process` on `buf`, a `width` x `height` image, with params given as JSON.
+///
+/// The context is a default one with the pixel format of `T`.
+pub fn run<P: DeserializeOwned, T: Sample>(
+    process: InPlaceFn<P>,
+    width: u32,
+    height: u32,
+    buf: &mut [T],
+    params: &str,
+) -> Result<(), PluginError> {
+    run_with(process, &CallContext::new(0), width, height, buf, params)
+}
+
+/// Like [`run`], with the given context; its pixel format is set to that of `T`.
+pub fn run_with<P: DeserializeOwned, T: Sample>(
+    process: InPlaceFn<P>,
+    ctx: &CallContext,
+    width: u32,
+    height: u32,
+    buf: &mut [T],
+    params: &str,
+) -> Result<(), PluginError> {
+    assert_eq!(buf.len(), width as usize * height as usize * 4, "buffer does not match the size");
+    let params = crate::params_from_str::<P>(params)?;
+    let ctx = CallContext { pixel_format: T::PIXEL_FORMAT, ..*ctx };
+    process(Image { width, height, pixels: T::pixels(buf) }, &params, &ctx)
+}
+
+/// The image a geometry-changing plugin allocated and wrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output<T> {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// `width * height * 4` values.
+    pub data: Vec<T>,
+}
+
+unsafe extern "C" fn alloc<T: Sample>(host: *mut c_void, width: u32, height: u32) -> *mut u8 {
+    // SAFETY: `run_v2` passes a pointer to its live `Output<T>`.
+    let out = unsafe { &mut *host.cast::<Output<T>>() };
+    *out = Output { width, height, data: vec![T::from_f32(0.0); width as usize * height as usize * 4] };
+    out.data.as_mut_ptr().cast()
+}
+
+/// Runs a geometry-changing `process` on `src` with params given as JSON and a default context.
+///
+/// Returns the allocated output, or `None` if the plugin left the image unchanged.
+pub fn run_v2<P: DeserializeOwned, T: Sample>(
+    process: GeometryFn<P>,
+    width: u32,
+    height: u32,
+    src: &[T],
+    params: &str,
+) -> Result<Option<Output<T>>, PluginError> {
+    run_v2_with(process, &CallContext::new(0), width, height, src, params)
+}
+
+/// Like [`run_v2`], with the given context; its pixel format is set to that of `T`.
+pub fn run_v2_with<P: DeserializeOwned, T: Sample>(
+    process: GeometryFn<P>,
+    ctx: &CallContext,
+    width: u32,
+    height: u32,
+    src: &[T],
+    params: &str,
+) -> Result<Option<Output<T>>, PluginError> {
+    assert_eq!(src.len(), width as usize * height as usize * 4, "buffer does not match the size");
+    let params = crate::params_from_str::<P>(params)?;
+    let ctx = CallContext { pixel_format: T::PIXEL_FORMAT, ..*ctx };
+    let mut out = Output { width: 0, height: 0, data: Vec::new() };
+    let mut buffer = OutputBuffer { host: (&raw mut out).cast(), alloc: alloc::<T> };
+    // SAFETY: `buffer` allocates `Output<T>` values for this call only.
+    let mut output = unsafe { Allocator::new(&mut buffer, T::PIXEL_FORMAT) };
+    process(ImageRef { width, height, pixels: T::pixels_ref(src) }, &mut output, &params, &ctx)?;
+    Ok((!out.data.is_empty()).then_some(out))
+}
@@ -0,0 +1,289 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// How the output colors are chosen.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum Method {
+    /// `levels` evenly spaced values per channel.
+    #[default]
+    Posterize,
+    /// A palette of `colors` from repeatedly splitting the color box with the widest range at
+    /// its median.
+    MedianCut,
+    /// The median-cut palette refined by `iterations` rounds of k-means; closer to the image's
+    /// colors, but slower.
+    Kmeans,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    method: Method,
+    /// Values per channel for `posterize`, at least 2.
+    #[serde(default = "default_levels")]
+    levels: u32,
+    /// Palette size for `median_cut` and `kmeans`.
+    #[serde(default = "default_colors")]
+    colors: u32,
+    /// Rounds of k-means.
+    #[serde(default = "default_iterations")]
+    iterations: u32,
+    /// Spreads each pixel's rounding error to its unprocessed neighbors (Floyd-Steinberg),
+    /// trading banding for fine noise.
+    #[serde(default)]
+    dither: bool,
+}
+
+fn default_levels() -> u32 {
+    4
+}
+
+fn default_colors() -> u32 {
+    16
+}
+
+fn default_iterations() -> u32 {
+    10
+}
+
+const MANIFEST: &CStr = cr#"name = "quantize_plugin"
+version = "0.1.0"
+description = "Posterizes or quantizes to a median-cut or k-means palette, optionally dithered"
+
+[defaults]
+method = "posterize"
+levels = 4
+colors = 16
+iterations = 10
+dither = false
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Quantizes both formats.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, pixels, .. } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.levels < 2 || params.colors == 0 {
+        return Err(PluginError::Invalid("levels must be at least 2 and colors positive"));
+    }
+
+    match pixels {
+        Pixels::Rgba8(buf) => quantize(width as usize, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => quantize(width as usize, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Posterizing without dithering works per pixel, while palettes are built from the whole image
+/// and dithering carries errors across it.
+fn halo(params: &Params) -> Option<u32> {
+    (params.method == Method::Posterize && !params.dither).then_some(0)
+}
+
+/// Replaces every color by its nearest output color, working on straight sRGB-encoded colors
+/// so that levels and palettes are spaced evenly to the eye. Alpha is kept, and fully
+/// transparent pixels neither shape the palette nor receive dithering error.
+fn quantize<T: Sample>(width: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 {
+        return;
+    }
+    let alphas: Vec<f32> = buf.chunks_exact(4).map(|px| px[3].to_f32() / T::MAX).collect();
+    let mut colors: Vec<[f32; 3]> = buf
+        .chunks_exact(4)
+        .zip(&alphas)
+        .map(|(px, &a)| {
+            let scale = if premultiplied && a > 0.0 { T::MAX * a } else { T::MAX };
+            std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale))
+        })
+        .collect();
+
+    let visible = || colors.iter().zip(&alphas).filter(|(_, a)| **a > 0.0).map(|(c, _)| *c);
+    let palette = match params.method {
+        Method::Posterize => None,
+        Method::MedianCut => Some(median_cut(visible().collect(), params.colors as usize)),
+        Method::Kmeans => {
+            let samples: Vec<[f32; 3]> = visible().collect();
+            let initial = median_cut(samples.clone(), params.colors as usize);
+            Some(kmeans(&samples, initial, params.iterations))
+        }
+    };
+    let steps = (params.levels - 1) as f32;
+    let nearest = |c: [f32; 3]| match &palette {
+        None => c.map(|v| (v.clamp(0.0, 1.0) * steps).round() / steps),
+        Some(palette) => palette[closest(palette, c)],
+    };
+
+    let height = colors.len() / width;
+    for i in 0..colors.len() {
+        if alphas[i] <= 0.0 {
+            continue;
+        }
+        let old = colors[i];
+        let new = nearest(old);
+        colors[i] = new;
+        if params.dither {
+            let (x, y) = (i % width, i / width);
+            let error: [f32; 3] = std::array::from_fn(|c| old[c] - new[c]);
+            for (dx, dy, weight) in [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)] {
+                let (nx, ny) = (x as i64 + dx, y + dy);
+                if nx < 0 || nx >= width as i64 || ny >= height {
+                    continue;
+                }
+                let n = ny * width + nx as usize;
+                for c in 0..3 {
+                    colors[n][c] += error[c] * weight / 16.0;
+                }
+            }
+        }
+    }
+
+    for ((px, color), a) in buf.chunks_exact_mut(4).zip(&colors).zip(&alphas) {
+        if *a <= 0.0 {
+            continue;
+        }
+        let scale = if premultiplied { T::MAX * a } else { T::MAX };
+        for c in 0..3 {
+            px[c] = T::from_f32(plugin_sdk::from_encoded::<T>(color[c]) * scale);
+        }
+    }
+}
+
+/// Splits the colors into up to `count` boxes, each time halving the box with the widest
+/// channel range at the median of that channel, and returns the mean color of each box.
+fn median_cut(colors: Vec<[f32; 3]>, count: usize) -> Vec<[f32; 3]> {
+    if colors.is_empty() {
+        return vec![[0.0; 3]];
+    }
+    let widest = |b: &[[f32; 3]]| {
+        (0..3)
+            .map(|c| {
+                let (lo, hi) = b.iter().fold((f32::MAX, f32::MIN), |(lo, hi), v| (lo.min(v[c]), hi.max(v[c])));
+                (c, hi - lo)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0))
+    };
+    let mut boxes = vec![colors];
+    while boxes.len() < count {
+        let Some((index, (channel, range))) =
+            boxes.iter().map(|b| widest(b)).enumerate().max_by(|a, b| a.1.1.total_cmp(&b.1.1))
+        else {
+            break;
+        };
+        if range <= 0.0 {
+            break;
+        }
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_by(|a, b| a[channel].total_cmp(&b[channel]));
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+    boxes.iter().map(|b| mean(b)).collect()
+}
+
+/// Moves each palette color to the mean of the colors closest to it, `iterations` times.
+fn kmeans(colors: &[[f32; 3]], mut palette: Vec<[f32; 3]>, iterations: u32) -> Vec<[f32; 3]> {
+    for _ in 0..iterations {
+        let mut sums = vec![([0.0f64; 3], 0usize); palette.len()];
+        for &color in colors {
+            let (sum, n) = &mut sums[closest(&palette, color)];
+            for c in 0..3 {
+                sum[c] += color[c] as f64;
+            }
+            *n += 1;
+        }
+        let mut moved = false;
+        for (p, (sum, n)) in palette.iter_mut().zip(sums) {
+            if n > 0 {
+                let next = sum.map(|s| (s / n as f64) as f32);
+                moved |= next != *p;
+                *p = next;
+            }
+        }
+        if !moved {
+            break;
+        }
+    }
+    palette
+}
+
+/// Index of the palette color nearest to `color`.
+fn closest(palette: &[[f32; 3]], color: [f32; 3]) -> usize {
+    let distance = |p: &[f32; 3]| (0..3).map(|c| (p[c] - color[c]).powi(2)).sum::<f32>();
+    (0..palette.len()).min_by(|&a, &b| distance(&palette[a]).total_cmp(&distance(&palette[b]))).unwrap_or(0)
+}
+
+fn mean(colors: &[[f32; 3]]) -> [f32; 3] {
+    let n = colors.len().max(1) as f64;
+    std::array::from_fn(|c| (colors.iter().map(|v| v[c] as f64).sum::<f64>() / n) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 16x16 image with a smooth diagonal color gradient.
+    fn gradient() -> Vec<u8> {
+        (0..256).flat_map(|i| [(i % 16 * 16) as u8, (i / 16 * 16) as u8, 128, 255]).collect()
+    }
+
+    fn distinct(img: &[u8]) -> usize {
+        img.chunks(4).map(|p| [p[0], p[1], p[2]]).collect::<HashSet<_>>().len()
+    }
+
+    #[test]
+    fn test_posterize() {
+        let mut img = vec![10, 100, 140, 255, 250, 60, 0, 77];
+        run(&mut img, 2, 1, r#"{"levels": 2}"#).unwrap();
+        assert_eq!(img, [0, 0, 255, 255, 255, 0, 0, 77]);
+
+        let mut img = gradient();
+        run(&mut img, 16, 16, r#"{"levels": 3}"#).unwrap();
+        assert!(img.chunks(4).flat_map(|p| &p[..3]).all(|v| [0, 128, 255].contains(v)));
+        assert!(run(&mut img, 16, 16, r#"{"levels": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_palettes() {
+        // Two colors quantized to a two-color palette come back exactly.
+        let src = [[200u8, 30, 30, 255], [20, 20, 220, 255]].repeat(8).concat();
+        for method in ["median_cut", "kmeans"] {
+            let mut img = src.clone();
+            run(&mut img, 4, 4, &format!(r#"{{"method": "{method}", "colors": 2}}"#)).unwrap();
+            assert_eq!(img, src, "{method}");
+
+            let mut img = gradient();
+            run(&mut img, 16, 16, &format!(r#"{{"method": "{method}", "colors": 8}}"#)).unwrap();
+            assert!(distinct(&img) <= 8, "{method}");
+        }
+    }
+
+    #[test]
+    fn test_dither_keeps_average() {
+        // A flat gray posterized to black and white: a mix of both that keeps the gray level.
+        let mut img = [128u8, 128, 128, 255].repeat(64);
+        run(&mut img, 8, 8, r#"{"levels": 2, "dither": true}"#).unwrap();
+        assert_eq!(distinct(&img), 2);
+        let mean = img.chunks(4).map(|p| p[0] as f32).sum::<f32>() / 64.0;
+        assert!((mean - 128.0).abs() < 12.0, "{mean}");
+
+        let params = plugin_sdk::params_from_str(r#"{"dither": true}"#).unwrap();
+        assert_eq!(halo(&params), None);
+    }
+}
@@ -0,0 +1,430 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Color, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Number of box blur passes; three approximate a Gaussian closely.
+const BLUR_PASSES: usize = 3;
+
+/// An area to redact, in pixel coordinates of the image the plugin receives.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum Region {
+    /// An axis-aligned rectangle, as most detectors report.
+    Rect { x: f32, y: f32, width: f32, height: f32 },
+    /// A polygon, filled with the even-odd rule; its last point connects back to the first.
+    Polygon { points: Vec<[f32; 2]> },
+}
+
+/// How the regions are made unrecognizable.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// A strong blur.
+    Blur,
+    /// Blocks of the average color of the region's pixels in them.
+    #[default]
+    Pixelate,
+    /// A solid color; the only method that leaves nothing of the original.
+    Fill,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    regions: Vec<Region>,
+    /// Path of a JSON file holding a list of further regions, e.g. written by a detector; empty
+    /// for none.
+    #[serde(default)]
+    regions_file: String,
+    #[serde(default)]
+    method: Method,
+    /// Pixels every region is grown by on each side, to cover detections that are a bit tight.
+    #[serde(default)]
+    padding: u32,
+    /// Blur: radius in pixels of each box blur pass.
+    #[serde(default = "default_radius")]
+    radius: u32,
+    /// Pixelate: side of the square blocks in pixels; the grid starts at the image's corner.
+    #[serde(default = "default_block")]
+    block: u32,
+    /// Fill: the color, composited over the region with its alpha.
+    #[serde(default = "default_color")]
+    color: Color,
+}
+
+fn default_radius() -> u32 {
+    16
+}
+
+fn default_block() -> u32 {
+    16
+}
+
+fn default_color() -> Color {
+    Color::BLACK
+}
+
+const MANIFEST: &CStr = cr##"name = "redact_plugin"
+version = "0.1.0"
+description = "Blurs, pixelates or fills listed rectangles and polygons, e.g. detected faces or plates"
+
+[defaults]
+regions = []
+regions_file = ""
+method = "pixelate"
+padding = 0
+radius = 16
+block = 16
+color = "#000000"
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Redacts both formats. It is not local: the regions are given in coordinates of the whole image.
+///
+/// Fails if `regions_file` cannot be read or parsed.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    if params.block == 0 {
+        return Err(PluginError::Invalid("block must be positive"));
+    }
+    let mut regions = params.regions.clone();
+    if !params.regions_file.is_empty() {
+        let text = std::fs::read_to_string(&params.regions_file)?;
+        regions.extend(plugin_sdk::params_from_str::<Vec<Region>>(&text)?);
+    }
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => redact(w, h, buf, &regions, params, premultiplied),
+        Pixels::Rgba32F(buf) => redact(w, h, buf, &regions, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Which pixels are redacted, one flag per pixel, row by row.
+struct Mask {
+    width: usize,
+    height: usize,
+    inside: Vec<bool>,
+}
+
+impl Mask {
+    /// Marks the pixels whose centers lie inside any of the regions.
+    fn new(width: usize, height: usize, regions: &[Region]) -> Self {
+        let mut inside = vec![false; width * height];
+        for region in regions {
+            for y in 0..height {
+                let cy = y as f32 + 0.5;
+                for [from, to] in region.spans(cy) {
+                    // Pixel `x` is covered if its center `x + 0.5` lies in `[from, to)`.
+                    let start = (from - 0.5).ceil().clamp(0.0, width as f32) as usize;
+                    let end = (to - 0.5).ceil().clamp(0.0, width as f32) as usize;
+                    for flag in &mut inside[y * width + start..y * width + end.max(start)] {
+                        *flag = true;
+                    }
+                }
+            }
+        }
+        Self { width, height, inside }
+    }
+
+    /// Grows the marked area by `radius` pixels in every direction (a square window).
+    fn dilate(&mut self, radius: usize) {
+        if radius == 0 {
+            return;
+        }
+        let (w, h) = (self.width, self.height);
+        // A running count of marked pixels within `radius` along a row or column.
+        let grow = |get: &dyn Fn(usize) -> bool, len: usize, set: &mut dyn FnMut(usize, bool)| {
+            let mut count = (0..radius.min(len)).filter(|&i| get(i)).count();
+            for i in 0..len {
+                if i + radius < len && get(i + radius) {
+                    count += 1;
+                }
+                set(i, count > 0);
+                if i >= radius && get(i - radius) {
+                    count -= 1;
+                }
+            }
+        };
+        let mut rows = vec![false; w * h];
+        for y in 0..h {
+            let line = &self.inside[y * w..(y + 1) * w];
+            grow(&|x| line[x], w, &mut |x, v| rows[y * w + x] = v);
+        }
+        for x in 0..w {
+            grow(&|y| rows[y * w + x], h, &mut |y, v| self.inside[y * w + x] = v);
+        }
+    }
+
+    /// The smallest rectangle `(x0, y0, x1, y1)`, exclusive at the end, holding every marked pixel.
+    fn bounds(&self) -> Option<(usize, usize, usize, usize)> {
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for (i, _) in self.inside.iter().enumerate().filter(|&(_, &v)| v) {
+            let (x, y) = (i % self.width, i / self.width);
+            bounds = Some(match bounds {
+                None => (x, y, x + 1, y + 1),
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)),
+            });
+        }
+        bounds
+    }
+}
+
+impl Region {
+    /// The horizontal intervals `[from, to)` of the row at height `y` that lie inside the region.
+    fn spans(&self, y: f32) -> Vec<[f32; 2]> {
+        match self {
+            Self::Rect { x, y: top, width, height } => {
+                if y >= *top && y < top + height && *width > 0.0 {
+                    vec![[*x, x + width]]
+                } else {
+                    Vec::new()
+                }
+            }
+            Self::Polygon { points } => {
+                let mut crossings: Vec<f32> = Vec::new();
+                for (i, &[x0, y0]) in points.iter().enumerate() {
+                    let [x1, y1] = points[(i + 1) % points.len()];
+                    // Half-open in y, so a vertex shared by two edges is counted once.
+                    if (y0 <= y) != (y1 <= y) {
+                        crossings.push(x0 + (y - y0) / (y1 - y0) * (x1 - x0));
+                    }
+                }
+                crossings.sort_by(f32::total_cmp);
+                crossings.chunks_exact(2).map(|pair| [pair[0], pair[1]]).collect()
+            }
+        }
+    }
+}
+
+/// Applies the method to every pixel of the (padded) regions, leaving the rest untouched.
+fn redact<T: Sample>(
+    width: usize,
+    height: usize,
+    buf: &mut [T],
+    regions: &[Region],
+    params: &Params,
+    premultiplied: bool,
+) {
+    let mut mask = Mask::new(width, height, regions);
+    mask.dilate(params.padding as usize);
+    let Some(bounds) = mask.bounds() else {
+        return;
+    };
+    match params.method {
+        Method::Blur => blur(buf, &mask, bounds, params.radius as usize, premultiplied),
+        Method::Pixelate => pixelate(buf, &mask, bounds, params.block as usize, premultiplied),
+        Method::Fill => fill(buf, &mask, params.color, premultiplied),
+    }
+}
+
+/// A pixel with its color weighted by alpha, so averages are not tinted by transparent pixels.
+fn premultiplied_px<T: Sample>(px: &[T], premultiplied: bool) -> [f32; 4] {
+    let alpha = px[3].to_f32() / T::MAX;
+    let weight = if premultiplied { 1.0 } else { alpha };
+    [px[0].to_f32() * weight, px[1].to_f32() * weight, px[2].to_f32() * weight, px[3].to_f32()]
+}
+
+/// Stores an alpha-weighted color back in the buffer's alpha convention.
+fn store<T: Sample>(px: &mut [T], value: [f32; 4], premultiplied: bool) {
+    let alpha = value[3] / T::MAX;
+    for c in 0..3 {
+        let v = if premultiplied { value[c] } else if alpha > 0.0 { value[c] / alpha } else { 0.0 };
+        px[c] = T::from_f32(v);
+    }
+    px[3] = T::from_f32(value[3]);
+}
+
+/// Box blurs the marked area's bounding box, extended by the blur's reach, and copies the
+/// result into the marked pixels.
+fn blur<T: Sample>(
+    buf: &mut [T],
+    mask: &Mask,
+    (x0, y0, x1, y1): (usize, usize, usize, usize),
+    radius: usize,
+    premultiplied: bool,
+) {
+    if radius == 0 {
+        return;
+    }
+    let width = mask.width;
+    let reach = radius * BLUR_PASSES;
+    let (bx0, by0) = (x0.saturating_sub(reach), y0.saturating_sub(reach));
+    let (bx1, by1) = ((x1 + reach).min(width), (y1 + reach).min(mask.height));
+    let (w, h) = (bx1 - bx0, by1 - by0);
+
+    let mut area: Vec<[f32; 4]> = (by0..by1)
+        .flat_map(|y| (bx0..bx1).map(move |x| (y * width + x) * 4))
+        .map(|i| premultiplied_px::<T>(&buf[i..i + 4], premultiplied))
+        .collect();
+    for _ in 0..BLUR_PASSES {
+        area = box_pass(&area, w, h, radius, (1, 0));
+        area = box_pass(&area, w, h, radius, (0, 1));
+    }
+
+    for y in y0..y1 {
+        for x in x0..x1 {
+            if mask.inside[y * width + x] {
+                let i = (y * width + x) * 4;
+                store(&mut buf[i..i + 4], area[(y - by0) * w + (x - bx0)], premultiplied);
+            }
+        }
+    }
+}
+
+/// One box blur pass along `step`, averaging over the pixels within `radius` that exist.
+fn box_pass(src: &[[f32; 4]], width: usize, height: usize, radius: usize, step: (usize, usize)) -> Vec<[f32; 4]> {
+    let (lines, len) = if step.0 == 1 { (height, width) } else { (width, height) };
+    let index = |line: usize, i: usize| if step.0 == 1 { line * width + i } else { i * width + line };
+    let mut out = vec![[0.0; 4]; src.len()];
+    for line in 0..lines {
+        let mut sum = [0.0f32; 4];
+        let mut count = 0usize;
+        for i in 0..radius.min(len) {
+            sum = std::array::from_fn(|c| sum[c] + src[index(line, i)][c]);
+            count += 1;
+        }
+        for i in 0..len {
+            if i + radius < len {
+                sum = std::array::from_fn(|c| sum[c] + src[index(line, i + radius)][c]);
+                count += 1;
+            }
+            out[index(line, i)] = sum.map(|v| v / count as f32);
+            if i >= radius {
+                sum = std::array::from_fn(|c| sum[c] - src[index(line, i - radius)][c]);
+                count -= 1;
+            }
+        }
+    }
+    out
+}
+
+/// Replaces the marked pixels of each block by the average of the marked pixels in it, so
+/// nothing from outside the regions bleeds in.
+fn pixelate<T: Sample>(
+    buf: &mut [T],
+    mask: &Mask,
+    (x0, y0, x1, y1): (usize, usize, usize, usize),
+    block: usize,
+    premultiplied: bool,
+) {
+    let width = mask.width;
+    for by in (y0 / block * block..y1).step_by(block) {
+        for bx in (x0 / block * block..x1).step_by(block) {
+            let marked: Vec<usize> = (by..(by + block).min(mask.height))
+                .flat_map(|y| (bx..(bx + block).min(width)).map(move |x| y * width + x))
+                .filter(|&i| mask.inside[i])
+                .collect();
+            if marked.is_empty() {
+                continue;
+            }
+            let mut sum = [0.0f64; 4];
+            for &i in &marked {
+                let px = premultiplied_px::<T>(&buf[i * 4..i * 4 + 4], premultiplied);
+                for (s, v) in sum.iter_mut().zip(px) {
+                    *s += v as f64;
+                }
+            }
+            let average = sum.map(|s| (s / marked.len() as f64) as f32);
+            for &i in &marked {
+                store(&mut buf[i * 4..i * 4 + 4], average, premultiplied);
+            }
+        }
+    }
+}
+
+/// Composites `color` over the marked pixels.
+fn fill<T: Sample>(buf: &mut [T], mask: &Mask, color: Color, premultiplied: bool) {
+    let [r, g, b, a] = color.normalized::<T>();
+    let over = [r * a * T::MAX, g * a * T::MAX, b * a * T::MAX, a * T::MAX];
+    for (px, _) in buf.chunks_exact_mut(4).zip(&mask.inside).filter(|(_, inside)| **inside) {
+        let below = premultiplied_px::<T>(px, premultiplied);
+        let value: [f32; 4] = std::array::from_fn(|c| over[c] + below[c] * (1.0 - a));
+        store(px, value, premultiplied);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// An 8x8 image whose red channel counts the pixels.
+    fn counting() -> Vec<u8> {
+        (0..64u8).flat_map(|i| [i * 4, 100, 50, 255]).collect()
+    }
+
+    fn changed(img: &[u8]) -> Vec<usize> {
+        img.chunks(4).zip(counting().chunks(4)).enumerate().filter(|(_, (a, b))| a != b).map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn test_regions_and_padding() {
+        let mut img = counting();
+        let params = r##"{"method": "fill", "regions": [{"x": 1, "y": 1, "width": 2, "height": 1}]}"##;
+        run(&mut img, 8, 8, params).unwrap();
+        assert_eq!(changed(&img), [9, 10]);
+        assert_eq!(&img[9 * 4..10 * 4], &[0, 0, 0, 255]);
+
+        // A triangle with corners on pixel corners covers the pixels whose centers it contains.
+        let mut img = counting();
+        let params = r#"{"method": "fill", "regions": [{"points": [[0, 0], [4, 0], [0, 4]]}]}"#;
+        run(&mut img, 8, 8, params).unwrap();
+        assert_eq!(changed(&img), [0, 1, 2, 8, 9, 16]);
+
+        let mut img = counting();
+        let params = r#"{"method": "fill", "padding": 1, "regions": [{"x": 3, "y": 3, "width": 1, "height": 1}]}"#;
+        run(&mut img, 8, 8, params).unwrap();
+        assert_eq!(changed(&img), [18, 19, 20, 26, 27, 28, 34, 35, 36]);
+
+        // No regions, no change.
+        let mut img = counting();
+        run(&mut img, 8, 8, "{}").unwrap();
+        assert_eq!(img, counting());
+    }
+
+    #[test]
+    fn test_pixelate_and_blur_stay_inside() {
+        let mut img = counting();
+        let params = r#"{"block": 2, "regions": [{"x": 0, "y": 0, "width": 3, "height": 2}]}"#;
+        run(&mut img, 8, 8, params).unwrap();
+        assert_eq!(changed(&img), [0, 1, 2, 8, 9, 10]);
+        // Pixels 0, 1, 8 and 9 form one block; 2 and 10 the marked part of the next.
+        let red: Vec<u8> = [0, 1, 8, 9, 2, 10].iter().map(|&i| img[i * 4]).collect();
+        assert_eq!(red, [18, 18, 18, 18, 24, 24]);
+
+        let mut img = counting();
+        let params = r#"{"method": "blur", "radius": 2, "regions": [{"x": 2, "y": 2, "width": 4, "height": 4}]}"#;
+        run(&mut img, 8, 8, params).unwrap();
+        let inside: Vec<usize> = (2..6).flat_map(|y| (2..6).map(move |x| y * 8 + x)).collect();
+        assert!(changed(&img).iter().all(|i| inside.contains(i)));
+        assert!(changed(&img).len() > 10);
+    }
+
+    #[test]
+    fn test_regions_file() {
+        let file = std::env::temp_dir().join(format!("redact_plugin_{}.json", std::process::id()));
+        std::fs::write(&file, r#"[{"x": 0, "y": 7, "width": 8, "height": 1}]"#).unwrap();
+        let path = file.to_str().unwrap().replace('\\', "/");
+
+        let mut img = counting();
+        let params = format!(r#"{{"method": "fill", "regions_file": "{path}"}}"#);
+        run(&mut img, 8, 8, &params).unwrap();
+        assert_eq!(changed(&img), (56..64).collect::<Vec<_>>());
+
+        assert!(run(&mut img, 8, 8, r#"{"regions_file": "/nonexistent/regions.json"}"#).is_err());
+
+        std::fs::remove_file(&file).unwrap();
+    }
+}
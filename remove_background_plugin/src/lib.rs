@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Where the flood fill through the background starts.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Seeds {
+    /// The four corners.
+    #[default]
+    Corners,
+    /// Every border pixel matching a corner's color, which also reaches background cut off from
+    /// the corners by a subject touching the edges.
+    Edges,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    seeds: Seeds,
+    /// Largest distance from a corner's color, in units of the sRGB cube's diagonal, that counts
+    /// as background.
+    #[serde(default = "default_tolerance")]
+    tolerance: f32,
+    /// Whether only background connected to the seeds is removed; with `false`, every pixel of a
+    /// background color is, including holes enclosed by the subject.
+    #[serde(default = "default_contiguous")]
+    contiguous: bool,
+    /// Width in pixels over which the subject's edge fades out, hiding the staircase of a hard
+    /// cut; 0 for none. Only the subject is faded, so no background fringe comes back.
+    #[serde(default = "default_feather")]
+    feather: u32,
+}
+
+fn default_tolerance() -> f32 {
+    0.1
+}
+
+fn default_contiguous() -> bool {
+    true
+}
+
+fn default_feather() -> u32 {
+    1
+}
+
+const MANIFEST: &CStr = cr#"name = "remove_background_plugin"
+version = "0.1.0"
+description = "Makes a near-uniform background transparent by flood fill from the corners"
+
+[defaults]
+seeds = "corners"
+tolerance = 0.1
+contiguous = true
+feather = 1
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+}
+
+/// Removes the background from both formats. It is not local: the background is found by
+/// flooding from the corners.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.tolerance.is_nan() {
+        return Err(PluginError::Invalid("tolerance must be a number"));
+    }
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => remove_background(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => remove_background(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Finds the background, the pixels within `tolerance` of a corner's color (reached from the
+/// seeds if `contiguous`), and multiplies alpha by a matte that is 0 there and 1 on the subject,
+/// faded inward over `feather` pixels.
+///
+/// Colors are compared as straight, sRGB-encoded values; premultiplied colors are scaled with the
+/// new alpha.
+fn remove_background<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let colors: Vec<[f32; 3]> = buf
+        .chunks_exact(4)
+        .map(|px| {
+            let alpha = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+            std::array::from_fn(|c| plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale))
+        })
+        .collect();
+    let corners = [0, width - 1, (height - 1) * width, height * width - 1];
+    let keys: Vec<[f32; 3]> = corners.iter().map(|&i| colors[i]).collect();
+    let limit = params.tolerance * 3.0f32.sqrt();
+    let near = |i: usize| {
+        keys.iter().any(|k| {
+            let d2: f32 = (0..3).map(|c| (colors[i][c] - k[c]).powi(2)).sum();
+            d2.sqrt() <= limit
+        })
+    };
+
+    let background: Vec<bool> = if params.contiguous {
+        let starts: Vec<usize> = match params.seeds {
+            Seeds::Corners => corners.to_vec(),
+            Seeds::Edges => (0..width * height)
+                .filter(|&i| {
+                    let (x, y) = (i % width, i / width);
+                    x == 0 || y == 0 || x == width - 1 || y == height - 1
+                })
+                .collect(),
+        };
+        flood(width, height, &starts, near)
+    } else {
+        (0..width * height).map(near).collect()
+    };
+
+    let subject: Vec<f32> = background.iter().map(|&b| if b { 0.0 } else { 1.0 }).collect();
+    let matte = if params.feather > 0 {
+        let soft = box_blur(&subject, width, height, params.feather as usize);
+        subject.iter().zip(soft).map(|(&s, b)| s.min(b)).collect()
+    } else {
+        subject
+    };
+
+    for (px, m) in buf.chunks_exact_mut(4).zip(matte) {
+        if m >= 1.0 {
+            continue;
+        }
+        let channels = if premultiplied { 0..4 } else { 3..4 };
+        for c in channels {
+            px[c] = T::from_f32(px[c].to_f32() * m);
+        }
+    }
+}
+
+/// Marks the pixels reachable from `starts` through 4-connected pixels for which `near` holds.
+fn flood(width: usize, height: usize, starts: &[usize], near: impl Fn(usize) -> bool) -> Vec<bool> {
+    let mut reached = vec![false; width * height];
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    for &i in starts {
+        if !reached[i] && near(i) {
+            reached[i] = true;
+            queue.push_back(i);
+        }
+    }
+    while let Some(i) = queue.pop_front() {
+        let (x, y) = (i % width, i / width);
+        let around = [
+            (x > 0).then(|| i - 1),
+            (x + 1 < width).then(|| i + 1),
+            (y > 0).then(|| i - width),
+            (y + 1 < height).then(|| i + width),
+        ];
+        for n in around.into_iter().flatten() {
+            if !reached[n] && near(n) {
+                reached[n] = true;
+                queue.push_back(n);
+            }
+        }
+    }
+    reached
+}
+
+/// Averages over the `2 * radius + 1` square window, clamped at the image edges.
+fn box_blur(src: &[f32], width: usize, height: usize, radius: usize) -> Vec<f32> {
+    let pass = |src: &[f32], len: usize, lines: usize, index: &dyn Fn(usize, usize) -> usize| {
+        let mut out = vec![0.0; src.len()];
+        for line in 0..lines {
+            for i in 0..len {
+                let sum: f32 = (0..=2 * radius)
+                    .map(|k| src[index(line, (i + k).saturating_sub(radius).min(len - 1))])
+                    .sum();
+                out[index(line, i)] = sum / (2 * radius + 1) as f32;
+            }
+        }
+        out
+    };
+    let rows = pass(src, width, height, &|line, i| line * width + i);
+    pass(&rows, height, width, &|line, i| i * width + line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 9x9 slightly noisy white background with a dark red ring around a white hole.
+    fn product() -> Vec<u8> {
+        (0..81)
+            .flat_map(|i| {
+                let (x, y) = (i % 9, i / 9);
+                let ring = (2..7).contains(&x) && (2..7).contains(&y) && !(x == 4 && y == 4);
+                let noise = (i * 7 % 5) as u8;
+                if ring { [120, 20, 30, 255] } else { [250 + noise, 251 - noise, 250, 255] }
+            })
+            .collect()
+    }
+
+    fn alphas(img: &[u8]) -> Vec<u8> {
+        img.chunks(4).map(|p| p[3]).collect()
+    }
+
+    #[test]
+    fn test_background_removed_subject_kept() {
+        let mut img = product();
+        run(&mut img, 9, 9, r#"{"feather": 0}"#).unwrap();
+        let a = alphas(&img);
+        for (i, &alpha) in a.iter().enumerate() {
+            let (x, y) = (i % 9, i / 9);
+            let inside = (2..7).contains(&x) && (2..7).contains(&y);
+            assert_eq!(alpha, if inside { 255 } else { 0 }, "pixel {x},{y}");
+        }
+        // Colors are untouched in straight alpha.
+        assert!(img.chunks(4).zip(product().chunks(4)).all(|(a, b)| a[..3] == b[..3]));
+
+        // Not contiguous, the hole inside the ring goes too.
+        let mut img = product();
+        run(&mut img, 9, 9, r#"{"feather": 0, "contiguous": false}"#).unwrap();
+        assert_eq!(img[(4 * 9 + 4) * 4 + 3], 0);
+        assert_eq!(img[(3 * 9 + 3) * 4 + 3], 255);
+
+        // Too tight a tolerance keeps the noisy background.
+        let mut img = product();
+        run(&mut img, 9, 9, r#"{"feather": 0, "tolerance": 0.001}"#).unwrap();
+        assert!(alphas(&img).iter().filter(|&&a| a == 255).count() > 50);
+    }
+
+    #[test]
+    fn test_edges_seeds_and_feather() {
+        // An arch standing on the bottom edge of a 7x5 image cuts its inside off from the corners.
+        let mut src = [200u8, 220, 240, 255].repeat(35);
+        for (x, y) in (1..6).map(|x| (x, 1)).chain((2..5).flat_map(|y| [(1, y), (5, y)])) {
+            src[(y * 7 + x) * 4..][..4].copy_from_slice(&[10, 10, 10, 255]);
+        }
+        let mut img = src.clone();
+        run(&mut img, 7, 5, r#"{"feather": 0}"#).unwrap();
+        assert_eq!(alphas(&img).iter().filter(|&&a| a == 255).count(), 20);
+        let mut img = src.clone();
+        run(&mut img, 7, 5, r#"{"feather": 0, "seeds": "edges"}"#).unwrap();
+        assert_eq!(alphas(&img).iter().filter(|&&a| a == 255).count(), 11);
+
+        // Feathering fades the subject's edge but never brings the background back.
+        let mut img = product();
+        run(&mut img, 9, 9, r#"{"feather": 1}"#).unwrap();
+        let a = alphas(&img);
+        assert_eq!(a[0], 0);
+        assert_eq!(a[9 + 4], 0);
+        assert!(a[2 * 9 + 4] > 0 && a[2 * 9 + 4] < 255, "{a:?}");
+        assert_eq!(a[3 * 9 + 3], 255);
+    }
+}
@@ -0,0 +1,386 @@
+use std::f32::consts::PI;
+use std::ffi::CStr;
+use plugin_sdk::{Allocator, CallContext, ImageRef, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+/// Resampling filter.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Filter {
+    /// The closest source pixel; blocky and aliased, but keeps hard pixel edges.
+    Nearest,
+    /// Triangle filter over 2 pixels.
+    Bilinear,
+    /// Catmull-Rom spline over 4 pixels.
+    Bicubic,
+    /// Windowed sinc over 6 pixels; the sharpest, with slight ringing at hard edges.
+    #[default]
+    Lanczos3,
+}
+
+impl Filter {
+    /// Half-width of the kernel in source pixels at a scale of 1.
+    fn radius(self) -> f32 {
+        match self {
+            Self::Nearest => 0.5,
+            Self::Bilinear => 1.0,
+            Self::Bicubic => 2.0,
+            Self::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Self::Nearest => f32::from(u8::from(x < 0.5)),
+            Self::Bilinear => (1.0 - x).max(0.0),
+            Self::Bicubic if x < 1.0 => 1.5 * x * x * x - 2.5 * x * x + 1.0,
+            Self::Bicubic if x < 2.0 => -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0,
+            Self::Bicubic => 0.0,
+            Self::Lanczos3 if x < 1e-6 => 1.0,
+            Self::Lanczos3 if x < 3.0 => {
+                let px = PI * x;
+                3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+            }
+            Self::Lanczos3 => 0.0,
+        }
+    }
+}
+
+/// How `width` and `height` are met when both are given.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Fit {
+    /// Exactly `width` x `height`, distorting the aspect ratio if needed.
+    Stretch,
+    /// As large as possible within `width` x `height`, keeping the aspect ratio.
+    #[default]
+    Contain,
+    /// Fills `width` x `height`, keeping the aspect ratio and cropping the overflow evenly.
+    Cover,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Target width; 0 derives it from `height` and the aspect ratio.
+    #[serde(default)]
+    width: u32,
+    /// Target height; 0 derives it from `width` and the aspect ratio.
+    #[serde(default)]
+    height: u32,
+    /// Scale factor, used instead of `width` and `height` if positive.
+    #[serde(default)]
+    scale: f64,
+    #[serde(default)]
+    fit: Fit,
+    #[serde(default)]
+    filter: Filter,
+    /// Unsharp-mask amount applied after downscaling; 0 disables it.
+    #[serde(default)]
+    sharpen: f32,
+}
+
+/// Output geometry: the resampled size, and the centered crop taken from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Plan {
+    scaled: (u32, u32),
+    out: (u32, u32),
+}
+
+impl Params {
+    fn plan(&self, width: u32, height: u32) -> Option<Plan> {
+        let (w, h) = (width as f64, height as f64);
+        let size = |v: f64| (v.round() as u32).max(1);
+        let scaled = if self.scale > 0.0 {
+            (size(w * self.scale), size(h * self.scale))
+        } else {
+            match (self.width, self.height) {
+                (0, 0) => return None,
+                (tw, 0) => (tw, size(h * tw as f64 / w)),
+                (0, th) => (size(w * th as f64 / h), th),
+                (tw, th) => {
+                    let (sx, sy) = (tw as f64 / w, th as f64 / h);
+                    match self.fit {
+                        Fit::Stretch => (tw, th),
+                        Fit::Contain => (size(w * sx.min(sy)), size(h * sx.min(sy))),
+                        Fit::Cover => {
+                            let s = sx.max(sy);
+                            let scaled = (size(w * s).max(tw), size(h * s).max(th));
+                            return Some(Plan { scaled, out: (tw, th) });
+                        }
+                    }
+                }
+            }
+        };
+        Some(Plan { scaled, out: scaled })
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "resize_plugin"
+version = "0.1.0"
+description = "Resizes with nearest, bilinear, bicubic or Lanczos3 filtering and fit modes"
+
+[defaults]
+width = 0
+height = 0
+scale = 0.0
+fit = "contain"
+filter = "lanczos3"
+sharpen = 0.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process_v2: process,
+}
+
+/// Resizes both formats.
+fn process(
+    image: ImageRef<'_>,
+    output: &mut Allocator<'_>,
+    params: &Params,
+    ctx: &CallContext,
+) -> Result<(), PluginError> {
+    let ImageRef { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let plan = params.plan(width, height).ok_or(PluginError::Invalid("no valid target size"))?;
+
+    match pixels {
+        PixelsRef::Rgba8(src) => resize(src, (width, height), plan, params, premultiplied, output),
+        PixelsRef::Rgba32F(src) => resize(src, (width, height), plan, params, premultiplied, output),
+    }
+}
+
+/// Resamples `src` per `plan` into a buffer allocated from `output`.
+///
+/// Filtering happens on premultiplied values, so transparent pixels do not bleed their color
+/// into visible ones. Fails if the host refused the buffer; an unchanged size
+/// allocates nothing.
+fn resize<T: Sample>(
+    src: &[T],
+    (width, height): (u32, u32),
+    plan: Plan,
+    params: &Params,
+    premultiplied: bool,
+    output: &mut Allocator<'_>,
+) -> Result<(), PluginError> {
+    if plan.scaled == (width, height) && plan.out == plan.scaled {
+        return Ok(());
+    }
+
+    let mut work: Vec<f32> = src.iter().map(|v| v.to_f32()).collect();
+    if !premultiplied {
+        for px in work.chunks_exact_mut(4) {
+            let alpha = px[3] / T::MAX;
+            for v in &mut px[..3] {
+                *v *= alpha;
+            }
+        }
+    }
+
+    let (sw, sh) = plan.scaled;
+    let horizontal = weights(width, sw, params.filter);
+    let vertical = weights(height, sh, params.filter);
+    let wide = resample_rows(&work, width as usize, height as usize, &horizontal);
+    let mut scaled = resample_columns(&wide, sw as usize, &vertical);
+
+    if params.sharpen > 0.0 && (sw < width || sh < height) {
+        scaled = unsharp(&scaled, sw as usize, sh as usize, params.sharpen);
+    }
+
+    let (ow, oh) = plan.out;
+    let dst = output.alloc::<T>(ow, oh)?;
+    let (left, top) = ((sw - ow) as usize / 2, (sh - oh) as usize / 2);
+    for (y, row) in dst.chunks_exact_mut(ow as usize * 4).enumerate() {
+        let start = ((top + y) * sw as usize + left) * 4;
+        for (out, px) in row.chunks_exact_mut(4).zip(scaled[start..].chunks_exact(4)) {
+            store(px, out, premultiplied);
+        }
+    }
+    Ok(())
+}
+
+/// Contributions to one output sample: the first source index and one weight per source sample.
+struct Taps {
+    start: usize,
+    weights: Vec<f32>,
+}
+
+/// Computes the filter taps for resampling `src` samples to `dst` along one axis.
+///
+/// When shrinking, the kernel is widened by the scale factor so that it averages over all the
+/// source pixels an output pixel covers instead of skipping some (which would alias). `Nearest`
+/// always takes a single pixel.
+fn weights(src: u32, dst: u32, filter: Filter) -> Vec<Taps> {
+    let scale = dst as f32 / src as f32;
+    let stretch = (1.0 / scale).max(1.0);
+    let support = filter.radius() * stretch;
+    (0..dst)
+        .map(|i| {
+            let center = (i as f32 + 0.5) / scale;
+            if filter == Filter::Nearest {
+                return Taps { start: (center as usize).min(src as usize - 1), weights: vec![1.0] };
+            }
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(src as usize);
+            let mut weights: Vec<f32> =
+                (start..end).map(|j| filter.weight((j as f32 + 0.5 - center) / stretch)).collect();
+            let sum: f32 = weights.iter().sum();
+            if sum.abs() > f32::EPSILON {
+                weights.iter_mut().for_each(|w| *w /= sum);
+            }
+            Taps { start, weights }
+        })
+        .collect()
+}
+
+fn resample_rows(src: &[f32], width: usize, height: usize, taps: &[Taps]) -> Vec<f32> {
+    let mut out = vec![0.0; taps.len() * height * 4];
+    for (y, row) in out.chunks_exact_mut(taps.len() * 4).enumerate() {
+        let src_row = &src[y * width * 4..(y + 1) * width * 4];
+        for (px, tap) in row.chunks_exact_mut(4).zip(taps) {
+            for (k, w) in tap.weights.iter().enumerate() {
+                let i = (tap.start + k) * 4;
+                for c in 0..4 {
+                    px[c] += src_row[i + c] * w;
+                }
+            }
+        }
+    }
+    out
+}
+
+fn resample_columns(src: &[f32], width: usize, taps: &[Taps]) -> Vec<f32> {
+    let stride = width * 4;
+    let mut out = vec![0.0; taps.len() * stride];
+    for (row, tap) in out.chunks_exact_mut(stride).zip(taps) {
+        for (k, w) in tap.weights.iter().enumerate() {
+            let src_row = &src[(tap.start + k) * stride..][..stride];
+            for (o, s) in row.iter_mut().zip(src_row) {
+                *o += s * w;
+            }
+        }
+    }
+    out
+}
+
+/// Sharpens with an unsharp mask against a 3x3 box blur, edges clamped.
+fn unsharp(src: &[f32], width: usize, height: usize, amount: f32) -> Vec<f32> {
+    let mut out = src.to_vec();
+    for y in 0..height {
+        for x in 0..width {
+            let mut blur = [0.0; 4];
+            for dy in -1i64..=1 {
+                for dx in -1i64..=1 {
+                    let sx = (x as i64 + dx).clamp(0, width as i64 - 1) as usize;
+                    let sy = (y as i64 + dy).clamp(0, height as i64 - 1) as usize;
+                    let i = (sy * width + sx) * 4;
+                    for c in 0..4 {
+                        blur[c] += src[i + c] / 9.0;
+                    }
+                }
+            }
+            let i = (y * width + x) * 4;
+            for c in 0..4 {
+                out[i + c] = src[i + c] + amount * (src[i + c] - blur[c]);
+            }
+        }
+    }
+    out
+}
+
+/// Writes a premultiplied sample in the buffer's alpha convention, clamping filter overshoot.
+fn store<T: Sample>(px: &[f32], out: &mut [T], premultiplied: bool) {
+    let alpha = px[3].clamp(0.0, T::MAX);
+    let scale = alpha / T::MAX;
+    for c in 0..3 {
+        let v = px[c].max(0.0);
+        out[c] = T::from_f32(if premultiplied {
+            v
+        } else if scale > 0.0 {
+            v / scale
+        } else {
+            0.0
+        });
+    }
+    out[3] = T::from_f32(alpha);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing::{self, Output};
+
+    /// Runs an RGBA8 image and returns the output, if one was allocated.
+    fn run(src: &[u8], width: u32, height: u32, params: &str) -> Result<Option<Output<u8>>, PluginError> {
+        testing::run_v2(process, width, height, src, params)
+    }
+
+    #[test]
+    fn test_fit_modes() {
+        let parse = |params: &str| plugin_sdk::params_from_str::<Params>(params).unwrap();
+        let plan = |w, h, params: &str| parse(params).plan(w, h).unwrap();
+        assert_eq!(plan(400, 200, r#"{"width": 100, "height": 100}"#).out, (100, 50));
+        assert_eq!(plan(400, 200, r#"{"width": 100, "height": 100, "fit": "stretch"}"#).out, (100, 100));
+        let cover = plan(400, 200, r#"{"width": 100, "height": 100, "fit": "cover"}"#);
+        assert_eq!((cover.scaled, cover.out), ((200, 100), (100, 100)));
+        assert_eq!(plan(400, 200, r#"{"height": 50}"#).out, (100, 50));
+        assert_eq!(plan(400, 200, r#"{"scale": 0.5, "width": 7}"#).out, (200, 100));
+        assert!(parse("{}").plan(4, 4).is_none());
+    }
+
+    #[test]
+    fn test_filters_keep_flat_color() {
+        let src = [200u8, 100, 50, 255].repeat(9 * 6);
+        for filter in ["nearest", "bilinear", "bicubic", "lanczos3"] {
+            for size in [r#""width": 4"#, r#""width": 20"#] {
+                let out = run(&src, 9, 6, &format!(r#"{{"filter": "{filter}", {size}}}"#)).unwrap().unwrap();
+                assert!(out.data.chunks(4).all(|p| p == [200, 100, 50, 255]), "{filter} {size}");
+            }
+        }
+        assert_eq!(run(&src, 9, 6, r#"{"scale": 1.0}"#), Ok(None));
+    }
+
+    #[test]
+    fn test_nearest_upscale_and_filtered_downscale() {
+        let src: Vec<u8> = [0u8, 100, 200, 250].iter().flat_map(|&v| [v, v, v, 255]).collect();
+        let up = run(&src, 2, 2, r#"{"scale": 2, "filter": "nearest"}"#).unwrap().unwrap();
+        let firsts: Vec<u8> = up.data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(firsts, [0, 0, 100, 100, 0, 0, 100, 100, 200, 200, 250, 250, 200, 200, 250, 250]);
+
+        // Halving a one-pixel checkerboard: nearest picks one phase, the filters average to gray.
+        let checker: Vec<u8> = (0..8 * 8)
+            .flat_map(|i| {
+                let v = if (i % 8 + i / 8) % 2 == 0 { 0 } else { 255 };
+                [v, v, v, 255]
+            })
+            .collect();
+        let nearest = run(&checker, 8, 8, r#"{"scale": 0.5, "filter": "nearest"}"#).unwrap().unwrap();
+        assert!(nearest.data.chunks(4).all(|p| p[0] == nearest.data[0]));
+        assert!(nearest.data[0] == 0 || nearest.data[0] == 255);
+        for filter in ["bilinear", "bicubic", "lanczos3"] {
+            let out = run(&checker, 8, 8, &format!(r#"{{"scale": 0.5, "filter": "{filter}"}}"#)).unwrap().unwrap();
+            assert_eq!((out.width, out.height), (4, 4));
+            assert!(out.data.chunks(4).all(|p| p[0].abs_diff(128) < 40), "{filter}");
+        }
+    }
+
+    #[test]
+    fn test_transparent_pixels_do_not_bleed() {
+        // Opaque white next to transparent black: the downscaled pixel stays white, half transparent.
+        let src = [255u8, 255, 255, 255, 0, 0, 0, 0].repeat(2);
+        let params = r#"{"width": 1, "height": 1, "fit": "stretch", "filter": "bilinear"}"#;
+        let out = run(&src, 2, 2, params).unwrap().unwrap();
+        assert_eq!(out.data, [255, 255, 255, 128]);
+
+        // Sharpening after a downscale increases contrast at an edge.
+        let edge: Vec<u8> =
+            (0..16 * 16).flat_map(|i| if i % 16 < 8 { [40, 40, 40, 255] } else { [200, 200, 200, 255] }).collect();
+        let soft = run(&edge, 16, 16, r#"{"scale": 0.5, "filter": "bilinear"}"#).unwrap().unwrap();
+        let sharp = run(&edge, 16, 16, r#"{"scale": 0.5, "filter": "bilinear", "sharpen": 1.0}"#).unwrap().unwrap();
+        let spread = |d: &[u8]| d.chunks(4).map(|p| p[0]).max().unwrap() - d.chunks(4).map(|p| p[0]).min().unwrap();
+        assert!(spread(&sharp.data) > spread(&soft.data));
+    }
+}
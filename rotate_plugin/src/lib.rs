@@ -0,0 +1,296 @@
+use std::ffi::CStr;
+use plugin_sdk::{Allocator, CallContext, Color, ImageRef, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+/// How source pixels are sampled for angles that are not a multiple of 90 degrees.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Interpolation {
+    /// The closest source pixel.
+    Nearest,
+    /// A weighted average of the 2x2 closest pixels.
+    #[default]
+    Bilinear,
+    /// A Catmull-Rom spline through the 4x4 closest pixels; sharper than bilinear.
+    Bicubic,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Clockwise rotation in degrees.
+    #[serde(default)]
+    angle: f64,
+    #[serde(default)]
+    interpolation: Interpolation,
+    /// Fill for the corners the rotated image does not cover.
+    #[serde(default = "default_background")]
+    background: Color,
+    /// Grows the output to hold the whole rotated image; otherwise the input size is kept and the
+    /// corners are cut off. Quarter turns always swap width and height.
+    #[serde(default = "default_expand")]
+    expand: bool,
+}
+
+fn default_background() -> Color {
+    Color::TRANSPARENT
+}
+
+fn default_expand() -> bool {
+    true
+}
+
+const MANIFEST: &CStr = cr##"name = "rotate_plugin"
+version = "0.1.0"
+description = "Rotates by quarter turns losslessly, or by any angle with interpolation"
+
+[defaults]
+angle = 0.0
+interpolation = "bilinear"
+background = "#00000000"
+expand = true
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process_v2: process,
+}
+
+/// Rotates both formats.
+fn process(
+    image: ImageRef<'_>,
+    output: &mut Allocator<'_>,
+    params: &Params,
+    ctx: &CallContext,
+) -> Result<(), PluginError> {
+    let ImageRef { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    match pixels {
+        PixelsRef::Rgba8(src) => rotate(src, width, height, params, premultiplied, output),
+        PixelsRef::Rgba32F(src) => rotate(src, width, height, params, premultiplied, output),
+    }
+}
+
+/// Rotates `src` into a buffer allocated from `output`; fails if the host refused it.
+///
+/// A full turn allocates nothing, which leaves the image unchanged.
+fn rotate<T: Sample>(
+    src: &[T],
+    width: u32,
+    height: u32,
+    params: &Params,
+    premultiplied: bool,
+    output: &mut Allocator<'_>,
+) -> Result<(), PluginError> {
+    let angle = params.angle.rem_euclid(360.0);
+    let quarter = angle / 90.0;
+    if quarter.fract() == 0.0 {
+        let turns = quarter as u32;
+        if turns == 0 {
+            return Ok(());
+        }
+        let (out_w, out_h) = if turns == 2 { (width, height) } else { (height, width) };
+        let dst = output.alloc::<T>(out_w, out_h)?;
+        quarter_turns(src, width, height, turns, dst);
+        return Ok(());
+    }
+
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (out_w, out_h) = if params.expand {
+        // Shave off rounding noise so that e.g. 45 degrees of a square does not gain a pixel.
+        let fit = |a: f64, b: f64| ((a + b) - 1e-6).ceil().max(1.0) as u32;
+        (
+            fit(width as f64 * cos.abs(), height as f64 * sin.abs()),
+            fit(width as f64 * sin.abs(), height as f64 * cos.abs()),
+        )
+    } else {
+        (width, height)
+    };
+    let dst = output.alloc::<T>(out_w, out_h)?;
+
+    let source = Source { data: src, width, height, premultiplied, background: background::<T>(params.background) };
+    let (src_cx, src_cy) = (width as f64 / 2.0, height as f64 / 2.0);
+    let (dst_cx, dst_cy) = (out_w as f64 / 2.0, out_h as f64 / 2.0);
+    for (i, px) in dst.chunks_exact_mut(4).enumerate() {
+        let dx = (i % out_w as usize) as f64 + 0.5 - dst_cx;
+        let dy = (i / out_w as usize) as f64 + 0.5 - dst_cy;
+        // Inverse rotation: where in the source this output pixel's center comes from.
+        let sx = dx * cos + dy * sin + src_cx - 0.5;
+        let sy = -dx * sin + dy * cos + src_cy - 0.5;
+        let value = match params.interpolation {
+            Interpolation::Nearest => source.pixel(sx.round() as i64, sy.round() as i64),
+            Interpolation::Bilinear => source.bilinear(sx, sy),
+            Interpolation::Bicubic => source.bicubic(sx, sy),
+        };
+        source.store(value, px);
+    }
+    Ok(())
+}
+
+/// Rotates clockwise by `turns` quarter turns without resampling.
+fn quarter_turns<T: Copy>(src: &[T], width: u32, height: u32, turns: u32, dst: &mut [T]) {
+    let (w, h) = (width as usize, height as usize);
+    for y in 0..h {
+        for x in 0..w {
+            let (tx, ty, out_w) = match turns {
+                1 => (h - 1 - y, x, h),
+                2 => (w - 1 - x, h - 1 - y, w),
+                _ => (y, w - 1 - x, h),
+            };
+            let from = (y * w + x) * 4;
+            let to = (ty * out_w + tx) * 4;
+            dst[to..to + 4].copy_from_slice(&src[from..from + 4]);
+        }
+    }
+}
+
+/// The background color as premultiplied values in the scale of `T`.
+fn background<T: Sample>(color: Color) -> [f32; 4] {
+    let [r, g, b, a] = color.normalized::<T>();
+    [r * a * T::MAX, g * a * T::MAX, b * a * T::MAX, a * T::MAX]
+}
+
+/// The input image, read as premultiplied values so interpolation does not bleed the color of
+/// transparent pixels, and padded with the background beyond its edges.
+struct Source<'a, T> {
+    data: &'a [T],
+    width: u32,
+    height: u32,
+    premultiplied: bool,
+    background: [f32; 4],
+}
+
+impl<T: Sample> Source<'_, T> {
+    fn pixel(&self, x: i64, y: i64) -> [f32; 4] {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return self.background;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        let px: [f32; 4] = std::array::from_fn(|c| self.data[i + c].to_f32());
+        if self.premultiplied {
+            return px;
+        }
+        let alpha = px[3] / T::MAX;
+        [px[0] * alpha, px[1] * alpha, px[2] * alpha, px[3]]
+    }
+
+    fn bilinear(&self, x: f64, y: f64) -> [f32; 4] {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = ((x - x0) as f32, (y - y0) as f32);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let taps = [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)];
+        let mut out = [0.0; 4];
+        for (ox, oy, weight) in taps {
+            let px = self.pixel(x0 + ox, y0 + oy);
+            for c in 0..4 {
+                out[c] += px[c] * weight;
+            }
+        }
+        out
+    }
+
+    fn bicubic(&self, x: f64, y: f64) -> [f32; 4] {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (wx, wy) = (catmull_rom((x - x0) as f32), catmull_rom((y - y0) as f32));
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let mut out = [0.0; 4];
+        for (j, wy) in wy.iter().enumerate() {
+            for (i, wx) in wx.iter().enumerate() {
+                let px = self.pixel(x0 + i as i64 - 1, y0 + j as i64 - 1);
+                for c in 0..4 {
+                    out[c] += px[c] * wx * wy;
+                }
+            }
+        }
+        // The spline overshoots at edges; keep alpha in range and colors within alpha.
+        out[3] = out[3].clamp(0.0, T::MAX);
+        for c in 0..3 {
+            out[c] = out[c].clamp(0.0, out[3]);
+        }
+        out
+    }
+
+    /// Writes a premultiplied value back in the buffer's alpha convention.
+    fn store(&self, value: [f32; 4], px: &mut [T]) {
+        let alpha = value[3] / T::MAX;
+        for c in 0..3 {
+            let v = if self.premultiplied {
+                value[c]
+            } else if alpha > 0.0 {
+                value[c] / alpha
+            } else {
+                0.0
+            };
+            px[c] = T::from_f32(v);
+        }
+        px[3] = T::from_f32(value[3]);
+    }
+}
+
+/// Catmull-Rom weights of the four taps around a sample at fraction `t` past the second one.
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        0.5 * (-t3 + 2.0 * t2 - t),
+        0.5 * (3.0 * t3 - 5.0 * t2 + 2.0),
+        0.5 * (-3.0 * t3 + 4.0 * t2 + t),
+        0.5 * (t3 - t2),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing::{self, Output};
+
+    /// Runs an RGBA8 image and returns the output, if one was allocated.
+    fn run(src: &[u8], width: u32, height: u32, params: &str) -> Result<Option<Output<u8>>, PluginError> {
+        testing::run_v2(process, width, height, src, params)
+    }
+
+    #[test]
+    fn test_quarter_turns_are_lossless() {
+        // 3x2 image with a distinct value per pixel.
+        let src: Vec<u8> = (0..6).flat_map(|i| [i, i, i, 255]).collect();
+        let cw = run(&src, 3, 2, r#"{"angle": 90}"#).unwrap().unwrap();
+        assert_eq!((cw.width, cw.height), (2, 3));
+        let firsts: Vec<u8> = cw.data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(firsts, [3, 0, 4, 1, 5, 2]);
+
+        let ccw = run(&src, 3, 2, r#"{"angle": -90}"#).unwrap().unwrap();
+        let firsts: Vec<u8> = ccw.data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(firsts, [2, 5, 1, 4, 0, 3]);
+
+        let half = run(&src, 3, 2, r#"{"angle": 180}"#).unwrap().unwrap();
+        let back = run(&half.data, 3, 2, r#"{"angle": 180}"#).unwrap().unwrap();
+        assert_eq!(back.data, src);
+
+        assert_eq!(run(&src, 3, 2, r#"{"angle": 360}"#), Ok(None));
+    }
+
+    #[test]
+    fn test_arbitrary_angle_size_and_background() {
+        let src = [200u8, 100, 50, 255].repeat(10 * 10);
+        let out = run(&src, 10, 10, r##"{"angle": 45, "background": "#0000ff"}"##).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (15, 15));
+        // The corner is background, the center keeps the uniform color.
+        assert_eq!(&out.data[..4], &[0, 0, 255, 255]);
+        let center = (7 * 15 + 7) * 4;
+        assert_eq!(&out.data[center..center + 4], &[200, 100, 50, 255]);
+
+        let kept = run(&src, 10, 10, r#"{"angle": 30, "expand": false, "interpolation": "bicubic"}"#).unwrap().unwrap();
+        assert_eq!((kept.width, kept.height), (10, 10));
+        assert_eq!(kept.data[3], 0);
+    }
+
+    #[test]
+    fn test_transparent_background_does_not_darken_edges() {
+        // With a transparent background, edge pixels fade out in alpha but keep their color.
+        let src = [255u8, 255, 255, 255].repeat(8 * 8);
+        let out = run(&src, 8, 8, r#"{"angle": 10}"#).unwrap().unwrap();
+        for px in out.data.chunks(4).filter(|p| p[3] > 16) {
+            assert!(px[0] >= 250, "{px:?}");
+        }
+    }
+}
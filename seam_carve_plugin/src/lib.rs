@@ -0,0 +1,312 @@
+use std::ffi::CStr;
+use plugin_sdk::{Allocator, CallContext, ImageRef, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Vertical seams to add (positive) or remove (negative), changing the width by as much.
+    #[serde(default)]
+    columns: i32,
+    /// Horizontal seams to add (positive) or remove (negative), changing the height by as much.
+    #[serde(default)]
+    rows: i32,
+}
+
+const MANIFEST: &CStr = cr#"name = "seam_carve_plugin"
+version = "0.1.0"
+description = "Content-aware resizing that removes or inserts low-energy seams"
+
+[defaults]
+columns = 0
+rows = 0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process_v2: process,
+}
+
+/// Carves both formats.
+///
+/// Fails if the seams to remove would leave no column or row.
+fn process(
+    image: ImageRef<'_>,
+    output: &mut Allocator<'_>,
+    params: &Params,
+    ctx: &CallContext,
+) -> Result<(), PluginError> {
+    let ImageRef { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let out_width = i64::from(width) + i64::from(params.columns);
+    let out_height = i64::from(height) + i64::from(params.rows);
+    if out_width < 1 || out_height < 1 || out_width > i64::from(u32::MAX) || out_height > i64::from(u32::MAX) {
+        return Err(PluginError::Invalid("the seams would leave no column or row"));
+    }
+
+    match pixels {
+        PixelsRef::Rgba8(src) => carve(src, (width, height), params, premultiplied, output),
+        PixelsRef::Rgba32F(src) => carve(src, (width, height), params, premultiplied, output),
+    }
+}
+
+/// A premultiplied working image.
+#[derive(Clone)]
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Image {
+    fn transposed(&self) -> Self {
+        let pixels = (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .map(|(x, y)| self.pixels[y * self.width + x])
+            .collect();
+        Self { width: self.height, height: self.width, pixels }
+    }
+
+    /// Gradient magnitude per pixel: the summed absolute differences of all channels to the four
+    /// neighbours. Unlike central differences, this does not miss one-pixel lines.
+    fn energy(&self) -> Vec<f32> {
+        let (w, h) = (self.width, self.height);
+        let mut energy = vec![0.0; w * h];
+        for y in 0..h {
+            for x in 0..w {
+                let diff = |a: usize, b: usize| -> f32 {
+                    (0..4).map(|c| (self.pixels[a][c] - self.pixels[b][c]).abs()).sum()
+                };
+                let (left, right) = (x.saturating_sub(1), (x + 1).min(w - 1));
+                let (up, down) = (y.saturating_sub(1), (y + 1).min(h - 1));
+                let i = y * w + x;
+                energy[i] =
+                    diff(i, y * w + left) + diff(i, y * w + right) + diff(i, up * w + x) + diff(i, down * w + x);
+            }
+        }
+        energy
+    }
+
+    /// The connected top-to-bottom path of least total energy, as one column per row.
+    fn cheapest_seam(&self) -> Vec<usize> {
+        let (w, h) = (self.width, self.height);
+        let mut cost = self.energy();
+        for y in 1..h {
+            for x in 0..w {
+                let above = (x.saturating_sub(1)..=(x + 1).min(w - 1))
+                    .map(|px| cost[(y - 1) * w + px])
+                    .fold(f32::INFINITY, f32::min);
+                cost[y * w + x] += above;
+            }
+        }
+        let last = &cost[(h - 1) * w..];
+        let mut x = (0..w).min_by(|&a, &b| last[a].total_cmp(&last[b])).unwrap_or(0);
+        let mut seam = vec![0; h];
+        for y in (0..h).rev() {
+            seam[y] = x;
+            if y > 0 {
+                let row = &cost[(y - 1) * w..y * w];
+                x = (x.saturating_sub(1)..=(x + 1).min(w - 1)).min_by(|&a, &b| row[a].total_cmp(&row[b])).unwrap_or(x);
+            }
+        }
+        seam
+    }
+
+    fn remove_seam(&mut self, seam: &[usize]) {
+        let w = self.width;
+        let mut pixels = Vec::with_capacity((w - 1) * self.height);
+        for (y, &skip) in seam.iter().enumerate() {
+            let row = &self.pixels[y * w..(y + 1) * w];
+            pixels.extend_from_slice(&row[..skip]);
+            pixels.extend_from_slice(&row[skip + 1..]);
+        }
+        self.pixels = pixels;
+        self.width -= 1;
+    }
+
+    /// Removes `count` seams one after the other, recomputing the energy each time.
+    fn shrink(&mut self, count: usize) {
+        for _ in 0..count {
+            let seam = self.cheapest_seam();
+            self.remove_seam(&seam);
+        }
+    }
+
+    /// Widens by `count` columns, in batches of at most half the current width.
+    ///
+    /// Each batch finds the seams that removal would take first and duplicates all of them at
+    /// once, each copy averaging its pixel with the right-hand neighbour; inserting the same
+    /// cheapest seam repeatedly would only stretch it.
+    fn grow(&mut self, mut count: usize) {
+        while count > 0 {
+            let batch = count.min((self.width / 2).max(1));
+            self.insert_seams(batch);
+            count -= batch;
+        }
+    }
+
+    fn insert_seams(&mut self, count: usize) {
+        let (w, h) = (self.width, self.height);
+        // Remove seams from a copy that remembers each pixel's original column.
+        let mut work = self.clone();
+        let mut columns: Vec<usize> = (0..h).flat_map(|_| 0..w).collect();
+        let mut duplicate = vec![false; w * h];
+        for _ in 0..count {
+            let seam = work.cheapest_seam();
+            let ww = work.width;
+            for (y, &x) in seam.iter().enumerate() {
+                duplicate[y * w + columns[y * ww + x]] = true;
+            }
+            columns = seam
+                .iter()
+                .enumerate()
+                .flat_map(|(y, &x)| {
+                    let row = &columns[y * ww..(y + 1) * ww];
+                    row[..x].iter().chain(&row[x + 1..]).copied().collect::<Vec<_>>()
+                })
+                .collect();
+            work.remove_seam(&seam);
+        }
+
+        let mut pixels = Vec::with_capacity((w + count) * h);
+        for y in 0..h {
+            let row = &self.pixels[y * w..(y + 1) * w];
+            for x in 0..w {
+                pixels.push(row[x]);
+                if duplicate[y * w + x] {
+                    let right = row[(x + 1).min(w - 1)];
+                    pixels.push(std::array::from_fn(|c| (row[x][c] + right[c]) / 2.0));
+                }
+            }
+        }
+        self.pixels = pixels;
+        self.width += count;
+    }
+
+    /// Adds (positive) or removes (negative) `delta` vertical seams.
+    fn resize_width(&mut self, delta: i32) {
+        let count = delta.unsigned_abs() as usize;
+        if delta < 0 {
+            self.shrink(count);
+        } else {
+            self.grow(count);
+        }
+    }
+}
+
+/// Changes the width and then the height by the requested number of seams into a buffer
+/// allocated from `output`. Horizontal seams are found as vertical seams of the transposed
+/// image.
+///
+/// Fails if the host refused the buffer; an unchanged size allocates nothing.
+fn carve<T: Sample>(
+    src: &[T],
+    (width, height): (u32, u32),
+    params: &Params,
+    premultiplied: bool,
+    output: &mut Allocator<'_>,
+) -> Result<(), PluginError> {
+    if params.columns == 0 && params.rows == 0 {
+        return Ok(());
+    }
+
+    let pixels = src
+        .chunks_exact(4)
+        .map(|px| {
+            let alpha = if premultiplied { 1.0 } else { px[3].to_f32() / T::MAX };
+            [px[0].to_f32() * alpha, px[1].to_f32() * alpha, px[2].to_f32() * alpha, px[3].to_f32()]
+        })
+        .collect();
+    let mut image = Image { width: width as usize, height: height as usize, pixels };
+    if params.columns != 0 {
+        image.resize_width(params.columns);
+    }
+    if params.rows != 0 {
+        let mut turned = image.transposed();
+        turned.resize_width(params.rows);
+        image = turned.transposed();
+    }
+
+    let dst = output.alloc::<T>(image.width as u32, image.height as u32)?;
+    for (out, px) in dst.chunks_exact_mut(4).zip(&image.pixels) {
+        store(px, out, premultiplied);
+    }
+    Ok(())
+}
+
+/// Writes a premultiplied sample in the buffer's alpha convention.
+fn store<T: Sample>(px: &[f32; 4], out: &mut [T], premultiplied: bool) {
+    let scale = px[3] / T::MAX;
+    for c in 0..3 {
+        out[c] = T::from_f32(if premultiplied {
+            px[c]
+        } else if scale > 0.0 {
+            px[c] / scale
+        } else {
+            0.0
+        });
+    }
+    out[3] = T::from_f32(px[3]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing::{self, Output};
+
+    /// Runs an RGBA8 image and returns the output, if one was allocated.
+    fn run(src: &[u8], width: u32, height: u32, params: &str) -> Result<Option<Output<u8>>, PluginError> {
+        testing::run_v2(process, width, height, src, params)
+    }
+
+    /// A flat gray image crossed by a one-pixel red line, vertical at column `at` if `vertical`,
+    /// else horizontal at row `at`.
+    fn lined(width: usize, height: usize, at: usize, vertical: bool) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let on = if vertical { i % width == at } else { i / width == at };
+                if on { [255, 0, 0, 255] } else { [128, 128, 128, 255] }
+            })
+            .collect()
+    }
+
+    fn red_count(data: &[u8]) -> usize {
+        data.chunks(4).filter(|p| p == &[255, 0, 0, 255]).count()
+    }
+
+    #[test]
+    fn test_removal_keeps_detail() {
+        let out = run(&lined(10, 4, 6, true), 10, 4, r#"{"columns": -4}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (6, 4));
+        // Every row keeps its red pixel, and the line stays straight.
+        let reds: Vec<usize> =
+            out.data.chunks(4).enumerate().filter(|(_, p)| p[0] == 255).map(|(i, _)| i % 6).collect();
+        assert_eq!(reds.len(), 4);
+        assert!(reds.iter().all(|&x| x == reds[0]));
+        assert!(out.data.chunks(4).all(|p| p == [255, 0, 0, 255] || p == [128, 128, 128, 255]));
+
+        let out = run(&lined(4, 9, 2, false), 4, 9, r#"{"rows": -5}"#).unwrap().unwrap();
+        assert_eq!(((out.width, out.height), red_count(&out.data)), ((4, 4), 4));
+    }
+
+    #[test]
+    fn test_insertion_spreads_seams() {
+        let out = run(&lined(8, 3, 1, true), 8, 3, r#"{"columns": 6, "rows": 2}"#).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (14, 5));
+        // The line is neither widened nor blended away; the gray area is stretched instead.
+        let reds: Vec<usize> =
+            out.data.chunks(4).enumerate().filter(|(_, p)| p[0] == 255).map(|(i, _)| i % 14).collect();
+        assert_eq!(reds.len(), 5);
+        assert!(reds.iter().all(|&x| x == reds[0]));
+        assert!(out.data.chunks(4).filter(|p| p[0] != 255).all(|p| p == [128, 128, 128, 255]));
+    }
+
+    #[test]
+    fn test_invalid_and_unchanged() {
+        let src = lined(4, 4, 1, true);
+        assert!(matches!(run(&src, 4, 4, r#"{"columns": -4}"#), Err(PluginError::Invalid(_))));
+        assert!(matches!(run(&src, 4, 4, r#"{"rows": -9}"#), Err(PluginError::Invalid(_))));
+        assert_eq!(run(&src, 4, 4, "{}"), Ok(None));
+    }
+}
@@ -0,0 +1,226 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Adds back the difference from a Gaussian blur of `radius`.
+    #[default]
+    Unsharp,
+    /// The 3x3 kernel `[0 -1 0; -1 5 -1; 0 -1 0]` at `amount` 1; quick, for small touch-ups.
+    Kernel,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// How much of the detail is added back; 0 leaves the image unchanged.
+    #[serde(default = "default_amount")]
+    amount: f32,
+    /// Standard deviation of the unsharp-mask blur in pixels; larger values sharpen coarser detail.
+    #[serde(default = "default_radius")]
+    radius: f32,
+    /// Smallest difference from the blur, between sRGB-encoded values in units of full scale,
+    /// that is sharpened; raise it to leave noise and smooth gradients alone.
+    #[serde(default)]
+    threshold: f32,
+}
+
+fn default_amount() -> f32 {
+    1.0
+}
+
+fn default_radius() -> f32 {
+    1.0
+}
+
+impl Params {
+    /// How far the filter reaches from each pixel.
+    fn reach(&self) -> u32 {
+        match self.mode {
+            Mode::Unsharp => (3.0 * self.radius).ceil().max(0.0) as u32,
+            Mode::Kernel => 1,
+        }
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "sharpen_plugin"
+version = "0.1.0"
+description = "Unsharp mask with threshold, or a 3x3 sharpen kernel"
+
+[defaults]
+mode = "unsharp"
+amount = 1.0
+radius = 1.0
+threshold = 0.0
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Sharpens both formats on the host's thread budget.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    let run = move || match pixels {
+        Pixels::Rgba8(buf) => sharpen(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => sharpen(w, h, buf, params, premultiplied),
+    };
+
+    plugin_sdk::install(ctx, run)
+}
+
+/// The unsharp mask reaches three times its radius and the kernel one pixel.
+fn halo(params: &Params) -> Option<u32> {
+    Some(params.reach())
+}
+
+/// Adds `amount` times the difference between each pixel and its blurred surroundings.
+///
+/// Only the color channels are sharpened; alpha is kept. Pixels whose largest difference is
+/// below `threshold` are left alone. Overshoot is clamped at zero, and at alpha for
+/// premultiplied data.
+fn sharpen<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 || params.amount == 0.0 {
+        return;
+    }
+    let src: Vec<f32> = buf.par_iter().map(|v| v.to_f32()).collect();
+    let blurred = match params.mode {
+        Mode::Unsharp if params.radius > 0.0 => gaussian(&src, width, height, params.radius),
+        Mode::Unsharp => return,
+        Mode::Kernel => cross_mean(&src, width, height),
+    };
+    // The kernel is `v + 4 * (v - mean of the four neighbors)` at amount 1.
+    let amount = match params.mode {
+        Mode::Unsharp => params.amount,
+        Mode::Kernel => 4.0 * params.amount,
+    };
+
+    buf.par_chunks_exact_mut(4).enumerate().for_each(|(i, px)| {
+        let (v, b) = (&src[i * 4..i * 4 + 4], &blurred[i * 4..i * 4 + 4]);
+        if params.threshold > 0.0 {
+            let encoded = |x: f32| plugin_sdk::to_encoded::<T>(x / T::MAX);
+            let largest = (0..3).map(|c| (encoded(v[c]) - encoded(b[c])).abs()).fold(0.0, f32::max);
+            if largest < params.threshold {
+                return;
+            }
+        }
+        let ceiling = if premultiplied { v[3] } else { f32::INFINITY };
+        for c in 0..3 {
+            px[c] = T::from_f32((v[c] + amount * (v[c] - b[c])).clamp(0.0, ceiling));
+        }
+    });
+}
+
+/// Separable Gaussian blur with standard deviation `sigma`, clamping at the edges.
+fn gaussian(src: &[f32], width: usize, height: usize, sigma: f32) -> Vec<f32> {
+    let r = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let norm: f32 = kernel.iter().sum();
+    let kernel: Vec<f32> = kernel.iter().map(|k| k / norm).collect();
+    let row_len = width * 4;
+
+    let mut rows = vec![0.0f32; src.len()];
+    rows.par_chunks_exact_mut(row_len).zip(src.par_chunks_exact(row_len)).for_each(|(dst, src)| {
+        for x in 0..width {
+            for (k, w) in kernel.iter().enumerate() {
+                let sx = (x as i64 + k as i64 - r).clamp(0, width as i64 - 1) as usize;
+                for c in 0..4 {
+                    dst[x * 4 + c] += src[sx * 4 + c] * w;
+                }
+            }
+        }
+    });
+    let mut out = vec![0.0f32; src.len()];
+    out.par_chunks_exact_mut(row_len).enumerate().for_each(|(y, dst)| {
+        for (k, w) in kernel.iter().enumerate() {
+            let sy = (y as i64 + k as i64 - r).clamp(0, height as i64 - 1) as usize;
+            for (d, v) in dst.iter_mut().zip(&rows[sy * row_len..(sy + 1) * row_len]) {
+                *d += v * w;
+            }
+        }
+    });
+    out
+}
+
+/// Mean of the four direct neighbors of each pixel, clamping at the edges.
+fn cross_mean(src: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut out = vec![0.0f32; src.len()];
+    out.par_chunks_exact_mut(width * 4).enumerate().for_each(|(y, dst)| {
+        for x in 0..width {
+            let neighbors = [
+                (x.saturating_sub(1), y),
+                ((x + 1).min(width - 1), y),
+                (x, y.saturating_sub(1)),
+                (x, (y + 1).min(height - 1)),
+            ];
+            for (nx, ny) in neighbors {
+                for c in 0..4 {
+                    dst[x * 4 + c] += src[(ny * width + nx) * 4 + c] / 4.0;
+                }
+            }
+        }
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 6x1 step from 80 to 160.
+    fn step() -> Vec<u8> {
+        (0..6).flat_map(|x| if x < 3 { [80, 80, 80, 255] } else { [160, 160, 160, 255] }).collect()
+    }
+
+    fn reds(img: &[u8]) -> Vec<u8> {
+        img.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_unsharp_increases_edge_contrast() {
+        let mut img = step();
+        run(&mut img, 6, 1, r#"{"amount": 1.0, "radius": 1.0}"#).unwrap();
+        let out = reds(&img);
+        assert!(out[2] < 80 && out[3] > 160, "{out:?}");
+        // Far from the edge, nothing changes; alpha is kept.
+        assert_eq!((out[0], out[5]), (80, 160));
+        assert!(img.chunks(4).all(|p| p[3] == 255));
+
+        // A threshold above the step's local difference leaves the image alone.
+        let mut img = step();
+        run(&mut img, 6, 1, r#"{"threshold": 0.5}"#).unwrap();
+        assert_eq!(img, step());
+    }
+
+    #[test]
+    fn test_kernel_mode() {
+        // 3x3 with a brighter center: 5 * 120 - 4 * 100 = 200.
+        let mut img = [100u8, 100, 100, 255].repeat(9);
+        img[16..19].fill(120);
+        run(&mut img, 3, 3, r#"{"mode": "kernel"}"#).unwrap();
+        assert_eq!(&img[16..20], &[200, 200, 200, 255]);
+
+        let flat = [90u8, 60, 30, 255].repeat(9);
+        let mut img = flat.clone();
+        run(&mut img, 3, 3, r#"{"mode": "kernel", "amount": 3.0}"#).unwrap();
+        assert_eq!(img, flat);
+
+        let params = plugin_sdk::params_from_str(r#"{"radius": 2.0}"#).unwrap();
+        assert_eq!(halo(&params), Some(6));
+    }
+}
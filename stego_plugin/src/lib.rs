@@ -0,0 +1,274 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Rng, FORMAT_MASK_RGBA8};
+use serde::Deserialize;
+
+/// Marks the start of an embedded payload; a mismatch on extraction means there is none, or
+/// the password is wrong.
+const MAGIC: &[u8; 4] = b"STG1";
+
+/// Bytes of framing around the payload: the magic, the payload length and a checksum.
+const FRAMING: usize = MAGIC.len() + 4 + 4;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Mode {
+    /// Hides the payload in the image.
+    #[default]
+    Embed,
+    /// Reads a payload hidden by `embed` and writes it to `output`; the image is left as it is.
+    Extract,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    mode: Mode,
+    /// Embed: text to hide.
+    #[serde(default)]
+    message: String,
+    /// Embed: path of a file whose bytes to hide, instead of `message`.
+    #[serde(default)]
+    payload_file: String,
+    /// Extract: path of the file the payload is written to.
+    #[serde(default)]
+    output: String,
+    /// Scatters the payload over the image in an order derived from it, and scrambles its bytes;
+    /// empty for none. This hides the payload from casual inspection but is not encryption.
+    #[serde(default)]
+    password: String,
+}
+
+const MANIFEST: &CStr = cr#"name = "stego_plugin"
+version = "0.1.0"
+description = "Hides a byte payload in the least significant bits of the image, or extracts it"
+
+[defaults]
+mode = "embed"
+message = ""
+payload_file = ""
+output = ""
+password = ""
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    formats: FORMAT_MASK_RGBA8,
+}
+
+/// Embeds or extracts the payload in RGBA8 buffers.
+///
+/// Embedding fails if both or neither of `message` and `payload_file` are given, the file cannot
+/// be read, or the payload does not fit. Extraction fails if `output` is empty, no payload is
+/// found (or the password differs), or the file cannot be written.
+fn process(image: Image<'_>, params: &Params, _ctx: &CallContext) -> Result<(), PluginError> {
+    let Pixels::Rgba8(buf) = image.pixels else {
+        return Err(PluginError::Format);
+    };
+
+    match params.mode {
+        Mode::Embed => {
+            let payload = match (params.message.is_empty(), params.payload_file.is_empty()) {
+                (false, true) => params.message.as_bytes().to_vec(),
+                (true, false) => std::fs::read(&params.payload_file)?,
+                _ => return Err(PluginError::Invalid("give exactly one of message and payload_file")),
+            };
+            if !embed(buf, &payload, &params.password) {
+                return Err(PluginError::Invalid("the payload does not fit in the image"));
+            }
+        }
+        Mode::Extract => {
+            if params.output.is_empty() {
+                return Err(PluginError::Invalid("output is required to extract"));
+            }
+            let payload = extract(buf, &params.password).ok_or(PluginError::Invalid("no payload found"))?;
+            std::fs::write(&params.output, payload)?;
+        }
+    }
+    Ok(())
+}
+
+/// 64-bit FNV-1a hash.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01B3))
+}
+
+/// The bytes carrying the payload's bits, in the order they are used: the color channels of the
+/// opaque pixels, so extraction finds the same ones whatever the alpha convention. With a
+/// password they are visited in a shuffled order, drawn one at a time by a partial
+/// Fisher-Yates shuffle so only the positions used are computed.
+struct Carriers {
+    slots: Vec<usize>,
+    used: usize,
+    shuffle: Option<Rng>,
+}
+
+impl Carriers {
+    fn new(buf: &[u8], password: &str) -> Self {
+        let slots = buf
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, px)| px[3] == u8::MAX)
+            .flat_map(|(i, _)| (0..3).map(move |c| i * 4 + c))
+            .collect();
+        let shuffle = (!password.is_empty()).then(|| Rng::new(fnv1a(password.as_bytes())));
+        Self { slots, used: 0, shuffle }
+    }
+
+    /// Payload bytes the carriers can hold, framing excluded.
+    fn capacity(&self) -> usize {
+        (self.slots.len() / 8).saturating_sub(FRAMING)
+    }
+}
+
+impl Iterator for Carriers {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let remaining = self.slots.len().checked_sub(self.used).filter(|&n| n > 0)?;
+        if let Some(rng) = &mut self.shuffle {
+            let pick = self.used + (rng.next_u64() % remaining as u64) as usize;
+            self.slots.swap(self.used, pick);
+        }
+        self.used += 1;
+        Some(self.slots[self.used - 1])
+    }
+}
+
+/// The keystream the framed payload is XORed with: zeros without a password, so the bytes are
+/// stored as they are.
+fn keystream(password: &str) -> impl Iterator<Item = u8> {
+    let mut rng = (!password.is_empty()).then(|| Rng::new(fnv1a(password.as_bytes()) ^ 0x5354_4547_4B45_5953));
+    std::iter::repeat_with(move || rng.as_mut().map_or(0, |r| r.next_u64() as u8))
+}
+
+/// Writes the magic, the payload's length, the payload and its checksum into the lowest bit of
+/// the carrier bytes, most significant bit first. Returns `false`, leaving the image unchanged,
+/// if it does not fit.
+fn embed(buf: &mut [u8], payload: &[u8], password: &str) -> bool {
+    let carriers = Carriers::new(buf, password);
+    let Ok(length) = u32::try_from(payload.len()) else {
+        return false;
+    };
+    if payload.len() > carriers.capacity() {
+        return false;
+    }
+    let (length, checksum) = (length.to_be_bytes(), (fnv1a(payload) as u32).to_be_bytes());
+    let framed = MAGIC.iter().chain(&length).chain(payload).chain(&checksum);
+    let bits = framed.zip(keystream(password)).flat_map(|(b, k)| (0..8).rev().map(move |i| (b ^ k) >> i & 1));
+    for (slot, bit) in carriers.zip(bits) {
+        buf[slot] = buf[slot] & !1 | bit;
+    }
+    true
+}
+
+/// Reads back a payload written by [`embed`] with the same password; `None` if the magic or the
+/// checksum does not match.
+fn extract(buf: &[u8], password: &str) -> Option<Vec<u8>> {
+    let carriers = Carriers::new(buf, password);
+    let capacity = carriers.capacity();
+    let mut bits = carriers.map(|slot| buf[slot] & 1);
+    let mut keys = keystream(password);
+    let mut read = |count: usize| -> Option<Vec<u8>> {
+        (0..count)
+            .map(|_| {
+                let byte = (0..8).try_fold(0u8, |acc, _| Some(acc << 1 | bits.next()?))?;
+                Some(byte ^ keys.next()?)
+            })
+            .collect()
+    };
+
+    if read(MAGIC.len())? != MAGIC {
+        return None;
+    }
+    let length = u32::from_be_bytes(read(4)?.try_into().ok()?) as usize;
+    if length > capacity {
+        return None;
+    }
+    let payload = read(length)?;
+    let checksum = u32::from_be_bytes(read(4)?.try_into().ok()?);
+    (checksum == fnv1a(&payload) as u32).then_some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+    use std::path::{Path, PathBuf};
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// A 16x16 opaque gradient, with a transparent first row.
+    fn image() -> Vec<u8> {
+        (0..256u32).flat_map(|i| [i as u8, (i * 7) as u8, 255 - i as u8, if i < 16 { 0 } else { 255 }]).collect()
+    }
+
+    /// Creates a new directory for the test `name`; the test removes it at the end.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stego-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Path of the file `name` in `dir`, as written in params.
+    fn file_in(dir: &Path, name: &str) -> String {
+        dir.join(name).to_str().unwrap().replace('\\', "/")
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = test_dir("round-trip");
+        for password in ["", "hunter2"] {
+            let mut img = image();
+            let params = format!(r#"{{"message": "copy #17 for reviewer B", "password": "{password}"}}"#);
+            run(&mut img, 16, 16, &params).unwrap();
+            // Only the lowest bits of opaque colors change.
+            for (i, (&a, &b)) in img.iter().zip(&image()).enumerate() {
+                assert!(a.abs_diff(b) <= 1, "byte {i}");
+                assert!(a == b || (i % 4 != 3 && i >= 16 * 4), "byte {i}");
+            }
+
+            let output = file_in(&dir, &format!("payload{}", password.len()));
+            let params = format!(r#"{{"mode": "extract", "output": "{output}", "password": "{password}"}}"#);
+            let marked = img.clone();
+            run(&mut img, 16, 16, &params).unwrap();
+            assert_eq!(img, marked);
+            assert_eq!(std::fs::read(&output).unwrap(), b"copy #17 for reviewer B");
+
+            // The wrong password finds nothing.
+            let params = format!(r#"{{"mode": "extract", "output": "{output}", "password": "{password}x"}}"#);
+            assert!(run(&mut img, 16, 16, &params).is_err());
+        }
+        let output = file_in(&dir, "none");
+        assert!(run(&mut image(), 16, 16, &format!(r#"{{"mode": "extract", "output": "{output}"}}"#)).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_payload_file_and_capacity() {
+        let dir = test_dir("payload-file");
+        let payload: Vec<u8> = (0..=255).collect();
+        let input = file_in(&dir, "input.bin");
+        std::fs::write(&input, &payload).unwrap();
+        let mut img = image();
+        // 240 opaque pixels hold 90 bytes, 12 of them framing.
+        assert!(run(&mut img, 16, 16, &format!(r#"{{"payload_file": "{input}"}}"#)).is_err());
+        assert_eq!(img, image());
+
+        std::fs::write(&input, &payload[..78]).unwrap();
+        run(&mut img, 16, 16, &format!(r#"{{"payload_file": "{input}", "password": "p"}}"#)).unwrap();
+        let output = file_in(&dir, "output.bin");
+        let params = format!(r#"{{"mode": "extract", "output": "{output}", "password": "p"}}"#);
+        run(&mut img, 16, 16, &params).unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), &payload[..78]);
+
+        assert!(run(&mut img, 16, 16, "{}").is_err());
+        assert!(run(&mut img, 16, 16, &format!(r#"{{"message": "a", "payload_file": "{input}"}}"#)).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+use std::ffi::CStr;
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont, point};
+use plugin_sdk::{CallContext, Color, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// DejaVu Sans Mono, used when no `font` is given; see `fonts/LICENSE`.
+static BUNDLED_FONT: &[u8] = include_bytes!("../fonts/DejaVuSansMono.ttf");
+
+/// Where the text block is placed, inset by `margin`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum Position {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    #[default]
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Position {
+    /// Horizontal and vertical alignment: 0 is the start, 1 the middle and 2 the end.
+    fn alignment(self) -> (u8, u8) {
+        match self {
+            Self::TopLeft => (0, 0),
+            Self::Top => (1, 0),
+            Self::TopRight => (2, 0),
+            Self::Left => (0, 1),
+            Self::Center => (1, 1),
+            Self::Right => (2, 1),
+            Self::BottomLeft => (0, 2),
+            Self::Bottom => (1, 2),
+            Self::BottomRight => (2, 2),
+        }
+    }
+}
+
+/// How the lines of the text are aligned with each other.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// The text to draw; `\n` starts a new line.
+    text: String,
+    /// Path of a TrueType or OpenType font; the bundled DejaVu Sans Mono if empty.
+    #[serde(default)]
+    font: String,
+    /// Font size in pixels.
+    #[serde(default = "default_size")]
+    size: f32,
+    #[serde(default = "default_color")]
+    color: Color,
+    #[serde(default)]
+    position: Position,
+    /// Distance in pixels from the image edges.
+    #[serde(default = "default_margin")]
+    margin: u32,
+    #[serde(default)]
+    align: Align,
+    /// Width in pixels of the outline around the glyphs; 0 draws none.
+    #[serde(default)]
+    outline: f32,
+    #[serde(default = "default_outline_color")]
+    outline_color: Color,
+}
+
+fn default_size() -> f32 {
+    32.0
+}
+
+fn default_color() -> Color {
+    Color::WHITE
+}
+
+fn default_margin() -> u32 {
+    16
+}
+
+fn default_outline_color() -> Color {
+    Color::BLACK
+}
+
+const MANIFEST: &CStr = cr##"name = "text_plugin"
+version = "0.1.0"
+description = "Draws UTF-8 text with a bundled or given font, optionally outlined"
+
+[defaults]
+font = ""
+size = 32.0
+color = "#ffffff"
+position = "bottom_left"
+margin = 16
+align = "left"
+outline = 0.0
+outline_color = "#000000"
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    required: r#"text = "proof""#,
+}
+
+/// Draws the text onto both formats.
+///
+/// Fails if the size or outline is not a number, or the font cannot be read or parsed.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.size.is_nan() || params.size <= 0.0 || params.outline.is_nan() {
+        return Err(PluginError::Invalid("size and outline must be numbers, size above 0"));
+    }
+    let font = if params.font.is_empty() {
+        FontArc::try_from_slice(BUNDLED_FONT).ok()
+    } else {
+        std::fs::read(&params.font).ok().and_then(|data| FontArc::try_from_vec(data).ok())
+    };
+    let Some(font) = font else {
+        return Err(PluginError::Invalid("cannot read or parse the font"));
+    };
+    let mask = render(&font, params);
+
+    match pixels {
+        Pixels::Rgba8(buf) => composite(width, height, buf, &mask, params, premultiplied),
+        Pixels::Rgba32F(buf) => composite(width, height, buf, &mask, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Coverage of the glyphs and of their outline over the text block, including the outline.
+struct Mask {
+    width: usize,
+    height: usize,
+    glyphs: Vec<f32>,
+    outline: Vec<f32>,
+}
+
+/// Lays out the lines with kerning and rasterizes them into a mask, padded by the outline width.
+fn render(font: &FontArc, params: &Params) -> Mask {
+    let scaled = font.as_scaled(PxScale::from(params.size));
+    let line_height = scaled.height() + scaled.line_gap();
+    let lines: Vec<Vec<_>> = params
+        .text
+        .lines()
+        .map(|line| {
+            let mut caret = 0.0f32;
+            let mut previous = None;
+            line.chars()
+                .map(|ch| {
+                    let id = scaled.glyph_id(ch);
+                    if let Some(previous) = previous {
+                        caret += scaled.kern(previous, id);
+                    }
+                    let x = caret;
+                    caret += scaled.h_advance(id);
+                    previous = Some(id);
+                    (id, x, caret)
+                })
+                .collect()
+        })
+        .collect();
+    let widths: Vec<f32> = lines.iter().map(|l| l.last().map_or(0.0, |g| g.2)).collect();
+    let block = widths.iter().copied().fold(0.0, f32::max);
+
+    let pad = params.outline.max(0.0).ceil() as usize + 1;
+    let width = block.ceil() as usize + 2 * pad;
+    let height = (line_height * lines.len() as f32).ceil() as usize + 2 * pad;
+    let mut glyphs = vec![0.0f32; width * height];
+    for (row, (line, line_width)) in lines.iter().zip(&widths).enumerate() {
+        let indent = match params.align {
+            Align::Left => 0.0,
+            Align::Center => (block - line_width) / 2.0,
+            Align::Right => block - line_width,
+        };
+        let baseline = pad as f32 + row as f32 * line_height + scaled.ascent();
+        for &(id, x, _) in line {
+            let glyph = id.with_scale_and_position(params.size, point(pad as f32 + indent + x, baseline));
+            let Some(outlined) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let (x, y) = (bounds.min.x as i64 + gx as i64, bounds.min.y as i64 + gy as i64);
+                if (0..width as i64).contains(&x) && (0..height as i64).contains(&y) {
+                    let v = &mut glyphs[y as usize * width + x as usize];
+                    *v = (*v + coverage).min(1.0);
+                }
+            });
+        }
+    }
+    let outline = dilate(&glyphs, width, height, params.outline);
+    Mask { width, height, glyphs, outline }
+}
+
+/// Grows the coverage by `radius` pixels with a round brush, antialiased at its edge.
+fn dilate(src: &[f32], width: usize, height: usize, radius: f32) -> Vec<f32> {
+    if radius <= 0.0 {
+        return vec![0.0; src.len()];
+    }
+    let reach = radius.ceil() as i64 + 1;
+    let brush: Vec<(i64, i64, f32)> = (-reach..=reach)
+        .flat_map(|dy| (-reach..=reach).map(move |dx| (dx, dy)))
+        .map(|(dx, dy)| (dx, dy, (radius + 0.5 - (dx as f32).hypot(dy as f32)).clamp(0.0, 1.0)))
+        .filter(|b| b.2 > 0.0)
+        .collect();
+    let mut out = vec![0.0f32; src.len()];
+    for y in 0..height as i64 {
+        for x in 0..width as i64 {
+            let v = src[(y * width as i64 + x) as usize];
+            if v <= 0.0 {
+                continue;
+            }
+            for &(dx, dy, weight) in &brush {
+                let (nx, ny) = (x + dx, y + dy);
+                if (0..width as i64).contains(&nx) && (0..height as i64).contains(&ny) {
+                    let o = &mut out[(ny * width as i64 + nx) as usize];
+                    *o = o.max(v * weight);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A color as premultiplied values in the scale of `T`.
+fn premultiplied_color<T: Sample>(color: Color) -> [f32; 4] {
+    let [r, g, b, a] = color.normalized::<T>();
+    [r * a * T::MAX, g * a * T::MAX, b * a * T::MAX, a * T::MAX]
+}
+
+/// Draws the outline and then the glyphs over the image with the "over" operator.
+fn composite<T: Sample>(width: u32, height: u32, buf: &mut [T], mask: &Mask, params: &Params, premultiplied: bool) {
+    let (w, h) = (width as i64, height as i64);
+    let (mw, mh, margin) = (mask.width as i64, mask.height as i64, params.margin as i64);
+    let place = |align: u8, size: i64, inner: i64| match align {
+        0 => margin,
+        1 => (size - inner) / 2,
+        _ => size - inner - margin,
+    };
+    let (ax, ay) = params.position.alignment();
+    let (ox, oy) = (place(ax, w, mw), place(ay, h, mh));
+    let color = premultiplied_color::<T>(params.color);
+    let outline_color = premultiplied_color::<T>(params.outline_color);
+    let over = |top: [f32; 4], bottom: [f32; 4]| -> [f32; 4] {
+        let rest = 1.0 - top[3] / T::MAX;
+        std::array::from_fn(|c| top[c] + bottom[c] * rest)
+    };
+
+    for my in (0.max(-oy))..mh.min(h - oy) {
+        for mx in (0.max(-ox))..mw.min(w - ox) {
+            let i = (my * mw + mx) as usize;
+            let (glyph, outline) = (mask.glyphs[i], mask.outline[i]);
+            if glyph <= 0.0 && outline <= 0.0 {
+                continue;
+            }
+            let px = &mut buf[(((oy + my) * w + ox + mx) * 4) as usize..][..4];
+            let alpha = px[3].to_f32() / T::MAX;
+            let scale = if premultiplied { 1.0 } else { alpha };
+            let below = [px[0].to_f32() * scale, px[1].to_f32() * scale, px[2].to_f32() * scale, px[3].to_f32()];
+            let value = over(color.map(|v| v * glyph), over(outline_color.map(|v| v * outline), below));
+            let divisor = if premultiplied || value[3] <= 0.0 { 1.0 } else { value[3] / T::MAX };
+            for c in 0..3 {
+                px[c] = T::from_f32(value[c] / divisor);
+            }
+            px[3] = T::from_f32(value[3]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// Bounding box `(min x, min y, max x, max y)` of the pixels that are no longer black.
+    fn inked(img: &[u8], width: usize) -> (usize, usize, usize, usize) {
+        img.chunks(4).enumerate().filter(|(_, p)| p[..3] != [0, 0, 0]).fold(
+            (usize::MAX, usize::MAX, 0, 0),
+            |(x0, y0, x1, y1), (i, _)| (x0.min(i % width), y0.min(i / width), x1.max(i % width), y1.max(i / width)),
+        )
+    }
+
+    #[test]
+    fn test_draws_text_at_position() {
+        let black = [0u8, 0, 0, 255].repeat(120 * 60);
+        let mut img = black.clone();
+        run(&mut img, 120, 60, r#"{"text": "Hi", "size": 20.0, "margin": 4}"#).unwrap();
+        let (x0, y0, x1, y1) = inked(&img, 120);
+        assert!((4..10).contains(&x0) && x1 < 40, "{x0}..{x1}");
+        assert!(y0 > 25 && y1 < 56, "{y0}..{y1}");
+        assert!(img.chunks(4).any(|p| p == [255, 255, 255, 255]));
+        assert!(img.chunks(4).all(|p| p[0] == p[1] && p[1] == p[2] && p[3] == 255));
+
+        let mut img = black.clone();
+        let params = r#"{"text": "Hi", "size": 20.0, "margin": 4, "position": "top_right"}"#;
+        run(&mut img, 120, 60, params).unwrap();
+        let (x0, y0, x1, _) = inked(&img, 120);
+        assert!(x0 > 80 && x1 < 116 && y0 < 12, "{x0}..{x1}, {y0}");
+    }
+
+    #[test]
+    fn test_lines_and_alignment() {
+        let draw = |align: &str| {
+            let mut img = [0u8, 0, 0, 255].repeat(200 * 100);
+            let params =
+                format!(r#"{{"text": "wide line\nx", "size": 16.0, "position": "top_left", "align": "{align}"}}"#);
+            run(&mut img, 200, 100, &params).unwrap();
+            // The second line's ink, below the first.
+            let second = &img[200 * 4 * 38..];
+            inked(second, 200)
+        };
+        let (left, center, right) = (draw("left").0, draw("center").0, draw("right").0);
+        assert!(left < center && center < right, "{left} {center} {right}");
+    }
+
+    #[test]
+    fn test_outline() {
+        let mut img = [0u8, 0, 255, 255].repeat(80 * 40);
+        let params = r##"{"text": "o", "size": 24.0, "color": "#ffffff", "outline": 2.0, "outline_color": "#ff0000"}"##;
+        run(&mut img, 80, 40, params).unwrap();
+        assert!(img.chunks(4).any(|p| p == [255, 0, 0, 255]));
+        assert!(img.chunks(4).any(|p| p == [255, 255, 255, 255]));
+
+        let mut img = [0u8; 4];
+        assert!(run(&mut img, 1, 1, r#"{"text": "x", "font": "/nonexistent/font.ttf"}"#).is_err());
+        assert!(run(&mut img, 1, 1, "{}").is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Rec. 709 weights for the luma of sRGB-encoded values.
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// How the threshold is chosen.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Method {
+    /// The `threshold` param.
+    #[default]
+    Fixed,
+    /// The level that best separates the luma histogram into two classes (Otsu's method).
+    Otsu,
+    /// The average of the window around each pixel minus `offset`; follows uneven lighting.
+    Adaptive,
+}
+
+/// Weighting of the adaptive window.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Window {
+    /// Equal weights over the square of `radius`.
+    #[default]
+    Mean,
+    /// A Gaussian with a standard deviation of half the radius.
+    Gaussian,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    method: Method,
+    /// Luma of the sRGB-encoded colors (0 to 1) above which pixels turn white, for `fixed`.
+    #[serde(default = "default_threshold")]
+    threshold: f32,
+    #[serde(default)]
+    window: Window,
+    /// Half-width of the adaptive window in pixels.
+    #[serde(default = "default_radius")]
+    radius: u32,
+    /// Subtracted from the adaptive window's average; raise it to keep faint texture white.
+    #[serde(default = "default_offset")]
+    offset: f32,
+    /// Swaps black and white.
+    #[serde(default)]
+    invert: bool,
+    /// Keeps alpha; otherwise the output is opaque.
+    #[serde(default = "default_keep_alpha")]
+    keep_alpha: bool,
+}
+
+fn default_threshold() -> f32 {
+    0.5
+}
+
+fn default_radius() -> u32 {
+    7
+}
+
+fn default_offset() -> f32 {
+    0.02
+}
+
+fn default_keep_alpha() -> bool {
+    true
+}
+
+const MANIFEST: &CStr = cr#"name = "threshold_plugin"
+version = "0.1.0"
+description = "Binarizes with a fixed, Otsu or adaptive threshold"
+
+[defaults]
+method = "fixed"
+threshold = 0.5
+window = "mean"
+radius = 7
+offset = 0.02
+invert = false
+keep_alpha = true
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Thresholds both formats.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let (w, h) = (width as usize, height as usize);
+    match pixels {
+        Pixels::Rgba8(buf) => binarize(w, h, buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => binarize(w, h, buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// A fixed threshold is per pixel and an adaptive one reaches its radius, while Otsu's method
+/// looks at the whole image.
+fn halo(params: &Params) -> Option<u32> {
+    match params.method {
+        Method::Fixed => Some(0),
+        Method::Adaptive => Some(params.radius),
+        Method::Otsu => None,
+    }
+}
+
+/// Turns every pixel black or white by comparing the luma of its straight sRGB-encoded color
+/// with the threshold for its position.
+fn binarize<T: Sample>(width: usize, height: usize, buf: &mut [T], params: &Params, premultiplied: bool) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let luma: Vec<f32> = buf.chunks_exact(4).map(|px| luma::<T>(px, premultiplied)).collect();
+    let thresholds: Vec<f32> = match params.method {
+        Method::Fixed => vec![params.threshold; luma.len()],
+        Method::Otsu => {
+            let visible = buf.chunks_exact(4).map(|px| px[3].to_f32() > 0.0);
+            let level = otsu(luma.iter().zip(visible).filter(|(_, v)| *v).map(|(l, _)| *l));
+            vec![level; luma.len()]
+        }
+        Method::Adaptive => {
+            let local = match params.window {
+                Window::Mean => box_mean(&luma, width, height, params.radius as usize),
+                Window::Gaussian => gaussian(&luma, width, height, params.radius),
+            };
+            local.iter().map(|m| m - params.offset).collect()
+        }
+    };
+
+    for ((px, l), t) in buf.chunks_exact_mut(4).zip(&luma).zip(&thresholds) {
+        let white = (l > t) != params.invert;
+        if !params.keep_alpha {
+            px[3] = T::from_f32(T::MAX);
+        }
+        let value = if !white {
+            0.0
+        } else if premultiplied {
+            px[3].to_f32()
+        } else {
+            T::MAX
+        };
+        for v in &mut px[..3] {
+            *v = T::from_f32(value);
+        }
+    }
+}
+
+/// Otsu's threshold over a 256-bin histogram: the level maximizing the variance between the
+/// pixels at or below it and those above it.
+fn otsu(values: impl Iterator<Item = f32>) -> f32 {
+    let mut hist = [0u64; 256];
+    for v in values {
+        hist[(v.clamp(0.0, 1.0) * 255.0).round() as usize] += 1;
+    }
+    let total: u64 = hist.iter().sum();
+    let sum: f64 = hist.iter().enumerate().map(|(i, n)| i as f64 * *n as f64).sum();
+    let (mut below, mut below_sum) = (0u64, 0.0f64);
+    let (mut best, mut best_variance) = (127usize, -1.0f64);
+    for (level, n) in hist.iter().enumerate() {
+        below += n;
+        below_sum += level as f64 * *n as f64;
+        let above = total - below;
+        if below == 0 || above == 0 {
+            continue;
+        }
+        let (mean_below, mean_above) = (below_sum / below as f64, (sum - below_sum) / above as f64);
+        let variance = below as f64 * above as f64 * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            (best, best_variance) = (level, variance);
+        }
+    }
+    // Pixels strictly above the threshold are white, so the level itself stays black.
+    (best as f32 + 0.5) / 255.0
+}
+
+/// Averages `src` over the `2 * r + 1` square around each pixel, clipped to the image.
+fn box_mean(src: &[f32], width: usize, height: usize, r: usize) -> Vec<f32> {
+    // Summed-area table with a zero row and column in front.
+    let mut table = vec![0.0f64; (width + 1) * (height + 1)];
+    for y in 0..height {
+        for x in 0..width {
+            table[(y + 1) * (width + 1) + x + 1] = src[y * width + x] as f64
+                + table[y * (width + 1) + x + 1]
+                + table[(y + 1) * (width + 1) + x]
+                - table[y * (width + 1) + x];
+        }
+    }
+    let at = |x: usize, y: usize| table[y * (width + 1) + x];
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width, i / width);
+            let (x0, x1) = (x.saturating_sub(r), (x + r + 1).min(width));
+            let (y0, y1) = (y.saturating_sub(r), (y + r + 1).min(height));
+            let sum = at(x1, y1) - at(x0, y1) - at(x1, y0) + at(x0, y0);
+            (sum / ((x1 - x0) * (y1 - y0)) as f64) as f32
+        })
+        .collect()
+}
+
+/// Separable Gaussian average with a standard deviation of half of `radius`, over `radius`
+/// pixels each way, renormalized where the kernel is clipped by the image edges.
+fn gaussian(src: &[f32], width: usize, height: usize, radius: u32) -> Vec<f32> {
+    let r = radius as i64;
+    let sigma = (radius as f32 / 2.0).max(0.5);
+    let kernel: Vec<f32> = (-r..=r).map(|d| (-((d * d) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let pass = |src: &[f32], (sx, sy): (i64, i64)| -> Vec<f32> {
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as i64, (i / width) as i64);
+                let (mut sum, mut norm) = (0.0, 0.0);
+                for (k, d) in kernel.iter().zip(-r..) {
+                    let (nx, ny) = (x + d * sx, y + d * sy);
+                    if nx >= 0 && ny >= 0 && nx < width as i64 && ny < height as i64 {
+                        sum += k * src[ny as usize * width + nx as usize];
+                        norm += k;
+                    }
+                }
+                sum / norm
+            })
+            .collect()
+    };
+    pass(&pass(src, (1, 0)), (0, 1))
+}
+
+/// Luma of a pixel's straight sRGB-encoded color.
+fn luma<T: Sample>(px: &[T], premultiplied: bool) -> f32 {
+    let alpha = px[3].to_f32() / T::MAX;
+    let scale = if premultiplied && alpha > 0.0 { T::MAX * alpha } else { T::MAX };
+    (0..3).map(|c| LUMA[c] * plugin_sdk::to_encoded::<T>(px[c].to_f32() / scale)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    fn gray(values: &[u8], alpha: u8) -> Vec<u8> {
+        values.iter().flat_map(|&v| [v, v, v, alpha]).collect()
+    }
+
+    fn reds(img: &[u8]) -> Vec<u8> {
+        img.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_fixed_invert_and_alpha() {
+        let mut img = gray(&[0, 100, 128, 200], 90);
+        run(&mut img, 4, 1, "{}").unwrap();
+        assert_eq!(img, [0, 0, 0, 90, 0, 0, 0, 90, 255, 255, 255, 90, 255, 255, 255, 90]);
+
+        let mut img = gray(&[0, 100, 128, 200], 90);
+        run(&mut img, 4, 1, r#"{"threshold": 0.3, "invert": true, "keep_alpha": false}"#).unwrap();
+        assert_eq!(img, [255, 255, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_otsu_splits_bimodal_image() {
+        // Two clusters around 30 and 90: a fixed threshold of one half makes everything black.
+        let values = [25, 30, 35, 28, 85, 90, 95, 92];
+        let mut img = gray(&values, 255);
+        run(&mut img, 8, 1, r#"{"method": "otsu"}"#).unwrap();
+        assert_eq!(reds(&img), [0, 0, 0, 0, 255, 255, 255, 255]);
+        let level = otsu([0.1, 0.1, 0.9, 0.9].into_iter());
+        assert!(level > 0.1 && level < 0.9);
+    }
+
+    #[test]
+    fn test_adaptive_follows_uneven_lighting() {
+        // Dark ink on paper that darkens to the right: the ink on the left is brighter than the
+        // paper on the right, so no single threshold separates them.
+        let paper: Vec<u8> = (0..16).map(|x| 220 - 10 * x as u8).collect();
+        let values: Vec<u8> = paper.iter().enumerate().map(|(x, p)| if x % 4 == 1 { p - 60 } else { *p }).collect();
+        for window in ["mean", "gaussian"] {
+            let mut img = gray(&values, 255);
+            let params = format!(r#"{{"method": "adaptive", "window": "{window}", "radius": 3, "offset": 0.05}}"#);
+            run(&mut img, 16, 1, &params).unwrap();
+            let expected: Vec<u8> = (0..16).map(|x| if x % 4 == 1 { 0 } else { 255 }).collect();
+            assert_eq!(reds(&img), expected, "{window}");
+        }
+
+        let params = plugin_sdk::params_from_str(r#"{"method": "adaptive", "radius": 5}"#).unwrap();
+        assert_eq!(halo(&params), Some(5));
+    }
+}
@@ -0,0 +1,144 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// The curve compressing linear light into the displayable range.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Operator {
+    /// Extended Reinhard, `x (1 + x / white²) / (1 + x)`: soft, keeps shadows as they are.
+    Reinhard,
+    /// Narkowicz's fit of the ACES filmic curve: punchy contrast, highlights roll off to white.
+    #[default]
+    Aces,
+    /// Hable's "Uncharted 2" curve with its exposure bias of 2: a toe in the shadows and a long
+    /// shoulder.
+    Filmic,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    #[serde(default)]
+    operator: Operator,
+    /// Exposure adjustment in stops applied before the curve; +1 doubles the light.
+    #[serde(default)]
+    exposure: f32,
+    /// Linear value, after exposure, that maps to full white; brighter values clip.
+    #[serde(default = "default_white")]
+    white: f32,
+}
+
+fn default_white() -> f32 {
+    11.2
+}
+
+impl Operator {
+    /// The raw curve, before normalizing to `white`.
+    fn curve(self, x: f32, white: f32) -> f32 {
+        match self {
+            Self::Reinhard => x * (1.0 + x / (white * white)) / (1.0 + x),
+            Self::Aces => x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14),
+            Self::Filmic => {
+                let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+                let x = 2.0 * x;
+                (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+            }
+        }
+    }
+
+    /// Maps linear light to `[0, 1]`, with `white` at 1.
+    fn map(self, x: f32, white: f32) -> f32 {
+        (self.curve(x.max(0.0), white) / self.curve(white, white)).clamp(0.0, 1.0)
+    }
+}
+
+const MANIFEST: &CStr = cr#"name = "tone_map_plugin"
+version = "0.1.0"
+description = "Tone maps HDR linear light to the displayable range with Reinhard, ACES or filmic curves"
+
+[defaults]
+operator = "aces"
+exposure = 0.0
+white = 11.2
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: |_| Some(0),
+}
+
+/// Tone maps both formats; working per pixel, it is local with no halo.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    if !params.exposure.is_finite() || params.white.is_nan() || params.white <= 0.0 {
+        return Err(PluginError::Invalid("exposure must be finite and white positive"));
+    }
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => tone_map(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => tone_map(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// Scales linear light by the exposure and maps each channel through the operator's curve.
+///
+/// Only float data can hold values above 1; 8-bit data is decoded to linear light first, so the
+/// curve still applies, but there are no highlights to recover.
+fn tone_map<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let gain = params.exposure.exp2();
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        rgb.map(|v| {
+            let linear = plugin_sdk::to_linear::<T>(v) * gain;
+            plugin_sdk::from_linear::<T>(params.operator.map(linear, params.white))
+        })
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    /// Tone maps a one-row linear RGBA32F image.
+    fn run(buf: &mut [f32], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_operators_compress_to_white() {
+        for operator in [Operator::Reinhard, Operator::Aces, Operator::Filmic] {
+            let white = default_white();
+            assert!(operator.map(0.0, white) < 1e-6, "{operator:?}");
+            assert!((operator.map(white, white) - 1.0).abs() < 1e-6, "{operator:?}");
+            assert_eq!(operator.map(2.0 * white, white), 1.0, "{operator:?}");
+            // Monotonic, and the slope falls off towards the highlights.
+            let inputs = [0.05, 0.18, 1.0, 4.0];
+            let samples: Vec<f32> = inputs.iter().map(|&x| operator.map(x, white)).collect();
+            assert!(samples.windows(2).all(|w| w[0] < w[1]), "{operator:?}: {samples:?}");
+            let slope = |i: usize| (samples[i + 1] - samples[i]) / (inputs[i + 1] - inputs[i]);
+            assert!(slope(2) < slope(1), "{operator:?}: {samples:?}");
+        }
+    }
+
+    #[test]
+    fn test_hdr_pixels_brought_into_range() {
+        let mut img = [8.0, 2.0, 0.18, 1.0, 0.5, 0.5, 0.5, 0.25];
+        run(&mut img, r#"{"operator": "reinhard", "white": 8.0}"#).unwrap();
+        assert!((img[0] - 1.0).abs() < 1e-6);
+        assert!(img[..3].iter().all(|v| (0.0..=1.0).contains(v)));
+        assert!(img[1] > img[2] && img[2] < 0.18);
+        assert_eq!((img[3], img[7]), (1.0, 0.25));
+
+        // Each stop of exposure doubles the light going into the curve.
+        let (mut brighter, mut plain) = ([0.25, 0.25, 0.25, 1.0], [0.5, 0.5, 0.5, 1.0]);
+        run(&mut brighter, r#"{"exposure": 1.0}"#).unwrap();
+        run(&mut plain, "{}").unwrap();
+        assert_eq!(brighter, plain);
+
+        assert!(run(&mut plain, r#"{"white": 0.0}"#).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use std::ffi::CStr;
+use plugin_sdk::{Allocator, CallContext, Color, ImageRef, PixelsRef, PluginError, Sample};
+use serde::Deserialize;
+
+/// How source pixels are sampled.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Interpolation {
+    /// The closest source pixel.
+    Nearest,
+    /// A weighted average of the 2x2 closest pixels.
+    #[default]
+    Bilinear,
+}
+
+/// What is sampled outside the source image.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+enum Edge {
+    /// The `background` color.
+    #[default]
+    Background,
+    /// The nearest edge pixel.
+    Clamp,
+    /// The image repeated as a tile.
+    Wrap,
+    /// The image mirrored at its edges.
+    Mirror,
+}
+
+/// A point in pixel coordinates; the image spans `(0, 0)` to `(width, height)`.
+type Point = [f64; 2];
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Row-major 3x3 homography taking source points to output points. The last row is
+    /// `[0, 0, 1]` for an affine transform.
+    #[serde(default)]
+    matrix: Option<[f64; 9]>,
+    /// Source quad corners in the order top-left, top-right, bottom-right, bottom-left; the
+    /// source image's corners if only `to` is given.
+    #[serde(default)]
+    from: Option<[Point; 4]>,
+    /// Output quad corners that `from` is mapped to; the output's corners if only `from` is given.
+    #[serde(default)]
+    to: Option<[Point; 4]>,
+    /// Output width; 0 keeps the input width.
+    #[serde(default)]
+    width: u32,
+    /// Output height; 0 keeps the input height.
+    #[serde(default)]
+    height: u32,
+    #[serde(default)]
+    interpolation: Interpolation,
+    #[serde(default)]
+    edge: Edge,
+    /// Fill outside the source image with `edge = "background"`.
+    #[serde(default = "default_background")]
+    background: Color,
+}
+
+fn default_background() -> Color {
+    Color::TRANSPARENT
+}
+
+const MANIFEST: &CStr = cr##"name = "warp_plugin"
+version = "0.1.0"
+description = "Applies an affine or perspective transform given as a matrix or quad corners"
+
+[defaults]
+width = 0
+height = 0
+interpolation = "bilinear"
+edge = "background"
+background = "#00000000"
+"##;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process_v2: process,
+}
+
+/// Warps both formats.
+fn process(
+    image: ImageRef<'_>,
+    output: &mut Allocator<'_>,
+    params: &Params,
+    ctx: &CallContext,
+) -> Result<(), PluginError> {
+    let ImageRef { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    let out_size = (
+        if params.width == 0 { width } else { params.width },
+        if params.height == 0 { height } else { params.height },
+    );
+    let Some(inverse) = params.transform((width, height), out_size).and_then(|m| invert(&m)) else {
+        return Err(PluginError::Invalid("the transform is missing, conflicting or not invertible"));
+    };
+
+    match pixels {
+        PixelsRef::Rgba8(data) => {
+            let source = Source::new(data, width, height, premultiplied, params);
+            warp(&source, out_size, &inverse, params.interpolation, output)
+        }
+        PixelsRef::Rgba32F(data) => {
+            let source = Source::new(data, width, height, premultiplied, params);
+            warp(&source, out_size, &inverse, params.interpolation, output)
+        }
+    }
+}
+
+/// A row-major 3x3 matrix acting on homogeneous points `[x, y, 1]`.
+type Matrix = [f64; 9];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+impl Params {
+    /// The source-to-output transform, or `None` if the params are contradictory or degenerate.
+    fn transform(&self, (width, height): (u32, u32), (out_w, out_h): (u32, u32)) -> Option<Matrix> {
+        let corners = |w: u32, h: u32| {
+            let (w, h) = (w as f64, h as f64);
+            [[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]]
+        };
+        match (self.matrix, self.from, self.to) {
+            (Some(m), None, None) => Some(m),
+            (Some(_), _, _) => None,
+            (None, None, None) => Some(IDENTITY),
+            (None, from, to) => {
+                homography(&from.unwrap_or(corners(width, height)), &to.unwrap_or(corners(out_w, out_h)))
+            }
+        }
+    }
+}
+
+/// Solves for the homography taking each `from` corner to the matching `to` corner.
+///
+/// Returns `None` if three of the points are collinear.
+fn homography(from: &[Point; 4], to: &[Point; 4]) -> Option<Matrix> {
+    // With h33 fixed at 1, each pair gives two linear equations in the other eight entries.
+    let mut rows = [[0.0; 9]; 8];
+    for (i, (&[x, y], &[u, v])) in from.iter().zip(to).enumerate() {
+        rows[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+        rows[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+    }
+    // Gaussian elimination with partial pivoting.
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&a, &b| rows[a][col].abs().total_cmp(&rows[b][col].abs()))?;
+        if rows[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        rows.swap(col, pivot);
+        let pivot_row = rows[col];
+        for (_, row) in rows.iter_mut().enumerate().filter(|&(r, _)| r != col) {
+            let factor = row[col] / pivot_row[col];
+            for (v, p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                *v -= factor * p;
+            }
+        }
+    }
+    let h: [f64; 8] = std::array::from_fn(|i| rows[i][8] / rows[i][i]);
+    Some([h[0], h[1], h[2], h[3], h[4], h[5], h[6], h[7], 1.0])
+}
+
+/// Inverts `m`, or returns `None` if it is singular.
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let [a, b, c, d, e, f, g, h, i] = *m;
+    let cofactors = [
+        e * i - f * h,
+        c * h - b * i,
+        b * f - c * e,
+        f * g - d * i,
+        a * i - c * g,
+        c * d - a * f,
+        d * h - e * g,
+        b * g - a * h,
+        a * e - b * d,
+    ];
+    let det = a * cofactors[0] + b * cofactors[3] + c * cofactors[6];
+    if det.abs() < 1e-12 || !det.is_finite() {
+        return None;
+    }
+    Some(cofactors.map(|v| v / det))
+}
+
+/// Fills a buffer allocated from `output` by sampling the source where `inverse` maps each
+/// output pixel's center; fails if the host refused the buffer.
+///
+/// Output points that map to infinity or behind the camera get the background.
+fn warp<T: Sample>(
+    source: &Source<'_, T>,
+    (out_w, out_h): (u32, u32),
+    inverse: &Matrix,
+    interpolation: Interpolation,
+    output: &mut Allocator<'_>,
+) -> Result<(), PluginError> {
+    if (out_w, out_h) == (source.width, source.height) && *inverse == IDENTITY {
+        return Ok(());
+    }
+    let dst = output.alloc::<T>(out_w, out_h)?;
+    let [a, b, c, d, e, f, g, h, i] = *inverse;
+    for (n, px) in dst.chunks_exact_mut(4).enumerate() {
+        let x = (n % out_w as usize) as f64 + 0.5;
+        let y = (n / out_w as usize) as f64 + 0.5;
+        let w = g * x + h * y + i;
+        let value = if w <= 1e-12 {
+            source.background
+        } else {
+            let (sx, sy) = ((a * x + b * y + c) / w - 0.5, (d * x + e * y + f) / w - 0.5);
+            match interpolation {
+                Interpolation::Nearest => source.pixel(sx.round() as i64, sy.round() as i64),
+                Interpolation::Bilinear => source.bilinear(sx, sy),
+            }
+        };
+        source.store(value, px);
+    }
+    Ok(())
+}
+
+/// The input image, read as premultiplied values so interpolation does not bleed the color of
+/// transparent pixels, and extended beyond its edges per the edge mode.
+struct Source<'a, T> {
+    data: &'a [T],
+    width: u32,
+    height: u32,
+    premultiplied: bool,
+    edge: Edge,
+    background: [f32; 4],
+}
+
+impl<'a, T: Sample> Source<'a, T> {
+    fn new(data: &'a [T], width: u32, height: u32, premultiplied: bool, params: &Params) -> Self {
+        let [r, g, b, a] = params.background.normalized::<T>();
+        let background = [r * a * T::MAX, g * a * T::MAX, b * a * T::MAX, a * T::MAX];
+        Self { data, width, height, premultiplied, edge: params.edge, background }
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> [f32; 4] {
+        let (Some(x), Some(y)) = (self.edge.locate(x, self.width), self.edge.locate(y, self.height)) else {
+            return self.background;
+        };
+        let i = (y * self.width as usize + x) * 4;
+        let px: [f32; 4] = std::array::from_fn(|c| self.data[i + c].to_f32());
+        if self.premultiplied {
+            return px;
+        }
+        let alpha = px[3] / T::MAX;
+        [px[0] * alpha, px[1] * alpha, px[2] * alpha, px[3]]
+    }
+
+    fn bilinear(&self, x: f64, y: f64) -> [f32; 4] {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = ((x - x0) as f32, (y - y0) as f32);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let taps = [(0, 0, (1.0 - fx) * (1.0 - fy)), (1, 0, fx * (1.0 - fy)), (0, 1, (1.0 - fx) * fy), (1, 1, fx * fy)];
+        let mut out = [0.0; 4];
+        for (ox, oy, weight) in taps {
+            let px = self.pixel(x0 + ox, y0 + oy);
+            for c in 0..4 {
+                out[c] += px[c] * weight;
+            }
+        }
+        out
+    }
+
+    /// Writes a premultiplied value back in the buffer's alpha convention.
+    fn store(&self, value: [f32; 4], px: &mut [T]) {
+        let alpha = value[3] / T::MAX;
+        for c in 0..3 {
+            let v = if self.premultiplied {
+                value[c]
+            } else if alpha > 0.0 {
+                value[c] / alpha
+            } else {
+                0.0
+            };
+            px[c] = T::from_f32(v);
+        }
+        px[3] = T::from_f32(value[3]);
+    }
+}
+
+impl Edge {
+    /// Maps a possibly out-of-range coordinate to an index below `len`, or `None` for the background.
+    fn locate(self, v: i64, len: u32) -> Option<usize> {
+        let len = len as i64;
+        let v = match self {
+            _ if (0..len).contains(&v) => v,
+            Self::Background => return None,
+            Self::Clamp => v.clamp(0, len - 1),
+            Self::Wrap => v.rem_euclid(len),
+            Self::Mirror => {
+                let m = v.rem_euclid(2 * len);
+                if m < len { m } else { 2 * len - 1 - m }
+            }
+        };
+        Some(v as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing::{self, Output};
+
+    /// Warps an RGBA8 image and returns the output, if one was allocated.
+    fn run(src: &[u8], width: u32, height: u32, params: &str) -> Result<Option<Output<u8>>, PluginError> {
+        testing::run_v2(process, width, height, src, params)
+    }
+
+    fn firsts(data: &[u8]) -> Vec<u8> {
+        data.chunks(4).map(|p| p[0]).collect()
+    }
+
+    #[test]
+    fn test_homography_maps_corners() {
+        let from = [[10.0, 20.0], [90.0, 5.0], [110.0, 80.0], [0.0, 70.0]];
+        let to = [[0.0, 0.0], [64.0, 0.0], [64.0, 48.0], [0.0, 48.0]];
+        let m = homography(&from, &to).unwrap();
+        for (&[x, y], &[u, v]) in from.iter().zip(&to) {
+            let w = m[6] * x + m[7] * y + m[8];
+            assert!(((m[0] * x + m[1] * y + m[2]) / w - u).abs() < 1e-9);
+            assert!(((m[3] * x + m[4] * y + m[5]) / w - v).abs() < 1e-9);
+        }
+        let collinear = [[0.0, 0.0], [1.0, 1.0], [2.0, 2.0], [0.0, 5.0]];
+        assert!(homography(&collinear, &to).is_none());
+        assert!(invert(&[1.0, 2.0, 3.0, 2.0, 4.0, 6.0, 0.0, 0.0, 1.0]).is_none());
+    }
+
+    #[test]
+    fn test_affine_matrix_and_edges() {
+        // 4x1 ramp shifted right by one pixel.
+        let src: Vec<u8> = [10u8, 20, 30, 40].iter().flat_map(|&v| [v, v, v, 255]).collect();
+        let shift = r#""matrix": [1, 0, 1, 0, 1, 0, 0, 0, 1]"#;
+        let out = run(&src, 4, 1, &format!("{{{shift}}}")).unwrap().unwrap();
+        assert_eq!(firsts(&out.data), [0, 10, 20, 30]);
+        assert_eq!(out.data[3], 0);
+
+        let edge = |mode: &str| {
+            let out = run(&src, 4, 1, &format!(r#"{{{shift}, "edge": "{mode}"}}"#)).unwrap().unwrap();
+            firsts(&out.data)
+        };
+        assert_eq!(edge("clamp"), [10, 10, 20, 30]);
+        assert_eq!(edge("wrap"), [40, 10, 20, 30]);
+        assert_eq!(edge("mirror"), [10, 10, 20, 30]);
+
+        // No transform and no size change leaves the image alone; conflicting params fail.
+        assert_eq!(run(&src, 4, 1, "{}"), Ok(None));
+        let conflicting = format!(r#"{{{shift}, "to": [[0,0],[1,0],[1,1],[0,1]]}}"#);
+        assert!(matches!(run(&src, 4, 1, &conflicting), Err(PluginError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_quad_rectification() {
+        // Rectify the right half of a 4x2 image into a 2x2 output.
+        let src: Vec<u8> = (0..8u8).flat_map(|i| [i * 10, 0, 0, 255]).collect();
+        let params = r#"{"from": [[2,0],[4,0],[4,2],[2,2]], "width": 2, "height": 2, "interpolation": "nearest"}"#;
+        let out = run(&src, 4, 2, params).unwrap().unwrap();
+        assert_eq!((out.width, out.height), (2, 2));
+        assert_eq!(firsts(&out.data), [20, 30, 60, 70]);
+
+        // The inverse direction puts the whole image into a quad of the same-size output.
+        let out = run(&src, 4, 2, r#"{"to": [[0,0],[2,0],[2,2],[0,2]]}"#).unwrap().unwrap();
+        assert_eq!(out.data[2 * 4 + 3], 0);
+    }
+}
@@ -0,0 +1,303 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Where a single watermark is placed, inset by `margin`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum Position {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+impl Position {
+    /// Horizontal and vertical alignment: 0 is the start, 1 the middle and 2 the end.
+    fn alignment(self) -> (u8, u8) {
+        match self {
+            Self::TopLeft => (0, 0),
+            Self::Top => (1, 0),
+            Self::TopRight => (2, 0),
+            Self::Left => (0, 1),
+            Self::Center => (1, 1),
+            Self::Right => (2, 1),
+            Self::BottomLeft => (0, 2),
+            Self::Bottom => (1, 2),
+            Self::BottomRight => (2, 2),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Path of the watermark image, in any format the `image` crate reads.
+    path: String,
+    #[serde(default)]
+    position: Position,
+    /// Distance in pixels from the image edges, or between copies when tiling.
+    #[serde(default = "default_margin")]
+    margin: u32,
+    /// Size of the watermark relative to its own pixel size.
+    #[serde(default = "default_scale")]
+    scale: f32,
+    /// Multiplies the watermark's alpha.
+    #[serde(default = "default_opacity")]
+    opacity: f32,
+    /// Repeats the watermark over the whole image from the top-left corner, ignoring `position`.
+    #[serde(default)]
+    tile: bool,
+}
+
+fn default_margin() -> u32 {
+    16
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_opacity() -> f32 {
+    0.5
+}
+
+const MANIFEST: &CStr = cr#"name = "watermark_plugin"
+version = "0.1.0"
+description = "Composites a watermark image from a file, placed or tiled, at a scale and opacity"
+
+[defaults]
+position = "bottom_right"
+margin = 16
+scale = 1.0
+opacity = 0.5
+tile = false
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    required: r#"path = "mark.png""#,
+}
+
+/// Composites the watermark onto both formats.
+///
+/// Fails if the scale or opacity is not a number, or the watermark cannot be read or decoded.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let Image { width, height, pixels } = image;
+    let premultiplied = ctx.premultiplied();
+
+    if params.scale.is_nan() || params.scale <= 0.0 || params.opacity.is_nan() {
+        return Err(PluginError::Invalid("scale and opacity must be numbers, scale above 0"));
+    }
+    let Some(mark) = Mark::load(&params.path, params.scale) else {
+        return Err(PluginError::Invalid("cannot read or decode the watermark"));
+    };
+
+    match pixels {
+        Pixels::Rgba8(buf) => composite(width, height, buf, &mark, params, premultiplied),
+        Pixels::Rgba32F(buf) => composite(width, height, buf, &mark, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// The scaled watermark as premultiplied sRGB-encoded values in `[0, 1]`.
+struct Mark {
+    width: usize,
+    height: usize,
+    data: Vec<[f32; 4]>,
+}
+
+impl Mark {
+    /// Reads and scales the watermark; `None` if it cannot be read or scales to nothing.
+    fn load(path: &str, scale: f32) -> Option<Self> {
+        let img = image::open(path).ok()?.into_rgba8();
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let data = img
+            .pixels()
+            .map(|p| {
+                let a = p[3] as f32 / 255.0;
+                [p[0] as f32 / 255.0 * a, p[1] as f32 / 255.0 * a, p[2] as f32 / 255.0 * a, a]
+            })
+            .collect();
+        let mark = Self { width, height, data };
+        if scale == 1.0 {
+            return Some(mark);
+        }
+        let size = |v: usize| (v as f32 * scale).round() as usize;
+        let (w, h) = (size(width), size(height));
+        (w > 0 && h > 0).then(|| mark.resized(w, h))
+    }
+
+    /// Resamples to `width`x`height`: averaging the covered pixels when shrinking and
+    /// interpolating bilinearly when enlarging.
+    fn resized(&self, width: usize, height: usize) -> Self {
+        let (sx, sy) = (self.width as f32 / width as f32, self.height as f32 / height as f32);
+        let at = |x: usize, y: usize| self.data[y.min(self.height - 1) * self.width + x.min(self.width - 1)];
+        let data = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                if sx > 1.0 || sy > 1.0 {
+                    let xs = (x * sx) as usize..(((x + 1.0) * sx).ceil() as usize).min(self.width);
+                    let ys = (y * sy) as usize..(((y + 1.0) * sy).ceil() as usize).min(self.height);
+                    let n = (xs.len() * ys.len()).max(1) as f32;
+                    let mut sum = [0.0; 4];
+                    for py in ys {
+                        for px in xs.clone() {
+                            let v = at(px, py);
+                            (0..4).for_each(|c| sum[c] += v[c] / n);
+                        }
+                    }
+                    sum
+                } else {
+                    let (fx, fy) = (((x + 0.5) * sx - 0.5).max(0.0), ((y + 0.5) * sy - 0.5).max(0.0));
+                    let (x0, y0) = (fx as usize, fy as usize);
+                    let (tx, ty) = (fx.fract(), fy.fract());
+                    let [a, b, c, d] = [at(x0, y0), at(x0 + 1, y0), at(x0, y0 + 1), at(x0 + 1, y0 + 1)];
+                    std::array::from_fn(|k| {
+                        let top = a[k] + (b[k] - a[k]) * tx;
+                        let bottom = c[k] + (d[k] - c[k]) * tx;
+                        top + (bottom - top) * ty
+                    })
+                }
+            })
+            .collect();
+        Self { width, height, data }
+    }
+}
+
+/// Draws the watermark over the image with the "over" operator, once at `position` or tiled.
+/// The watermark's sRGB colors are converted to the buffer's encoding first.
+fn composite<T: Sample>(width: u32, height: u32, buf: &mut [T], mark: &Mark, params: &Params, premultiplied: bool) {
+    let (w, h) = (width as i64, height as i64);
+    let (mw, mh, margin) = (mark.width as i64, mark.height as i64, params.margin as i64);
+    let opacity = params.opacity.clamp(0.0, 1.0);
+    // The watermark in premultiplied values of `T`, with the opacity applied.
+    let values: Vec<[f32; 4]> = mark
+        .data
+        .iter()
+        .map(|&[r, g, b, a]| {
+            let straight = |v: f32| if a > 0.0 { plugin_sdk::from_encoded::<T>(v / a) } else { 0.0 };
+            let alpha = a * opacity;
+            [straight(r) * alpha * T::MAX, straight(g) * alpha * T::MAX, straight(b) * alpha * T::MAX, alpha * T::MAX]
+        })
+        .collect();
+
+    let origins: Vec<(i64, i64)> = if params.tile {
+        let (step_x, step_y) = (mw + margin, mh + margin);
+        (0..(h + step_y - 1) / step_y)
+            .flat_map(|j| (0..(w + step_x - 1) / step_x).map(move |i| (i * step_x, j * step_y)))
+            .collect()
+    } else {
+        let place = |align: u8, size: i64, inner: i64| match align {
+            0 => margin,
+            1 => (size - inner) / 2,
+            _ => size - inner - margin,
+        };
+        let (ax, ay) = params.position.alignment();
+        vec![(place(ax, w, mw), place(ay, h, mh))]
+    };
+
+    for (ox, oy) in origins {
+        for my in (0.max(-oy))..mh.min(h - oy) {
+            for mx in (0.max(-ox))..mw.min(w - ox) {
+                let top = values[(my * mw + mx) as usize];
+                if top[3] <= 0.0 {
+                    continue;
+                }
+                let px = &mut buf[(((oy + my) * w + ox + mx) * 4) as usize..][..4];
+                let alpha = px[3].to_f32() / T::MAX;
+                let scale = if premultiplied { 1.0 } else { alpha };
+                let rest = 1.0 - top[3] / T::MAX;
+                let out_alpha = top[3] + px[3].to_f32() * rest;
+                for c in 0..3 {
+                    let value = top[c] + px[c].to_f32() * scale * rest;
+                    let divisor = if premultiplied || out_alpha <= 0.0 { 1.0 } else { out_alpha / T::MAX };
+                    px[c] = T::from_f32(value / divisor);
+                }
+                px[3] = T::from_f32(out_alpha);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], width: u32, height: u32, params: &str) -> Result<(), PluginError> {
+        testing::run(process, width, height, buf, params)
+    }
+
+    /// Writes a `width`x`height` watermark of one color into a new directory for the test `name`.
+    ///
+    /// Returns the directory, for the test to remove, and the path of the PNG for the params.
+    fn mark_file(name: &str, width: u32, height: u32, color: [u8; 4]) -> (std::path::PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("watermark-test-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mark.png");
+        image::RgbaImage::from_pixel(width, height, image::Rgba(color)).save(&path).unwrap();
+        (dir, path.to_str().unwrap().replace('\\', "/"))
+    }
+
+    fn covered(img: &[u8], width: usize) -> Vec<(usize, usize)> {
+        img.chunks(4).enumerate().filter(|(_, p)| p[0] != 0).map(|(i, _)| (i % width, i / width)).collect()
+    }
+
+    #[test]
+    fn test_position_margin_and_opacity() {
+        let (dir, path) = mark_file("corner", 2, 2, [255, 255, 255, 255]);
+        let mut img = [0u8, 0, 0, 255].repeat(10 * 8);
+        let params = format!(r#"{{"path": "{path}", "margin": 1, "opacity": 1.0}}"#);
+        run(&mut img, 10, 8, &params).unwrap();
+        assert_eq!(covered(&img, 10), [(7, 5), (8, 5), (7, 6), (8, 6)]);
+        assert_eq!(img[(5 * 10 + 7) * 4..][..4], [255, 255, 255, 255]);
+
+        let mut img = [0u8, 0, 0, 255].repeat(10 * 8);
+        let params = format!(r#"{{"path": "{path}", "position": "top", "margin": 1}}"#);
+        run(&mut img, 10, 8, &params).unwrap();
+        assert_eq!(covered(&img, 10), [(4, 1), (5, 1), (4, 2), (5, 2)]);
+        // Half opacity white over black lands on the middle of the sRGB range.
+        assert_eq!(img[(10 + 4) * 4..][..4], [128, 128, 128, 255]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tile_and_scale() {
+        let (dir, path) = mark_file("tile", 4, 4, [255, 0, 0, 255]);
+        let mut img = [0u8, 0, 0, 255].repeat(8 * 8);
+        let params = format!(r#"{{"path": "{path}", "tile": true, "scale": 0.5, "margin": 2}}"#);
+        run(&mut img, 8, 8, &params).unwrap();
+        // 2x2 copies every 4 pixels.
+        let expected: Vec<(usize, usize)> = (0..8)
+            .flat_map(|y| (0..8).map(move |x| (x, y)))
+            .filter(|(x, y)| x % 4 < 2 && y % 4 < 2)
+            .collect();
+        assert_eq!(covered(&img, 8), expected);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_transparent_image_and_missing_file() {
+        let (dir, path) = mark_file("over", 1, 1, [0, 0, 255, 255]);
+        let mut img = [255u8, 0, 0, 0];
+        let params = format!(r#"{{"path": "{path}", "margin": 0, "opacity": 1.0}}"#);
+        run(&mut img, 1, 1, &params).unwrap();
+        assert_eq!(img, [0, 0, 255, 255]);
+
+        let mut img = [0u8; 4];
+        assert!(run(&mut img, 1, 1, r#"{"path": "/nonexistent/watermark.png"}"#).is_err());
+        assert!(run(&mut img, 1, 1, "{}").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use std::ffi::CStr;
+use plugin_sdk::{CallContext, Image, Pixels, PluginError, Sample};
+use serde::Deserialize;
+
+/// Color temperature, in Kelvin, that the correction maps to white.
+const NEUTRAL_KELVIN: f32 = 6500.0;
+
+/// Range of the Planckian locus approximation.
+const MIN_KELVIN: f32 = 1667.0;
+const MAX_KELVIN: f32 = 25000.0;
+
+/// Fraction of pixels above the white point in `white_patch` estimation, ignoring specular
+/// highlights and hot pixels.
+const WHITE_PATCH_QUANTILE: f32 = 0.99;
+
+/// How `auto` estimates the color of the light.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum Method {
+    /// Assumes the scene averages to gray.
+    #[default]
+    GrayWorld,
+    /// Assumes the brightest colors are white.
+    WhitePatch,
+}
+
+#[derive(Deserialize, Debug)]
+struct Params {
+    /// Color temperature of the light the image was taken under; 6500 leaves it unchanged,
+    /// lower values cool the image and higher values warm it.
+    #[serde(default = "default_temperature")]
+    temperature: f32,
+    /// Green-magenta shift; positive removes green, each unit is half a stop.
+    #[serde(default)]
+    tint: f32,
+    /// Estimates the correction from the image itself, instead of `temperature` and `tint`.
+    #[serde(default)]
+    auto: bool,
+    #[serde(default)]
+    method: Method,
+}
+
+fn default_temperature() -> f32 {
+    NEUTRAL_KELVIN
+}
+
+const MANIFEST: &CStr = cr#"name = "white_balance_plugin"
+version = "0.1.0"
+description = "Corrects color temperature and tint, manually or estimated from the image"
+
+[defaults]
+temperature = 6500.0
+tint = 0.0
+auto = false
+method = "gray_world"
+"#;
+
+plugin_sdk::export_plugin! {
+    manifest: MANIFEST,
+    params: Params,
+    process: process,
+    halo: halo,
+}
+
+/// Balances both formats.
+fn process(image: Image<'_>, params: &Params, ctx: &CallContext) -> Result<(), PluginError> {
+    let premultiplied = ctx.premultiplied();
+
+    match image.pixels {
+        Pixels::Rgba8(buf) => balance(buf, params, premultiplied),
+        Pixels::Rgba32F(buf) => balance(buf, params, premultiplied),
+    }
+    Ok(())
+}
+
+/// `auto` looks at the whole image, so only the manual correction is local.
+fn halo(params: &Params) -> Option<u32> {
+    (!params.auto).then_some(0)
+}
+
+/// Linear sRGB color of a black body at `kelvin`, scaled to a luminance of one.
+///
+/// Uses the cubic fit of the Planckian locus by Kim et al., valid from 1667 K to 25000 K.
+fn blackbody(kelvin: f32) -> [f32; 3] {
+    let t = kelvin.clamp(MIN_KELVIN, MAX_KELVIN) as f64;
+    let (t2, t3) = (t * t, t * t * t);
+    let x = if t <= 4000.0 {
+        -0.266_123_9e9 / t3 - 0.234_358_9e6 / t2 + 0.877_695_6e3 / t + 0.179_910
+    } else {
+        -3.025_846_9e9 / t3 + 2.107_037_9e6 / t2 + 0.222_634_7e3 / t + 0.240_390
+    };
+    let (x2, x3) = (x * x, x * x * x);
+    let y = if t <= 2222.0 {
+        -1.106_381_4 * x3 - 1.348_110_20 * x2 + 2.185_558_32 * x - 0.202_196_83
+    } else if t <= 4000.0 {
+        -0.954_947_6 * x3 - 1.374_185_93 * x2 + 2.091_370_15 * x - 0.167_488_67
+    } else {
+        3.081_758_0 * x3 - 5.873_386_70 * x2 + 3.751_129_97 * x - 0.370_014_83
+    };
+    let (cx, cy, cz) = (x / y, 1.0, (1.0 - x - y) / y);
+    [
+        3.2406 * cx - 1.5372 * cy - 0.4986 * cz,
+        -0.9689 * cx + 1.8758 * cy + 0.0415 * cz,
+        0.0557 * cx - 0.2040 * cy + 1.0570 * cz,
+    ]
+    .map(|v| v as f32)
+}
+
+/// Per-channel gains that turn light of `temperature` into neutral light, with green kept.
+fn manual_gains(temperature: f32, tint: f32) -> [f32; 3] {
+    let source = blackbody(temperature);
+    let neutral = blackbody(NEUTRAL_KELVIN);
+    let gains: [f32; 3] = std::array::from_fn(|c| neutral[c] / source[c] * source[1] / neutral[1]);
+    [gains[0], gains[1] * (-tint * 0.5).exp2(), gains[2]]
+}
+
+/// Estimates the light color from the straight, linear colors of all visible pixels and returns
+/// the gains that make it gray, with green kept.
+fn auto_gains<T: Sample>(buf: &[T], method: Method, premultiplied: bool) -> [f32; 3] {
+    let visible = buf.chunks_exact(4).filter_map(|px| {
+        let alpha = if premultiplied { px[3].to_f32() / T::MAX } else { 1.0 };
+        (alpha > 0.0).then(|| std::array::from_fn(|c| plugin_sdk::to_linear::<T>(px[c].to_f32() / T::MAX / alpha)))
+    });
+
+    let light: [f32; 3] = match method {
+        Method::GrayWorld => {
+            let (sum, count) = visible.fold(([0.0f64; 3], 0usize), |(mut sum, count), rgb: [f32; 3]| {
+                for c in 0..3 {
+                    sum[c] += rgb[c] as f64;
+                }
+                (sum, count + 1)
+            });
+            sum.map(|s| (s / count.max(1) as f64) as f32)
+        }
+        Method::WhitePatch => {
+            let mut channels: [Vec<f32>; 3] = Default::default();
+            for rgb in visible {
+                for c in 0..3 {
+                    channels[c].push(rgb[c]);
+                }
+            }
+            channels.map(|mut values| {
+                if values.is_empty() {
+                    return 1.0;
+                }
+                let rank = ((values.len() - 1) as f32 * WHITE_PATCH_QUANTILE).round() as usize;
+                *values.select_nth_unstable_by(rank, f32::total_cmp).1
+            })
+        }
+    };
+
+    if light.iter().any(|&v| v <= 0.0) {
+        return [1.0; 3];
+    }
+    light.map(|v| light[1] / v)
+}
+
+/// Scales each channel in linear light, where the light's color is a per-channel factor; alpha
+/// is kept.
+fn balance<T: Sample>(buf: &mut [T], params: &Params, premultiplied: bool) {
+    let gains = if params.auto {
+        auto_gains(buf, params.method, premultiplied)
+    } else {
+        manual_gains(params.temperature, params.tint)
+    };
+    plugin_sdk::map_colors(buf, premultiplied, |rgb| {
+        std::array::from_fn(|c| plugin_sdk::from_linear::<T>(plugin_sdk::to_linear::<T>(rgb[c]) * gains[c]))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plugin_sdk::testing;
+
+    fn run(buf: &mut [u8], params: &str) -> Result<(), PluginError> {
+        testing::run(process, buf.len() as u32 / 4, 1, buf, params)
+    }
+
+    #[test]
+    fn test_neutral_temperature_is_identity() {
+        let src: Vec<u8> = (0..=255).flat_map(|v| [v, 255 - v, v / 3, v]).collect();
+        let mut buf = src.clone();
+        run(&mut buf, "{}").unwrap();
+        assert_eq!(buf, src);
+    }
+
+    #[test]
+    fn test_temperature_and_tint() {
+        // Correcting for tungsten light cools the image; for shade, warms it.
+        let mut tungsten = [128u8, 128, 128, 255];
+        run(&mut tungsten, r#"{"temperature": 3000}"#).unwrap();
+        assert!(tungsten[2] > tungsten[1] && tungsten[1] > tungsten[0], "{tungsten:?}");
+
+        let mut shade = [128u8, 128, 128, 255];
+        run(&mut shade, r#"{"temperature": 9000}"#).unwrap();
+        assert!(shade[0] > shade[1] && shade[1] > shade[2], "{shade:?}");
+
+        let mut magenta = [128u8, 128, 128, 255];
+        run(&mut magenta, r#"{"tint": 1}"#).unwrap();
+        assert_eq!(magenta[0], magenta[2]);
+        assert!(magenta[1] < magenta[0]);
+    }
+
+    #[test]
+    fn test_auto_removes_cast() {
+        // A warm cast: every pixel has more red and less blue than green.
+        let cast: Vec<f32> = [[0.6f32, 0.4, 0.2], [0.3, 0.2, 0.1], [0.9, 0.6, 0.3]]
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .collect();
+        for method in ["gray_world", "white_patch"] {
+            let mut img = cast.clone();
+            testing::run(process, 3, 1, &mut img, &format!(r#"{{"auto": true, "method": "{method}"}}"#)).unwrap();
+            for px in img.chunks_exact(4) {
+                assert!((px[0] - px[1]).abs() < 1e-5 && (px[2] - px[1]).abs() < 1e-5, "{method}: {px:?}");
+            }
+        }
+
+        let params = plugin_sdk::params_from_str(r#"{"auto": true}"#).unwrap();
+        assert_eq!(halo(&params), None);
+    }
+}